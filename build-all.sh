@@ -0,0 +1,20 @@
+#!/bin/sh
+# Builds the portable library for a Cortex-M0+ (as on an RP2040), then the
+# firmware for every supported chip, each with the features its pins allow,
+# stopping at the first failure. Extra arguments go to every
+# `cargo build`, e.g. `./build-all.sh --release`.
+set -e
+
+build() {
+    chip=$1 target=$2 features=$3
+    shift 3
+    echo "== $chip"
+    cargo build --no-default-features --target "$target" --features "$chip,$features" "$@"
+}
+
+echo "== core"
+cargo build --lib --no-default-features --target thumbv6m-none-eabi "$@"
+build esp32s3 xtensa-esp32s3-none-elf battery,bldc,light-sleep,lockin,power-meter,protobuf,touch,ulp-wake "$@"
+build esp32s2 xtensa-esp32s2-none-elf battery,light-sleep,protobuf,oled "$@"
+build esp32c3 riscv32imc-unknown-none-elf battery,fixed-point,light-sleep,mock-sensor,protobuf "$@"
+build esp32c6 riscv32imac-unknown-none-elf battery,encoder,fixed-point,ir-remote,protobuf,timer-sampling "$@"
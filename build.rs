@@ -1,8 +1,16 @@
 fn main() {
     linker_be_nice();
-    println!("cargo:rustc-link-arg=-Tdefmt.x");
+    // Only the firmware and the on-target tests; the simulator is an
+    // ordinary host binary
+    for target in ["bin=hall-effect", "tests"] {
+        println!("cargo:rustc-link-arg-{target}=-nostartfiles");
+        println!("cargo:rustc-link-arg-{target}=-Tdefmt.x");
+    }
+    println!("cargo:rustc-link-arg-tests=-Tembedded-test.x");
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
-    println!("cargo:rustc-link-arg=-Tlinkall.x");
+    for target in ["bin=hall-effect", "tests"] {
+        println!("cargo:rustc-link-arg-{target}=-Tlinkall.x");
+    }
 }
 
 fn linker_be_nice() {
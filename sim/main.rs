@@ -0,0 +1,257 @@
+//! Host-side simulator: runs synthetic or recorded readings through the
+//! library's processing (rail monitor, threshold detector, tachometer,
+//! gestures and the LED colour) and prints what the device would do, so
+//! algorithm changes can be tried without hardware.
+//!
+//! ```text
+//! cargo +stable sim -- sine --freq-hz 5 --seconds 2
+//! cargo +stable sim -- trace log.csv --plain
+//! ```
+//!
+//! A trace is CSV as the SD and flash logs write it (`time_us,raw,mv,...`);
+//! the header and any other columns are ignored.
+
+use std::fmt::Write as _;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::{env, fs};
+
+use hall_effect::color::{self, RGB8};
+use hall_effect::gesture::GestureDetector;
+use hall_effect::mode::Tachometer;
+use hall_effect::palette::Palette;
+use hall_effect::schema::{Config, Event, Sample};
+use hall_effect::selftest::RailMonitor;
+use hall_effect::threshold::ThresholdDetector;
+use hall_effect::waveform::{Generator, Shape, Waveform};
+
+const USAGE: &str = "\
+usage: simulate <sine|step|noise|trace FILE> [options]
+  --seconds N        length of a synthetic waveform (default 1)
+  --period-ms N      sample period (default from Config)
+  --freq-hz F        waveform frequency (default 2)
+  --amplitude-mv A   peak deviation from the zero-field output (default 800)
+  --every N          print every Nth sample, events always (default 1)
+  --palette NAME     LED palette: red-blue (default), orange-blue, brightness
+                     or blink
+  --plain            no colour swatches
+";
+
+enum Source {
+    Synthetic(Shape),
+    Trace(String),
+}
+
+struct Options {
+    source: Source,
+    seconds: f32,
+    period_ms: u32,
+    freq_hz: f32,
+    amplitude_mv: f32,
+    every: usize,
+    palette: Palette,
+    plain: bool,
+}
+
+fn parse_args(config: &Config) -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let source = match args.next().as_deref() {
+        Some("trace") => Source::Trace(args.next().ok_or("trace needs a file")?),
+        Some(name) => Source::Synthetic(Shape::parse(name).ok_or(USAGE)?),
+        None => return Err(USAGE.into()),
+    };
+    let mut options = Options {
+        source,
+        seconds: 1.0,
+        period_ms: config.sample_period_ms,
+        freq_hz: 2.0,
+        amplitude_mv: 800.0,
+        every: 1,
+        palette: Palette::default(),
+        plain: false,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--plain" {
+            options.plain = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        match arg.as_str() {
+            "--seconds" => options.seconds = parse(&arg, &value)?,
+            "--period-ms" => options.period_ms = parse(&arg, &value)?,
+            "--freq-hz" => options.freq_hz = parse(&arg, &value)?,
+            "--amplitude-mv" => options.amplitude_mv = parse(&arg, &value)?,
+            "--every" => options.every = parse::<usize>(&arg, &value)?.max(1),
+            "--palette" => {
+                options.palette =
+                    Palette::parse(&value).ok_or_else(|| format!("unknown palette {value}"))?;
+            }
+            _ => return Err(format!("unknown option {arg}\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+fn parse<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("bad value for {arg}: {value}"))
+}
+
+/// Builds a sample the way the sampler does, from a voltage at the pin.
+fn sample(timestamp_us: u64, voltage_mv: f32) -> Sample {
+    let voltage_mv = voltage_mv.clamp(0.0, 3300.0) as u32;
+    Sample {
+        timestamp_us,
+        raw: (voltage_mv * 4095 / 3300) as u16,
+        voltage_mv,
+    }
+}
+
+/// The waveform the `mock-sensor` build produces.
+fn synthetic(shape: Shape, options: &Options, config: &Config) -> Vec<Sample> {
+    let waveform = Waveform {
+        shape,
+        period_ms: (1000.0 / options.freq_hz) as u32,
+        amplitude_mv: options.amplitude_mv as u32,
+    };
+    let mut generator = Generator::new(waveform, config.zero_field_mv);
+    let count = (options.seconds * 1000.0 / options.period_ms.max(1) as f32) as u64;
+    (0..count)
+        .map(|i| {
+            let t_us = i * options.period_ms as u64 * 1000;
+            sample(t_us, generator.voltage_mv(t_us) as f32)
+        })
+        .collect()
+}
+
+fn trace(path: &str) -> Result<Vec<Sample>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut samples = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        // Comments, as in a console dump
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split(',');
+        let (Some(time), Some(raw), Some(mv)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        // The header, or anything else that is not a sample
+        let (Ok(timestamp_us), Ok(raw), Ok(voltage_mv)) = (time.parse(), raw.parse(), mv.parse())
+        else {
+            if n > 0 {
+                eprintln!("{path}:{}: skipped", n + 1);
+            }
+            continue;
+        };
+        samples.push(Sample {
+            timestamp_us,
+            raw,
+            voltage_mv,
+        });
+    }
+    Ok(samples)
+}
+
+fn swatch(color: RGB8) -> String {
+    format!("\x1b[48;2;{};{};{}m  \x1b[0m", color.r, color.g, color.b)
+}
+
+fn main() -> ExitCode {
+    let config = Config::default();
+    let options = match parse_args(&config) {
+        Ok(options) => options,
+        Err(e) => {
+            eprint!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let samples = match &options.source {
+        Source::Trace(path) => match trace(path) {
+            Ok(samples) => samples,
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        Source::Synthetic(shape) => synthetic(*shape, &options, &config),
+    };
+
+    // Samples here are 12-bit counts, as from an uncalibrated ADC
+    let mut rail_monitor = RailMonitor::new(4095);
+    let mut threshold = ThresholdDetector::new();
+    let mut tachometer = Tachometer::new();
+    let mut gestures = GestureDetector::new();
+    let (mut crossings, mut faults, mut gesture_count) = (0, 0, 0);
+    let mut rpm = 0;
+
+    println!("    time_ms     mV       mT  colour   events");
+    for (i, sample) in samples.iter().enumerate() {
+        let timestamp_us = sample.timestamp_us;
+        let mut events = String::new();
+
+        let fault = rail_monitor.update(sample, &config);
+        if let Some(Event::WiringFault { stuck_high }) = fault {
+            faults += 1;
+            let _ = write!(
+                events,
+                " wiring-fault({})",
+                if stuck_high { "high" } else { "low" }
+            );
+        } else if fault.is_some() {
+            events.push_str(" wiring-fault-cleared");
+        }
+        let valid = !rail_monitor.is_faulted();
+        let field_mt = config.field_mt(sample.voltage_mv);
+
+        if valid {
+            if let Some(gesture) = gestures.update(timestamp_us, field_mt) {
+                gesture_count += 1;
+                let _ = write!(events, " gesture({gesture:?})");
+            }
+            if let Some(Event::ThresholdCrossed { rising, .. }) =
+                threshold.update(sample.voltage_mv, &config)
+            {
+                crossings += 1;
+                let _ = write!(events, " {}", if rising { "rising" } else { "falling" });
+                if rising {
+                    tachometer.pulse(timestamp_us);
+                }
+            }
+        }
+        let now_rpm = tachometer.rpm(timestamp_us);
+        if now_rpm != rpm {
+            rpm = now_rpm;
+            let _ = write!(events, " rpm={rpm}");
+        }
+
+        if i % options.every != 0 && events.is_empty() {
+            continue;
+        }
+        let colour = if valid {
+            let color = color::voltage_to_color(sample.voltage_mv, &config, options.palette);
+            let hex = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+            if options.plain {
+                hex
+            } else {
+                format!("{} {hex}", swatch(color))
+            }
+        } else {
+            "fault".into()
+        };
+        println!(
+            "{:>11.1} {:>6} {:>8.2}  {colour}{events}",
+            timestamp_us as f64 / 1000.0,
+            sample.voltage_mv,
+            field_mt
+        );
+    }
+    println!(
+        "{} samples, {crossings} crossings, {gesture_count} gestures, {faults} wiring faults, \
+         final rpm {rpm}",
+        samples.len()
+    );
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,64 @@
+//! Activity detection for adaptive sampling: the field counts as active
+//! while it changes faster than `Config::activity_mt_per_s`, and for a
+//! while after.
+
+use crate::schema::Config;
+
+// How long sampling stays fast after the last sign of activity
+const HOLD_US: u64 = 5_000_000;
+
+pub struct ActivityDetector {
+    last: Option<(u64, f32)>,
+    active_until_us: Option<u64>,
+}
+
+impl ActivityDetector {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            active_until_us: None,
+        }
+    }
+
+    /// Whether the field is active as of this reading. It is until the
+    /// second reading, as there is no rate of change to go on before that.
+    pub fn update(&mut self, timestamp_us: u64, field_mt: f32, config: &Config) -> bool {
+        if let Some((last_us, last_mt)) = self.last.replace((timestamp_us, field_mt))
+            && timestamp_us > last_us
+        {
+            let rate = (field_mt - last_mt).abs() * 1e6 / (timestamp_us - last_us) as f32;
+            if rate >= config.activity_mt_per_s {
+                self.active_until_us = Some(timestamp_us + HOLD_US);
+            }
+        } else {
+            self.active_until_us = Some(timestamp_us + HOLD_US);
+        }
+        self.active_until_us
+            .is_some_and(|until_us| timestamp_us < until_us)
+    }
+}
+
+impl Default for ActivityDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 20mT/s is the default activity rate
+    #[test]
+    fn active_until_the_hold_expires() {
+        let config = Config::default();
+        let mut detector = ActivityDetector::new();
+        assert!(detector.update(0, 0.0, &config));
+        assert!(detector.update(100_000, 0.1, &config));
+        assert!(!detector.update(5_000_000, 0.1, &config));
+        // 1mT in 10ms is 100mT/s
+        assert!(detector.update(5_010_000, 1.1, &config));
+        assert!(detector.update(10_009_999, 1.1, &config));
+        assert!(!detector.update(10_010_000, 1.1, &config));
+    }
+}
@@ -0,0 +1,146 @@
+//! Allan deviation of the field reading, for telling the sensor's and the
+//! ADC's drift from their noise over runs of hours. Averaging more readings
+//! lowers the deviation while noise dominates and raises it once drift
+//! does; the averaging time at the bottom of that curve is the longest
+//! worth using.
+//!
+//! Averaging times are the sample period doubled at each level, up to
+//! [`MAX_LEVELS`]. Each level keeps one average of its own and hands pairs
+//! of them to the level above, so a run of any length takes the same
+//! memory. The averages do not overlap, which costs confidence at the
+//! longest times but needs nothing of the history.
+
+use crate::spectrum::sqrt;
+
+/// Up to 2^23 sample periods, 23 hours at 10ms.
+pub const MAX_LEVELS: usize = 24;
+
+#[derive(Clone, Copy)]
+struct Level {
+    /// The last complete average, to difference the next against.
+    previous: Option<f64>,
+    /// The first of a pair still to be handed up.
+    pending: Option<f64>,
+    sum_squares: f64,
+    count: u32,
+}
+
+impl Level {
+    const EMPTY: Self = Self {
+        previous: None,
+        pending: None,
+        sum_squares: 0.0,
+        count: 0,
+    };
+}
+
+#[derive(Clone, Copy)]
+pub struct Allan {
+    levels: [Level; MAX_LEVELS],
+    samples: u64,
+}
+
+impl Allan {
+    pub const fn new() -> Self {
+        Self {
+            levels: [Level::EMPTY; MAX_LEVELS],
+            samples: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        self.samples += 1;
+        let mut average = value as f64;
+        for level in &mut self.levels {
+            if let Some(previous) = level.previous {
+                let difference = average - previous;
+                level.sum_squares += difference * difference;
+                level.count += 1;
+            }
+            level.previous = Some(average);
+            match level.pending.take() {
+                Some(first) => average = (first + average) / 2.0,
+                None => {
+                    level.pending = Some(average);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// At an averaging time of 2^`level` sample periods, in the units
+    /// pushed; `None` until two averages have been taken.
+    pub fn deviation(&self, level: usize) -> Option<f32> {
+        let level = self.levels.get(level).filter(|l| l.count > 0)?;
+        Some(sqrt((level.sum_squares / (2 * level.count) as f64) as f32))
+    }
+
+    /// Differences behind the deviation at `level`: its confidence grows
+    /// with their square root.
+    pub fn count(&self, level: usize) -> u32 {
+        self.levels.get(level).map_or(0, |l| l.count)
+    }
+}
+
+impl Default for Allan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_noise_averages_down() {
+        // Uniform over -1 to 1 from xorshift, a deviation of 1/sqrt(3)
+        let mut allan = Allan::new();
+        let mut state = 0x1234_5678u32;
+        for _ in 0..1 << 16 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            allan.push(state as f32 / u32::MAX as f32 * 2.0 - 1.0);
+        }
+        let sigma = 1.0 / 3.0f32.sqrt();
+        let at_1 = allan.deviation(0).unwrap();
+        let at_16 = allan.deviation(4).unwrap();
+        assert!((at_1 - sigma).abs() < sigma * 0.02, "{at_1}");
+        assert!((at_16 - sigma / 4.0).abs() < sigma / 4.0 * 0.1, "{at_16}");
+        assert_eq!(allan.count(4), (1 << 12) - 1);
+        assert_eq!(allan.samples(), 1 << 16);
+    }
+
+    #[test]
+    fn drift_grows_with_averaging_time() {
+        // A ramp's averages 2^level apart differ by 2^level steps
+        let mut allan = Allan::new();
+        for i in 0..1024 {
+            allan.push(i as f32 * 0.01);
+        }
+        for level in 0..8 {
+            let expected = 0.01 * (1 << level) as f32 / 2.0f32.sqrt();
+            let found = allan.deviation(level).unwrap();
+            assert!(
+                (found - expected).abs() < expected * 1e-3,
+                "{level}: {found}"
+            );
+        }
+    }
+
+    #[test]
+    fn needs_two_averages() {
+        let mut allan = Allan::new();
+        allan.push(1.0);
+        assert_eq!(allan.deviation(0), None);
+        allan.push(1.0);
+        assert_eq!(allan.deviation(0), Some(0.0));
+        assert_eq!(allan.deviation(1), None);
+        assert_eq!(allan.deviation(MAX_LEVELS), None);
+    }
+}
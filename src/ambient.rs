@@ -0,0 +1,144 @@
+//! Ambient light: a photoresistor, or an analog light sensor, read on a
+//! spare ADC channel sets the LED's brightness, from a minimum in the dark
+//! to a maximum in full light, so the LED is not glaring at night nor lost
+//! in daylight. The light level is smoothed, so a passing shadow or a lamp
+//! switched on eases the LED to its new brightness rather than jumping it.
+//!
+//! The photoresistor goes from 3.3V to the pin, with 10k from the pin to
+//! ground; a light sensor's output, rising with the light, goes straight
+//! to the pin.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 6;
+
+/// The pin's voltage in the dark, and in light bright enough for the
+/// maximum.
+pub const DARK_MV: u32 = 100;
+pub const BRIGHT_MV: u32 = 2500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    pub enabled: bool,
+    /// Of the brightness set, in the dark and in full light, in percent.
+    pub min_pct: u8,
+    pub max_pct: u8,
+    /// Time constant of the smoothing; zero follows the light as it is.
+    pub smoothing_s: u16,
+}
+
+impl Settings {
+    /// Off until set, then from a tenth of the brightness in the dark.
+    pub const DEFAULT: Self = Self {
+        enabled: false,
+        min_pct: 10,
+        max_pct: 100,
+        smoothing_s: 5,
+    };
+
+    /// `brightness`, out of 255, scaled for a light level from 0 in the
+    /// dark to 1 in full light.
+    pub fn scale(&self, brightness: u8, level: f32) -> u8 {
+        if !self.enabled {
+            return brightness;
+        }
+        let (min, max) = (self.min_pct.min(100) as f32, self.max_pct.min(100) as f32);
+        let pct = min + (max - min) * level.clamp(0.0, 1.0);
+        (brightness as f32 * pct / 100.0 + 0.5) as u8
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The light level for the pin's voltage, from 0 in the dark to 1 in full
+/// light.
+pub fn level(voltage_mv: u32) -> f32 {
+    let above_dark_mv = voltage_mv.clamp(DARK_MV, BRIGHT_MV) - DARK_MV;
+    above_dark_mv as f32 / (BRIGHT_MV - DARK_MV) as f32
+}
+
+/// Smooths the light level with a first-order low-pass.
+pub struct Smoother {
+    /// The smoothed level, and when it was last updated.
+    state: Option<(f32, u64)>,
+}
+
+impl Smoother {
+    pub const fn new() -> Self {
+        Self { state: None }
+    }
+
+    /// Takes a light level read at `time_ms`, returning the smoothed level.
+    /// The first is taken as it is.
+    pub fn update(&mut self, time_ms: u64, level: f32, smoothing_s: u16) -> f32 {
+        let smoothed = match self.state {
+            Some((smoothed, last_ms)) if smoothing_s > 0 => {
+                let dt_s = time_ms.saturating_sub(last_ms) as f32 / 1000.0;
+                let alpha = dt_s / (smoothing_s as f32 + dt_s);
+                smoothed + alpha * (level - smoothed)
+            }
+            _ => level,
+        };
+        self.state = Some((smoothed, time_ms));
+        smoothed
+    }
+
+    pub fn level(&self) -> Option<f32> {
+        self.state.map(|(smoothed, _)| smoothed)
+    }
+}
+
+impl Default for Smoother {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADAPTIVE: Settings = Settings {
+        enabled: true,
+        min_pct: 20,
+        max_pct: 80,
+        smoothing_s: 5,
+    };
+
+    #[test]
+    fn levels_span_dark_to_full_light() {
+        assert_eq!(level(0), 0.0);
+        assert_eq!(level(DARK_MV), 0.0);
+        assert_eq!(level((DARK_MV + BRIGHT_MV) / 2), 0.5);
+        assert_eq!(level(BRIGHT_MV), 1.0);
+        assert_eq!(level(3300), 1.0);
+    }
+
+    #[test]
+    fn scales_between_the_minimum_and_maximum() {
+        assert_eq!(ADAPTIVE.scale(255, 0.0), 51);
+        assert_eq!(ADAPTIVE.scale(255, 0.5), 128);
+        assert_eq!(ADAPTIVE.scale(255, 1.0), 204);
+        assert_eq!(ADAPTIVE.scale(100, 2.0), 80);
+        assert_eq!(Settings::DEFAULT.scale(255, 0.0), 255);
+    }
+
+    #[test]
+    fn smooths_changes_in_light() {
+        let mut smoother = Smoother::new();
+        assert_eq!(smoother.level(), None);
+        assert_eq!(smoother.update(0, 1.0, 5), 1.0);
+        // After one time constant in the dark, about 1/e of the light is left
+        let mut smoothed = 1.0;
+        for step in 1..=50 {
+            smoothed = smoother.update(step * 100, 0.0, 5);
+        }
+        assert!((0.3..0.4).contains(&smoothed), "{}", smoothed);
+        assert_eq!(smoother.update(5100, 0.0, 0), 0.0);
+    }
+}
@@ -0,0 +1,46 @@
+//! Retry bookkeeping for subsystems that can fail transiently.
+
+/// Exponential backoff counted in attempts: after the n-th consecutive
+/// failure the next `2^n - 1` attempts are skipped, and after `max_failures`
+/// the subsystem is given up on.
+pub struct Backoff {
+    failures: u32,
+    skip: u32,
+    max_failures: u32,
+}
+
+// Longest pause is 2^MAX_SHIFT - 1 attempts
+const MAX_SHIFT: u32 = 10;
+
+impl Backoff {
+    pub const fn new(max_failures: u32) -> Self {
+        Self {
+            failures: 0,
+            skip: 0,
+            max_failures,
+        }
+    }
+
+    /// Whether the subsystem should be tried this time round.
+    pub fn ready(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn success(&mut self) {
+        self.failures = 0;
+        self.skip = 0;
+    }
+
+    /// Records a failure. Returns `false` once the subsystem should be
+    /// disabled.
+    pub fn failure(&mut self) -> bool {
+        self.failures += 1;
+        self.skip = (1 << self.failures.min(MAX_SHIFT)) - 1;
+        self.failures < self.max_failures
+    }
+}
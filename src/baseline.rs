@@ -0,0 +1,131 @@
+//! Auto-zero: slow tracking of the zero-field output as the sensor and its
+//! supply drift with temperature. The zero follows the reading with a long
+//! time constant, but only while the field is quiet and close to zero: a
+//! magnet held still near the sensor is a genuine field, and tracking it
+//! would null it out.
+
+use crate::schema::Config;
+
+/// Readings further than this from the zero are taken for a real field,
+/// and freeze the tracking; thermal drift stays well inside it.
+pub const FREEZE_BAND_MV: f32 = 20.0;
+
+pub struct Baseline {
+    /// The zero with its fraction of a millivolt.
+    zero_mv: Option<f32>,
+    last_us: Option<u64>,
+    frozen: bool,
+}
+
+impl Baseline {
+    pub const fn new() -> Self {
+        Self {
+            zero_mv: None,
+            last_us: None,
+            frozen: false,
+        }
+    }
+
+    /// Moves the zero toward `voltage_mv` by the share of `time_constant_s`
+    /// since the last reading, unless the field is `active` or outside
+    /// [`FREEZE_BAND_MV`]. Returns the new zero when it reaches another
+    /// whole millivolt. A zero set elsewhere, say by calibration, is taken
+    /// up as it is.
+    pub fn update(
+        &mut self,
+        timestamp_us: u64,
+        voltage_mv: u32,
+        active: bool,
+        time_constant_s: u32,
+        config: &Config,
+    ) -> Option<u32> {
+        let zero_mv = match self.zero_mv {
+            Some(zero_mv) if (zero_mv + 0.5) as u32 == config.zero_field_mv => zero_mv,
+            _ => config.zero_field_mv as f32,
+        };
+        let elapsed_us = self
+            .last_us
+            .replace(timestamp_us)
+            .map_or(0, |last_us| timestamp_us.saturating_sub(last_us));
+
+        let offset_mv = voltage_mv as f32 - zero_mv;
+        self.frozen = active || offset_mv.abs() > FREEZE_BAND_MV;
+        let weight = if self.frozen {
+            0.0
+        } else {
+            // Capped, so a long gap between readings cannot overshoot
+            (elapsed_us as f32 / (time_constant_s.max(1) as f32 * 1e6)).min(1.0)
+        };
+        let zero_mv = zero_mv + offset_mv * weight;
+        self.zero_mv = Some(zero_mv);
+        let rounded_mv = (zero_mv + 0.5) as u32;
+        (rounded_mv != config.zero_field_mv).then_some(rounded_mv)
+    }
+
+    /// Whether the last reading held the zero where it was.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `mv(t)` every 100ms for `seconds`, updating `config` as the
+    /// application does.
+    fn run(
+        baseline: &mut Baseline,
+        config: &mut Config,
+        seconds: u64,
+        active: bool,
+        mv: impl Fn(u64) -> u32,
+    ) {
+        for i in 0..seconds * 10 {
+            let timestamp_us = i * 100_000;
+            if let Some(zero_mv) = baseline.update(timestamp_us, mv(i), active, 10, config) {
+                config.zero_field_mv = zero_mv;
+            }
+        }
+    }
+
+    #[test]
+    fn follows_slow_drift() {
+        let mut config = Config::default();
+        let mut baseline = Baseline::new();
+        // 10mV over 100s, against a 10s time constant
+        run(&mut baseline, &mut config, 200, false, |i| {
+            1650 + (i / 100).min(10) as u32
+        });
+        assert_eq!(config.zero_field_mv, 1660);
+        assert!(!baseline.is_frozen());
+    }
+
+    #[test]
+    fn holds_for_a_real_field() {
+        let mut config = Config::default();
+        let mut baseline = Baseline::new();
+        run(&mut baseline, &mut config, 100, false, |_| 1750);
+        assert_eq!(config.zero_field_mv, 1650);
+        assert!(baseline.is_frozen());
+
+        let mut baseline = Baseline::new();
+        run(&mut baseline, &mut config, 100, true, |_| 1655);
+        assert_eq!(config.zero_field_mv, 1650);
+    }
+
+    #[test]
+    fn takes_up_a_new_calibration() {
+        let mut config = Config::default();
+        let mut baseline = Baseline::new();
+        assert_eq!(baseline.update(0, 1650, false, 10, &config), None);
+        config.zero_field_mv = 1700;
+        assert_eq!(baseline.update(100_000, 1700, false, 10, &config), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Battery voltage, measured through a divider on a second ADC channel, and
+//! the low-battery state and load shedding stage.
+
+use crate::schema::{Config, Event, PowerStage};
+
+/// Two equal resistors, so a full single-cell Li-ion (4.2V) reads 2.1V.
+pub const DIVIDER_RATIO: u32 = 2;
+
+// How far above a stage's threshold the battery must be to leave it
+const HYSTERESIS_MV: u32 = 100;
+
+// Lower readings mean no cell is fitted, e.g. on USB power, rather than a
+// flat one; a Li-ion cell's protection cuts off above this
+const NO_BATTERY_MV: u32 = 2500;
+
+const REPORT_MS: u64 = 60_000;
+
+// Resting single-cell Li-ion voltage at 0%, 10%, ... 100% charge
+const CHARGE_CURVE_MV: [u32; 11] = [
+    3300, 3600, 3690, 3750, 3780, 3820, 3870, 3920, 3980, 4060, 4200,
+];
+
+/// Battery voltage from the voltage at the ADC pin.
+pub fn battery_mv(pin_mv: u32) -> u32 {
+    pin_mv * DIVIDER_RATIO
+}
+
+/// Approximate state of charge, interpolated from a typical discharge curve.
+pub fn percent(battery_mv: u32) -> u8 {
+    let Some(i) = CHARGE_CURVE_MV.iter().position(|&mv| battery_mv < mv) else {
+        return 100;
+    };
+    if i == 0 {
+        return 0;
+    }
+    let (lo, hi) = (CHARGE_CURVE_MV[i - 1], CHARGE_CURVE_MV[i]);
+    ((i as u32 - 1) * 10 + (battery_mv - lo) * 10 / (hi - lo)) as u8
+}
+
+/// Tracks the load shedding stage and decides when to report the battery.
+pub struct BatteryMonitor {
+    stage: PowerStage,
+    report_ms: Option<u64>,
+}
+
+impl BatteryMonitor {
+    pub const fn new() -> Self {
+        Self {
+            stage: PowerStage::Normal,
+            report_ms: None,
+        }
+    }
+
+    pub fn stage(&self) -> PowerStage {
+        self.stage
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.stage != PowerStage::Normal
+    }
+
+    /// Returns a [`Event::Battery`] once a minute, and straight away when
+    /// the stage changes.
+    pub fn update(&mut self, now_ms: u64, voltage_mv: u32, config: &Config) -> Option<Event> {
+        let stage = self.stage_for(voltage_mv, config);
+        let changed = stage != self.stage;
+        self.stage = stage;
+
+        if !changed && self.report_ms.is_some_and(|at| now_ms - at < REPORT_MS) {
+            return None;
+        }
+        self.report_ms = Some(now_ms);
+        Some(Event::Battery {
+            voltage_mv,
+            low: self.is_low(),
+        })
+    }
+
+    fn stage_for(&self, voltage_mv: u32, config: &Config) -> PowerStage {
+        if voltage_mv < NO_BATTERY_MV {
+            return PowerStage::Normal;
+        }
+        [
+            (PowerStage::Shutdown, config.battery_shutdown_mv),
+            (PowerStage::Reduced, config.battery_reduced_mv),
+            (PowerStage::Dimmed, config.low_battery_mv),
+        ]
+        .into_iter()
+        .filter(|&(_, threshold_mv)| threshold_mv > 0)
+        .find(|&(stage, threshold_mv)| {
+            // Stages already reached take the hysteresis to leave
+            let threshold_mv = if stage <= self.stage {
+                threshold_mv + HYSTERESIS_MV
+            } else {
+                threshold_mv
+            };
+            voltage_mv < threshold_mv
+        })
+        .map_or(PowerStage::Normal, |(stage, _)| stage)
+    }
+}
+
+impl Default for BatteryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_follows_the_curve() {
+        assert_eq!(percent(3000), 0);
+        assert_eq!(percent(3300), 0);
+        assert_eq!(percent(3600), 10);
+        assert_eq!(percent(3645), 15);
+        assert_eq!(percent(4200), 100);
+        assert_eq!(percent(4300), 100);
+    }
+
+    fn stages(voltages: &[u32]) -> Vec<PowerStage> {
+        let config = Config::default();
+        let mut monitor = BatteryMonitor::new();
+        voltages
+            .iter()
+            .map(|&mv| {
+                monitor.update(0, mv, &config);
+                monitor.stage()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stages_with_hysteresis() {
+        use PowerStage::*;
+        // Thresholds 3500, 3400 and 3300mV
+        assert_eq!(
+            stages(&[3700, 3499, 3550, 3600, 3399, 3450, 3299]),
+            [Normal, Dimmed, Dimmed, Normal, Reduced, Reduced, Shutdown]
+        );
+    }
+
+    #[test]
+    fn no_cell_reads_normal() {
+        assert_eq!(
+            stages(&[3450, 100]),
+            [PowerStage::Dimmed, PowerStage::Normal]
+        );
+    }
+
+    #[test]
+    fn zero_threshold_disables_stage() {
+        let config = Config {
+            low_battery_mv: 0,
+            ..Config::default()
+        };
+        let mut monitor = BatteryMonitor::new();
+        monitor.update(0, 3450, &config);
+        assert_eq!(monitor.stage(), PowerStage::Normal);
+        assert!(!monitor.is_low());
+    }
+
+    #[test]
+    fn reports_once_a_minute_or_on_change() {
+        let config = Config::default();
+        let mut monitor = BatteryMonitor::new();
+        assert!(monitor.update(0, 3900, &config).is_some());
+        assert!(monitor.update(30_000, 3900, &config).is_none());
+        assert!(monitor.update(40_000, 3450, &config).is_some());
+        assert!(monitor.update(99_999, 3450, &config).is_none());
+        assert!(monitor.update(100_000, 3450, &config).is_some());
+    }
+}
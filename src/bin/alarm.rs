@@ -0,0 +1,18 @@
+//! Alarm muting. Threshold crossings are still detected, recorded and
+//! published while muted; only the outputs that draw attention to them
+//! (the crossing log line and the haptic buzz) stay quiet.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+static MUTED: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+pub fn is_muted() -> bool {
+    critical_section::with(|cs| MUTED.borrow(cs).get())
+}
+
+#[cfg_attr(not(feature = "ir-remote"), expect(dead_code))]
+pub fn set_muted(muted: bool) {
+    critical_section::with(|cs| MUTED.borrow(cs).set(muted));
+}
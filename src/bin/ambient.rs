@@ -0,0 +1,84 @@
+//! Ambient light, see `hall_effect::ambient`: with the `ambient-light`
+//! feature, a photoresistor or light sensor on GPIO6 (ADC1 channel 5, or 6
+//! on the C6) is read by the sampler between sensor samples, as the
+//! battery is, and sets the LED's brightness between a minimum and maximum
+//! set with `ambient` on the console and kept in the `state` partition.
+//! The TM1637 follows it, as it follows the LED's brightness, and night
+//! mode still limits it.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::analog::adc::AdcPin;
+use esp_hal::peripherals::{ADC1, GPIO6};
+use hall_effect::ambient::{self, Settings, Smoother};
+use hall_effect::verbosity::Module;
+
+use crate::chip;
+use crate::sensor::{self, SensorAdc};
+use crate::state::STATE;
+use crate::verbosity::log;
+
+pub type AmbientPin = AdcPin<GPIO6<'static>, ADC1<'static>, chip::AdcCal<ADC1<'static>>>;
+
+/// Time between light readings.
+pub const PERIOD_US: u64 = 200_000;
+
+static PIN: Mutex<RefCell<Option<AmbientPin>>> = Mutex::new(RefCell::new(None));
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static SMOOTHER: Mutex<RefCell<Smoother>> = Mutex::new(RefCell::new(Smoother::new()));
+
+/// Hands the pin to the sampler.
+pub fn init(pin: AmbientPin) {
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next colour shown, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_ambient_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Ambient light not saved: {}", e);
+    }
+}
+
+/// The smoothed light level, from 0 in the dark to 1 in full light, once
+/// the sensor has been read.
+pub fn level() -> Option<f32> {
+    critical_section::with(|cs| SMOOTHER.borrow_ref(cs).level())
+}
+
+/// `brightness`, out of 255, as the light sets it now; as it is until the
+/// sensor has been read.
+pub fn scale(brightness: u8) -> u8 {
+    level().map_or(brightness, |level| settings().scale(brightness, level))
+}
+
+/// Reads the light sensor on the sampler's ADC.
+pub async fn sample(adc: &mut SensorAdc) {
+    let Some(mut pin) = critical_section::with(|cs| PIN.borrow_ref_mut(cs).take()) else {
+        return;
+    };
+    match sensor::read_sample(|| adc.read_oneshot(&mut pin)).await {
+        Ok(sample) => {
+            let level = ambient::level(sample.voltage_mv);
+            let (time_ms, smoothing_s) = (sample.timestamp_us / 1000, settings().smoothing_s);
+            critical_section::with(|cs| {
+                let mut smoother = SMOOTHER.borrow_ref_mut(cs);
+                smoother.update(time_ms, level, smoothing_s);
+            });
+        }
+        Err(e) => log!(Module::Led, warn, "Light reading skipped: {}", e),
+    }
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
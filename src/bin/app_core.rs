@@ -0,0 +1,40 @@
+//! The ESP32-S3's second core, in the `dual-core` build. The sampler and
+//! processing stage keep the first core to themselves, and the tasks that
+//! block on slow peripherals (the LED, displays and loggers) run on an
+//! executor of their own here, so a long SPI transfer can no longer hold up
+//! a sample. The console stays on the first core: its async port is not
+//! `Send`, as its interrupt is bound to the core that set it up.
+//!
+//! Everything the two sides share is behind a critical section or an
+//! embassy-sync primitive on `CriticalSectionRawMutex`, which esp-hal makes
+//! safe across cores.
+
+use embassy_executor::SendSpawner;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use esp_hal::interrupt::software::SoftwareInterrupt;
+use esp_hal::peripherals::CPU_CTRL;
+use esp_hal::system::Stack;
+use esp_rtos::embassy::Executor;
+use static_cell::StaticCell;
+
+// The display tasks' frame buffers live in statics, so this is mostly
+// futures
+const STACK_SIZE: usize = 16 * 1024;
+
+static STACK: StaticCell<Stack<STACK_SIZE>> = StaticCell::new();
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+static SPAWNER: Signal<CriticalSectionRawMutex, SendSpawner> = Signal::new();
+
+/// Starts the second core's executor and returns a spawner for it.
+pub async fn start(
+    cpu_ctrl: CPU_CTRL<'static>,
+    int0: SoftwareInterrupt<'static, 0>,
+    int1: SoftwareInterrupt<'static, 1>,
+) -> SendSpawner {
+    esp_rtos::start_second_core(cpu_ctrl, int0, int1, STACK.init(Stack::new()), || {
+        let executor = EXECUTOR.init(Executor::new());
+        executor.run(|spawner| SPAWNER.signal(spawner.make_send()));
+    });
+    SPAWNER.wait().await
+}
@@ -0,0 +1,87 @@
+//! Auto-zero, switched on from the console with `autozero <seconds>`: the
+//! processing stage moves the zero-field output after the sensor's thermal
+//! drift while the field is quiet.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::activity::ActivityDetector;
+use hall_effect::baseline::Baseline;
+use hall_effect::schema::{Config, Sample};
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    /// `None` while auto-zero is off.
+    pub time_constant_s: Option<u32>,
+    /// The zero as last moved, if it has been.
+    pub zero_field_mv: Option<u32>,
+    pub frozen: bool,
+}
+
+static STATUS: Mutex<Cell<Status>> = Mutex::new(Cell::new(Status {
+    time_constant_s: None,
+    zero_field_mv: None,
+    frozen: false,
+}));
+
+/// Switches auto-zero on with `time_constant_s`, or off.
+pub fn configure(time_constant_s: Option<u32>) {
+    critical_section::with(|cs| {
+        let status = STATUS.borrow(cs);
+        status.set(Status {
+            time_constant_s,
+            ..status.get()
+        });
+    });
+}
+
+pub fn status() -> Status {
+    critical_section::with(|cs| STATUS.borrow(cs).get())
+}
+
+/// The processing stage's half: a baseline, and activity detection of its
+/// own to freeze it.
+pub struct Tracker {
+    baseline: Baseline,
+    activity: ActivityDetector,
+}
+
+impl Tracker {
+    pub const fn new() -> Self {
+        Self {
+            baseline: Baseline::new(),
+            activity: ActivityDetector::new(),
+        }
+    }
+
+    /// Takes a valid reading, outside calibration, and returns the new zero
+    /// for `config` when it moves.
+    pub fn update(&mut self, sample: &Sample, config: &Config) -> Option<u32> {
+        let time_constant_s = status().time_constant_s?;
+        let field_mt = config.field_mt(sample.voltage_mv);
+        let active = self.activity.update(sample.timestamp_us, field_mt, config);
+        let zero_field_mv = self.baseline.update(
+            sample.timestamp_us,
+            sample.voltage_mv,
+            active,
+            time_constant_s,
+            config,
+        );
+        let frozen = self.baseline.is_frozen();
+        critical_section::with(|cs| {
+            let status = STATUS.borrow(cs);
+            status.set(Status {
+                frozen,
+                zero_field_mv: zero_field_mv.or(status.get().zero_field_mv),
+                ..status.get()
+            });
+        });
+        if let Some(zero_field_mv) = zero_field_mv {
+            log!(Module::Sensor, info, "Auto-zero: {}mV", zero_field_mv);
+        }
+        zero_field_mv
+    }
+}
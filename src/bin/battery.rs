@@ -0,0 +1,66 @@
+//! Battery voltage through a divider on GPIO3 (ADC1 channel 2, or 3 on the
+//! C3 and C6), read by the sampler between sensor samples, since both share
+//! ADC1, and the load shedding stage the processing stage derives from it.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use esp_hal::analog::adc::AdcPin;
+use esp_hal::peripherals::{ADC1, GPIO3};
+use hall_effect::battery;
+use hall_effect::schema::PowerStage;
+use hall_effect::verbosity::Module;
+
+use crate::chip;
+use crate::sensor::{self, SensorAdc};
+use crate::verbosity::log;
+
+pub type BatteryPin = AdcPin<GPIO3<'static>, ADC1<'static>, chip::AdcCal<ADC1<'static>>>;
+
+/// Time between battery readings.
+pub const PERIOD_US: u64 = 1_000_000;
+
+static PIN: Mutex<RefCell<Option<BatteryPin>>> = Mutex::new(RefCell::new(None));
+
+static VOLTAGE_MV: Mutex<Cell<Option<u32>>> = Mutex::new(Cell::new(None));
+
+static STAGE: Mutex<Cell<PowerStage>> = Mutex::new(Cell::new(PowerStage::Normal));
+
+/// Readings on their way to the processing stage.
+pub static READINGS: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+/// Hands the pin to the sampler.
+pub fn init(pin: BatteryPin) {
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
+
+/// The latest battery voltage, if one has been read.
+pub fn voltage_mv() -> Option<u32> {
+    critical_section::with(|cs| VOLTAGE_MV.borrow(cs).get())
+}
+
+pub fn stage() -> PowerStage {
+    critical_section::with(|cs| STAGE.borrow(cs).get())
+}
+
+pub fn set_stage(stage: PowerStage) {
+    critical_section::with(|cs| STAGE.borrow(cs).set(stage));
+}
+
+/// Reads the battery on the sampler's ADC.
+pub async fn sample(adc: &mut SensorAdc) {
+    let Some(mut pin) = critical_section::with(|cs| PIN.borrow_ref_mut(cs).take()) else {
+        return;
+    };
+    match sensor::read_sample(|| adc.read_oneshot(&mut pin)).await {
+        Ok(sample) => {
+            let voltage_mv = battery::battery_mv(sample.voltage_mv);
+            critical_section::with(|cs| VOLTAGE_MV.borrow(cs).set(Some(voltage_mv)));
+            READINGS.signal(voltage_mv);
+        }
+        Err(e) => log!(Module::Supply, warn, "Battery reading skipped: {}", e),
+    }
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
@@ -0,0 +1,236 @@
+//! Brushless motor control, with the `bldc` feature: three hall sensors on
+//! GPIO15, 16 and 17, pulled up for open-collector outputs, and a
+//! three-phase bridge from MCPWM0, with the high sides on GPIO5, 6 and 7
+//! and the low sides on GPIO10, 11 and 12, all active high.
+//!
+//! Each hall edge commutates straight from the GPIO interrupt. A control
+//! task sets the duty every 10ms to hold the speed set with `motor` on the
+//! console, and drives the sector the rotor is in, which starts it from a
+//! standstill.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::{CriticalSection, Mutex};
+use embassy_time::{Duration, Ticker};
+use esp_hal::gpio::{AnyPin, Event, Input, InputConfig, Pull};
+use esp_hal::handler;
+use esp_hal::mcpwm::operator::{PwmPin, PwmPinConfig};
+use esp_hal::mcpwm::timer::PwmWorkingMode;
+use esp_hal::mcpwm::{McPwm, PeripheralClockConfig};
+use esp_hal::peripherals::MCPWM0;
+use esp_hal::time::Rate;
+use hall_effect::bldc::{self, Drive, SpeedMeter};
+use hall_effect::pid::{Gains, Pid};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::verbosity::log;
+
+const CONTROL_PERIOD: Duration = Duration::from_millis(10);
+const MCPWM_CLOCK: Rate = Rate::from_mhz(40);
+// Above hearing, with 2000 steps of duty from the 40MHz clock
+const PWM_FREQUENCY: Rate = Rate::from_khz(20);
+const PERIOD: u16 = 1999;
+// A common outrunner's; the speed reads in proportion to it
+const POLE_PAIRS: u32 = 7;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// Zero to stop, with the bridge off.
+    pub setpoint_rpm: u32,
+    pub reverse: bool,
+    pub gains: Gains,
+}
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    pub rpm: f32,
+    /// From 0 to 1.
+    pub duty: f32,
+    /// `None` with the halls all high or all low.
+    pub sector: Option<u8>,
+}
+
+pub struct Pins {
+    pub high: [AnyPin<'static>; 3],
+    pub low: [AnyPin<'static>; 3],
+    pub halls: [AnyPin<'static>; 3],
+}
+
+type Phase<const OP: u8> = (
+    PwmPin<'static, MCPWM0<'static>, OP, true>,
+    PwmPin<'static, MCPWM0<'static>, OP, false>,
+);
+
+struct Bridge {
+    a: Phase<0>,
+    b: Phase<1>,
+    c: Phase<2>,
+}
+
+impl Bridge {
+    fn apply(&mut self, phases: [Drive; 3], duty: f32) {
+        let high = (duty.clamp(0.0, 1.0) * (PERIOD + 1) as f32) as u16;
+        // Past the period, the low side stays on for the whole of it
+        let levels = phases.map(|drive| match drive {
+            Drive::High => (high, 0),
+            Drive::Low => (0, PERIOD + 1),
+            Drive::Float => (0, 0),
+        });
+        self.a.0.set_timestamp(levels[0].0);
+        self.a.1.set_timestamp(levels[0].1);
+        self.b.0.set_timestamp(levels[1].0);
+        self.b.1.set_timestamp(levels[1].1);
+        self.c.0.set_timestamp(levels[2].0);
+        self.c.1.set_timestamp(levels[2].1);
+    }
+}
+
+// Untuned, in duty per rpm: enough to spin up an unloaded motor
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings {
+    setpoint_rpm: 0,
+    reverse: false,
+    gains: Gains {
+        kp: 0.0002,
+        ki: 0.0005,
+        kd: 0.0,
+    },
+}));
+static BRIDGE: Mutex<RefCell<Option<Bridge>>> = Mutex::new(RefCell::new(None));
+static HALLS: Mutex<RefCell<Option<[Input<'static>; 3]>>> = Mutex::new(RefCell::new(None));
+static SECTOR: Mutex<Cell<Option<u8>>> = Mutex::new(Cell::new(None));
+static SPEED: Mutex<RefCell<SpeedMeter>> = Mutex::new(RefCell::new(SpeedMeter::new(POLE_PAIRS)));
+static DUTY: Mutex<Cell<f32>> = Mutex::new(Cell::new(0.0));
+static RPM: Mutex<Cell<f32>> = Mutex::new(Cell::new(0.0));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes effect from the next control period.
+pub fn configure(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+pub fn status() -> Status {
+    critical_section::with(|cs| Status {
+        rpm: RPM.borrow(cs).get(),
+        duty: DUTY.borrow(cs).get(),
+        sector: SECTOR.borrow(cs).get(),
+    })
+}
+
+/// Sets up the bridge, off, and the hall interrupts, at boot. The GPIO
+/// interrupt handler is [`on_hall`].
+pub fn init(mcpwm: MCPWM0<'static>, pins: Pins) {
+    let Ok(clock) = PeripheralClockConfig::with_frequency(MCPWM_CLOCK) else {
+        log!(Module::Sensor, warn, "Motor PWM clock unavailable");
+        return;
+    };
+    let mut mcpwm = McPwm::new(mcpwm, clock);
+    mcpwm.operator0.set_timer(&mcpwm.timer0);
+    mcpwm.operator1.set_timer(&mcpwm.timer0);
+    mcpwm.operator2.set_timer(&mcpwm.timer0);
+    let [high_a, high_b, high_c] = pins.high;
+    let [low_a, low_b, low_c] = pins.low;
+    let active_high = PwmPinConfig::UP_ACTIVE_HIGH;
+    let mut bridge = Bridge {
+        a: mcpwm
+            .operator0
+            .with_pins(high_a, active_high, low_a, active_high),
+        b: mcpwm
+            .operator1
+            .with_pins(high_b, active_high, low_b, active_high),
+        c: mcpwm
+            .operator2
+            .with_pins(high_c, active_high, low_c, active_high),
+    };
+    bridge.apply([Drive::Float; 3], 0.0);
+    match clock.timer_clock_with_frequency(PERIOD, PwmWorkingMode::Increase, PWM_FREQUENCY) {
+        Ok(timer_clock) => mcpwm.timer0.start(timer_clock),
+        Err(e) => {
+            log!(Module::Sensor, warn, "Motor PWM unavailable: {}", e);
+            return;
+        }
+    }
+
+    let pull_up = InputConfig::default().with_pull(Pull::Up);
+    let mut halls = pins.halls.map(|pin| Input::new(pin, pull_up));
+    critical_section::with(|cs| {
+        for hall in &mut halls {
+            hall.listen(Event::AnyEdge);
+        }
+        SECTOR.borrow(cs).set(read_sector(&halls));
+        HALLS.replace(cs, Some(halls));
+        BRIDGE.replace(cs, Some(bridge));
+    });
+}
+
+fn read_sector(halls: &[Input<'static>; 3]) -> Option<u8> {
+    let levels = halls
+        .iter()
+        .enumerate()
+        .fold(0, |levels, (i, hall)| levels | (hall.is_high() as u8) << i);
+    bldc::sector(levels)
+}
+
+/// Drives the sector the rotor is in at the present duty, or nothing.
+fn commutate(cs: CriticalSection) {
+    let duty = DUTY.borrow(cs).get();
+    let reverse = SETTINGS.borrow(cs).get().reverse;
+    let phases = match SECTOR.borrow(cs).get() {
+        Some(sector) if duty > 0.0 => bldc::phases(sector, reverse),
+        _ => [Drive::Float; 3],
+    };
+    if let Some(bridge) = BRIDGE.borrow_ref_mut(cs).as_mut() {
+        bridge.apply(phases, duty);
+    }
+}
+
+#[handler]
+pub fn on_hall() {
+    let timestamp_us = clock::monotonic_us();
+    critical_section::with(|cs| {
+        let sector = {
+            let mut halls = HALLS.borrow_ref_mut(cs);
+            let Some(halls) = halls.as_mut() else {
+                return;
+            };
+            for hall in halls.iter_mut() {
+                hall.clear_interrupt();
+            }
+            read_sector(halls)
+        };
+        SECTOR.borrow(cs).set(sector);
+        if let Some(sector) = sector {
+            SPEED.borrow_ref_mut(cs).push(timestamp_us, sector);
+        }
+        commutate(cs);
+    });
+}
+
+#[embassy_executor::task]
+pub async fn control_task() {
+    let mut pid = Pid::new(settings().gains);
+    let mut ticker = Ticker::every(CONTROL_PERIOD);
+    let dt_s = CONTROL_PERIOD.as_micros() as f32 / 1e6;
+    loop {
+        ticker.next().await;
+        let settings = settings();
+        let now_us = clock::monotonic_us();
+        let rpm = critical_section::with(|cs| SPEED.borrow_ref(cs).rpm(now_us));
+        let duty = if settings.setpoint_rpm == 0 {
+            // Starts over from rest next time
+            pid = Pid::new(settings.gains);
+            0.0
+        } else {
+            pid.set_gains(settings.gains);
+            pid.update(settings.setpoint_rpm as f32, rpm.abs(), dt_s)
+        };
+        critical_section::with(|cs| {
+            DUTY.borrow(cs).set(duty);
+            RPM.borrow(cs).set(rpm);
+            commutate(cs);
+        });
+    }
+}
@@ -0,0 +1,256 @@
+//! Where the sensor, WS2812 LED and user button are wired, chosen at build
+//! time with a `board-*` feature; without one, the chip's own devkit: an
+//! ESP32-S3-DevKitC-1, S2-Saola-1, C3-DevKitM-1 or C6-DevKitC-1. A board
+//! feature enables its chip's feature, so build it with
+//! `--no-default-features` unless the chip is the S3.
+//!
+//! Only these three move with the board. The optional peripherals keep the
+//! DevKitC pin map given in `main`, so check it against the board before
+//! enabling them. The sensor must be on an ADC1 pin and, on the S3 and S2,
+//! the button on an RTC GPIO (0 to 21), which can wake the chip from deep
+//! sleep.
+
+const _: () = assert!(
+    cfg!(feature = "board-m5stamp-c3") as u8
+        + cfg!(feature = "board-s3-devkitc-v11") as u8
+        + cfg!(feature = "board-s3-zero") as u8
+        + cfg!(feature = "board-xiao-s3") as u8
+        <= 1,
+    "Enable at most one board-* feature"
+);
+
+#[cfg(all(
+    feature = "esp32s3",
+    not(any(
+        feature = "board-s3-devkitc-v11",
+        feature = "board-s3-zero",
+        feature = "board-xiao-s3"
+    ))
+))]
+mod pins {
+    use esp_hal::peripherals::{GPIO0, GPIO4, GPIO48};
+
+    pub const NAME: &str = "ESP32-S3-DevKitC-1";
+
+    pub type SensorGpio = GPIO4<'static>;
+    /// SAR ADC1 channel of the sensor pin.
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 3;
+    pub type LedGpio = GPIO48<'static>;
+    pub type ButtonGpio = GPIO0<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO48
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO0
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// ESP32-S2-Saola-1: the WS2812 is on GPIO18.
+#[cfg(feature = "esp32s2")]
+mod pins {
+    use esp_hal::peripherals::{GPIO0, GPIO4, GPIO18};
+
+    pub const NAME: &str = "ESP32-S2-Saola-1";
+
+    pub type SensorGpio = GPIO4<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 3;
+    pub type LedGpio = GPIO18<'static>;
+    pub type ButtonGpio = GPIO0<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO18
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO0
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// ESP32-C3-DevKitM-1 and ESP32-C6-DevKitC-1: the WS2812 is on GPIO8 and
+/// BOOT on GPIO9, and GPIO4 is ADC1 channel 4.
+#[cfg(any(
+    all(feature = "esp32c3", not(feature = "board-m5stamp-c3")),
+    feature = "esp32c6"
+))]
+mod pins {
+    use esp_hal::peripherals::{GPIO4, GPIO8, GPIO9};
+
+    pub const NAME: &str = if cfg!(feature = "esp32c3") {
+        "ESP32-C3-DevKitM-1"
+    } else {
+        "ESP32-C6-DevKitC-1"
+    };
+
+    pub type SensorGpio = GPIO4<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 4;
+    pub type LedGpio = GPIO8<'static>;
+    pub type ButtonGpio = GPIO9<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO8
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO9
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// ESP32-S3-DevKitC-1 v1.1, which moved the WS2812 to GPIO38.
+#[cfg(feature = "board-s3-devkitc-v11")]
+mod pins {
+    use esp_hal::peripherals::{GPIO0, GPIO4, GPIO38};
+
+    pub const NAME: &str = "ESP32-S3-DevKitC-1 v1.1";
+
+    pub type SensorGpio = GPIO4<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 3;
+    pub type LedGpio = GPIO38<'static>;
+    pub type ButtonGpio = GPIO0<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO38
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO0
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// Waveshare ESP32-S3-Zero: the on-board WS2812 is on GPIO21.
+#[cfg(feature = "board-s3-zero")]
+mod pins {
+    use esp_hal::peripherals::{GPIO0, GPIO4, GPIO21};
+
+    pub const NAME: &str = "Waveshare ESP32-S3-Zero";
+
+    pub type SensorGpio = GPIO4<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 3;
+    pub type LedGpio = GPIO21<'static>;
+    pub type ButtonGpio = GPIO0<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO21
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO0
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// Seeed XIAO ESP32S3: the sensor on A0 (GPIO1) and an external WS2812 on
+/// D6 (GPIO43), as the board has no RGB LED of its own.
+#[cfg(feature = "board-xiao-s3")]
+mod pins {
+    use esp_hal::peripherals::{GPIO0, GPIO1, GPIO43};
+
+    pub const NAME: &str = "Seeed XIAO ESP32S3";
+
+    pub type SensorGpio = GPIO1<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 0;
+    pub type LedGpio = GPIO43<'static>;
+    pub type ButtonGpio = GPIO0<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO1
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO43
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO0
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+/// M5Stamp C3: the WS2812 is on GPIO2 and the button on GPIO3, with the
+/// sensor on GPIO4 (ADC1 channel 4) of the pin header.
+#[cfg(feature = "board-m5stamp-c3")]
+mod pins {
+    use esp_hal::peripherals::{GPIO2, GPIO3, GPIO4};
+
+    pub const NAME: &str = "M5Stamp C3";
+
+    pub type SensorGpio = GPIO4<'static>;
+    #[cfg_attr(not(feature = "ulp-wake"), expect(dead_code))]
+    pub const SENSOR_CHANNEL: u32 = 4;
+    pub type LedGpio = GPIO2<'static>;
+    pub type ButtonGpio = GPIO3<'static>;
+
+    macro_rules! sensor_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO4
+        };
+    }
+    macro_rules! led_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO2
+        };
+    }
+    macro_rules! button_pin {
+        ($peripherals:ident) => {
+            $peripherals.GPIO3
+        };
+    }
+    pub(crate) use {button_pin, led_pin, sensor_pin};
+}
+
+pub(crate) use pins::*;
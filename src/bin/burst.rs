@@ -0,0 +1,125 @@
+//! Burst sampling: on request, the sampler reads the sensor back to back at
+//! up to 20kHz into RAM, busy-waiting on the system timer between readings,
+//! and the console summarises or dumps the result (`burst`).
+//!
+//! esp-hal has no continuous (DMA) ADC mode, so each reading is a oneshot
+//! conversion and the executor is blocked for the length of the burst, at
+//! most [`MAX_SAMPLES`] readings or [`MAX_DURATION_MS`]. The achieved rate
+//! is measured and reported in case the conversions cannot keep up.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use esp_hal::time::{Duration, Instant};
+use hall_effect::burst::{self, Summary};
+use hall_effect::verbosity::Module;
+
+use crate::sensor::{self, SensorAdc, SensorPin};
+use crate::verbosity::log;
+use crate::{chip, watchdog};
+
+/// About 0.8s at the highest rate, in 32KB of RAM.
+pub const MAX_SAMPLES: usize = 16_384;
+pub const MIN_RATE_HZ: u32 = 1000;
+pub const MAX_RATE_HZ: u32 = 20_000;
+/// Well inside the hardware watchdog's timeout, which nothing can feed
+/// while the burst runs.
+pub const MAX_DURATION_MS: u32 = 2000;
+
+struct Burst {
+    raw: [u16; MAX_SAMPLES],
+    len: usize,
+    /// Achieved, rather than requested.
+    rate_hz: u32,
+}
+
+static BURST: Mutex<RefCell<Burst>> = Mutex::new(RefCell::new(Burst {
+    raw: [0; MAX_SAMPLES],
+    len: 0,
+    rate_hz: 0,
+}));
+static REQUEST: Signal<CriticalSectionRawMutex, (u32, u32)> = Signal::new();
+
+/// Asks the sampler for a burst at `rate_hz` for `duration_ms`, both
+/// limited to what the buffer and the ADC allow.
+pub fn request(rate_hz: u32, duration_ms: u32) {
+    REQUEST.signal((
+        rate_hz.clamp(MIN_RATE_HZ, MAX_RATE_HZ),
+        duration_ms.min(MAX_DURATION_MS),
+    ));
+}
+
+/// Whether a burst is waiting for [`poll`].
+#[cfg_attr(not(feature = "timer-sampling"), expect(dead_code))]
+pub fn requested() -> bool {
+    REQUEST.signaled()
+}
+
+/// Runs a requested burst, from the sampler between samples.
+pub fn poll(adc: &mut SensorAdc, pin: &mut SensorPin) {
+    let Some((rate_hz, duration_ms)) = REQUEST.try_take() else {
+        return;
+    };
+    let count = (rate_hz as u64 * duration_ms as u64 / 1000).clamp(1, MAX_SAMPLES as u64);
+    log!(
+        Module::Sensor,
+        info,
+        "Burst of {} samples at {}Hz",
+        count,
+        rate_hz
+    );
+    watchdog::feed(watchdog::Task::Sensor);
+
+    let start = Instant::now();
+    let mut len = 0;
+    for i in 0..count {
+        let due = start + Duration::from_micros(i * 1_000_000 / rate_hz as u64);
+        while Instant::now() < due {}
+        // A failed conversion ends the burst early
+        let Ok(raw) = nb::block!(sensor::convert(adc, pin)) else {
+            break;
+        };
+        let raw = raw >> (chip::ADC_BITS - 12);
+        critical_section::with(|cs| BURST.borrow(cs).borrow_mut().raw[len] = raw);
+        len += 1;
+    }
+    let elapsed_us = (Instant::now() - start).as_micros().max(1);
+
+    let rate_hz = (len as u64 * 1_000_000 / elapsed_us) as u32;
+    critical_section::with(|cs| {
+        let mut burst = BURST.borrow(cs).borrow_mut();
+        burst.len = len;
+        burst.rate_hz = rate_hz;
+    });
+    log!(
+        Module::Sensor,
+        info,
+        "Burst done, {} samples at {}Hz",
+        len,
+        rate_hz
+    );
+}
+
+/// The last burst's summary, if there was one.
+pub fn summary() -> Option<Summary> {
+    critical_section::with(|cs| {
+        let burst = BURST.borrow(cs).borrow();
+        (burst.len > 0).then(|| burst::summarize(&burst.raw[..burst.len], burst.rate_hz))
+    })
+}
+
+/// Copies readings into `buf`, each the average of `factor` from the burst,
+/// starting at decimated index `start`. Returns how many were copied.
+pub fn read(start: usize, factor: usize, buf: &mut [u16]) -> usize {
+    critical_section::with(|cs| {
+        let burst = BURST.borrow(cs).borrow();
+        let raw = &burst.raw[..burst.len];
+        let from = (start * factor.max(1)).min(raw.len());
+        buf.iter_mut()
+            .zip(burst::decimate(&raw[from..], factor))
+            .map(|(slot, raw)| *slot = raw)
+            .count()
+    })
+}
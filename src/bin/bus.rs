@@ -0,0 +1,85 @@
+//! Event bus for things that happen, as opposed to the stream of samples:
+//! threshold crossings, faults, calibration, button presses, mode changes,
+//! magnet gestures and tamper alarms. Consumers (logging, LED animations,
+//! network notifiers) each subscribe on their own and never hold up the
+//! publisher.
+
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{PubSubChannel, WaitResult};
+use hall_effect::button::Press;
+use hall_effect::gesture::Gesture;
+use hall_effect::mode::Mode;
+#[cfg(feature = "battery")]
+use hall_effect::schema::PowerStage;
+use hall_effect::schema::Tamper;
+use hall_effect::selftest;
+use hall_effect::verbosity::Module;
+
+use crate::alarm;
+use crate::verbosity::log;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Fault {
+    WiringStuckLow,
+    WiringStuckHigh,
+    LowSupply,
+    SelfTest(selftest::Fault),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum BusEvent {
+    ThresholdCrossed { rising: bool, voltage_mv: u32 },
+    FaultDetected(Fault),
+    FaultCleared(Fault),
+    CalibrationDone { zero_field_mv: u32 },
+    ButtonPressed(Press),
+    ModeChanged { from: Mode, to: Mode },
+    Gesture(Gesture),
+    #[cfg(feature = "battery")]
+    PowerStageChanged(PowerStage),
+    /// A tamper alarm latched, in tamper mode.
+    Tamper(Tamper),
+}
+
+impl BusEvent {
+    /// Log module the event is reported under.
+    fn module(&self) -> Module {
+        match self {
+            BusEvent::FaultDetected(Fault::LowSupply)
+            | BusEvent::FaultCleared(Fault::LowSupply) => Module::Supply,
+            #[cfg(feature = "battery")]
+            BusEvent::PowerStageChanged(_) => Module::Supply,
+            _ => Module::Sensor,
+        }
+    }
+}
+
+// Consumers: logging, the LED and the haptics, with room for a network
+// notifier
+const SUBSCRIBERS: usize = 4;
+
+pub static EVENTS: PubSubChannel<CriticalSectionRawMutex, BusEvent, 8, SUBSCRIBERS, 1> =
+    PubSubChannel::new();
+
+/// Never blocks; a subscriber that falls behind loses the oldest events.
+pub fn publish(event: BusEvent) {
+    EVENTS.immediate_publisher().publish_immediate(event);
+}
+
+#[embassy_executor::task]
+pub async fn log_task() {
+    let Ok(mut subscriber) = EVENTS.subscriber() else {
+        return;
+    };
+    loop {
+        match subscriber.next_message().await {
+            WaitResult::Message(event @ (BusEvent::FaultDetected(_) | BusEvent::Tamper(_))) => {
+                log!(event.module(), warn, "{}", event);
+            }
+            WaitResult::Message(BusEvent::ThresholdCrossed { .. }) if alarm::is_muted() => {}
+            WaitResult::Message(event) => log!(event.module(), info, "{}", event),
+            WaitResult::Lagged(n) => log!(Module::Sensor, warn, "{} events not logged", n),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The board's button (BOOT on the devkits, see `board`, active low) as a
+//! user button: a short press cycles the mode, a double press steps to
+//! the next saved profile, a long press starts calibration and a very long
+//! press erases the stored state and log and restarts.
+//!
+//! Pressed as the device starts and held, it does the same before the
+//! stored settings are applied, as a way out of settings that keep the
+//! device from running. It has to go down just after reset or power-on,
+//! as held through them BOOT selects the ROM download mode instead, and be
+//! down by the time the stored state has been read. The LED counts down,
+//! blinking once in the first second and faster each second after, then
+//! shows solid red as the state is erased; letting go before then boots
+//! as usual.
+
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use esp_hal::gpio::Input;
+use esp_hal::rmt::PulseCode;
+use hall_effect::button::{BOOT_RESET_MS, DOUBLE_PRESS_GAP_MS, Press, countdown_lit};
+use hall_effect::color::RGB8;
+use hall_effect::mode::Mode;
+use hall_effect::verbosity::Module;
+
+use crate::bus::{self, BusEvent};
+use crate::flash_log::FLASH_LOG;
+use crate::led::{self, LedChannel};
+use crate::state::STATE;
+use crate::verbosity::log;
+use crate::{mode, panic, profile, sleep};
+
+// Contact bounce to ignore after each edge
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+// How often the button is checked and the LED updated during the countdown
+const COUNTDOWN_STEP: Duration = Duration::from_millis(10);
+
+const RESET_COLOR: RGB8 = RGB8 { r: 255, g: 0, b: 0 };
+
+#[embassy_executor::task]
+pub async fn button_task(mut button: Input<'static>) {
+    loop {
+        button.wait_for_falling_edge().await;
+        let pressed = Instant::now();
+        Timer::after(DEBOUNCE).await;
+        if button.is_high() {
+            // A glitch, not a press
+            continue;
+        }
+        button.wait_for_high().await;
+        let mut press = Press::from_duration_ms(pressed.elapsed().as_millis());
+        Timer::after(DEBOUNCE).await;
+        let gap = Duration::from_millis(DOUBLE_PRESS_GAP_MS);
+        if press == Press::Short
+            && with_timeout(gap, button.wait_for_falling_edge())
+                .await
+                .is_ok()
+        {
+            button.wait_for_high().await;
+            Timer::after(DEBOUNCE).await;
+            press = Press::Double;
+        }
+
+        bus::publish(BusEvent::ButtonPressed(press));
+        match press {
+            Press::Short => mode::request(mode::current().next()),
+            Press::Long => mode::request(Mode::Calibrate),
+            Press::VeryLong => factory_reset().await,
+            Press::Double => {
+                if let Err(e) = profile::next().await {
+                    log!(Module::Storage, warn, "No profile to switch to: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Whether the button is pressed as the device starts and held until the
+/// countdown ends, shown on `led`.
+pub async fn held_at_boot(
+    button: &mut Input<'static>,
+    led: &mut Option<LedChannel>,
+    pulses: (PulseCode, PulseCode),
+) -> bool {
+    // Checked once rather than waited for, so other boots are not held up
+    if button.is_high() {
+        return false;
+    }
+    log!(
+        Module::Storage,
+        warn,
+        "Button held, factory reset in {} s unless released",
+        BOOT_RESET_MS / 1000
+    );
+    let pressed = Instant::now();
+    let mut lit = None;
+    loop {
+        let held_ms = pressed.elapsed().as_millis();
+        if button.is_high() {
+            led::show(led, pulses, RGB8::new(0, 0, 0));
+            log!(
+                Module::Storage,
+                info,
+                "Button released, factory reset cancelled"
+            );
+            return false;
+        }
+        if held_ms >= BOOT_RESET_MS {
+            led::show(led, pulses, RESET_COLOR);
+            return true;
+        }
+        let now_lit = countdown_lit(held_ms);
+        if lit != Some(now_lit) {
+            let color = if now_lit {
+                RESET_COLOR
+            } else {
+                RGB8::new(0, 0, 0)
+            };
+            led::show(led, pulses, color);
+            lit = Some(now_lit);
+        }
+        Timer::after(COUNTDOWN_STEP).await;
+    }
+}
+
+/// Erases the state store, the flash log, any panic record and what is
+/// kept in RTC RAM, then restarts.
+pub async fn factory_reset() -> ! {
+    log!(Module::Storage, warn, "Factory reset");
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.erase().await
+    {
+        log!(Module::Storage, warn, "State erase failed: {}", e);
+    }
+    // Taken out rather than locked, as erasing it takes several seconds
+    let flash_log = FLASH_LOG.lock().await.take();
+    if let Some(log) = flash_log
+        && let Err(e) = log.erase().await
+    {
+        log!(Module::Storage, warn, "Flash log erase failed: {}", e);
+    }
+    let _ = panic::take();
+    sleep::forget();
+    esp_hal::system::software_reset()
+}
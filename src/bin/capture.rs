@@ -0,0 +1,70 @@
+//! The triggered capture, fed every processed sample and read out from the
+//! console with `capture`.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hall_effect::capture::{Capture, State, Trigger};
+use hall_effect::schema::Sample;
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+
+/// Samples in a capture, about five seconds at the default rate.
+pub const SAMPLES: usize = 512;
+
+static CAPTURE: Mutex<RefCell<Capture<SAMPLES>>> = Mutex::new(RefCell::new(Capture::new()));
+
+pub fn arm(trigger: Trigger) {
+    critical_section::with(|cs| CAPTURE.borrow(cs).borrow_mut().arm(trigger));
+    log!(
+        Module::Sensor,
+        info,
+        "Capture armed: {} through {}mV",
+        trigger.edge.name(),
+        trigger.level_mv
+    );
+}
+
+/// Hands the capture a sample, from the processing stage.
+pub fn update(sample: &Sample) {
+    let state = critical_section::with(|cs| {
+        let mut capture = CAPTURE.borrow(cs).borrow_mut();
+        let before = capture.state();
+        capture.update(sample);
+        (before, capture.state())
+    });
+    match state {
+        (State::Armed, State::Triggered) => log!(Module::Sensor, info, "Capture triggered"),
+        (State::Armed | State::Triggered, State::Done) => {
+            log!(Module::Sensor, info, "Capture complete")
+        }
+        _ => {}
+    }
+}
+
+/// The state, trigger, length and crossing index of the capture.
+pub fn status() -> (State, Option<Trigger>, usize, usize) {
+    critical_section::with(|cs| {
+        let capture = CAPTURE.borrow(cs).borrow();
+        (
+            capture.state(),
+            capture.trigger(),
+            capture.len(),
+            capture.trigger_index(),
+        )
+    })
+}
+
+/// Copies samples from index `start` into `buf`, returning how many there
+/// were. A chunk at a time keeps the processing stage from waiting on the
+/// console.
+pub fn read(start: usize, buf: &mut [Sample]) -> usize {
+    critical_section::with(|cs| {
+        let capture = CAPTURE.borrow(cs).borrow();
+        buf.iter_mut()
+            .zip(capture.samples(start))
+            .map(|(slot, sample)| *slot = *sample)
+            .count()
+    })
+}
@@ -0,0 +1,155 @@
+//! What differs between the supported chips, chosen at build time with a
+//! chip feature: `esp32s3` (the default), `esp32s2`, `esp32c3` or `esp32c6`.
+//! `./build-all.sh` builds each of them.
+//!
+//! Touch, the ULP and a second core are ESP32-S3 only, and the C3 and C6
+//! have neither the GPIOs nor the second I2C and SPI buses the optional
+//! displays and loggers use. Their BOOT button (GPIO9) is not an RTC GPIO either, so deep sleep
+//! wakes on the timer alone and only RESET ends low-power mode.
+
+#[cfg(not(any(
+    feature = "esp32s3",
+    feature = "esp32s2",
+    feature = "esp32c3",
+    feature = "esp32c6"
+)))]
+compile_error!("Enable a chip feature: esp32s3, esp32s2, esp32c3 or esp32c6");
+#[cfg(any(
+    all(feature = "esp32s3", feature = "esp32s2"),
+    all(feature = "esp32s3", feature = "esp32c3"),
+    all(feature = "esp32s3", feature = "esp32c6"),
+    all(feature = "esp32s2", feature = "esp32c3"),
+    all(feature = "esp32s2", feature = "esp32c6"),
+    all(feature = "esp32c3", feature = "esp32c6"),
+))]
+compile_error!("Enable only one chip feature; use --no-default-features for other than esp32s3");
+
+#[cfg(all(
+    not(feature = "esp32s3"),
+    any(feature = "dual-core", feature = "touch", feature = "ulp-wake")
+))]
+compile_error!("the `dual-core`, `touch` and `ulp-wake` features need an ESP32-S3");
+#[cfg(all(feature = "esp32s2", any(feature = "ir-remote", feature = "tm1637")))]
+compile_error!("the ESP32-S2 has no GPIO47 for `tm1637`, and GPIO18 drives its LED");
+#[cfg(all(
+    any(feature = "esp32c3", feature = "esp32c6"),
+    any(
+        feature = "ds3231",
+        feature = "epaper",
+        feature = "haptic",
+        feature = "lcd",
+        feature = "max7219",
+        feature = "mux",
+        feature = "oled",
+        feature = "sd-log",
+        feature = "tft",
+        feature = "tm1637",
+        feature = "x27-gauge",
+    )
+))]
+compile_error!("the ESP32-C3 and C6 lack the pins or buses of the display and logger features");
+#[cfg(all(any(feature = "esp32c3", feature = "esp32c6"), feature = "relay"))]
+compile_error!("the ESP32-C3 and C6 have no GPIO45 for `relay`");
+#[cfg(all(not(feature = "esp32s3"), feature = "batch-output"))]
+compile_error!("only the ESP32-S3's GPIO46 can drive `batch-output`");
+#[cfg(all(feature = "esp32c3", feature = "trigger-out"))]
+compile_error!("the ESP32-C3's GPIO21 is its UART0 TX, which `trigger-out` needs");
+#[cfg(all(feature = "esp32c3", any(feature = "encoder", feature = "ir-remote")))]
+compile_error!("the ESP32-C3 has no PCNT for `encoder`, and GPIO18 is its USB D-");
+#[cfg(all(feature = "esp32c3", feature = "ambient-light"))]
+compile_error!("the ESP32-C3's GPIO6 is not an ADC1 pin, which `ambient-light` needs");
+
+use esp_hal::analog::adc::Attenuation;
+
+/// Resolution of a SAR ADC reading. The S2's 13-bit readings are scaled
+/// down, so a sample's `raw` is a 12-bit count on every chip.
+pub const ADC_BITS: u32 = if cfg!(feature = "esp32s2") { 13 } else { 12 };
+
+/// Curve fitting where eFuse calibration data supports it; the S2 only has
+/// the basic offset calibration.
+#[cfg(not(feature = "esp32s2"))]
+pub type AdcCal<ADCI> = esp_hal::analog::adc::AdcCalCurve<ADCI>;
+#[cfg(feature = "esp32s2")]
+pub type AdcCal<ADCI> = esp_hal::analog::adc::AdcCalBasic<ADCI>;
+
+/// Up to about 1.75V on the S3, S2 and C3, 1.9V on the C6, which covers a
+/// ratiometric sensor on 3.3V around its midpoint.
+pub const SENSOR_ATTENUATION: Attenuation = Attenuation::_6dB;
+/// Up to about 2.5V on the C3 and S2, more on the others; a full cell reads
+/// 2.1V through the divider.
+#[cfg_attr(not(feature = "battery"), expect(dead_code))]
+pub const BATTERY_ATTENUATION: Attenuation = Attenuation::_11dB;
+/// The same range, for the power meter's voltage divider.
+#[cfg_attr(not(feature = "power-meter"), expect(dead_code))]
+pub const VOLTAGE_ATTENUATION: Attenuation = Attenuation::_11dB;
+/// The same again, for the light sensor.
+#[cfg_attr(not(feature = "ambient-light"), expect(dead_code))]
+pub const AMBIENT_ATTENUATION: Attenuation = Attenuation::_11dB;
+
+/// RMT source clock. This is the APB clock on all but the C6, where the APB
+/// runs at 40MHz and the RMT from the 80MHz PLL, so the LED's pulse lengths
+/// come from here rather than from `Clocks`.
+pub const RMT_CLOCK_MHZ: u32 = 80;
+
+/// Moves the first RX-capable channel out of an `Rmt`: the S3 has RX only
+/// on channels 4 to 7 and the C3 and C6 on 2 and 3, while any of the S2's
+/// four will do. Channel 0 is the LED's.
+#[cfg(feature = "esp32s3")]
+macro_rules! ir_rx_channel {
+    ($rmt:ident) => {
+        $rmt.channel4
+    };
+}
+#[cfg(any(feature = "esp32c3", feature = "esp32c6"))]
+macro_rules! ir_rx_channel {
+    ($rmt:ident) => {
+        $rmt.channel2
+    };
+}
+#[cfg(feature = "esp32s2")]
+macro_rules! ir_rx_channel {
+    ($rmt:ident) => {
+        $rmt.channel1
+    };
+}
+pub(crate) use ir_rx_channel;
+
+/// The console's port: USB Serial/JTAG, or UART0 (GPIO43/44, the devkit's
+/// USB-UART bridge) on the S2, which has no Serial/JTAG controller.
+#[cfg(not(feature = "esp32s2"))]
+pub type ConsolePort = esp_hal::usb_serial_jtag::UsbSerialJtag<'static, esp_hal::Async>;
+#[cfg(not(feature = "esp32s2"))]
+pub type ConsoleTx = esp_hal::usb_serial_jtag::UsbSerialJtagTx<'static, esp_hal::Async>;
+#[cfg(feature = "esp32s2")]
+pub type ConsolePort = esp_hal::uart::Uart<'static, esp_hal::Async>;
+#[cfg(feature = "esp32s2")]
+pub type ConsoleTx = esp_hal::uart::UartTx<'static, esp_hal::Async>;
+
+/// Takes the console's port from the peripherals, if it could be set up.
+#[cfg(not(feature = "esp32s2"))]
+macro_rules! console_port {
+    ($peripherals:ident) => {
+        Some(esp_hal::usb_serial_jtag::UsbSerialJtag::new($peripherals.USB_DEVICE).into_async())
+    };
+}
+#[cfg(feature = "esp32s2")]
+macro_rules! console_port {
+    ($peripherals:ident) => {
+        esp_hal::uart::Uart::new($peripherals.UART0, esp_hal::uart::Config::default())
+            .inspect_err(|e| defmt::warn!("Console UART unavailable: {}", e))
+            .ok()
+            .map(|uart| {
+                uart.with_rx($peripherals.GPIO44)
+                    .with_tx($peripherals.GPIO43)
+                    .into_async()
+            })
+    };
+}
+pub(crate) use console_port;
+
+/// What wakes the chip from low-power mode for a normal boot.
+pub const WAKE_BUTTON: &str = if cfg!(any(feature = "esp32c3", feature = "esp32c6")) {
+    "RESET"
+} else {
+    "BOOT"
+};
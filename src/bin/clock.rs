@@ -0,0 +1,91 @@
+//! Wall-clock time, kept as the Unix time of the monotonic clock's zero.
+//!
+//! The anchor is set at boot from the DS3231 (when fitted) or later through
+//! the console; until then only monotonic timestamps are available.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::datetime::DateTime;
+use hall_effect::schema::Event;
+use hall_effect::verbosity::Module;
+
+use crate::flash_log::FLASH_LOG;
+#[cfg(feature = "light-sleep")]
+use crate::light_sleep::slept_us;
+use crate::verbosity::log;
+
+#[cfg(feature = "ds3231")]
+pub type Rtc = hall_effect::ds3231::Ds3231<esp_hal::i2c::master::I2c<'static, esp_hal::Blocking>>;
+
+#[cfg(feature = "ds3231")]
+pub static RTC: embassy_sync::mutex::Mutex<
+    embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex,
+    Option<Rtc>,
+> = embassy_sync::mutex::Mutex::new(None);
+
+static BOOT_UNIX_US: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+pub fn monotonic_us() -> u64 {
+    // The timer stops in light sleep
+    esp_hal::time::Instant::now()
+        .duration_since_epoch()
+        .as_micros()
+        + slept_us()
+}
+
+#[cfg(not(feature = "light-sleep"))]
+fn slept_us() -> u64 {
+    0
+}
+
+/// Converts a monotonic timestamp to Unix microseconds, if the clock is set.
+pub fn unix_us(monotonic_us: u64) -> Option<u64> {
+    critical_section::with(|cs| BOOT_UNIX_US.borrow(cs).get()).map(|boot| boot + monotonic_us)
+}
+
+pub fn now() -> Option<DateTime> {
+    unix_us(monotonic_us()).map(|us| DateTime::from_unix(us / 1_000_000))
+}
+
+/// Anchors the wall clock to `now` and records the change in the flash log.
+pub async fn set(now: &DateTime) {
+    let mono = monotonic_us();
+    let unix_s = now.to_unix();
+    let boot = (unix_s * 1_000_000).saturating_sub(mono);
+    critical_section::with(|cs| BOOT_UNIX_US.borrow(cs).set(Some(boot)));
+    log!(Module::Clock, info, "Clock set to {}", now);
+
+    if let Some(log) = FLASH_LOG.lock().await.as_mut()
+        && let Err(e) = log
+            .record_event(mono / 1000, Event::ClockSet { unix_s })
+            .await
+    {
+        log!(Module::Storage, warn, "Flash log write failed: {}", e);
+    }
+}
+
+/// The temperature read by the DS3231, when fitted, to go with results
+/// that depend on it.
+pub async fn temperature_c() -> Option<f32> {
+    #[cfg(feature = "ds3231")]
+    if let Some(rtc) = RTC.lock().await.as_mut() {
+        return rtc
+            .temperature_c()
+            .inspect_err(|e| log!(Module::Clock, warn, "RTC temperature read failed: {}", e))
+            .ok();
+    }
+    None
+}
+
+/// Like [`set`], and also writes the time to the RTC so it survives reboots.
+pub async fn set_and_persist(now: &DateTime) {
+    set(now).await;
+
+    #[cfg(feature = "ds3231")]
+    if let Some(rtc) = RTC.lock().await.as_mut()
+        && let Err(e) = rtc.set(now)
+    {
+        log!(Module::Clock, warn, "RTC write failed: {}", e);
+    }
+}
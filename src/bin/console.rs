@@ -0,0 +1,1897 @@
+//! Line-oriented command console on the USB Serial/JTAG port, or UART0 on
+//! the ESP32-S2.
+
+use core::fmt::Write as _;
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use embassy_time::{Duration, with_timeout};
+use embedded_io_async::{Read, Write};
+use esp_hal::rtc_cntl::SocResetReason;
+use hall_effect::allan::MAX_LEVELS;
+#[cfg(feature = "ambient-light")]
+use hall_effect::ambient::Settings as AmbientSettings;
+use hall_effect::capture::{State, Trigger};
+use hall_effect::command::{self, Command};
+use hall_effect::contact::Settings as ContactSettings;
+use hall_effect::counting::Settings as CountSettings;
+use hall_effect::csv;
+use hall_effect::current::{PROFILES, Scale};
+use hall_effect::datalog::Record;
+use hall_effect::datetime::DateTime;
+use hall_effect::filter::{Chain, Stage};
+use hall_effect::fixture::{Limits as FixtureLimits, Measurement, write_record};
+#[cfg(feature = "mux")]
+use hall_effect::fixture::{Scan, write_scan_record};
+use hall_effect::goertzel::Tone;
+use hall_effect::grading::Settings as GradeSettings;
+use hall_effect::mode::Mode;
+use hall_effect::night::Settings as NightSettings;
+use hall_effect::noise::Noise;
+use hall_effect::pas::Settings as PasSettings;
+use hall_effect::power::ratio_for;
+use hall_effect::profile::Error as ProfileError;
+#[cfg(feature = "relay")]
+use hall_effect::relay::Settings as RelaySettings;
+use hall_effect::remote::{write_ack, write_pairs};
+use hall_effect::rules::Action;
+use hall_effect::schema::{Config, Sample};
+#[cfg(feature = "servo")]
+use hall_effect::servo::Settings as ServoSettings;
+use hall_effect::tamper::Settings as TamperSettings;
+use hall_effect::verbosity::{Level, MODULES, Module};
+#[cfg(feature = "x27-gauge")]
+use hall_effect::x27::Scale as GaugeScale;
+use heapless::String;
+
+#[cfg(feature = "ambient-light")]
+use crate::ambient;
+use crate::baseline;
+use crate::burst;
+use crate::capture;
+use crate::chip::{self, ConsolePort};
+use crate::clock;
+use crate::contact;
+use crate::counting;
+use crate::current;
+use crate::diag;
+use crate::drift::{self, Run};
+use crate::filter;
+use crate::fixture;
+use crate::flash_log::{FLASH_LOG, Page};
+use crate::goertzel;
+use crate::grading;
+use crate::histogram;
+use crate::latency;
+#[cfg(feature = "ambient-light")]
+use crate::led;
+use crate::levitate;
+use crate::lockin;
+use crate::mode;
+use crate::night;
+use crate::noise;
+use crate::palette;
+use crate::panic;
+use crate::pas;
+use crate::power;
+use crate::profile;
+use crate::reading::{self, LATEST};
+use crate::remote;
+use crate::replay;
+use crate::rules;
+use crate::schedule;
+use crate::sleep;
+use crate::spectrum::{self, Analysis};
+use crate::speed;
+use crate::state::STATE;
+use crate::tamper;
+use crate::tare;
+use crate::units;
+use crate::verbosity::{self, log};
+use crate::watchdog::{self, Task};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+// Wake up this often while idle to check in with the watchdog
+const IDLE_CHECK_IN: Duration = Duration::from_secs(1);
+
+const MAX_NOISE_S: u32 = 60;
+
+// Weaker than this, `tamper learn` has no bias magnet to learn
+const MIN_BIAS_MT: f32 = 1.0;
+
+type Tx = chip::ConsoleTx;
+
+#[embassy_executor::task]
+pub async fn console_task(port: ConsolePort, mut config: Config) {
+    let (mut rx, mut tx) = port.split();
+    let mut line: String<64> = String::new();
+    let mut buf = [0u8; 16];
+
+    loop {
+        watchdog::feed(Task::Console);
+        let Ok(Ok(n)) = with_timeout(IDLE_CHECK_IN, rx.read(&mut buf)).await else {
+            continue;
+        };
+        for &b in &buf[..n] {
+            match b {
+                b'\r' | b'\n' => {
+                    let _ = tx.write_all(b"\r\n").await;
+                    if !line.is_empty() {
+                        run(&mut tx, &line, &mut config).await;
+                        line.clear();
+                    }
+                }
+                // Backspace / DEL
+                0x08 | 0x7f => {
+                    if line.pop().is_some() {
+                        let _ = tx.write_all(b"\x08 \x08").await;
+                    }
+                }
+                _ => {
+                    if line.push(b as char).is_ok() {
+                        let _ = tx.write_all(&[b]).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn run(tx: &mut Tx, line: &str, config: &mut Config) {
+    match Command::parse(line) {
+        Ok(Command::Dump { start, count }) => dump(tx, start, count, config).await,
+        Ok(Command::Diag) => {
+            let mut out: String<1024> = String::new();
+            let stats = STATE.lock().await.as_ref().map(|state| *state.stats());
+            if diag::write_report(&mut out, config, stats.as_ref()).is_err() {
+                out.clear();
+                let _ = writeln!(out, "error: report too long");
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::Drift) => match drift::run() {
+            Some(run) => show_drift(tx, &run).await,
+            None => {
+                let _ = tx.write_all(b"no drift run yet, see `mode drift`\n").await;
+            }
+        },
+        Ok(Command::Filters) => show_filters(tx).await,
+        Ok(Command::SetFilter { index, stage }) => {
+            edit_filters(tx, |chain, index| chain.set(index, stage), index).await
+        }
+        Ok(Command::EnableFilter { index, enabled }) => {
+            edit_filters(tx, |chain, index| chain.enable(index, enabled), index).await
+        }
+        Ok(Command::RemoveFilter { index }) => {
+            edit_filters(tx, |chain, index| chain.remove(index), index).await
+        }
+        Ok(Command::Fixture) => {
+            let limits = fixture::limits();
+            let mut out: String<160> = String::new();
+            let _ = writeln!(
+                out,
+                "pass {}mT to {}mT, spread up to {}mT, over {}ms",
+                limits.low_mt, limits.high_mt, limits.spread_mt, limits.measure_ms
+            );
+            let _ = match fixture::verdict() {
+                Some(verdict) => writeln!(out, "last test: {}", verdict.name()),
+                None => writeln!(out, "no test yet"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetFixtureLimits {
+            low_mt,
+            high_mt,
+            spread_mt,
+        }) => {
+            let limits = fixture::limits();
+            fixture::configure(FixtureLimits {
+                low_mt,
+                high_mt,
+                spread_mt: spread_mt.unwrap_or(limits.spread_mt),
+                ..limits
+            })
+            .await
+        }
+        Ok(Command::SetFixtureTime { ms }) => {
+            fixture::configure(FixtureLimits {
+                measure_ms: ms,
+                ..fixture::limits()
+            })
+            .await
+        }
+        Ok(Command::RunFixture { unit }) => run_fixture(tx, unit).await,
+        #[cfg(feature = "mux")]
+        Ok(Command::ScanFixture { positions, unit }) => scan_fixture(tx, positions, unit).await,
+        #[cfg(not(feature = "mux"))]
+        Ok(Command::ScanFixture { .. }) => {
+            let _ = tx.write_all(b"not a mux build\n").await;
+        }
+        #[cfg(feature = "x27-gauge")]
+        Ok(Command::Gauge) => {
+            let scale = crate::gauge::scale();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(
+                out,
+                "{}mT to {}mT, 0 to {}rpm",
+                scale.low_mt, scale.high_mt, scale.full_rpm
+            );
+            let _ = match crate::gauge::angle_deg() {
+                Some(angle_deg) => writeln!(out, "angle {:.1}deg", angle_deg),
+                None => writeln!(out, "homing"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "x27-gauge")]
+        Ok(Command::SetGaugeRange { low_mt, high_mt }) => {
+            crate::gauge::set_scale(GaugeScale {
+                low_mt,
+                high_mt,
+                ..crate::gauge::scale()
+            })
+            .await
+        }
+        #[cfg(feature = "x27-gauge")]
+        Ok(Command::SetGaugeRpm { full_rpm }) => {
+            crate::gauge::set_scale(GaugeScale {
+                full_rpm,
+                ..crate::gauge::scale()
+            })
+            .await
+        }
+        #[cfg(not(feature = "x27-gauge"))]
+        Ok(Command::Gauge | Command::SetGaugeRange { .. } | Command::SetGaugeRpm { .. }) => {
+            let _ = tx.write_all(b"not a gauge build\n").await;
+        }
+        Ok(Command::Grade) => {
+            let settings = grading::settings();
+            let mut out: String<384> = String::new();
+            let _ = writeln!(
+                out,
+                "{} of {} placements in",
+                grading::run().count(),
+                settings.shots
+            );
+            if let Some(graded) = grading::last() {
+                let run = graded.run;
+                let _ = match graded.grade {
+                    Some(grade) => writeln!(out, "magnet {}: grade {}", graded.magnet, grade),
+                    None => writeln!(out, "magnet {}: reject", graded.magnet),
+                };
+                let _ = writeln!(
+                    out,
+                    "peak {:.2}mT, {:.2} to {:.2}mT, std dev {:.3}mT ({:.1}%)",
+                    run.mean_mt(),
+                    run.min_mt(),
+                    run.max_mt(),
+                    run.std_dev_mt(),
+                    run.repeatability_pct()
+                );
+                if let Some(temp_c) = graded.temp_c {
+                    let _ = writeln!(out, "at {:.2}C", temp_c);
+                }
+            }
+            let _ = write!(out, "at {}mm, grades", settings.distance_mm);
+            for (i, bound_mt) in settings.grades.bounds_mt().iter().enumerate() {
+                let _ = write!(out, " {}:{}mT", (b'A' + i as u8) as char, bound_mt);
+            }
+            let _ = writeln!(out);
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetGradeShots { shots }) => {
+            grading::configure(GradeSettings {
+                shots,
+                ..grading::settings()
+            })
+            .await
+        }
+        Ok(Command::SetGradeDistance { mm }) => {
+            grading::configure(GradeSettings {
+                distance_mm: mm,
+                ..grading::settings()
+            })
+            .await
+        }
+        Ok(Command::SetGrades(grades)) => {
+            grading::configure(GradeSettings {
+                grades,
+                ..grading::settings()
+            })
+            .await
+        }
+        Ok(Command::ResetGrade) => grading::reset(),
+        #[cfg(feature = "haptic")]
+        Ok(Command::Haptic) => {
+            let mut out: String<32> = String::new();
+            let _ = writeln!(out, "buzz on {}", crate::haptic::trigger().name());
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "haptic")]
+        Ok(Command::SetHaptic(trigger)) => crate::haptic::set_trigger(trigger),
+        #[cfg(not(feature = "haptic"))]
+        Ok(Command::Haptic | Command::SetHaptic(_)) => {
+            let _ = tx.write_all(b"not a haptic build\n").await;
+        }
+        Ok(Command::Help) => {
+            let _ = tx.write_all(command::HELP.as_bytes()).await;
+        }
+        Ok(Command::Histogram) => show_histogram(tx, config).await,
+        Ok(Command::SetHistogram {
+            bin_width_mv,
+            window,
+        }) => histogram::configure(bin_width_mv, window),
+        Ok(Command::LastPanic) => match panic::take() {
+            Some(message) => {
+                let _ = tx.write_all(message.as_bytes()).await;
+                let _ = tx.write_all(b"\n").await;
+            }
+            None => {
+                let _ = tx.write_all(b"no panic recorded\n").await;
+            }
+        },
+        Ok(Command::Latency) => {
+            let mut out: String<192> = String::new();
+            match latency::summary() {
+                Some(s) => {
+                    let _ = writeln!(out, "sample to LED over {} readings", s.count);
+                    let _ = writeln!(
+                        out,
+                        "min {}us, mean {}us, max {}us",
+                        s.min_us, s.mean_us, s.max_us
+                    );
+                    let _ = writeln!(
+                        out,
+                        "p50 {}us, p90 {}us, p99 {}us, p99.9 {}us",
+                        s.p50_us, s.p90_us, s.p99_us, s.p999_us
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "nothing measured yet; see mode latency");
+                }
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::ResetLatency) => latency::reset(),
+        Ok(Command::Levitate) => {
+            let settings = levitate::settings();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(
+                out,
+                "setpoint {}mT, kp {} ki {} kd {}",
+                settings.setpoint_mt, settings.gains.kp, settings.gains.ki, settings.gains.kd
+            );
+            let _ = match levitate::status() {
+                Some(status) => writeln!(
+                    out,
+                    "field {:.2}mT, duty {:.1}%",
+                    status.field_mt,
+                    status.duty * 100.0
+                ),
+                None => writeln!(out, "not running, see `mode levitate`"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetLevitate { setpoint_mt }) => levitate::configure(levitate::Settings {
+            setpoint_mt,
+            ..levitate::settings()
+        }),
+        Ok(Command::SetLevitateGains(gains)) => levitate::configure(levitate::Settings {
+            gains,
+            ..levitate::settings()
+        }),
+        Ok(Command::LockIn) => {
+            let settings = lockin::settings();
+            let mut out: String<96> = String::new();
+            let _ = writeln!(
+                out,
+                "{}Hz, {}ms time constant",
+                settings.frequency_hz, settings.time_constant_ms
+            );
+            let _ = match lockin::latest() {
+                Some(response) => writeln!(
+                    out,
+                    "{:.3}uT at {:.1}deg{}",
+                    response.amplitude * 1000.0,
+                    response.phase_deg,
+                    if response.settled { "" } else { ", settling" }
+                ),
+                None => writeln!(out, "not running, see `mode lockin`"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetLockIn {
+            frequency_hz,
+            time_constant_ms,
+        }) => {
+            let rate_hz = 1000.0 / config.sample_period_ms as f32;
+            let settings = lockin::Settings {
+                frequency_hz,
+                time_constant_ms,
+            };
+            if frequency_hz as f32 >= rate_hz / 2.0 || !lockin::configure(settings) {
+                let mut out: String<64> = String::new();
+                let _ = writeln!(
+                    out,
+                    "needs under {}Hz and a time constant of a period",
+                    rate_hz / 2.0
+                );
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+        }
+        Ok(Command::Log) => {
+            let mut out: String<128> = String::new();
+            let levels = verbosity::levels();
+            for module in MODULES {
+                let _ = writeln!(out, "{:<8} {}", module.name(), levels.get(module).name());
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetLog { module, level }) => verbosity::set(module, level),
+        Ok(Command::Memory) => {
+            let mut out: String<128> = String::new();
+            let (size, free) = diag::stack_usage();
+            let _ = writeln!(out, "stack: {} of {} bytes used at most", size - free, size);
+            let _ = writeln!(out, "heap:  no allocator");
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "mock-sensor")]
+        Ok(Command::Mock(change)) => {
+            if let Some(change) = change {
+                crate::mock::set(change.apply(crate::mock::waveform()));
+            }
+            let waveform = crate::mock::waveform();
+            let mut out: String<64> = String::new();
+            let _ = writeln!(
+                out,
+                "{}, {}ms period, {}mV amplitude",
+                waveform.shape.name(),
+                waveform.period_ms,
+                waveform.amplitude_mv
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(not(feature = "mock-sensor"))]
+        Ok(Command::Mock(_)) => {
+            let _ = tx.write_all(b"not a mock-sensor build\n").await;
+        }
+        Ok(Command::Mode(None)) => {
+            let mut out: String<32> = String::new();
+            let _ = writeln!(out, "{}", mode::current().name());
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::Mode(Some(next))) => mode::request(next),
+        #[cfg(feature = "bldc")]
+        Ok(Command::Motor) => {
+            let settings = crate::bldc::settings();
+            let status = crate::bldc::status();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(
+                out,
+                "setpoint {}rpm{}, kp {} ki {} kd {}",
+                settings.setpoint_rpm,
+                if settings.reverse { " reverse" } else { "" },
+                settings.gains.kp,
+                settings.gains.ki,
+                settings.gains.kd
+            );
+            let _ = match status.sector {
+                Some(sector) => writeln!(
+                    out,
+                    "{:.0}rpm, duty {:.1}%, sector {}",
+                    status.rpm,
+                    status.duty * 100.0,
+                    sector
+                ),
+                None => writeln!(out, "no hall signal"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "bldc")]
+        Ok(Command::SetMotor { rpm, reverse }) => crate::bldc::configure(crate::bldc::Settings {
+            setpoint_rpm: rpm,
+            reverse,
+            ..crate::bldc::settings()
+        }),
+        #[cfg(feature = "bldc")]
+        Ok(Command::SetMotorGains(gains)) => crate::bldc::configure(crate::bldc::Settings {
+            gains,
+            ..crate::bldc::settings()
+        }),
+        #[cfg(not(feature = "bldc"))]
+        Ok(Command::Motor | Command::SetMotor { .. } | Command::SetMotorGains(_)) => {
+            let _ = tx.write_all(b"not a bldc build\n").await;
+        }
+        #[cfg(feature = "mux")]
+        Ok(Command::Mux(None)) => {
+            let mut out: String<16> = String::new();
+            let _ = writeln!(out, "channel {}", crate::mux::channel());
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "mux")]
+        Ok(Command::Mux(Some(channel))) => crate::mux::select(channel),
+        #[cfg(not(feature = "mux"))]
+        Ok(Command::Mux(_)) => {
+            let _ = tx.write_all(b"not a mux build\n").await;
+        }
+        Ok(Command::AutoZero) => {
+            let status = baseline::status();
+            let zero_field_mv = status.zero_field_mv.unwrap_or(config.zero_field_mv);
+            let mut out: String<64> = String::new();
+            let _ = match status.time_constant_s {
+                Some(time_constant_s) => writeln!(
+                    out,
+                    "{}s time constant, zero {}mV, {}",
+                    time_constant_s,
+                    zero_field_mv,
+                    if status.frozen { "frozen" } else { "tracking" }
+                ),
+                None => writeln!(out, "off, zero {}mV", zero_field_mv),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetAutoZero(time_constant_s)) => baseline::configure(time_constant_s),
+        Ok(Command::Battery) => {
+            let mut out: String<32> = String::new();
+            #[cfg(feature = "battery")]
+            match crate::battery::voltage_mv() {
+                Some(voltage_mv) => {
+                    let _ = writeln!(
+                        out,
+                        "{}.{:02} V, {}%",
+                        voltage_mv / 1000,
+                        voltage_mv % 1000 / 10,
+                        hall_effect::battery::percent(voltage_mv)
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "no battery reading yet");
+                }
+            }
+            #[cfg(not(feature = "battery"))]
+            let _ = writeln!(out, "no battery monitor fitted");
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::Burst) => {
+            let mut out: String<128> = String::new();
+            match burst::summary() {
+                Some(s) => {
+                    let _ = write!(
+                        out,
+                        "{} samples at {}Hz, {}-{} mV, mean {} mV",
+                        s.samples, s.rate_hz, s.min_mv, s.max_mv, s.mean_mv
+                    );
+                    let _ = match s.frequency_hz {
+                        Some(frequency_hz) => writeln!(out, ", {:.1} Hz", frequency_hz),
+                        None => writeln!(out),
+                    };
+                }
+                None => {
+                    let _ = writeln!(out, "no burst yet");
+                }
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::RunBurst {
+            rate_hz,
+            duration_ms,
+        }) => burst::request(rate_hz, duration_ms),
+        Ok(Command::DumpBurst { decimate }) => dump_burst(tx, decimate.max(1) as usize).await,
+        Ok(Command::Capture) => show_capture(tx, config).await,
+        Ok(Command::ArmCapture {
+            level_mv,
+            edge,
+            pre,
+        }) => capture::arm(Trigger {
+            level_mv,
+            edge,
+            pre: pre.map_or(capture::SAMPLES / 4, |pre| pre as usize),
+        }),
+        Ok(Command::Contact) => {
+            let settings = contact::settings();
+            let mut out: String<512> = String::new();
+            let _ = match contact::is_closed() {
+                Some(closed) => writeln!(out, "{}", if closed { "closed" } else { "open" }),
+                None => writeln!(out, "not known (mode contact)"),
+            };
+            for change in contact::changes() {
+                let state = if change.closed { "closed" } else { "open" };
+                let _ = match clock::unix_us(change.time_ms * 1000) {
+                    Some(unix_us) => {
+                        let time = DateTime::from_unix(unix_us / 1_000_000);
+                        writeln!(out, "{} {}", time, state)
+                    }
+                    None => writeln!(out, "{}ms {}", change.time_ms, state),
+                };
+            }
+            let _ = writeln!(
+                out,
+                "closed at {}mT, open at {}mT, debounce {}ms",
+                settings.closed_mt, settings.open_mt, settings.debounce_ms
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetContactThresholds { closed_mt, open_mt }) => {
+            contact::configure(ContactSettings {
+                closed_mt,
+                open_mt,
+                ..contact::settings()
+            })
+            .await
+        }
+        Ok(Command::SetContactDebounce { ms }) => {
+            contact::configure(ContactSettings {
+                debounce_ms: ms,
+                ..contact::settings()
+            })
+            .await
+        }
+        Ok(Command::Count) => {
+            let settings = counting::settings();
+            let totals = counting::totals();
+            let mut out: String<160> = String::new();
+            let _ = writeln!(out, "{} parts", totals.parts);
+            if settings.batch > 0 {
+                let _ = writeln!(
+                    out,
+                    "{} batches, {} of {} in this one",
+                    totals.batches, totals.in_batch, settings.batch
+                );
+            }
+            let _ = writeln!(
+                out,
+                "dead time {}ms, batch pulse {}ms",
+                settings.dead_ms, settings.pulse_ms
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetCountDeadTime { ms }) => {
+            counting::configure(CountSettings {
+                dead_ms: ms,
+                ..counting::settings()
+            })
+            .await
+        }
+        Ok(Command::SetBatch { size }) => {
+            counting::configure(CountSettings {
+                batch: size,
+                ..counting::settings()
+            })
+            .await
+        }
+        Ok(Command::SetBatchPulse { ms }) => {
+            counting::configure(CountSettings {
+                pulse_ms: ms,
+                ..counting::settings()
+            })
+            .await
+        }
+        Ok(Command::ResetCount) => counting::reset(),
+        Ok(Command::Current) => {
+            let scale = current::scale();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(out, "zero {}mV, {}mV/A", scale.zero_mv, scale.mv_per_a);
+            let _ = match current::full_scale_a() {
+                Some(full_scale_a) => writeln!(out, "closed loop, {}A full scale", full_scale_a),
+                None => writeln!(out, "open loop"),
+            };
+            let _ = match current::amps() {
+                Some(amps) => writeln!(out, "{:.3}A", amps),
+                None => writeln!(out, "not running, see `mode current`"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::ZeroCurrent | Command::CalibrateCurrent { .. })
+            if current::full_scale_a().is_some() =>
+        {
+            // The winding would hold the output at the zero
+            let _ = tx.write_all(b"stop the closed loop first\n").await;
+        }
+        Ok(Command::ZeroCurrent) => match reading::average(|r| r.sample.voltage_mv as f32).await {
+            Some(zero_mv) => {
+                current::set_scale(Scale {
+                    zero_mv,
+                    ..current::scale()
+                })
+                .await
+            }
+            None => {
+                let _ = tx.write_all(b"no readings\n").await;
+            }
+        },
+        Ok(Command::CalibrateCurrent { amps }) => {
+            match reading::average(|r| r.sample.voltage_mv as f32).await {
+                Some(voltage_mv) => match current::scale().calibrated(voltage_mv, amps) {
+                    Some(scale) => current::set_scale(scale).await,
+                    None => {
+                        let _ = tx.write_all(b"too close to the zero\n").await;
+                    }
+                },
+                None => {
+                    let _ = tx.write_all(b"no readings\n").await;
+                }
+            }
+        }
+        Ok(Command::CurrentProfiles) => {
+            for profile in PROFILES {
+                let mut out: String<64> = String::new();
+                let _ = writeln!(
+                    out,
+                    "{:<12} {}mV/A at 5V, {}{}A",
+                    profile.name,
+                    profile.mv_per_a,
+                    if profile.bidirectional { "+/-" } else { "0-" },
+                    profile.range_a
+                );
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+        }
+        Ok(Command::SetCurrentProfile { profile, supply_mv }) => {
+            current::set_scale(profile.scale(supply_mv)).await
+        }
+        Ok(Command::SetCurrentLoop(Some(_))) if !cfg!(feature = "null-flux") => {
+            let _ = tx
+                .write_all(b"the closed loop needs the `null-flux` feature's winding\n")
+                .await;
+        }
+        Ok(Command::SetCurrentLoop(full_scale_a)) => current::set_loop(full_scale_a),
+        Ok(Command::Noise { duration_s }) => measure_noise(tx, duration_s, config).await,
+        Ok(Command::Odometer) => {
+            let mut out: String<32> = String::new();
+            match STATE.lock().await.as_ref() {
+                Some(state) => {
+                    let _ = writeln!(out, "pulses: {}", state.pulses());
+                }
+                None => {
+                    let _ = writeln!(out, "state store unavailable");
+                }
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::ResetOdometer { confirmed: false }) => {
+            let _ = tx
+                .write_all(b"type `odometer reset confirm` to clear the pulse count\n")
+                .await;
+        }
+        Ok(Command::ResetOdometer { confirmed: true }) => {
+            if let Some(state) = STATE.lock().await.as_mut() {
+                match state.reset_pulses().await {
+                    Ok(()) => log!(Module::Storage, info, "Odometer reset"),
+                    Err(e) => log!(Module::Storage, warn, "Odometer reset failed: {}", e),
+                }
+            }
+        }
+        Ok(Command::Pas) => {
+            let settings = pas::settings();
+            let mut out: String<192> = String::new();
+            if let Some(status) = pas::status() {
+                let _ = writeln!(
+                    out,
+                    "cadence {:.0}rpm, assist {:.0}%",
+                    status.cadence_rpm,
+                    status.level * 100.0
+                );
+            }
+            let _ = write!(out, "{} magnets, curve", settings.magnets);
+            for point in settings.curve.points() {
+                let _ = write!(out, " {}:{}", point.cadence_rpm, point.percent);
+            }
+            let _ = writeln!(out);
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetPasMagnets { magnets }) => {
+            pas::configure(PasSettings {
+                magnets,
+                ..pas::settings()
+            })
+            .await
+        }
+        Ok(Command::SetPasCurve(curve)) => {
+            pas::configure(PasSettings {
+                curve,
+                ..pas::settings()
+            })
+            .await
+        }
+        Ok(Command::Power) => {
+            let mut out: String<128> = String::new();
+            let _ = match (power::volts(), power::watts()) {
+                (Some(volts), Some(watts)) => writeln!(out, "{:.2}V, {:.2}W", volts, watts),
+                _ => writeln!(out, "not running, see `mode power`"),
+            };
+            let _ = writeln!(
+                out,
+                "{:.3}kWh, divider ratio {}",
+                power::energy_wh() / 1000.0,
+                power::ratio()
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::CalibrateVoltage { volts }) => match power::voltage_mv() {
+            Some(voltage_mv) => match ratio_for(voltage_mv, volts) {
+                Some(ratio) => power::set_ratio(ratio).await,
+                None => {
+                    let _ = tx.write_all(b"too close to zero\n").await;
+                }
+            },
+            None => {
+                let _ = tx
+                    .write_all(b"no voltage reading, see `mode power`\n")
+                    .await;
+            }
+        },
+        Ok(Command::ResetEnergy { confirmed: false }) => {
+            let _ = tx
+                .write_all(b"type `power reset confirm` to clear the energy total\n")
+                .await;
+        }
+        Ok(Command::ResetEnergy { confirmed: true }) => {
+            if let Some(state) = STATE.lock().await.as_mut() {
+                match state.reset_energy().await {
+                    Ok(()) => log!(Module::Storage, info, "Energy total reset"),
+                    Err(e) => log!(Module::Storage, warn, "Energy total reset failed: {}", e),
+                }
+            }
+        }
+        Ok(Command::Quiet(quiet)) => {
+            let level = if quiet { Level::Off } else { Level::Info };
+            verbosity::set(Some(Module::Sample), level);
+        }
+        Ok(Command::Reading) => {
+            let mut out: String<64> = String::new();
+            match LATEST.try_get() {
+                Some(reading) if reading.valid => {
+                    let _ = writeln!(out, "{}", units::of(&reading));
+                }
+                Some(reading) => {
+                    let _ = writeln!(out, "invalid (wiring fault), raw={}", reading.sample.raw);
+                }
+                None => {
+                    let _ = writeln!(out, "no reading yet");
+                }
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "relay")]
+        Ok(Command::Relay) => {
+            let settings = crate::relay::settings();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(
+                out,
+                "in at {}mT, out at {}mT, held {}ms, fail-safe {}",
+                settings.pull_in_mt,
+                settings.drop_out_mt,
+                settings.min_on_ms,
+                settings.fail_safe.name()
+            );
+            let _ = writeln!(out, "{}", if crate::relay::is_on() { "in" } else { "out" });
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "relay")]
+        Ok(Command::SetRelayThresholds {
+            pull_in_mt,
+            drop_out_mt,
+        }) => {
+            crate::relay::configure(RelaySettings {
+                pull_in_mt,
+                drop_out_mt,
+                ..crate::relay::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "relay")]
+        Ok(Command::SetRelayMinOn { ms }) => {
+            crate::relay::configure(RelaySettings {
+                min_on_ms: ms,
+                ..crate::relay::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "relay")]
+        Ok(Command::SetRelayFailSafe(fail_safe)) => {
+            crate::relay::configure(RelaySettings {
+                fail_safe,
+                ..crate::relay::settings()
+            })
+            .await
+        }
+        #[cfg(not(feature = "relay"))]
+        Ok(
+            Command::Relay
+            | Command::SetRelayThresholds { .. }
+            | Command::SetRelayMinOn { .. }
+            | Command::SetRelayFailSafe(_),
+        ) => {
+            let _ = tx.write_all(b"not a relay build\n").await;
+        }
+        Ok(Command::Replay { start, count }) => replay::start(start, count),
+        Ok(Command::StopReplay) => replay::stop(),
+        #[cfg(feature = "servo")]
+        Ok(Command::Servo) => {
+            let settings = crate::servo::settings();
+            let mut out: String<160> = String::new();
+            let _ = writeln!(
+                out,
+                "{}mT to {}mT, pulses {}us to {}us, slew {}deg/s",
+                settings.low_mt,
+                settings.high_mt,
+                settings.min_pulse_us,
+                settings.max_pulse_us,
+                settings.slew_deg_per_s
+            );
+            let _ = match crate::servo::angle_deg() {
+                Some(angle_deg) => writeln!(out, "angle {:.1}deg", angle_deg),
+                None => writeln!(out, "no reading yet"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "servo")]
+        Ok(Command::SetServoRange { low_mt, high_mt }) => {
+            crate::servo::configure(ServoSettings {
+                low_mt,
+                high_mt,
+                ..crate::servo::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "servo")]
+        Ok(Command::SetServoPulses { min_us, max_us }) => {
+            crate::servo::configure(ServoSettings {
+                min_pulse_us: min_us,
+                max_pulse_us: max_us,
+                ..crate::servo::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "servo")]
+        Ok(Command::SetServoSlew { deg_per_s }) => {
+            crate::servo::configure(ServoSettings {
+                slew_deg_per_s: deg_per_s,
+                ..crate::servo::settings()
+            })
+            .await
+        }
+        #[cfg(not(feature = "servo"))]
+        Ok(
+            Command::Servo
+            | Command::SetServoRange { .. }
+            | Command::SetServoPulses { .. }
+            | Command::SetServoSlew { .. },
+        ) => {
+            let _ = tx.write_all(b"not a servo build\n").await;
+        }
+        Ok(Command::Sleep) => {
+            let mut out: String<32> = String::new();
+            let _ = writeln!(out, "sleeping, press {} to wake", chip::WAKE_BUTTON);
+            let _ = tx.write_all(out.as_bytes()).await;
+            let _ = tx.flush().await;
+            sleep::prepare().await;
+            sleep::enter(config)
+        }
+        Ok(Command::Spectrum) => match spectrum::latest() {
+            Some(analysis) => show_spectrum(tx, &analysis).await,
+            None => {
+                let _ = tx
+                    .write_all(b"no spectrum yet, see `mode spectrum` or `spectrum burst`\n")
+                    .await;
+            }
+        },
+        Ok(Command::BurstSpectrum { points }) => match spectrum::of_burst(points as usize).await {
+            Some(analysis) => show_spectrum(tx, &analysis).await,
+            None => {
+                let _ = tx
+                    .write_all(b"needs a burst of at least 256, 512 or 1024 readings\n")
+                    .await;
+            }
+        },
+        Ok(Command::Speed) => {
+            let settings = speed::settings();
+            let mut out: String<128> = String::new();
+            let _ = writeln!(
+                out,
+                "setpoint {}rpm, kp {} ki {} kd {}",
+                settings.setpoint_rpm, settings.gains.kp, settings.gains.ki, settings.gains.kd
+            );
+            let _ = writeln!(
+                out,
+                "{}rpm, duty {:.1}%",
+                mode::rpm(),
+                speed::duty() * 100.0
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetSpeed { rpm }) if rpm > 0 && !cfg!(feature = "speed-control") => {
+            let _ = tx
+                .write_all(b"the speed loop needs the `speed-control` feature's motor\n")
+                .await;
+        }
+        Ok(Command::SetSpeed { rpm }) => speed::configure(speed::Settings {
+            setpoint_rpm: rpm,
+            ..speed::settings()
+        }),
+        Ok(Command::SetSpeedGains(gains)) => speed::configure(speed::Settings {
+            gains,
+            ..speed::settings()
+        }),
+        Ok(Command::Stats) => stats(tx).await,
+        Ok(Command::Tamper) => {
+            let settings = tamper::settings();
+            let mut out: String<160> = String::new();
+            let _ = match tamper::alarm() {
+                Some(cause) => writeln!(out, "ALARM: {}", cause.name()),
+                None => writeln!(out, "no alarm"),
+            };
+            let _ = if settings.is_learned() {
+                writeln!(out, "bias magnet {:.2}mT", settings.expected_mt)
+            } else {
+                writeln!(out, "bias magnet not learned, see `tamper learn`")
+            };
+            let _ = writeln!(
+                out,
+                "tolerance {}%, hold {}ms",
+                settings.tolerance_pct, settings.hold_ms
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::LearnTamper) => match reading::average(|r| r.field_mt).await {
+            Some(expected_mt) if expected_mt.abs() >= MIN_BIAS_MT => {
+                tamper::configure(TamperSettings {
+                    expected_mt,
+                    ..tamper::settings()
+                })
+                .await;
+                let mut out: String<32> = String::new();
+                let _ = writeln!(out, "bias magnet {:.2}mT", expected_mt);
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+            Some(_) => {
+                let _ = tx.write_all(b"no bias magnet found\n").await;
+            }
+            None => {
+                let _ = tx.write_all(b"no readings\n").await;
+            }
+        },
+        Ok(Command::SetTamperTolerance { pct }) => {
+            tamper::configure(TamperSettings {
+                tolerance_pct: pct,
+                ..tamper::settings()
+            })
+            .await
+        }
+        Ok(Command::SetTamperHold { ms }) => {
+            tamper::configure(TamperSettings {
+                hold_ms: ms,
+                ..tamper::settings()
+            })
+            .await
+        }
+        Ok(Command::ClearTamper) => tamper::clear(),
+        Ok(Command::Tare) => {
+            let mut out: String<32> = String::new();
+            let offset_mt = tare::offset_mt();
+            let _ = if offset_mt == 0.0 {
+                writeln!(out, "no tare")
+            } else {
+                writeln!(out, "tare {:.3}mT", offset_mt)
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetTare) => match tare::capture().await {
+            Some(offset_mt) => {
+                let mut out: String<32> = String::new();
+                let _ = writeln!(out, "tare {:.3}mT", offset_mt);
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+            None => {
+                let _ = tx.write_all(b"no readings\n").await;
+            }
+        },
+        Ok(Command::ClearTare) => tare::clear().await,
+        Ok(Command::Time) => {
+            let mut out: String<32> = String::new();
+            match clock::now() {
+                Some(now) => {
+                    let _ = writeln!(out, "{}", now);
+                }
+                None => {
+                    let _ = writeln!(out, "clock not set");
+                }
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetTime(now)) => clock::set_and_persist(&now).await,
+        Ok(Command::Tone) => match goertzel::latest() {
+            Some(tone) => show_tone(tx, &tone).await,
+            None => {
+                let _ = tx.write_all(b"no tone yet, see `tone <Hz>`\n").await;
+            }
+        },
+        Ok(Command::SetTone {
+            frequency_hz,
+            points,
+        }) => {
+            let rate_hz = 1000.0 / config.sample_period_ms as f32;
+            if !goertzel::start(frequency_hz, points, rate_hz) {
+                let mut out: String<64> = String::new();
+                let _ = writeln!(out, "needs under {}Hz and 2 to 4096 samples", rate_hz / 2.0);
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+        }
+        Ok(Command::StopTone) => goertzel::stop(),
+        Ok(Command::BurstTone { frequency_hz }) => match goertzel::of_burst(frequency_hz) {
+            Some(tone) => show_tone(tx, &tone).await,
+            None => {
+                let _ = tx
+                    .write_all(b"needs a burst at over twice the frequency\n")
+                    .await;
+            }
+        },
+        #[cfg(feature = "trigger-out")]
+        Ok(Command::Trigger) => {
+            use hall_effect::trigger::Condition;
+
+            let settings = crate::trigger::settings();
+            let stats = crate::trigger::stats();
+            let mut out: String<192> = String::new();
+            let _ = match settings.condition {
+                Condition::Off => writeln!(out, "off"),
+                Condition::Crossing { level_mv, edge } => {
+                    writeln!(out, "crossing {}mV, {} edge", level_mv, edge.name())
+                }
+                Condition::Peak {
+                    level_mv,
+                    rising: true,
+                } => writeln!(out, "peaks above {}mV", level_mv),
+                Condition::Peak {
+                    level_mv,
+                    rising: false,
+                } => writeln!(out, "dips below {}mV", level_mv),
+            };
+            let _ = writeln!(
+                out,
+                "pulse {}us, hold-off {}ms",
+                settings.pulse_us, settings.holdoff_ms
+            );
+            let _ = match stats.last_lag_us {
+                Some(last_lag_us) => writeln!(
+                    out,
+                    "{} pulses, the last {}us after its event, at most {}us",
+                    stats.pulses, last_lag_us, stats.max_lag_us
+                ),
+                None => writeln!(out, "no pulses yet"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "trigger-out")]
+        Ok(Command::SetTrigger(condition)) => {
+            crate::trigger::configure(hall_effect::trigger::Settings {
+                condition,
+                ..crate::trigger::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "trigger-out")]
+        Ok(Command::SetTriggerPulse { us }) => {
+            crate::trigger::configure(hall_effect::trigger::Settings {
+                pulse_us: us,
+                ..crate::trigger::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "trigger-out")]
+        Ok(Command::SetTriggerHoldoff { ms }) => {
+            crate::trigger::configure(hall_effect::trigger::Settings {
+                holdoff_ms: ms,
+                ..crate::trigger::settings()
+            })
+            .await
+        }
+        #[cfg(not(feature = "trigger-out"))]
+        Ok(
+            Command::Trigger
+            | Command::SetTrigger(_)
+            | Command::SetTriggerPulse { .. }
+            | Command::SetTriggerHoldoff { .. },
+        ) => {
+            let _ = tx.write_all(b"not a trigger-out build\n").await;
+        }
+        Ok(Command::Config) => {
+            let mut out: String<512> = String::new();
+            let _ = write_pairs(&mut out, config);
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetConfig(update)) => {
+            let result = match update {
+                Ok(update) => remote::set(config, &update).await.map(|()| update),
+                Err(rejection) => Err(rejection),
+            };
+            let mut out: String<96> = String::new();
+            let _ = write_ack(&mut out, result.as_ref().map_err(|&e| e));
+            let _ = writeln!(out);
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "sync")]
+        Ok(Command::Sync) => {
+            use hall_effect::sync::Role;
+
+            let status = crate::sync::status();
+            let mut out: String<160> = String::new();
+            let _ = match status.role {
+                Role::Off => writeln!(out, "off"),
+                Role::Leader => writeln!(out, "leader, {} pulses sent", status.pulses),
+                Role::Follower => writeln!(
+                    out,
+                    "follower, {}, {} pulses taken, {} samples on its own timer",
+                    if status.locked {
+                        "locked"
+                    } else {
+                        "free-running"
+                    },
+                    status.pulses,
+                    status.free_runs
+                ),
+            };
+            if status.role != Role::Off {
+                let _ = match (status.index, status.epoch_us) {
+                    (Some(index), Some(epoch_us)) => {
+                        writeln!(out, "sample {} since the epoch at {}us", index, epoch_us)
+                    }
+                    _ => writeln!(out, "no epoch yet"),
+                };
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "sync")]
+        Ok(Command::SetSync(role)) => crate::sync::configure(role).await,
+        #[cfg(feature = "sync")]
+        Ok(Command::RestartSync) => {
+            if !crate::sync::restart() {
+                let _ = tx.write_all(b"only the leader marks an epoch\n").await;
+            }
+        }
+        #[cfg(not(feature = "sync"))]
+        Ok(Command::Sync | Command::SetSync(_) | Command::RestartSync) => {
+            let _ = tx.write_all(b"not a sync build\n").await;
+        }
+        Ok(Command::Units) => {
+            let mut out: String<16> = String::new();
+            let _ = writeln!(out, "{}", units::current().name());
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetUnits(unit)) => units::configure(unit).await,
+        Ok(Command::Palette) => {
+            let mut out: String<16> = String::new();
+            let _ = writeln!(out, "{}", palette::current().name());
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetPalette(palette)) => palette::configure(palette).await,
+        Ok(Command::Profiles) => match profile::list().await {
+            Ok(listing) => {
+                let mut out: String<96> = String::new();
+                for (slot, name) in listing.names.iter().enumerate() {
+                    if let Some(name) = name {
+                        let marker = if listing.active == Some(slot) {
+                            '*'
+                        } else {
+                            ' '
+                        };
+                        let _ = writeln!(out, "{} {}", marker, name.as_str());
+                    }
+                }
+                if out.is_empty() {
+                    let _ = writeln!(out, "no profiles saved");
+                }
+                let _ = tx.write_all(out.as_bytes()).await;
+            }
+            Err(e) => profile_error(tx, e).await,
+        },
+        Ok(Command::SaveProfile(name)) => {
+            // With the zero as calibration or auto-zero left it
+            let current = sleep::restore(*config);
+            if let Err(e) = profile::save(name, &current).await {
+                profile_error(tx, e).await;
+            }
+        }
+        Ok(Command::UseProfile(name)) => match profile::switch(&name).await {
+            Ok(switched) => *config = switched,
+            Err(e) => profile_error(tx, e).await,
+        },
+        Ok(Command::RemoveProfile(name)) => {
+            if let Err(e) = profile::remove(&name).await {
+                profile_error(tx, e).await;
+            }
+        }
+        Ok(Command::Rules) => {
+            let mut out: String<192> = String::new();
+            for (index, rule) in rules::rules().iter().enumerate() {
+                let Some(rule) = rule else {
+                    continue;
+                };
+                let number = index + 1;
+                let condition = rule.condition;
+                let _ = write!(
+                    out,
+                    "{} {}: {} {}mT for {}ms, ",
+                    if rules::is_met(number) { '*' } else { ' ' },
+                    number,
+                    condition.comparison.name(),
+                    condition.level_mt,
+                    condition.for_ms
+                );
+                let _ = match rule.action {
+                    Action::Output => writeln!(out, "output"),
+                    Action::Publish => writeln!(out, "publish"),
+                    Action::Flash(color) => writeln!(out, "flash {}", color.name()),
+                };
+            }
+            if out.is_empty() {
+                let _ = writeln!(out, "no rules");
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::AddRule(rule)) => {
+            let mut out: String<48> = String::new();
+            let _ = match rules::add(rule).await {
+                Some(number) => writeln!(out, "rule {}", number),
+                None => writeln!(out, "error: no free place, remove a rule first"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::RemoveRule { number }) => {
+            if !rules::remove(number).await {
+                let _ = tx.write_all(b"error: no such rule\n").await;
+            }
+        }
+        Ok(Command::Schedule) => {
+            let mut out: String<192> = String::new();
+            for (index, entry) in schedule::entries().iter().enumerate() {
+                if let Some(entry) = entry {
+                    let _ = writeln!(out, "{}: {} {}", index + 1, entry.when, entry.task.name());
+                }
+            }
+            if out.is_empty() {
+                let _ = writeln!(out, "nothing scheduled");
+            }
+            if clock::now().is_none() {
+                let _ = writeln!(out, "clock not set, nothing runs until it is");
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::AddSchedule(entry)) => {
+            let mut out: String<48> = String::new();
+            let _ = match schedule::add(entry).await {
+                Some(number) => writeln!(out, "entry {}", number),
+                None => writeln!(out, "error: no free place, remove an entry first"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::RemoveSchedule { number }) => {
+            if !schedule::remove(number).await {
+                let _ = tx.write_all(b"error: no such entry\n").await;
+            }
+        }
+        Ok(Command::Night) => {
+            let settings = night::settings();
+            let mut out: String<96> = String::new();
+            let _ = writeln!(
+                out,
+                "{} to {} UTC at {}%, {}",
+                settings.start,
+                settings.end,
+                settings.brightness_pct,
+                if settings.enabled { "on" } else { "off" }
+            );
+            if night::is_active() {
+                let _ = writeln!(out, "night now");
+            } else if settings.enabled && clock::now().is_none() {
+                let _ = writeln!(out, "clock not set, not in effect until it is");
+            }
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        Ok(Command::SetNight(settings)) => night::configure(settings).await,
+        Ok(Command::NightOff) => {
+            night::configure(NightSettings {
+                enabled: false,
+                ..night::settings()
+            })
+            .await
+        }
+        #[cfg(feature = "ambient-light")]
+        Ok(Command::Ambient) => {
+            let settings = ambient::settings();
+            let mut out: String<96> = String::new();
+            let _ = writeln!(
+                out,
+                "{}% to {}%, smoothed over {}s, {}",
+                settings.min_pct,
+                settings.max_pct,
+                settings.smoothing_s,
+                if settings.enabled { "on" } else { "off" }
+            );
+            let _ = match ambient::level() {
+                Some(level) => writeln!(
+                    out,
+                    "light {:.0}%, LED at {}/255",
+                    level * 100.0,
+                    led::shown_brightness()
+                ),
+                None => writeln!(out, "no light reading yet"),
+            };
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+        #[cfg(feature = "ambient-light")]
+        Ok(Command::SetAmbient(settings)) => ambient::configure(settings).await,
+        #[cfg(feature = "ambient-light")]
+        Ok(Command::AmbientOff) => {
+            ambient::configure(AmbientSettings {
+                enabled: false,
+                ..ambient::settings()
+            })
+            .await
+        }
+        #[cfg(not(feature = "ambient-light"))]
+        Ok(Command::Ambient | Command::SetAmbient(_) | Command::AmbientOff) => {
+            let _ = tx.write_all(b"not an ambient-light build\n").await;
+        }
+        Err(e) => {
+            let mut out: String<64> = String::new();
+            let _ = writeln!(out, "error: {:?}", e);
+            let _ = tx.write_all(out.as_bytes()).await;
+        }
+    }
+}
+
+async fn stats(tx: &mut Tx) {
+    let mut out: String<256> = String::new();
+    let session_s = clock::monotonic_us() / 1_000_000;
+    match STATE.lock().await.as_ref().map(|state| *state.stats()) {
+        Some(stats) => {
+            let _ = writeln!(out, "boots:        {}", stats.boot_count);
+            let _ = writeln!(out, "uptime:       {} s", session_s);
+            let _ = writeln!(
+                out,
+                "total uptime: {} s",
+                stats.previous_uptime_s + session_s
+            );
+            let _ = writeln!(out, "last session: {} s", stats.last_session_s);
+            match stats.reset_reason {
+                Some(code) => {
+                    let reason = SocResetReason::from_repr(code as usize);
+                    let _ = writeln!(out, "reset reason: {:?} (0x{:02x})", reason, code);
+                }
+                None => {
+                    let _ = writeln!(out, "reset reason: unknown");
+                }
+            }
+            if let Some(task) = stats.stalled_task {
+                let _ = writeln!(out, "watchdog:     {:?} task stalled", task);
+            }
+        }
+        None => {
+            let _ = writeln!(out, "uptime:       {} s", session_s);
+            let _ = writeln!(out, "state store unavailable");
+        }
+    }
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Streams `count` records (or all of the rest) as CSV, from the one
+/// numbered `start`. Each record is introduced by a `# record <number>`
+/// marker and the trailer carries a CRC-32 of every byte in between, so a
+/// client can verify a page and resume at `next`.
+async fn dump(tx: &mut Tx, start: u32, count: Option<u32>, config: &Config) {
+    let mut digest = CRC32.digest();
+    let mut page = Page::new();
+    let mut next = start;
+    let mut left = count.unwrap_or(u32::MAX);
+
+    // Room for a full sample block rendered as CSV
+    let mut out: String<4096> = String::new();
+    let _ = write!(out, "# dump start={}\n{}", start, csv::HEADER);
+    let _ = tx.write_all(out.as_bytes()).await;
+
+    // Make sure the samples buffered in RAM are part of the dump
+    if let Some(log) = FLASH_LOG.lock().await.as_mut()
+        && let Err(e) = log.flush().await
+    {
+        log!(Module::Storage, warn, "Flash log flush failed: {}", e);
+    }
+
+    'pages: while left > 0 {
+        let more = match FLASH_LOG.lock().await.as_mut() {
+            Some(log) => log.read_page(next, &mut page).await,
+            None => break,
+        };
+
+        for (sequence, raw) in &page {
+            if left == 0 {
+                break 'pages;
+            }
+            out.clear();
+            let _ = writeln!(out, "# record {}", sequence);
+            match Record::decode(raw) {
+                Ok(record) => {
+                    let _ = record.write_csv(&mut out, config);
+                }
+                Err(_) => {
+                    let _ = writeln!(out, "# corrupt");
+                }
+            }
+            digest.update(out.as_bytes());
+            let _ = tx.write_all(out.as_bytes()).await;
+            next = sequence + 1;
+            left -= 1;
+            watchdog::feed(Task::Console);
+        }
+
+        match more {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                log!(Module::Storage, warn, "Flash log read failed: {}", e);
+                break;
+            }
+        }
+    }
+
+    out.clear();
+    let _ = writeln!(out, "# end next={} crc32={:08x}", next, digest.finalize());
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Prints the capture's state and, once it is complete, its samples as CSV
+/// with the crossing marked.
+async fn show_capture(tx: &mut Tx, config: &Config) {
+    let (state, trigger, len, trigger_index) = capture::status();
+    let mut out: String<128> = String::new();
+    let Some(trigger) = trigger else {
+        let _ = tx.write_all(b"no capture armed\n").await;
+        return;
+    };
+    let _ = writeln!(
+        out,
+        "# capture {} {}mV {}, {} samples",
+        trigger.edge.name(),
+        trigger.level_mv,
+        match state {
+            State::Idle | State::Armed => "armed",
+            State::Triggered => "triggered",
+            State::Done => "complete",
+        },
+        len
+    );
+    let _ = tx.write_all(out.as_bytes()).await;
+    if state != State::Done {
+        return;
+    }
+
+    let _ = tx.write_all(csv::HEADER.as_bytes()).await;
+    let mut chunk = [Sample {
+        timestamp_us: 0,
+        raw: 0,
+        voltage_mv: 0,
+    }; 32];
+    let mut index = 0;
+    while index < len {
+        let n = capture::read(index, &mut chunk);
+        if n == 0 {
+            break;
+        }
+        for sample in &chunk[..n] {
+            out.clear();
+            if index == trigger_index {
+                let _ = writeln!(out, "# trigger");
+            }
+            let _ = csv::write_row(&mut out, sample, config, None);
+            let _ = tx.write_all(out.as_bytes()).await;
+            index += 1;
+        }
+        watchdog::feed(Task::Console);
+    }
+}
+
+/// Samples for `duration_s` and prints the spread of the raw readings, in
+/// millivolts and millitesla.
+async fn measure_noise(tx: &mut Tx, duration_s: u32, config: &Config) {
+    let duration_s = duration_s.clamp(1, MAX_NOISE_S);
+    let mut out: String<256> = String::new();
+    let _ = writeln!(out, "sampling for {}s, keep magnets away", duration_s);
+    let _ = tx.write_all(out.as_bytes()).await;
+    noise::start(duration_s);
+
+    // A few seconds' grace for the first reading
+    let mut result = None;
+    for _ in 0..duration_s + 5 {
+        watchdog::feed(Task::Console);
+        if let Ok(noise) = with_timeout(IDLE_CHECK_IN, noise::DONE.wait()).await {
+            result = Some(noise);
+            break;
+        }
+    }
+    let Some(noise) = result.filter(|noise| noise.count() > 1) else {
+        let _ = tx.write_all(b"no readings\n").await;
+        return;
+    };
+
+    let mean_mv = Noise::to_mv(noise.mean());
+    let std_dev_mv = Noise::to_mv(noise.std_dev());
+    out.clear();
+    let _ = writeln!(out, "{} readings", noise.count());
+    let _ = writeln!(
+        out,
+        "mean      {:>7.1}mV {:>8.3}mT",
+        mean_mv,
+        config.field_mt(mean_mv as u32)
+    );
+    let _ = writeln!(
+        out,
+        "std dev   {:>7.2}mV {:>8.3}mT {:.2} counts",
+        std_dev_mv,
+        std_dev_mv / config.sensitivity_mv_per_mt,
+        noise.std_dev()
+    );
+    let _ = writeln!(
+        out,
+        "pk-pk     {:>7.1}mV {:>8.3}mT {} counts",
+        Noise::to_mv(noise.peak_to_peak() as f32),
+        Noise::to_mv(noise.peak_to_peak() as f32) / config.sensitivity_mv_per_mt,
+        noise.peak_to_peak()
+    );
+    let _ = writeln!(out, "effective {:>7.1} bits", noise.effective_bits());
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Tests a unit in fixture mode, switching to it first if need be, and
+/// prints the record.
+async fn run_fixture(tx: &mut Tx, unit: Option<u32>) {
+    let unit = unit.unwrap_or_else(fixture::next_unit);
+    let limits = fixture::limits();
+    let measurement = measure_fixture(&limits).await;
+    let mut out: String<256> = String::new();
+    if write_record(&mut out, unit, &measurement, &limits).is_err() {
+        out.clear();
+        let _ = writeln!(out, "error: record too long");
+    }
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Runs one fixture test, switching to fixture mode if need be. Readings
+/// that stop partway fail it.
+async fn measure_fixture(limits: &FixtureLimits) -> Measurement {
+    fixture::start();
+    if mode::current() != Mode::Fixture {
+        mode::request(Mode::Fixture);
+    }
+
+    // A few seconds' grace for the first reading
+    for _ in 0..limits.measure_ms / 1000 + 5 {
+        watchdog::feed(Task::Console);
+        if let Ok(measurement) = with_timeout(IDLE_CHECK_IN, fixture::DONE.wait()).await {
+            return measurement;
+        }
+    }
+    fixture::abandon()
+}
+
+/// Tests a magnet array a position at a time, each on its own mux
+/// channel, then puts back the channel that was selected. Prints a table
+/// for the operator and the record for the test station.
+#[cfg(feature = "mux")]
+async fn scan_fixture(tx: &mut Tx, positions: u8, unit: Option<u32>) {
+    let unit = unit.unwrap_or_else(fixture::next_unit);
+    let limits = fixture::limits();
+    let selected = crate::mux::channel();
+    let mut scan = Scan::new();
+    for position in 0..positions {
+        crate::mux::select(position);
+        scan.push(measure_fixture(&limits).await);
+    }
+    crate::mux::select(selected);
+    fixture::judge_scan(&scan);
+
+    let mut out: String<2048> = String::new();
+    let _ = writeln!(out, "# position result mean_mT spread_mT");
+    for (position, measurement) in scan.measurements().iter().enumerate() {
+        let _ = write!(
+            out,
+            "# {:>8} {:<6}",
+            position,
+            measurement.verdict(&limits).name()
+        );
+        let _ = match (measurement.mean_mt(), measurement.spread_mt()) {
+            (Some(mean_mt), Some(spread_mt)) => {
+                writeln!(out, " {:>7.3} {:>9.3}", mean_mt, spread_mt)
+            }
+            _ => writeln!(out, " {:>7} {:>9}", "-", "-"),
+        };
+    }
+    let _ = tx.write_all(out.as_bytes()).await;
+
+    out.clear();
+    if write_scan_record(&mut out, unit, &scan, &limits).is_err() {
+        out.clear();
+        let _ = writeln!(out, "error: record too long");
+    }
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Prints the Allan deviation at each averaging time reached so far. The
+/// longest times rest on few differences, so their figures are rough.
+async fn show_drift(tx: &mut Tx, run: &Run) {
+    let period_us = run.period_us();
+    let mut out: String<64> = String::new();
+    let _ = writeln!(
+        out,
+        "{} readings {}us apart\n   tau_s    adev_uT  n",
+        run.allan.samples(),
+        period_us
+    );
+    let _ = tx.write_all(out.as_bytes()).await;
+    for level in 0..MAX_LEVELS {
+        let Some(deviation_mt) = run.allan.deviation(level) else {
+            break;
+        };
+        out.clear();
+        let _ = writeln!(
+            out,
+            "{:>8.2} {:>10.2} {}",
+            (period_us << level) as f32 / 1e6,
+            deviation_mt * 1000.0,
+            run.allan.count(level)
+        );
+        let _ = tx.write_all(out.as_bytes()).await;
+    }
+}
+
+async fn show_filters(tx: &mut Tx) {
+    let mut out: String<48> = String::new();
+    for (i, stage) in filter::chain().stages().enumerate() {
+        let mut setting: String<16> = String::new();
+        let _ = match stage.stage {
+            Stage::Median { window } => write!(setting, "{} samples", window),
+            Stage::Notch(mains) if mains.harmonics => {
+                write!(setting, "{}Hz harmonics", mains.frequency_hz)
+            }
+            Stage::Notch(mains) => write!(setting, "{}Hz", mains.frequency_hz),
+            Stage::Average { weight_percent } => write!(setting, "{}%", weight_percent),
+        };
+        out.clear();
+        let _ = writeln!(
+            out,
+            "{} {:<6} {:<14} {}",
+            i + 1,
+            stage.stage.name(),
+            setting.as_str(),
+            if stage.enabled { "on" } else { "off" }
+        );
+        let _ = tx.write_all(out.as_bytes()).await;
+    }
+}
+
+/// Applies `edit` to the filter chain at the stage numbered `index` from 1,
+/// saving the chain if it succeeds.
+async fn edit_filters(tx: &mut Tx, edit: impl FnOnce(&mut Chain, usize) -> bool, index: u32) {
+    let Some(index) = (index as usize).checked_sub(1) else {
+        let _ = tx.write_all(b"stages are numbered from 1\n").await;
+        return;
+    };
+    if filter::edit(|chain| edit(chain, index)).await {
+        show_filters(tx).await;
+    } else {
+        let _ = tx
+            .write_all(b"no such stage; a new one goes at the end, up to 4\n")
+            .await;
+    }
+}
+
+/// Prints the histogram's non-empty span, a bin to a line with a bar scaled
+/// to the fullest bin.
+async fn show_histogram(tx: &mut Tx, config: &Config) {
+    const BAR_WIDTH: u32 = 40;
+
+    let h = histogram::snapshot(config.threshold_mv);
+    let mut out: String<96> = String::new();
+    let _ = writeln!(
+        out,
+        "{}mV bins, {} of {} samples; {}% at or above {}mV",
+        h.bin_width_mv, h.len, h.window, h.percent_from, config.threshold_mv
+    );
+    let _ = tx.write_all(out.as_bytes()).await;
+
+    let counts = &h.counts[..h.bins];
+    let (Some(first), Some(last)) = (
+        counts.iter().position(|&c| c > 0),
+        counts.iter().rposition(|&c| c > 0),
+    ) else {
+        return;
+    };
+    let fullest = counts.iter().copied().max().unwrap_or(1) as u32;
+    for (i, &count) in counts.iter().enumerate().take(last + 1).skip(first) {
+        let low_mv = i as u32 * h.bin_width_mv;
+        out.clear();
+        let _ = write!(
+            out,
+            "{:>4}mV {:>6.1}mT {:>5} ",
+            low_mv,
+            config.field_mt(low_mv),
+            count
+        );
+        for _ in 0..count as u32 * BAR_WIDTH / fullest {
+            let _ = out.push('#');
+        }
+        let _ = out.push('\n');
+        let _ = tx.write_all(out.as_bytes()).await;
+    }
+}
+
+async fn show_spectrum(tx: &mut Tx, analysis: &Analysis) {
+    let mut out: String<160> = String::new();
+    let _ = writeln!(
+        out,
+        "{} points at {:.1}Hz, {:.2}Hz resolution",
+        analysis.points,
+        analysis.rate_hz,
+        analysis.rate_hz / analysis.points as f32
+    );
+    for peak in analysis.peaks.iter().flatten() {
+        let _ = writeln!(
+            out,
+            "{:>8.2}Hz {:>7.1}mV",
+            peak.frequency_hz, peak.amplitude_mv
+        );
+    }
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+async fn show_tone(tx: &mut Tx, tone: &Tone) {
+    let mut out: String<64> = String::new();
+    let _ = writeln!(
+        out,
+        "{}Hz: {:.1}mV at {:.1} degrees",
+        tone.frequency_hz, tone.amplitude_mv, tone.phase_deg
+    );
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+/// Streams the last burst as `time_us,raw,mv` rows, timed from its start.
+async fn profile_error(tx: &mut Tx, e: ProfileError) {
+    let mut out: String<64> = String::new();
+    let _ = writeln!(out, "error: {}", e.reason());
+    let _ = tx.write_all(out.as_bytes()).await;
+}
+
+async fn dump_burst(tx: &mut Tx, factor: usize) {
+    let Some(summary) = burst::summary() else {
+        let _ = tx.write_all(b"no burst yet\n").await;
+        return;
+    };
+    let mut out: String<64> = String::new();
+    let _ = write!(
+        out,
+        "# burst {}Hz decimate={}\ntime_us,raw,mv\n",
+        summary.rate_hz, factor
+    );
+    let _ = tx.write_all(out.as_bytes()).await;
+
+    let period_us = factor as f32 * 1e6 / summary.rate_hz.max(1) as f32;
+    let mut chunk = [0u16; 64];
+    let mut index = 0;
+    loop {
+        let n = burst::read(index, factor, &mut chunk);
+        if n == 0 {
+            break;
+        }
+        for &raw in &chunk[..n] {
+            out.clear();
+            let _ = writeln!(
+                out,
+                "{},{},{}",
+                (index as f32 * period_us) as u32,
+                raw,
+                hall_effect::burst::voltage_mv(raw)
+            );
+            let _ = tx.write_all(out.as_bytes()).await;
+            index += 1;
+        }
+        watchdog::feed(Task::Console);
+    }
+}
@@ -0,0 +1,107 @@
+//! Contact mode: a door or window sensor. Each settled change between open
+//! and closed is logged, kept with its time for `contact` on the console,
+//! and published to telemetry as `Event::Contact`, which every transport
+//! carries. The thresholds and debounce time are set with `contact` on the
+//! console and kept in the `state` partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use hall_effect::contact::{Contact, Settings};
+use hall_effect::schema::Event;
+use hall_effect::verbosity::Module;
+use heapless::Deque;
+
+use crate::state::STATE;
+use crate::telemetry::{self, Telemetry};
+use crate::verbosity::log;
+
+/// Changes kept for the console.
+pub const HISTORY: usize = 8;
+
+/// A settled change, at `time_ms` since boot.
+#[derive(Clone, Copy)]
+pub struct Change {
+    pub time_ms: u64,
+    pub closed: bool,
+}
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static CLOSED: Mutex<Cell<Option<bool>>> = Mutex::new(Cell::new(None));
+static CHANGES: Mutex<RefCell<Deque<Change, HISTORY>>> = Mutex::new(RefCell::new(Deque::new()));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next reading, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_contact_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Contact settings not saved: {}", e);
+    }
+}
+
+/// Whether the contact is closed, once known, in contact mode.
+pub fn is_closed() -> Option<bool> {
+    critical_section::with(|cs| CLOSED.borrow(cs).get())
+}
+
+/// The latest changes, oldest first.
+pub fn changes() -> Deque<Change, HISTORY> {
+    critical_section::with(|cs| CHANGES.borrow_ref(cs).clone())
+}
+
+/// The processing stage's half, for contact mode.
+pub struct Sensor {
+    contact: Contact,
+}
+
+impl Sensor {
+    pub const fn new() -> Self {
+        Self {
+            contact: Contact::new(),
+        }
+    }
+
+    /// Takes a valid reading.
+    pub fn push(&mut self, timestamp_us: u64, field_mt: f32) {
+        let time_ms = timestamp_us / 1000;
+        let Some(closed) = self.contact.update(time_ms, field_mt, &settings()) else {
+            return;
+        };
+        critical_section::with(|cs| {
+            CLOSED.borrow(cs).set(Some(closed));
+            let mut changes = CHANGES.borrow_ref_mut(cs);
+            if changes.is_full() {
+                changes.pop_front();
+            }
+            let _ = changes.push_back(Change { time_ms, closed });
+        });
+        log!(
+            Module::Sensor,
+            info,
+            "Contact {} at {}ms",
+            if closed { "closed" } else { "open" },
+            time_ms
+        );
+        telemetry::publish(Telemetry::Event {
+            time_ms,
+            event: Event::Contact { closed },
+        });
+    }
+
+    /// On leaving contact mode: the state is unknown until it is entered
+    /// again and settles.
+    pub fn stop(&mut self) {
+        self.contact = Contact::new();
+        critical_section::with(|cs| CLOSED.borrow(cs).set(None));
+    }
+}
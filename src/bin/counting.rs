@@ -0,0 +1,106 @@
+//! Count mode: parts going past with magnets in them, counted on rising
+//! threshold crossings and gathered into batches. With the
+//! `batch-output` feature, the end of each batch pulses GPIO46 high, as
+//! for a diverter or a PLC input. The counts carry over when leaving the
+//! mode, until `count reset` on the console; the settings are kept in the
+//! `state` partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+#[cfg(feature = "batch-output")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "batch-output")]
+use embassy_sync::signal::Signal;
+#[cfg(feature = "batch-output")]
+use embassy_time::{Duration, Timer};
+#[cfg(feature = "batch-output")]
+use esp_hal::gpio::Output;
+use hall_effect::counting::{Count, Counter, Settings, Totals};
+use hall_effect::verbosity::Module;
+
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static COUNTER: Mutex<RefCell<Counter>> = Mutex::new(RefCell::new(Counter::new()));
+#[cfg(feature = "batch-output")]
+static BATCH_DONE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next crossing, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_count_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Count settings not saved: {}", e);
+    }
+}
+
+pub fn totals() -> Totals {
+    critical_section::with(|cs| COUNTER.borrow_ref(cs).totals())
+}
+
+/// Starts the counts over.
+pub fn reset() {
+    critical_section::with(|cs| COUNTER.replace(cs, Counter::new()));
+}
+
+/// On entering count mode.
+pub fn start() {
+    let totals = totals();
+    log!(
+        Module::Sensor,
+        info,
+        "Counting from {} parts, {} batches: {}",
+        totals.parts,
+        totals.batches,
+        settings()
+    );
+}
+
+/// Takes a rising crossing at `timestamp_us`, in count mode.
+pub fn pass(timestamp_us: u64) {
+    let settings = settings();
+    let count = critical_section::with(|cs| {
+        COUNTER
+            .borrow_ref_mut(cs)
+            .pass(timestamp_us / 1000, &settings)
+    });
+    if count == Some(Count::BatchDone) {
+        let totals = totals();
+        log!(
+            Module::Sensor,
+            info,
+            "Batch {} done, {} parts",
+            totals.batches,
+            totals.parts
+        );
+        #[cfg(feature = "batch-output")]
+        BATCH_DONE.signal(());
+    }
+}
+
+/// Pulses the batch output at the end of each batch. Batches ending
+/// close together get a pulse each, held apart by a gap as long.
+#[cfg(feature = "batch-output")]
+#[embassy_executor::task]
+pub async fn output_task(mut output: Output<'static>) {
+    loop {
+        BATCH_DONE.wait().await;
+        let width = Duration::from_millis(settings().pulse_ms as u64);
+        output.set_high();
+        Timer::after(width).await;
+        output.set_low();
+        Timer::after(width).await;
+    }
+}
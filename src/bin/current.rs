@@ -0,0 +1,199 @@
+//! Current mode: the reading in amps, through the scale set from the
+//! console with `current` and kept in the `state` partition. With the
+//! `null-flux` feature, `current loop` drives a compensation winding from
+//! LEDC PWM on GPIO39, through a bipolar stage whose output is zero at
+//! half duty, to hold the core's flux at zero.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+#[cfg(feature = "null-flux")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "null-flux")]
+use embassy_sync::signal::Signal;
+#[cfg(feature = "null-flux")]
+use esp_hal::gpio::DriveMode;
+#[cfg(feature = "null-flux")]
+use esp_hal::ledc::channel::{self, Channel, ChannelHW, ChannelIFace};
+#[cfg(feature = "null-flux")]
+use esp_hal::ledc::timer::{self, TimerIFace};
+#[cfg(feature = "null-flux")]
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+#[cfg(feature = "null-flux")]
+use esp_hal::peripherals::{GPIO39, LEDC};
+#[cfg(feature = "null-flux")]
+use esp_hal::time::Rate;
+use hall_effect::current::{NullFlux, Scale};
+use hall_effect::schema::Sample;
+use hall_effect::verbosity::Module;
+#[cfg(feature = "null-flux")]
+use static_cell::StaticCell;
+
+use crate::state::STATE;
+use crate::verbosity::log;
+
+// How often the current is logged
+const REPORT_US: u64 = 1_000_000;
+#[cfg(feature = "null-flux")]
+const PWM_FREQUENCY: Rate = Rate::from_khz(20);
+#[cfg(feature = "null-flux")]
+const DUTY_RANGE: u32 = 1 << 10;
+
+static SCALE: Mutex<Cell<Scale>> = Mutex::new(Cell::new(Scale::DEFAULT));
+/// The closed loop's full scale in amps, while it is on.
+static FULL_SCALE_A: Mutex<Cell<Option<f32>>> = Mutex::new(Cell::new(None));
+static AMPS: Mutex<Cell<Option<f32>>> = Mutex::new(Cell::new(None));
+/// The winding's next duty, for [`winding_task`], which owns the channel.
+#[cfg(feature = "null-flux")]
+static WINDING: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+pub fn scale() -> Scale {
+    critical_section::with(|cs| SCALE.borrow(cs).get())
+}
+
+/// Takes on the scale saved in flash, at boot.
+pub fn restore(scale: Scale) {
+    critical_section::with(|cs| SCALE.borrow(cs).set(scale));
+}
+
+/// Changes the scale and saves it.
+pub async fn set_scale(scale: Scale) {
+    restore(scale);
+    log!(
+        Module::Sensor,
+        info,
+        "Current scale: zero {}mV, {}mV/A",
+        scale.zero_mv,
+        scale.mv_per_a
+    );
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_current_scale(&scale).await
+    {
+        log!(Module::Storage, warn, "Current scale not saved: {}", e);
+    }
+}
+
+pub fn full_scale_a() -> Option<f32> {
+    critical_section::with(|cs| FULL_SCALE_A.borrow(cs).get())
+}
+
+/// Switches the closed loop on with `full_scale_a`, the primary current
+/// the winding balances at either end of its drive, or off.
+pub fn set_loop(full_scale_a: Option<f32>) {
+    critical_section::with(|cs| FULL_SCALE_A.borrow(cs).set(full_scale_a));
+}
+
+/// The last current measured, in current mode.
+pub fn amps() -> Option<f32> {
+    critical_section::with(|cs| AMPS.borrow(cs).get())
+}
+
+/// Sets up the PWM, with the winding idle, at boot.
+#[cfg(feature = "null-flux")]
+pub fn init(ledc: LEDC<'static>, pin: GPIO39<'static>) -> Option<Channel<'static, LowSpeed>> {
+    static TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+    let mut ledc = Ledc::new(ledc);
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let timer = TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+    if let Err(e) = timer.configure(timer::config::Config {
+        duty: timer::config::Duty::Duty10Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: PWM_FREQUENCY,
+    }) {
+        log!(Module::Sensor, warn, "Compensation PWM unavailable: {}", e);
+        return None;
+    }
+    let mut winding = ledc.channel(channel::Number::Channel0, pin);
+    if let Err(e) = winding.configure(channel::config::Config {
+        timer: &*timer,
+        duty_pct: 50,
+        drive_mode: DriveMode::PushPull,
+    }) {
+        log!(Module::Sensor, warn, "Compensation PWM unavailable: {}", e);
+        return None;
+    }
+    Some(winding)
+}
+
+#[cfg(feature = "null-flux")]
+#[embassy_executor::task]
+pub async fn winding_task(winding: Channel<'static, LowSpeed>) {
+    loop {
+        winding.set_duty_hw(WINDING.wait().await);
+    }
+}
+
+#[cfg(feature = "null-flux")]
+fn set_duty(duty: f32) {
+    WINDING.signal((duty.clamp(0.0, 1.0) * DUTY_RANGE as f32) as u32);
+}
+
+#[cfg(not(feature = "null-flux"))]
+fn set_duty(_duty: f32) {}
+
+/// The processing stage's half, for current mode.
+pub struct Meter {
+    null_flux: Option<NullFlux>,
+    last_us: Option<u64>,
+    report_us: u64,
+}
+
+impl Meter {
+    pub const fn new() -> Self {
+        Self {
+            null_flux: None,
+            last_us: None,
+            report_us: 0,
+        }
+    }
+
+    /// Takes a valid reading, rebuilding the loop when the scale or its
+    /// full scale changes, and returns the current.
+    pub fn push(&mut self, sample: &Sample) -> f32 {
+        let voltage_mv = sample.voltage_mv as f32;
+        let dt_s = self
+            .last_us
+            .replace(sample.timestamp_us)
+            .map_or(0.0, |last_us| {
+                sample.timestamp_us.saturating_sub(last_us) as f32 / 1e6
+            });
+        let scale = scale();
+        let amps = match full_scale_a() {
+            Some(full_scale_a) => {
+                let null_flux = match &mut self.null_flux {
+                    Some(null_flux)
+                        if null_flux.scale() == scale
+                            && null_flux.full_scale_a() == full_scale_a =>
+                    {
+                        null_flux
+                    }
+                    slot => slot.insert(NullFlux::new(scale, full_scale_a)),
+                };
+                set_duty(null_flux.update(voltage_mv, dt_s));
+                null_flux.amps(voltage_mv)
+            }
+            None => {
+                if self.null_flux.take().is_some() {
+                    set_duty(0.5);
+                }
+                scale.amps(voltage_mv)
+            }
+        };
+        critical_section::with(|cs| AMPS.borrow(cs).set(Some(amps)));
+        if sample.timestamp_us - self.report_us >= REPORT_US {
+            self.report_us = sample.timestamp_us;
+            log!(Module::Sample, info, "Current: {}A", amps);
+        }
+        amps
+    }
+
+    /// On leaving current or power mode: the winding is left idle.
+    pub fn stop(&mut self) {
+        if self.null_flux.take().is_some() {
+            set_duty(0.5);
+        }
+        self.last_us = None;
+        critical_section::with(|cs| AMPS.borrow(cs).set(None));
+    }
+}
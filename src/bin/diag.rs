@@ -0,0 +1,213 @@
+//! Diagnostics report for the `diag` command: one line of JSON covering
+//! firmware, configuration, sampling statistics, stack headroom and recent
+//! faults.
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+
+use critical_section::Mutex;
+use hall_effect::diag::RateStats;
+use hall_effect::schema::{Config, Event};
+use heapless::Deque;
+
+use crate::flash_log;
+#[cfg(feature = "timer-sampling")]
+use crate::sample_timer;
+use crate::state::BootStats;
+use crate::{board, clock, panic};
+
+/// How often tasks log their timing summaries.
+pub const TIMING_REPORT_US: u64 = 10_000_000;
+
+// Faults kept for the report, oldest dropped first
+const RECENT_FAULTS: usize = 8;
+
+// What paces the sampler, for reading the jitter
+#[cfg(feature = "timer-sampling")]
+const SAMPLE_SOURCE: &str = "timer";
+#[cfg(all(feature = "light-sleep", not(feature = "timer-sampling")))]
+const SAMPLE_SOURCE: &str = "sleep";
+#[cfg(not(any(feature = "light-sleep", feature = "timer-sampling")))]
+const SAMPLE_SOURCE: &str = "ticker";
+
+const PAINT: u32 = 0xa5a5_a5a5;
+
+// Left unpainted below the stack pointer for the painting code itself
+const PAINT_MARGIN: usize = 1024;
+
+static RATE: Mutex<RefCell<RateStats>> = Mutex::new(RefCell::new(RateStats::new()));
+static FAULTS: Mutex<RefCell<Deque<(u64, Event), RECENT_FAULTS>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+unsafe extern "C" {
+    // Lowest word of the stack in use; painting starts above it so the
+    // stack guard watchpoint is left alone
+    static __stack_chk_guard: u32;
+    static _stack_start: u32;
+}
+
+fn stack_bounds() -> (usize, usize) {
+    let bottom = (&raw const __stack_chk_guard) as usize + 4;
+    let top = (&raw const _stack_start) as usize;
+    (bottom, top)
+}
+
+/// Fills the unused part of the stack with a known pattern, so the high
+/// water mark can be found later. Call once, early.
+pub fn paint_stack() {
+    critical_section::with(|_| {
+        let marker = 0u8;
+        let limit = (&raw const marker) as usize - PAINT_MARGIN;
+        let (bottom, _) = stack_bounds();
+        let mut p = bottom as *mut u32;
+        while (p as usize) < limit {
+            // SAFETY: below the stack pointer, with interrupts disabled
+            unsafe {
+                p.write_volatile(PAINT);
+                p = p.add(1);
+            }
+        }
+    });
+}
+
+/// Bytes of stack that have never been used since [`paint_stack`].
+pub fn stack_free() -> usize {
+    let (bottom, top) = stack_bounds();
+    let mut p = bottom as *const u32;
+    // SAFETY: reads stay within the stack region
+    while (p as usize) < top && unsafe { p.read_volatile() } == PAINT {
+        p = unsafe { p.add(1) };
+    }
+    p as usize - bottom
+}
+
+/// Total stack and the part never used, in bytes.
+pub fn stack_usage() -> (usize, usize) {
+    let (bottom, top) = stack_bounds();
+    (top - bottom, stack_free())
+}
+
+pub fn record_sample(timestamp_us: u64) {
+    critical_section::with(|cs| RATE.borrow_ref_mut(cs).update(timestamp_us));
+}
+
+pub fn record_fault(time_ms: u64, event: Event) {
+    critical_section::with(|cs| {
+        let mut faults = FAULTS.borrow_ref_mut(cs);
+        if faults.is_full() {
+            faults.pop_front();
+        }
+        let _ = faults.push_back((time_ms, event));
+    });
+}
+
+fn write_fault<W: Write>(w: &mut W, time_ms: u64, event: &Event) -> fmt::Result {
+    write!(w, "{{\"t_ms\":{},", time_ms)?;
+    match *event {
+        Event::LowSupply { duration_ms } => {
+            write!(w, "\"kind\":\"low_supply\",\"duration_ms\":{}", duration_ms)?
+        }
+        Event::WiringFault { stuck_high } => write!(
+            w,
+            "\"kind\":\"wiring_fault\",\"rail\":\"{}\"",
+            if stuck_high { "high" } else { "low" }
+        )?,
+        Event::WiringFaultCleared => write!(w, "\"kind\":\"wiring_fault_cleared\"")?,
+        _ => write!(w, "\"kind\":\"other\"")?,
+    }
+    w.write_char('}')
+}
+
+pub fn write_report<W: Write>(w: &mut W, config: &Config, boot: Option<&BootStats>) -> fmt::Result {
+    write!(
+        w,
+        "{{\"fw\":\"{}\",\"chip\":\"{}\",\"board\":\"{}\",\"uptime_s\":{}",
+        env!("CARGO_PKG_VERSION"),
+        esp_hal::chip!(),
+        board::NAME,
+        clock::monotonic_us() / 1_000_000
+    )?;
+    if let Some(boot) = boot {
+        write!(w, ",\"boots\":{}", boot.boot_count)?;
+        match boot.reset_reason {
+            Some(code) => write!(w, ",\"reset_reason\":{}", code)?,
+            None => write!(w, ",\"reset_reason\":null")?,
+        }
+        match boot.stalled_task {
+            Some(task) => write!(w, ",\"stalled_task\":\"{:?}\"", task)?,
+            None => write!(w, ",\"stalled_task\":null")?,
+        }
+        match boot.settings {
+            Some(loaded) => {
+                write!(
+                    w,
+                    ",\"settings\":{{\"slot\":\"{}\",\"sequence\":{}",
+                    loaded.slot.name(),
+                    loaded.sequence
+                )?;
+                match loaded.other_error {
+                    Some(e) => write!(w, ",\"other_error\":\"{}\"}}", e.name())?,
+                    None => write!(w, ",\"other_error\":null}}")?,
+                }
+            }
+            None => write!(w, ",\"settings\":null")?,
+        }
+    }
+
+    write!(
+        w,
+        ",\"calibration\":{{\"zero_field_mv\":{},\"sensitivity_mv_per_mt\":{}}}",
+        config.zero_field_mv, config.sensitivity_mv_per_mt
+    )?;
+    write!(
+        w,
+        ",\"filter\":{{\"threshold_mv\":{},\"hysteresis_mv\":{},\"decimation_ms\":{}",
+        config.threshold_mv,
+        config.hysteresis_mv,
+        flash_log::decimation_ms(config)
+    )?;
+    write!(w, ",\"fault_timeout_ms\":{}}}", config.fault_timeout_ms)?;
+
+    let (count, intervals, jitter) = critical_section::with(|cs| {
+        let rate = RATE.borrow_ref(cs);
+        (rate.count(), rate.intervals_us(), rate.jitter_us())
+    });
+    write!(
+        w,
+        ",\"sampling\":{{\"period_ms\":{},\"source\":\"{}\",\"count\":{}",
+        config.sample_period_ms, SAMPLE_SOURCE, count
+    )?;
+    if let Some((mean, min, max)) = intervals {
+        write!(
+            w,
+            ",\"mean_us\":{},\"min_us\":{},\"max_us\":{}",
+            mean, min, max
+        )?;
+    }
+    if let Some(jitter) = jitter {
+        write!(w, ",\"jitter_us\":{}", jitter)?;
+    }
+    #[cfg(feature = "timer-sampling")]
+    write!(w, ",\"overflows\":{}", sample_timer::overflows())?;
+    w.write_char('}')?;
+
+    // No radio or allocator in this firmware
+    let (size, free) = stack_usage();
+    write!(
+        w,
+        ",\"wifi\":null,\"heap\":null,\"stack\":{{\"size\":{},\"free\":{}}}",
+        size, free
+    )?;
+
+    write!(w, ",\"panic_pending\":{},\"faults\":[", panic::pending())?;
+    critical_section::with(|cs| {
+        for (i, (time_ms, event)) in FAULTS.borrow_ref(cs).iter().enumerate() {
+            if i > 0 {
+                w.write_char(',')?;
+            }
+            write_fault(w, *time_ms, event)?;
+        }
+        Ok(())
+    })?;
+    w.write_str("]}\n")
+}
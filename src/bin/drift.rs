@@ -0,0 +1,76 @@
+//! Drift runs: the Allan deviation of the field, gathered in drift mode and
+//! shown on the console with `drift`.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hall_effect::allan::Allan;
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+
+#[derive(Clone, Copy)]
+pub struct Run {
+    pub allan: Allan,
+    first_us: u64,
+    last_us: u64,
+}
+
+impl Run {
+    const fn new() -> Self {
+        Self {
+            allan: Allan::new(),
+            first_us: 0,
+            last_us: 0,
+        }
+    }
+
+    /// The mean time between readings, which sets the averaging times.
+    pub fn period_us(&self) -> u64 {
+        (self.last_us - self.first_us) / self.allan.samples().saturating_sub(1).max(1)
+    }
+}
+
+static RUN: Mutex<RefCell<Run>> = Mutex::new(RefCell::new(Run::new()));
+
+/// Starts over, on entering drift mode.
+pub fn start() {
+    critical_section::with(|cs| RUN.replace(cs, Run::new()));
+    log!(Module::Sensor, info, "Drift run started, keep magnets away");
+}
+
+/// Adds a valid reading, logging each averaging time as it gets its first
+/// deviation.
+pub fn push(timestamp_us: u64, field_mt: f32) {
+    let first = critical_section::with(|cs| {
+        let mut run = RUN.borrow_ref_mut(cs);
+        if run.allan.samples() == 0 {
+            run.first_us = timestamp_us;
+        }
+        run.last_us = timestamp_us;
+        run.allan.push(field_mt);
+
+        // Level n has its first difference after 2^(n + 1) readings
+        let samples = run.allan.samples();
+        if samples < 2 || !samples.is_power_of_two() {
+            return None;
+        }
+        let level = samples.trailing_zeros() as usize - 1;
+        Some((run.period_us() << level, run.allan.deviation(level)?))
+    });
+    if let Some((tau_us, deviation_mt)) = first {
+        log!(
+            Module::Sensor,
+            info,
+            "Drift: {}mT over {}ms",
+            deviation_mt,
+            tau_us / 1000
+        );
+    }
+}
+
+/// The current run, if it has any readings.
+pub fn run() -> Option<Run> {
+    let run = critical_section::with(|cs| *RUN.borrow_ref(cs));
+    (run.allan.samples() > 0).then_some(run)
+}
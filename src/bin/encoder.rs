@@ -0,0 +1,95 @@
+//! Quadrature rotary encoder on a PCNT unit, with its push-button, for
+//! adjusting the alarm threshold and LED brightness on the device.
+//!
+//! Each press of the encoder's button selects the next setting, and the LED
+//! previews the value while it is turned: the threshold's colour in the
+//! palette, or white at the new brightness. Editing ends after the last
+//! setting or a few seconds without input.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use esp_hal::gpio::Input;
+use esp_hal::pcnt::unit::Unit;
+use hall_effect::color::{self, RGB8};
+use hall_effect::encoder::{Detents, Editor, Setting};
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+use crate::{led, palette};
+
+// How often the counter is read
+const POLL: Duration = Duration::from_millis(20);
+
+// Contact bounce to ignore after a press
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+// Editing ends after this long without input
+const EDIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// New alarm threshold, picked up by the processing stage before the next
+/// sample.
+pub static THRESHOLD: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+#[embassy_executor::task]
+pub async fn encoder_task(unit: Unit<'static, 0>, mut button: Input<'static>, config: Config) {
+    let mut editor = Editor::new(config.threshold_mv, led::brightness());
+    let mut detents = Detents::new();
+    let mut count = unit.value();
+    let mut last_input = Instant::now();
+
+    loop {
+        if with_timeout(POLL, button.wait_for_falling_edge())
+            .await
+            .is_ok()
+        {
+            Timer::after(DEBOUNCE).await;
+            if button.is_low() {
+                last_input = Instant::now();
+                match editor.select() {
+                    Some(setting) => {
+                        log!(Module::Led, info, "Editing {}", setting);
+                        show(&editor, &config);
+                    }
+                    None => log!(Module::Led, info, "Editing done"),
+                }
+            }
+        }
+
+        // The counter has no limits set, so it wraps and the difference holds
+        let value = unit.value();
+        let turned = detents.push(value.wrapping_sub(count));
+        count = value;
+        if turned != 0 {
+            last_input = Instant::now();
+        }
+        // Turned from wherever the brightness is now, which the IR remote
+        // may have changed since
+        editor.brightness = led::brightness();
+        if editor.turn(turned, &config) {
+            match editor.setting {
+                Some(Setting::Threshold) => THRESHOLD.signal(editor.threshold_mv),
+                Some(Setting::Brightness) => led::set_brightness(editor.brightness),
+                None => {}
+            }
+            show(&editor, &config);
+        }
+
+        if editor.setting.is_some() && last_input.elapsed() >= EDIT_TIMEOUT {
+            editor.setting = None;
+            log!(Module::Led, info, "Editing done");
+        }
+    }
+}
+
+fn show(editor: &Editor, config: &Config) {
+    match editor.setting {
+        Some(Setting::Threshold) => {
+            let color = color::voltage_to_color(editor.threshold_mv, config, palette::current());
+            led::preview(color);
+        }
+        Some(Setting::Brightness) => led::preview(RGB8::new(255, 255, 255)),
+        None => {}
+    }
+}
@@ -0,0 +1,179 @@
+//! Waveshare 2.13" e-paper on SPI3 for battery deployments: the reading in
+//! the units set, the lowest and highest seen today or since a scheduled
+//! reset and, with `battery`, the charge left, updated once a minute.
+//!
+//! Updates are partial refreshes, with a full refresh every so often to clear
+//! the ghosting they build up. Between updates the controller is in deep
+//! sleep, and the panel keeps its image without power.
+
+use core::fmt::Write;
+
+use defmt::Debug2Format;
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_hal::spi::ErrorType;
+use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+use esp_hal::Blocking;
+use esp_hal::gpio::{Input, Output};
+use esp_hal::spi::master::Spi;
+use hall_effect::ssd1680::{Frame, Ssd1680, WIDTH};
+use hall_effect::units::Range;
+use hall_effect::verbosity::Module;
+use heapless::String;
+
+use crate::clock;
+use crate::reading::{LATEST, Reading};
+use crate::schedule;
+use crate::units;
+use crate::verbosity::log;
+
+type Device = ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>;
+
+pub type Epd = Ssd1680<Device, Output<'static>>;
+
+const UPDATE_US: u64 = 60_000_000;
+
+// Partial refreshes between full ones
+const PARTIALS_PER_FULL: u32 = 30;
+
+// A full refresh takes about 2s; anything much longer is a stuck panel
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const US_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000;
+
+/// The control lines alongside the SPI device.
+pub struct Pins {
+    pub reset: Output<'static>,
+    pub busy: Input<'static>,
+}
+
+#[derive(Debug)]
+enum Error {
+    Spi(<Device as ErrorType>::Error),
+    /// BUSY stayed high.
+    Busy,
+}
+
+#[embassy_executor::task]
+pub async fn epaper_task(mut epd: Epd, mut pins: Pins) {
+    let Some(mut latest) = LATEST.receiver() else {
+        log!(Module::Display, warn, "E-paper has no reading receiver");
+        return;
+    };
+    let mut frame = Frame::new();
+    let mut range: Option<Range> = None;
+    let mut range_resets = schedule::range_resets();
+    let mut day = None;
+    // Forces a full refresh first, to set the base image
+    let mut partials = PARTIALS_PER_FULL;
+    let mut update_us = None;
+
+    loop {
+        let reading = latest.changed().await;
+        let now_us = clock::monotonic_us();
+
+        // Days follow the wall clock once it is set, and uptime until then
+        let today = clock::unix_us(now_us).unwrap_or(now_us) / US_PER_DAY;
+        let resets = schedule::range_resets();
+        if day.replace(today) != Some(today) || resets != range_resets {
+            range_resets = resets;
+            range = None;
+        }
+        if reading.valid {
+            range = Some(Range::widen(range, units::of(&reading)));
+        }
+
+        if update_us.is_some_and(|last_us| now_us - last_us < UPDATE_US) {
+            continue;
+        }
+        update_us = Some(now_us);
+        draw(&mut frame, &reading, range);
+
+        let partial = partials < PARTIALS_PER_FULL;
+        match update(&mut epd, &mut pins, &frame, partial).await {
+            Ok(()) => partials = if partial { partials + 1 } else { 0 },
+            Err(e) => {
+                log!(Module::Display, warn, "E-paper update failed: {}", Debug2Format(&e));
+                // Start over from a full refresh next time
+                partials = PARTIALS_PER_FULL;
+            }
+        }
+    }
+}
+
+async fn update(epd: &mut Epd, pins: &mut Pins, frame: &Frame, partial: bool) -> Result<(), Error> {
+    // A hardware reset also wakes the controller from deep sleep
+    pins.reset.set_low();
+    Timer::after_millis(1).await;
+    pins.reset.set_high();
+    Timer::after_millis(1).await;
+    wait(pins).await?;
+    if !partial {
+        epd.software_reset().map_err(Error::Spi)?;
+        wait(pins).await?;
+    }
+    epd.configure(partial).map_err(Error::Spi)?;
+    epd.write_frame(frame, !partial).map_err(Error::Spi)?;
+    epd.refresh(partial).map_err(Error::Spi)?;
+    wait(pins).await?;
+    epd.sleep().map_err(Error::Spi)
+}
+
+async fn wait(pins: &mut Pins) -> Result<(), Error> {
+    with_timeout(BUSY_TIMEOUT, pins.busy.wait_for_low())
+        .await
+        .map_err(|_| Error::Busy)
+}
+
+fn draw(frame: &mut Frame, reading: &Reading, range: Option<Range>) {
+    frame.clear();
+
+    let mut text: String<32> = String::new();
+    if reading.valid {
+        let shown = units::of(reading);
+        let _ = write!(text, "{}", shown.number());
+        // Smaller, beside the number and level with its foot
+        let symbol = shown.unit.symbol();
+        let symbol_x = WIDTH - Frame::text_width(symbol, 2);
+        frame.text(symbol_x, 22, symbol, 2);
+        frame.text(symbol_x - 6 - Frame::text_width(&text, 4), 8, &text, 4);
+    } else {
+        let _ = text.push_str("FAULT");
+        frame.text(WIDTH - Frame::text_width(&text, 4), 8, &text, 4);
+    }
+
+    text.clear();
+    match range {
+        Some(range) => {
+            let _ = write!(
+                text,
+                "today {} to {}",
+                range.min.number(),
+                range.max.number()
+            );
+        }
+        None => {
+            let _ = text.push_str("today -");
+        }
+    }
+    frame.text(0, 56, &text, 2);
+    frame.hline(0, WIDTH - 1, 80);
+
+    text.clear();
+    match clock::now() {
+        Some(now) => {
+            let _ = write!(text, "updated {:02}:{:02}", now.hour, now.minute);
+        }
+        None => {
+            let uptime_s = clock::monotonic_us() / 1_000_000;
+            let _ = write!(text, "up {}h {:02}m", uptime_s / 3600, uptime_s / 60 % 60);
+        }
+    }
+    frame.text(0, 88, &text, 2);
+
+    #[cfg(feature = "battery")]
+    if let Some(voltage_mv) = crate::battery::voltage_mv() {
+        text.clear();
+        let _ = write!(text, "battery {}%", hall_effect::battery::percent(voltage_mv));
+        frame.text(0, 104, &text, 2);
+    }
+}
@@ -0,0 +1,83 @@
+//! The filter chain, set from the console with `filter`, kept in the
+//! `state` partition, and applied to every sample as processing takes it.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::filter::{Chain, Filters};
+use hall_effect::schema::Sample;
+use hall_effect::verbosity::Module;
+
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static CHAIN: Mutex<Cell<Chain>> = Mutex::new(Cell::new(Chain::DEFAULT));
+
+pub fn chain() -> Chain {
+    critical_section::with(|cs| CHAIN.borrow(cs).get())
+}
+
+/// Takes on the chain saved in flash, at boot.
+pub fn restore(chain: Chain) {
+    critical_section::with(|cs| CHAIN.borrow(cs).set(chain));
+}
+
+/// Changes the chain through `edit` and saves it. Returns `false`, changing
+/// nothing, if `edit` does.
+pub async fn edit(edit: impl FnOnce(&mut Chain) -> bool) -> bool {
+    let mut chain = chain();
+    if !edit(&mut chain) {
+        return false;
+    }
+    restore(chain);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_filters(&chain).await
+    {
+        log!(Module::Storage, warn, "Filter chain not saved: {}", e);
+    }
+    true
+}
+
+/// The processing stage's filters, rebuilt whenever the chain or the
+/// sample period changes: a notch has to move with the rate, as a quiet
+/// field or a low battery slows sampling.
+pub struct Pipeline {
+    filters: Option<Filters>,
+    chain: Chain,
+    period_ms: u32,
+    last_us: Option<u64>,
+}
+
+impl Pipeline {
+    pub const fn new() -> Self {
+        Self {
+            filters: None,
+            chain: Chain::DEFAULT,
+            period_ms: 0,
+            last_us: None,
+        }
+    }
+
+    pub fn apply(&mut self, sample: &mut Sample) {
+        // Periods are whole milliseconds, so rounding takes out the jitter
+        let period_ms = self.last_us.map_or(0, |last_us| {
+            (sample.timestamp_us.saturating_sub(last_us) + 500) / 1000
+        }) as u32;
+        self.last_us = Some(sample.timestamp_us);
+        let chain = chain();
+        if chain != self.chain || period_ms != self.period_ms {
+            if chain != self.chain {
+                let enabled = chain.stages().filter(|s| s.enabled).count();
+                log!(Module::Sensor, info, "Filter chain: {} stages on", enabled);
+            }
+            self.chain = chain;
+            self.period_ms = period_ms;
+            self.filters = (period_ms > 0)
+                .then(|| Filters::new(&chain, 1000.0 / period_ms as f32))
+                .filter(|filters| !filters.is_empty());
+        }
+        if let Some(filters) = &mut self.filters {
+            sample.voltage_mv = filters.apply(sample.voltage_mv);
+        }
+    }
+}
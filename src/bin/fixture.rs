@@ -0,0 +1,145 @@
+//! Fixture mode, for production testing of magnet assemblies. `fixture
+//! run` on the console measures the field for the set time, judges it
+//! against the stored limits and prints the result as a JSON line for the
+//! test station. The LED shows green for a pass and red for a fail until
+//! the next test. The limits are set with `fixture` and kept in the
+//! `state` partition. With the `mux` feature, `fixture scan` tests a
+//! magnet array a position at a time and the LED shows the whole array's
+//! verdict.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+#[cfg(feature = "mux")]
+use hall_effect::fixture::Scan;
+use hall_effect::fixture::{Limits, Measurement, Verdict};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::reading::Reading;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+struct Test {
+    measurement: Measurement,
+    /// Readings sampled before the test started are dropped, as those
+    /// still on their way from the previous mux channel would be.
+    after_us: u64,
+    /// From the first reading, so the time spent waiting for it is not
+    /// counted.
+    end_us: Option<u64>,
+}
+
+static LIMITS: Mutex<Cell<Limits>> = Mutex::new(Cell::new(Limits::DEFAULT));
+static TEST: Mutex<RefCell<Option<Test>>> = Mutex::new(RefCell::new(None));
+static VERDICT: Mutex<Cell<Option<Verdict>>> = Mutex::new(Cell::new(None));
+static UNITS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+pub static DONE: Signal<CriticalSectionRawMutex, Measurement> = Signal::new();
+
+pub fn limits() -> Limits {
+    critical_section::with(|cs| LIMITS.borrow(cs).get())
+}
+
+/// Takes on the limits saved in flash, at boot.
+pub fn restore(limits: Limits) {
+    critical_section::with(|cs| LIMITS.borrow(cs).set(limits));
+}
+
+/// Changes the limits, from the next test, and saves them.
+pub async fn configure(limits: Limits) {
+    restore(limits);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_fixture_limits(&limits).await
+    {
+        log!(Module::Storage, warn, "Fixture limits not saved: {}", e);
+    }
+}
+
+/// The last test's verdict, for the LED.
+pub fn verdict() -> Option<Verdict> {
+    critical_section::with(|cs| VERDICT.borrow(cs).get())
+}
+
+/// Numbers the units tested since boot, from 1, for records not given
+/// one by the test station.
+pub fn next_unit() -> u32 {
+    critical_section::with(|cs| {
+        let units = UNITS.borrow(cs);
+        units.set(units.get() + 1);
+        units.get()
+    })
+}
+
+/// Starts a test; the measurement arrives on [`DONE`]. Replaces a test in
+/// progress.
+pub fn start() {
+    DONE.reset();
+    critical_section::with(|cs| {
+        VERDICT.borrow(cs).set(None);
+        TEST.replace(
+            cs,
+            Some(Test {
+                measurement: Measurement::new(),
+                after_us: clock::monotonic_us(),
+                end_us: None,
+            }),
+        )
+    });
+}
+
+/// Ends a test that has not finished, as when the readings stop, and
+/// judges what it has.
+pub fn abandon() -> Measurement {
+    let measurement = critical_section::with(|cs| TEST.take(cs))
+        .map_or(Measurement::new(), |test| test.measurement);
+    finish(&measurement);
+    measurement
+}
+
+/// Hands the test a reading, from the processing stage in fixture mode.
+pub fn push(reading: &Reading) {
+    let timestamp_us = reading.sample.timestamp_us;
+    let duration_us = limits().measure_ms as u64 * 1000;
+    let done = critical_section::with(|cs| {
+        let mut test = TEST.borrow_ref_mut(cs);
+        let t = test.as_mut()?;
+        if timestamp_us < t.after_us {
+            return None;
+        }
+        let end_us = *t.end_us.get_or_insert(timestamp_us + duration_us);
+        if timestamp_us < end_us {
+            t.measurement
+                .push(reading.valid.then_some(reading.field_mt));
+            return None;
+        }
+        test.take().map(|t| t.measurement)
+    });
+    if let Some(measurement) = done {
+        finish(&measurement);
+        DONE.signal(measurement);
+    }
+}
+
+/// Judges a scanned array as a whole, once all its positions are in.
+#[cfg(feature = "mux")]
+pub fn judge_scan(scan: &Scan) {
+    let verdict = scan.verdict(&limits());
+    critical_section::with(|cs| VERDICT.borrow(cs).set(Some(verdict)));
+    log!(Module::Sensor, info, "Fixture scan: {}", verdict.name());
+}
+
+/// On leaving fixture mode: a test in progress is dropped.
+pub fn stop() {
+    critical_section::with(|cs| {
+        TEST.take(cs);
+        VERDICT.borrow(cs).set(None);
+    });
+}
+
+fn finish(measurement: &Measurement) {
+    let verdict = measurement.verdict(&limits());
+    critical_section::with(|cs| VERDICT.borrow(cs).set(Some(verdict)));
+    log!(Module::Sensor, info, "Fixture test: {}", verdict.name());
+}
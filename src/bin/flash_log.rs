@@ -0,0 +1,261 @@
+//! Wear-levelled circular log in the `log` flash partition.
+//!
+//! Samples are decimated to one per second (unless they are reported by
+//! exception) and delta-compressed into blocks of up to a minute before
+//! they reach flash; events are always stored, after flushing any pending
+//! block so the log stays in order. When the partition fills up the oldest
+//! records are overwritten, so it always holds the most recent history
+//! (roughly a day with the default partition table).
+
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use embedded_storage_async::nor_flash::NorFlash;
+use esp_storage::FlashStorage;
+use hall_effect::datalog::{self, BLOCK_SIZE, Decimator, MAX_ENTRY_SIZE, MAX_RECORD_SIZE, Record};
+use hall_effect::delta::DeltaEncoder;
+use hall_effect::schema::{Config, Event, Sample};
+use heapless::Vec;
+use sequential_storage::cache::page_pointers::ArrayPagePointers;
+use sequential_storage::cache::page_states::ArrayPageStates;
+use sequential_storage::cache::{Cache, Uncached};
+use sequential_storage::queue::{QueueConfig, QueueStorage};
+
+use crate::storage::{self, FlashPartition};
+use crate::supply;
+
+// Time averaged into each stored sample
+const DECIMATION_MS: u32 = 1000;
+
+// Stored samples per block, bounding what a power cut can lose
+const MAX_BLOCK_SAMPLES: u32 = 60;
+
+// Enough for the 0x1ec000 partition in partitions.csv
+const MAX_PAGES: usize = 496;
+
+// Records fetched per read_page call
+pub const PAGE_RECORDS: usize = 8;
+
+/// Records with their sequence numbers, see [`FlashLog::read_page`].
+pub type Page = Vec<(u32, Vec<u8, MAX_RECORD_SIZE>), PAGE_RECORDS>;
+
+/// The log is shared between the flash sink and the console.
+pub static FLASH_LOG: Mutex<CriticalSectionRawMutex, Option<FlashLog>> = Mutex::new(None);
+
+/// A changed config, for the flash sink to take up.
+pub static RECONFIGURED: Signal<CriticalSectionRawMutex, Config> = Signal::new();
+
+type LogCache = Cache<ArrayPageStates<MAX_PAGES>, ArrayPagePointers<MAX_PAGES>, Uncached>;
+
+#[derive(Debug, Format)]
+pub enum Error {
+    NoPartition,
+    Storage(sequential_storage::Error<storage::Error>),
+    Encoding,
+    Erase(storage::Error),
+    /// Writes are suppressed while the supply is low.
+    LowSupply,
+}
+
+impl From<sequential_storage::Error<storage::Error>> for Error {
+    fn from(e: sequential_storage::Error<storage::Error>) -> Self {
+        Error::Storage(e)
+    }
+}
+
+pub struct FlashLog {
+    queue: QueueStorage<FlashPartition, LogCache>,
+    decimator: Decimator,
+    /// The sampler keeps the period it was started with until a restart.
+    sample_period_ms: u32,
+    nominal_period_ms: u32,
+    block: DeltaEncoder<BLOCK_SIZE>,
+    block_start_us: u64,
+    block_end_us: u64,
+    block_len: u32,
+    /// Number for the next entry, once the log has been read for it.
+    next_sequence: Option<u32>,
+}
+
+/// Time averaged into each stored sample. Samples reported by exception are
+/// few enough already, and each one matters, so they are all stored.
+pub fn decimation_ms(config: &Config) -> u32 {
+    if config.report_delta_mv > 0 {
+        0
+    } else {
+        DECIMATION_MS
+    }
+}
+
+impl FlashLog {
+    pub fn new(partition: Option<FlashPartition>, config: &Config) -> Result<Self, Error> {
+        let partition = partition.ok_or(Error::NoPartition)?;
+        let len = partition
+            .size()
+            .min((MAX_PAGES as u32) * FlashStorage::SECTOR_SIZE);
+
+        let queue_config = QueueConfig::try_new(0..len).map_err(|_| Error::NoPartition)?;
+        let cache = Cache::new(ArrayPageStates::new(), ArrayPagePointers::new(), Uncached);
+        Ok(Self {
+            queue: QueueStorage::new(partition, queue_config, cache),
+            decimator: Decimator::new(decimation_ms(config) as u64 * 1000),
+            sample_period_ms: config.sample_period_ms,
+            nominal_period_ms: decimation_ms(config).max(config.sample_period_ms),
+            block: DeltaEncoder::new(),
+            block_start_us: 0,
+            block_end_us: 0,
+            block_len: 0,
+            next_sequence: None,
+        })
+    }
+
+    /// Decimates at the rate `config` calls for from a fresh block, the
+    /// pending one being written out first.
+    pub async fn set_config(&mut self, config: &Config) -> Result<(), Error> {
+        let flushed = self.flush().await;
+        self.decimator = Decimator::new(decimation_ms(config) as u64 * 1000);
+        self.nominal_period_ms = decimation_ms(config).max(self.sample_period_ms);
+        flushed
+    }
+
+    /// Samples taken while the supply is low are dropped.
+    pub async fn record_sample(&mut self, sample: &Sample) -> Result<(), Error> {
+        if supply::is_low() {
+            return Ok(());
+        }
+        let Some(sample) = self.decimator.push(sample) else {
+            return Ok(());
+        };
+
+        // A block stores one spacing for all its samples, so a change in
+        // spacing (adaptive sampling, report-by-exception) starts a new one
+        if self.block_len >= 2 {
+            let gap_us = sample.timestamp_us - self.block_end_us;
+            let mean_us = (self.block_end_us - self.block_start_us) / (self.block_len as u64 - 1);
+            if gap_us.abs_diff(mean_us) > mean_us / 2 {
+                self.flush().await?;
+            }
+        }
+        if !self.block.push(&sample) {
+            self.flush().await?;
+            self.block.push(&sample);
+        }
+        if self.block_len == 0 {
+            self.block_start_us = sample.timestamp_us;
+        }
+        self.block_end_us = sample.timestamp_us;
+
+        self.block_len += 1;
+        if self.block_len >= MAX_BLOCK_SAMPLES {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn record_event(&mut self, time_ms: u64, event: Event) -> Result<(), Error> {
+        self.flush().await?;
+        self.push(&Record::Event { time_ms, event }).await
+    }
+
+    /// Writes out the pending sample block, if any.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        if supply::is_low() {
+            return Err(Error::LowSupply);
+        }
+
+        // Store the spacing actually achieved rather than the nominal one
+        let period_ms = match self.block_len {
+            0 | 1 => self.nominal_period_ms,
+            n => ((self.block_end_us - self.block_start_us) / (n as u64 - 1) / 1000) as u32,
+        };
+        let deltas = self.block.finish();
+        self.block_len = 0;
+        self.push(&Record::SampleBlock {
+            time_ms: self.block_start_us / 1000,
+            period_ms,
+            deltas: &deltas,
+        })
+        .await
+    }
+
+    /// Erases the whole partition a sector at a time, pausing between
+    /// sectors so other tasks and the watchdog keep running. Consumes the
+    /// log; meant to be followed by a reset.
+    pub async fn erase(self) -> Result<(), Error> {
+        let (mut partition, _) = self.queue.destroy();
+        for start in (0..partition.size()).step_by(FlashStorage::SECTOR_SIZE as usize) {
+            partition
+                .erase(start, start + FlashStorage::SECTOR_SIZE)
+                .await
+                .map_err(Error::Erase)?;
+            Timer::after(Duration::from_millis(1)).await;
+        }
+        Ok(())
+    }
+
+    /// Fills `page` with raw records numbered `from` onwards, oldest first.
+    /// Returns `false` once the end of the log has been reached. Numbers
+    /// stay with their records as the oldest are overwritten, so a reader
+    /// resumes after the last one it got without skipping or repeating any.
+    ///
+    /// The queue cannot resume a pass, so each call reads from the oldest
+    /// record; callers hold the log for one page at a time, never while they
+    /// write the records out or wait on anything else.
+    pub async fn read_page(&mut self, from: u32, page: &mut Page) -> Result<bool, Error> {
+        page.clear();
+        let mut buf = [0u8; MAX_ENTRY_SIZE];
+        let mut iter = self.queue.iter().await?;
+        let mut position = 0;
+        while let Some(entry) = iter.next(&mut buf).await? {
+            let (sequence, record) = datalog::split_entry(&entry);
+            // Left by older firmware, ahead of any numbered ones
+            let sequence = sequence.unwrap_or(position);
+            if sequence >= from {
+                // Cannot fail: records are never larger than the buffer
+                let _ = page.push((sequence, Vec::from_slice(record).unwrap_or_default()));
+                if page.is_full() {
+                    return Ok(true);
+                }
+            }
+            position += 1;
+        }
+        Ok(false)
+    }
+
+    /// The number for the next entry: one past the newest entry's, found by
+    /// reading the log through the first time it is needed.
+    async fn next_sequence(&mut self) -> Result<u32, Error> {
+        if let Some(sequence) = self.next_sequence {
+            return Ok(sequence);
+        }
+        let mut buf = [0u8; MAX_ENTRY_SIZE];
+        let mut iter = self.queue.iter().await?;
+        let mut next = 0;
+        while let Some(entry) = iter.next(&mut buf).await? {
+            next = match datalog::split_entry(&entry).0 {
+                Some(sequence) => sequence.wrapping_add(1),
+                None => next + 1,
+            };
+        }
+        self.next_sequence = Some(next);
+        Ok(next)
+    }
+
+    async fn push(&mut self, record: &Record<'_>) -> Result<(), Error> {
+        if supply::is_low() {
+            return Err(Error::LowSupply);
+        }
+        let sequence = self.next_sequence().await?;
+        let mut buf = [0u8; MAX_ENTRY_SIZE];
+        let bytes =
+            datalog::encode_entry(sequence, record, &mut buf).map_err(|_| Error::Encoding)?;
+        self.queue.push(bytes, true).await?;
+        self.next_sequence = Some(sequence.wrapping_add(1));
+        Ok(())
+    }
+}
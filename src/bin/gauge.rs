@@ -0,0 +1,94 @@
+//! Analog dial: with the `x27-gauge` feature, an X27.168-style gauge
+//! stepper on GPIO10 to GPIO13 (coil pins 1 to 4) turns a needle with the
+//! field, or with the speed in tachometer mode. It homes against its stop
+//! at boot. The dial's scale is set with `gauge` on the console and kept
+//! in the `state` partition.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_time::{Duration, Timer};
+use esp_hal::gpio::Output;
+use hall_effect::mode::Mode;
+use hall_effect::verbosity::Module;
+use hall_effect::x27::{self, Motion, Scale, X27};
+
+use crate::mode;
+use crate::reading::LATEST;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+pub type Gauge = X27<Output<'static>>;
+
+// How often a needle at rest looks for a new target
+const IDLE: Duration = Duration::from_millis(20);
+// Past the sweep while homing, in case the needle was left beyond it
+const HOMING_MARGIN: u32 = 30;
+
+static SCALE: Mutex<Cell<Scale>> = Mutex::new(Cell::new(Scale::DEFAULT));
+/// In steps from the stop, once homed.
+static POSITION: Mutex<Cell<Option<i32>>> = Mutex::new(Cell::new(None));
+
+pub fn scale() -> Scale {
+    critical_section::with(|cs| SCALE.borrow(cs).get())
+}
+
+/// Takes on the scale saved in flash, at boot.
+pub fn restore(scale: Scale) {
+    critical_section::with(|cs| SCALE.borrow(cs).set(scale));
+}
+
+/// Changes the scale, from the next step, and saves it.
+pub async fn set_scale(scale: Scale) {
+    restore(scale);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_gauge_scale(&scale).await
+    {
+        log!(Module::Storage, warn, "Gauge scale not saved: {}", e);
+    }
+}
+
+/// The needle's angle from the stop.
+pub fn angle_deg() -> Option<f32> {
+    critical_section::with(|cs| POSITION.borrow(cs).get())
+        .map(|steps| steps as f32 * x27::SWEEP_DEG / x27::STEPS as f32)
+}
+
+/// Where the needle should be, if there is anything to show.
+fn target() -> Option<i32> {
+    let scale = scale();
+    if mode::current() == Mode::Tachometer {
+        return Some(scale.rpm_steps(mode::rpm()));
+    }
+    LATEST
+        .try_get()
+        .filter(|reading| reading.valid)
+        .map(|reading| scale.field_steps(reading.field_mt))
+}
+
+#[embassy_executor::task]
+pub async fn gauge_task(mut gauge: Gauge) {
+    // Its pins are plain outputs, which cannot fail
+    for _ in 0..x27::STEPS + HOMING_MARGIN {
+        let _ = gauge.step(false);
+        Timer::after_micros(x27::HOMING_STEP_US as u64).await;
+    }
+    critical_section::with(|cs| POSITION.borrow(cs).set(Some(0)));
+    log!(Module::Display, info, "Gauge homed");
+
+    let mut motion = Motion::new(0, x27::MAX_SPEED, x27::ACCELERATION);
+    loop {
+        if let Some(target) = target() {
+            motion.set_target(target);
+        }
+        match motion.advance() {
+            Some((forward, wait_us)) => {
+                Timer::after_micros(wait_us as u64).await;
+                let _ = gauge.step(forward);
+                let position = motion.position();
+                critical_section::with(|cs| POSITION.borrow(cs).set(Some(position)));
+            }
+            None => Timer::after(IDLE).await,
+        }
+    }
+}
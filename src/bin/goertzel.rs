@@ -0,0 +1,128 @@
+//! The Goertzel detector: fed every processed sample once started with
+//! `tone <Hz>`, and run over the last burst on request.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use hall_effect::goertzel::{Goertzel, MAX_POINTS, Tone};
+use hall_effect::schema::Sample;
+use hall_effect::verbosity::Module;
+
+use crate::burst;
+use crate::verbosity::log;
+
+/// Blocks whose measured rate is further than this from the nominal one,
+/// in percent, are dropped: their tone would be measured at the wrong
+/// frequency.
+const RATE_TOLERANCE_PERCENT: f32 = 2.0;
+
+struct Detector {
+    goertzel: Goertzel,
+    rate_hz: f32,
+    first_us: u64,
+}
+
+static DETECTOR: Mutex<RefCell<Option<Detector>>> = Mutex::new(RefCell::new(None));
+static LATEST: Mutex<Cell<Option<Tone>>> = Mutex::new(Cell::new(None));
+
+/// Starts measuring `frequency_hz` in blocks of `points` samples taken at
+/// `rate_hz`. Returns `false`, leaving any running detector alone, if the
+/// detector cannot measure it.
+pub fn start(frequency_hz: u32, points: u32, rate_hz: f32) -> bool {
+    let Some(goertzel) = Goertzel::new(frequency_hz as f32, rate_hz, points) else {
+        return false;
+    };
+    critical_section::with(|cs| {
+        DETECTOR.borrow(cs).replace(Some(Detector {
+            goertzel,
+            rate_hz,
+            first_us: 0,
+        }));
+        LATEST.borrow(cs).set(None);
+    });
+    log!(
+        Module::Sample,
+        info,
+        "Tone: {}Hz over {} samples",
+        frequency_hz,
+        points
+    );
+    true
+}
+
+pub fn stop() {
+    critical_section::with(|cs| DETECTOR.borrow(cs).replace(None));
+}
+
+/// Whether a detector is running, which keeps sampling at the full rate.
+pub fn running() -> bool {
+    critical_section::with(|cs| DETECTOR.borrow_ref(cs).is_some())
+}
+
+/// Hands the detector a sample, from the processing stage.
+pub fn update(sample: &Sample) {
+    let tone = critical_section::with(|cs| {
+        let mut detector = DETECTOR.borrow_ref_mut(cs);
+        let detector = detector.as_mut()?;
+        if detector.goertzel.at_start() {
+            detector.first_us = sample.timestamp_us;
+        }
+        let tone = detector.goertzel.push(sample.voltage_mv as f32)?;
+
+        // A slow block, say from a stall in processing, would put the tone
+        // at another frequency
+        let points = detector.goertzel.points();
+        let elapsed_us = sample.timestamp_us.saturating_sub(detector.first_us).max(1);
+        let rate_hz = (points - 1) as f32 * 1e6 / elapsed_us as f32;
+        let error_percent = (rate_hz - detector.rate_hz) / detector.rate_hz * 100.0;
+        if error_percent.abs() > RATE_TOLERANCE_PERCENT {
+            return Some(Err(rate_hz));
+        }
+        LATEST.borrow(cs).set(Some(tone));
+        Some(Ok(tone))
+    });
+    match tone {
+        Some(Ok(tone)) => log!(
+            Module::Sample,
+            info,
+            "Tone: {}mV at {} degrees",
+            tone.amplitude_mv,
+            tone.phase_deg
+        ),
+        Some(Err(rate_hz)) => log!(
+            Module::Sample,
+            warn,
+            "Tone block dropped, sampled at {}Hz",
+            rate_hz
+        ),
+        None => {}
+    }
+}
+
+/// The tone of the last block measured since [`start`].
+pub fn latest() -> Option<Tone> {
+    critical_section::with(|cs| LATEST.borrow(cs).get())
+}
+
+/// Measures `frequency_hz` over the last burst, up to [`MAX_POINTS`] of its
+/// readings. `None` if there is no burst or the frequency is above half its
+/// rate.
+pub fn of_burst(frequency_hz: u32) -> Option<Tone> {
+    let summary = burst::summary()?;
+    let points = summary.samples.min(MAX_POINTS);
+    let mut goertzel = Goertzel::new(frequency_hz as f32, summary.rate_hz as f32, points)?;
+    let mut chunk = [0u16; 64];
+    let mut index = 0;
+    loop {
+        let n = burst::read(index, 1, &mut chunk);
+        if n == 0 {
+            return None;
+        }
+        for &raw in &chunk[..n] {
+            if let Some(tone) = goertzel.push(hall_effect::burst::voltage_mv(raw) as f32) {
+                return Some(tone);
+            }
+        }
+        index += n;
+    }
+}
@@ -0,0 +1,170 @@
+//! Grade mode: magnets graded for quality control by their peak field in
+//! the fixture, over a set number of placements each. Each magnet's
+//! result, with the temperature when the DS3231 is fitted, is logged and
+//! kept for `grade` on the console. The settings are kept in the `state`
+//! partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use hall_effect::grading::{Placement, Run, Settings};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+/// A magnet's result.
+#[derive(Clone, Copy)]
+pub struct Graded {
+    /// Numbered from 1 since boot.
+    pub magnet: u32,
+    pub run: Run,
+    /// `None` for a reject.
+    pub grade: Option<char>,
+    pub temp_c: Option<f32>,
+}
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static RUN: Mutex<Cell<Run>> = Mutex::new(Cell::new(Run::new()));
+/// A run complete and waiting for the temperature.
+static COMPLETE: Mutex<Cell<Option<Run>>> = Mutex::new(Cell::new(None));
+static LAST: Mutex<RefCell<Option<Graded>>> = Mutex::new(RefCell::new(None));
+static MAGNETS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next placement, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_grade_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Grade settings not saved: {}", e);
+    }
+}
+
+/// The placements of the magnet under way.
+pub fn run() -> Run {
+    critical_section::with(|cs| RUN.borrow(cs).get())
+}
+
+/// The last magnet graded, if any.
+pub fn last() -> Option<Graded> {
+    critical_section::with(|cs| *LAST.borrow_ref(cs))
+}
+
+/// Drops the placements of the magnet under way.
+pub fn reset() {
+    critical_section::with(|cs| RUN.borrow(cs).set(Run::new()));
+}
+
+/// On entering grade mode.
+pub fn start() {
+    reset();
+    let settings = settings();
+    log!(
+        Module::Sensor,
+        info,
+        "Grading over {} placements at {}mm",
+        settings.shots,
+        settings.distance_mm
+    );
+}
+
+/// Grades a magnet whose placements are all in, from the processing
+/// stage, reading the temperature to go with it.
+pub async fn finish() {
+    let Some(run) = critical_section::with(|cs| COMPLETE.borrow(cs).take()) else {
+        return;
+    };
+    let temp_c = clock::temperature_c().await;
+    let grade = settings().grades.grade(run.mean_mt());
+    let magnet = critical_section::with(|cs| {
+        let magnets = MAGNETS.borrow(cs);
+        magnets.set(magnets.get() + 1);
+        magnets.get()
+    });
+    critical_section::with(|cs| {
+        LAST.replace(
+            cs,
+            Some(Graded {
+                magnet,
+                run,
+                grade,
+                temp_c,
+            }),
+        )
+    });
+    match grade {
+        Some(grade) => log!(
+            Module::Sensor,
+            info,
+            "Magnet {}: grade {}, {}mT mean peak, {}% repeatability",
+            magnet,
+            grade,
+            run.mean_mt(),
+            run.repeatability_pct()
+        ),
+        None => log!(
+            Module::Sensor,
+            info,
+            "Magnet {}: reject, {}mT mean peak",
+            magnet,
+            run.mean_mt()
+        ),
+    }
+}
+
+/// The processing stage's half, for grade mode.
+pub struct Grader {
+    placement: Placement,
+}
+
+impl Grader {
+    pub const fn new() -> Self {
+        Self {
+            placement: Placement::new(),
+        }
+    }
+
+    /// Takes a valid reading.
+    pub fn push(&mut self, field_mt: f32) {
+        let Some(peak_mt) = self.placement.update(field_mt) else {
+            return;
+        };
+        let shots = settings().shots;
+        let count = critical_section::with(|cs| {
+            let cell = RUN.borrow(cs);
+            let mut run = cell.get();
+            run.push(peak_mt);
+            if run.count() >= shots {
+                COMPLETE.borrow(cs).set(Some(run));
+                cell.set(Run::new());
+            } else {
+                cell.set(run);
+            }
+            run.count()
+        });
+        log!(
+            Module::Sensor,
+            info,
+            "Placement {} of {}: {}mT peak",
+            count,
+            shots,
+            peak_mt
+        );
+    }
+
+    /// On leaving grade mode: a placement under way is dropped.
+    pub fn stop(&mut self) {
+        self.placement = Placement::new();
+    }
+}
@@ -0,0 +1,80 @@
+//! Haptic feedback: with the `haptic` feature, a DRV2605 on I2C0 drives an
+//! ERM vibration motor, buzzing on threshold crossings or on each pole
+//! found, as set with `haptic` on the console. Muting alarms silences the
+//! threshold buzz too.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_sync::pubsub::WaitResult;
+use esp_hal::Blocking;
+use esp_hal::i2c::master::I2c;
+use hall_effect::drv2605::{Actuator, Drv2605};
+use hall_effect::haptic::{PoleDetector, THRESHOLD_EFFECTS, Trigger};
+use hall_effect::verbosity::Module;
+
+use crate::alarm;
+use crate::bus::{BusEvent, EVENTS};
+use crate::reading::LATEST;
+use crate::verbosity::log;
+
+pub type Haptic = Drv2605<I2c<'static, Blocking>>;
+
+static TRIGGER: Mutex<Cell<Trigger>> = Mutex::new(Cell::new(Trigger::Pole));
+
+pub fn trigger() -> Trigger {
+    critical_section::with(|cs| TRIGGER.borrow(cs).get())
+}
+
+pub fn set_trigger(trigger: Trigger) {
+    critical_section::with(|cs| TRIGGER.borrow(cs).set(trigger));
+}
+
+#[embassy_executor::task]
+pub async fn haptic_task(mut haptic: Haptic) {
+    match haptic.init(Actuator::Erm) {
+        Ok(id) => log!(Module::Sensor, info, "DRV2605 ready, device ID {}", id),
+        Err(e) => {
+            log!(Module::Sensor, warn, "DRV2605 not responding: {}", e);
+            return;
+        }
+    }
+    // Only fails if the Watch has no receiver slots left
+    let Some(mut latest) = LATEST.receiver() else {
+        log!(Module::Sensor, warn, "Haptics have no reading receiver");
+        return;
+    };
+    let Ok(mut events) = EVENTS.subscriber() else {
+        log!(Module::Sensor, warn, "Haptics have no event subscriber");
+        return;
+    };
+    let mut poles = PoleDetector::new();
+
+    loop {
+        let reading = latest.changed().await;
+        let mut crossed = false;
+        while let Some(event) = events.try_next_message() {
+            crossed |= matches!(
+                event,
+                WaitResult::Message(BusEvent::ThresholdCrossed { rising: true, .. })
+            );
+        }
+        // Tracked whatever the trigger, so switching to it does not buzz
+        // for a magnet already there
+        let pole = reading
+            .valid
+            .then(|| poles.update(reading.field_mt))
+            .flatten();
+        let effects = match trigger() {
+            Trigger::Threshold if crossed && !alarm::is_muted() => THRESHOLD_EFFECTS,
+            Trigger::Pole => match pole {
+                Some(pole) => pole.effects(),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if let Err(e) = haptic.play(effects) {
+            log!(Module::Sensor, warn, "Haptic effect failed: {}", e);
+        }
+    }
+}
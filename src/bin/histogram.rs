@@ -0,0 +1,70 @@
+//! The rolling histogram, fed every processed sample and shown on the
+//! console with `hist`.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hall_effect::histogram::{Histogram, MAX_BINS};
+use hall_effect::schema::Sample;
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+
+/// Longest window, 20s at the default rate.
+const MAX_WINDOW: usize = 2048;
+const DEFAULT_BIN_WIDTH_MV: u32 = 100;
+const DEFAULT_WINDOW: usize = 1000;
+
+static HISTOGRAM: Mutex<RefCell<Histogram<MAX_WINDOW>>> = Mutex::new(RefCell::new(Histogram::new(
+    DEFAULT_BIN_WIDTH_MV,
+    DEFAULT_WINDOW,
+)));
+
+/// The histogram as of one moment, for printing outside the lock.
+pub struct Snapshot {
+    pub counts: [u16; MAX_BINS],
+    pub bins: usize,
+    pub bin_width_mv: u32,
+    pub window: usize,
+    pub len: usize,
+    /// Of the samples from `level_mv` up, as passed to [`snapshot`].
+    pub percent_from: u32,
+}
+
+/// Hands the histogram a sample, from the processing stage.
+pub fn update(sample: &Sample) {
+    critical_section::with(|cs| HISTOGRAM.borrow_ref_mut(cs).add(sample.voltage_mv));
+}
+
+/// Starts over with new settings; `window` is kept if not given.
+pub fn configure(bin_width_mv: u32, window: Option<u32>) {
+    let (bin_width_mv, window) = critical_section::with(|cs| {
+        let mut histogram = HISTOGRAM.borrow_ref_mut(cs);
+        let window = window.map_or(histogram.window(), |w| w as usize);
+        histogram.configure(bin_width_mv, window);
+        (histogram.bin_width_mv(), histogram.window())
+    });
+    log!(
+        Module::Sensor,
+        info,
+        "Histogram: {}mV bins over {} samples",
+        bin_width_mv,
+        window
+    );
+}
+
+pub fn snapshot(level_mv: u32) -> Snapshot {
+    critical_section::with(|cs| {
+        let histogram = HISTOGRAM.borrow_ref(cs);
+        let mut counts = [0; MAX_BINS];
+        counts[..histogram.bins()].copy_from_slice(histogram.counts());
+        Snapshot {
+            counts,
+            bins: histogram.bins(),
+            bin_width_mv: histogram.bin_width_mv(),
+            window: histogram.window(),
+            len: histogram.len(),
+            percent_from: histogram.percent_from(level_mv),
+        }
+    })
+}
@@ -0,0 +1,109 @@
+//! NEC infrared remote control, received on an RMT RX channel from a
+//! demodulating receiver (TSOP38238 or similar, active low).
+//!
+//! Keys are those of the common 21-key "Car MP3" remote: CH cycles the mode,
+//! VOL+/VOL- change the LED brightness (repeating while held) and EQ toggles
+//! the alarm mute.
+
+use embassy_time::{Duration, Timer};
+use esp_hal::Blocking;
+use esp_hal::gpio::Level;
+use esp_hal::rmt::{Channel, PulseCode, Rx, RxChannelConfig};
+use hall_effect::color::RGB8;
+use hall_effect::encoder::BRIGHTNESS_STEP;
+use hall_effect::nec::{self, Frame};
+use hall_effect::verbosity::Module;
+
+use crate::led;
+use crate::verbosity::log;
+use crate::{alarm, mode};
+
+pub type IrChannel = Channel<'static, Blocking, Rx>;
+
+// Address 0x00 and its inverse
+const ADDRESS: u16 = 0xff00;
+const KEY_MODE: u8 = 0x46;
+const KEY_BRIGHTER: u8 = 0x15;
+const KEY_DIMMER: u8 = 0x07;
+const KEY_MUTE: u8 = 0x09;
+
+// A frame is 34 codes: leader, 32 bits and the stop mark
+const BUFFER_CODES: usize = 48;
+
+// How often a reception in progress is checked on
+const POLL: Duration = Duration::from_millis(10);
+
+/// 1us ticks from the 80MHz RMT clock. The frame ends once the line has been
+/// idle for longer than any NEC space.
+pub fn ir_rx_config() -> RxChannelConfig {
+    RxChannelConfig::default()
+        .with_clk_divider(80)
+        .with_carrier_modulation(false)
+        .with_filter_threshold(100)
+        .with_idle_threshold(12_000)
+}
+
+#[embassy_executor::task]
+pub async fn ir_task(mut channel: IrChannel) {
+    let mut buffer = [PulseCode::default(); BUFFER_CODES];
+    let mut held = None;
+
+    loop {
+        let mut transaction = match channel.receive(&mut buffer) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                log!(Module::Sensor, warn, "IR receiver disabled: {}", e);
+                return;
+            }
+        };
+        while !transaction.poll() {
+            Timer::after(POLL).await;
+        }
+        let count;
+        (count, channel) = match transaction.wait() {
+            Ok(received) => received,
+            Err((e, channel)) => {
+                log!(Module::Sensor, info, "IR frame lost: {}", e);
+                (0, channel)
+            }
+        };
+
+        // The receiver idles high, so each code is a mark then a space
+        let pulses = buffer[..count]
+            .iter()
+            .filter(|code| code.level1() == Level::Low)
+            .map(|code| (code.length1() as u32, code.length2() as u32));
+        match nec::decode(pulses) {
+            Some(Frame::Key { address, command }) if address == ADDRESS => {
+                held = Some(command);
+                key(command, false);
+            }
+            Some(Frame::Key { .. }) => held = None,
+            Some(Frame::Repeat) => {
+                if let Some(command) = held {
+                    key(command, true);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn key(command: u8, repeat: bool) {
+    match command {
+        KEY_MODE if !repeat => mode::request(mode::current().next()),
+        KEY_BRIGHTER => set_brightness(led::brightness().saturating_add(BRIGHTNESS_STEP)),
+        KEY_DIMMER => set_brightness(led::brightness().saturating_sub(BRIGHTNESS_STEP)),
+        KEY_MUTE if !repeat => {
+            let muted = !alarm::is_muted();
+            alarm::set_muted(muted);
+            log!(Module::Sensor, info, "Alarm muted: {}", muted);
+        }
+        _ => {}
+    }
+}
+
+fn set_brightness(brightness: u8) {
+    led::set_brightness(brightness);
+    led::preview(RGB8::new(255, 255, 255));
+}
@@ -0,0 +1,35 @@
+//! Latency mode: the LED task times each reading it shows from the
+//! sample's timestamp to the end of the RMT transfer, covering the
+//! sampler, processing and the LED itself. The distribution is logged
+//! with the other timing reports and printed by `latency` on the console.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hall_effect::latency::{Distribution, Summary};
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+
+static DISTRIBUTION: Mutex<RefCell<Distribution>> = Mutex::new(RefCell::new(Distribution::new()));
+
+/// On entering latency mode: starts a new distribution.
+pub fn start() {
+    reset();
+    log!(Module::Timing, info, "Measuring latency from sample to LED");
+}
+
+pub fn reset() {
+    critical_section::with(|cs| DISTRIBUTION.replace(cs, Distribution::new()));
+}
+
+/// From the LED task, once a reading is on the LED.
+pub fn record(latency_us: u64) {
+    let latency_us = latency_us.min(u32::MAX as u64) as u32;
+    critical_section::with(|cs| DISTRIBUTION.borrow_ref_mut(cs).record(latency_us));
+}
+
+/// `None` until a reading has been shown.
+pub fn summary() -> Option<Summary> {
+    critical_section::with(|cs| DISTRIBUTION.borrow_ref(cs).summary())
+}
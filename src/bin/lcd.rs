@@ -0,0 +1,111 @@
+//! 16x2 HD44780 character LCD on a PCF8574 backpack, sharing I2C1 with the
+//! OLED's pins: the reading in the units set and the field's polarity on
+//! the top row, the alarm state below.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
+use esp_hal::Blocking;
+use esp_hal::delay::Delay;
+use esp_hal::i2c::master::I2c;
+use hall_effect::backoff::Backoff;
+use hall_effect::hd44780::{COLUMNS, Hd44780};
+use hall_effect::schema::Config;
+use hall_effect::threshold::ThresholdDetector;
+use hall_effect::verbosity::Module;
+use heapless::String;
+
+use crate::alarm;
+use crate::reading::LATEST;
+use crate::units;
+use crate::verbosity::log;
+
+pub type Lcd = Hd44780<I2c<'static, Blocking>>;
+
+const REFRESH: Duration = Duration::from_millis(250);
+
+// Weaker fields are shown without a polarity
+const POLARITY_MIN_MT: f32 = 0.5;
+
+// Consecutive failures before the display is given up on
+const LCD_MAX_FAILURES: u32 = 8;
+
+type Line = String<COLUMNS>;
+
+#[embassy_executor::task]
+pub async fn lcd_task(mut lcd: Lcd, config: Config) {
+    // The controller needs 40ms after power-up before it takes commands
+    Timer::after_millis(50).await;
+    if let Err(e) = lcd.init(&mut Delay::new()) {
+        log!(Module::Display, warn, "LCD not responding: {}", e);
+        return;
+    }
+
+    // Follows the readings with the same hysteresis as the alarm itself
+    let mut threshold = ThresholdDetector::new();
+    let mut shown: [Line; 2] = Default::default();
+    let mut backoff = Backoff::new(LCD_MAX_FAILURES);
+
+    loop {
+        Timer::after(REFRESH).await;
+        let Some(reading) = LATEST.try_get() else {
+            continue;
+        };
+
+        let mut lines: [Line; 2] = Default::default();
+        if reading.valid {
+            threshold.update(reading.sample.voltage_mv, &config);
+            let field_mt = reading.field_mt;
+            let polarity = if field_mt <= -POLARITY_MIN_MT {
+                "N"
+            } else if field_mt >= POLARITY_MIN_MT {
+                "S"
+            } else {
+                ""
+            };
+            let shown = units::of(&reading);
+            let _ = write!(
+                lines[0],
+                "{:>7} {:<7}{}",
+                shown.number(),
+                shown.unit.symbol(),
+                polarity
+            );
+            let _ = lines[1].push_str(match threshold.is_above() {
+                Some(true) if alarm::is_muted() => "ALARM (muted)",
+                Some(true) => "ALARM",
+                _ => "Alarm off",
+            });
+        } else {
+            let _ = write!(lines[0], "{:>7} {}", "--", units::current().symbol());
+            let _ = lines[1].push_str("Sensor fault");
+        }
+
+        // Only changed rows are rewritten, so the LCD does not flicker
+        if lines == shown || !backoff.ready() {
+            continue;
+        }
+        let result = lines
+            .iter()
+            .zip(&shown)
+            .enumerate()
+            .filter(|(_, (line, old))| line != old)
+            .try_for_each(|(row, (line, _))| lcd.write_line(row, line));
+        match result {
+            Ok(()) => {
+                backoff.success();
+                shown = lines;
+            }
+            Err(e) if backoff.failure() => log!(Module::Display, warn, "LCD write failed: {}", e),
+            Err(e) => {
+                log!(
+                    Module::Display,
+                    warn,
+                    "LCD write failed, display disabled: {}",
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
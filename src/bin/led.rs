@@ -0,0 +1,318 @@
+//! WS2812 status LED driven through the RMT peripheral, and the task that
+//! shows the latest reading as a colour.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_sync::pubsub::WaitResult;
+use embassy_time::{Duration, Timer, with_timeout};
+use esp_hal::Blocking;
+use esp_hal::gpio::Level;
+use esp_hal::rmt::{Channel, PulseCode, Tx, TxChannelConfig};
+use hall_effect::backoff::Backoff;
+use hall_effect::color::{self, Gradient, RGB8, WS2812_BITS};
+use hall_effect::mode::Mode;
+use hall_effect::palette::Palette;
+use hall_effect::schema::Config;
+#[cfg(feature = "battery")]
+use hall_effect::schema::PowerStage;
+use hall_effect::timing::Window;
+use hall_effect::verbosity::Module;
+
+#[cfg(feature = "ambient-light")]
+use crate::ambient;
+#[cfg(feature = "battery")]
+use crate::battery;
+use crate::bus::{BusEvent, EVENTS};
+use crate::reading::{LATEST, Reading};
+use crate::verbosity::log;
+use crate::{Error, clock, diag, fixture, latency, mode, night, palette, watchdog};
+
+// Consecutive failures before the LED is disabled
+const LED_MAX_FAILURES: u32 = 8;
+
+// Flash acknowledging a button press
+const FLASH_COLOR: RGB8 = RGB8 {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+const FLASH_TIME: Duration = Duration::from_millis(100);
+
+// Shown while calibrating; no palette shows green alone
+const CALIBRATE_COLOR: RGB8 = RGB8 { r: 0, g: 255, b: 0 };
+
+// Fixture mode's verdicts, and nothing while a test is under way
+const PASS_COLOR: RGB8 = RGB8 { r: 0, g: 255, b: 0 };
+const FAIL_COLOR: RGB8 = RGB8 { r: 255, g: 0, b: 0 };
+
+// Longest wait for a reading before checking in with the watchdog
+const IDLE_CHECK_IN: Duration = Duration::from_secs(1);
+
+// How long an edited value or a profile's colour stays on the LED
+const PREVIEW_US: u64 = 1_500_000;
+
+// A quarter of whatever the brightness is set to, while the battery is low
+#[cfg(feature = "battery")]
+const DIMMED_BRIGHTNESS: u8 = 64;
+
+static BRIGHTNESS: Mutex<Cell<u8>> = Mutex::new(Cell::new(u8::MAX));
+// Colour shown in place of the reading, and until when
+static PREVIEW: Mutex<Cell<Option<(RGB8, u64)>>> = Mutex::new(Cell::new(None));
+
+pub type LedChannel = Channel<'static, Blocking, Tx>;
+
+pub fn brightness() -> u8 {
+    critical_section::with(|cs| BRIGHTNESS.borrow(cs).get())
+}
+
+/// Scales every colour the LED task shows; fault blinks stay at full
+/// brightness.
+#[cfg_attr(
+    not(any(feature = "encoder", feature = "ir-remote")),
+    expect(dead_code)
+)]
+pub fn set_brightness(brightness: u8) {
+    critical_section::with(|cs| BRIGHTNESS.borrow(cs).set(brightness));
+}
+
+/// The brightness colours are shown at now: as set, scaled for the
+/// ambient light, and less in night mode.
+pub fn shown_brightness() -> u8 {
+    #[cfg(feature = "ambient-light")]
+    let brightness = ambient::scale(brightness());
+    #[cfg(not(feature = "ambient-light"))]
+    let brightness = brightness();
+    night::dim(brightness)
+}
+
+/// Shows `color` in place of the reading for a moment, e.g. a value being
+/// edited.
+pub fn preview(color: RGB8) {
+    let until_us = clock::monotonic_us() + PREVIEW_US;
+    critical_section::with(|cs| PREVIEW.borrow(cs).set(Some((color, until_us))));
+}
+
+fn previewing(now_us: u64) -> Option<RGB8> {
+    critical_section::with(|cs| PREVIEW.borrow(cs).get())
+        .and_then(|(color, until_us)| (now_us < until_us).then_some(color))
+}
+
+// Buffer size for one RGB LED (24 pulses + 1 delimiter)
+pub const BUFFER_SIZE: usize = WS2812_BITS + 1;
+
+// Shown after a panic; the red-blue gradient never has a green component,
+// and the orange-blue's orange is yellower
+pub const FAULT_COLOR: RGB8 = RGB8 {
+    r: 255,
+    g: 96,
+    b: 0,
+};
+
+pub fn led_tx_config() -> TxChannelConfig {
+    TxChannelConfig::default()
+        .with_clk_divider(1)
+        .with_idle_output_level(Level::Low)
+        .with_carrier_modulation(false)
+        .with_idle_output(true)
+}
+
+pub fn led_pulses_for_clock(src_clock_mhz: u32) -> (PulseCode, PulseCode) {
+    let pulse = |bit| {
+        let (high, low) = color::pulse_ticks(bit, src_clock_mhz);
+        PulseCode::new(Level::High.into(), high, Level::Low.into(), low)
+    };
+    (pulse(false), pulse(true))
+}
+
+pub fn ws2812_encode(
+    color: RGB8,
+    pulses: (PulseCode, PulseCode),
+    rmt_buffer: &mut [PulseCode; BUFFER_SIZE],
+) {
+    for (code, is_set) in rmt_buffer.iter_mut().zip(color::ws2812_bits(color)) {
+        *code = if is_set { pulses.1 } else { pulses.0 };
+    }
+    // Delimiter
+    rmt_buffer[WS2812_BITS] = PulseCode::new(Level::Low.into(), 0, Level::Low.into(), 0);
+}
+
+/// Colour shown for a reading. The LED is switched off while the supply is
+/// low to shed its load.
+pub fn color_for(reading: &Reading, gradient: &Gradient) -> RGB8 {
+    if reading.supply_low {
+        RGB8::new(0, 0, 0)
+    } else if !reading.valid {
+        FAULT_COLOR
+    } else {
+        gradient.color(reading.sample.voltage_mv)
+    }
+}
+
+/// Blinks `code` times in the fault colour, then pauses. Best effort: the LED
+/// is dropped if it stops working.
+pub async fn blink_fault_code(
+    led: &mut Option<LedChannel>,
+    pulses: (PulseCode, PulseCode),
+    code: u8,
+) {
+    for color in (0..code).flat_map(|_| [FAULT_COLOR, RGB8::new(0, 0, 0)]) {
+        show(led, pulses, color);
+        Timer::after(Duration::from_millis(250)).await;
+    }
+    Timer::after(Duration::from_millis(1500)).await;
+}
+
+/// Shows `color`, before the LED task has the LED. Best effort, as for
+/// [`blink_fault_code`].
+pub fn show(led: &mut Option<LedChannel>, pulses: (PulseCode, PulseCode), color: RGB8) {
+    let mut rmt_buffer = [PulseCode::default(); BUFFER_SIZE];
+    if let Some(channel) = led.take() {
+        ws2812_encode(color, pulses, &mut rmt_buffer);
+        *led = ws2812_transmit(channel, &rmt_buffer).map_or_else(|(_, channel)| channel, Some);
+    }
+}
+
+/// Sends one frame. The channel is lost if the transmission cannot start.
+fn ws2812_transmit(
+    channel: LedChannel,
+    rmt_buffer: &[PulseCode; BUFFER_SIZE],
+) -> Result<LedChannel, (Error, Option<LedChannel>)> {
+    let transaction = channel.transmit(rmt_buffer).map_err(|e| (e.into(), None))?;
+    transaction
+        .wait()
+        .map_err(|(e, channel)| (e.into(), Some(channel)))
+}
+
+#[embassy_executor::task]
+pub async fn led_task(mut led: Option<LedChannel>, pulses: (PulseCode, PulseCode), config: Config) {
+    // Only fails if the Watch has no receiver slots left
+    let Some(mut latest) = LATEST.receiver() else {
+        log!(Module::Led, warn, "LED has no reading receiver");
+        return;
+    };
+    let Ok(mut events) = EVENTS.subscriber() else {
+        log!(Module::Led, warn, "LED has no event subscriber");
+        return;
+    };
+    let mut gradient = Gradient::new(&config, palette::current());
+    let mut backoff = Backoff::new(LED_MAX_FAILURES);
+    let mut rmt_buffer = [PulseCode::default(); BUFFER_SIZE];
+    let mut write_time = Window::new();
+    let mut report_us = clock::monotonic_us();
+    // Presses not yet flashed, and whether the last frame was a flash
+    let mut presses: u8 = 0;
+    let mut flashed = false;
+
+    loop {
+        watchdog::feed(watchdog::Task::Led);
+        // A blinking colour is redrawn between readings, which may be slow
+        let blinking = gradient.palette() == Palette::Blink;
+        let check_in = if blinking {
+            palette::until_blink()
+        } else {
+            IDLE_CHECK_IN
+        };
+        // Intermediate readings are skipped if the LED falls behind
+        let (reading, fresh) = match with_timeout(check_in, latest.changed()).await {
+            Ok(reading) => (reading, true),
+            Err(_) => match LATEST.try_get().filter(|_| blinking) {
+                Some(reading) => (reading, false),
+                None => continue,
+            },
+        };
+        if gradient.palette() != palette::current() {
+            gradient = Gradient::new(&config, palette::current());
+        }
+        // Every event that came in since the last frame is looked at, so a
+        // press behind another event still flashes; presses in quick
+        // succession flash in turn, with the reading shown in between
+        while let Some(event) = events.try_next_message() {
+            match event {
+                WaitResult::Message(BusEvent::ButtonPressed(_)) => {
+                    presses = presses.saturating_add(1);
+                }
+                WaitResult::Message(_) => {}
+                WaitResult::Lagged(n) => {
+                    log!(Module::Led, warn, "LED missed {} events", n);
+                }
+            }
+        }
+        let flash = presses > 0 && !flashed;
+        if flash {
+            presses -= 1;
+        }
+        flashed = flash;
+        let between_blinks =
+            reading.valid && gradient.blinks(reading.sample.voltage_mv) && !palette::is_lit();
+        let color = if flash {
+            FLASH_COLOR
+        } else if let Some(color) = previewing(clock::monotonic_us()) {
+            color
+        } else if mode::current() == Mode::Calibrate {
+            CALIBRATE_COLOR
+        } else if mode::current() == Mode::Fixture {
+            match fixture::verdict() {
+                Some(verdict) if verdict.is_pass() => PASS_COLOR,
+                Some(_) => FAIL_COLOR,
+                None => RGB8::new(0, 0, 0),
+            }
+        } else if between_blinks {
+            RGB8::new(0, 0, 0)
+        } else {
+            color_for(&reading, &gradient)
+        };
+        let color = color.scaled(shown_brightness());
+        // Shed load as the battery runs down
+        #[cfg(feature = "battery")]
+        let color = match battery::stage() {
+            PowerStage::Normal => color,
+            PowerStage::Dimmed => color.scaled(DIMMED_BRIGHTNESS),
+            PowerStage::Reduced | PowerStage::Shutdown => RGB8::new(0, 0, 0),
+        };
+
+        if let Some(channel) = led.take() {
+            led = if backoff.ready() {
+                ws2812_encode(color, pulses, &mut rmt_buffer);
+                let start_us = clock::monotonic_us();
+                let result = ws2812_transmit(channel, &rmt_buffer);
+                write_time.record((clock::monotonic_us() - start_us) as u32);
+                match result {
+                    Ok(channel) => {
+                        backoff.success();
+                        if fresh && mode::current() == Mode::Latency {
+                            latency::record(clock::monotonic_us() - reading.sample.timestamp_us);
+                        }
+                        Some(channel)
+                    }
+                    Err((e, channel)) if backoff.failure() => {
+                        log!(Module::Led, warn, "LED write failed: {}", e);
+                        channel
+                    }
+                    Err((e, _)) => {
+                        log!(Module::Led, warn, "LED write failed, LED disabled: {}", e);
+                        None
+                    }
+                }
+            } else {
+                Some(channel)
+            };
+        }
+        if flash {
+            Timer::after(FLASH_TIME).await;
+        }
+
+        let now_us = clock::monotonic_us();
+        if now_us - report_us >= diag::TIMING_REPORT_US {
+            report_us = now_us;
+            if let Some(s) = write_time.take() {
+                log!(Module::Timing, info, "LED write: {}", s);
+            }
+            if mode::current() == Mode::Latency
+                && let Some(s) = latency::summary()
+            {
+                log!(Module::Timing, info, "Sample to LED: {}", s);
+            }
+        }
+    }
+}
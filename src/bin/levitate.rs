@@ -0,0 +1,201 @@
+//! Levitate mode: with the `levitate` feature, an electromagnet driven
+//! through a MOSFET from LEDC PWM on GPIO39 holds a magnet under it, with
+//! the sensor as feedback. The sampler runs the loop at 1kHz in place of
+//! its own schedule, passing on one reading per sample period so the rest
+//! of the firmware carries on. The setpoint and PID gains are set live
+//! from the console with `levitate`.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+#[cfg(feature = "levitate")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "levitate")]
+use embassy_sync::signal::Signal;
+#[cfg(feature = "levitate")]
+use embassy_time::{Duration, Ticker};
+#[cfg(feature = "levitate")]
+use esp_hal::gpio::DriveMode;
+#[cfg(feature = "levitate")]
+use esp_hal::ledc::channel::{self, Channel, ChannelHW, ChannelIFace};
+#[cfg(feature = "levitate")]
+use esp_hal::ledc::timer::{self, TimerIFace};
+#[cfg(feature = "levitate")]
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+#[cfg(feature = "levitate")]
+use esp_hal::peripherals::{GPIO39, LEDC};
+#[cfg(feature = "levitate")]
+use esp_hal::time::Rate;
+use hall_effect::mode::Mode;
+use hall_effect::pid::Gains;
+#[cfg(feature = "levitate")]
+use hall_effect::pid::Pid;
+#[cfg(feature = "levitate")]
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+#[cfg(feature = "levitate")]
+use static_cell::StaticCell;
+
+use crate::mode;
+#[cfg(feature = "levitate")]
+use crate::sensor::{self, SensorAdc, SensorPin};
+use crate::verbosity::log;
+#[cfg(feature = "levitate")]
+use crate::{replay, watchdog};
+
+#[cfg(feature = "levitate")]
+const CONTROL_PERIOD: Duration = Duration::from_millis(1);
+// Above hearing, and within what 10-bit duty allows from the 80MHz APB
+// clock
+#[cfg(feature = "levitate")]
+const PWM_FREQUENCY: Rate = Rate::from_khz(20);
+#[cfg(feature = "levitate")]
+const DUTY_RANGE: u32 = 1 << 10;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub setpoint_mt: f32,
+    pub gains: Gains,
+}
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    pub field_mt: f32,
+    /// From 0 to 1.
+    pub duty: f32,
+}
+
+// A starting point only: the gains depend on the coil, the magnet and
+// the gap, and are meant to be tuned
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings {
+    setpoint_mt: 10.0,
+    gains: Gains {
+        kp: 0.05,
+        ki: 0.5,
+        kd: 0.0005,
+    },
+}));
+static STATUS: Mutex<Cell<Option<Status>>> = Mutex::new(Cell::new(None));
+/// The coil's next duty, for [`coil_task`], which owns the channel.
+#[cfg(feature = "levitate")]
+static COIL: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes effect from the next control period.
+pub fn configure(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// The last reading and duty, while levitating.
+pub fn status() -> Option<Status> {
+    critical_section::with(|cs| STATUS.borrow(cs).get())
+}
+
+/// Sets up the PWM, with the coil off, at boot.
+#[cfg(feature = "levitate")]
+pub fn init(ledc: LEDC<'static>, pin: GPIO39<'static>) -> Option<Channel<'static, LowSpeed>> {
+    static TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+    let mut ledc = Ledc::new(ledc);
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let timer = TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+    if let Err(e) = timer.configure(timer::config::Config {
+        duty: timer::config::Duty::Duty10Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: PWM_FREQUENCY,
+    }) {
+        log!(Module::Sensor, warn, "Electromagnet PWM unavailable: {}", e);
+        return None;
+    }
+    let mut coil = ledc.channel(channel::Number::Channel0, pin);
+    if let Err(e) = coil.configure(channel::config::Config {
+        timer: &*timer,
+        duty_pct: 0,
+        drive_mode: DriveMode::PushPull,
+    }) {
+        log!(Module::Sensor, warn, "Electromagnet PWM unavailable: {}", e);
+        return None;
+    }
+    Some(coil)
+}
+
+#[cfg(feature = "levitate")]
+#[embassy_executor::task]
+pub async fn coil_task(coil: Channel<'static, LowSpeed>) {
+    loop {
+        coil.set_duty_hw(COIL.wait().await);
+    }
+}
+
+#[cfg(feature = "levitate")]
+fn set_duty(duty: f32) {
+    COIL.signal((duty.clamp(0.0, 1.0) * DUTY_RANGE as f32) as u32);
+}
+
+/// On entering levitate mode.
+pub fn start() {
+    if cfg!(feature = "levitate") {
+        let settings = settings();
+        log!(
+            Module::Sensor,
+            info,
+            "Levitating at {}mT",
+            settings.setpoint_mt
+        );
+    } else {
+        log!(
+            Module::Sensor,
+            warn,
+            "Levitation needs the `levitate` feature's electromagnet"
+        );
+        mode::request(Mode::Measure);
+    }
+}
+
+/// The control loop, from the sampler, until levitate mode is left; the
+/// coil is switched off on the way out.
+#[cfg(feature = "levitate")]
+pub async fn run(adc: &mut SensorAdc, pin: &mut SensorPin, config: &Config) {
+    let mut pid = Pid::new(settings().gains);
+    let mut ticker = Ticker::every(CONTROL_PERIOD);
+    let mut last_us = None;
+    let mut forwarded_us = 0;
+    let sample_period_us = config.sample_period_ms as u64 * 1000;
+
+    while mode::current() == Mode::Levitate {
+        let duty = match sensor::read_sample(|| sensor::convert(adc, pin)).await {
+            Ok(sample) => {
+                let timestamp_us = sample.timestamp_us;
+                let dt_s = last_us
+                    .replace(timestamp_us)
+                    .map_or(0.0, |last_us| (timestamp_us - last_us) as f32 / 1e6);
+                let settings = settings();
+                pid.set_gains(settings.gains);
+                let field_mt = config.field_mt(sample.voltage_mv);
+                let duty = pid.update(settings.setpoint_mt, field_mt, dt_s);
+                critical_section::with(|cs| {
+                    STATUS.borrow(cs).set(Some(Status { field_mt, duty }));
+                });
+                if timestamp_us - forwarded_us >= sample_period_us && !replay::active() {
+                    forwarded_us = timestamp_us;
+                    sensor::forward(sample).await;
+                }
+                duty
+            }
+            // Better dropped than held on with no feedback
+            Err(e) => {
+                log!(Module::Sensor, warn, "Levitation reading failed: {}", e);
+                0.0
+            }
+        };
+        set_duty(duty);
+        watchdog::feed(watchdog::Task::Sensor);
+        ticker.next().await;
+    }
+    set_duty(0.0);
+    critical_section::with(|cs| STATUS.borrow(cs).set(None));
+    log!(Module::Sensor, info, "Levitation stopped");
+}
@@ -0,0 +1,79 @@
+//! Light sleep between samples, to cut the chip's power draw (and the heat
+//! it puts into the sensor) on installs that are not battery powered.
+//!
+//! Once the rest of the pipeline has had [`AWAKE`] to deal with a sample,
+//! the sampler puts the whole chip into light sleep until the next one is
+//! due. The timers behind `esp_hal::time` and the executor stop while it
+//! sleeps, so the time spent asleep is measured on the RTC and added back
+//! to the monotonic clock; timers in other tasks stretch by it instead.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_time::{Duration, Timer};
+use esp_hal::peripherals::LPWR;
+use esp_hal::rtc_cntl::Rtc;
+use esp_hal::rtc_cntl::sleep::TimerWakeupSource;
+
+use crate::clock;
+
+/// Time left for processing, displays and the like after each sample.
+const AWAKE: Duration = Duration::from_millis(2);
+
+// Shorter gaps are not worth the wake-up cost
+const MIN_SLEEP_US: u64 = 3_000;
+
+static SLEPT_US: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+
+/// Total time spent in light sleep, which the monotonic clock adds back.
+pub fn slept_us() -> u64 {
+    critical_section::with(|cs| SLEPT_US.borrow(cs).get())
+}
+
+pub struct Sleeper {
+    rtc: Rtc<'static>,
+    /// Time asleep and in all since the last [`Sleeper::take_percent`].
+    asleep_us: u64,
+    since_us: u64,
+}
+
+impl Sleeper {
+    pub fn new(lpwr: LPWR<'static>) -> Self {
+        Self {
+            rtc: Rtc::new(lpwr),
+            asleep_us: 0,
+            since_us: clock::monotonic_us(),
+        }
+    }
+
+    /// Returns at `deadline_us` on the monotonic clock, sleeping for as much
+    /// of the wait as is worthwhile.
+    pub async fn until(&mut self, deadline_us: u64) {
+        Timer::after(AWAKE).await;
+        let remaining_us = deadline_us.saturating_sub(clock::monotonic_us());
+        if remaining_us < MIN_SLEEP_US {
+            Timer::after_micros(remaining_us).await;
+            return;
+        }
+
+        let start_us = self.rtc.current_time_us();
+        let timer = TimerWakeupSource::new(core::time::Duration::from_micros(remaining_us));
+        self.rtc.sleep_light(&[&timer]);
+        let slept_us = self.rtc.current_time_us().saturating_sub(start_us);
+        critical_section::with(|cs| {
+            let total = SLEPT_US.borrow(cs);
+            total.set(total.get() + slept_us);
+        });
+        self.asleep_us += slept_us;
+    }
+
+    /// Percentage of the time spent asleep since the last call.
+    pub fn take_percent(&mut self) -> u32 {
+        let now_us = clock::monotonic_us();
+        let elapsed_us = (now_us - self.since_us).max(1);
+        let percent = (self.asleep_us * 100 / elapsed_us) as u32;
+        self.asleep_us = 0;
+        self.since_us = now_us;
+        percent
+    }
+}
@@ -0,0 +1,153 @@
+//! Lock-in mode: with the `lockin` feature the excitation coil, through a
+//! logic-level MOSFET on GPIO39, is driven with a square wave while the
+//! mode lasts, and the processing stage measures the field in step with
+//! it. Set the frequency and time constant from the console with `lockin`.
+//!
+//! Without the feature nothing drives the coil, but the detector still
+//! runs against the same reference, for a coil driven from outside at the
+//! same frequency; its phase is then meaningless.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+#[cfg(feature = "lockin")]
+use embassy_time::{Duration, Timer};
+#[cfg(feature = "lockin")]
+use esp_hal::gpio::Output;
+use hall_effect::lockin::{LockIn, Response};
+#[cfg(feature = "lockin")]
+use hall_effect::lockin::{drive_high, next_edge_us};
+#[cfg(feature = "lockin")]
+use hall_effect::mode::Mode;
+use hall_effect::verbosity::Module;
+
+use crate::verbosity::log;
+#[cfg(feature = "lockin")]
+use crate::{clock, mode};
+
+// How often the response is logged
+const REPORT_US: u64 = 1_000_000;
+// How often to look for lock-in mode while the coil is off
+#[cfg(feature = "lockin")]
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub frequency_hz: u32,
+    pub time_constant_ms: u32,
+}
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings {
+    frequency_hz: 10,
+    time_constant_ms: 1000,
+}));
+
+struct Detector {
+    lockin: LockIn,
+    report_us: u64,
+}
+
+static DETECTOR: Mutex<RefCell<Option<Detector>>> = Mutex::new(RefCell::new(None));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Changes the drive, starting the measurement over if it is running.
+/// `false`, changing nothing, for settings [`LockIn::new`] turns down.
+pub fn configure(settings: Settings) -> bool {
+    let Some(lockin) = LockIn::new(settings.frequency_hz, settings.time_constant_ms) else {
+        return false;
+    };
+    critical_section::with(|cs| {
+        SETTINGS.borrow(cs).set(settings);
+        if let Some(detector) = DETECTOR.borrow_ref_mut(cs).as_mut() {
+            detector.lockin = lockin;
+        }
+    });
+    true
+}
+
+/// Starts over, on entering lock-in mode.
+pub fn start() {
+    let settings = settings();
+    let lockin = LockIn::new(settings.frequency_hz, settings.time_constant_ms);
+    critical_section::with(|cs| {
+        DETECTOR.replace(
+            cs,
+            lockin.map(|lockin| Detector {
+                lockin,
+                report_us: 0,
+            }),
+        )
+    });
+    log!(
+        Module::Sensor,
+        info,
+        "Lock-in at {}Hz, {}ms time constant",
+        settings.frequency_hz,
+        settings.time_constant_ms
+    );
+}
+
+/// Adds a valid reading, logging the response every second.
+pub fn push(timestamp_us: u64, field_mt: f32) {
+    let report = critical_section::with(|cs| {
+        let mut detector = DETECTOR.borrow_ref_mut(cs);
+        let detector = detector.as_mut()?;
+        detector.lockin.push(timestamp_us, field_mt);
+        if timestamp_us - detector.report_us < REPORT_US {
+            return None;
+        }
+        detector.report_us = timestamp_us;
+        Some(detector.lockin.response())
+    });
+    if let Some(response) = report {
+        log!(
+            Module::Sample,
+            info,
+            "Lock-in: {}uT at {}deg{}",
+            response.amplitude * 1000.0,
+            response.phase_deg,
+            if response.settled { "" } else { ", settling" }
+        );
+    }
+}
+
+/// The response so far, while in lock-in mode.
+pub fn latest() -> Option<Response> {
+    critical_section::with(|cs| {
+        DETECTOR
+            .borrow_ref(cs)
+            .as_ref()
+            .map(|detector| detector.lockin.response())
+    })
+}
+
+/// Drops the detector on leaving lock-in mode.
+pub fn stop() {
+    critical_section::with(|cs| DETECTOR.replace(cs, None));
+}
+
+#[cfg(feature = "lockin")]
+#[embassy_executor::task]
+pub async fn coil_task(mut coil: Output<'static>) {
+    loop {
+        if mode::current() != Mode::LockIn {
+            coil.set_low();
+            Timer::after(IDLE_POLL).await;
+            continue;
+        }
+        // Edges fall on the monotonic clock's grid, which the detector
+        // takes the reference from; lateness here is a fixed phase lag
+        let frequency_hz = settings().frequency_hz;
+        let now_us = clock::monotonic_us();
+        let edge_us = next_edge_us(frequency_hz, now_us);
+        Timer::after(Duration::from_micros(edge_us - now_us)).await;
+        if drive_high(frequency_hz, edge_us) {
+            coil.set_high();
+        } else {
+            coil.set_low();
+        }
+    }
+}
@@ -6,152 +6,1199 @@
               holding buffers for the duration of a data transfer."
 )]
 
-use defmt::{Format, info};
+mod alarm;
+#[cfg(feature = "ambient-light")]
+mod ambient;
+#[cfg(feature = "dual-core")]
+mod app_core;
+mod baseline;
+#[cfg(feature = "battery")]
+mod battery;
+#[cfg(feature = "bldc")]
+mod bldc;
+mod board;
+mod bus;
+mod burst;
+mod button;
+mod capture;
+mod chip;
+mod clock;
+mod console;
+mod contact;
+mod counting;
+mod current;
+mod diag;
+mod drift;
+#[cfg(feature = "encoder")]
+mod encoder;
+#[cfg(feature = "epaper")]
+mod epaper;
+mod filter;
+mod fixture;
+mod flash_log;
+#[cfg(feature = "x27-gauge")]
+mod gauge;
+mod goertzel;
+mod grading;
+#[cfg(feature = "haptic")]
+mod haptic;
+mod histogram;
+#[cfg(feature = "ir-remote")]
+mod ir;
+mod latency;
+#[cfg(feature = "lcd")]
+mod lcd;
+mod led;
+mod levitate;
+#[cfg(feature = "light-sleep")]
+mod light_sleep;
+mod lockin;
+#[cfg(feature = "max7219")]
+mod matrix;
+#[cfg(feature = "mock-sensor")]
+mod mock;
+mod mode;
+#[cfg(feature = "mux")]
+mod mux;
+mod night;
+mod noise;
+#[cfg(feature = "oled")]
+mod oled;
+mod palette;
+mod panic;
+mod pas;
+mod power;
+mod profile;
+mod reading;
+#[cfg(feature = "relay")]
+mod relay;
+mod remote;
+mod replay;
+mod rules;
+#[cfg(feature = "timer-sampling")]
+mod sample_timer;
+mod schedule;
+#[cfg(feature = "sd-log")]
+mod sd_log;
+mod sensor;
+#[cfg(feature = "servo")]
+mod servo;
+mod sleep;
+mod spectrum;
+mod speed;
+mod state;
+mod storage;
+mod supply;
+#[cfg(feature = "sync")]
+mod sync;
+mod tamper;
+mod tare;
+mod telemetry;
+#[cfg(feature = "tft")]
+mod tft;
+#[cfg(feature = "tm1637")]
+mod tm1637;
+#[cfg(feature = "touch")]
+mod touch;
+#[cfg(feature = "trigger-out")]
+mod trigger;
+#[cfg(feature = "ulp-wake")]
+mod ulp;
+mod units;
+mod verbosity;
+mod watchdog;
+
+use bus::{BusEvent, Fault};
+use defmt::{Format, info, warn};
 use embassy_executor::Spawner;
 use embassy_time::{Duration, Timer};
-use esp_hal::analog::adc::{Adc, AdcCalCurve, AdcConfig, Attenuation};
+use esp_hal::analog::adc::{Adc, AdcConfig};
 use esp_hal::clock::CpuClock;
-use esp_hal::gpio::Level;
-use esp_hal::rmt::{PulseCode, Rmt, TxChannelConfig, TxChannelCreator};
+use esp_hal::gpio::{Input, InputConfig, Pull};
+use esp_hal::rmt::{Rmt, TxChannelCreator};
 use esp_hal::time::Rate;
 use esp_hal::timer::timg::TimerGroup;
-use nb;
-use panic_rtt_target as _;
+use flash_log::FLASH_LOG;
+use hall_effect::gesture::GestureDetector;
+use hall_effect::mode::Mode;
+use hall_effect::report::ExceptionFilter;
+#[cfg(feature = "battery")]
+use hall_effect::schema::PowerStage;
+use hall_effect::schema::{Config, Event, Sample};
+use hall_effect::selftest::{self, RailMonitor};
+use hall_effect::threshold::ThresholdDetector;
+use hall_effect::timing::Window;
+use hall_effect::verbosity::Module;
+use heapless::Vec;
+use mode::ModeState;
+use reading::{LATEST, Reading};
+use sensor::SAMPLES;
+use telemetry::Telemetry;
+use verbosity::log;
+
+#[cfg(all(feature = "oled", feature = "lcd"))]
+compile_error!("the `oled` and `lcd` features both need I2C1");
+#[cfg(all(feature = "ds3231", feature = "haptic"))]
+compile_error!("the `ds3231` and `haptic` features both need I2C0");
+#[cfg(any(
+    all(feature = "tft", feature = "max7219"),
+    all(feature = "tft", feature = "epaper"),
+    all(feature = "max7219", feature = "epaper"),
+))]
+compile_error!("only one of the `tft`, `max7219` and `epaper` features can have SPI3");
+#[cfg(all(feature = "timer-sampling", feature = "light-sleep"))]
+compile_error!("the `timer-sampling` and `light-sleep` features each pace the sampler");
+const _: () = assert!(
+    cfg!(feature = "lockin") as u8
+        + cfg!(feature = "levitate") as u8
+        + cfg!(feature = "null-flux") as u8
+        + cfg!(feature = "speed-control") as u8
+        + cfg!(feature = "pas") as u8
+        + cfg!(feature = "servo") as u8
+        <= 1,
+    "Enable at most one of the features that drive GPIO39"
+);
+#[cfg(all(
+    feature = "levitate",
+    any(feature = "timer-sampling", feature = "light-sleep")
+))]
+compile_error!("the `levitate` feature's control loop runs in place of the sampler's schedule");
+#[cfg(all(
+    feature = "power-meter",
+    any(feature = "tft", feature = "epaper", feature = "board-m5stamp-c3")
+))]
+compile_error!("the `power-meter` feature's divider needs GPIO2, which the display or LED has");
+#[cfg(all(feature = "bldc", not(feature = "esp32s3")))]
+compile_error!("the `bldc` feature needs the S3's MCPWM");
+#[cfg(all(
+    feature = "bldc",
+    any(
+        feature = "tft",
+        feature = "max7219",
+        feature = "epaper",
+        feature = "sd-log",
+        feature = "encoder"
+    )
+))]
+compile_error!("the `bldc` feature's pins are the SPI display's, the SD card's and the encoder's");
+#[cfg(all(feature = "x27-gauge", any(feature = "sd-log", feature = "bldc")))]
+compile_error!("the `x27-gauge` feature's pins are the SD card's and the motor bridge's");
+#[cfg(all(
+    feature = "mux",
+    any(feature = "x27-gauge", feature = "sd-log", feature = "bldc")
+))]
+compile_error!("the `mux` feature's select lines are the gauge's, the SD card's and the bridge's");
+#[cfg(all(feature = "trigger-out", feature = "tm1637"))]
+compile_error!("the `trigger-out` feature's GPIO21 is the TM1637's clock");
+#[cfg(all(
+    feature = "sync",
+    any(feature = "tft", feature = "epaper", feature = "board-xiao-s3")
+))]
+compile_error!("the `sync` feature's GPIO1 is the SPI display's DC and the XIAO's sensor");
+#[cfg(all(
+    feature = "rule-output",
+    any(
+        feature = "tft",
+        feature = "max7219",
+        feature = "epaper",
+        feature = "bldc"
+    )
+))]
+compile_error!("the `rule-output` feature's GPIO5 is the SPI display's clock and the bridge's");
+#[cfg(all(
+    feature = "ambient-light",
+    any(
+        feature = "tft",
+        feature = "max7219",
+        feature = "epaper",
+        feature = "bldc"
+    )
+))]
+compile_error!("the `ambient-light` feature's GPIO6 is the SPI display's MOSI and the bridge's");
+#[cfg(all(feature = "sync", feature = "bldc"))]
+compile_error!("the `sync` and `bldc` features each need the GPIO interrupt handler");
+#[cfg(all(feature = "dual-core", feature = "light-sleep"))]
+compile_error!("light sleep would halt the second core; use one of `dual-core` and `light-sleep`");
 
 // This creates a default app-descriptor required by the esp-idf bootloader.
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[derive(Clone, Copy, Debug, PartialEq, Format)]
-pub struct RGB8 {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-}
+// Longest wait for a sample before checking in with the watchdog
+const IDLE_CHECK_IN: Duration = Duration::from_secs(1);
 
-impl RGB8 {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
-    }
-}
+// Never-used stack below which a warning is logged
+const STACK_LOW_BYTES: usize = 4096;
 
-// WS2812 timing constants (in nanoseconds)
-const CODE_PERIOD_NS: u32 = 1250; // 800kHz
-const T0H_NS: u32 = 400;
-const T0L_NS: u32 = CODE_PERIOD_NS - T0H_NS;
-const T1H_NS: u32 = 850;
-const T1L_NS: u32 = CODE_PERIOD_NS - T1H_NS;
-
-// Buffer size for one RGB LED (24 pulses + 1 delimiter)
-const BUFFER_SIZE: usize = 25;
-
-const MIN_VOLTAGE_MV: f32 = 500.0; // ~0.5V for strong north pole
-const MAX_VOLTAGE_MV: f32 = 2800.0; // ~2.8V for strong south pole
-
-fn led_pulses_for_clock(src_clock_mhz: u32) -> (PulseCode, PulseCode) {
-    (
-        PulseCode::new(
-            Level::High.into(),
-            ((T0H_NS * src_clock_mhz) / 1000) as u16,
-            Level::Low.into(),
-            ((T0L_NS * src_clock_mhz) / 1000) as u16,
-        ),
-        PulseCode::new(
-            Level::High.into(),
-            ((T1H_NS * src_clock_mhz) / 1000) as u16,
-            Level::Low.into(),
-            ((T1L_NS * src_clock_mhz) / 1000) as u16,
-        ),
-    )
-}
+// Time between deciding to shut down on a flat battery and doing so
+#[cfg(feature = "battery")]
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
 
-fn ws2812_encode(
-    color: RGB8,
-    pulses: (PulseCode, PulseCode),
-    rmt_buffer: &mut [PulseCode; BUFFER_SIZE],
-) {
-    let bytes = [color.g, color.r, color.b];
-    let mut idx = 0;
-
-    for &byte in bytes.iter() {
-        for bit in (0..8).rev() {
-            let is_set = (byte & (1 << bit)) != 0;
-            rmt_buffer[idx] = if is_set { pulses.1 } else { pulses.0 };
-            idx += 1;
-        }
-    }
-    rmt_buffer[24] = PulseCode::new(Level::Low.into(), 0, Level::Low.into(), 0); // Delimiter
+/// Failures the sampler and LED tasks recover from rather than panicking on.
+#[derive(Debug, Format)]
+enum Error {
+    /// The ADC still failed after all retries.
+    Adc,
+    Rmt(esp_hal::rmt::Error),
 }
 
-fn voltage_to_color(voltage_mv: u32) -> RGB8 {
-    let v = voltage_mv as f32;
-    let t = if v <= MIN_VOLTAGE_MV {
-        0.0
-    } else if v >= MAX_VOLTAGE_MV {
-        1.0
-    } else {
-        (v - MIN_VOLTAGE_MV) / (MAX_VOLTAGE_MV - MIN_VOLTAGE_MV)
-    };
-    let r = (255.0 * (1.0 - t)) as u8; // Red for low voltage (north)
-    let b = (255.0 * t) as u8; // Blue for high voltage (south)
-    RGB8::new(r, 0, b)
+impl From<esp_hal::rmt::Error> for Error {
+    fn from(e: esp_hal::rmt::Error) -> Self {
+        Error::Rmt(e)
+    }
 }
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
     // generator version: 0.6.0
     rtt_target::rtt_init_defmt!();
+    diag::paint_stack();
 
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
+    let hal_config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(hal_config);
 
     let timg0 = TimerGroup::new(peripherals.TIMG0);
+    #[cfg(any(feature = "esp32s3", feature = "esp32s2"))]
     esp_rtos::start(timg0.timer0);
+    // The RISC-V chips switch tasks from a software interrupt
+    #[cfg(any(feature = "esp32c3", feature = "esp32c6"))]
+    {
+        use esp_hal::interrupt::software::SoftwareInterruptControl;
+
+        let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+        esp_rtos::start(timg0.timer0, sw_int.software_interrupt0);
+    }
 
     info!("Embassy initialized!");
 
-    // Initialize ADC for hall effect sensor on GPIO4
+    // The LED, displays and loggers, kept off the sampler's core
+    // where there is a second one
+    #[cfg(feature = "dual-core")]
+    let io_spawner = {
+        use esp_hal::interrupt::software::SoftwareInterruptControl;
+
+        let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+        app_core::start(
+            peripherals.CPU_CTRL,
+            sw_int.software_interrupt0,
+            sw_int.software_interrupt1,
+        )
+        .await
+    };
+    #[cfg(not(feature = "dual-core"))]
+    let io_spawner = spawner.make_send();
+
+    let mut config = sleep::restore(Config::default());
+    #[cfg(feature = "mock-sensor")]
+    {
+        mock::init(&config);
+        warn!("Mock sensor build: readings are synthetic, see `mock` on the console");
+    }
+    // Left running from deep sleep, the ULP would contend for the ADC
+    #[cfg(feature = "ulp-wake")]
+    ulp::disarm();
+
+    // Initialize ADC for hall effect sensor
     let mut adc_config = AdcConfig::new();
-    let analog_pin = peripherals.GPIO4;
+    let analog_pin = board::sensor_pin!(peripherals);
     let mut adc_pin =
-        adc_config.enable_pin_with_cal::<_, AdcCalCurve<_>>(analog_pin, Attenuation::_6dB);
+        adc_config.enable_pin_with_cal::<_, chip::AdcCal<_>>(analog_pin, chip::SENSOR_ATTENUATION);
+    // Battery through a divider on GPIO3
+    #[cfg(feature = "battery")]
+    battery::init(
+        adc_config.enable_pin_with_cal::<_, chip::AdcCal<_>>(
+            peripherals.GPIO3,
+            chip::BATTERY_ATTENUATION,
+        ),
+    );
+    // Power meter's voltage divider on GPIO2
+    #[cfg(feature = "power-meter")]
+    power::init(
+        adc_config.enable_pin_with_cal::<_, chip::AdcCal<_>>(
+            peripherals.GPIO2,
+            chip::VOLTAGE_ATTENUATION,
+        ),
+    );
+    // Light sensor on GPIO6
+    #[cfg(feature = "ambient-light")]
+    ambient::init(
+        adc_config.enable_pin_with_cal::<_, chip::AdcCal<_>>(
+            peripherals.GPIO6,
+            chip::AMBIENT_ATTENUATION,
+        ),
+    );
     let mut adc = Adc::new(peripherals.ADC1, adc_config);
 
-    // Initialize RMT for WS2812 control
-    let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(80)).unwrap();
-    let tx_config = TxChannelConfig::default()
-        .with_clk_divider(1)
-        .with_idle_output_level(Level::Low)
-        .with_carrier_modulation(false)
-        .with_idle_output(true);
-    let channel_creator = rmt.channel0;
-    let mut channel = channel_creator
-        .configure_tx(peripherals.GPIO48, tx_config)
+    // In low-power mode a timer wake takes a reading and goes back to sleep
+    let reset_reason = esp_hal::system::reset_reason();
+    if sleep::is_timer_wake(reset_reason) {
+        sleep::wake_cycle(
+            &mut adc,
+            &mut adc_pin,
+            &config,
+            peripherals.LPWR,
+            board::button_pin!(peripherals),
+        )
+        .await
+    }
+
+    // Initialize RMT for WS2812 control; sampling carries on without it
+    let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(chip::RMT_CLOCK_MHZ))
+        .inspect_err(|e| warn!("RMT unavailable: {}", e))
+        .ok();
+    #[cfg_attr(not(feature = "ir-remote"), expect(unused_variables))]
+    let (led_channel, ir_channel) = rmt
+        .map(|rmt| (rmt.channel0, chip::ir_rx_channel!(rmt)))
+        .unzip();
+    let mut led = led_channel.and_then(|channel| {
+        channel
+            .configure_tx(board::led_pin!(peripherals), led::led_tx_config())
+            .inspect_err(|e| warn!("LED unavailable: {}", e))
+            .ok()
+    });
+
+    // Precompute pulses based on the RMT clock
+    let pulses = led::led_pulses_for_clock(chip::RMT_CLOCK_MHZ);
+
+    info!(
+        "WS2812 LED initialized, pins for {} ({})",
+        board::NAME,
+        esp_hal::chip!()
+    );
+
+    // SD card on SPI2, clocked at 400kHz until the card is initialized
+    #[cfg(feature = "sd-log")]
+    let sd_logger = {
+        use embedded_hal_bus::spi::ExclusiveDevice;
+        use embedded_sdmmc::SdCard;
+        use esp_hal::delay::Delay;
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+        use esp_hal::spi::master::{Config as SpiConfig, Spi};
+
+        let spi_config = SpiConfig::default().with_frequency(Rate::from_khz(400));
+        let cs = Output::new(peripherals.GPIO10, Level::High, OutputConfig::default());
+        let sdcard = Spi::new(peripherals.SPI2, spi_config)
+            .inspect_err(|e| warn!("SD card SPI unavailable: {}", e))
+            .ok()
+            .map(|spi| {
+                let spi = spi
+                    .with_sck(peripherals.GPIO12)
+                    .with_mosi(peripherals.GPIO11)
+                    .with_miso(peripherals.GPIO13);
+                let Ok(device) = ExclusiveDevice::new_no_delay(spi, cs);
+                SdCard::new(device, Delay::new())
+            });
+
+        match sdcard.map(|sdcard| (sdcard.num_bytes(), sdcard)) {
+            Some((Ok(size), sdcard)) => {
+                info!("SD card detected, {} bytes", size);
+                let fast = spi_config.with_frequency(Rate::from_mhz(20));
+                if let Err(e) = sdcard.spi(|dev| dev.bus_mut().apply_config(&fast)) {
+                    warn!("SD card left at 400kHz: {}", e);
+                }
+                sd_log::SdLogger::new(sdcard)
+                    .inspect_err(|e| warn!("SD log unavailable: {}", e))
+                    .ok()
+            }
+            Some((Err(e), _)) => {
+                warn!("No SD card: {}", e);
+                None
+            }
+            None => None,
+        }
+    };
+
+    // DS3231 on I2C0 (SDA GPIO8, SCL GPIO9) provides the wall clock at boot
+    #[cfg(feature = "ds3231")]
+    let boot_time = {
+        use esp_hal::i2c::master::{Config as I2cConfig, I2c};
+        use hall_effect::ds3231::Ds3231;
+
+        let mut rtc = I2c::new(
+            peripherals.I2C0,
+            I2cConfig::default().with_frequency(Rate::from_khz(100)),
+        )
+        .inspect_err(|e| warn!("RTC I2C unavailable: {}", e))
+        .ok()
+        .map(|i2c| Ds3231::new(i2c.with_sda(peripherals.GPIO8).with_scl(peripherals.GPIO9)));
+        let now = rtc.as_mut().and_then(|rtc| {
+            rtc.read()
+                .inspect_err(|e| warn!("RTC not responding: {}", e))
+                .ok()
+                .flatten()
+        });
+        if now.is_none() {
+            warn!("RTC time unavailable, set the clock with `time set`");
+        }
+        *clock::RTC.lock().await = rtc;
+        now
+    };
+    #[cfg(not(feature = "ds3231"))]
+    let boot_time = None;
+
+    // DRV2605 haptic driver on I2C0 (SDA GPIO8, SCL GPIO9), in place of the RTC
+    #[cfg(feature = "haptic")]
+    {
+        use esp_hal::i2c::master::{Config as I2cConfig, I2c};
+        use hall_effect::drv2605::Drv2605;
+
+        let i2c = I2c::new(
+            peripherals.I2C0,
+            I2cConfig::default().with_frequency(Rate::from_khz(400)),
+        )
+        .inspect_err(|e| warn!("Haptic I2C unavailable: {}", e))
+        .ok();
+        if let Some(i2c) = i2c {
+            let i2c = i2c.with_sda(peripherals.GPIO8).with_scl(peripherals.GPIO9);
+            io_spawner
+                .spawn(haptic::haptic_task(Drv2605::new(i2c)))
+                .unwrap();
+        }
+    }
+
+    let partitions = storage::init(peripherals.FLASH);
+
+    info!("Reset reason: {}", defmt::Debug2Format(&reset_reason));
+    if reset_reason == Some(esp_hal::rtc_cntl::SocResetReason::SysBrownOut) {
+        warn!("Previous reset was a brown-out");
+    }
+    let stalled_task = watchdog::init(reset_reason);
+    if let Some(task) = stalled_task {
+        warn!("Watchdog reset, {} task stalled", task);
+    }
+    if panic::pending() {
+        warn!("Previous boot ended in a panic, see `last-panic`");
+    }
+    let mut state = state::State::new(
+        partitions.state,
+        reset_reason.map(|r| r as u8),
+        stalled_task,
+    )
+    .await
+    .inspect_err(|e| warn!("State store unavailable: {}", e))
+    .ok();
+    // Pulses counted while in low-power mode go on the odometer
+    if let Some(totals) = sleep::take_totals() {
+        info!("Low-power mode ended: {}", totals);
+        if let Some(state) = state.as_mut() {
+            state.add_pulses(totals.pulses as u64);
+        }
+    }
+    if let Some(state) = state.as_ref() {
+        info!("Boot stats: {}", state.stats());
+    }
+
+    // BOOT button, free once the chip is running. Pressed as it starts and
+    // held, it erases what is stored before any of it is applied
+    let mut button = Input::new(
+        board::button_pin!(peripherals),
+        InputConfig::default().with_pull(Pull::Up),
+    );
+    if button::held_at_boot(&mut button, &mut led, pulses).await {
+        *state::STATE.lock().await = state;
+        button::factory_reset().await
+    }
+    if let Some(state) = state.as_mut() {
+        let settings = *state.settings();
+        if let Some(stored) = settings.config {
+            config = sleep::restore(stored);
+        }
+        if let Some(chain) = settings.filters {
+            filter::restore(chain);
+        }
+        if let Some(offset_mt) = settings.tare_mt {
+            tare::restore(offset_mt);
+        }
+        if let Some(scale) = settings.current_scale {
+            current::restore(scale);
+        }
+        power::restore_energy(state.energy_mj());
+        if let Some(ratio) = settings.voltage_ratio {
+            power::restore_ratio(ratio);
+        }
+        if let Some(settings) = settings.pas {
+            pas::restore(settings);
+        }
+        if let Some(settings) = settings.contact {
+            contact::restore(settings);
+        }
+        if let Some(settings) = settings.count {
+            counting::restore(settings);
+        }
+        if let Some(limits) = settings.fixture {
+            fixture::restore(limits);
+        }
+        if let Some(settings) = settings.grading {
+            grading::restore(settings);
+        }
+        if let Some(settings) = settings.tamper {
+            tamper::restore(settings);
+        }
+        match state.tamper_alarm().await {
+            Ok(Some(cause)) => tamper::restore_alarm(cause),
+            Ok(None) => {}
+            Err(e) => warn!("Tamper alarm unavailable: {}", e),
+        }
+        #[cfg(feature = "x27-gauge")]
+        if let Some(scale) = settings.gauge_scale {
+            gauge::restore(scale);
+        }
+        #[cfg(feature = "servo")]
+        if let Some(settings) = settings.servo {
+            servo::restore(settings);
+        }
+        #[cfg(feature = "relay")]
+        if let Some(settings) = settings.relay {
+            relay::restore(settings);
+        }
+        #[cfg(feature = "trigger-out")]
+        if let Some(settings) = settings.trigger {
+            trigger::restore(settings);
+        }
+        if let Some(unit) = settings.unit {
+            units::restore(unit);
+        }
+        if let Some(palette) = settings.palette {
+            palette::restore(palette);
+        }
+        if let Some(saved) = settings.rules {
+            rules::restore(saved);
+        }
+        if let Some(entries) = settings.schedule {
+            schedule::restore(entries);
+        }
+        if let Some(settings) = settings.night {
+            night::restore(settings);
+        }
+        #[cfg(feature = "ambient-light")]
+        if let Some(settings) = settings.ambient {
+            ambient::restore(settings);
+        }
+        #[cfg(feature = "sync")]
+        if let Some(role) = settings.sync_role {
+            sync::restore(role);
+        }
+    }
+    *state::STATE.lock().await = state;
+
+    let mut flash_log = flash_log::FlashLog::new(partitions.log, &config)
+        .inspect_err(|e| warn!("Flash log unavailable: {}", e))
+        .ok();
+    if let Some(log) = flash_log.as_mut()
+        && let Err(e) = log.record_event(0, Event::Boot).await
+    {
+        warn!("Flash log write failed: {}", e);
+    }
+    *FLASH_LOG.lock().await = flash_log;
+
+    if let Some(now) = boot_time {
+        clock::set(&now).await;
+    }
+
+    if let Some(port) = chip::console_port!(peripherals) {
+        spawner.spawn(console::console_task(port, config)).unwrap();
+    }
+    spawner.spawn(bus::log_task()).unwrap();
+
+    spawner.spawn(button::button_task(button)).unwrap();
+
+    // IR receiver on GPIO18, decoded on the first RMT RX channel
+    #[cfg(feature = "ir-remote")]
+    {
+        use esp_hal::rmt::RxChannelCreator;
+
+        let ir = ir_channel.and_then(|channel| {
+            channel
+                .configure_rx(peripherals.GPIO18, ir::ir_rx_config())
+                .inspect_err(|e| warn!("IR receiver unavailable: {}", e))
+                .ok()
+        });
+        if let Some(channel) = ir {
+            spawner.spawn(ir::ir_task(channel)).unwrap();
+        }
+    }
+    #[cfg(feature = "touch")]
+    spawner
+        .spawn(touch::touch_task(peripherals.GPIO14))
         .unwrap();
 
-    // Precompute pulses based on actual clock
-    let src_clock_mhz = esp_hal::clock::Clocks::get().apb_clock.as_mhz();
-    let pulses = led_pulses_for_clock(src_clock_mhz);
+    // Electromagnet through a MOSFET on GPIO39, from LEDC PWM
+    #[cfg(feature = "levitate")]
+    if let Some(coil) = levitate::init(peripherals.LEDC, peripherals.GPIO39) {
+        spawner.spawn(levitate::coil_task(coil)).unwrap();
+    }
 
-    info!("WS2812 LED initialized on GPIO48, ADC on GPIO4");
+    // Compensation winding through a bipolar stage on GPIO39, from LEDC PWM
+    #[cfg(feature = "null-flux")]
+    if let Some(winding) = current::init(peripherals.LEDC, peripherals.GPIO39) {
+        spawner.spawn(current::winding_task(winding)).unwrap();
+    }
 
-    let _ = spawner;
+    // Motor through a MOSFET on GPIO39, from LEDC PWM
+    #[cfg(feature = "speed-control")]
+    if let Some(motor) = speed::init(peripherals.LEDC, peripherals.GPIO39) {
+        spawner.spawn(speed::motor_task(motor)).unwrap();
+    }
 
-    let mut rmt_buffer = [PulseCode::default(); BUFFER_SIZE];
+    // Throttle output through an RC filter on GPIO39, from LEDC PWM, and
+    // the crank's second sensor on GPIO40
+    #[cfg(feature = "pas")]
+    if let Some(throttle) = pas::init(peripherals.LEDC, peripherals.GPIO39, peripherals.GPIO40) {
+        spawner.spawn(pas::throttle_task(throttle)).unwrap();
+    }
 
-    loop {
-        let raw: u16 = nb::block!(adc.read_oneshot(&mut adc_pin)).unwrap();
-        let voltage_mv = ((raw as f32 / 4095.0) * 3300.0) as u32;
-        let color = voltage_to_color(voltage_mv);
-        ws2812_encode(color, pulses, &mut rmt_buffer);
+    // Servo gauge on GPIO39, from LEDC PWM at 50Hz
+    #[cfg(feature = "servo")]
+    if let Some(channel) = servo::init(peripherals.LEDC, peripherals.GPIO39) {
+        spawner.spawn(servo::servo_task(channel)).unwrap();
+    }
+
+    // Relay driver on GPIO45, in its fail-safe state until the first reading
+    #[cfg(feature = "relay")]
+    relay::init(peripherals.GPIO45);
+
+    // Trigger output on GPIO21, for a scope or camera
+    #[cfg(feature = "trigger-out")]
+    trigger::init(peripherals.GPIO21);
+
+    // Rule output on GPIO5, low until an output rule is met
+    #[cfg(feature = "rule-output")]
+    rules::init(peripherals.GPIO5);
+
+    // Sampling sync line on GPIO1, shared with the other boards
+    #[cfg(feature = "sync")]
+    {
+        use esp_hal::gpio::Io;
+
+        sync::init(peripherals.GPIO1, config.sample_period_ms);
+        Io::new(peripherals.IO_MUX).set_interrupt_handler(sync::on_edge);
+    }
+
+    // Batch output on GPIO46, pulsed at the end of each batch in count mode
+    #[cfg(feature = "batch-output")]
+    {
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+
+        let output = Output::new(peripherals.GPIO46, Level::Low, OutputConfig::default());
+        spawner.spawn(counting::output_task(output)).unwrap();
+    }
+
+    // Lock-in excitation coil through a MOSFET on GPIO39
+    #[cfg(feature = "lockin")]
+    {
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+
+        let coil = Output::new(peripherals.GPIO39, Level::Low, OutputConfig::default());
+        spawner.spawn(lockin::coil_task(coil)).unwrap();
+    }
+
+    // Rotary encoder on PCNT unit 0 (A GPIO15, B GPIO16, button GPIO17)
+    #[cfg(feature = "encoder")]
+    {
+        use esp_hal::pcnt::Pcnt;
+        use esp_hal::pcnt::channel::{CtrlMode, EdgeMode};
+
+        let unit = Pcnt::new(peripherals.PCNT).unit0;
+        let pull_up = InputConfig::default().with_pull(Pull::Up);
+        let a = Input::new(peripherals.GPIO15, pull_up).peripheral_input();
+        let b = Input::new(peripherals.GPIO16, pull_up).peripheral_input();
+        // Both edges of both signals, so four counts per detent
+        unit.channel0
+            .set_ctrl_signal(a.clone())
+            .set_edge_signal(b.clone());
+        unit.channel0
+            .set_ctrl_mode(CtrlMode::Reverse, CtrlMode::Keep);
+        unit.channel0
+            .set_input_mode(EdgeMode::Increment, EdgeMode::Decrement);
+        unit.channel1.set_ctrl_signal(b).set_edge_signal(a);
+        unit.channel1
+            .set_ctrl_mode(CtrlMode::Reverse, CtrlMode::Keep);
+        unit.channel1
+            .set_input_mode(EdgeMode::Decrement, EdgeMode::Increment);
+        // Ignore pulses shorter than 1023 APB cycles, about 13us
+        if let Err(e) = unit.set_filter(Some(1023)) {
+            warn!("Encoder glitch filter not set: {}", e);
+        }
+        unit.clear();
+        unit.resume();
+
+        let button = Input::new(peripherals.GPIO17, pull_up);
+        spawner
+            .spawn(encoder::encoder_task(unit, button, config))
+            .unwrap();
+    }
+    // Motor bridge on MCPWM0 (high sides GPIO5, 6, 7, low sides GPIO10, 11,
+    // 12), halls on GPIO15, 16 and 17
+    #[cfg(feature = "bldc")]
+    {
+        use esp_hal::gpio::Io;
+
+        bldc::init(
+            peripherals.MCPWM0,
+            bldc::Pins {
+                high: [
+                    peripherals.GPIO5.into(),
+                    peripherals.GPIO6.into(),
+                    peripherals.GPIO7.into(),
+                ],
+                low: [
+                    peripherals.GPIO10.into(),
+                    peripherals.GPIO11.into(),
+                    peripherals.GPIO12.into(),
+                ],
+                halls: [
+                    peripherals.GPIO15.into(),
+                    peripherals.GPIO16.into(),
+                    peripherals.GPIO17.into(),
+                ],
+            },
+        );
+        Io::new(peripherals.IO_MUX).set_interrupt_handler(bldc::on_hall);
+        spawner.spawn(bldc::control_task()).unwrap();
+    }
+    spawner.spawn(state::checkpoint_task()).unwrap();
+    spawner.spawn(schedule::schedule_task()).unwrap();
+    spawner.spawn(replay::replay_task()).unwrap();
+
+    // Supervise the sampler, processing, the LED and the console from here on
+    let timg1 = TimerGroup::new(peripherals.TIMG1);
+    spawner.spawn(watchdog::watchdog_task(timg1.wdt)).unwrap();
+    #[cfg(feature = "timer-sampling")]
+    sample_timer::init(timg1.timer0);
+
+    // Stay in the fault state, retesting, until the sensor reads plausibly
+    // or the attempts run out
+    for attempt in 1..=selftest::ATTEMPTS {
+        let mut samples: Vec<Sample, { selftest::SAMPLES }> = Vec::new();
+        while !samples.is_full() {
+            if let Ok(sample) =
+                sensor::read_sample(|| sensor::convert(&mut adc, &mut adc_pin)).await
+            {
+                let _ = samples.push(sample);
+            }
+            Timer::after(Duration::from_millis(1)).await;
+        }
+        watchdog::feed(watchdog::Task::Sensor);
+        watchdog::feed(watchdog::Task::Process);
+
+        match selftest::check(&samples, &config, sensor::FULL_SCALE) {
+            Ok(()) => {
+                info!("Sensor self-test passed");
+                break;
+            }
+            Err(fault) => {
+                bus::publish(BusEvent::FaultDetected(Fault::SelfTest(fault)));
+                led::blink_fault_code(&mut led, pulses, fault.blink_code()).await;
+                watchdog::feed(watchdog::Task::Led);
+                if attempt == selftest::ATTEMPTS {
+                    warn!("Sensor self-test failed, carrying on: {}", fault);
+                }
+            }
+        }
+    }
+
+    io_spawner.spawn(telemetry::flash_sink_task()).unwrap();
+    #[cfg(feature = "sd-log")]
+    if let Some(logger) = sd_logger {
+        io_spawner
+            .spawn(telemetry::sd_sink_task(logger, config))
+            .unwrap();
+    }
+    io_spawner
+        .spawn(led::led_task(led, pulses, config))
+        .unwrap();
+
+    // SSD1306 OLED on I2C1 (SDA GPIO41, SCL GPIO42)
+    #[cfg(feature = "oled")]
+    {
+        use esp_hal::i2c::master::{Config as I2cConfig, I2c};
+        use hall_effect::ssd1306::Ssd1306;
+
+        let display = I2c::new(
+            peripherals.I2C1,
+            I2cConfig::default().with_frequency(Rate::from_khz(400)),
+        )
+        .inspect_err(|e| warn!("OLED I2C unavailable: {}", e))
+        .ok()
+        .map(|i2c| {
+            Ssd1306::new(
+                i2c.with_sda(peripherals.GPIO41)
+                    .with_scl(peripherals.GPIO42),
+            )
+        });
+        if let Some(display) = display {
+            io_spawner.spawn(oled::oled_task(display, config)).unwrap();
+        }
+    }
+
+    // HD44780 LCD on I2C1 (SDA GPIO41, SCL GPIO42), in place of the OLED
+    #[cfg(feature = "lcd")]
+    {
+        use esp_hal::i2c::master::{Config as I2cConfig, I2c};
+        use hall_effect::hd44780::{DEFAULT_ADDRESS, Hd44780};
+
+        let lcd = I2c::new(
+            peripherals.I2C1,
+            I2cConfig::default().with_frequency(Rate::from_khz(100)),
+        )
+        .inspect_err(|e| warn!("LCD I2C unavailable: {}", e))
+        .ok()
+        .map(|i2c| {
+            Hd44780::new(
+                i2c.with_sda(peripherals.GPIO41)
+                    .with_scl(peripherals.GPIO42),
+                DEFAULT_ADDRESS,
+            )
+        });
+        if let Some(lcd) = lcd {
+            io_spawner.spawn(lcd::lcd_task(lcd, config)).unwrap();
+        }
+    }
 
-        let transaction = channel.transmit(&rmt_buffer).unwrap();
-        channel = transaction.wait().unwrap();
+    // TFT on SPI3 (SCK GPIO5, MOSI GPIO6, CS GPIO7, DC GPIO1, RST GPIO2)
+    #[cfg(feature = "tft")]
+    {
+        use embedded_hal_bus::spi::ExclusiveDevice;
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+        use esp_hal::spi::master::{Config as SpiConfig, Spi};
 
-        info!(
-            "Voltage: {}mV, LED color: R={}, G={}, B={}",
-            voltage_mv, color.r, color.g, color.b
+        let cs = Output::new(peripherals.GPIO7, Level::High, OutputConfig::default());
+        let dc = Output::new(peripherals.GPIO1, Level::Low, OutputConfig::default());
+        let rst = Output::new(peripherals.GPIO2, Level::High, OutputConfig::default());
+        let spi = Spi::new(
+            peripherals.SPI3,
+            SpiConfig::default().with_frequency(Rate::from_mhz(20)),
+        )
+        .inspect_err(|e| warn!("TFT SPI unavailable: {}", e))
+        .ok();
+        if let Some(spi) = spi {
+            let spi = spi
+                .with_sck(peripherals.GPIO5)
+                .with_mosi(peripherals.GPIO6);
+            let Ok(device) = ExclusiveDevice::new_no_delay(spi, cs);
+            io_spawner
+                .spawn(tft::tft_task(device, dc, rst, config))
+                .unwrap();
+        }
+    }
+
+    // MAX7219 chain on SPI3 (SCK GPIO5, MOSI GPIO6, LOAD GPIO7), in place of the TFT
+    #[cfg(feature = "max7219")]
+    {
+        use embedded_hal_bus::spi::ExclusiveDevice;
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+        use esp_hal::spi::master::{Config as SpiConfig, Spi};
+        use hall_effect::max7219::Max7219;
+
+        let load = Output::new(peripherals.GPIO7, Level::High, OutputConfig::default());
+        let spi = Spi::new(
+            peripherals.SPI3,
+            SpiConfig::default().with_frequency(Rate::from_mhz(8)),
+        )
+        .inspect_err(|e| warn!("LED matrix SPI unavailable: {}", e))
+        .ok();
+        if let Some(spi) = spi {
+            let spi = spi
+                .with_sck(peripherals.GPIO5)
+                .with_mosi(peripherals.GPIO6);
+            let Ok(device) = ExclusiveDevice::new_no_delay(spi, load);
+            io_spawner
+                .spawn(matrix::matrix_task(Max7219::new(device), config))
+                .unwrap();
+        }
+    }
+
+    // E-paper on SPI3 (SCK GPIO5, MOSI GPIO6, CS GPIO7, DC GPIO1, RST GPIO2,
+    // BUSY GPIO40), in place of the TFT
+    #[cfg(feature = "epaper")]
+    {
+        use embedded_hal_bus::spi::ExclusiveDevice;
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+        use esp_hal::spi::master::{Config as SpiConfig, Spi};
+        use hall_effect::ssd1680::Ssd1680;
+
+        let cs = Output::new(peripherals.GPIO7, Level::High, OutputConfig::default());
+        let dc = Output::new(peripherals.GPIO1, Level::Low, OutputConfig::default());
+        let pins = epaper::Pins {
+            reset: Output::new(peripherals.GPIO2, Level::High, OutputConfig::default()),
+            busy: Input::new(peripherals.GPIO40, InputConfig::default()),
+        };
+        let spi = Spi::new(
+            peripherals.SPI3,
+            SpiConfig::default().with_frequency(Rate::from_mhz(4)),
+        )
+        .inspect_err(|e| warn!("E-paper SPI unavailable: {}", e))
+        .ok();
+        if let Some(spi) = spi {
+            let spi = spi
+                .with_sck(peripherals.GPIO5)
+                .with_mosi(peripherals.GPIO6);
+            let Ok(device) = ExclusiveDevice::new_no_delay(spi, cs);
+            io_spawner
+                .spawn(epaper::epaper_task(Ssd1680::new(device, dc), pins))
+                .unwrap();
+        }
+    }
+
+    // TM1637 on CLK GPIO21 and DIO GPIO47, open-drain with the module's pull-up
+    #[cfg(feature = "tm1637")]
+    {
+        use esp_hal::delay::Delay;
+        use esp_hal::gpio::{DriveMode, Flex, Level, Output, OutputConfig};
+        use hall_effect::tm1637::Tm1637;
+
+        let clk = Output::new(peripherals.GPIO21, Level::High, OutputConfig::default());
+        let mut dio = Flex::new(peripherals.GPIO47);
+        dio.apply_output_config(
+            &OutputConfig::default()
+                .with_drive_mode(DriveMode::OpenDrain)
+                .with_pull(Pull::Up),
         );
+        dio.set_high();
+        dio.set_output_enable(true);
+        dio.set_input_enable(true);
+        io_spawner
+            .spawn(tm1637::tm1637_task(Tm1637::new(clk, dio, Delay::new())))
+            .unwrap();
+    }
+
+    // X27 gauge stepper, coil pins 1 to 4 on GPIO10 to GPIO13
+    #[cfg(feature = "x27-gauge")]
+    {
+        use esp_hal::gpio::{AnyPin, Level, Output, OutputConfig};
+        use hall_effect::x27::X27;
+
+        let pin = |gpio: AnyPin<'static>| Output::new(gpio, Level::Low, OutputConfig::default());
+        let pins = [
+            pin(peripherals.GPIO10.into()),
+            pin(peripherals.GPIO11.into()),
+            pin(peripherals.GPIO12.into()),
+            pin(peripherals.GPIO13.into()),
+        ];
+        io_spawner.spawn(gauge::gauge_task(X27::new(pins))).unwrap();
+    }
+
+    // Sensor mux select lines S0 to S3 on GPIO10 to GPIO13
+    #[cfg(feature = "mux")]
+    {
+        use esp_hal::gpio::{AnyPin, Level, Output, OutputConfig};
+
+        let pin = |gpio: AnyPin<'static>| Output::new(gpio, Level::Low, OutputConfig::default());
+        mux::init([
+            pin(peripherals.GPIO10.into()),
+            pin(peripherals.GPIO11.into()),
+            pin(peripherals.GPIO12.into()),
+            pin(peripherals.GPIO13.into()),
+        ]);
+    }
+    spawner
+        .spawn(sensor::sampler_task(adc, adc_pin, config))
+        .unwrap();
+
+    // The main task carries on as the processing stage
+    process(config).await
+}
 
-        Timer::after(Duration::from_millis(10)).await;
+/// Turns samples into readings, events and telemetry.
+async fn process(mut config: Config) -> ! {
+    let mut supply = supply::Monitor::new();
+    let mut rail_monitor = RailMonitor::new(sensor::FULL_SCALE);
+    let mut wiring_fault = None;
+    let mut latency = Window::new();
+    let mut report_us = clock::monotonic_us();
+    let mut stack_warned = false;
+    let mut threshold = ThresholdDetector::new();
+    let mut modes = ModeState::new(&config);
+    let mut gestures = GestureDetector::new();
+    let mut exceptions = ExceptionFilter::new();
+    let mut filters = filter::Pipeline::new();
+    let mut auto_zero = baseline::Tracker::new();
+    let mut self_test = schedule::SelfTest::new();
+    #[cfg(feature = "relay")]
+    let mut relay = relay::Switch::new();
+    #[cfg(feature = "battery")]
+    let mut battery_monitor = hall_effect::battery::BatteryMonitor::new();
+
+    loop {
+        watchdog::feed(watchdog::Task::Process);
+        modes.poll_request();
+        let mut reconfigured = false;
+        if let Some(switched) = profile::SWITCHED.try_take() {
+            config = switched;
+            reconfigured = true;
+        }
+        while let Ok(update) = remote::UPDATES.try_receive() {
+            match update.apply(&config) {
+                Ok(updated) => {
+                    config = updated;
+                    reconfigured = true;
+                }
+                Err(e) => log!(Module::Sensor, warn, "Config update refused: {}", e),
+            }
+        }
+        if reconfigured {
+            flash_log::RECONFIGURED.signal(config);
+        }
+        #[cfg(feature = "encoder")]
+        if let Some(threshold_mv) = encoder::THRESHOLD.try_take() {
+            config.threshold_mv = threshold_mv;
+            log!(Module::Sensor, info, "Threshold set to {}mV", threshold_mv);
+        }
+        let Ok(mut sample) = embassy_time::with_timeout(IDLE_CHECK_IN, SAMPLES.receive()).await
+        else {
+            #[cfg(feature = "relay")]
+            relay.stall(clock::monotonic_us());
+            continue;
+        };
+        self_test.push(&sample, &config);
+        filters.apply(&mut sample);
+        let timestamp_us = sample.timestamp_us;
+        match supply.poll(timestamp_us) {
+            Some(supply::Change::Low) => bus::publish(BusEvent::FaultDetected(Fault::LowSupply)),
+            Some(supply::Change::Recovered {
+                start_us,
+                duration_ms,
+            }) => {
+                bus::publish(BusEvent::FaultCleared(Fault::LowSupply));
+                let event = Event::LowSupply { duration_ms };
+                diag::record_fault(start_us / 1000, event);
+                telemetry::publish(Telemetry::Event {
+                    time_ms: start_us / 1000,
+                    event,
+                });
+            }
+            None => {}
+        }
+        let supply_low = supply::is_low();
+        #[cfg(feature = "battery")]
+        if let Some(voltage_mv) = battery::READINGS.try_take() {
+            let stage = battery_monitor.stage();
+            if let Some(event) = battery_monitor.update(timestamp_us / 1000, voltage_mv, &config) {
+                if let Event::Battery { low: true, .. } = event {
+                    log!(Module::Supply, warn, "Battery low: {}mV", voltage_mv);
+                }
+                telemetry::publish(Telemetry::Event {
+                    time_ms: timestamp_us / 1000,
+                    event,
+                });
+            }
+            if battery_monitor.stage() != stage {
+                let stage = battery_monitor.stage();
+                battery::set_stage(stage);
+                bus::publish(BusEvent::PowerStageChanged(stage));
+                telemetry::publish(Telemetry::Event {
+                    time_ms: timestamp_us / 1000,
+                    event: Event::PowerStage { stage, voltage_mv },
+                });
+                if stage == PowerStage::Shutdown {
+                    // Leave the sinks a moment to write the event out
+                    Timer::after(SHUTDOWN_GRACE).await;
+                    sleep::prepare().await;
+                    sleep::shutdown();
+                }
+            }
+        }
+
+        // A reading pinned at a rail is a wiring fault, not a strong pole
+        let fault_event = rail_monitor.update(&sample, &config);
+        if let Some(event) = fault_event {
+            diag::record_fault(timestamp_us / 1000, event);
+        }
+        match fault_event {
+            Some(Event::WiringFault { stuck_high }) => {
+                let fault = if stuck_high {
+                    Fault::WiringStuckHigh
+                } else {
+                    Fault::WiringStuckLow
+                };
+                wiring_fault = Some(fault);
+                bus::publish(BusEvent::FaultDetected(fault));
+            }
+            Some(Event::WiringFaultCleared) => {
+                if let Some(fault) = wiring_fault.take() {
+                    bus::publish(BusEvent::FaultCleared(fault));
+                }
+            }
+            _ => {}
+        }
+        let valid = !rail_monitor.is_faulted();
+        if valid
+            && mode::current() != Mode::Calibrate
+            && let Some(zero_field_mv) = auto_zero.update(&sample, &config)
+        {
+            config.zero_field_mv = zero_field_mv;
+            sleep::set_zero_field_mv(zero_field_mv);
+        }
+
+        let reading = Reading {
+            sample,
+            field_mt: config.field_mt(sample.voltage_mv) - tare::offset_mt(),
+            valid,
+            supply_low,
+        };
+        LATEST.sender().send(reading);
+        #[cfg(feature = "relay")]
+        relay.push(&reading);
+        rules::update(&reading);
+        capture::update(&sample);
+        histogram::update(&sample);
+        goertzel::update(&sample);
+        noise::update(&sample);
+        if let Some(gesture) = valid
+            .then(|| gestures.update(timestamp_us, reading.field_mt))
+            .flatten()
+        {
+            bus::publish(BusEvent::Gesture(gesture));
+        }
+
+        let event = valid
+            .then(|| threshold.update(sample.voltage_mv, &config))
+            .flatten();
+        let mut pulse = false;
+        if let Some(Event::ThresholdCrossed {
+            rising, voltage_mv, ..
+        }) = event
+        {
+            bus::publish(BusEvent::ThresholdCrossed { rising, voltage_mv });
+            pulse = rising;
+            if rising
+                && !replay::active()
+                && let Some(state) = state::STATE.lock().await.as_mut()
+            {
+                state.add_pulse();
+            }
+        }
+
+        if exceptions.pass(&sample, &config) {
+            telemetry::publish(Telemetry::Sample(sample));
+        }
+        for event in [fault_event, event].into_iter().flatten() {
+            telemetry::publish(Telemetry::Event {
+                time_ms: timestamp_us / 1000,
+                event,
+            });
+        }
+
+        let now_us = clock::monotonic_us();
+        modes.step(&reading, pulse, now_us - timestamp_us, &mut config);
+        tamper::persist().await;
+        grading::finish().await;
+        latency.record((now_us - timestamp_us) as u32);
+        if now_us - report_us >= diag::TIMING_REPORT_US {
+            report_us = now_us;
+            if let Some(s) = latency.take() {
+                log!(Module::Timing, info, "Processing latency: {}", s);
+            }
+
+            // All tasks run on the one stack, so this covers all of them
+            let (_, free) = diag::stack_usage();
+            if free < STACK_LOW_BYTES && !core::mem::replace(&mut stack_warned, true) {
+                log!(
+                    Module::Timing,
+                    warn,
+                    "Stack nearly exhausted, {} bytes never used",
+                    free
+                );
+            }
+        }
     }
 }
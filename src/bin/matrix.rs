@@ -0,0 +1,87 @@
+//! MAX7219 LED matrix modules on SPI3 as a bar graph of the field: the bar
+//! grows from the centre to the right for a south pole and to the left for a
+//! north pole. An alternative to the status LED where the strength should be
+//! readable from across a room.
+
+use defmt::Debug2Format;
+use embassy_time::{Duration, Timer};
+use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+use esp_hal::Blocking;
+use esp_hal::gpio::Output;
+use esp_hal::spi::master::Spi;
+use hall_effect::backoff::Backoff;
+use hall_effect::max7219::{self, Max7219, ROWS};
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+
+use crate::led;
+use crate::reading::LATEST;
+use crate::verbosity::log;
+
+/// Modules in the chain; 4 for the common FC-16 boards.
+pub const MODULES: usize = 4;
+
+pub type Matrix =
+    Max7219<ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>, MODULES>;
+
+const REFRESH: Duration = Duration::from_millis(50);
+
+// The whole matrix blinks at this rate while the wiring is faulted
+const FAULT_BLINK_REFRESHES: u32 = 10;
+
+// Consecutive failures before the display is given up on
+const MATRIX_MAX_FAILURES: u32 = 8;
+
+#[embassy_executor::task]
+pub async fn matrix_task(mut matrix: Matrix, config: Config) {
+    if let Err(e) = matrix.init() {
+        log!(Module::Display, warn, "LED matrix not responding: {}", Debug2Format(&e));
+        return;
+    }
+
+    // Full scale is the strongest field the sensor can report
+    let full_scale_mt = config
+        .field_mt(config.min_voltage_mv)
+        .abs()
+        .max(config.field_mt(config.max_voltage_mv).abs());
+    let mut backoff = Backoff::new(MATRIX_MAX_FAILURES);
+    let mut refreshes = 0u32;
+
+    loop {
+        Timer::after(REFRESH).await;
+        refreshes = refreshes.wrapping_add(1);
+        let Some(reading) = LATEST.try_get() else {
+            continue;
+        };
+        if !backoff.ready() {
+            continue;
+        }
+
+        let frame = if reading.valid {
+            max7219::bar::<MODULES>(reading.field_mt / full_scale_mt)
+        } else if (refreshes / FAULT_BLINK_REFRESHES) % 2 == 0 {
+            [[u8::MAX; MODULES]; ROWS]
+        } else {
+            [[0; MODULES]; ROWS]
+        };
+        // Same brightness as the LED, in the matrix's sixteen steps
+        let result = matrix
+            .set_intensity(led::shown_brightness() >> 4)
+            .and_then(|()| matrix.flush(&frame));
+        match result {
+            Ok(()) => backoff.success(),
+            Err(e) if backoff.failure() => {
+                log!(Module::Display, warn, "LED matrix write failed: {}", Debug2Format(&e))
+            }
+            Err(e) => {
+                log!(
+                    Module::Display,
+                    warn,
+                    "LED matrix write failed, display disabled: {}",
+                    Debug2Format(&e)
+                );
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! A stand-in for the sensor in the `mock-sensor` build: every ADC reading
+//! of the sensor pin is replaced by a synthetic waveform, so the LED, logging
+//! and telemetry can be worked on with nothing wired to the board. The
+//! waveform is changed from the console with `mock`.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hall_effect::schema::Config;
+use hall_effect::waveform::{Generator, Waveform};
+
+use crate::{chip, clock};
+
+static GENERATOR: Mutex<RefCell<Option<Generator>>> = Mutex::new(RefCell::new(None));
+
+/// Starts the default waveform around the configured zero-field output.
+pub fn init(config: &Config) {
+    let generator = Generator::new(Waveform::default(), config.zero_field_mv);
+    critical_section::with(|cs| GENERATOR.borrow(cs).replace(Some(generator)));
+}
+
+pub fn waveform() -> Waveform {
+    critical_section::with(|cs| {
+        GENERATOR
+            .borrow(cs)
+            .borrow()
+            .as_ref()
+            .map_or_else(Waveform::default, Generator::waveform)
+    })
+}
+
+pub fn set(waveform: Waveform) {
+    critical_section::with(|cs| {
+        if let Some(generator) = GENERATOR.borrow(cs).borrow_mut().as_mut() {
+            generator.set(waveform);
+        }
+    });
+}
+
+/// The count the ADC would read now, at the chip's resolution; mid-scale
+/// before [`init`].
+pub fn read() -> u16 {
+    let voltage_mv = critical_section::with(|cs| {
+        GENERATOR
+            .borrow(cs)
+            .borrow_mut()
+            .as_mut()
+            .map_or(1650, |generator| {
+                generator.voltage_mv(clock::monotonic_us())
+            })
+    });
+    ((voltage_mv * 4095 / 3300) << (chip::ADC_BITS - 12)) as u16
+}
@@ -0,0 +1,264 @@
+//! The current operating mode and the processing stage's per-mode state.
+//!
+//! Mode changes are requested from the console (or anything else) through
+//! [`request`] and carried out by the processing stage between samples, so
+//! each mode's exit and entry hooks run exactly once. Other subsystems learn
+//! about the change from `BusEvent::ModeChanged`.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use defmt::Display2Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use hall_effect::color::Gradient;
+use hall_effect::mode::{Mode, Tachometer, ZeroCalibration};
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+
+use crate::bus::{self, BusEvent};
+use crate::contact;
+use crate::counting;
+use crate::current;
+use crate::drift;
+use crate::fixture;
+use crate::grading;
+use crate::latency;
+use crate::led;
+use crate::levitate;
+use crate::lockin;
+use crate::palette;
+use crate::pas;
+use crate::power;
+use crate::reading::Reading;
+use crate::sleep;
+use crate::spectrum;
+use crate::speed;
+use crate::tamper;
+use crate::units;
+use crate::verbosity::log;
+
+// How often the tachometer reading is logged
+const RPM_REPORT_US: u64 = 1_000_000;
+
+static CURRENT: Mutex<Cell<Mode>> = Mutex::new(Cell::new(Mode::Measure));
+static REQUEST: Signal<CriticalSectionRawMutex, Mode> = Signal::new();
+static RPM: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+pub fn current() -> Mode {
+    critical_section::with(|cs| CURRENT.borrow(cs).get())
+}
+
+/// The tachometer reading as last reported, for displays and the console.
+pub fn rpm() -> u32 {
+    critical_section::with(|cs| RPM.borrow(cs).get())
+}
+
+/// Asks the processing stage to switch to `mode` before the next sample.
+pub fn request(mode: Mode) {
+    REQUEST.signal(mode);
+}
+
+pub struct ModeState {
+    mode: Mode,
+    calibration: ZeroCalibration,
+    tachometer: Tachometer,
+    report_us: u64,
+    /// For the colour logged in measure mode, as the LED shows it.
+    gradient: Gradient,
+    spectrum: spectrum::Collector,
+    current: current::Meter,
+    power: power::Meter,
+    speed: speed::Loop,
+    assist: pas::Assist,
+    contact: contact::Sensor,
+    tamper: tamper::Guard,
+    grader: grading::Grader,
+}
+
+impl ModeState {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            mode: Mode::Measure,
+            calibration: ZeroCalibration::new(),
+            tachometer: Tachometer::new(),
+            report_us: 0,
+            gradient: Gradient::new(config, palette::current()),
+            spectrum: spectrum::Collector::new(),
+            current: current::Meter::new(),
+            power: power::Meter::new(),
+            speed: speed::Loop::new(),
+            assist: pas::Assist::new(),
+            contact: contact::Sensor::new(),
+            tamper: tamper::Guard::new(),
+            grader: grading::Grader::new(),
+        }
+    }
+
+    /// Carries out a pending request, if any.
+    pub fn poll_request(&mut self) {
+        if let Some(mode) = REQUEST.try_take() {
+            self.switch(mode);
+        }
+    }
+
+    fn switch(&mut self, to: Mode) {
+        let from = self.mode;
+        if from == to {
+            return;
+        }
+        self.exit();
+        self.mode = to;
+        self.enter();
+        critical_section::with(|cs| CURRENT.borrow(cs).set(to));
+        bus::publish(BusEvent::ModeChanged { from, to });
+    }
+
+    fn enter(&mut self) {
+        match self.mode {
+            Mode::Calibrate => {
+                self.calibration = ZeroCalibration::new();
+                log!(Module::Sensor, info, "Calibrating, keep magnets away");
+            }
+            Mode::Tachometer => self.tachometer = Tachometer::new(),
+            Mode::Spectrum => self.spectrum = spectrum::Collector::new(),
+            Mode::Drift => drift::start(),
+            Mode::LockIn => lockin::start(),
+            Mode::Levitate => levitate::start(),
+            Mode::Current => log!(Module::Sensor, info, "Current: {}", current::scale()),
+            Mode::Power => power::start(),
+            Mode::Pas => pas::start(),
+            Mode::Contact => log!(Module::Sensor, info, "Contact: {}", contact::settings()),
+            Mode::Tamper => tamper::start(),
+            Mode::Count => counting::start(),
+            Mode::Fixture => log!(Module::Sensor, info, "Fixture: {}", fixture::limits()),
+            Mode::Grade => grading::start(),
+            Mode::Latency => latency::start(),
+            Mode::Measure | Mode::Diagnostics => {}
+        }
+    }
+
+    fn exit(&mut self) {
+        if self.mode == Mode::Calibrate && !self.calibration.is_done() {
+            log!(Module::Sensor, warn, "Calibration abandoned");
+        }
+        if self.mode == Mode::LockIn {
+            lockin::stop();
+        }
+        if self.mode == Mode::Tachometer {
+            self.speed.stop();
+        }
+        if matches!(self.mode, Mode::Current | Mode::Power) {
+            self.current.stop();
+        }
+        if self.mode == Mode::Power {
+            self.power.stop();
+        }
+        if self.mode == Mode::Pas {
+            self.assist.stop();
+        }
+        if self.mode == Mode::Contact {
+            self.contact.stop();
+        }
+        if self.mode == Mode::Tamper {
+            self.tamper.stop();
+        }
+        if self.mode == Mode::Fixture {
+            fixture::stop();
+        }
+        if self.mode == Mode::Grade {
+            self.grader.stop();
+        }
+    }
+
+    /// Per-mode handling of one reading. `pulse` is set on a rising
+    /// threshold crossing. Calibration updates `config` in place.
+    pub fn step(&mut self, reading: &Reading, pulse: bool, latency_us: u64, config: &mut Config) {
+        let sample = &reading.sample;
+        match self.mode {
+            Mode::Measure if reading.valid => {
+                if self.gradient.palette() != palette::current() {
+                    self.gradient = Gradient::new(config, palette::current());
+                }
+                let color = led::color_for(reading, &self.gradient);
+                log!(
+                    Module::Sample,
+                    info,
+                    "Reading: {}, LED color: R={}, G={}, B={}",
+                    Display2Format(&units::of(reading)),
+                    color.r,
+                    color.g,
+                    color.b
+                );
+            }
+            Mode::Measure => log!(
+                Module::Sample,
+                info,
+                "Voltage: invalid (wiring fault), raw={}",
+                sample.raw
+            ),
+            Mode::Calibrate => {
+                if let Some(zero_field_mv) = reading
+                    .valid
+                    .then(|| self.calibration.push(sample.voltage_mv))
+                    .flatten()
+                {
+                    config.zero_field_mv = zero_field_mv;
+                    sleep::set_zero_field_mv(zero_field_mv);
+                    bus::publish(BusEvent::CalibrationDone { zero_field_mv });
+                    self.switch(Mode::Measure);
+                }
+            }
+            Mode::Tachometer => {
+                if pulse {
+                    self.tachometer.pulse(sample.timestamp_us);
+                }
+                let rpm = self.tachometer.rpm_bound(sample.timestamp_us);
+                self.speed.push(sample.timestamp_us, rpm);
+                if sample.timestamp_us - self.report_us >= RPM_REPORT_US {
+                    self.report_us = sample.timestamp_us;
+                    let rpm = self.tachometer.rpm(sample.timestamp_us);
+                    critical_section::with(|cs| RPM.borrow(cs).set(rpm));
+                    log!(Module::Sample, info, "RPM: {}", rpm);
+                }
+            }
+            Mode::Diagnostics => log!(
+                Module::Sample,
+                info,
+                "raw={}, {}mV, latency={}us",
+                sample.raw,
+                sample.voltage_mv,
+                latency_us
+            ),
+            Mode::Spectrum => self.spectrum.push(sample),
+            Mode::Drift if reading.valid => drift::push(sample.timestamp_us, reading.field_mt),
+            Mode::Drift => {}
+            Mode::LockIn if reading.valid => lockin::push(sample.timestamp_us, reading.field_mt),
+            Mode::LockIn => {}
+            // The sampler runs the loop; these are the readings it passes on
+            Mode::Levitate => {}
+            Mode::Current if reading.valid => {
+                self.current.push(sample);
+            }
+            Mode::Power if reading.valid => {
+                let amps = self.current.push(sample);
+                self.power.push(sample.timestamp_us, amps);
+            }
+            Mode::Current | Mode::Power => {}
+            Mode::Pas => self.assist.push(sample.timestamp_us, pulse),
+            Mode::Contact if reading.valid => {
+                self.contact.push(sample.timestamp_us, reading.field_mt)
+            }
+            Mode::Contact => {}
+            // Invalid readings count too, as a cut wire
+            Mode::Tamper => self.tamper.push(reading),
+            Mode::Count if pulse => counting::pass(sample.timestamp_us),
+            Mode::Count => {}
+            Mode::Fixture => fixture::push(reading),
+            Mode::Grade if reading.valid => self.grader.push(reading.field_mt),
+            Mode::Grade => {}
+            // The LED task does the timing, as it shows each reading
+            Mode::Latency => {}
+        }
+    }
+}
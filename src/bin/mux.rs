@@ -0,0 +1,35 @@
+//! Sensor multiplexer: with the `mux` feature, a CD74HC4067 puts one of up
+//! to 16 sensors on the sensor's ADC pin, chosen by its select lines on
+//! GPIO10 (S0) to GPIO13 (S3). Every mode reads whichever sensor is
+//! chosen, channel 0 from boot or another with `mux` on the console;
+//! `fixture scan` steps through them to test a magnet array in one pass.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::gpio::{Level, Output};
+
+static SELECT: Mutex<RefCell<Option<[Output<'static>; 4]>>> = Mutex::new(RefCell::new(None));
+static CHANNEL: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+
+/// Takes the select lines, S0 first, at boot.
+pub fn init(select: [Output<'static>; 4]) {
+    critical_section::with(|cs| SELECT.replace(cs, Some(select)));
+    self::select(0);
+}
+
+pub fn channel() -> u8 {
+    critical_section::with(|cs| CHANNEL.borrow(cs).get())
+}
+
+/// Puts `channel`'s sensor on the ADC, from the next sample.
+pub fn select(channel: u8) {
+    critical_section::with(|cs| {
+        if let Some(lines) = SELECT.borrow_ref_mut(cs).as_mut() {
+            for (bit, line) in lines.iter_mut().enumerate() {
+                line.set_level(Level::from(channel >> bit & 1 != 0));
+            }
+        }
+        CHANNEL.borrow(cs).set(channel);
+    });
+}
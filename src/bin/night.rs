@@ -0,0 +1,52 @@
+//! Night mode, see `hall_effect::night`: set with `night` on the console
+//! and kept in the `state` partition. Through the window, by the wall
+//! clock, it limits the LED's brightness; the MAX7219 and TM1637 follow
+//! it, down to their dimmest, as they cannot be turned off by brightness.
+//! With the clock not set it does nothing. Fault blinks stay at full
+//! brightness.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::night::{Settings, TimeOfDay};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next colour shown, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_night_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Night mode not saved: {}", e);
+    }
+}
+
+/// The time of day now, once the wall clock is set.
+fn now() -> Option<TimeOfDay> {
+    clock::unix_us(clock::monotonic_us()).map(|unix_us| TimeOfDay::of(unix_us / 1_000_000))
+}
+
+/// Whether night mode holds now.
+pub fn is_active() -> bool {
+    now().is_some_and(|time| settings().covers(time))
+}
+
+/// `brightness`, out of 255, as limited now.
+pub fn dim(brightness: u8) -> u8 {
+    now().map_or(brightness, |time| settings().dim(brightness, time))
+}
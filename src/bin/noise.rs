@@ -0,0 +1,55 @@
+//! The `noise` command's measurement: raw readings gathered by the
+//! processing stage for a set time, handed to the console when done.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use hall_effect::noise::Noise;
+use hall_effect::schema::Sample;
+
+struct Measurement {
+    noise: Noise,
+    duration_us: u64,
+    /// From the first reading, so the time spent waiting for it is not
+    /// counted.
+    end_us: Option<u64>,
+}
+
+static MEASUREMENT: Mutex<RefCell<Option<Measurement>>> = Mutex::new(RefCell::new(None));
+pub static DONE: Signal<CriticalSectionRawMutex, Noise> = Signal::new();
+
+/// Starts gathering readings for `duration_s`; the result arrives on
+/// [`DONE`]. Replaces a measurement in progress.
+pub fn start(duration_s: u32) {
+    DONE.reset();
+    critical_section::with(|cs| {
+        MEASUREMENT.replace(
+            cs,
+            Some(Measurement {
+                noise: Noise::new(),
+                duration_us: duration_s as u64 * 1_000_000,
+                end_us: None,
+            }),
+        )
+    });
+}
+
+/// Hands the measurement a sample, from the processing stage. The raw
+/// count is used, ahead of the filter chain, as the ADC delivered it.
+pub fn update(sample: &Sample) {
+    critical_section::with(|cs| {
+        let mut measurement = MEASUREMENT.borrow_ref_mut(cs);
+        let Some(m) = measurement.as_mut() else {
+            return;
+        };
+        let end_us = *m.end_us.get_or_insert(sample.timestamp_us + m.duration_us);
+        if sample.timestamp_us >= end_us {
+            DONE.signal(m.noise);
+            *measurement = None;
+        } else {
+            m.noise.push(sample.raw);
+        }
+    });
+}
@@ -0,0 +1,179 @@
+//! SSD1306 OLED readout: the reading in the units set with the field's
+//! polarity, the range seen since boot or a scheduled reset, and a rolling
+//! graph of the field over the last 25 seconds.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
+use esp_hal::Blocking;
+use esp_hal::i2c::master::{Error as I2cError, I2c};
+use hall_effect::backoff::Backoff;
+use hall_effect::schema::Config;
+use hall_effect::ssd1306::{Frame, PAGES, Ssd1306, WIDTH};
+use hall_effect::units::Range;
+use hall_effect::verbosity::Module;
+use heapless::{Deque, String};
+
+use crate::clock;
+use crate::reading::{LATEST, Reading};
+use crate::schedule;
+use crate::units;
+use crate::verbosity::log;
+
+pub type Display = Ssd1306<I2c<'static, Blocking>>;
+
+// Consecutive failures before the display is given up on
+const OLED_MAX_FAILURES: u32 = 8;
+
+// One graph column per refresh
+const REFRESH_US: u64 = 200_000;
+
+// Between pages sent, so the tasks sharing the executor keep running
+const PAGE_PAUSE: Duration = Duration::from_millis(1);
+
+// Weaker fields are shown without a polarity
+const POLARITY_MIN_MT: f32 = 0.5;
+
+// The graph fills the lower half of the screen
+const GRAPH_TOP: usize = PAGES * 8 / 2;
+const GRAPH_HALF: usize = PAGES * 8 / 4 - 1;
+const GRAPH_ZERO: usize = GRAPH_TOP + GRAPH_HALF + 1;
+
+#[embassy_executor::task]
+pub async fn oled_task(mut display: Display, config: Config) {
+    let Some(mut latest) = LATEST.receiver() else {
+        log!(Module::Display, warn, "OLED has no reading receiver");
+        return;
+    };
+    // The controller needs a moment after power-up before it takes commands
+    Timer::after_millis(100).await;
+    if let Err(e) = display.init() {
+        log!(Module::Display, warn, "OLED not responding: {}", e);
+        return;
+    }
+
+    // Full scale of the graph is the strongest field the sensor can report
+    let full_scale_mt = config
+        .field_mt(config.min_voltage_mv)
+        .abs()
+        .max(config.field_mt(config.max_voltage_mv).abs());
+    let mut frame = Frame::new();
+    let mut history: Deque<f32, WIDTH> = Deque::new();
+    let mut range: Option<Range> = None;
+    let mut range_resets = schedule::range_resets();
+    // Strongest field since the last graph column, so brief peaks show
+    let mut peak_mt: Option<f32> = None;
+    let mut backoff = Backoff::new(OLED_MAX_FAILURES);
+    let mut refresh_us = clock::monotonic_us();
+
+    loop {
+        let reading = latest.changed().await;
+        let resets = schedule::range_resets();
+        if resets != range_resets {
+            range_resets = resets;
+            range = None;
+        }
+        if reading.valid {
+            let field_mt = reading.field_mt;
+            range = Some(Range::widen(range, units::of(&reading)));
+            if peak_mt.is_none_or(|peak| field_mt.abs() > peak.abs()) {
+                peak_mt = Some(field_mt);
+            }
+        }
+
+        let now_us = clock::monotonic_us();
+        if now_us - refresh_us < REFRESH_US {
+            continue;
+        }
+        refresh_us = now_us;
+        if history.is_full() {
+            history.pop_front();
+        }
+        let _ = history.push_back(peak_mt.take().unwrap_or(0.0));
+
+        if !backoff.ready() {
+            continue;
+        }
+        draw(&mut frame, &reading, range, &history, full_scale_mt);
+        match flush(&mut display, &frame).await {
+            Ok(()) => backoff.success(),
+            Err(e) if backoff.failure() => log!(Module::Display, warn, "OLED write failed: {}", e),
+            Err(e) => {
+                log!(
+                    Module::Display,
+                    warn,
+                    "OLED write failed, display disabled: {}",
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Sends the pages of `frame` that changed, pausing after each so that no
+/// other task waits on more than one page's transfer.
+async fn flush(display: &mut Display, frame: &Frame) -> Result<(), I2cError> {
+    for page in 0..PAGES {
+        if display.flush_page(frame, page)? {
+            Timer::after(PAGE_PAUSE).await;
+        }
+    }
+    Ok(())
+}
+
+fn draw(
+    frame: &mut Frame,
+    reading: &Reading,
+    range: Option<Range>,
+    history: &Deque<f32, WIDTH>,
+    full_scale_mt: f32,
+) {
+    frame.clear();
+
+    let mut text: String<24> = String::new();
+    if reading.valid {
+        let field_mt = reading.field_mt;
+        let shown = units::of(reading);
+        let _ = write!(text, "{}", shown.number());
+        // Small, beside the number, so even counts fit
+        let symbol_x = Frame::text_width(&text, 2) + 2;
+        frame.text(symbol_x, 7, shown.unit.symbol(), 1);
+        let polarity = if field_mt <= -POLARITY_MIN_MT {
+            "N"
+        } else if field_mt >= POLARITY_MIN_MT {
+            "S"
+        } else {
+            ""
+        };
+        frame.text(WIDTH - Frame::text_width(polarity, 2), 0, polarity, 2);
+    } else {
+        let _ = text.push_str("FAULT");
+    }
+    frame.text(0, 0, &text, 2);
+
+    if let Some(range) = range {
+        text.clear();
+        let _ = write!(
+            text,
+            "min {} max {}",
+            range.min.number(),
+            range.max.number()
+        );
+        frame.text(0, 18, &text, 1);
+    }
+
+    // Newest at the right, drawn as bars from the zero line
+    frame.hline(0, WIDTH - 1, GRAPH_ZERO);
+    let offset = WIDTH - history.len();
+    for (i, field_mt) in history.iter().enumerate() {
+        let height = ((field_mt.abs() / full_scale_mt) * GRAPH_HALF as f32) as usize;
+        let height = height.min(GRAPH_HALF);
+        let y = if *field_mt >= 0.0 {
+            GRAPH_ZERO - height
+        } else {
+            GRAPH_ZERO + height
+        };
+        frame.vline(offset + i, GRAPH_ZERO, y);
+    }
+}
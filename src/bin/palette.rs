@@ -0,0 +1,47 @@
+//! The palette the LED shows the field in, see `hall_effect::palette`;
+//! set with `palette` and kept in the `state` partition. The LED and the
+//! colour logged in measure mode change to it with the next reading.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_time::Duration;
+use hall_effect::palette::{self, BLINK_MS, Palette};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static PALETTE: Mutex<Cell<Palette>> = Mutex::new(Cell::new(Palette::RedBlue));
+
+pub fn current() -> Palette {
+    critical_section::with(|cs| PALETTE.borrow(cs).get())
+}
+
+/// Takes on the palette saved in flash, at boot.
+pub fn restore(palette: Palette) {
+    critical_section::with(|cs| PALETTE.borrow(cs).set(palette));
+}
+
+/// Changes the palette and saves it.
+pub async fn configure(palette: Palette) {
+    restore(palette);
+    log!(Module::Led, info, "Palette {}", palette.name());
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_palette(&palette).await
+    {
+        log!(Module::Storage, warn, "Palette not saved: {}", e);
+    }
+}
+
+/// Whether a blinking colour is lit now.
+pub fn is_lit() -> bool {
+    palette::is_lit(clock::monotonic_us() / 1000)
+}
+
+/// The time until a blinking colour next turns on or off.
+pub fn until_blink() -> Duration {
+    let half_ms = BLINK_MS / 2;
+    Duration::from_millis(half_ms - clock::monotonic_us() / 1000 % half_ms)
+}
@@ -0,0 +1,140 @@
+//! Panic handler that turns the status LED to the fault colour and halts.
+//!
+//! The message is kept in RTC RAM, so once the watchdog has reset the chip it
+//! can be read back with the `last-panic` command.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use esp_hal::peripherals::RMT;
+use esp_hal::rmt::{PulseCode, Rmt, TxChannelCreator};
+use esp_hal::time::Rate;
+use heapless::String;
+
+use crate::{board, chip};
+use crate::led::{self, BUFFER_SIZE, FAULT_COLOR};
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+const MAGIC: u32 = 0x504e_4943;
+
+pub const MESSAGE_LEN: usize = 256;
+
+/// Persistent RAM is not cleared by a power cycle either, so the record is
+/// only trusted if the magic and checksum match.
+#[repr(C)]
+struct PanicRecord {
+    magic: u32,
+    crc: u32,
+    len: u32,
+    message: [u8; MESSAGE_LEN],
+}
+
+unsafe impl esp_hal::Persistable for PanicRecord {}
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut LAST_PANIC: PanicRecord = PanicRecord {
+    magic: 0,
+    crc: 0,
+    len: 0,
+    message: [0; MESSAGE_LEN],
+};
+
+/// Truncating writer into the record's message buffer.
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+fn with_record<R>(f: impl FnOnce(&mut PanicRecord) -> R) -> R {
+    let record = &raw mut LAST_PANIC;
+    critical_section::with(|_| f(unsafe { &mut *record }))
+}
+
+fn valid(record: &PanicRecord) -> bool {
+    record.magic == MAGIC
+        && (record.len as usize) <= MESSAGE_LEN
+        && CRC32.checksum(&record.message[..record.len as usize]) == record.crc
+}
+
+/// Whether a panic message from a previous boot is waiting to be read.
+pub fn pending() -> bool {
+    with_record(|record| valid(record))
+}
+
+/// Returns the stored panic message, if any, and clears it.
+pub fn take() -> Option<String<MESSAGE_LEN>> {
+    with_record(|record| {
+        if !valid(record) {
+            return None;
+        }
+        record.magic = 0;
+        let message = &record.message[..record.len as usize];
+        // Truncation may have split a UTF-8 sequence
+        let message = match core::str::from_utf8(message) {
+            Ok(s) => s,
+            Err(e) => core::str::from_utf8(&message[..e.valid_up_to()]).unwrap_or_default(),
+        };
+        String::try_from(message).ok()
+    })
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    defmt::error!("{}", defmt::Display2Format(info));
+
+    with_record(|record| {
+        let mut cursor = Cursor {
+            buf: &mut record.message,
+            len: 0,
+        };
+        // The message includes the panic location
+        let _ = write!(cursor, "{}", info);
+        let len = cursor.len;
+        record.len = len as u32;
+        record.crc = CRC32.checksum(&record.message[..len]);
+        record.magic = MAGIC;
+    });
+
+    show_fault();
+
+    // Halt; the watchdog resets the chip if it has been started
+    critical_section::with(|_| {
+        loop {
+            core::hint::spin_loop();
+        }
+    })
+}
+
+/// Drives the LED through a fresh RMT driver, since the one owned by the
+/// LED task cannot be reached from here.
+fn show_fault() {
+    // SAFETY: nothing else runs after a panic
+    let (rmt, pin) = unsafe { (RMT::steal(), board::LedGpio::steal()) };
+    let Ok(rmt) = Rmt::new(rmt, Rate::from_mhz(chip::RMT_CLOCK_MHZ)) else {
+        return;
+    };
+    let Ok(channel) = rmt.channel0.configure_tx(pin, led::led_tx_config()) else {
+        return;
+    };
+
+    let mut rmt_buffer = [PulseCode::default(); BUFFER_SIZE];
+    led::ws2812_encode(
+        FAULT_COLOR,
+        led::led_pulses_for_clock(chip::RMT_CLOCK_MHZ),
+        &mut rmt_buffer,
+    );
+    if let Ok(transaction) = channel.transmit(&rmt_buffer) {
+        let _ = transaction.wait();
+    }
+}
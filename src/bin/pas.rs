@@ -0,0 +1,237 @@
+//! Pedal-assist mode: with the `pas` feature, the second sensor of the
+//! crank's pair is a digital hall on GPIO40, pulled up for an
+//! open-collector output, read by the sampler with each reading. The
+//! throttle level goes out as LEDC PWM on GPIO39, through an RC filter to
+//! the controller's throttle input. The magnets and the assist curve are
+//! set with `pas` on the console and kept in the `state` partition.
+
+use core::cell::Cell;
+#[cfg(feature = "pas")]
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+#[cfg(feature = "pas")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "pas")]
+use embassy_sync::signal::Signal;
+#[cfg(feature = "pas")]
+use esp_hal::gpio::{DriveMode, Input, InputConfig, Pull};
+#[cfg(feature = "pas")]
+use esp_hal::ledc::channel::{self, Channel, ChannelHW, ChannelIFace};
+#[cfg(feature = "pas")]
+use esp_hal::ledc::timer::{self, TimerIFace};
+#[cfg(feature = "pas")]
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+#[cfg(feature = "pas")]
+use esp_hal::peripherals::{GPIO39, GPIO40, LEDC};
+#[cfg(feature = "pas")]
+use esp_hal::time::Rate;
+use hall_effect::mode::Mode;
+#[cfg(feature = "pas")]
+use hall_effect::pas;
+use hall_effect::pas::{Cadence, Settings};
+use hall_effect::verbosity::Module;
+#[cfg(feature = "pas")]
+use static_cell::StaticCell;
+
+use crate::mode;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+// How often the cadence is logged
+const REPORT_US: u64 = 1_000_000;
+// The PWM's high level, which the filter averages down
+#[cfg(feature = "pas")]
+const OUTPUT_MV: f32 = 3300.0;
+#[cfg(feature = "pas")]
+const PWM_FREQUENCY: Rate = Rate::from_khz(20);
+#[cfg(feature = "pas")]
+const DUTY_RANGE: u32 = 1 << 10;
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    /// Negative pedalling backward.
+    pub cadence_rpm: f32,
+    /// From 0 to 1.
+    pub level: f32,
+}
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static STATUS: Mutex<Cell<Option<Status>>> = Mutex::new(Cell::new(None));
+#[cfg(feature = "pas")]
+static SECOND: Mutex<RefCell<Option<Input<'static>>>> = Mutex::new(RefCell::new(None));
+#[cfg(feature = "pas")]
+static SECOND_HIGH: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+/// The time of the reading the second sensor was first seen high at.
+static SECOND_ROSE_US: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+/// The throttle's next duty, for [`throttle_task`], which owns the
+/// channel.
+#[cfg(feature = "pas")]
+static THROTTLE: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next reading, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_pas_settings(&settings).await
+    {
+        log!(
+            Module::Storage,
+            warn,
+            "Pedal assist settings not saved: {}",
+            e
+        );
+    }
+}
+
+/// The cadence and assist, in pedal-assist mode.
+pub fn status() -> Option<Status> {
+    critical_section::with(|cs| STATUS.borrow(cs).get())
+}
+
+/// Sets up the second sensor and the throttle output, at idle, at boot.
+#[cfg(feature = "pas")]
+pub fn init(
+    ledc: LEDC<'static>,
+    throttle: GPIO39<'static>,
+    second: GPIO40<'static>,
+) -> Option<Channel<'static, LowSpeed>> {
+    static TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+    let second = Input::new(second, InputConfig::default().with_pull(Pull::Up));
+    critical_section::with(|cs| SECOND.replace(cs, Some(second)));
+
+    let mut ledc = Ledc::new(ledc);
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let timer = TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+    if let Err(e) = timer.configure(timer::config::Config {
+        duty: timer::config::Duty::Duty10Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: PWM_FREQUENCY,
+    }) {
+        log!(Module::Sensor, warn, "Throttle PWM unavailable: {}", e);
+        return None;
+    }
+    let mut channel = ledc.channel(channel::Number::Channel0, throttle);
+    if let Err(e) = channel.configure(channel::config::Config {
+        timer: &*timer,
+        duty_pct: 0,
+        drive_mode: DriveMode::PushPull,
+    }) {
+        log!(Module::Sensor, warn, "Throttle PWM unavailable: {}", e);
+        return None;
+    }
+    set_level(0.0);
+    Some(channel)
+}
+
+#[cfg(feature = "pas")]
+#[embassy_executor::task]
+pub async fn throttle_task(throttle: Channel<'static, LowSpeed>) {
+    loop {
+        throttle.set_duty_hw(THROTTLE.wait().await);
+    }
+}
+
+/// Reads the second sensor, with the sensor sample taken at
+/// `timestamp_us`.
+#[cfg(feature = "pas")]
+pub fn sample(timestamp_us: u64) {
+    critical_section::with(|cs| {
+        let Some(high) = SECOND.borrow_ref(cs).as_ref().map(Input::is_high) else {
+            return;
+        };
+        let was_high = SECOND_HIGH.borrow(cs).replace(high);
+        if high && !was_high {
+            SECOND_ROSE_US.borrow(cs).set(Some(timestamp_us));
+        }
+    });
+}
+
+#[cfg(feature = "pas")]
+fn set_level(level: f32) {
+    let duty = pas::throttle_mv(level) / OUTPUT_MV;
+    THROTTLE.signal((duty.clamp(0.0, 1.0) * DUTY_RANGE as f32) as u32);
+}
+
+#[cfg(not(feature = "pas"))]
+fn set_level(_level: f32) {}
+
+/// On entering pedal-assist mode.
+pub fn start() {
+    if cfg!(feature = "pas") {
+        log!(
+            Module::Sensor,
+            info,
+            "Pedal assist, {} magnets",
+            settings().magnets
+        );
+    } else {
+        log!(
+            Module::Sensor,
+            warn,
+            "Pedal assist needs the `pas` feature's second sensor and throttle output"
+        );
+        mode::request(Mode::Measure);
+    }
+}
+
+/// The processing stage's half, for pedal-assist mode.
+pub struct Assist {
+    cadence: Cadence,
+    report_us: u64,
+}
+
+impl Assist {
+    pub const fn new() -> Self {
+        Self {
+            cadence: Cadence::new(),
+            report_us: 0,
+        }
+    }
+
+    /// Takes a reading, with `pulse` set on the first sensor's rising
+    /// crossing.
+    pub fn push(&mut self, timestamp_us: u64, pulse: bool) {
+        if pulse {
+            let second_rose_us = critical_section::with(|cs| SECOND_ROSE_US.borrow(cs).get());
+            self.cadence.pulse(timestamp_us, second_rose_us);
+        }
+        let settings = settings();
+        let cadence_rpm = self.cadence.rpm(timestamp_us, settings.magnets);
+        let level = settings.curve.level(cadence_rpm);
+        set_level(level);
+        critical_section::with(|cs| {
+            STATUS.borrow(cs).set(Some(Status { cadence_rpm, level }));
+        });
+        if timestamp_us - self.report_us >= REPORT_US {
+            self.report_us = timestamp_us;
+            log!(
+                Module::Sample,
+                info,
+                "Cadence: {}rpm, assist {}%",
+                cadence_rpm,
+                level * 100.0
+            );
+        }
+    }
+
+    /// On leaving pedal-assist mode: the throttle goes back to idle.
+    pub fn stop(&mut self) {
+        self.cadence = Cadence::new();
+        set_level(0.0);
+        critical_section::with(|cs| {
+            STATUS.borrow(cs).set(None);
+            SECOND_ROSE_US.borrow(cs).set(None);
+        });
+    }
+}
@@ -0,0 +1,200 @@
+//! Power mode: with the `power-meter` feature, the voltage feeding the load
+//! is read through a divider on GPIO2 (ADC1 channel 1, or 2 on the C3 and
+//! C6) after each sensor sample, and the processing stage multiplies it by
+//! the current mode's reading. The energy total is kept in the `state`
+//! partition with its other checkpointed counts, and reported to telemetry
+//! every minute.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+#[cfg(feature = "power-meter")]
+use esp_hal::analog::adc::AdcPin;
+#[cfg(feature = "power-meter")]
+use esp_hal::peripherals::{ADC1, GPIO2};
+use hall_effect::mode::Mode;
+use hall_effect::power::{self, Totalizer};
+use hall_effect::schema::Event;
+use hall_effect::verbosity::Module;
+
+#[cfg(feature = "power-meter")]
+use crate::chip;
+use crate::mode;
+use crate::replay;
+#[cfg(feature = "power-meter")]
+use crate::sensor::{self, SensorAdc};
+use crate::state::STATE;
+use crate::telemetry::{self, Telemetry};
+use crate::verbosity::log;
+
+#[cfg(feature = "power-meter")]
+pub type VoltagePin = AdcPin<GPIO2<'static>, ADC1<'static>, chip::AdcCal<ADC1<'static>>>;
+
+// How often the power is logged
+const REPORT_US: u64 = 1_000_000;
+
+#[cfg(feature = "power-meter")]
+static PIN: Mutex<RefCell<Option<VoltagePin>>> = Mutex::new(RefCell::new(None));
+/// At the divider's output.
+static VOLTAGE_MV: Mutex<Cell<Option<f32>>> = Mutex::new(Cell::new(None));
+static RATIO: Mutex<Cell<f32>> = Mutex::new(Cell::new(power::DEFAULT_RATIO));
+static TOTAL: Mutex<RefCell<Totalizer>> = Mutex::new(RefCell::new(Totalizer::new(0)));
+static WATTS: Mutex<Cell<Option<f32>>> = Mutex::new(Cell::new(None));
+
+/// Hands the pin to the sampler.
+#[cfg(feature = "power-meter")]
+pub fn init(pin: VoltagePin) {
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
+
+/// Reads the divider on the sampler's ADC.
+#[cfg(feature = "power-meter")]
+pub async fn sample(adc: &mut SensorAdc) {
+    let Some(mut pin) = critical_section::with(|cs| PIN.borrow_ref_mut(cs).take()) else {
+        return;
+    };
+    match sensor::read_sample(|| adc.read_oneshot(&mut pin)).await {
+        Ok(sample) => {
+            critical_section::with(|cs| {
+                VOLTAGE_MV.borrow(cs).set(Some(sample.voltage_mv as f32));
+            });
+        }
+        Err(e) => log!(Module::Sensor, warn, "Voltage reading skipped: {}", e),
+    }
+    critical_section::with(|cs| PIN.borrow_ref_mut(cs).replace(pin));
+}
+
+/// The divider's output, as last read.
+pub fn voltage_mv() -> Option<f32> {
+    critical_section::with(|cs| VOLTAGE_MV.borrow(cs).get())
+}
+
+pub fn ratio() -> f32 {
+    critical_section::with(|cs| RATIO.borrow(cs).get())
+}
+
+/// Takes on the ratio saved in flash, at boot.
+pub fn restore_ratio(ratio: f32) {
+    critical_section::with(|cs| RATIO.borrow(cs).set(ratio));
+}
+
+/// Changes the divider ratio and saves it.
+pub async fn set_ratio(ratio: f32) {
+    restore_ratio(ratio);
+    log!(Module::Sensor, info, "Voltage divider ratio: {}", ratio);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_voltage_ratio(ratio).await
+    {
+        log!(
+            Module::Storage,
+            warn,
+            "Voltage divider ratio not saved: {}",
+            e
+        );
+    }
+}
+
+/// The voltage feeding the load, as last read.
+pub fn volts() -> Option<f32> {
+    voltage_mv().map(|voltage_mv| power::volts(voltage_mv, ratio()))
+}
+
+/// The last power measured, in power mode.
+pub fn watts() -> Option<f32> {
+    critical_section::with(|cs| WATTS.borrow(cs).get())
+}
+
+/// Takes on the total saved in flash, at boot.
+pub fn restore_energy(millijoules: u64) {
+    critical_section::with(|cs| TOTAL.replace(cs, Totalizer::new(millijoules)));
+}
+
+/// The energy total, for the state checkpoint.
+pub fn energy_mj() -> u64 {
+    critical_section::with(|cs| TOTAL.borrow_ref(cs).millijoules())
+}
+
+pub fn energy_wh() -> f32 {
+    critical_section::with(|cs| TOTAL.borrow_ref(cs).energy_wh())
+}
+
+/// On entering power mode.
+pub fn start() {
+    if cfg!(feature = "power-meter") {
+        log!(
+            Module::Sensor,
+            info,
+            "Power metering, divider ratio {}",
+            ratio()
+        );
+    } else {
+        log!(
+            Module::Sensor,
+            warn,
+            "Power metering needs the `power-meter` feature's voltage divider"
+        );
+        mode::request(Mode::Measure);
+    }
+}
+
+/// The processing stage's half, for power mode.
+pub struct Meter {
+    last_us: Option<u64>,
+    report_us: u64,
+    event_ms: u64,
+}
+
+impl Meter {
+    pub const fn new() -> Self {
+        Self {
+            last_us: None,
+            report_us: 0,
+            event_ms: 0,
+        }
+    }
+
+    /// Takes the current measured from a valid reading.
+    pub fn push(&mut self, timestamp_us: u64, amps: f32) {
+        let Some(volts) = volts() else {
+            return;
+        };
+        let watts = amps * volts;
+        let dt_s = self.last_us.replace(timestamp_us).map_or(0.0, |last_us| {
+            timestamp_us.saturating_sub(last_us) as f32 / 1e6
+        });
+        let energy_wh = critical_section::with(|cs| {
+            WATTS.borrow(cs).set(Some(watts));
+            let mut total = TOTAL.borrow_ref_mut(cs);
+            // Replayed readings were counted when they were taken
+            if !replay::active() {
+                total.add(watts, dt_s);
+            }
+            total.energy_wh()
+        });
+        if timestamp_us - self.report_us >= REPORT_US {
+            self.report_us = timestamp_us;
+            log!(
+                Module::Sample,
+                info,
+                "Power: {}V, {}W, {}kWh",
+                volts,
+                watts,
+                energy_wh / 1000.0
+            );
+        }
+        let time_ms = timestamp_us / 1000;
+        if time_ms - self.event_ms >= power::REPORT_INTERVAL_MS {
+            self.event_ms = time_ms;
+            telemetry::publish(Telemetry::Event {
+                time_ms,
+                event: Event::Energy { watts, energy_wh },
+            });
+        }
+    }
+
+    /// On leaving power mode.
+    pub fn stop(&mut self) {
+        self.last_us = None;
+        critical_section::with(|cs| WATTS.borrow(cs).set(None));
+    }
+}
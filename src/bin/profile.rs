@@ -0,0 +1,116 @@
+//! Named profiles, see `hall_effect::profile`: `profile save <name>`
+//! stores the config in use, calibration included, `profile use <name>`
+//! switches to one and a double press of the button steps to the next.
+//! Switching hands the profile's config to processing from the next
+//! sample, saves it as the config to boot with and shows the profile's
+//! colour on the LED for a moment. Keys that need a restart (see
+//! `Update::needs_restart`) take effect at the next boot, as with
+//! `config set`. Profiles are kept in the `state` partition.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use hall_effect::profile::{self, Error, MAX_PROFILES, Name, Profile};
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+
+use crate::state::{self, STATE, State};
+use crate::verbosity::log;
+use crate::{led, sleep};
+
+/// The config switched to, for processing to take up.
+pub static SWITCHED: Signal<CriticalSectionRawMutex, Config> = Signal::new();
+
+pub struct Listing {
+    pub names: [Option<Name>; MAX_PROFILES],
+    pub active: Option<usize>,
+}
+
+pub async fn list() -> Result<Listing, Error> {
+    let mut state = STATE.lock().await;
+    let state = state.as_mut().ok_or(Error::Storage)?;
+    Ok(Listing {
+        names: names(state).await?,
+        active: active(state),
+    })
+}
+
+/// Saves `config` as profile `name`, over the one of that name if any, and
+/// makes it the active profile.
+pub async fn save(name: Name, config: &Config) -> Result<(), Error> {
+    let mut state = STATE.lock().await;
+    let state = state.as_mut().ok_or(Error::Storage)?;
+    let slot = profile::slot_for(&names(state).await?, &name)?;
+    let saved = Profile {
+        name,
+        config: *config,
+    };
+    stored(state.save_profile(slot, &saved).await)?;
+    stored(state.save_active_profile(slot).await)?;
+    log!(Module::Storage, info, "Profile {} saved", name);
+    led::preview(profile::color(slot));
+    Ok(())
+}
+
+/// Switches to profile `name`, returning its config.
+pub async fn switch(name: &Name) -> Result<Config, Error> {
+    let mut state = STATE.lock().await;
+    let state = state.as_mut().ok_or(Error::Storage)?;
+    let slot = find(state, name).await?;
+    activate(state, slot).await
+}
+
+/// Switches to the next saved profile after the active one.
+pub async fn next() -> Result<Config, Error> {
+    let mut state = STATE.lock().await;
+    let state = state.as_mut().ok_or(Error::Storage)?;
+    let slot = profile::next(&names(state).await?, active(state)).ok_or(Error::NotFound)?;
+    activate(state, slot).await
+}
+
+pub async fn remove(name: &Name) -> Result<(), Error> {
+    let mut state = STATE.lock().await;
+    let state = state.as_mut().ok_or(Error::Storage)?;
+    let slot = find(state, name).await?;
+    stored(state.remove_profile(slot).await)?;
+    log!(Module::Storage, info, "Profile {} removed", name);
+    Ok(())
+}
+
+async fn activate(state: &mut State, slot: usize) -> Result<Config, Error> {
+    let profile = stored(state.profile(slot).await)?.ok_or(Error::NotFound)?;
+    stored(state.save_config(&profile.config).await)?;
+    stored(state.save_active_profile(slot).await)?;
+    // Or a wake from low-power mode would bring back the zero it replaced
+    sleep::set_zero_field_mv(profile.config.zero_field_mv);
+    SWITCHED.signal(profile.config);
+    led::preview(profile::color(slot));
+    log!(Module::Storage, info, "Profile {} active", profile.name);
+    Ok(profile.config)
+}
+
+fn active(state: &State) -> Option<usize> {
+    state.settings().profile.map(usize::from)
+}
+
+async fn names(state: &mut State) -> Result<[Option<Name>; MAX_PROFILES], Error> {
+    let mut names = [None; MAX_PROFILES];
+    for (slot, name) in names.iter_mut().enumerate() {
+        *name = stored(state.profile(slot).await)?.map(|profile| profile.name);
+    }
+    Ok(names)
+}
+
+async fn find(state: &mut State, name: &Name) -> Result<usize, Error> {
+    names(state)
+        .await?
+        .iter()
+        .position(|saved| saved.as_ref() == Some(name))
+        .ok_or(Error::NotFound)
+}
+
+fn stored<T>(result: Result<T, state::Error>) -> Result<T, Error> {
+    result.map_err(|e| {
+        log!(Module::Storage, warn, "Profile store failed: {}", e);
+        Error::Storage
+    })
+}
@@ -0,0 +1,51 @@
+//! The most recent processed reading, shared with any number of consumers
+//! without coupling them to the sampler or to each other.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::Watch;
+use embassy_time::{Duration, Instant, Timer};
+use hall_effect::schema::Sample;
+
+#[derive(Clone, Copy, Debug, defmt::Format)]
+pub struct Reading {
+    pub sample: Sample,
+    pub field_mt: f32,
+    /// `false` while the sensor wiring is faulted.
+    pub valid: bool,
+    pub supply_low: bool,
+}
+
+// Receivers that wait for changes: the LED task, up to two displays and the
+// haptics, with room for a network notifier. Readers that only poll use
+// `LATEST.try_get()` and need no slot.
+const RECEIVERS: usize = 5;
+
+pub static LATEST: Watch<CriticalSectionRawMutex, Reading, RECEIVERS> = Watch::new();
+
+// Readings averaged by `average`, a second's worth at the default rate
+const AVERAGED: u32 = 100;
+// Longest wait for them, as a quiet field slows sampling; well inside the
+// console's watchdog timeout
+const AVERAGE_TIMEOUT: Duration = Duration::from_secs(3);
+const POLL: Duration = Duration::from_millis(5);
+
+/// The mean of `value` over the next valid readings, polling rather than
+/// taking a receiver. `None` if none came.
+pub async fn average(value: impl Fn(&Reading) -> f32) -> Option<f32> {
+    let mut sum = 0.0;
+    let mut count = 0;
+    let mut last_us = None;
+    let deadline = Instant::now() + AVERAGE_TIMEOUT;
+    while count < AVERAGED && Instant::now() < deadline {
+        if let Some(reading) = LATEST.try_get()
+            && reading.valid
+            && last_us != Some(reading.sample.timestamp_us)
+        {
+            last_us = Some(reading.sample.timestamp_us);
+            sum += value(&reading);
+            count += 1;
+        }
+        Timer::after(POLL).await;
+    }
+    (count > 0).then(|| sum / count as f32)
+}
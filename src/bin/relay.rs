@@ -0,0 +1,111 @@
+//! Relay output: with the `relay` feature, a relay or contactor's driver
+//! on GPIO45 switches with magnet presence, from every reading in every
+//! mode. GPIO45's strapping pull-down keeps the driver off through reset.
+//! Its thresholds, hold time and fail-safe state are set with `relay` on
+//! the console and kept in the `state` partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::peripherals::GPIO45;
+use hall_effect::relay::{FailSafe, Relay, Settings};
+use hall_effect::verbosity::Module;
+
+use crate::reading::Reading;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static OUTPUT: Mutex<RefCell<Option<Output<'static>>>> = Mutex::new(RefCell::new(None));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next reading, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_relay_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Relay settings not saved: {}", e);
+    }
+}
+
+/// Whether the relay is energised.
+pub fn is_on() -> bool {
+    critical_section::with(|cs| {
+        OUTPUT
+            .borrow_ref(cs)
+            .as_ref()
+            .is_some_and(Output::is_set_high)
+    })
+}
+
+/// Sets up the output in the fail-safe state until the first reading, at
+/// boot, once the settings are restored.
+pub fn init(pin: GPIO45<'static>) {
+    let level = Level::from(settings().fail_safe == FailSafe::On);
+    let output = Output::new(pin, level, OutputConfig::default());
+    critical_section::with(|cs| OUTPUT.replace(cs, Some(output)));
+}
+
+/// The processing stage's half.
+pub struct Switch {
+    relay: Relay,
+}
+
+impl Switch {
+    pub const fn new() -> Self {
+        Self {
+            relay: Relay::new(),
+        }
+    }
+
+    /// Takes a reading. Readings from a faulted sensor or a low supply
+    /// count as none.
+    pub fn push(&mut self, reading: &Reading) {
+        let field_mt = (reading.valid && !reading.supply_low).then_some(reading.field_mt);
+        self.update(reading.sample.timestamp_us, field_mt);
+    }
+
+    /// With no readings coming in, at `timestamp_us`.
+    pub fn stall(&mut self, timestamp_us: u64) {
+        self.update(timestamp_us, None);
+    }
+
+    fn update(&mut self, timestamp_us: u64, field_mt: Option<f32>) {
+        let was_on = self.relay.is_on();
+        let on = self
+            .relay
+            .update(timestamp_us / 1000, field_mt, &settings());
+        critical_section::with(|cs| {
+            if let Some(output) = OUTPUT.borrow_ref_mut(cs).as_mut() {
+                output.set_level(Level::from(on));
+            }
+        });
+        if on != was_on {
+            match field_mt {
+                Some(field_mt) => log!(
+                    Module::Sensor,
+                    info,
+                    "Relay {} at {}mT",
+                    if on { "in" } else { "out" },
+                    field_mt
+                ),
+                None => log!(
+                    Module::Sensor,
+                    warn,
+                    "Relay {}, fail-safe without a reading",
+                    if on { "in" } else { "out" }
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Remote configuration: `config set` on the console takes the same
+//! `key=value` payload a `.../config/set` topic would carry and answers
+//! with the same JSON acknowledgement, so a network client only has to
+//! pass both along. Accepted changes are saved in the `state` partition,
+//! restored at boot, and reach processing from the next sample; see
+//! [`Update::needs_restart`] for those that wait for a restart.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use hall_effect::remote::{Rejection, Update};
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+
+use crate::state::STATE;
+use crate::verbosity::log;
+
+/// Accepted updates, for processing to apply to its own config, which
+/// auto-zero and the encoder also change.
+pub static UPDATES: Channel<CriticalSectionRawMutex, Update, 4> = Channel::new();
+
+/// Checks `update` against `config` and, if accepted, applies it there,
+/// hands it to processing and saves the result.
+pub async fn set(config: &mut Config, update: &Update) -> Result<(), Rejection> {
+    let updated = update.apply(config)?;
+    if UPDATES.try_send(*update).is_err() {
+        return Err(Rejection::Busy);
+    }
+    *config = updated;
+    log!(Module::Storage, info, "Config updated: {}", update);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_config(config).await
+    {
+        log!(Module::Storage, warn, "Config not saved: {}", e);
+    }
+    Ok(())
+}
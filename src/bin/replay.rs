@@ -0,0 +1,130 @@
+//! Replays samples from the flash log through the processing stage in place
+//! of the sensor, to reproduce what the device did with a recorded field
+//! (`replay` on the console).
+//!
+//! Samples keep their recorded spacing, rebased onto the current time, so
+//! the threshold detector, tachometer and gestures see what they saw in the
+//! field. Stored samples are decimated (see `flash_log`), so this is the
+//! once-a-second history rather than every reading. While a replay runs the
+//! sensor's own samples are dropped, and nothing reaches the log or the
+//! odometer, so a replay does not record itself.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, with_timeout};
+use hall_effect::datalog::Record;
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::flash_log::{self, FLASH_LOG, Page};
+use crate::sensor::SAMPLES;
+use crate::verbosity::log;
+
+#[derive(Clone, Copy)]
+enum Request {
+    Start { start: u32, count: Option<u32> },
+    Stop,
+}
+
+// Longest wait between replayed samples. Quiet stretches are stored
+// sparsely when samples are reported by exception.
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+static ACTIVE: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+static REQUEST: Signal<CriticalSectionRawMutex, Request> = Signal::new();
+
+/// Whether recorded samples are standing in for the sensor.
+pub fn active() -> bool {
+    critical_section::with(|cs| ACTIVE.borrow(cs).get())
+}
+
+/// Replays `count` records (or all of the rest) from the one numbered
+/// `start`, as `dump` numbers them. Replaces a replay already running.
+pub fn start(start: u32, count: Option<u32>) {
+    REQUEST.signal(Request::Start { start, count });
+}
+
+pub fn stop() {
+    REQUEST.signal(Request::Stop);
+}
+
+fn set_active(active: bool) {
+    critical_section::with(|cs| ACTIVE.borrow(cs).set(active));
+}
+
+#[embassy_executor::task]
+pub async fn replay_task() {
+    loop {
+        let Request::Start { start, count } = REQUEST.wait().await else {
+            continue;
+        };
+        log!(
+            Module::Storage,
+            info,
+            "Replaying the flash log from record {}",
+            start
+        );
+        set_active(true);
+        match replay(start, count).await {
+            Ok(samples) => log!(Module::Storage, info, "Replay ended, {} samples", samples),
+            Err(e) => log!(Module::Storage, warn, "Replay failed: {}", e),
+        }
+        set_active(false);
+    }
+}
+
+/// Feeds the records' samples to the processing stage at their recorded
+/// pace, until the records run out or another request arrives. Returns the
+/// number of samples replayed.
+async fn replay(start: u32, count: Option<u32>) -> Result<u32, flash_log::Error> {
+    let mut page = Page::new();
+    let mut next = start;
+    let mut left = count.unwrap_or(u32::MAX);
+    // Recorded and replayed time of the previous sample
+    let mut previous: Option<(u64, u64)> = None;
+    let mut replayed = 0;
+
+    while left > 0 {
+        let more = match FLASH_LOG.lock().await.as_mut() {
+            Some(log) => log.read_page(next, &mut page).await?,
+            None => return Err(flash_log::Error::NoPartition),
+        };
+        for (sequence, raw) in page.iter().take(left as usize) {
+            next = sequence + 1;
+            left -= 1;
+            // Corrupt records are skipped, as `dump` marks and skips them
+            let Ok(record) = Record::decode(raw) else {
+                continue;
+            };
+            for mut sample in record.samples() {
+                let recorded_us = sample.timestamp_us;
+                let replayed_us = match previous {
+                    // Time runs backwards across a reboot
+                    Some((last_recorded_us, last_replayed_us)) => {
+                        let gap_us = recorded_us.saturating_sub(last_recorded_us);
+                        last_replayed_us + gap_us.min(MAX_GAP.as_micros())
+                    }
+                    None => clock::monotonic_us(),
+                };
+                let wait_us = replayed_us.saturating_sub(clock::monotonic_us());
+                if let Ok(next) = with_timeout(Duration::from_micros(wait_us), REQUEST.wait()).await
+                {
+                    // Left for the task to pick up
+                    REQUEST.signal(next);
+                    return Ok(replayed);
+                }
+                sample.timestamp_us = replayed_us;
+                SAMPLES.send(sample).await;
+                previous = Some((recorded_us, replayed_us));
+                replayed += 1;
+            }
+        }
+        if !more {
+            break;
+        }
+    }
+    Ok(replayed)
+}
@@ -0,0 +1,124 @@
+//! Automation rules, see `hall_effect::rules`: set with `rule` on the
+//! console and checked on every reading, in every mode. A rule met or
+//! cleared is logged and its action taken. With the `rule-output` feature
+//! an output rule drives GPIO5 high while it is met, a publish rule sends
+//! `Event::Rule` to telemetry, and a flash rule shows its colour on the LED
+//! for a moment. The rules are kept in the `state` partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::gpio::{Level, Output};
+use hall_effect::rules::{self, Action, Engine, MAX_RULES, Rule, Rules};
+use hall_effect::schema::Event;
+use hall_effect::verbosity::Module;
+
+use crate::led;
+use crate::reading::Reading;
+use crate::state::STATE;
+use crate::telemetry::{self, Telemetry};
+use crate::verbosity::log;
+
+static RULES: Mutex<Cell<Rules>> = Mutex::new(Cell::new([None; MAX_RULES]));
+static ENGINE: Mutex<RefCell<Engine>> = Mutex::new(RefCell::new(Engine::new()));
+static OUTPUT: Mutex<RefCell<Option<Output<'static>>>> = Mutex::new(RefCell::new(None));
+
+pub fn rules() -> Rules {
+    critical_section::with(|cs| RULES.borrow(cs).get())
+}
+
+pub fn is_met(number: usize) -> bool {
+    critical_section::with(|cs| ENGINE.borrow_ref(cs).is_met(number))
+}
+
+/// Takes on the rules saved in flash, at boot. None of them is met until
+/// the next reading.
+pub fn restore(rules: Rules) {
+    critical_section::with(|cs| {
+        RULES.borrow(cs).set(rules);
+        ENGINE.replace(cs, Engine::new());
+        if let Some(output) = OUTPUT.borrow_ref_mut(cs).as_mut() {
+            output.set_low();
+        }
+    });
+}
+
+/// Adds `rule`, returning its number, or `None` with no place free.
+pub async fn add(rule: Rule) -> Option<usize> {
+    let mut updated = rules();
+    let number = rules::add(&mut updated, rule)?;
+    configure(updated).await;
+    Some(number)
+}
+
+/// Removes rule `number`, returning whether there was one.
+pub async fn remove(number: usize) -> bool {
+    let mut updated = rules();
+    let removed = rules::remove(&mut updated, number);
+    if removed {
+        configure(updated).await;
+    }
+    removed
+}
+
+async fn configure(rules: Rules) {
+    restore(rules);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_rules(&rules).await
+    {
+        log!(Module::Storage, warn, "Rules not saved: {}", e);
+    }
+}
+
+/// Sets up the output, low until an output rule is met, at boot.
+#[cfg(feature = "rule-output")]
+pub fn init(pin: esp_hal::peripherals::GPIO5<'static>) {
+    use esp_hal::gpio::OutputConfig;
+
+    let output = Output::new(pin, Level::Low, OutputConfig::default());
+    critical_section::with(|cs| OUTPUT.replace(cs, Some(output)));
+}
+
+/// Takes a reading. Readings from a faulted sensor meet no rule.
+pub fn update(reading: &Reading) {
+    let rules = rules();
+    let time_ms = reading.sample.timestamp_us / 1000;
+    let field_mt = reading.valid.then_some(reading.field_mt);
+    let changes = critical_section::with(|cs| {
+        let mut engine = ENGINE.borrow_ref_mut(cs);
+        let changes = engine.update(time_ms, field_mt, &rules);
+        if let Some(output) = OUTPUT.borrow_ref_mut(cs).as_mut() {
+            output.set_level(Level::from(engine.output(&rules)));
+        }
+        changes
+    });
+    for (index, (rule, met)) in rules.iter().zip(changes).enumerate() {
+        let (Some(rule), Some(met)) = (rule, met) else {
+            continue;
+        };
+        let number = index + 1;
+        log!(
+            Module::Sensor,
+            info,
+            "Rule {} {} at {}mT",
+            number,
+            if met { "met" } else { "cleared" },
+            reading.field_mt
+        );
+        match rule.action {
+            Action::Output => {}
+            Action::Publish => telemetry::publish(Telemetry::Event {
+                time_ms,
+                event: Event::Rule {
+                    rule: number as u8,
+                    met,
+                },
+            }),
+            Action::Flash(color) => {
+                if met {
+                    led::preview(color.rgb());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Sampling paced by a hardware timer, in the `timer-sampling` build: TIMG1's
+//! general-purpose timer interrupts once per sample period and the handler
+//! takes the reading there and then, so samples are as evenly spaced as the
+//! crystal rather than wherever the executor gets round to the sampler. The
+//! sampler task only collects the timestamped readings.
+//!
+//! Readings reach the sampler through a single-producer single-consumer
+//! ring: the handler is the only producer and the sampler the only
+//! consumer, so neither side locks the other out. The ring holds a second
+//! or so of readings, letting the sampler wait on a processing stage held
+//! up by the network rather than drop samples; a full ring is counted as an
+//! overflow and shows in the diagnostics.
+//!
+//! The ADC lives here while the timer runs. Bursts and battery readings
+//! check it out with [`take_adc`] and hand it back with [`restore_adc`];
+//! ticks in between are missed, and counted.
+//!
+//! With the `sync` feature a follower takes its readings on the leader's
+//! pulses instead, through [`take_reading`]; see src/bin/sync.rs.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use esp_hal::Blocking;
+use esp_hal::handler;
+use esp_hal::time::Duration;
+use esp_hal::timer::PeriodicTimer;
+use esp_hal::timer::timg::Timer;
+use hall_effect::schema::Sample;
+use heapless::spsc::{Consumer, Producer, Queue};
+use static_cell::StaticCell;
+
+use crate::clock;
+use crate::sensor::{self, SensorAdc, SensorPin};
+
+/// One slot fewer than this, about 1.3s at the default period.
+const QUEUE_LEN: usize = 128;
+
+static QUEUE: StaticCell<Queue<Sample, QUEUE_LEN>> = StaticCell::new();
+static TIMER: Mutex<RefCell<Option<PeriodicTimer<'static, Blocking>>>> =
+    Mutex::new(RefCell::new(None));
+static ADC: Mutex<RefCell<Option<(SensorAdc, SensorPin)>>> = Mutex::new(RefCell::new(None));
+static PRODUCER: Mutex<RefCell<Option<Producer<'static, Sample, QUEUE_LEN>>>> =
+    Mutex::new(RefCell::new(None));
+static READY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static MISSED: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static OVERFLOWS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// The sampler's end of the queue.
+pub struct Readings {
+    consumer: Consumer<'static, Sample, QUEUE_LEN>,
+}
+
+impl Readings {
+    /// The oldest reading not yet taken, waiting for the next tick if
+    /// there is none.
+    pub async fn next(&mut self) -> Sample {
+        loop {
+            if let Some(sample) = self.consumer.dequeue() {
+                return sample;
+            }
+            READY.wait().await;
+        }
+    }
+}
+
+/// Takes the timer, from `main` before the sampler is spawned.
+pub fn init(timer: Timer<'static>) {
+    let mut timer = PeriodicTimer::new(timer);
+    timer.set_interrupt_handler(on_tick);
+    critical_section::with(|cs| TIMER.borrow_ref_mut(cs).replace(timer));
+}
+
+/// Hands the ADC to the interrupt handler and starts a tick every
+/// `period_ms`, returning the end of the queue the readings arrive on.
+pub fn start(adc: SensorAdc, pin: SensorPin, period_ms: u32) -> Readings {
+    let (producer, consumer) = QUEUE.init(Queue::new()).split();
+    critical_section::with(|cs| {
+        ADC.borrow_ref_mut(cs).replace((adc, pin));
+        PRODUCER.borrow_ref_mut(cs).replace(producer);
+        let mut timer = TIMER.borrow_ref_mut(cs);
+        let Some(timer) = timer.as_mut() else {
+            defmt::error!("Sample timer not initialised");
+            return;
+        };
+        timer.listen();
+        if let Err(e) = timer.start(Duration::from_millis(period_ms as u64)) {
+            defmt::error!("Sample timer not started: {}", e);
+        }
+    });
+    Readings { consumer }
+}
+
+/// Ticks without a reading since the last call, because the ADC was
+/// checked out or the conversion failed.
+pub fn take_missed() -> u32 {
+    critical_section::with(|cs| MISSED.borrow(cs).replace(0))
+}
+
+/// Readings dropped on a full queue since boot.
+pub fn overflows() -> u32 {
+    critical_section::with(|cs| OVERFLOWS.borrow(cs).get())
+}
+
+pub fn take_adc() -> Option<(SensorAdc, SensorPin)> {
+    critical_section::with(|cs| ADC.borrow_ref_mut(cs).take())
+}
+
+pub fn restore_adc(adc: SensorAdc, pin: SensorPin) {
+    critical_section::with(|cs| ADC.borrow_ref_mut(cs).replace((adc, pin)));
+}
+
+fn count(counter: &Mutex<Cell<u32>>, cs: critical_section::CriticalSection) {
+    let counter = counter.borrow(cs);
+    counter.set(counter.get().wrapping_add(1));
+}
+
+/// Converts a reading taken at `timestamp_us` and queues it for the
+/// sampler, from the tick or, with the `sync` feature, the sync pulse.
+pub fn take_reading(cs: critical_section::CriticalSection, timestamp_us: u64) {
+    let Some(sample) = ADC
+        .borrow_ref_mut(cs)
+        .as_mut()
+        .and_then(|(adc, pin)| nb::block!(sensor::convert(adc, pin)).ok())
+        .map(|raw| sensor::to_sample(timestamp_us, raw))
+    else {
+        count(&MISSED, cs);
+        return;
+    };
+    let mut producer = PRODUCER.borrow_ref_mut(cs);
+    match producer.as_mut().map(|p| p.enqueue(sample)) {
+        Some(Ok(())) => READY.signal(()),
+        _ => count(&OVERFLOWS, cs),
+    }
+}
+
+#[handler]
+fn on_tick() {
+    let timestamp_us = clock::monotonic_us();
+    critical_section::with(|cs| {
+        if let Some(timer) = TIMER.borrow_ref_mut(cs).as_mut() {
+            timer.clear_interrupt();
+        }
+        #[cfg(feature = "sync")]
+        if !crate::sync::tick(cs, timestamp_us) {
+            return;
+        }
+        take_reading(cs, timestamp_us);
+        #[cfg(feature = "sync")]
+        crate::sync::tick_done(cs);
+    });
+}
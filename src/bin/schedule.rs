@@ -0,0 +1,168 @@
+//! Scheduled actions, see `hall_effect::schedule`: entries set with
+//! `schedule` on the console are checked at the start of each minute by
+//! the wall clock, once it is set, and kept in the `state` partition.
+//!
+//! Resetting the range starts the OLED's and e-paper's minimum and maximum
+//! over. Flushing the log writes out the samples the flash log holds in
+//! RAM. A self-test runs the boot self-test on the next samples as they
+//! come in, and a failure is published on the event bus as a fault, which
+//! the next passing test clears.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use hall_effect::schedule::{self, Entries, Entry, MAX_ENTRIES, Scheduler, Task};
+use hall_effect::schema::{Config, Sample};
+use hall_effect::selftest;
+use hall_effect::verbosity::Module;
+use heapless::Vec;
+
+use crate::bus::{self, BusEvent, Fault};
+use crate::clock;
+use crate::flash_log::FLASH_LOG;
+use crate::replay;
+use crate::sensor;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+const US_PER_MINUTE: u64 = 60_000_000;
+
+// Until the wall clock is set
+const CLOCK_WAIT: Duration = Duration::from_secs(60);
+
+static ENTRIES: Mutex<Cell<Entries>> = Mutex::new(Cell::new([None; MAX_ENTRIES]));
+static RANGE_RESETS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static SELF_TEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn entries() -> Entries {
+    critical_section::with(|cs| ENTRIES.borrow(cs).get())
+}
+
+/// Takes on the entries saved in flash, at boot.
+pub fn restore(entries: Entries) {
+    critical_section::with(|cs| ENTRIES.borrow(cs).set(entries));
+}
+
+/// Adds `entry`, returning its number, or `None` with no place free.
+pub async fn add(entry: Entry) -> Option<usize> {
+    let mut updated = entries();
+    let number = schedule::add(&mut updated, entry)?;
+    configure(updated).await;
+    Some(number)
+}
+
+/// Removes entry `number`, returning whether there was one.
+pub async fn remove(number: usize) -> bool {
+    let mut updated = entries();
+    let removed = schedule::remove(&mut updated, number);
+    if removed {
+        configure(updated).await;
+    }
+    removed
+}
+
+async fn configure(entries: Entries) {
+    restore(entries);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_schedule(&entries).await
+    {
+        log!(Module::Storage, warn, "Schedule not saved: {}", e);
+    }
+}
+
+/// How many times the range has been reset; a display starts its range
+/// over when this changes.
+#[cfg_attr(not(any(feature = "oled", feature = "epaper")), expect(dead_code))]
+pub fn range_resets() -> u32 {
+    critical_section::with(|cs| RANGE_RESETS.borrow(cs).get())
+}
+
+#[embassy_executor::task]
+pub async fn schedule_task() {
+    let mut scheduler = Scheduler::new();
+    loop {
+        let Some(unix_us) = clock::unix_us(clock::monotonic_us()) else {
+            Timer::after(CLOCK_WAIT).await;
+            continue;
+        };
+        let entries = entries();
+        let due = scheduler.due(unix_us / 1_000_000, &entries);
+        for (entry, due) in entries.iter().zip(due) {
+            if let Some(entry) = entry.filter(|_| due) {
+                run(entry.task).await;
+            }
+        }
+        let next_minute_us = US_PER_MINUTE - unix_us % US_PER_MINUTE;
+        Timer::after(Duration::from_micros(next_minute_us)).await;
+    }
+}
+
+async fn run(task: Task) {
+    log!(Module::Clock, info, "Scheduled {}", task.name());
+    match task {
+        Task::ResetRange => {
+            critical_section::with(|cs| {
+                let resets = RANGE_RESETS.borrow(cs);
+                resets.set(resets.get().wrapping_add(1));
+            });
+        }
+        Task::FlushLog => {
+            if let Some(log) = FLASH_LOG.lock().await.as_mut()
+                && let Err(e) = log.flush().await
+            {
+                log!(Module::Storage, warn, "Flash log flush failed: {}", e);
+            }
+        }
+        Task::SelfTest => SELF_TEST.signal(()),
+    }
+}
+
+/// The processing stage's half of a scheduled self-test.
+pub struct SelfTest {
+    /// The samples so far, while a test runs.
+    samples: Option<Vec<Sample, { selftest::SAMPLES }>>,
+    failed: Option<selftest::Fault>,
+}
+
+impl SelfTest {
+    pub const fn new() -> Self {
+        Self {
+            samples: None,
+            failed: None,
+        }
+    }
+
+    /// Takes a sample from the sensor, before filtering. Replayed samples
+    /// are not the sensor's, and are left out.
+    pub fn push(&mut self, sample: &Sample, config: &Config) {
+        if SELF_TEST.try_take().is_some() {
+            self.samples.get_or_insert_with(Vec::new);
+        }
+        let Some(samples) = self.samples.as_mut().filter(|_| !replay::active()) else {
+            return;
+        };
+        let _ = samples.push(*sample);
+        if !samples.is_full() {
+            return;
+        }
+        let result = selftest::check(samples, config, sensor::FULL_SCALE);
+        self.samples = None;
+        match result {
+            Ok(()) => {
+                log!(Module::Sensor, info, "Scheduled self-test passed");
+                if let Some(fault) = self.failed.take() {
+                    bus::publish(BusEvent::FaultCleared(Fault::SelfTest(fault)));
+                }
+            }
+            // Published once, until a test passes
+            Err(fault) if self.failed.is_none() => {
+                self.failed = Some(fault);
+                bus::publish(BusEvent::FaultDetected(Fault::SelfTest(fault)));
+            }
+            Err(_) => {}
+        }
+    }
+}
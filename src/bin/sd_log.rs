@@ -0,0 +1,160 @@
+//! CSV logging to an SD card over SPI.
+//!
+//! Rows are batched into a block-sized buffer so the card sees one write per
+//! ~15 samples. A new `LOGnnnnn.CSV` is started when the current one reaches
+//! `MAX_FILE_BYTES` or the day rolls over.
+
+use core::fmt::Write;
+
+use embedded_sdmmc::{
+    BlockDevice, Error, Mode, RawDirectory, RawFile, TimeSource, Timestamp, VolumeIdx,
+    VolumeManager,
+};
+use hall_effect::csv;
+use hall_effect::schema::{Config, Sample};
+use hall_effect::verbosity::Module;
+use heapless::String;
+
+use crate::clock;
+use crate::verbosity::log;
+
+/// The card as wired up in `main`: SPI2 with a dedicated chip select.
+pub type Card = embedded_sdmmc::SdCard<
+    embedded_hal_bus::spi::ExclusiveDevice<
+        esp_hal::spi::master::Spi<'static, esp_hal::Blocking>,
+        esp_hal::gpio::Output<'static>,
+        embedded_hal_bus::spi::NoDelay,
+    >,
+    esp_hal::delay::Delay,
+>;
+
+const MAX_FILE_BYTES: u32 = 16 * 1024 * 1024;
+const US_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000;
+const MAX_FILE_INDEX: u32 = 99_999;
+
+// Maximum length of one CSV row
+const MAX_ROW_LEN: usize = 56;
+
+/// Stamps files with the wall clock, or the FAT epoch while it is unset.
+pub struct WallClock;
+
+impl TimeSource for WallClock {
+    fn get_timestamp(&self) -> Timestamp {
+        clock::now()
+            .and_then(|t| {
+                Timestamp::from_calendar(t.year, t.month, t.day, t.hour, t.minute, t.second).ok()
+            })
+            .unwrap_or(Timestamp::from_fat(0, 0))
+    }
+}
+
+pub struct SdLogger<D: BlockDevice> {
+    volume_mgr: VolumeManager<D, WallClock>,
+    dir: RawDirectory,
+    file: RawFile,
+    index: u32,
+    /// The day of the last row, once there is one.
+    day: Option<u64>,
+    /// Whether `day` counts calendar days rather than days of uptime.
+    calendar: bool,
+    buf: String<512>,
+}
+
+impl<D: BlockDevice> SdLogger<D> {
+    pub fn new(device: D) -> Result<Self, Error<D::Error>> {
+        let volume_mgr = VolumeManager::new(device, WallClock);
+        let volume = volume_mgr.open_raw_volume(VolumeIdx(0))?;
+        let dir = volume_mgr.open_root_dir(volume)?;
+
+        // Continue numbering after whatever earlier campaigns left on the card
+        let mut index = 0;
+        while index < MAX_FILE_INDEX
+            && volume_mgr
+                .find_directory_entry(dir, file_name(index).as_str())
+                .is_ok()
+        {
+            index += 1;
+        }
+
+        let file = open(&volume_mgr, dir, index)?;
+        Ok(Self {
+            volume_mgr,
+            dir,
+            file,
+            index,
+            day: None,
+            calendar: false,
+            buf: String::new(),
+        })
+    }
+
+    pub fn log(&mut self, sample: &Sample, config: &Config) -> Result<(), Error<D::Error>> {
+        // Calendar days once the wall clock is known, days of uptime before
+        let unix_us = clock::unix_us(sample.timestamp_us);
+        let day = unix_us.unwrap_or(sample.timestamp_us) / US_PER_DAY;
+        // Setting the clock changes how days are counted, not the day
+        if self.calendar != unix_us.is_some() {
+            self.calendar = unix_us.is_some();
+            self.day = None;
+        }
+        if self.day.replace(day).is_some_and(|last| last != day) {
+            self.rotate()?;
+        }
+
+        if self.buf.capacity() - self.buf.len() < MAX_ROW_LEN {
+            self.flush()?;
+        }
+        // Cannot fail: the buffer has room for a full row
+        let _ = csv::write_row(&mut self.buf, sample, config, None);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error<D::Error>> {
+        self.volume_mgr.write(self.file, self.buf.as_bytes())?;
+        self.buf.clear();
+        self.volume_mgr.flush_file(self.file)?;
+
+        if self.volume_mgr.file_length(self.file)? >= MAX_FILE_BYTES {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Error<D::Error>> {
+        if !self.buf.is_empty() {
+            self.volume_mgr.write(self.file, self.buf.as_bytes())?;
+            self.buf.clear();
+        }
+        self.volume_mgr.close_file(self.file)?;
+
+        self.index = (self.index + 1) % (MAX_FILE_INDEX + 1);
+        self.file = open(&self.volume_mgr, self.dir, self.index)?;
+        log!(
+            Module::Storage,
+            info,
+            "SD log rotated to {}",
+            file_name(self.index).as_str()
+        );
+        Ok(())
+    }
+}
+
+fn file_name(index: u32) -> String<12> {
+    let mut name = String::new();
+    let _ = write!(name, "LOG{:05}.CSV", index);
+    name
+}
+
+fn open<D: BlockDevice>(
+    volume_mgr: &VolumeManager<D, WallClock>,
+    dir: RawDirectory,
+    index: u32,
+) -> Result<RawFile, Error<D::Error>> {
+    let file = volume_mgr.open_file_in_dir(
+        dir,
+        file_name(index).as_str(),
+        Mode::ReadWriteCreateOrTruncate,
+    )?;
+    volume_mgr.write(file, csv::HEADER.as_bytes())?;
+    Ok(file)
+}
@@ -0,0 +1,339 @@
+//! Sampler task: reads the hall sensor at the configured period, or more
+//! slowly while the field is quiet, and hands the samples to the processing
+//! stage. In the `timer-sampling` build the readings are taken by a timer
+//! interrupt instead, see src/bin/sample_timer.rs.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+#[cfg(not(any(feature = "light-sleep", feature = "timer-sampling")))]
+use embassy_time::Ticker;
+use embassy_time::{Duration, Timer};
+use esp_hal::Blocking;
+use esp_hal::analog::adc::{Adc, AdcPin};
+use esp_hal::peripherals::ADC1;
+use hall_effect::activity::ActivityDetector;
+use hall_effect::mode::Mode;
+#[cfg(feature = "battery")]
+use hall_effect::schema::PowerStage;
+use hall_effect::schema::{Config, Sample};
+use hall_effect::timing::Window;
+use hall_effect::verbosity::Module;
+
+#[cfg(feature = "ambient-light")]
+use crate::ambient;
+#[cfg(feature = "battery")]
+use crate::battery;
+#[cfg(feature = "levitate")]
+use crate::levitate;
+#[cfg(feature = "light-sleep")]
+use crate::light_sleep::Sleeper;
+#[cfg(feature = "mock-sensor")]
+use crate::mock;
+#[cfg(feature = "pas")]
+use crate::pas;
+#[cfg(feature = "power-meter")]
+use crate::power;
+#[cfg(feature = "timer-sampling")]
+use crate::sample_timer;
+#[cfg(feature = "trigger-out")]
+use crate::trigger;
+use crate::verbosity::log;
+use crate::{Error, board, burst, chip, clock, diag, goertzel, mode, replay, watchdog};
+
+pub type SensorAdc = Adc<'static, ADC1<'static>, Blocking>;
+pub type SensorPin = AdcPin<board::SensorGpio, ADC1<'static>, chip::AdcCal<ADC1<'static>>>;
+
+/// The highest `raw` reading: calibrated millivolts at `SENSOR_ATTENUATION`,
+/// except where the S2's basic calibration and the mock waveform give
+/// 12-bit counts.
+pub const FULL_SCALE: u16 = if cfg!(any(feature = "esp32s2", feature = "mock-sensor")) {
+    4095
+} else if cfg!(feature = "esp32c6") {
+    1900
+} else {
+    1750
+};
+
+// ADC reads per sample before the sample is skipped
+const ADC_RETRIES: u32 = 4;
+
+// Sampling slows by this much once the battery is low enough
+#[cfg(feature = "battery")]
+const REDUCED_RATE_DIVIDER: u32 = 4;
+
+/// Samples on their way to processing. Holds a few periods, so the
+/// processing stage can fall briefly behind without losing any.
+pub static SAMPLES: Channel<CriticalSectionRawMutex, Sample, 16> = Channel::new();
+
+/// One conversion of the sensor pin.
+#[cfg(not(feature = "mock-sensor"))]
+pub fn convert(adc: &mut SensorAdc, pin: &mut SensorPin) -> nb::Result<u16, ()> {
+    adc.read_oneshot(pin)
+}
+
+/// The mock waveform in place of a conversion; the ADC is left idle.
+#[cfg(feature = "mock-sensor")]
+pub fn convert(_adc: &mut SensorAdc, _pin: &mut SensorPin) -> nb::Result<u16, ()> {
+    Ok(mock::read())
+}
+
+/// A sample from a full-resolution conversion taken at `timestamp_us`.
+pub fn to_sample(timestamp_us: u64, raw: u16) -> Sample {
+    let raw = raw >> (chip::ADC_BITS - 12);
+    Sample {
+        timestamp_us,
+        raw,
+        voltage_mv: hall_effect::burst::voltage_mv(raw),
+    }
+}
+
+/// Reads the ADC, retrying with a growing delay before giving up on the
+/// sample.
+pub async fn read_sample(mut read: impl FnMut() -> nb::Result<u16, ()>) -> Result<Sample, Error> {
+    for attempt in 0..ADC_RETRIES {
+        if let Ok(raw) = nb::block!(read()) {
+            return Ok(to_sample(clock::monotonic_us(), raw));
+        }
+        Timer::after(Duration::from_millis(1 << attempt)).await;
+    }
+    Err(Error::Adc)
+}
+
+/// The sample period for an active or quiet field, a whole number of
+/// `sample_period_ms`. Tachometer, calibration, spectrum, drift, lock-in,
+/// current, power and pedal-assist readings, and the Goertzel detector's,
+/// are always taken at the full rate.
+fn period_ms(active: bool, config: &Config) -> u32 {
+    let quiet = !active
+        && !goertzel::running()
+        && !matches!(
+            mode::current(),
+            Mode::Tachometer
+                | Mode::Calibrate
+                | Mode::Spectrum
+                | Mode::Drift
+                | Mode::LockIn
+                | Mode::Current
+                | Mode::Power
+                | Mode::Pas
+        );
+    if quiet && config.idle_sample_period_ms > config.sample_period_ms {
+        config.idle_sample_period_ms / config.sample_period_ms * config.sample_period_ms
+    } else {
+        config.sample_period_ms
+    }
+}
+
+/// Multiplies the sample period while the battery is low, which also cuts
+/// what is published.
+#[cfg(feature = "battery")]
+fn periods_per_sample() -> u32 {
+    match battery::stage() {
+        PowerStage::Normal | PowerStage::Dimmed => 1,
+        PowerStage::Reduced | PowerStage::Shutdown => REDUCED_RATE_DIVIDER,
+    }
+}
+
+#[cfg(not(feature = "battery"))]
+fn periods_per_sample() -> u32 {
+    1
+}
+
+/// Hands a sample to processing, dropping it if processing is behind.
+#[cfg(not(feature = "timer-sampling"))]
+pub async fn forward(sample: Sample) {
+    if SAMPLES.try_send(sample).is_err() {
+        log!(Module::Sensor, warn, "Processing behind, sample dropped");
+    }
+}
+
+/// Hands a sample to processing, waiting for room: the sample timer's queue
+/// keeps the readings coming in the meantime.
+#[cfg(feature = "timer-sampling")]
+async fn forward(sample: Sample) {
+    SAMPLES.send(sample).await;
+}
+
+/// Runs a requested burst with the ADC checked out from the sample timer.
+#[cfg(feature = "timer-sampling")]
+fn poll_burst() {
+    if !burst::requested() {
+        return;
+    }
+    if let Some((mut adc, mut pin)) = sample_timer::take_adc() {
+        burst::poll(&mut adc, &mut pin);
+        sample_timer::restore_adc(adc, pin);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn sampler_task(adc: SensorAdc, pin: SensorPin, config: Config) {
+    #[cfg(not(feature = "timer-sampling"))]
+    let (mut adc, mut pin) = (adc, pin);
+    #[cfg(feature = "timer-sampling")]
+    let (mut readings, mut overflows) = (sample_timer::start(adc, pin, config.sample_period_ms), 0);
+    #[cfg(not(any(feature = "light-sleep", feature = "timer-sampling")))]
+    let mut ticker = Ticker::every(Duration::from_millis(config.sample_period_ms as u64));
+    // The executor's timers stop in light sleep, so the sampler keeps its
+    // own schedule on the monotonic clock
+    #[cfg(feature = "light-sleep")]
+    let (mut sleeper, mut next_us) = (
+        // SAFETY: only `sleep` takes LPWR as well, on the way into deep
+        // sleep, and it never returns
+        Sleeper::new(unsafe { esp_hal::peripherals::LPWR::steal() }),
+        clock::monotonic_us(),
+    );
+    let mut period = Window::new();
+    let mut last_sample_us = None;
+    let mut report_us = clock::monotonic_us();
+    #[cfg(feature = "battery")]
+    let mut battery_us = 0;
+    #[cfg(feature = "ambient-light")]
+    let mut ambient_us = 0;
+    let mut activity = ActivityDetector::new();
+    let mut active = true;
+
+    loop {
+        #[cfg(feature = "levitate")]
+        if mode::current() == Mode::Levitate {
+            levitate::run(&mut adc, &mut pin, &config).await;
+            // Rather than catch up on the periods the loop took
+            ticker.reset();
+        }
+        #[cfg(not(feature = "timer-sampling"))]
+        let reading = {
+            burst::poll(&mut adc, &mut pin);
+            read_sample(|| convert(&mut adc, &mut pin)).await
+        };
+        #[cfg(feature = "timer-sampling")]
+        let reading = {
+            poll_burst();
+            Ok::<_, Error>(readings.next().await)
+        };
+        match reading {
+            Ok(sample) => {
+                #[cfg(feature = "trigger-out")]
+                trigger::sample(&sample);
+                let timestamp_us = sample.timestamp_us;
+                let was_active = core::mem::replace(
+                    &mut active,
+                    activity.update(timestamp_us, config.field_mt(sample.voltage_mv), &config),
+                );
+                if active != was_active {
+                    log!(
+                        Module::Sensor,
+                        info,
+                        "Field {}, sampling every {}ms",
+                        if active { "active" } else { "quiet" },
+                        period_ms(active, &config)
+                    );
+                }
+                diag::record_sample(timestamp_us);
+                #[cfg(feature = "pas")]
+                if mode::current() == Mode::Pas {
+                    pas::sample(timestamp_us);
+                }
+                if let Some(last_us) = last_sample_us.replace(timestamp_us) {
+                    period.record((timestamp_us - last_us) as u32);
+                }
+                if replay::active() {
+                    // Recorded samples stand in for the sensor's
+                } else {
+                    forward(sample).await;
+                }
+            }
+            Err(e) => log!(Module::Sensor, warn, "Sample skipped: {}", e),
+        }
+        watchdog::feed(watchdog::Task::Sensor);
+
+        let now_us = clock::monotonic_us();
+        #[cfg(feature = "battery")]
+        if now_us - battery_us >= battery::PERIOD_US {
+            battery_us = now_us;
+            #[cfg(not(feature = "timer-sampling"))]
+            battery::sample(&mut adc).await;
+            #[cfg(feature = "timer-sampling")]
+            if let Some((mut adc, pin)) = sample_timer::take_adc() {
+                battery::sample(&mut adc).await;
+                sample_timer::restore_adc(adc, pin);
+            }
+        }
+        #[cfg(feature = "ambient-light")]
+        if now_us - ambient_us >= ambient::PERIOD_US {
+            ambient_us = now_us;
+            #[cfg(not(feature = "timer-sampling"))]
+            ambient::sample(&mut adc).await;
+            #[cfg(feature = "timer-sampling")]
+            if let Some((mut adc, pin)) = sample_timer::take_adc() {
+                ambient::sample(&mut adc).await;
+                sample_timer::restore_adc(adc, pin);
+            }
+        }
+        #[cfg(feature = "power-meter")]
+        if mode::current() == Mode::Power {
+            #[cfg(not(feature = "timer-sampling"))]
+            power::sample(&mut adc).await;
+            #[cfg(feature = "timer-sampling")]
+            if let Some((mut adc, pin)) = sample_timer::take_adc() {
+                power::sample(&mut adc).await;
+                sample_timer::restore_adc(adc, pin);
+            }
+        }
+        if now_us - report_us >= diag::TIMING_REPORT_US {
+            report_us = now_us;
+            if let Some(s) = period.take() {
+                log!(
+                    Module::Timing,
+                    info,
+                    "Sample period: nominal {}ms, {}",
+                    config.sample_period_ms,
+                    s
+                );
+            }
+            #[cfg(feature = "timer-sampling")]
+            if sample_timer::overflows() != overflows {
+                let total = sample_timer::overflows();
+                log!(
+                    Module::Timing,
+                    warn,
+                    "Sample queue: {} readings dropped",
+                    total - overflows
+                );
+                overflows = total;
+            }
+            #[cfg(feature = "timer-sampling")]
+            match sample_timer::take_missed() {
+                0 => {}
+                missed => log!(
+                    Module::Timing,
+                    warn,
+                    "Sample timer: {} ticks missed",
+                    missed
+                ),
+            }
+            #[cfg(feature = "light-sleep")]
+            log!(
+                Module::Timing,
+                info,
+                "Light sleep: {}% of the time",
+                sleeper.take_percent()
+            );
+        }
+
+        let periods = period_ms(active, &config) / config.sample_period_ms;
+        #[cfg(not(any(feature = "light-sleep", feature = "timer-sampling")))]
+        for _ in 0..periods * periods_per_sample() {
+            ticker.next().await;
+        }
+        // The reading at the top of the loop is the last of these periods
+        #[cfg(feature = "timer-sampling")]
+        for _ in 1..periods * periods_per_sample() {
+            readings.next().await;
+        }
+        #[cfg(feature = "light-sleep")]
+        {
+            next_us += (periods * periods_per_sample() * config.sample_period_ms) as u64 * 1000;
+            sleeper.until(next_us).await;
+        }
+    }
+}
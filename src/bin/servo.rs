@@ -0,0 +1,106 @@
+//! Servo gauge: with the `servo` feature, a hobby servo on GPIO39, driven
+//! by LEDC PWM at 50Hz, turns a needle with the latest valid reading in
+//! every mode. Its range, pulse widths and slew rate are set with `servo`
+//! on the console and kept in the `state` partition.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_time::{Duration, Ticker};
+use esp_hal::gpio::DriveMode;
+use esp_hal::ledc::channel::{self, Channel, ChannelHW, ChannelIFace};
+use esp_hal::ledc::timer::{self, TimerIFace};
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+use esp_hal::peripherals::{GPIO39, LEDC};
+use esp_hal::time::Rate;
+use hall_effect::servo::{self, Settings, Slew};
+use hall_effect::verbosity::Module;
+use static_cell::StaticCell;
+
+use crate::reading::LATEST;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+// One step a frame: the servo takes no more than that
+const FRAME: Duration = Duration::from_millis(servo::PERIOD_US as u64 / 1000);
+const PWM_FREQUENCY: Rate = Rate::from_hz(1_000_000 / servo::PERIOD_US);
+// About 1.2us a step, or 0.2 degrees over the usual travel
+const DUTY_RANGE: u32 = 1 << 14;
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+/// From 0 to 1, once a reading has been seen.
+static POSITION: Mutex<Cell<Option<f32>>> = Mutex::new(Cell::new(None));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next frame, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_servo_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Servo settings not saved: {}", e);
+    }
+}
+
+/// The needle's angle along the travel.
+pub fn angle_deg() -> Option<f32> {
+    critical_section::with(|cs| POSITION.borrow(cs).get()).map(|p| p * servo::TRAVEL_DEG)
+}
+
+/// Sets up the PWM, with no pulses until the first reading.
+pub fn init(ledc: LEDC<'static>, pin: GPIO39<'static>) -> Option<Channel<'static, LowSpeed>> {
+    static TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+    let mut ledc = Ledc::new(ledc);
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let timer = TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+    if let Err(e) = timer.configure(timer::config::Config {
+        duty: timer::config::Duty::Duty14Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: PWM_FREQUENCY,
+    }) {
+        log!(Module::Display, warn, "Servo PWM unavailable: {}", e);
+        return None;
+    }
+    let mut channel = ledc.channel(channel::Number::Channel0, pin);
+    if let Err(e) = channel.configure(channel::config::Config {
+        timer: &*timer,
+        duty_pct: 0,
+        drive_mode: DriveMode::PushPull,
+    }) {
+        log!(Module::Display, warn, "Servo PWM unavailable: {}", e);
+        return None;
+    }
+    Some(channel)
+}
+
+#[embassy_executor::task]
+pub async fn servo_task(channel: Channel<'static, LowSpeed>) {
+    let mut slew = Slew::new();
+    let mut ticker = Ticker::every(FRAME);
+    let dt_s = FRAME.as_micros() as f32 / 1e6;
+    loop {
+        ticker.next().await;
+        // Invalid readings leave the needle where it is
+        let Some(reading) = LATEST.try_get().filter(|reading| reading.valid) else {
+            continue;
+        };
+        let settings = settings();
+        let position = slew.step(
+            settings.position(reading.field_mt),
+            settings.slew_deg_per_s,
+            dt_s,
+        );
+        let duty = settings.pulse_us(position) / servo::PERIOD_US as f32;
+        channel.set_duty_hw((duty * DUTY_RANGE as f32) as u32);
+        critical_section::with(|cs| POSITION.borrow(cs).set(Some(position)));
+    }
+}
@@ -0,0 +1,323 @@
+//! Low-power mode: the chip spends its time in deep sleep and wakes every
+//! [`WAKE_INTERVAL`] to take one reading, then goes straight back to sleep.
+//!
+//! A timer wake skips everything but the ADC, so the totals it keeps (pulse
+//! count, range, the threshold detector's side) live in RTC RAM along with
+//! the zero-field calibration. Pressing BOOT (RESET on the C3 and C6, whose
+//! BOOT pin cannot wake them) wakes the chip for a normal boot, which ends
+//! low-power mode and adds the pulses counted while asleep to the odometer.
+//!
+//! With `ulp-wake`, the ULP also watches the field while the chip sleeps and
+//! wakes it on a threshold crossing, so the timer only needs to wake it for
+//! an occasional heartbeat.
+
+use core::time::Duration;
+
+use defmt::Format;
+use embassy_time::Timer;
+use esp_hal::peripherals::LPWR;
+#[cfg(feature = "ulp-wake")]
+use esp_hal::rtc_cntl::sleep::UlpWakeupSource;
+#[cfg(any(feature = "esp32s3", feature = "esp32s2"))]
+use esp_hal::rtc_cntl::sleep::{Ext0WakeupSource, WakeupLevel};
+use esp_hal::rtc_cntl::sleep::{TimerWakeupSource, WakeSource};
+use esp_hal::rtc_cntl::{Rtc, SocResetReason};
+use esp_hal::system::SleepSource;
+use hall_effect::schema::{Config, Event};
+use hall_effect::threshold::ThresholdDetector;
+use hall_effect::verbosity::Module;
+
+use crate::board::ButtonGpio;
+use crate::chip;
+use crate::flash_log::FLASH_LOG;
+use crate::reading::LATEST;
+use crate::sensor::{self, SensorAdc, SensorPin};
+use crate::state::STATE;
+#[cfg(feature = "ulp-wake")]
+use crate::ulp;
+use crate::verbosity::log;
+
+#[cfg(not(feature = "ulp-wake"))]
+pub const WAKE_INTERVAL: Duration = Duration::from_secs(60);
+#[cfg(feature = "ulp-wake")]
+pub const WAKE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Readings averaged on each wake
+const SAMPLES_PER_WAKE: u32 = 8;
+
+const MAGIC: u32 = 0x534c_5050;
+
+// Stand-ins for `Option`s and `bool`s, as RTC RAM may hold any bit pattern
+const NONE: u32 = u32::MAX;
+const UNKNOWN: u8 = 0;
+const BELOW: u8 = 1;
+const ABOVE: u8 = 2;
+
+#[repr(C)]
+struct Retained {
+    magic: u32,
+    active: u8,
+    /// Side of the threshold, as `UNKNOWN`, `BELOW` or `ABOVE`.
+    side: u8,
+    zero_field_mv: u32,
+    wakes: u32,
+    pulses: u32,
+    min_mv: u32,
+    max_mv: u32,
+}
+
+unsafe impl esp_hal::Persistable for Retained {}
+
+const CLEARED: Retained = Retained {
+    magic: MAGIC,
+    active: 0,
+    side: UNKNOWN,
+    zero_field_mv: NONE,
+    wakes: 0,
+    pulses: 0,
+    min_mv: NONE,
+    max_mv: 0,
+};
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut RETAINED: Retained = Retained { magic: 0, ..CLEARED };
+
+/// Totals since low-power mode was entered.
+#[derive(Clone, Copy, Debug, Format)]
+pub struct Totals {
+    pub wakes: u32,
+    pub pulses: u32,
+    /// Lowest and highest reading, if any was taken.
+    pub range_mv: Option<(u32, u32)>,
+}
+
+/// Runs `f` on the retained state, first clearing it if it does not survive
+/// from an earlier boot (a power cycle leaves RTC RAM undefined).
+fn with_retained<R>(f: impl FnOnce(&mut Retained) -> R) -> R {
+    let retained = &raw mut RETAINED;
+    critical_section::with(|_| {
+        let retained = unsafe { &mut *retained };
+        if retained.magic != MAGIC {
+            *retained = CLEARED;
+        }
+        f(retained)
+    })
+}
+
+/// `config` with the retained calibration applied, so a calibration
+/// survives sleep and any reset short of a power cycle.
+pub fn restore(mut config: Config) -> Config {
+    let zero_field_mv = with_retained(|r| r.zero_field_mv);
+    if zero_field_mv != NONE {
+        config.zero_field_mv = zero_field_mv;
+    }
+    config
+}
+
+pub fn set_zero_field_mv(zero_field_mv: u32) {
+    with_retained(|r| r.zero_field_mv = zero_field_mv);
+}
+
+/// Clears the retained state, calibration included, so a factory reset
+/// does not leave it to outlive the erased store until a power cycle.
+pub fn forget() {
+    with_retained(|r| *r = CLEARED);
+}
+
+/// Whether this boot is a timer (or ULP) wake in low-power mode. Any other
+/// boot ends the mode.
+pub fn is_timer_wake(reset_reason: Option<SocResetReason>) -> bool {
+    let timer_wake = reset_reason == Some(SocResetReason::CoreDeepSleep)
+        && match esp_hal::rtc_cntl::wakeup_cause() {
+            SleepSource::Timer => true,
+            #[cfg(feature = "ulp-wake")]
+            SleepSource::Ulp => true,
+            _ => false,
+        };
+    with_retained(|r| {
+        let active = r.active != 0;
+        if !timer_wake {
+            r.active = 0;
+        }
+        active && timer_wake
+    })
+}
+
+/// Ends low-power mode's bookkeeping, returning the totals so the pulses
+/// can be added to the odometer. `None` if there was nothing to collect.
+pub fn take_totals() -> Option<Totals> {
+    with_retained(|r| {
+        let totals = (r.wakes > 0).then_some(Totals {
+            wakes: r.wakes,
+            pulses: r.pulses,
+            range_mv: (r.min_mv != NONE).then_some((r.min_mv, r.max_mv)),
+        });
+        r.wakes = 0;
+        r.pulses = 0;
+        r.min_mv = NONE;
+        r.max_mv = 0;
+        r.side = UNKNOWN;
+        totals
+    })
+}
+
+/// Saves what would otherwise be lost in deep sleep: the flash log's
+/// buffered records and the uptime and odometer.
+pub async fn prepare() {
+    if let Some(log) = FLASH_LOG.lock().await.as_mut()
+        && let Err(e) = log.flush().await
+    {
+        log!(Module::Storage, warn, "Flash log flush failed: {}", e);
+    }
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.checkpoint().await
+    {
+        log!(Module::Storage, warn, "State checkpoint failed: {}", e);
+    }
+}
+
+/// Sleeps until the BOOT button is pressed, taking no readings, to protect
+/// a flat battery.
+#[cfg(feature = "battery")]
+pub fn shutdown() -> ! {
+    log!(
+        Module::Supply,
+        warn,
+        "Battery flat, shutting down until {} is pressed",
+        chip::WAKE_BUTTON
+    );
+    // SAFETY: as in `enter`
+    let (lpwr, button) = unsafe { (LPWR::steal(), ButtonGpio::steal()) };
+    sleep_deep(Rtc::new(lpwr), button, &[])
+}
+
+/// Enters low-power mode from a normal boot.
+pub fn enter(config: &Config) -> ! {
+    log!(
+        Module::Supply,
+        info,
+        "Entering low-power mode, press {} to wake",
+        chip::WAKE_BUTTON
+    );
+    // Start from the side of the threshold the field is on now, so the first
+    // crossing counts
+    let side = match LATEST.try_get() {
+        Some(reading) if reading.valid => {
+            if reading.sample.voltage_mv >= config.threshold_mv {
+                ABOVE
+            } else {
+                BELOW
+            }
+        }
+        _ => UNKNOWN,
+    };
+    with_retained(|r| {
+        r.active = 1;
+        r.side = side;
+    });
+    // SAFETY: nothing else runs once the chip is asleep, and the chip resets
+    // on waking
+    let (lpwr, button) = unsafe { (LPWR::steal(), ButtonGpio::steal()) };
+    deep_sleep(lpwr, button, config)
+}
+
+/// Takes one averaged reading, updates the totals and sleeps until the next
+/// wake.
+pub async fn wake_cycle(
+    adc: &mut SensorAdc,
+    pin: &mut SensorPin,
+    config: &Config,
+    lpwr: LPWR<'static>,
+    button: ButtonGpio,
+) -> ! {
+    let mut sum_mv = 0;
+    let mut count = 0;
+    for _ in 0..SAMPLES_PER_WAKE {
+        if let Ok(sample) = sensor::read_sample(|| sensor::convert(adc, pin)).await {
+            sum_mv += sample.voltage_mv;
+            count += 1;
+        }
+        Timer::after_millis(1).await;
+    }
+
+    let totals = with_retained(|r| {
+        r.wakes += 1;
+        if let Some(voltage_mv) = sum_mv.checked_div(count) {
+            r.min_mv = r.min_mv.min(voltage_mv);
+            r.max_mv = r.max_mv.max(voltage_mv);
+
+            let side = match r.side {
+                BELOW => Some(false),
+                ABOVE => Some(true),
+                _ => None,
+            };
+            let mut threshold = ThresholdDetector::resume(side);
+            if let Some(Event::ThresholdCrossed { rising: true, .. }) =
+                threshold.update(voltage_mv, config)
+            {
+                r.pulses += 1;
+            }
+            r.side = match threshold.is_above() {
+                Some(true) => ABOVE,
+                Some(false) => BELOW,
+                None => UNKNOWN,
+            };
+        }
+        Totals {
+            wakes: r.wakes,
+            pulses: r.pulses,
+            range_mv: (r.min_mv != NONE).then_some((r.min_mv, r.max_mv)),
+        }
+    });
+    match count {
+        0 => log!(Module::Sensor, warn, "No reading this wake"),
+        _ => log!(
+            Module::Sample,
+            info,
+            "Voltage: {}mV, {}",
+            sum_mv / count,
+            totals
+        ),
+    }
+
+    deep_sleep(lpwr, button, config)
+}
+
+fn deep_sleep(
+    lpwr: LPWR<'static>,
+    button: ButtonGpio,
+    #[cfg_attr(not(feature = "ulp-wake"), expect(unused_variables))] config: &Config,
+) -> ! {
+    let rtc = Rtc::new(lpwr);
+    let timer = TimerWakeupSource::new(WAKE_INTERVAL);
+    #[cfg(not(feature = "ulp-wake"))]
+    sleep_deep(rtc, button, &[&timer]);
+    #[cfg(feature = "ulp-wake")]
+    {
+        // Until the side is known the heartbeat has to find it
+        match with_retained(|r| r.side) {
+            BELOW => ulp::arm(config, false),
+            ABOVE => ulp::arm(config, true),
+            _ => {}
+        }
+        sleep_deep(rtc, button, &[&timer, &UlpWakeupSource::new()])
+    }
+}
+
+/// Sleeps until one of `sources` or, where it can, the button wakes the
+/// chip.
+fn sleep_deep(mut rtc: Rtc<'static>, button: ButtonGpio, sources: &[&dyn WakeSource]) -> ! {
+    #[cfg(any(feature = "esp32s3", feature = "esp32s2"))]
+    {
+        let ext0 = Ext0WakeupSource::new(button, WakeupLevel::Low);
+        let mut all: heapless::Vec<&dyn WakeSource, 3> = heapless::Vec::new();
+        let _ = all.push(&ext0);
+        let _ = all.extend_from_slice(sources);
+        rtc.sleep_deep(&all)
+    }
+    #[cfg(any(feature = "esp32c3", feature = "esp32c6"))]
+    {
+        let _ = button;
+        rtc.sleep_deep(sources)
+    }
+}
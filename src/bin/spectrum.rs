@@ -0,0 +1,117 @@
+//! Spectrum analysis of the field: in spectrum mode, of each block of
+//! readings at the sample rate; on request from the console, of the last
+//! burst, whose rate reaches mains and motor frequencies.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use hall_effect::schema::Sample;
+use hall_effect::spectrum::{self, MAX_POINTS, Peak};
+use hall_effect::verbosity::Module;
+
+use crate::burst;
+use crate::verbosity::log;
+
+/// Readings per block in spectrum mode, 2.56s at the default rate.
+pub const POINTS: usize = 256;
+pub const PEAKS: usize = 3;
+
+#[derive(Clone, Copy)]
+pub struct Analysis {
+    pub points: usize,
+    pub rate_hz: f32,
+    pub peaks: [Option<Peak>; PEAKS],
+}
+
+static LATEST: Mutex<Cell<Option<Analysis>>> = Mutex::new(Cell::new(None));
+/// For a burst's larger blocks, held across the analysis without blocking
+/// interrupts.
+static BUFFERS: AsyncMutex<CriticalSectionRawMutex, ([f32; MAX_POINTS], [f32; MAX_POINTS])> =
+    AsyncMutex::new(([0.0; MAX_POINTS], [0.0; MAX_POINTS]));
+
+/// Gathers readings in spectrum mode and analyses each full block.
+pub struct Collector {
+    signal: [f32; POINTS],
+    len: usize,
+    first_us: u64,
+}
+
+impl Collector {
+    pub const fn new() -> Self {
+        Self {
+            signal: [0.0; POINTS],
+            len: 0,
+            first_us: 0,
+        }
+    }
+
+    /// Takes a reading, logging the block's peaks once it is full.
+    pub fn push(&mut self, sample: &Sample) {
+        if self.len == 0 {
+            self.first_us = sample.timestamp_us;
+        }
+        self.signal[self.len] = sample.voltage_mv as f32;
+        self.len += 1;
+        if self.len < POINTS {
+            return;
+        }
+        self.len = 0;
+
+        // The measured rate, as quiet-field or low-battery sampling may
+        // have stretched the period
+        let elapsed_us = sample.timestamp_us.saturating_sub(self.first_us).max(1);
+        let rate_hz = (POINTS - 1) as f32 * 1e6 / elapsed_us as f32;
+        let mut scratch = [0.0; POINTS];
+        let analysis = Analysis {
+            points: POINTS,
+            rate_hz,
+            peaks: spectrum::peaks(&mut self.signal, &mut scratch, rate_hz),
+        };
+        critical_section::with(|cs| LATEST.borrow(cs).set(Some(analysis)));
+        for peak in analysis.peaks.iter().flatten() {
+            log!(
+                Module::Sample,
+                info,
+                "Peak: {}Hz, {}mV",
+                peak.frequency_hz,
+                peak.amplitude_mv
+            );
+        }
+    }
+}
+
+/// The last block analysed in spectrum mode.
+pub fn latest() -> Option<Analysis> {
+    critical_section::with(|cs| LATEST.borrow(cs).get())
+}
+
+/// Analyses the first `points` readings of the last burst; `None` if there
+/// are fewer, or `points` is not a size the analysis takes.
+pub async fn of_burst(points: usize) -> Option<Analysis> {
+    let summary = burst::summary()?;
+    if !spectrum::valid_points(points) || (summary.samples as usize) < points {
+        return None;
+    }
+    let mut buffers = BUFFERS.lock().await;
+    let (signal, scratch) = &mut *buffers;
+    let mut chunk = [0u16; 64];
+    let mut index = 0;
+    while index < points {
+        let n = burst::read(index, 1, &mut chunk).min(points - index);
+        if n == 0 {
+            return None;
+        }
+        for (slot, &raw) in signal[index..].iter_mut().zip(&chunk[..n]) {
+            *slot = hall_effect::burst::voltage_mv(raw) as f32;
+        }
+        index += n;
+    }
+    let rate_hz = summary.rate_hz as f32;
+    Some(Analysis {
+        points,
+        rate_hz,
+        peaks: spectrum::peaks(&mut signal[..points], &mut scratch[..points], rate_hz),
+    })
+}
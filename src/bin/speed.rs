@@ -0,0 +1,159 @@
+//! Speed control in tachometer mode: with the `speed-control` feature, a
+//! brushed motor driven through a MOSFET from LEDC PWM on GPIO39 is held
+//! at the speed set with `speed` on the console, measured from the
+//! magnet's pulses, one per revolution. The PI loop runs on every reading
+//! rather than every pulse, so it sees a stalling motor slow down.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+#[cfg(feature = "speed-control")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "speed-control")]
+use embassy_sync::signal::Signal;
+#[cfg(feature = "speed-control")]
+use esp_hal::gpio::DriveMode;
+#[cfg(feature = "speed-control")]
+use esp_hal::ledc::channel::{self, Channel, ChannelHW, ChannelIFace};
+#[cfg(feature = "speed-control")]
+use esp_hal::ledc::timer::{self, TimerIFace};
+#[cfg(feature = "speed-control")]
+use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed};
+#[cfg(feature = "speed-control")]
+use esp_hal::peripherals::{GPIO39, LEDC};
+#[cfg(feature = "speed-control")]
+use esp_hal::time::Rate;
+use hall_effect::pid::{Gains, Pid};
+#[cfg(feature = "speed-control")]
+use hall_effect::verbosity::Module;
+#[cfg(feature = "speed-control")]
+use static_cell::StaticCell;
+
+#[cfg(feature = "speed-control")]
+use crate::verbosity::log;
+
+#[cfg(feature = "speed-control")]
+const PWM_FREQUENCY: Rate = Rate::from_khz(20);
+#[cfg(feature = "speed-control")]
+const DUTY_RANGE: u32 = 1 << 10;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// Zero for the motor off.
+    pub setpoint_rpm: u32,
+    pub gains: Gains,
+}
+
+// Untuned, in duty per rpm
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings {
+    setpoint_rpm: 0,
+    gains: Gains {
+        kp: 0.0005,
+        ki: 0.001,
+        kd: 0.0,
+    },
+}));
+static DUTY: Mutex<Cell<f32>> = Mutex::new(Cell::new(0.0));
+/// The motor's next duty, for [`motor_task`], which owns the channel.
+#[cfg(feature = "speed-control")]
+static MOTOR: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes effect from the next reading in tachometer mode.
+pub fn configure(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// The duty last set, from 0 to 1.
+pub fn duty() -> f32 {
+    critical_section::with(|cs| DUTY.borrow(cs).get())
+}
+
+/// Sets up the PWM, with the motor off, at boot.
+#[cfg(feature = "speed-control")]
+pub fn init(ledc: LEDC<'static>, pin: GPIO39<'static>) -> Option<Channel<'static, LowSpeed>> {
+    static TIMER: StaticCell<timer::Timer<'static, LowSpeed>> = StaticCell::new();
+
+    let mut ledc = Ledc::new(ledc);
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let timer = TIMER.init(ledc.timer::<LowSpeed>(timer::Number::Timer0));
+    if let Err(e) = timer.configure(timer::config::Config {
+        duty: timer::config::Duty::Duty10Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: PWM_FREQUENCY,
+    }) {
+        log!(Module::Sensor, warn, "Motor PWM unavailable: {}", e);
+        return None;
+    }
+    let mut motor = ledc.channel(channel::Number::Channel0, pin);
+    if let Err(e) = motor.configure(channel::config::Config {
+        timer: &*timer,
+        duty_pct: 0,
+        drive_mode: DriveMode::PushPull,
+    }) {
+        log!(Module::Sensor, warn, "Motor PWM unavailable: {}", e);
+        return None;
+    }
+    Some(motor)
+}
+
+#[cfg(feature = "speed-control")]
+#[embassy_executor::task]
+pub async fn motor_task(motor: Channel<'static, LowSpeed>) {
+    loop {
+        motor.set_duty_hw(MOTOR.wait().await);
+    }
+}
+
+fn set_duty(duty: f32) {
+    critical_section::with(|cs| DUTY.borrow(cs).set(duty));
+    #[cfg(feature = "speed-control")]
+    MOTOR.signal((duty.clamp(0.0, 1.0) * DUTY_RANGE as f32) as u32);
+}
+
+/// The processing stage's half, for tachometer mode.
+pub struct Loop {
+    pid: Pid,
+    last_us: Option<u64>,
+}
+
+impl Loop {
+    pub const fn new() -> Self {
+        Self {
+            pid: Pid::new(Gains {
+                kp: 0.0,
+                ki: 0.0,
+                kd: 0.0,
+            }),
+            last_us: None,
+        }
+    }
+
+    /// Takes the speed measured at `timestamp_us`.
+    pub fn push(&mut self, timestamp_us: u64, rpm: f32) {
+        let settings = settings();
+        if settings.setpoint_rpm == 0 {
+            if self.last_us.take().is_some() {
+                self.pid = Pid::new(settings.gains);
+                set_duty(0.0);
+            }
+            return;
+        }
+        let dt_s = self.last_us.replace(timestamp_us).map_or(0.0, |last_us| {
+            timestamp_us.saturating_sub(last_us) as f32 / 1e6
+        });
+        self.pid.set_gains(settings.gains);
+        set_duty(self.pid.update(settings.setpoint_rpm as f32, rpm, dt_s));
+    }
+
+    /// On leaving tachometer mode: the motor is switched off, and starts
+    /// from rest next time.
+    pub fn stop(&mut self) {
+        self.last_us = None;
+        self.pid = Pid::new(settings().gains);
+        set_duty(0.0);
+    }
+}
@@ -0,0 +1,609 @@
+//! Boot counter, uptime statistics, the pulse odometer, the energy total,
+//! the latched tamper alarm and the settings, kept in the `state` flash
+//! partition so they survive resets. The settings are one versioned
+//! document, see `hall_effect::settings`, saved whole on each change to
+//! two slots in turn.
+//!
+//! The current session's uptime, the pulse count and the energy total are
+//! checkpointed every few minutes rather than on every change, to limit
+//! flash wear; a spontaneous reset loses at most one checkpoint interval.
+
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Timer};
+use hall_effect::ambient::Settings as AmbientSettings;
+use hall_effect::contact::{self, Settings as ContactSettings};
+use hall_effect::counting::{self, Settings as CountSettings};
+use hall_effect::current::{self, Scale};
+use hall_effect::filter::{self, Chain};
+use hall_effect::fixture::{self, Limits as FixtureLimits};
+use hall_effect::grading::{self, Settings as GradeSettings};
+use hall_effect::night::Settings as NightSettings;
+use hall_effect::palette::Palette;
+use hall_effect::pas::{self, Settings as PasSettings};
+use hall_effect::profile::{self, Profile};
+use hall_effect::relay::{self, Settings as RelaySettings};
+use hall_effect::remote;
+use hall_effect::rules::Rules;
+use hall_effect::schedule::Entries as Schedule;
+use hall_effect::schema::{Config, Tamper};
+use hall_effect::servo::{self, Settings as ServoSettings};
+use hall_effect::settings::{self, DecodeError, Document, Loaded, Slot};
+use hall_effect::sync::{self, Role as SyncRole};
+use hall_effect::tamper::{self, Settings as TamperSettings};
+use hall_effect::trigger::{self, Settings as TriggerSettings};
+use hall_effect::units::Unit;
+use hall_effect::verbosity::Module;
+use hall_effect::x27::{self, Scale as GaugeScale};
+use sequential_storage::cache::{Cache, Uncached};
+use sequential_storage::map::{MapConfig, MapStorage, Value};
+use serde::Deserialize;
+
+use crate::clock;
+use crate::power;
+use crate::storage::{self, FlashPartition};
+use crate::supply;
+use crate::verbosity::log;
+use crate::watchdog;
+
+const BOOT_COUNT: u8 = 0;
+const UPTIME_TOTAL_S: u8 = 1;
+const SESSION_UPTIME_S: u8 = 2;
+const RESET_REASON: u8 = 3;
+const PULSE_COUNT: u8 = 4;
+const STALLED_TASK: u8 = 5;
+const FILTER_CHAIN: u8 = 6;
+const TARE_MT: u8 = 7;
+const CURRENT_SCALE: u8 = 8;
+const ENERGY_MJ: u8 = 9;
+const VOLTAGE_RATIO: u8 = 10;
+const PAS_SETTINGS: u8 = 11;
+const SERVO_SETTINGS: u8 = 12;
+const GAUGE_SCALE: u8 = 13;
+const RELAY_SETTINGS: u8 = 14;
+const CONTACT_SETTINGS: u8 = 15;
+const TAMPER_SETTINGS: u8 = 16;
+const TAMPER_ALARM: u8 = 17;
+const COUNT_SETTINGS: u8 = 18;
+const FIXTURE_LIMITS: u8 = 19;
+const GRADE_SETTINGS: u8 = 20;
+const TRIGGER_SETTINGS: u8 = 21;
+const SYNC_ROLE: u8 = 22;
+const CONFIG: u8 = 23;
+// The settings document's two slots, saved to in turn
+const SETTINGS_A: u8 = 24;
+const SETTINGS_B: u8 = 25;
+// The first of profile::MAX_PROFILES keys, one per profile slot
+const PROFILES: u8 = 26;
+
+// Earlier firmware kept each setting in an item of its own; they are
+// gathered into the settings document on the first boot without one
+const LEGACY_SETTINGS: [u8; 16] = [
+    FILTER_CHAIN,
+    TARE_MT,
+    CURRENT_SCALE,
+    VOLTAGE_RATIO,
+    PAS_SETTINGS,
+    SERVO_SETTINGS,
+    GAUGE_SCALE,
+    RELAY_SETTINGS,
+    CONTACT_SETTINGS,
+    TAMPER_SETTINGS,
+    COUNT_SETTINGS,
+    FIXTURE_LIMITS,
+    GRADE_SETTINGS,
+    TRIGGER_SETTINGS,
+    SYNC_ROLE,
+    CONFIG,
+];
+
+// Room for the largest item, the settings document, and its key
+const ITEM_SIZE: usize = settings::ENCODED_SIZE + 16;
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(600);
+
+pub static STATE: Mutex<CriticalSectionRawMutex, Option<State>> = Mutex::new(None);
+
+type StateCache = Cache<Uncached, Uncached, Uncached, u8>;
+
+#[derive(Debug, Format)]
+pub enum Error {
+    NoPartition,
+    Storage(sequential_storage::Error<storage::Error>),
+}
+
+impl From<sequential_storage::Error<storage::Error>> for Error {
+    fn from(e: sequential_storage::Error<storage::Error>) -> Self {
+        Error::Storage(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Format)]
+pub struct BootStats {
+    pub boot_count: u32,
+    /// Uptime of all previous sessions, as of their last checkpoint.
+    pub previous_uptime_s: u64,
+    pub last_session_s: u64,
+    /// Raw `SocResetReason` code of this boot.
+    pub reset_reason: Option<u8>,
+    /// Task blamed for this boot's watchdog reset, if it was one.
+    pub stalled_task: Option<watchdog::Task>,
+    /// The settings copy loaded, `None` if there was none to load.
+    pub settings: Option<Loaded>,
+}
+
+pub struct State {
+    map: MapStorage<u8, FlashPartition, StateCache>,
+    stats: BootStats,
+    pulses: u64,
+    saved_pulses: u64,
+    saved_energy_mj: u64,
+    settings: Document,
+    /// The slot holding the latest copy, and its sequence number.
+    settings_slot: Option<Slot>,
+    settings_sequence: u32,
+}
+
+impl State {
+    /// Opens the store and records this boot.
+    pub async fn new(
+        partition: Option<FlashPartition>,
+        reset_reason: Option<u8>,
+        stalled_task: Option<watchdog::Task>,
+    ) -> Result<Self, Error> {
+        let partition = partition.ok_or(Error::NoPartition)?;
+        let config = MapConfig::try_new(0..partition.size()).map_err(|_| Error::NoPartition)?;
+        let mut state = Self {
+            map: MapStorage::new(partition, config, Cache::new_uncached()),
+            stats: BootStats {
+                boot_count: 0,
+                previous_uptime_s: 0,
+                last_session_s: 0,
+                reset_reason,
+                stalled_task,
+                settings: None,
+            },
+            pulses: 0,
+            saved_pulses: 0,
+            saved_energy_mj: 0,
+            settings: Document::default(),
+            settings_slot: None,
+            settings_sequence: 0,
+        };
+
+        let boot_count = state.get::<u32>(BOOT_COUNT).await?.unwrap_or(0) + 1;
+        let last_session_s = state.get::<u64>(SESSION_UPTIME_S).await?.unwrap_or(0);
+        let previous_uptime_s =
+            state.get::<u64>(UPTIME_TOTAL_S).await?.unwrap_or(0) + last_session_s;
+
+        state.set(BOOT_COUNT, &boot_count).await?;
+        state.set(UPTIME_TOTAL_S, &previous_uptime_s).await?;
+        state.set(SESSION_UPTIME_S, &0u64).await?;
+        if let Some(reason) = reset_reason {
+            state.set(RESET_REASON, &reason).await?;
+        }
+        state
+            .set(STALLED_TASK, &stalled_task.map(|t| t as u8))
+            .await?;
+
+        state.stats.boot_count = boot_count;
+        state.stats.previous_uptime_s = previous_uptime_s;
+        state.stats.last_session_s = last_session_s;
+        state.pulses = state.get::<u64>(PULSE_COUNT).await?.unwrap_or(0);
+        state.saved_pulses = state.pulses;
+        state.saved_energy_mj = state.get::<u64>(ENERGY_MJ).await?.unwrap_or(0);
+        state.settings = state.load_settings().await?;
+        Ok(state)
+    }
+
+    pub fn stats(&self) -> &BootStats {
+        &self.stats
+    }
+
+    /// Lifetime count of rising threshold crossings.
+    pub fn pulses(&self) -> u64 {
+        self.pulses
+    }
+
+    pub fn add_pulse(&mut self) {
+        self.pulses += 1;
+    }
+
+    /// Adds pulses counted elsewhere, e.g. while in low-power mode.
+    pub fn add_pulses(&mut self, count: u64) {
+        self.pulses += count;
+    }
+
+    /// Clears the pulse count and saves it straight away.
+    pub async fn reset_pulses(&mut self) -> Result<(), Error> {
+        self.pulses = 0;
+        self.set(PULSE_COUNT, &0u64).await?;
+        self.saved_pulses = 0;
+        Ok(())
+    }
+
+    /// The energy total as of the last checkpoint, for power mode to carry
+    /// on from.
+    pub fn energy_mj(&self) -> u64 {
+        self.saved_energy_mj
+    }
+
+    /// Clears the energy total and saves it straight away.
+    pub async fn reset_energy(&mut self) -> Result<(), Error> {
+        power::restore_energy(0);
+        self.set(ENERGY_MJ, &0u64).await?;
+        self.saved_energy_mj = 0;
+        Ok(())
+    }
+
+    /// The settings as last saved, restored at boot.
+    pub fn settings(&self) -> &Document {
+        &self.settings
+    }
+
+    pub async fn save_filters(&mut self, chain: &Chain) -> Result<(), Error> {
+        self.settings.filters = Some(*chain);
+        self.save_settings().await
+    }
+
+    /// Zero once cleared.
+    pub async fn save_tare(&mut self, offset_mt: f32) -> Result<(), Error> {
+        self.settings.tare_mt = Some(offset_mt);
+        self.save_settings().await
+    }
+
+    pub async fn save_current_scale(&mut self, scale: &Scale) -> Result<(), Error> {
+        self.settings.current_scale = Some(*scale);
+        self.save_settings().await
+    }
+
+    pub async fn save_voltage_ratio(&mut self, ratio: f32) -> Result<(), Error> {
+        self.settings.voltage_ratio = Some(ratio);
+        self.save_settings().await
+    }
+
+    pub async fn save_pas_settings(&mut self, settings: &PasSettings) -> Result<(), Error> {
+        self.settings.pas = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_contact_settings(&mut self, settings: &ContactSettings) -> Result<(), Error> {
+        self.settings.contact = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_count_settings(&mut self, settings: &CountSettings) -> Result<(), Error> {
+        self.settings.count = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_fixture_limits(&mut self, limits: &FixtureLimits) -> Result<(), Error> {
+        self.settings.fixture = Some(*limits);
+        self.save_settings().await
+    }
+
+    pub async fn save_grade_settings(&mut self, settings: &GradeSettings) -> Result<(), Error> {
+        self.settings.grading = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_tamper_settings(&mut self, settings: &TamperSettings) -> Result<(), Error> {
+        self.settings.tamper = Some(*settings);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "servo"), expect(dead_code))]
+    pub async fn save_servo_settings(&mut self, settings: &ServoSettings) -> Result<(), Error> {
+        self.settings.servo = Some(*settings);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "x27-gauge"), expect(dead_code))]
+    pub async fn save_gauge_scale(&mut self, scale: &GaugeScale) -> Result<(), Error> {
+        self.settings.gauge_scale = Some(*scale);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "trigger-out"), expect(dead_code))]
+    pub async fn save_trigger_settings(&mut self, settings: &TriggerSettings) -> Result<(), Error> {
+        self.settings.trigger = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_config(&mut self, config: &Config) -> Result<(), Error> {
+        self.settings.config = Some(*config);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "sync"), expect(dead_code))]
+    pub async fn save_sync_role(&mut self, role: &SyncRole) -> Result<(), Error> {
+        self.settings.sync_role = Some(*role);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "relay"), expect(dead_code))]
+    pub async fn save_relay_settings(&mut self, settings: &RelaySettings) -> Result<(), Error> {
+        self.settings.relay = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_unit(&mut self, unit: &Unit) -> Result<(), Error> {
+        self.settings.unit = Some(*unit);
+        self.save_settings().await
+    }
+
+    pub async fn save_rules(&mut self, rules: &Rules) -> Result<(), Error> {
+        self.settings.rules = Some(*rules);
+        self.save_settings().await
+    }
+
+    pub async fn save_schedule(&mut self, schedule: &Schedule) -> Result<(), Error> {
+        self.settings.schedule = Some(*schedule);
+        self.save_settings().await
+    }
+
+    pub async fn save_night_settings(&mut self, settings: &NightSettings) -> Result<(), Error> {
+        self.settings.night = Some(*settings);
+        self.save_settings().await
+    }
+
+    #[cfg_attr(not(feature = "ambient-light"), expect(dead_code))]
+    pub async fn save_ambient_settings(&mut self, settings: &AmbientSettings) -> Result<(), Error> {
+        self.settings.ambient = Some(*settings);
+        self.save_settings().await
+    }
+
+    pub async fn save_palette(&mut self, palette: &Palette) -> Result<(), Error> {
+        self.settings.palette = Some(*palette);
+        self.save_settings().await
+    }
+
+    pub async fn save_active_profile(&mut self, slot: usize) -> Result<(), Error> {
+        self.settings.profile = Some(slot as u8);
+        self.save_settings().await
+    }
+
+    /// The profile in `slot`, if one is saved there and reads back.
+    pub async fn profile(&mut self, slot: usize) -> Result<Option<Profile>, Error> {
+        let encoded = self
+            .get::<[u8; profile::ENCODED_SIZE]>(PROFILES + slot as u8)
+            .await?;
+        Ok(encoded.and_then(|encoded| postcard::from_bytes(&encoded).ok()))
+    }
+
+    pub async fn save_profile(&mut self, slot: usize, profile: &Profile) -> Result<(), Error> {
+        let mut encoded = [0u8; profile::ENCODED_SIZE];
+        // Any profile fits, as its tests check
+        let _ = postcard::to_slice(profile, &mut encoded);
+        self.set(PROFILES + slot as u8, &encoded).await
+    }
+
+    pub async fn remove_profile(&mut self, slot: usize) -> Result<(), Error> {
+        let mut buf = [0u8; ITEM_SIZE];
+        self.map
+            .remove_item(&mut buf, &(PROFILES + slot as u8))
+            .await?;
+        if self.settings.profile == Some(slot as u8) {
+            self.settings.profile = None;
+            self.save_settings().await?;
+        }
+        Ok(())
+    }
+
+    /// The tamper alarm latched and not yet cleared, if any.
+    pub async fn tamper_alarm(&mut self) -> Result<Option<Tamper>, Error> {
+        let encoded = self
+            .get::<[u8; tamper::ALARM_ENCODED_SIZE]>(TAMPER_ALARM)
+            .await?;
+        Ok(encoded.and_then(|encoded| {
+            postcard::from_bytes::<Option<Tamper>>(&encoded)
+                .ok()
+                .flatten()
+        }))
+    }
+
+    pub async fn save_tamper_alarm(&mut self, alarm: Option<Tamper>) -> Result<(), Error> {
+        let mut encoded = [0u8; tamper::ALARM_ENCODED_SIZE];
+        // Any alarm fits, as the tests check
+        let _ = postcard::to_slice(&alarm, &mut encoded);
+        self.set(TAMPER_ALARM, &encoded).await
+    }
+
+    /// Reads the later of the settings document's two copies, or builds
+    /// it from the items earlier firmware kept each setting in and
+    /// removes those.
+    async fn load_settings(&mut self) -> Result<Document, Error> {
+        let a = self.read_settings(SETTINGS_A).await?;
+        let b = self.read_settings(SETTINGS_B).await?;
+        for (slot, read) in [(Slot::A, a), (Slot::B, b)] {
+            if let Some(Err(e)) = read {
+                log!(
+                    Module::Storage,
+                    warn,
+                    "Settings copy {} unreadable: {}",
+                    slot.name(),
+                    e
+                );
+            }
+        }
+        let error = |read: Option<Result<_, DecodeError>>| read.and_then(Result::err);
+        match settings::newest(a.and_then(Result::ok), b.and_then(Result::ok)) {
+            Some((slot, stored)) => {
+                self.settings_slot = Some(slot);
+                self.settings_sequence = stored.sequence;
+                self.stats.settings = Some(Loaded {
+                    slot,
+                    sequence: stored.sequence,
+                    other_error: error(if slot == Slot::A { b } else { a }),
+                });
+                return Ok(stored.document);
+            }
+            None if a.is_some() || b.is_some() => {
+                log!(
+                    Module::Storage,
+                    warn,
+                    "No settings copy readable, using defaults"
+                );
+                return Ok(Document::default());
+            }
+            None => {}
+        }
+
+        let document = Document {
+            config: self
+                .legacy::<Config, { remote::ENCODED_SIZE }>(CONFIG)
+                .await?,
+            filters: self
+                .legacy::<Chain, { filter::ENCODED_SIZE }>(FILTER_CHAIN)
+                .await?,
+            tare_mt: self.get::<f32>(TARE_MT).await?,
+            current_scale: self
+                .legacy::<Scale, { current::ENCODED_SIZE }>(CURRENT_SCALE)
+                .await?,
+            voltage_ratio: self.get::<f32>(VOLTAGE_RATIO).await?,
+            pas: self
+                .legacy::<PasSettings, { pas::ENCODED_SIZE }>(PAS_SETTINGS)
+                .await?,
+            servo: self
+                .legacy::<ServoSettings, { servo::ENCODED_SIZE }>(SERVO_SETTINGS)
+                .await?,
+            gauge_scale: self
+                .legacy::<GaugeScale, { x27::ENCODED_SIZE }>(GAUGE_SCALE)
+                .await?,
+            relay: self
+                .legacy::<RelaySettings, { relay::ENCODED_SIZE }>(RELAY_SETTINGS)
+                .await?,
+            contact: self
+                .legacy::<ContactSettings, { contact::ENCODED_SIZE }>(CONTACT_SETTINGS)
+                .await?,
+            tamper: self
+                .legacy::<TamperSettings, { tamper::ENCODED_SIZE }>(TAMPER_SETTINGS)
+                .await?,
+            count: self
+                .legacy::<CountSettings, { counting::ENCODED_SIZE }>(COUNT_SETTINGS)
+                .await?,
+            fixture: self
+                .legacy::<FixtureLimits, { fixture::ENCODED_SIZE }>(FIXTURE_LIMITS)
+                .await?,
+            grading: self
+                .legacy::<GradeSettings, { grading::ENCODED_SIZE }>(GRADE_SETTINGS)
+                .await?,
+            trigger: self
+                .legacy::<TriggerSettings, { trigger::ENCODED_SIZE }>(TRIGGER_SETTINGS)
+                .await?,
+            sync_role: self
+                .legacy::<SyncRole, { sync::ENCODED_SIZE }>(SYNC_ROLE)
+                .await?,
+            // Settings added since had no item of their own
+            ..Document::default()
+        };
+        self.settings = document;
+        self.save_settings().await?;
+        let mut buf = [0u8; ITEM_SIZE];
+        for key in LEGACY_SETTINGS {
+            self.map.remove_item(&mut buf, &key).await?;
+        }
+        log!(
+            Module::Storage,
+            info,
+            "Settings gathered into one document, version {}",
+            settings::VERSION
+        );
+        Ok(document)
+    }
+
+    /// A setting as earlier firmware kept it, postcard-encoded in an item
+    /// of its own.
+    async fn legacy<T, const N: usize>(&mut self, key: u8) -> Result<Option<T>, Error>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let encoded = self.get::<[u8; N]>(key).await?;
+        Ok(encoded.and_then(|encoded| postcard::from_bytes(&encoded).ok()))
+    }
+
+    async fn read_settings(
+        &mut self,
+        key: u8,
+    ) -> Result<Option<Result<settings::Stored, DecodeError>>, Error> {
+        let mut buf = [0u8; ITEM_SIZE];
+        Ok(self
+            .map
+            .fetch_item::<&[u8]>(&mut buf, &key)
+            .await?
+            .map(settings::decode))
+    }
+
+    /// Saves the document over the older copy, so the later one survives
+    /// if the write is cut short.
+    async fn save_settings(&mut self) -> Result<(), Error> {
+        let slot = self.settings_slot.map_or(Slot::A, Slot::other);
+        let sequence = self.settings_sequence.wrapping_add(1);
+        let mut encoded = [0u8; settings::ENCODED_SIZE];
+        // Any document fits, as its tests check
+        let len = settings::encode(&self.settings, sequence, &mut encoded).map_or(0, |e| e.len());
+        let key = match slot {
+            Slot::A => SETTINGS_A,
+            Slot::B => SETTINGS_B,
+        };
+        let mut buf = [0u8; ITEM_SIZE];
+        self.map
+            .store_item(&mut buf, &key, &&encoded[..len])
+            .await?;
+        self.settings_slot = Some(slot);
+        self.settings_sequence = sequence;
+        Ok(())
+    }
+
+    /// Erases every stored value. The in-memory copies are left as they are,
+    /// so this is meant to be followed by a reset.
+    pub async fn erase(&mut self) -> Result<(), Error> {
+        self.map.erase_all().await?;
+        Ok(())
+    }
+
+    /// Saves the current session's uptime, and the pulse count and energy
+    /// total if changed.
+    /// Skipped while the supply is low.
+    pub async fn checkpoint(&mut self) -> Result<(), Error> {
+        if supply::is_low() {
+            return Ok(());
+        }
+        let session_s = clock::monotonic_us() / 1_000_000;
+        self.set(SESSION_UPTIME_S, &session_s).await?;
+        if self.pulses != self.saved_pulses {
+            let pulses = self.pulses;
+            self.set(PULSE_COUNT, &pulses).await?;
+            self.saved_pulses = pulses;
+        }
+        let energy_mj = power::energy_mj();
+        if energy_mj != self.saved_energy_mj {
+            self.set(ENERGY_MJ, &energy_mj).await?;
+            self.saved_energy_mj = energy_mj;
+        }
+        Ok(())
+    }
+
+    async fn get<V: for<'a> Value<'a>>(&mut self, key: u8) -> Result<Option<V>, Error> {
+        let mut buf = [0u8; ITEM_SIZE];
+        Ok(self.map.fetch_item(&mut buf, &key).await?)
+    }
+
+    async fn set<V: for<'a> Value<'a>>(&mut self, key: u8, value: &V) -> Result<(), Error> {
+        let mut buf = [0u8; ITEM_SIZE];
+        Ok(self.map.store_item(&mut buf, &key, value).await?)
+    }
+}
+
+#[embassy_executor::task]
+pub async fn checkpoint_task() {
+    loop {
+        Timer::after(CHECKPOINT_INTERVAL).await;
+        if let Some(state) = STATE.lock().await.as_mut()
+            && let Err(e) = state.checkpoint().await
+        {
+            log!(Module::Storage, warn, "State checkpoint failed: {}", e);
+        }
+    }
+}
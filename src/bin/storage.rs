@@ -0,0 +1,43 @@
+//! Shared access to the internal flash, split into the data partitions
+//! listed in partitions.csv.
+
+use embassy_embedded_hal::adapter::BlockingAsync;
+use embassy_embedded_hal::flash::partition::{self, Partition};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use esp_bootloader_esp_idf::partitions::{self as table, PARTITION_TABLE_MAX_LEN};
+use esp_hal::peripherals::FLASH;
+use esp_storage::{FlashStorage, FlashStorageError};
+use static_cell::StaticCell;
+
+pub type Flash = BlockingAsync<FlashStorage<'static>>;
+pub type FlashPartition = Partition<'static, CriticalSectionRawMutex, Flash>;
+pub type Error = partition::Error<FlashStorageError>;
+
+static FLASH_STORAGE: StaticCell<Mutex<CriticalSectionRawMutex, Flash>> = StaticCell::new();
+
+pub struct Partitions {
+    pub log: Option<FlashPartition>,
+    pub state: Option<FlashPartition>,
+}
+
+/// Reads the partition table and hands out the data partitions by label.
+/// A missing partition is reported as `None` rather than failing the rest.
+pub fn init(flash: FLASH<'static>) -> Partitions {
+    let mut storage = FlashStorage::new(flash);
+
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    let mut find = |label: &str| {
+        let table = table::read_partition_table(&mut storage, &mut buf).ok()?;
+        let entry = table.iter().find(|p| p.label_as_str() == label)?;
+        Some((entry.offset(), entry.len()))
+    };
+    let log = find("log");
+    let state = find("state");
+
+    let flash = &*FLASH_STORAGE.init(Mutex::new(BlockingAsync::new(storage)));
+    Partitions {
+        log: log.map(|(offset, len)| Partition::new(flash, offset, len)),
+        state: state.map(|(offset, len)| Partition::new(flash, offset, len)),
+    }
+}
@@ -0,0 +1,104 @@
+//! Supply monitoring through the chip's brown-out detector.
+//!
+//! The detector is polled from the processing stage. While the supply is low,
+//! flash and SD writes are suppressed and the LED is switched off to shed
+//! load; the episode is logged once the supply has been good for a second.
+//!
+//! The ESP32-C6's detector sits in the LP analog block rather than the RTC
+//! controller and is not polled, so its supply never reads low.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+#[cfg(not(feature = "esp32c6"))]
+use esp_hal::peripherals::LPWR;
+
+const RECOVERY_US: u64 = 1_000_000;
+
+static LOW: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Whether the supply is currently considered low.
+pub fn is_low() -> bool {
+    critical_section::with(|cs| LOW.borrow(cs).get())
+}
+
+fn set_low(low: bool) {
+    critical_section::with(|cs| LOW.borrow(cs).set(low));
+}
+
+pub enum Change {
+    Low,
+    /// The supply recovered after being low from `start_us` for
+    /// `duration_ms`.
+    Recovered {
+        start_us: u64,
+        duration_ms: u32,
+    },
+}
+
+pub struct Monitor {
+    low_since_us: Option<u64>,
+    good_since_us: Option<u64>,
+}
+
+impl Monitor {
+    /// Turns the detector on; whether it also resets the chip is left as the
+    /// bootloader configured it.
+    pub fn new() -> Self {
+        enable_detector();
+        Self {
+            low_since_us: None,
+            good_since_us: None,
+        }
+    }
+
+    pub fn poll(&mut self, now_us: u64) -> Option<Change> {
+        let detected = detected();
+
+        match self.low_since_us {
+            None if detected => {
+                self.low_since_us = Some(now_us);
+                set_low(true);
+                Some(Change::Low)
+            }
+            None => None,
+            Some(_) if detected => {
+                self.good_since_us = None;
+                None
+            }
+            Some(start_us) => {
+                let good_since_us = *self.good_since_us.get_or_insert(now_us);
+                if now_us - good_since_us < RECOVERY_US {
+                    return None;
+                }
+                self.low_since_us = None;
+                self.good_since_us = None;
+                set_low(false);
+                Some(Change::Recovered {
+                    start_us,
+                    duration_ms: ((good_since_us - start_us) / 1000) as u32,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "esp32c6"))]
+fn enable_detector() {
+    LPWR::regs()
+        .brown_out()
+        .modify(|_, w| w.brown_out_ena().set_bit());
+}
+
+#[cfg(not(feature = "esp32c6"))]
+fn detected() -> bool {
+    LPWR::regs().brown_out().read().det().bit_is_set()
+}
+
+#[cfg(feature = "esp32c6")]
+fn enable_detector() {}
+
+#[cfg(feature = "esp32c6")]
+fn detected() -> bool {
+    false
+}
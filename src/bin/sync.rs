@@ -0,0 +1,207 @@
+//! Sampling in step across boards, with the `sync` feature: the boards'
+//! GPIO1s and grounds are wired together, one board is made the leader
+//! with `sync leader` and the rest followers with `sync follower`. The
+//! leader drives the line high as each of its timer ticks starts a
+//! conversion and low once it is done; followers convert from the GPIO
+//! interrupt on the rising edge, so every board samples within a few
+//! microseconds of the leader. A follower that misses two pulses goes
+//! back to its own timer until they return. Only one board may lead,
+//! as the leader drives the line push-pull.
+//!
+//! Timestamps stay each board's own. What the boards share is the sample
+//! index since the leader's last epoch, which `sync epoch` restarts:
+//! `sync` prints the index and the epoch's local time, from which a
+//! sample's index is its time since the epoch over the period. The role
+//! is kept in the `state` partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::{CriticalSection, Mutex};
+use esp_hal::gpio::{Event, Flex, InputConfig, Pull};
+use esp_hal::handler;
+use esp_hal::peripherals::GPIO1;
+use hall_effect::sync::{Follower, Leader, Role};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::sample_timer;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    pub role: Role,
+    /// Whether the leader's pulses are pacing the samples, as a follower.
+    pub locked: bool,
+    /// The last sample's index since the epoch, `None` until one is seen.
+    pub index: Option<u32>,
+    /// Local time of the epoch's first sample.
+    pub epoch_us: Option<u64>,
+    /// Pulses sent as leader, or taken as follower, since boot.
+    pub pulses: u32,
+    /// Samples a follower took on its own timer since boot.
+    pub free_runs: u32,
+}
+
+static ROLE: Mutex<Cell<Role>> = Mutex::new(Cell::new(Role::Off));
+static LINE: Mutex<RefCell<Option<Flex<'static>>>> = Mutex::new(RefCell::new(None));
+static PERIOD_US: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+static LEADER: Mutex<RefCell<Leader>> = Mutex::new(RefCell::new(Leader::new()));
+static FOLLOWER: Mutex<RefCell<Follower>> = Mutex::new(RefCell::new(Follower::new(0)));
+static INDEX: Mutex<Cell<Option<u32>>> = Mutex::new(Cell::new(None));
+static EPOCH_US: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+static PULSES: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static FREE_RUNS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Takes on the role saved in flash, at boot.
+pub fn restore(role: Role) {
+    critical_section::with(|cs| ROLE.borrow(cs).set(role));
+}
+
+/// Changes the role, from the next tick, and saves it.
+pub async fn configure(role: Role) {
+    critical_section::with(|cs| apply(cs, role));
+    log!(Module::Sensor, info, "Sampling sync {}", role.name());
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_sync_role(&role).await
+    {
+        log!(Module::Storage, warn, "Sync role not saved: {}", e);
+    }
+}
+
+/// Marks a new epoch, as leader; `false` otherwise.
+pub fn restart() -> bool {
+    critical_section::with(|cs| {
+        let leading = ROLE.borrow(cs).get() == Role::Leader;
+        if leading {
+            LEADER.replace(cs, Leader::new());
+        }
+        leading
+    })
+}
+
+pub fn status() -> Status {
+    critical_section::with(|cs| {
+        let role = ROLE.borrow(cs).get();
+        let now_us = clock::monotonic_us();
+        Status {
+            role,
+            locked: role == Role::Follower
+                && FOLLOWER
+                    .borrow_ref(cs)
+                    .is_locked(now_us, PERIOD_US.borrow(cs).get()),
+            index: INDEX.borrow(cs).get(),
+            epoch_us: EPOCH_US.borrow(cs).get(),
+            pulses: PULSES.borrow(cs).get(),
+            free_runs: FREE_RUNS.borrow(cs).get(),
+        }
+    })
+}
+
+/// Sets up the line in the restored role, at boot; the GPIO interrupt
+/// handler is [`on_edge`].
+pub fn init(pin: GPIO1<'static>, period_ms: u32) {
+    let mut line = Flex::new(pin);
+    // Held low while no board leads, so an idle line reads no pulses
+    line.apply_input_config(&InputConfig::default().with_pull(Pull::Down));
+    line.set_low();
+    critical_section::with(|cs| {
+        PERIOD_US.borrow(cs).set(period_ms as u64 * 1000);
+        LINE.replace(cs, Some(line));
+        apply(cs, ROLE.borrow(cs).get());
+    });
+}
+
+fn apply(cs: CriticalSection, role: Role) {
+    ROLE.borrow(cs).set(role);
+    INDEX.borrow(cs).set(None);
+    EPOCH_US.borrow(cs).set(None);
+    LEADER.replace(cs, Leader::new());
+    FOLLOWER.replace(cs, Follower::new(clock::monotonic_us()));
+    let mut line = LINE.borrow_ref_mut(cs);
+    let Some(line) = line.as_mut() else {
+        return;
+    };
+    line.set_low();
+    line.set_output_enable(role == Role::Leader);
+    line.set_input_enable(role == Role::Follower);
+    if role == Role::Follower {
+        line.clear_interrupt();
+        line.listen(Event::RisingEdge);
+    } else {
+        line.unlisten();
+    }
+}
+
+fn count(counter: &Mutex<Cell<u32>>, cs: CriticalSection) {
+    let counter = counter.borrow(cs);
+    counter.set(counter.get().wrapping_add(1));
+}
+
+/// From the sample timer's tick, before converting: whether to take the
+/// reading. The leader pulses the line here, and a follower leaves the
+/// reading to the pulse unless the pulses have stopped.
+pub fn tick(cs: CriticalSection, timestamp_us: u64) -> bool {
+    match ROLE.borrow(cs).get() {
+        Role::Off => true,
+        Role::Leader => {
+            let index = LEADER.borrow_ref_mut(cs).tick();
+            INDEX.borrow(cs).set(index);
+            if let Some(index) = index {
+                if index == 0 {
+                    EPOCH_US.borrow(cs).set(Some(timestamp_us));
+                }
+                if let Some(line) = LINE.borrow_ref_mut(cs).as_mut() {
+                    line.set_high();
+                }
+                count(&PULSES, cs);
+            }
+            true
+        }
+        Role::Follower => {
+            let period_us = PERIOD_US.borrow(cs).get();
+            let locked = FOLLOWER.borrow_ref(cs).is_locked(timestamp_us, period_us);
+            if !locked {
+                count(&FREE_RUNS, cs);
+            }
+            !locked
+        }
+    }
+}
+
+/// From the sample timer's tick, once the reading is taken.
+pub fn tick_done(cs: CriticalSection) {
+    if ROLE.borrow(cs).get() == Role::Leader
+        && let Some(line) = LINE.borrow_ref_mut(cs).as_mut()
+    {
+        line.set_low();
+    }
+}
+
+#[handler]
+pub fn on_edge() {
+    let timestamp_us = clock::monotonic_us();
+    critical_section::with(|cs| {
+        {
+            let mut line = LINE.borrow_ref_mut(cs);
+            let Some(line) = line.as_mut() else {
+                return;
+            };
+            if !line.is_interrupt_set() {
+                return;
+            }
+            line.clear_interrupt();
+        }
+        if ROLE.borrow(cs).get() != Role::Follower {
+            return;
+        }
+        let period_us = PERIOD_US.borrow(cs).get();
+        let index = FOLLOWER.borrow_ref_mut(cs).pulse(timestamp_us, period_us);
+        INDEX.borrow(cs).set(index);
+        if index == Some(0) {
+            EPOCH_US.borrow(cs).set(Some(timestamp_us));
+        }
+        count(&PULSES, cs);
+        sample_timer::take_reading(cs, timestamp_us);
+    });
+}
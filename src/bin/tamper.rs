@@ -0,0 +1,151 @@
+//! Tamper mode: a magnetic security sensor that watches its own bias
+//! magnet. An alarm latches until cleared with `tamper clear` on the
+//! console, and is kept in the `state` partition so a reset does not clear
+//! it either. Each alarm and clearing goes on the event bus and to
+//! telemetry. The bias magnet's field is learned with `tamper learn`.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::mode::Mode;
+use hall_effect::schema::{Event, Tamper};
+use hall_effect::tamper::{Detector, Settings};
+use hall_effect::verbosity::Module;
+
+use crate::bus::{self, BusEvent};
+use crate::clock;
+use crate::mode;
+use crate::reading::Reading;
+use crate::state::STATE;
+use crate::telemetry::{self, Telemetry};
+use crate::verbosity::log;
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static ALARM: Mutex<Cell<Option<Tamper>>> = Mutex::new(Cell::new(None));
+/// Set when the alarm has changed since it was last saved.
+static UNSAVED: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| SETTINGS.borrow(cs).set(settings));
+}
+
+/// Changes the settings, from the next reading, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_tamper_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Tamper settings not saved: {}", e);
+    }
+}
+
+/// The latched alarm, if any.
+pub fn alarm() -> Option<Tamper> {
+    critical_section::with(|cs| ALARM.borrow(cs).get())
+}
+
+/// Takes on the alarm latched before a reset, at boot.
+pub fn restore_alarm(cause: Tamper) {
+    critical_section::with(|cs| ALARM.borrow(cs).set(Some(cause)));
+    log!(
+        Module::Sensor,
+        warn,
+        "Tamper alarm still latched: {}",
+        cause
+    );
+}
+
+/// Clears the latched alarm. In tamper mode it goes off again if the
+/// cause is still there.
+pub fn clear() {
+    let cleared = critical_section::with(|cs| {
+        let cleared = ALARM.borrow(cs).take().is_some();
+        if cleared {
+            UNSAVED.borrow(cs).set(true);
+        }
+        cleared
+    });
+    if cleared {
+        log!(Module::Sensor, info, "Tamper alarm cleared");
+        telemetry::publish(Telemetry::Event {
+            time_ms: clock::monotonic_us() / 1000,
+            event: Event::TamperCleared,
+        });
+    }
+}
+
+/// Saves the alarm if it has changed, from the processing stage.
+pub async fn persist() {
+    if !critical_section::with(|cs| UNSAVED.borrow(cs).replace(false)) {
+        return;
+    }
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_tamper_alarm(alarm()).await
+    {
+        log!(Module::Storage, warn, "Tamper alarm not saved: {}", e);
+    }
+}
+
+/// On entering tamper mode.
+pub fn start() {
+    if settings().is_learned() {
+        log!(Module::Sensor, info, "Tamper: {}", settings());
+    } else {
+        log!(
+            Module::Sensor,
+            warn,
+            "Tamper mode needs the bias magnet's field, `tamper learn` on the console"
+        );
+        mode::request(Mode::Measure);
+    }
+}
+
+/// The processing stage's half, for tamper mode.
+pub struct Guard {
+    detector: Detector,
+}
+
+impl Guard {
+    pub const fn new() -> Self {
+        Self {
+            detector: Detector::new(),
+        }
+    }
+
+    pub fn push(&mut self, reading: &Reading) {
+        let timestamp_us = reading.sample.timestamp_us;
+        let field_mt = reading.valid.then_some(reading.field_mt);
+        let Some(cause) = self
+            .detector
+            .update(timestamp_us / 1000, field_mt, &settings())
+        else {
+            return;
+        };
+        let latched = critical_section::with(|cs| {
+            let alarm = ALARM.borrow(cs);
+            if alarm.get().is_some() {
+                return false;
+            }
+            alarm.set(Some(cause));
+            UNSAVED.borrow(cs).set(true);
+            true
+        });
+        if latched {
+            bus::publish(BusEvent::Tamper(cause));
+            telemetry::publish(Telemetry::Event {
+                time_ms: timestamp_us / 1000,
+                event: Event::Tamper { cause },
+            });
+        }
+    }
+
+    /// On leaving tamper mode. A latched alarm stays.
+    pub fn stop(&mut self) {
+        self.detector = Detector::new();
+    }
+}
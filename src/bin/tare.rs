@@ -0,0 +1,50 @@
+//! Tare, taken from the console with `tare now`: the field at that moment,
+//! say the Earth's or that of a magnet fixed nearby, becomes the reading's
+//! zero. It is kept apart from the zero-field calibration, so clearing it
+//! leaves the calibration as it was, and saved in the `state` partition.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::verbosity::Module;
+
+use crate::reading;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static OFFSET_MT: Mutex<Cell<f32>> = Mutex::new(Cell::new(0.0));
+
+/// Taken off every reading's field; zero without a tare.
+pub fn offset_mt() -> f32 {
+    critical_section::with(|cs| OFFSET_MT.borrow(cs).get())
+}
+
+/// Takes on the tare saved in flash, at boot.
+pub fn restore(offset_mt: f32) {
+    critical_section::with(|cs| OFFSET_MT.borrow(cs).set(offset_mt));
+}
+
+/// Averages the field over the next readings and makes it the zero.
+/// Returns the new offset, or `None` if no valid readings came.
+pub async fn capture() -> Option<f32> {
+    // Readings already have the old tare taken off
+    let offset_mt = offset_mt() + reading::average(|reading| reading.field_mt).await?;
+    save(offset_mt).await;
+    log!(Module::Sensor, info, "Tare: {}mT", offset_mt);
+    Some(offset_mt)
+}
+
+/// Goes back to the calibrated zero.
+pub async fn clear() {
+    save(0.0).await;
+    log!(Module::Sensor, info, "Tare cleared");
+}
+
+async fn save(offset_mt: f32) {
+    restore(offset_mt);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_tare(offset_mt).await
+    {
+        log!(Module::Storage, warn, "Tare not saved: {}", e);
+    }
+}
@@ -0,0 +1,121 @@
+//! Telemetry fan-out: the processing stage publishes samples and events, and
+//! each sink (flash log, SD card) consumes them at its own pace. A sink that
+//! falls too far behind loses the oldest messages rather than stalling
+//! processing.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{PubSubChannel, WaitResult};
+use hall_effect::schema::{Event, Sample};
+use hall_effect::verbosity::Module;
+
+use crate::flash_log::{FLASH_LOG, RECONFIGURED};
+use crate::replay;
+use crate::supply;
+use crate::verbosity::log;
+
+#[derive(Clone)]
+pub enum Telemetry {
+    Sample(Sample),
+    Event { time_ms: u64, event: Event },
+}
+
+// About half a second of samples at the default rate, enough to ride out a
+// sector erase
+const CAPACITY: usize = 64;
+const SINKS: usize = if cfg!(feature = "sd-log") { 2 } else { 1 };
+
+pub static TELEMETRY: PubSubChannel<CriticalSectionRawMutex, Telemetry, CAPACITY, SINKS, 1> =
+    PubSubChannel::new();
+
+/// Never blocks; a full channel drops the oldest message. Nothing is
+/// published during a replay, so replayed samples are not logged again.
+pub fn publish(message: Telemetry) {
+    if !replay::active() {
+        TELEMETRY.immediate_publisher().publish_immediate(message);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn flash_sink_task() {
+    let Ok(mut subscriber) = TELEMETRY.subscriber() else {
+        return;
+    };
+    loop {
+        let message = match subscriber.next_message().await {
+            WaitResult::Message(message) => message,
+            WaitResult::Lagged(n) => {
+                log!(
+                    Module::Storage,
+                    warn,
+                    "Flash log behind, {} messages lost",
+                    n
+                );
+                continue;
+            }
+        };
+        let mut flash_log = FLASH_LOG.lock().await;
+        let Some(log) = flash_log.as_mut() else {
+            continue;
+        };
+        if let Some(config) = RECONFIGURED.try_take()
+            && let Err(e) = log.set_config(&config).await
+            && !supply::is_low()
+        {
+            log!(Module::Storage, warn, "Flash log write failed: {}", e);
+        }
+        let result = match message {
+            Telemetry::Sample(sample) => log.record_sample(&sample).await,
+            Telemetry::Event { time_ms, event } => log.record_event(time_ms, event).await,
+        };
+        if let Err(e) = result
+            && !supply::is_low()
+        {
+            log!(Module::Storage, warn, "Flash log write failed: {}", e);
+        }
+    }
+}
+
+#[cfg(feature = "sd-log")]
+#[embassy_executor::task]
+pub async fn sd_sink_task(
+    mut logger: crate::sd_log::SdLogger<crate::sd_log::Card>,
+    config: hall_effect::schema::Config,
+) {
+    use hall_effect::backoff::Backoff;
+
+    // Consecutive failures before SD logging is stopped
+    const SD_MAX_FAILURES: u32 = 8;
+
+    let Ok(mut subscriber) = TELEMETRY.subscriber() else {
+        return;
+    };
+    let mut backoff = Backoff::new(SD_MAX_FAILURES);
+    loop {
+        let sample = match subscriber.next_message().await {
+            WaitResult::Message(Telemetry::Sample(sample)) => sample,
+            WaitResult::Message(Telemetry::Event { .. }) => continue,
+            WaitResult::Lagged(n) => {
+                log!(Module::Storage, warn, "SD log behind, {} messages lost", n);
+                continue;
+            }
+        };
+        if supply::is_low() || !backoff.ready() {
+            continue;
+        }
+        match logger.log(&sample, &config) {
+            Ok(()) => backoff.success(),
+            Err(e) if backoff.failure() => {
+                log!(Module::Storage, warn, "SD log write failed: {}", e)
+            }
+            Err(e) => {
+                log!(
+                    Module::Storage,
+                    warn,
+                    "SD log write failed, logging stopped: {}",
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! ST7789 (or, with `tft-st7735`, ST7735) colour TFT on SPI3: the reading
+//! in the units set above a sweeping chart of the field's recent past,
+//! with the alarm threshold marked.
+
+use defmt::Debug2Format;
+use embedded_graphics::prelude::Dimensions;
+use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
+use esp_hal::Blocking;
+use esp_hal::delay::Delay;
+use esp_hal::gpio::Output;
+use esp_hal::spi::master::Spi;
+use hall_effect::backoff::Backoff;
+use hall_effect::chart::StripChart;
+use hall_effect::schema::Config;
+use hall_effect::verbosity::Module;
+use mipidsi::interface::SpiInterface;
+use mipidsi::options::{self, ColorOrder};
+use mipidsi::{Builder, Display};
+use static_cell::StaticCell;
+
+use crate::clock;
+use crate::reading::LATEST;
+use crate::units;
+use crate::verbosity::log;
+
+/// The bus as wired up in `main`: SPI3 with a dedicated chip select.
+pub type TftSpi = ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>;
+
+type Interface = SpiInterface<'static, TftSpi, Output<'static>>;
+
+#[cfg(not(feature = "tft-st7735"))]
+type Model = mipidsi::models::ST7789;
+#[cfg(not(feature = "tft-st7735"))]
+const MODEL: Model = mipidsi::models::ST7789;
+#[cfg(feature = "tft-st7735")]
+type Model = mipidsi::models::ST7735s;
+#[cfg(feature = "tft-st7735")]
+const MODEL: Model = mipidsi::models::ST7735s;
+
+// Consecutive failures before the display is given up on
+const TFT_MAX_FAILURES: u32 = 8;
+
+// One chart column per refresh
+const REFRESH_US: u64 = 200_000;
+
+// Pixels batched per SPI transfer
+static BUFFER: StaticCell<[u8; 512]> = StaticCell::new();
+
+#[embassy_executor::task]
+pub async fn tft_task(spi: TftSpi, dc: Output<'static>, rst: Output<'static>, config: Config) {
+    let Some(mut latest) = LATEST.receiver() else {
+        log!(Module::Display, warn, "TFT has no reading receiver");
+        return;
+    };
+    let interface = SpiInterface::new(spi, dc, BUFFER.init([0; 512]));
+    let mut display = match init(interface, rst) {
+        Ok(display) => display,
+        Err(e) => {
+            log!(Module::Display, warn, "TFT not responding: {}", Debug2Format(&e));
+            return;
+        }
+    };
+
+    let mut chart = StripChart::new(
+        display.bounding_box().size,
+        &config,
+        (REFRESH_US / 1000) as u32,
+    );
+    if let Err(e) = chart.draw_axes(&mut display) {
+        log!(Module::Display, warn, "TFT write failed: {}", Debug2Format(&e));
+        return;
+    }
+    // Strongest field since the last chart column, so brief peaks show
+    let mut peak_mt: Option<f32> = None;
+    let mut backoff = Backoff::new(TFT_MAX_FAILURES);
+    let mut refresh_us = clock::monotonic_us();
+
+    loop {
+        let reading = latest.changed().await;
+        if reading.valid && peak_mt.is_none_or(|peak| reading.field_mt.abs() > peak.abs()) {
+            peak_mt = Some(reading.field_mt);
+        }
+
+        let now_us = clock::monotonic_us();
+        if now_us - refresh_us < REFRESH_US {
+            continue;
+        }
+        refresh_us = now_us;
+        chart.push(peak_mt.take().unwrap_or(0.0));
+
+        if !backoff.ready() {
+            continue;
+        }
+        match chart.draw(&mut display, reading.valid.then(|| units::of(&reading))) {
+            Ok(()) => backoff.success(),
+            Err(e) if backoff.failure() => {
+                log!(Module::Display, warn, "TFT write failed: {}", Debug2Format(&e))
+            }
+            Err(e) => {
+                log!(
+                    Module::Display,
+                    warn,
+                    "TFT write failed, display disabled: {}",
+                    Debug2Format(&e)
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Resets and configures the controller for the common module of each kind:
+/// a 240x240 ST7789, or a 128x160 ST7735 turned to landscape.
+fn init(
+    interface: Interface,
+    rst: Output<'static>,
+) -> Result<Display<Interface, Model, Output<'static>>, impl core::fmt::Debug> {
+    let builder = Builder::new(MODEL, interface).reset_pin(rst);
+    #[cfg(not(feature = "tft-st7735"))]
+    let builder = builder
+        .display_size(240, 240)
+        .invert_colors(options::ColorInversion::Inverted)
+        .color_order(ColorOrder::Rgb);
+    #[cfg(feature = "tft-st7735")]
+    let builder = builder
+        .display_size(128, 160)
+        .orientation(options::Orientation::new().rotate(options::Rotation::Deg90))
+        .color_order(ColorOrder::Bgr);
+    builder.init(&mut Delay::new())
+}
@@ -0,0 +1,82 @@
+//! TM1637 4-digit 7-segment display for gauge-style installations: the
+//! reading in the units set, the RPM while in tachometer mode, amps in current mode or watts
+//! in power mode.
+
+use embassy_time::{Duration, Timer};
+use esp_hal::delay::Delay;
+use esp_hal::gpio::{Flex, Output};
+use hall_effect::backoff::Backoff;
+use hall_effect::mode::Mode;
+use hall_effect::tm1637::{self, Tm1637};
+use hall_effect::verbosity::Module;
+
+use crate::current;
+use crate::pas;
+use crate::power;
+use crate::reading::LATEST;
+use crate::units;
+use crate::verbosity::log;
+use crate::{led, mode};
+
+pub type Display = Tm1637<Output<'static>, Flex<'static>, Delay>;
+
+const REFRESH: Duration = Duration::from_millis(100);
+
+// Tenths of an amp reach 999.9A, past the largest profile
+const AMPS_DECIMALS: u8 = 1;
+
+// Consecutive failures before the display is given up on
+const TM1637_MAX_FAILURES: u32 = 8;
+
+#[embassy_executor::task]
+pub async fn tm1637_task(mut display: Display) {
+    let mut backoff = Backoff::new(TM1637_MAX_FAILURES);
+    loop {
+        Timer::after(REFRESH).await;
+        if !backoff.ready() {
+            continue;
+        }
+        let segments = match (mode::current(), LATEST.try_get()) {
+            (Mode::Tachometer, _) => tm1637::segments(mode::rpm() as f32, 0),
+            (Mode::Current, _) => match current::amps() {
+                Some(amps) => tm1637::segments(amps, AMPS_DECIMALS),
+                None => continue,
+            },
+            (Mode::Power, _) => match power::watts() {
+                Some(watts) => tm1637::segments(watts, 0),
+                None => continue,
+            },
+            (Mode::Pas, _) => match pas::status() {
+                Some(status) => tm1637::segments(status.cadence_rpm, 0),
+                None => continue,
+            },
+            (_, Some(reading)) if reading.valid => {
+                // As many of the unit's places as fit in the four digits
+                let shown = units::of(&reading);
+                (0..=shown.unit.decimals())
+                    .rev()
+                    .find_map(|decimals| tm1637::segments(shown.value, decimals))
+            }
+            (_, Some(_)) => Some(tm1637::ERROR),
+            (_, None) => continue,
+        };
+
+        // Same brightness as the LED, in the display's eight steps
+        let brightness = led::shown_brightness() >> 5;
+        match display.show(&segments.unwrap_or(tm1637::OVERFLOW), brightness) {
+            Ok(()) => backoff.success(),
+            Err(e) if backoff.failure() => {
+                log!(Module::Display, warn, "TM1637 write failed: {}", e)
+            }
+            Err(e) => {
+                log!(
+                    Module::Display,
+                    warn,
+                    "TM1637 write failed, display disabled: {}",
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
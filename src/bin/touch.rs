@@ -0,0 +1,114 @@
+//! A capacitive touch pad as the user button, so a sealed enclosure needs no
+//! mechanical one: a short touch cycles the mode and a long touch starts a
+//! zero-field calibration (tare).
+//!
+//! The ESP32-S3 touch sensor is set up at the register level, following
+//! ESP-IDF's defaults, as esp-hal only drives the original ESP32's. The
+//! sensor's timer scans the pad continuously and the task polls its raw
+//! count.
+
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::peripherals::{GPIO14, LPWR, RTC_IO, SENS};
+use hall_effect::button::Press;
+use hall_effect::mode::Mode;
+use hall_effect::touch::TouchDetector;
+use hall_effect::verbosity::Module;
+
+use crate::bus::{self, BusEvent};
+use crate::mode;
+use crate::verbosity::log;
+
+// Touch channel 14 is GPIO14
+const PAD: usize = 14;
+
+const POLL: Duration = Duration::from_millis(20);
+
+// Sensor settings, ESP-IDF's defaults: 500 8MHz cycles per measurement, 15
+// slow-clock cycles between scans, 2.7V/0.5V references, 0.5V attenuation,
+// the steepest charge slope
+const MEAS_CYCLES: u16 = 500;
+const SLEEP_CYCLES: u16 = 0xf;
+const REF_HIGH: u8 = 3;
+const REF_LOW: u8 = 0;
+const ATTENUATION: u8 = 2;
+const SLOPE: u8 = 7;
+
+#[embassy_executor::task]
+pub async fn touch_task(_pin: GPIO14<'static>) {
+    init();
+    log!(Module::Sensor, info, "Touch pad on GPIO{}", PAD);
+
+    let mut detector = TouchDetector::new();
+    let mut touched_at = None;
+    loop {
+        Timer::after(POLL).await;
+        let raw = SENS::regs().sar_touch_status(PAD - 1).read().data().bits();
+        match detector.update(raw) {
+            Some(true) => touched_at = Some(Instant::now()),
+            Some(false) => {
+                if let Some(at) = touched_at.take() {
+                    pressed(Press::from_duration_ms(at.elapsed().as_millis()));
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn pressed(press: Press) {
+    bus::publish(BusEvent::ButtonPressed(press));
+    match press {
+        Press::Short => mode::request(mode::current().next()),
+        Press::Long => mode::request(Mode::Calibrate),
+        // Factory reset stays on the BOOT button; a hand resting on the pad
+        // must not wipe the device
+        Press::VeryLong => {}
+        // Not told apart on the pad
+        Press::Double => {}
+    }
+}
+
+fn init() {
+    // The RTC IO mux is clocked separately
+    SENS::regs()
+        .sar_peri_clk_gate_conf()
+        .modify(|_, w| w.iomux_clk_en().set_bit());
+    RTC_IO::regs().touch_pad(PAD).modify(|_, w| {
+        w.mux_sel().set_bit();
+        w.fun_ie().clear_bit();
+        w.rue().clear_bit();
+        w.rde().clear_bit();
+        w.tie_opt().clear_bit()
+    });
+
+    let rtc = LPWR::regs();
+    rtc.touch_ctrl1().modify(|_, w| unsafe {
+        w.touch_meas_num().bits(MEAS_CYCLES);
+        w.touch_sleep_cycles().bits(SLEEP_CYCLES)
+    });
+    rtc.touch_dac1()
+        .modify(|_, w| unsafe { w.touch_pad14_dac().bits(SLOPE) });
+    rtc.touch_scan_ctrl().modify(|r, w| unsafe {
+        w.touch_scan_pad_map()
+            .bits(r.touch_scan_pad_map().bits() | 1 << PAD)
+    });
+    SENS::regs().sar_touch_conf().modify(|r, w| unsafe {
+        w.sar_touch_outen()
+            .bits(r.sar_touch_outen().bits() | 1 << PAD);
+        // Raw counts rather than the smoothed or benchmark values
+        w.sar_touch_data_sel().bits(0)
+    });
+
+    // Start the scan from the sensor's own timer
+    rtc.touch_ctrl2().modify(|_, w| unsafe {
+        w.touch_drefh().bits(REF_HIGH);
+        w.touch_drefl().bits(REF_LOW);
+        w.touch_drange().bits(ATTENUATION);
+        w.touch_xpd_wait().bits(u8::MAX);
+        w.touch_xpd_bias().set_bit();
+        w.touch_start_force().clear_bit();
+        w.touch_start_fsm_en().set_bit();
+        w.touch_slp_timer_en().set_bit();
+        w.touch_clkgate_en().set_bit()
+    });
+}
@@ -0,0 +1,100 @@
+//! Trigger output: with the `trigger-out` feature, GPIO21 pulses high for
+//! an oscilloscope or high-speed camera when a sample meets the condition
+//! set with `trigger` on the console, in every mode. The sampler checks
+//! each sample as soon as it is converted, ahead of processing, and times
+//! the pulse with interrupts held off so its width is exact. How long
+//! after the event each pulse started is kept for `trigger`, to correct
+//! the instrument's timebase by. The settings are kept in the `state`
+//! partition.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::peripherals::GPIO21;
+use hall_effect::schema::Sample;
+use hall_effect::trigger::{Settings, Trigger};
+use hall_effect::verbosity::Module;
+
+use crate::clock;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+/// Pulses since boot, and how long after their events they started.
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub pulses: u32,
+    pub last_lag_us: Option<u32>,
+    pub max_lag_us: u32,
+}
+
+static SETTINGS: Mutex<Cell<Settings>> = Mutex::new(Cell::new(Settings::DEFAULT));
+static OUTPUT: Mutex<RefCell<Option<Output<'static>>>> = Mutex::new(RefCell::new(None));
+static TRIGGER: Mutex<RefCell<Trigger>> = Mutex::new(RefCell::new(Trigger::new()));
+static STATS: Mutex<Cell<Stats>> = Mutex::new(Cell::new(Stats {
+    pulses: 0,
+    last_lag_us: None,
+    max_lag_us: 0,
+}));
+
+pub fn settings() -> Settings {
+    critical_section::with(|cs| SETTINGS.borrow(cs).get())
+}
+
+/// Takes on the settings saved in flash, at boot.
+pub fn restore(settings: Settings) {
+    critical_section::with(|cs| {
+        SETTINGS.borrow(cs).set(settings);
+        TRIGGER.replace(cs, Trigger::new());
+    });
+}
+
+/// Changes the settings, from the next sample, and saves them.
+pub async fn configure(settings: Settings) {
+    restore(settings);
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_trigger_settings(&settings).await
+    {
+        log!(Module::Storage, warn, "Trigger settings not saved: {}", e);
+    }
+}
+
+pub fn stats() -> Stats {
+    critical_section::with(|cs| STATS.borrow(cs).get())
+}
+
+/// Sets up the output, low, at boot.
+pub fn init(pin: GPIO21<'static>) {
+    let output = Output::new(pin, Level::Low, OutputConfig::default());
+    critical_section::with(|cs| OUTPUT.replace(cs, Some(output)));
+}
+
+/// Checks a sample, from the sampler, pulsing if it meets the condition.
+pub fn sample(sample: &Sample) {
+    let settings = settings();
+    let Some(event_us) =
+        critical_section::with(|cs| TRIGGER.borrow_ref_mut(cs).update(sample, &settings))
+    else {
+        return;
+    };
+    let lag_us = critical_section::with(|cs| {
+        let mut output = OUTPUT.borrow_ref_mut(cs);
+        let output = output.as_mut()?;
+        output.set_high();
+        let edge_us = clock::monotonic_us();
+        while clock::monotonic_us() - edge_us < settings.pulse_us as u64 {}
+        output.set_low();
+        Some((edge_us - event_us).min(u32::MAX as u64) as u32)
+    });
+    if let Some(lag_us) = lag_us {
+        critical_section::with(|cs| {
+            let cell = STATS.borrow(cs);
+            let stats = cell.get();
+            cell.set(Stats {
+                pulses: stats.pulses + 1,
+                last_lag_us: Some(lag_us),
+                max_lag_us: stats.max_lag_us.max(lag_us),
+            });
+        });
+    }
+}
@@ -0,0 +1,97 @@
+//! Wake on the field rather than on a timer: in deep sleep the ULP-FSM
+//! coprocessor reads the sensor every [`PERIOD_CYCLES`] slow-clock cycles
+//! and wakes the main CPU only once the reading crosses the alarm threshold,
+//! so a door or tamper sensor sleeps until something happens.
+//!
+//! The ULP and SAR ADC1 are set up at the register level, following
+//! ESP-IDF's `ulp_run` and `ulp_adc_init`, as esp-hal has no ULP-FSM
+//! support.
+
+use esp_hal::peripherals::{LPWR, SENS};
+use hall_effect::schema::Config;
+use hall_effect::ulp::{self, THRESHOLD_PROGRAM_LEN};
+
+use crate::board;
+
+// 6dB, as the sampler uses
+const ATTENUATION: u32 = 2;
+
+// Top of the 6dB range. The ULP reads raw 12-bit counts, uncalibrated, so
+// this is nominal and a given chip may differ by a few percent.
+const FULL_SCALE_MV: u32 = 1750;
+
+// About 100ms on the ~136kHz internal slow clock
+const PERIOD_CYCLES: u32 = 13_600;
+
+// Where RTC slow memory, and so the ULP's address space, starts
+const RTC_SLOW_MEM: usize = 0x5000_0000;
+
+#[esp_hal::ram(unstable(rtc_slow))]
+static mut PROGRAM: [u32; THRESHOLD_PROGRAM_LEN] = [0; THRESHOLD_PROGRAM_LEN];
+
+/// Starts the ULP watching for the field to cross to the other side of the
+/// threshold from `above`. It keeps running in deep sleep.
+pub fn arm(config: &Config, above: bool) {
+    let program = ulp::threshold_program(
+        board::SENSOR_CHANNEL,
+        above,
+        to_raw(config.threshold_mv.saturating_sub(config.hysteresis_mv)),
+        to_raw(config.threshold_mv.saturating_add(config.hysteresis_mv)),
+    );
+    let program_ptr = &raw mut PROGRAM;
+    disarm();
+    // SAFETY: the ULP is stopped, so nothing else reads the program
+    unsafe { program_ptr.write_volatile(program) };
+
+    init_adc();
+
+    let rtc = LPWR::regs();
+    rtc.ulp_cp_timer_1()
+        .modify(|_, w| unsafe { w.ulp_cp_timer_slp_cycle().bits(PERIOD_CYCLES) });
+    // The ULP-FSM rather than the RISC-V coprocessor
+    rtc.cocpu_ctrl().modify(|_, w| w.cocpu_sel().set_bit());
+    rtc.ulp_cp_ctrl().modify(|_, w| {
+        w.ulp_cp_force_start_top().clear_bit();
+        w.ulp_cp_start_top().clear_bit()
+    });
+    // The entry point is a word offset into RTC slow memory
+    let entry = ((program_ptr as usize - RTC_SLOW_MEM) / 4) as u16;
+    rtc.ulp_cp_timer().modify(|_, w| unsafe {
+        w.ulp_cp_pc_init().bits(entry);
+        w.ulp_cp_slp_timer_en().set_bit()
+    });
+}
+
+/// Stops the ULP so the sampler has the ADC to itself.
+pub fn disarm() {
+    LPWR::regs()
+        .ulp_cp_timer()
+        .modify(|_, w| w.ulp_cp_slp_timer_en().clear_bit());
+}
+
+/// Hands SAR ADC1 to the RTC controller, which the ULP's `ADC` instruction
+/// drives.
+fn init_adc() {
+    let sens = SENS::regs();
+    sens.sar_peri_clk_gate_conf()
+        .modify(|_, w| w.saradc_clk_en().set_bit());
+    sens.sar_atten1().modify(|r, w| unsafe {
+        let shift = board::SENSOR_CHANNEL * 2;
+        w.bits((r.bits() & !(0x3 << shift)) | (ATTENUATION << shift))
+    });
+    sens.sar_meas1_mux()
+        .modify(|_, w| w.sar1_dig_force().clear_bit());
+    sens.sar_meas1_ctrl2().modify(|_, w| {
+        w.meas1_start_force().clear_bit();
+        w.sar1_en_pad_force().clear_bit()
+    });
+    // Powered up by the FSM for each conversion only
+    sens.sar_power_xpd_sar()
+        .modify(|_, w| unsafe { w.force_xpd_sar().bits(0) });
+}
+
+/// The count the ULP's `ADC` instruction reads at `voltage_mv` on the 6dB
+/// range.
+fn to_raw(voltage_mv: u32) -> u16 {
+    (voltage_mv.min(FULL_SCALE_MV) * 4095 / FULL_SCALE_MV) as u16
+}
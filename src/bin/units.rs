@@ -0,0 +1,40 @@
+//! The unit the console's `reading`, the per-sample log line and the
+//! displays show readings in, see `hall_effect::units`; set with `units`
+//! and kept in the `state` partition. Stored records keep their own units.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::units::{Quantity, Unit};
+use hall_effect::verbosity::Module;
+
+use crate::reading::Reading;
+use crate::state::STATE;
+use crate::verbosity::log;
+
+static UNIT: Mutex<Cell<Unit>> = Mutex::new(Cell::new(Unit::Millitesla));
+
+pub fn current() -> Unit {
+    critical_section::with(|cs| UNIT.borrow(cs).get())
+}
+
+/// `reading` in the current unit.
+pub fn of(reading: &Reading) -> Quantity {
+    current().quantity(&reading.sample, reading.field_mt)
+}
+
+/// Takes on the unit saved in flash, at boot.
+pub fn restore(unit: Unit) {
+    critical_section::with(|cs| UNIT.borrow(cs).set(unit));
+}
+
+/// Changes the unit and saves it.
+pub async fn configure(unit: Unit) {
+    restore(unit);
+    log!(Module::Storage, info, "Readings in {}", unit.symbol());
+    if let Some(state) = STATE.lock().await.as_mut()
+        && let Err(e) = state.save_unit(&unit).await
+    {
+        log!(Module::Storage, warn, "Unit not saved: {}", e);
+    }
+}
@@ -0,0 +1,51 @@
+//! Log levels in effect, and the `log!` macro that checks them.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use hall_effect::verbosity::{Level, Levels, Module};
+
+static LEVELS: Mutex<Cell<Levels>> = Mutex::new(Cell::new(Levels::new()));
+
+pub fn levels() -> Levels {
+    critical_section::with(|cs| LEVELS.borrow(cs).get())
+}
+
+pub fn enabled(module: Module, level: Level) -> bool {
+    levels().enabled(module, level)
+}
+
+/// Sets the level of one module, or of all of them.
+pub fn set(module: Option<Module>, level: Level) {
+    critical_section::with(|cs| {
+        let cell = LEVELS.borrow(cs);
+        let mut levels = cell.get();
+        match module {
+            Some(module) => levels.set(module, level),
+            None => levels.set_all(level),
+        }
+        cell.set(levels);
+    });
+}
+
+/// A defmt message that is only emitted if its module's level allows it,
+/// e.g. `log!(Module::Led, warn, "LED write failed: {}", e)`.
+macro_rules! log {
+    ($module:expr, error, $($arg:tt)+) => {
+        if $crate::verbosity::enabled($module, hall_effect::verbosity::Level::Error) {
+            defmt::error!($($arg)+);
+        }
+    };
+    ($module:expr, warn, $($arg:tt)+) => {
+        if $crate::verbosity::enabled($module, hall_effect::verbosity::Level::Warn) {
+            defmt::warn!($($arg)+);
+        }
+    };
+    ($module:expr, info, $($arg:tt)+) => {
+        if $crate::verbosity::enabled($module, hall_effect::verbosity::Level::Info) {
+            defmt::info!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use log;
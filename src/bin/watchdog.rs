@@ -0,0 +1,108 @@
+//! Task watchdog on top of the TIMG1 hardware watchdog.
+//!
+//! Each supervised task checks in with [`feed`]; the supervisor only feeds the
+//! hardware watchdog while every task has checked in recently. The check-in
+//! times live in RTC RAM, so after a watchdog reset the stalest task can be
+//! named as the culprit. This also covers a blocking call that stalls the
+//! whole executor, e.g. a hung RMT wait.
+
+use defmt::Format;
+use embassy_time::{Duration, Timer};
+use esp_hal::peripherals::TIMG1;
+use esp_hal::rtc_cntl::SocResetReason;
+use esp_hal::timer::timg::{MwdtStage, Wdt};
+
+use crate::clock;
+
+const MAGIC: u32 = 0x5744_4f47;
+
+const TASK_TIMEOUT_US: u64 = 5_000_000;
+const HARDWARE_TIMEOUT: esp_hal::time::Duration = esp_hal::time::Duration::from_secs(5);
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+#[repr(u8)]
+pub enum Task {
+    Sensor,
+    Process,
+    Led,
+    Console,
+}
+
+const TASKS: [Task; 4] = [Task::Sensor, Task::Process, Task::Led, Task::Console];
+
+#[repr(C)]
+struct CheckIns {
+    magic: u32,
+    last_us: [u64; TASKS.len()],
+}
+
+unsafe impl esp_hal::Persistable for CheckIns {}
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut CHECK_INS: CheckIns = CheckIns {
+    magic: 0,
+    last_us: [0; TASKS.len()],
+};
+
+fn with_check_ins<R>(f: impl FnOnce(&mut CheckIns) -> R) -> R {
+    let check_ins = &raw mut CHECK_INS;
+    critical_section::with(|_| f(unsafe { &mut *check_ins }))
+}
+
+fn stalest(check_ins: &CheckIns) -> Task {
+    let index = check_ins
+        .last_us
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, us)| **us)
+        .map_or(0, |(i, _)| i);
+    TASKS[index]
+}
+
+/// Returns the task blamed for the previous reset, if it was a watchdog
+/// reset, and starts tracking the tasks of this boot.
+pub fn init(reset_reason: Option<SocResetReason>) -> Option<Task> {
+    let now = clock::monotonic_us();
+    with_check_ins(|check_ins| {
+        let culprit = (check_ins.magic == MAGIC && reset_reason == Some(SocResetReason::CoreMwdt1))
+            .then(|| stalest(check_ins));
+        check_ins.magic = MAGIC;
+        check_ins.last_us = [now; TASKS.len()];
+        culprit
+    })
+}
+
+pub fn feed(task: Task) {
+    let now = clock::monotonic_us();
+    with_check_ins(|check_ins| check_ins.last_us[task as usize] = now);
+}
+
+#[embassy_executor::task]
+pub async fn watchdog_task(mut wdt: Wdt<TIMG1<'static>>) {
+    // Start the clock now rather than at init, so slow setup is not a stall
+    let now = clock::monotonic_us();
+    with_check_ins(|check_ins| check_ins.last_us = [now; TASKS.len()]);
+
+    wdt.set_timeout(MwdtStage::Stage0, HARDWARE_TIMEOUT);
+    wdt.enable();
+
+    let mut reported = false;
+    loop {
+        Timer::after(CHECK_INTERVAL).await;
+        let now = clock::monotonic_us();
+        let stalled = with_check_ins(|check_ins| {
+            let task = stalest(check_ins);
+            (now.saturating_sub(check_ins.last_us[task as usize]) > TASK_TIMEOUT_US).then_some(task)
+        });
+        match stalled {
+            None => wdt.feed(),
+            // Stop feeding and let the hardware watchdog reset the chip
+            Some(task) if !reported => {
+                defmt::error!("{} task stalled, waiting for watchdog reset", task);
+                reported = true;
+            }
+            Some(_) => {}
+        }
+    }
+}
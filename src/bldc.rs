@@ -0,0 +1,201 @@
+//! Six-step (trapezoidal) commutation of a brushless motor from three
+//! digital hall sensors 120 electrical degrees apart.
+//!
+//! The halls give the rotor's sector, one of six per electrical turn, and
+//! each sector has two of the three phases driven: one pulled up through
+//! its PWM'd high side, one held down through its low side, the third left
+//! floating. Between sectors a phase only ever goes from driven to floating
+//! or back, never straight from one side to the other, so the bridge needs
+//! no dead time of its own.
+//!
+//! The speed is taken over a whole electrical turn, so uneven spacing of
+//! the halls around the stator does not show as ripple.
+
+use defmt::Format;
+
+pub const SECTORS: usize = 6;
+
+// Slower than this reads as stopped
+const STOPPED_US: u64 = 500_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Drive {
+    /// The high side, switched at the duty.
+    High,
+    /// The low side, held on.
+    Low,
+    Float,
+}
+
+use Drive::{Float, High, Low};
+
+/// Phases A, B and C for each step, in the order the field turns.
+const STEPS: [[Drive; 3]; SECTORS] = [
+    [High, Low, Float],
+    [High, Float, Low],
+    [Float, High, Low],
+    [Low, High, Float],
+    [Low, Float, High],
+    [Float, Low, High],
+];
+
+/// The sector, 0 to 5, from the hall levels as bits (A the lowest). `None`
+/// for all three high or all low, which only a wiring fault gives.
+pub fn sector(halls: u8) -> Option<u8> {
+    match halls & 0b111 {
+        0b001 => Some(0),
+        0b011 => Some(1),
+        0b010 => Some(2),
+        0b110 => Some(3),
+        0b100 => Some(4),
+        0b101 => Some(5),
+        _ => None,
+    }
+}
+
+/// How to drive phases A, B and C in `sector`. Reversing drives the step
+/// opposite, a half turn of the field away. A motor that turns the wrong
+/// way for its direction has two hall wires swapped.
+pub fn phases(sector: u8, reverse: bool) -> [Drive; 3] {
+    let offset = if reverse { SECTORS / 2 } else { 0 };
+    STEPS[(sector as usize + offset) % SECTORS]
+}
+
+/// Mechanical speed from the times of hall transitions.
+pub struct SpeedMeter {
+    pole_pairs: u32,
+    last_sector: Option<u8>,
+    /// Times of the last transitions, a whole electrical turn of them once
+    /// full, oldest first at `next`.
+    times_us: [u64; SECTORS],
+    next: usize,
+    count: usize,
+    /// Whether the sector last went down rather than up.
+    backwards: bool,
+}
+
+impl SpeedMeter {
+    pub const fn new(pole_pairs: u32) -> Self {
+        Self {
+            pole_pairs,
+            last_sector: None,
+            times_us: [0; SECTORS],
+            next: 0,
+            count: 0,
+            backwards: false,
+        }
+    }
+
+    /// Takes the sector read at `timestamp_us`, on a hall edge. A jump of
+    /// more than one sector, from a missed edge or a glitch, starts the
+    /// measurement over.
+    pub fn push(&mut self, timestamp_us: u64, sector: u8) {
+        let Some(last) = self.last_sector.replace(sector) else {
+            return;
+        };
+        let backwards = match (sector as usize + SECTORS - last as usize) % SECTORS {
+            0 => return,
+            1 => false,
+            5 => true,
+            _ => {
+                self.count = 0;
+                return;
+            }
+        };
+        if backwards != self.backwards {
+            self.backwards = backwards;
+            self.count = 0;
+        }
+        self.times_us[self.next] = timestamp_us;
+        self.next = (self.next + 1) % SECTORS;
+        self.count = (self.count + 1).min(SECTORS);
+    }
+
+    /// Revolutions per minute, negative while turning backwards, or zero
+    /// once the last transition is too long ago.
+    pub fn rpm(&self, now_us: u64) -> f32 {
+        if self.count < SECTORS {
+            return 0.0;
+        }
+        let newest_us = self.times_us[(self.next + SECTORS - 1) % SECTORS];
+        let oldest_us = self.times_us[self.next];
+        if now_us.saturating_sub(newest_us) > STOPPED_US || newest_us == oldest_us {
+            return 0.0;
+        }
+        // Five transitions between the oldest and newest
+        let turn_us = (newest_us - oldest_us) as f32 * SECTORS as f32 / (SECTORS - 1) as f32;
+        let rpm = 60e6 / (turn_us * self.pole_pairs as f32);
+        if self.backwards { -rpm } else { rpm }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sectors_follow_the_halls() {
+        let order = [0b001, 0b011, 0b010, 0b110, 0b100, 0b101];
+        for (i, halls) in order.into_iter().enumerate() {
+            assert_eq!(sector(halls), Some(i as u8));
+        }
+        assert_eq!(sector(0b000), None);
+        assert_eq!(sector(0b111), None);
+    }
+
+    #[test]
+    fn one_phase_floats_and_none_switches_sides() {
+        for reverse in [false, true] {
+            for s in 0..SECTORS as u8 {
+                let now = phases(s, reverse);
+                assert_eq!(now.iter().filter(|&&d| d == Float).count(), 1);
+                assert_eq!(now.iter().filter(|&&d| d == High).count(), 1);
+                let next = phases((s + 1) % SECTORS as u8, reverse);
+                for (a, b) in now.into_iter().zip(next) {
+                    assert!(!matches!((a, b), (High, Low) | (Low, High)));
+                }
+            }
+            // Reversing swaps the sides
+            let forward = phases(2, false);
+            let reverse = phases(2, true);
+            for (a, b) in forward.into_iter().zip(reverse) {
+                assert_eq!(
+                    b,
+                    match a {
+                        High => Low,
+                        Low => High,
+                        Float => Float,
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn speed_over_a_turn() {
+        // Seven pole pairs at 1000rpm: 42 transitions a revolution
+        let mut meter = SpeedMeter::new(7);
+        let step_us = 60_000_000 / 1000 / 42;
+        let mut t = 0;
+        for i in 0..12u64 {
+            // Uneven hall spacing, evened out over the turn
+            let jitter = if i % 2 == 0 { 100 } else { 0 };
+            meter.push(t + jitter, (i % 6) as u8);
+            t += step_us;
+        }
+        let rpm = meter.rpm(t);
+        assert!((rpm - 1000.0).abs() < 30.0, "{rpm}");
+        assert_eq!(meter.rpm(t + 1_000_000), 0.0);
+
+        // Backwards, after a turn to settle
+        let mut meter = SpeedMeter::new(7);
+        for i in 0..12u64 {
+            meter.push(i * step_us, (5 - i % 6) as u8);
+        }
+        assert!(meter.rpm(12 * step_us) < -900.0);
+
+        // A skipped sector starts over
+        meter.push(13 * step_us, 2);
+        assert_eq!(meter.rpm(13 * step_us), 0.0);
+    }
+}
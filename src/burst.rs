@@ -0,0 +1,128 @@
+//! Post-processing of a burst: raw readings taken back to back at a high,
+//! fixed rate for a short time, to look at motor commutation or solenoid
+//! transients that the normal sample period cannot resolve.
+
+use defmt::Format;
+
+use crate::fixed;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Summary {
+    pub samples: u32,
+    pub rate_hz: u32,
+    pub min_mv: u32,
+    pub max_mv: u32,
+    pub mean_mv: u32,
+    /// Of the strongest periodic component, from crossings of the mean;
+    /// `None` without at least two full cycles.
+    pub frequency_hz: Option<f32>,
+}
+
+/// The voltage the sampler reports for a 12-bit count.
+pub fn voltage_mv(raw: u16) -> u32 {
+    if cfg!(feature = "fixed-point") {
+        return fixed::voltage_mv(raw);
+    }
+    ((raw as f32 / 4095.0) * 3300.0) as u32
+}
+
+pub fn summarize(raw: &[u16], rate_hz: u32) -> Summary {
+    let min = raw.iter().copied().min().unwrap_or(0);
+    let max = raw.iter().copied().max().unwrap_or(0);
+    let sum: u64 = raw.iter().map(|&r| r as u64).sum();
+    let mean = sum.checked_div(raw.len() as u64).unwrap_or(0) as u16;
+    Summary {
+        samples: raw.len() as u32,
+        rate_hz,
+        min_mv: voltage_mv(min),
+        max_mv: voltage_mv(max),
+        mean_mv: voltage_mv(mean),
+        frequency_hz: frequency_hz(raw, rate_hz, mean, (max - min) / 10),
+    }
+}
+
+/// Counts rising crossings of `mean`, ignoring noise within `hysteresis`
+/// of it, and times the whole cycles between the first and last.
+fn frequency_hz(raw: &[u16], rate_hz: u32, mean: u16, hysteresis: u16) -> Option<f32> {
+    let low = mean.saturating_sub(hysteresis);
+    let high = mean.saturating_add(hysteresis);
+    let mut armed = false;
+    let mut first = None;
+    let mut last = 0;
+    let mut cycles = 0u32;
+    for (i, &r) in raw.iter().enumerate() {
+        if r < low {
+            armed = true;
+        } else if armed && r > high {
+            armed = false;
+            if first.is_none() {
+                first = Some(i);
+            } else {
+                cycles += 1;
+            }
+            last = i;
+        }
+    }
+    let span = last - first?;
+    (cycles >= 2).then(|| cycles as f32 * rate_hz as f32 / span as f32)
+}
+
+/// Averages each run of `factor` readings into one; a final short run is
+/// averaged over what there is.
+pub fn decimate(raw: &[u16], factor: usize) -> impl Iterator<Item = u16> + '_ {
+    raw.chunks(factor.max(1))
+        .map(|chunk| (chunk.iter().map(|&r| r as u32).sum::<u32>() / chunk.len() as u32) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square wave of `period` samples between 1000 and 3000.
+    fn square(len: usize, period: usize) -> Vec<u16> {
+        (0..len)
+            .map(|i| if i % period < period / 2 { 1000 } else { 3000 })
+            .collect()
+    }
+
+    #[test]
+    fn summary_of_a_square_wave() {
+        // 200 samples per cycle at 20kHz is 100Hz
+        let summary = summarize(&square(2000, 200), 20_000);
+        assert_eq!(summary.samples, 2000);
+        assert_eq!(summary.min_mv, voltage_mv(1000));
+        assert_eq!(summary.max_mv, voltage_mv(3000));
+        assert_eq!(summary.mean_mv, voltage_mv(2000));
+        let frequency_hz = summary.frequency_hz.unwrap();
+        assert!((frequency_hz - 100.0).abs() < 0.5, "{frequency_hz}");
+    }
+
+    #[test]
+    fn no_frequency_without_two_cycles() {
+        assert_eq!(summarize(&square(300, 200), 20_000).frequency_hz, None);
+        assert_eq!(summarize(&[2048; 100], 20_000).frequency_hz, None);
+        assert_eq!(summarize(&[], 20_000).frequency_hz, None);
+    }
+
+    #[test]
+    fn noise_near_the_mean_is_not_counted() {
+        let mut raw = square(2000, 400);
+        // Chatter around the mean on every edge
+        for i in (0..2000).step_by(200) {
+            raw[i] = 2010;
+            if i + 1 < raw.len() {
+                raw[i + 1] = 1990;
+            }
+        }
+        let frequency_hz = summarize(&raw, 20_000).frequency_hz.unwrap();
+        assert!((frequency_hz - 50.0).abs() < 0.5, "{frequency_hz}");
+    }
+
+    #[test]
+    fn decimation_averages() {
+        let raw = [1, 3, 5, 7, 9];
+        assert_eq!(decimate(&raw, 2).collect::<Vec<_>>(), [2, 6, 9]);
+        assert_eq!(decimate(&raw, 1).collect::<Vec<_>>(), raw);
+        assert_eq!(decimate(&raw, 0).collect::<Vec<_>>(), raw);
+    }
+}
@@ -0,0 +1,70 @@
+//! Classification of button presses by how long the button was held, and
+//! the LED countdown shown while it is held at boot to factory reset.
+
+use defmt::Format;
+
+/// Held at least this long for a long press.
+pub const LONG_PRESS_MS: u64 = 2_000;
+/// Held at least this long for a very long press.
+pub const VERY_LONG_PRESS_MS: u64 = 10_000;
+/// A second press starting within this long of a short one makes it a
+/// double press.
+pub const DOUBLE_PRESS_GAP_MS: u64 = 400;
+/// Held this long from boot to erase everything stored.
+pub const BOOT_RESET_MS: u64 = VERY_LONG_PRESS_MS;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Press {
+    Short,
+    Long,
+    VeryLong,
+    /// Two short presses close together, told apart by whoever watches
+    /// for the second.
+    Double,
+}
+
+impl Press {
+    pub fn from_duration_ms(held_ms: u64) -> Self {
+        if held_ms >= VERY_LONG_PRESS_MS {
+            Press::VeryLong
+        } else if held_ms >= LONG_PRESS_MS {
+            Press::Long
+        } else {
+            Press::Short
+        }
+    }
+}
+
+/// Whether the LED is lit `held_ms` into the hold at boot. It blinks once
+/// in the first second, twice in the second and so on, ten times in the
+/// last, then stays lit once the hold is long enough.
+pub fn countdown_lit(held_ms: u64) -> bool {
+    if held_ms >= BOOT_RESET_MS {
+        return true;
+    }
+    let blinks = held_ms / 1000 + 1;
+    let blink_ms = 1000 / blinks;
+    let ms = held_ms % 1000;
+    // Dark for whatever is left of the second after the last blink
+    ms / blink_ms < blinks && ms % blink_ms < blink_ms / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_blinks_faster_each_second() {
+        let blinks_in = |second: u64| {
+            (second * 1000..(second + 1) * 1000)
+                .filter(|&ms| countdown_lit(ms) && !countdown_lit(ms.wrapping_sub(1)))
+                .count()
+        };
+        // The first millisecond is lit with nothing before it
+        assert!(countdown_lit(0));
+        assert_eq!(blinks_in(1), 2);
+        assert_eq!(blinks_in(4), 5);
+        assert_eq!(blinks_in(9), 10);
+        assert!((BOOT_RESET_MS..BOOT_RESET_MS + 2000).all(countdown_lit));
+    }
+}
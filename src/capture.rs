@@ -0,0 +1,292 @@
+//! Oscilloscope-style triggered capture. Once armed, every sample goes into
+//! a circular buffer; when the voltage crosses the trigger level the samples
+//! from just before the crossing are kept, the rest of the buffer fills with
+//! those after it, and the capture is frozen until it is armed again.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Sample;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Either,
+}
+
+pub const EDGES: [Edge; 3] = [Edge::Rising, Edge::Falling, Edge::Either];
+
+impl Edge {
+    pub fn parse(name: &str) -> Option<Self> {
+        EDGES.into_iter().find(|e| e.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edge::Rising => "rising",
+            Edge::Falling => "falling",
+            Edge::Either => "either",
+        }
+    }
+
+    /// Whether going from `last_mv` to `voltage_mv` crosses `level_mv` this
+    /// way. Reaching the level counts as crossing it.
+    pub(crate) fn crossed(&self, last_mv: u32, voltage_mv: u32, level_mv: u32) -> bool {
+        let rising = last_mv < level_mv && voltage_mv >= level_mv;
+        let falling = last_mv >= level_mv && voltage_mv < level_mv;
+        match self {
+            Edge::Rising => rising,
+            Edge::Falling => falling,
+            Edge::Either => rising || falling,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Trigger {
+    pub level_mv: u32,
+    pub edge: Edge,
+    /// Samples kept from before the crossing.
+    pub pre: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum State {
+    /// Never armed.
+    Idle,
+    /// Waiting for the crossing.
+    Armed,
+    /// Filling the rest of the buffer.
+    Triggered,
+    /// Frozen, ready to be read.
+    Done,
+}
+
+const EMPTY: Sample = Sample {
+    timestamp_us: 0,
+    raw: 0,
+    voltage_mv: 0,
+};
+
+/// A capture of up to `N` samples, including the one that crossed.
+pub struct Capture<const N: usize> {
+    buffer: [Sample; N],
+    /// Where the next sample goes.
+    next: usize,
+    len: usize,
+    trigger: Option<Trigger>,
+    state: State,
+    /// Samples still to come after the crossing.
+    remaining: usize,
+    /// Position of the crossing sample in the capture.
+    trigger_index: usize,
+    last_mv: Option<u32>,
+}
+
+impl<const N: usize> Capture<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [EMPTY; N],
+            next: 0,
+            len: 0,
+            trigger: None,
+            state: State::Idle,
+            remaining: 0,
+            trigger_index: 0,
+            last_mv: None,
+        }
+    }
+
+    /// Discards any capture and waits for `trigger`. At most `N - 1`
+    /// samples are kept from before the crossing.
+    pub fn arm(&mut self, trigger: Trigger) {
+        *self = Self {
+            trigger: Some(Trigger {
+                pre: trigger.pre.min(N - 1),
+                ..trigger
+            }),
+            state: State::Armed,
+            ..Self::new()
+        };
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn trigger(&self) -> Option<Trigger> {
+        self.trigger
+    }
+
+    /// Takes the next sample. Returns `true` when it completes the capture.
+    pub fn update(&mut self, sample: &Sample) -> bool {
+        let Some(trigger) = self.trigger else {
+            return false;
+        };
+        match self.state {
+            State::Idle | State::Done => return false,
+            State::Armed => {
+                let last_mv = self.last_mv.replace(sample.voltage_mv);
+                self.push(sample);
+                if !last_mv.is_some_and(|last_mv| {
+                    trigger
+                        .edge
+                        .crossed(last_mv, sample.voltage_mv, trigger.level_mv)
+                }) {
+                    return false;
+                }
+                // Only the latest `pre` samples before this one are kept
+                self.len = self.len.min(trigger.pre + 1);
+                self.trigger_index = self.len - 1;
+                self.remaining = N - trigger.pre - 1;
+                self.state = State::Triggered;
+            }
+            State::Triggered => {
+                self.push(sample);
+                self.remaining -= 1;
+            }
+        }
+        if self.remaining == 0 {
+            self.state = State::Done;
+        }
+        self.state == State::Done
+    }
+
+    /// Samples captured so far, or all of them once done.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Position of the sample that crossed the level, once triggered.
+    pub fn trigger_index(&self) -> usize {
+        self.trigger_index
+    }
+
+    /// The samples from index `start`, oldest first.
+    pub fn samples(&self, start: usize) -> impl Iterator<Item = &Sample> {
+        let first = self.next + N - self.len;
+        (start..self.len).map(move |i| &self.buffer[(first + i) % N])
+    }
+
+    fn push(&mut self, sample: &Sample) {
+        self.buffer[self.next] = *sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+}
+
+impl<const N: usize> Default for Capture<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(i: u64, voltage_mv: u32) -> Sample {
+        Sample {
+            timestamp_us: i,
+            raw: 0,
+            voltage_mv,
+        }
+    }
+
+    fn times<const N: usize>(capture: &Capture<N>) -> Vec<u64> {
+        capture.samples(0).map(|s| s.timestamp_us).collect()
+    }
+
+    #[test]
+    fn keeps_samples_around_the_crossing() {
+        let mut capture = Capture::<8>::new();
+        capture.arm(Trigger {
+            level_mv: 2000,
+            edge: Edge::Rising,
+            pre: 3,
+        });
+        for i in 0..20 {
+            assert!(!capture.update(&sample(i, 1000)));
+        }
+        assert_eq!(capture.state(), State::Armed);
+        assert!(!capture.update(&sample(20, 2500)));
+        assert_eq!(capture.state(), State::Triggered);
+        for i in 21..24 {
+            assert!(!capture.update(&sample(i, 2500)));
+        }
+        assert!(capture.update(&sample(24, 2500)));
+        assert_eq!(capture.state(), State::Done);
+
+        // Frozen from here on
+        assert!(!capture.update(&sample(25, 1000)));
+        assert_eq!(times(&capture), [17, 18, 19, 20, 21, 22, 23, 24]);
+        assert_eq!(capture.trigger_index(), 3);
+    }
+
+    #[test]
+    fn early_crossing_keeps_what_there_is() {
+        let mut capture = Capture::<6>::new();
+        capture.arm(Trigger {
+            level_mv: 2000,
+            edge: Edge::Falling,
+            pre: 3,
+        });
+        capture.update(&sample(0, 2500));
+        capture.update(&sample(1, 1500));
+        assert_eq!(capture.trigger_index(), 1);
+        assert!(!capture.update(&sample(2, 1500)));
+        assert!(capture.update(&sample(3, 1500)));
+        assert_eq!(times(&capture), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn edges() {
+        assert!(Edge::Rising.crossed(1999, 2000, 2000));
+        assert!(!Edge::Rising.crossed(2000, 2100, 2000));
+        assert!(!Edge::Rising.crossed(2100, 1900, 2000));
+        assert!(Edge::Falling.crossed(2000, 1999, 2000));
+        assert!(Edge::Either.crossed(2100, 1900, 2000));
+        assert!(Edge::Either.crossed(1900, 2100, 2000));
+        for edge in EDGES {
+            assert_eq!(Edge::parse(edge.name()), Some(edge));
+        }
+    }
+
+    #[test]
+    fn pre_is_limited_to_the_buffer() {
+        let mut capture = Capture::<4>::new();
+        capture.arm(Trigger {
+            level_mv: 2000,
+            edge: Edge::Either,
+            pre: 100,
+        });
+        for i in 0..10 {
+            capture.update(&sample(i, 1000));
+        }
+        assert!(capture.update(&sample(10, 3000)));
+        assert_eq!(times(&capture), [7, 8, 9, 10]);
+        assert_eq!(capture.trigger_index(), 3);
+    }
+
+    #[test]
+    fn rearming_discards_the_capture() {
+        let mut capture = Capture::<4>::new();
+        assert!(!capture.update(&sample(0, 3000)));
+        assert_eq!(capture.state(), State::Idle);
+        let trigger = Trigger {
+            level_mv: 2000,
+            edge: Edge::Rising,
+            pre: 0,
+        };
+        capture.arm(trigger);
+        capture.update(&sample(0, 1000));
+        capture.arm(trigger);
+        assert!(capture.is_empty());
+        assert_eq!(capture.state(), State::Armed);
+    }
+}
@@ -0,0 +1,316 @@
+//! Sweeping strip chart of the field for colour displays, drawn with
+//! embedded-graphics: the reading in large type, in whatever unit it is
+//! given in, above a plot of the field's recent history, with the field
+//! scale down the left, the alarm threshold band dashed across it and the
+//! time span along the bottom.
+//!
+//! Like a patient monitor, the trace is written left to right over the
+//! oldest columns, with a short gap ahead of it, rather than scrolled: each
+//! update then sends only the new columns and the reading over the bus.
+
+use core::fmt::Write;
+
+use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_10X20};
+use embedded_graphics::mono_font::{MonoTextStyle, MonoTextStyleBuilder};
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyleBuilder};
+use heapless::{Deque, String};
+
+use crate::schema::Config;
+use crate::units::Quantity;
+
+/// Widest plot supported, in columns.
+pub const MAX_COLUMNS: usize = 320;
+
+// Room for the reading above the plot, the scale to its left and the time
+// span below it
+const HEADER_HEIGHT: u32 = 26;
+const SCALE_WIDTH: u32 = 30;
+const FOOTER_HEIGHT: u32 = 12;
+
+const BACKGROUND: Rgb565 = Rgb565::BLACK;
+const TEXT: Rgb565 = Rgb565::WHITE;
+const ZERO_LINE: Rgb565 = Rgb565::new(8, 16, 8);
+const THRESHOLD: Rgb565 = Rgb565::YELLOW;
+// The same ends as the LED gradient
+const NORTH: Rgb565 = Rgb565::RED;
+const SOUTH: Rgb565 = Rgb565::BLUE;
+const FAULT: Rgb565 = Rgb565::new(31, 24, 0);
+
+// Threshold lines are dashed so a trace running along one stays visible
+const DASH: i32 = 4;
+// Blank columns ahead of the trace, parting the newest from the oldest
+const GAP: i32 = 4;
+
+pub struct StripChart {
+    plot: Rectangle,
+    full_scale_mt: f32,
+    /// Where the threshold detector switches on the way up and on the way
+    /// down.
+    thresholds_mt: [f32; 2],
+    span_s: u32,
+    /// Columns pushed but not yet drawn.
+    pending: Deque<f32, MAX_COLUMNS>,
+    /// Plot column the next one is drawn in.
+    cursor: i32,
+    /// Row the trace reached in the last column drawn.
+    previous: Option<i32>,
+}
+
+impl StripChart {
+    /// A chart filling a display of `size`, one column per `column_ms`.
+    pub fn new(size: Size, config: &Config, column_ms: u32) -> Self {
+        let plot = Rectangle::new(
+            Point::new(SCALE_WIDTH as i32, HEADER_HEIGHT as i32),
+            Size::new(
+                size.width
+                    .saturating_sub(SCALE_WIDTH)
+                    .min(MAX_COLUMNS as u32),
+                size.height.saturating_sub(HEADER_HEIGHT + FOOTER_HEIGHT),
+            ),
+        );
+        // Full scale is the strongest field the sensor can report
+        let full_scale_mt = config
+            .field_mt(config.min_voltage_mv)
+            .abs()
+            .max(config.field_mt(config.max_voltage_mv).abs());
+        let thresholds_mt = [
+            config.field_mt(config.threshold_mv.saturating_add(config.hysteresis_mv)),
+            config.field_mt(config.threshold_mv.saturating_sub(config.hysteresis_mv)),
+        ];
+        Self {
+            plot,
+            full_scale_mt,
+            thresholds_mt,
+            span_s: plot.size.width * column_ms / 1000,
+            pending: Deque::new(),
+            cursor: 0,
+            previous: None,
+        }
+    }
+
+    /// Queues a column for the next `draw`, dropping the oldest queued if a
+    /// whole plot's worth is already waiting.
+    pub fn push(&mut self, field_mt: f32) {
+        if self.pending.len() >= self.plot.size.width as usize {
+            self.pending.pop_front();
+        }
+        let _ = self.pending.push_back(field_mt);
+    }
+
+    /// Clears the display and draws the parts that never change: the field
+    /// scale, the time span and the empty plot. The trace starts again from
+    /// the left.
+    pub fn draw_axes<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+    ) -> Result<(), D::Error> {
+        target.clear(BACKGROUND)?;
+        for column in 0..self.plot.size.width as i32 {
+            self.clear_column(target, column)?;
+        }
+        self.cursor = 0;
+        self.previous = None;
+        let small = MonoTextStyle::new(&FONT_6X10, TEXT);
+        let mut label: String<8> = String::new();
+
+        let scale = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Middle)
+            .build();
+        for field_mt in [self.full_scale_mt, 0.0, -self.full_scale_mt] {
+            label.clear();
+            let _ = write!(label, "{:.0}", field_mt);
+            let position = Point::new(SCALE_WIDTH as i32 - 3, self.y(field_mt));
+            Text::with_text_style(&label, position, small, scale).draw(target)?;
+        }
+
+        let footer = self.plot.top_left.y + self.plot.size.height as i32 + 1;
+        Text::with_baseline("mT", Point::new(0, footer), small, Baseline::Top).draw(target)?;
+        label.clear();
+        let _ = write!(label, "{}s", self.span_s);
+        Text::with_baseline(
+            &label,
+            Point::new(self.plot.top_left.x, footer),
+            small,
+            Baseline::Top,
+        )
+        .draw(target)?;
+        Ok(())
+    }
+
+    /// Redraws the reading, `None` while it is invalid, and adds the columns
+    /// pushed since the last call to the plot.
+    pub fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        reading: Option<Quantity>,
+    ) -> Result<(), D::Error> {
+        // Padded to a fixed width, so the background covers the last value,
+        // wide enough for counts or millitesla on a 160 pixel display
+        let mut text: String<16> = String::new();
+        let color = match reading {
+            Some(reading) => {
+                let mut shown: String<16> = String::new();
+                let _ = write!(shown, "{}", reading);
+                let _ = write!(text, "{:>11}", shown.as_str());
+                TEXT
+            }
+            None => {
+                let _ = write!(text, "{:>11}", "FAULT");
+                FAULT
+            }
+        };
+        let large = MonoTextStyleBuilder::new()
+            .font(&FONT_10X20)
+            .text_color(color)
+            .background_color(BACKGROUND)
+            .build();
+        Text::with_baseline(
+            &text,
+            Point::new(SCALE_WIDTH as i32, 0),
+            large,
+            Baseline::Top,
+        )
+        .draw(target)?;
+
+        let width = self.plot.size.width as i32;
+        if width == 0 {
+            self.pending.clear();
+            return Ok(());
+        }
+        while let Some(field_mt) = self.pending.pop_front() {
+            // The column under the cursor was cleared as the far edge of the
+            // gap, so only the trace and the new far edge need drawing
+            self.clear_column(target, (self.cursor + GAP) % width)?;
+            let x = self.plot.top_left.x + self.cursor;
+            let y = self.y(field_mt);
+            let color = if field_mt < 0.0 { NORTH } else { SOUTH };
+            let from = self.previous.replace(y).unwrap_or(y);
+            target.fill_solid(
+                &Rectangle::with_corners(Point::new(x, from), Point::new(x, y)),
+                color,
+            )?;
+            self.cursor = (self.cursor + 1) % width;
+        }
+        Ok(())
+    }
+
+    /// Blanks one column of the plot, leaving the zero line and the dashed
+    /// thresholds.
+    fn clear_column<D: DrawTarget<Color = Rgb565>>(
+        &self,
+        target: &mut D,
+        column: i32,
+    ) -> Result<(), D::Error> {
+        let x = self.plot.top_left.x + column;
+        target.fill_solid(
+            &Rectangle::new(
+                Point::new(x, self.plot.top_left.y),
+                Size::new(1, self.plot.size.height),
+            ),
+            BACKGROUND,
+        )?;
+        Pixel(Point::new(x, self.y(0.0)), ZERO_LINE).draw(target)?;
+        if (x / DASH) % 2 == 0 {
+            for field_mt in self.thresholds_mt {
+                Pixel(Point::new(x, self.y(field_mt)), THRESHOLD).draw(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Row of `field_mt` in the plot, south (positive) upwards.
+    fn y(&self, field_mt: f32) -> i32 {
+        let half = (self.plot.size.height as i32 - 1) / 2;
+        let offset = (field_mt / self.full_scale_mt * half as f32) as i32;
+        self.plot.top_left.y + half - offset.clamp(-half, half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use super::*;
+
+    const SIZE: Size = Size::new(240, 240);
+
+    /// Notes which columns of the plot were drawn in.
+    struct Columns([bool; SIZE.width as usize]);
+
+    impl OriginDimensions for Columns {
+        fn size(&self) -> Size {
+            SIZE
+        }
+    }
+
+    impl DrawTarget for Columns {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            let columns = SCALE_WIDTH as i32..SIZE.width as i32;
+            let rows = HEADER_HEIGHT as i32..(SIZE.height - FOOTER_HEIGHT) as i32;
+            for Pixel(point, _) in pixels {
+                if columns.contains(&point.x) && rows.contains(&point.y) {
+                    self.0[point.x as usize] = true;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Columns {
+        fn drawn(&mut self) -> impl Iterator<Item = i32> {
+            let drawn = core::mem::replace(&mut self.0, [false; SIZE.width as usize]);
+            (0..)
+                .zip(drawn)
+                .filter_map(|(x, drawn)| drawn.then_some(x - SCALE_WIDTH as i32))
+        }
+    }
+
+    #[test]
+    fn draws_only_the_new_columns() {
+        let mut target = Columns([false; SIZE.width as usize]);
+        let mut chart = StripChart::new(SIZE, &Config::default(), 200);
+        chart.draw_axes(&mut target).unwrap();
+        assert_eq!(target.drawn().count(), (SIZE.width - SCALE_WIDTH) as usize);
+
+        chart.push(10.0);
+        chart.draw(&mut target, None).unwrap();
+        assert!(target.drawn().eq([0, GAP]));
+
+        chart.push(-10.0);
+        chart.push(0.0);
+        chart.draw(&mut target, None).unwrap();
+        assert!(target.drawn().eq([1, 2, 1 + GAP, 2 + GAP]));
+
+        // Nothing new, so only the reading is drawn
+        chart.draw(&mut target, None).unwrap();
+        assert_eq!(target.drawn().count(), 0);
+    }
+
+    #[test]
+    fn wraps_round_to_the_left() {
+        let mut target = Columns([false; SIZE.width as usize]);
+        let mut chart = StripChart::new(SIZE, &Config::default(), 200);
+        chart.draw_axes(&mut target).unwrap();
+        let width = (SIZE.width - SCALE_WIDTH) as i32;
+        for _ in 0..width - 1 {
+            chart.push(0.0);
+        }
+        chart.draw(&mut target, None).unwrap();
+        target.drawn().for_each(drop);
+
+        chart.push(5.0);
+        chart.push(5.0);
+        chart.draw(&mut target, None).unwrap();
+        assert!(target.drawn().eq([0, GAP - 1, GAP, width - 1]));
+    }
+}
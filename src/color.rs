@@ -0,0 +1,240 @@
+//! The LED's colours and the WS2812 bit stream, independent of what drives
+//! the LED (the RMT on the ESP32, PIO or SPI elsewhere).
+
+use defmt::Format;
+
+use crate::fixed;
+use crate::palette::Palette;
+use crate::schema::Config;
+
+// WS2812 timing (in nanoseconds)
+pub const CODE_PERIOD_NS: u32 = 1250; // 800kHz
+pub const T0H_NS: u32 = 400;
+pub const T0L_NS: u32 = CODE_PERIOD_NS - T0H_NS;
+pub const T1H_NS: u32 = 850;
+pub const T1L_NS: u32 = CODE_PERIOD_NS - T1H_NS;
+
+/// Bits per LED.
+pub const WS2812_BITS: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct RGB8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl RGB8 {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The colour at `brightness` out of 255.
+    pub fn scaled(self, brightness: u8) -> Self {
+        let scale = |c: u8| (c as u16 * brightness as u16 / u8::MAX as u16) as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+/// The colour in `palette` for `voltage_mv` across the configured range:
+/// by default red for low voltage (north) through to blue for high voltage
+/// (south).
+pub fn voltage_to_color(voltage_mv: u32, config: &Config, palette: Palette) -> RGB8 {
+    if cfg!(feature = "fixed-point") && palette == Palette::RedBlue {
+        return fixed::voltage_to_color(voltage_mv, config);
+    }
+    let v = voltage_mv as f32;
+    let min = config.min_voltage_mv as f32;
+    let max = config.max_voltage_mv as f32;
+    let t = if v <= min {
+        0.0
+    } else if v >= max {
+        1.0
+    } else {
+        (v - min) / (max - min)
+    };
+    palette.color(t)
+}
+
+/// Entries in a [`Gradient`]'s table.
+pub const GRADIENT_STEPS: usize = 256;
+
+/// [`voltage_to_color`] as a table over the configured range, built once so
+/// each sample costs an integer division and a lookup rather than float
+/// arithmetic. The table's steps are as fine as a colour channel's.
+pub struct Gradient {
+    table: [RGB8; GRADIENT_STEPS],
+    min_mv: u32,
+    max_mv: u32,
+    palette: Palette,
+}
+
+impl Gradient {
+    pub fn new(config: &Config, palette: Palette) -> Self {
+        let last = (GRADIENT_STEPS - 1) as f32;
+        Self {
+            table: core::array::from_fn(|i| palette.color(i as f32 / last)),
+            min_mv: config.min_voltage_mv,
+            max_mv: config.max_voltage_mv,
+            palette,
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn color(&self, voltage_mv: u32) -> RGB8 {
+        self.table[self.index(voltage_mv)]
+    }
+
+    /// Whether the colour for `voltage_mv` blinks, see [`Palette::blinks`].
+    pub fn blinks(&self, voltage_mv: u32) -> bool {
+        let t = self.index(voltage_mv) as f32 / (GRADIENT_STEPS - 1) as f32;
+        self.palette.blinks(t)
+    }
+
+    fn index(&self, voltage_mv: u32) -> usize {
+        if voltage_mv <= self.min_mv {
+            0
+        } else if voltage_mv >= self.max_mv {
+            GRADIENT_STEPS - 1
+        } else {
+            let span = (self.max_mv - self.min_mv) as usize;
+            (voltage_mv - self.min_mv) as usize * (GRADIENT_STEPS - 1) / span
+        }
+    }
+}
+
+/// The bits a WS2812 expects for `color`, in the order they are sent: green,
+/// red then blue, most significant bit first.
+pub fn ws2812_bits(color: RGB8) -> impl Iterator<Item = bool> {
+    [color.g, color.r, color.b]
+        .into_iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| byte & (1 << bit) != 0))
+}
+
+/// A duration in cycles of a `clock_mhz` clock.
+pub fn ticks(ns: u32, clock_mhz: u32) -> u16 {
+    (ns * clock_mhz / 1000) as u16
+}
+
+/// High and low times of one bit, in cycles of a `clock_mhz` clock.
+pub fn pulse_ticks(bit: bool, clock_mhz: u32) -> (u16, u16) {
+    let (high_ns, low_ns) = if bit {
+        (T1H_NS, T1L_NS)
+    } else {
+        (T0H_NS, T0L_NS)
+    };
+    (ticks(high_ns, clock_mhz), ticks(low_ns, clock_mhz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            min_voltage_mv: 500,
+            max_voltage_mv: 2800,
+            ..Config::default()
+        }
+    }
+
+    fn red_blue(voltage_mv: u32) -> RGB8 {
+        voltage_to_color(voltage_mv, &config(), Palette::RedBlue)
+    }
+
+    #[test]
+    fn gradient_runs_red_to_blue() {
+        assert_eq!(red_blue(500), RGB8::new(255, 0, 0));
+        assert_eq!(red_blue(2800), RGB8::new(0, 0, 255));
+        assert_eq!(red_blue(1650), RGB8::new(127, 0, 127));
+    }
+
+    #[test]
+    fn gradient_clamps_outside_range() {
+        assert_eq!(red_blue(0), RGB8::new(255, 0, 0));
+        assert_eq!(red_blue(3300), RGB8::new(0, 0, 255));
+    }
+
+    #[test]
+    fn table_matches_the_gradient() {
+        for config in [
+            config(),
+            Config::default(),
+            Config {
+                min_voltage_mv: 1600,
+                max_voltage_mv: 1700,
+                ..Config::default()
+            },
+        ] {
+            let table = Gradient::new(&config, Palette::RedBlue);
+            for voltage_mv in 0..=3300 {
+                let (looked_up, computed) = (
+                    table.color(voltage_mv),
+                    voltage_to_color(voltage_mv, &config, Palette::RedBlue),
+                );
+                assert!(looked_up.r.abs_diff(computed.r) <= 1, "{voltage_mv}");
+                assert!(looked_up.b.abs_diff(computed.b) <= 1, "{voltage_mv}");
+            }
+            assert_eq!(table.color(config.min_voltage_mv), RGB8::new(255, 0, 0));
+            assert_eq!(table.color(config.max_voltage_mv), RGB8::new(0, 0, 255));
+        }
+    }
+
+    #[test]
+    fn table_follows_the_palette() {
+        let table = Gradient::new(&config(), Palette::OrangeBlue);
+        assert_eq!(table.palette(), Palette::OrangeBlue);
+        assert_eq!(table.color(500), Palette::OrangeBlue.color(0.0));
+        assert_eq!(table.color(2800), Palette::OrangeBlue.color(1.0));
+        assert!(!table.blinks(500));
+
+        let table = Gradient::new(&config(), Palette::Blink);
+        assert!(table.blinks(500));
+        assert!(table.blinks(1600));
+        assert!(!table.blinks(1700));
+        assert!(!table.blinks(2800));
+    }
+
+    #[test]
+    fn scaling() {
+        let color = RGB8::new(255, 128, 0);
+        assert_eq!(color.scaled(255), color);
+        assert_eq!(color.scaled(0), RGB8::new(0, 0, 0));
+        assert_eq!(color.scaled(64), RGB8::new(64, 32, 0));
+    }
+
+    #[test]
+    fn bits_are_grb_msb_first() {
+        let bits: Vec<bool> = ws2812_bits(RGB8::new(0x12, 0x34, 0x56)).collect();
+        let expected: Vec<bool> = "001101000001001001010110"
+            .chars()
+            .map(|c| c == '1')
+            .collect();
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn pulses_at_80mhz() {
+        // 12.5ns cycles: 400ns is 32 and 850ns is 68
+        assert_eq!(pulse_ticks(false, 80), (32, 68));
+        assert_eq!(pulse_ticks(true, 80), (68, 32));
+
+        let pulses: Vec<(u16, u16)> = ws2812_bits(RGB8::new(0x80, 0x01, 0x00))
+            .map(|bit| pulse_ticks(bit, 80))
+            .collect();
+        let mut expected = vec![(32, 68); WS2812_BITS];
+        // Bit 0 of green, then bit 7 of red
+        expected[7] = (68, 32);
+        expected[8] = (68, 32);
+        assert_eq!(pulses, expected);
+    }
+
+    #[test]
+    fn pulses_at_40mhz() {
+        assert_eq!(pulse_ticks(false, 40), (16, 34));
+        assert_eq!(pulse_ticks(true, 40), (34, 16));
+    }
+}
@@ -0,0 +1,1412 @@
+//! Console command parsing.
+
+use defmt::Format;
+use heapless::String;
+
+use crate::ambient::Settings as AmbientSettings;
+use crate::capture::Edge;
+use crate::current::Profile;
+use crate::datetime::DateTime;
+use crate::filter::{MAX_MEDIAN_WINDOW, Stage};
+use crate::fixture::MAX_POSITIONS;
+use crate::grading::{Grades, MAX_SHOTS};
+use crate::haptic::Trigger;
+use crate::mode::Mode;
+use crate::night::{Settings as NightSettings, TimeOfDay};
+use crate::notch::Mains;
+use crate::palette::Palette;
+use crate::pas::Curve;
+use crate::pid::Gains;
+use crate::profile::Name as ProfileName;
+use crate::relay::FailSafe;
+use crate::remote::{Rejection, Update};
+use crate::rules::{self, Action, Color, Comparison, Rule};
+use crate::schedule::{Entry, Task, When};
+use crate::sync::Role;
+use crate::trigger::{Condition, MAX_PULSE_US};
+use crate::units::Unit;
+use crate::verbosity::{Level, Module};
+use crate::waveform::{Shape, Waveform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Command {
+    /// Print the auto-zero setting and the zero it has reached.
+    AutoZero,
+    /// Track the zero with a time constant of this many seconds, or stop.
+    SetAutoZero(Option<u32>),
+    /// Print the battery voltage and charge.
+    Battery,
+    /// Print a summary of the last burst.
+    Burst,
+    /// Sample at `rate_hz` for `duration_ms` into RAM.
+    RunBurst { rate_hz: u32, duration_ms: u32 },
+    /// Stream the last burst as CSV, each row the average of `decimate`.
+    DumpBurst { decimate: u32 },
+    /// Print the triggered capture's state, and the capture once complete.
+    Capture,
+    /// Start a new capture, keeping `pre` samples from before the crossing
+    /// if given.
+    ArmCapture {
+        level_mv: u32,
+        edge: Edge,
+        pre: Option<u32>,
+    },
+    /// Print the contact's state, its latest changes and the settings.
+    Contact,
+    /// Read the contact as closed from `closed_mt` and open from `open_mt`.
+    SetContactThresholds { closed_mt: f32, open_mt: f32 },
+    /// Count a change only once it has held for `ms`.
+    SetContactDebounce { ms: u32 },
+    /// Print the part and batch counts and the settings.
+    Count,
+    /// Count a crossing only `ms` after the last one counted.
+    SetCountDeadTime { ms: u32 },
+    /// Gather the parts into batches of `size`, or not at zero.
+    SetBatch { size: u32 },
+    /// Pulse the batch output for `ms` at the end of each batch.
+    SetBatchPulse { ms: u32 },
+    ResetCount,
+    /// Print the current, the scale and the closed loop's setting.
+    Current,
+    /// Make the sensor's output now the current scale's zero.
+    ZeroCurrent,
+    /// Set the scale's sensitivity from a known current flowing now.
+    CalibrateCurrent { amps: f32 },
+    /// List the built-in current sensor profiles.
+    CurrentProfiles,
+    /// Take the scale from a profile, for the module run from `supply_mv`.
+    SetCurrentProfile { profile: Profile, supply_mv: u32 },
+    /// Run the closed loop with this full scale in amps, or stop.
+    SetCurrentLoop(Option<f32>),
+    /// Stream stored log records as CSV, optionally a page at a time.
+    Dump { start: u32, count: Option<u32> },
+    /// Print a one-line JSON diagnostics report.
+    Diag,
+    /// Print the Allan deviation of the drift run.
+    Drift,
+    /// Print the filter chain.
+    Filters,
+    /// Put `stage` at `index` in the filter chain, counting from 1, and
+    /// switch it on.
+    SetFilter { index: u32, stage: Stage },
+    /// Switch the stage at `index` on or off.
+    EnableFilter { index: u32, enabled: bool },
+    RemoveFilter { index: u32 },
+    /// Print the fixture's limits and the last test's verdict.
+    Fixture,
+    /// Pass a mean field from `low_mt` to `high_mt`, spreading no more
+    /// than `spread_mt` if given.
+    SetFixtureLimits { low_mt: f32, high_mt: f32, spread_mt: Option<f32> },
+    /// Measure for `ms` in each test.
+    SetFixtureTime { ms: u32 },
+    /// Test a unit, numbered `unit` in its record if given.
+    RunFixture { unit: Option<u32> },
+    /// Test a magnet array, one position per mux channel from 0.
+    ScanFixture { positions: u8, unit: Option<u32> },
+    /// Print the analog gauge's scale and needle angle.
+    Gauge,
+    /// Turn the gauge's needle across the field from `low_mt` to `high_mt`.
+    SetGaugeRange { low_mt: f32, high_mt: f32 },
+    /// Set the speed at the end of the gauge's sweep, in tachometer mode.
+    SetGaugeRpm { full_rpm: u32 },
+    /// Print the placements of the magnet being graded, the last magnet's
+    /// grade and the settings.
+    Grade,
+    /// Grade each magnet over `shots` placements.
+    SetGradeShots { shots: u8 },
+    /// Keep the fixture's distance, `mm`, with the results.
+    SetGradeDistance { mm: f32 },
+    SetGrades(Grades),
+    ResetGrade,
+    /// Print what the haptics buzz on.
+    Haptic,
+    SetHaptic(Trigger),
+    Help,
+    /// Print the rolling histogram of recent voltages.
+    Histogram,
+    /// Restart the histogram with `bin_width_mv` bins, over `window`
+    /// samples if given.
+    SetHistogram { bin_width_mv: u32, window: Option<u32> },
+    /// Print and clear the message of the last panic.
+    LastPanic,
+    /// Print the latency from sample to LED measured in latency mode.
+    Latency,
+    /// Start the latency distribution over.
+    ResetLatency,
+    /// Print the levitation setpoint, gains and loop state.
+    Levitate,
+    /// Hold the field at `setpoint_mt` while levitating.
+    SetLevitate { setpoint_mt: f32 },
+    /// Tune the loop, live.
+    SetLevitateGains(Gains),
+    /// Print the lock-in settings and response.
+    LockIn,
+    /// Drive the coil at `frequency_hz`, averaging over `time_constant_ms`.
+    SetLockIn {
+        frequency_hz: u32,
+        time_constant_ms: u32,
+    },
+    /// Print the log level of every module.
+    Log,
+    /// Set the log level of one module, or of all if `module` is `None`.
+    SetLog { module: Option<Module>, level: Level },
+    /// Print stack usage and heap headroom.
+    Memory,
+    /// Print the mock sensor's waveform, or change it. `period_ms` and
+    /// `amplitude_mv` are kept from the current waveform if not given.
+    Mock(Option<MockWaveform>),
+    /// Print the operating mode, or switch to another.
+    Mode(Option<Mode>),
+    /// Print the motor's setpoint, speed and duty.
+    Motor,
+    /// Run the motor at `rpm`, or stop it at zero.
+    SetMotor { rpm: u32, reverse: bool },
+    SetMotorGains(Gains),
+    /// Print the mux channel the sensor is read through, or select another.
+    Mux(Option<u8>),
+    /// Sample for `duration_s` and print the noise floor.
+    Noise { duration_s: u32 },
+    /// Print the lifetime pulse count.
+    Odometer,
+    /// Clear the lifetime pulse count; ignored unless `confirmed`.
+    ResetOdometer { confirmed: bool },
+    /// Print the pedal assist's cadence, assist and settings.
+    Pas,
+    /// Set the number of magnets around the crank's ring.
+    SetPasMagnets { magnets: u8 },
+    SetPasCurve(Curve),
+    /// Print the voltage, power and energy total.
+    Power,
+    /// Set the voltage divider's ratio from a known voltage present now.
+    CalibrateVoltage { volts: f32 },
+    /// Clear the energy total, if `confirmed`.
+    ResetEnergy { confirmed: bool },
+    /// Suppress (or restore) the per-sample log line.
+    Quiet(bool),
+    /// Print the latest processed reading.
+    Reading,
+    /// Print the relay's thresholds, fail-safe state and whether it is in.
+    Relay,
+    /// Pull the relay in at `pull_in_mt` and drop it out at `drop_out_mt`.
+    SetRelayThresholds { pull_in_mt: f32, drop_out_mt: f32 },
+    /// Keep the relay in for at least `ms` once pulled in.
+    SetRelayMinOn { ms: u32 },
+    SetRelayFailSafe(FailSafe),
+    /// Replay stored log records through the processing in place of the
+    /// sensor, from `start` and optionally only `count` of them.
+    Replay { start: u32, count: Option<u32> },
+    StopReplay,
+    /// Print the servo gauge's settings and angle.
+    Servo,
+    /// Map the field from `low_mt` to `high_mt` onto the servo's travel.
+    SetServoRange { low_mt: f32, high_mt: f32 },
+    /// Set the pulse widths at either end of the travel.
+    SetServoPulses { min_us: u16, max_us: u16 },
+    /// Limit the needle's speed, or lift the limit at zero.
+    SetServoSlew { deg_per_s: f32 },
+    /// Enter low-power mode until the BOOT button is pressed.
+    Sleep,
+    /// Print the peaks of the last block analysed in spectrum mode.
+    Spectrum,
+    /// Analyse the first `points` readings of the last burst.
+    BurstSpectrum { points: u32 },
+    /// Print the speed loop's setpoint, speed and duty.
+    Speed,
+    /// Hold the motor at `rpm` in tachometer mode, or stop it at zero.
+    SetSpeed { rpm: u32 },
+    SetSpeedGains(Gains),
+    /// Print boot count, uptime and reset reason.
+    Stats,
+    /// Print the tamper alarm, if latched, and the settings.
+    Tamper,
+    /// Take the field now, averaged over a second, as the bias magnet's.
+    LearnTamper,
+    /// Let the field stray by `pct` percent of the bias magnet's.
+    SetTamperTolerance { pct: u8 },
+    /// Count a condition only once it has held for `ms`.
+    SetTamperHold { ms: u32 },
+    ClearTamper,
+    /// Print the tare taken off readings.
+    Tare,
+    /// Make the field now the readings' zero.
+    SetTare,
+    /// Go back to the calibrated zero.
+    ClearTare,
+    /// Print the wall-clock time.
+    Time,
+    /// Set the wall-clock time (UTC) and the RTC if fitted.
+    SetTime(DateTime),
+    /// Print the tone measured in the last block by the Goertzel detector.
+    Tone,
+    /// Start measuring `frequency_hz` in blocks of `points` samples.
+    SetTone { frequency_hz: u32, points: u32 },
+    StopTone,
+    /// Measure `frequency_hz` over the last burst.
+    BurstTone { frequency_hz: u32 },
+    /// Print the trigger output's condition, pulse and timing.
+    Trigger,
+    SetTrigger(Condition),
+    /// Make each pulse `us` long.
+    SetTriggerPulse { us: u32 },
+    /// Ignore the condition for `ms` after each pulse.
+    SetTriggerHoldoff { ms: u32 },
+    /// Print the sampling sync role, lock and sample index.
+    Sync,
+    SetSync(Role),
+    /// Mark a new epoch, restarting the sample index, as leader.
+    RestartSync,
+    /// Print the config as `key=value` lines.
+    Config,
+    /// Change the config, answering with an acknowledgement either way.
+    SetConfig(Result<Update, Rejection>),
+    /// List the saved profiles, marking the active one.
+    Profiles,
+    /// Save the config in use as this profile, and make it the active one.
+    SaveProfile(ProfileName),
+    UseProfile(ProfileName),
+    RemoveProfile(ProfileName),
+    /// Print the unit readings are shown in.
+    Units,
+    SetUnits(Unit),
+    /// List the automation rules, marking those met.
+    Rules,
+    /// Add a rule, numbered by the first free place.
+    AddRule(Rule),
+    /// Remove the rule of this number, from 1.
+    RemoveRule { number: usize },
+    /// List the scheduled actions.
+    Schedule,
+    /// Add an entry, numbered by the first free place.
+    AddSchedule(Entry),
+    /// Remove the entry of this number, from 1.
+    RemoveSchedule { number: usize },
+    /// Print the night mode window and whether it holds now.
+    Night,
+    SetNight(NightSettings),
+    NightOff,
+    /// Print the light level and the brightness range it sets.
+    Ambient,
+    SetAmbient(AmbientSettings),
+    AmbientOff,
+    Palette,
+    SetPalette(Palette),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct MockWaveform {
+    pub shape: Shape,
+    pub period_ms: Option<u32>,
+    pub amplitude_mv: Option<u32>,
+}
+
+impl MockWaveform {
+    /// `current` with the given settings changed.
+    pub fn apply(&self, current: Waveform) -> Waveform {
+        Waveform {
+            shape: self.shape,
+            period_ms: self.period_ms.unwrap_or(current.period_ms),
+            amplitude_mv: self.amplitude_mv.unwrap_or(current.amplitude_mv),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    BadArgument,
+}
+
+/// An optional numeric argument.
+fn number(word: Option<&str>) -> Result<Option<u32>, ParseError> {
+    word.map(|w| w.parse::<u32>().map_err(|_| ParseError::BadArgument))
+        .transpose()
+}
+
+fn decimal(word: Option<&str>) -> Result<Option<f32>, ParseError> {
+    word.map(|w| w.parse::<f32>().map_err(|_| ParseError::BadArgument))
+        .transpose()
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut words = line.split_ascii_whitespace();
+        let name = words.next().ok_or(ParseError::Empty)?;
+        let mut arg = || number(words.next());
+
+        match name {
+            "ambient" => match words.next() {
+                None => Ok(Command::Ambient),
+                Some("off") => Ok(Command::AmbientOff),
+                min_pct => {
+                    let min_pct = number(min_pct)?;
+                    let max_pct = number(words.next())?;
+                    let smoothing_s = match number(words.next())? {
+                        None => Some(AmbientSettings::DEFAULT.smoothing_s),
+                        Some(smoothing_s) => u16::try_from(smoothing_s).ok(),
+                    };
+                    match (min_pct, max_pct, smoothing_s) {
+                        (Some(min_pct), Some(max_pct), Some(smoothing_s))
+                            if min_pct <= max_pct && max_pct <= 100 =>
+                        {
+                            Ok(Command::SetAmbient(AmbientSettings {
+                                enabled: true,
+                                min_pct: min_pct as u8,
+                                max_pct: max_pct as u8,
+                                smoothing_s,
+                            }))
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    }
+                }
+            },
+            "autozero" => match words.next() {
+                None => Ok(Command::AutoZero),
+                Some("off") => Ok(Command::SetAutoZero(None)),
+                time_constant_s => match number(time_constant_s)? {
+                    Some(0) | None => Err(ParseError::BadArgument),
+                    time_constant_s => Ok(Command::SetAutoZero(time_constant_s)),
+                },
+            },
+            "battery" => Ok(Command::Battery),
+            "burst" => match words.next() {
+                None => Ok(Command::Burst),
+                Some("run") => Ok(Command::RunBurst {
+                    rate_hz: number(words.next())?.unwrap_or(10_000),
+                    duration_ms: number(words.next())?.unwrap_or(500),
+                }),
+                Some("dump") => Ok(Command::DumpBurst {
+                    decimate: number(words.next())?.unwrap_or(1),
+                }),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "capture" => match words.next() {
+                None => Ok(Command::Capture),
+                Some("arm") => {
+                    let level_mv = number(words.next())?.ok_or(ParseError::BadArgument)?;
+                    let edge = match words.next() {
+                        None => Edge::Either,
+                        Some(name) => Edge::parse(name).ok_or(ParseError::BadArgument)?,
+                    };
+                    Ok(Command::ArmCapture {
+                        level_mv,
+                        edge,
+                        pre: number(words.next())?,
+                    })
+                }
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "contact" => match words.next() {
+                None => Ok(Command::Contact),
+                Some("closed") => {
+                    let closed_mt = decimal(words.next())?;
+                    let open_mt = match words.next() {
+                        Some("open") => decimal(words.next())?,
+                        _ => None,
+                    };
+                    match (closed_mt, open_mt) {
+                        (Some(closed_mt), Some(open_mt))
+                            if 0.0 <= open_mt && open_mt < closed_mt =>
+                        {
+                            Ok(Command::SetContactThresholds { closed_mt, open_mt })
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    }
+                }
+                Some("debounce") => match number(words.next())? {
+                    Some(ms) => Ok(Command::SetContactDebounce { ms }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "count" => match words.next() {
+                None => Ok(Command::Count),
+                Some("reset") => Ok(Command::ResetCount),
+                Some("dead") => match number(words.next())? {
+                    Some(ms) => Ok(Command::SetCountDeadTime { ms }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some("batch") => match words.next() {
+                    Some("off") => Ok(Command::SetBatch { size: 0 }),
+                    size => match number(size)? {
+                        Some(size @ 1..) => Ok(Command::SetBatch { size }),
+                        _ => Err(ParseError::BadArgument),
+                    },
+                },
+                Some("pulse") => match number(words.next())? {
+                    Some(ms @ 1..) => Ok(Command::SetBatchPulse { ms }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "current" => match words.next() {
+                None => Ok(Command::Current),
+                Some("zero") => Ok(Command::ZeroCurrent),
+                Some("cal") => match decimal(words.next())? {
+                    Some(amps) if amps != 0.0 => Ok(Command::CalibrateCurrent { amps }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("profile") => match words.next() {
+                    None => Ok(Command::CurrentProfiles),
+                    Some(name) => Ok(Command::SetCurrentProfile {
+                        profile: Profile::parse(name).ok_or(ParseError::BadArgument)?,
+                        supply_mv: number(words.next())?.unwrap_or(5000),
+                    }),
+                },
+                Some("loop") => match words.next() {
+                    Some("off") => Ok(Command::SetCurrentLoop(None)),
+                    full_scale_a => match decimal(full_scale_a)? {
+                        Some(full_scale_a) if full_scale_a > 0.0 => {
+                            Ok(Command::SetCurrentLoop(Some(full_scale_a)))
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    },
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "dump" => Ok(Command::Dump {
+                start: arg()?.unwrap_or(0),
+                count: arg()?,
+            }),
+            "diag" => Ok(Command::Diag),
+            "drift" => Ok(Command::Drift),
+            "filter" => {
+                let Some(index) = number(words.next())? else {
+                    return Ok(Command::Filters);
+                };
+                let stage = match words.next() {
+                    Some(switch @ ("on" | "off")) => {
+                        return Ok(Command::EnableFilter {
+                            index,
+                            enabled: switch == "on",
+                        });
+                    }
+                    Some("remove") => return Ok(Command::RemoveFilter { index }),
+                    Some("median") => {
+                        let window = number(words.next())?.unwrap_or(5);
+                        if window % 2 == 0 || window > MAX_MEDIAN_WINDOW as u32 {
+                            return Err(ParseError::BadArgument);
+                        }
+                        Stage::Median {
+                            window: window as u8,
+                        }
+                    }
+                    Some("notch") => {
+                        let frequency_hz = number(words.next())?.ok_or(ParseError::BadArgument)?;
+                        let harmonics = match words.next() {
+                            None => false,
+                            Some("harmonics") => true,
+                            Some(_) => return Err(ParseError::BadArgument),
+                        };
+                        if frequency_hz != 50 && frequency_hz != 60 {
+                            return Err(ParseError::BadArgument);
+                        }
+                        Stage::Notch(Mains {
+                            frequency_hz,
+                            harmonics,
+                        })
+                    }
+                    Some("ema") => {
+                        let weight_percent = number(words.next())?.unwrap_or(20);
+                        if !(1..=100).contains(&weight_percent) {
+                            return Err(ParseError::BadArgument);
+                        }
+                        Stage::Average {
+                            weight_percent: weight_percent as u8,
+                        }
+                    }
+                    _ => return Err(ParseError::BadArgument),
+                };
+                Ok(Command::SetFilter { index, stage })
+            }
+            "fixture" => match words.next() {
+                None => Ok(Command::Fixture),
+                Some("run") => Ok(Command::RunFixture {
+                    unit: number(words.next())?,
+                }),
+                Some("scan") => match number(words.next())? {
+                    Some(positions @ 1..) if positions <= MAX_POSITIONS as u32 => {
+                        Ok(Command::ScanFixture {
+                            positions: positions as u8,
+                            unit: number(words.next())?,
+                        })
+                    }
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("limits") => {
+                    match (
+                        decimal(words.next())?,
+                        decimal(words.next())?,
+                        decimal(words.next())?,
+                    ) {
+                        (Some(low_mt), Some(high_mt), spread_mt)
+                            if low_mt < high_mt && spread_mt.is_none_or(|s| s > 0.0) =>
+                        {
+                            Ok(Command::SetFixtureLimits {
+                                low_mt,
+                                high_mt,
+                                spread_mt,
+                            })
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    }
+                }
+                Some("time") => match number(words.next())? {
+                    Some(ms @ 1..) => Ok(Command::SetFixtureTime { ms }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "gauge" => match words.next() {
+                None => Ok(Command::Gauge),
+                Some("range") => match (decimal(words.next())?, decimal(words.next())?) {
+                    (Some(low_mt), Some(high_mt)) if low_mt != high_mt => {
+                        Ok(Command::SetGaugeRange { low_mt, high_mt })
+                    }
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("rpm") => match number(words.next())? {
+                    Some(full_rpm) if full_rpm > 0 => Ok(Command::SetGaugeRpm { full_rpm }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "grade" => match words.next() {
+                None => Ok(Command::Grade),
+                Some("reset") => Ok(Command::ResetGrade),
+                Some("shots") => match number(words.next())? {
+                    Some(shots @ 1..) if shots <= MAX_SHOTS as u32 => {
+                        Ok(Command::SetGradeShots { shots: shots as u8 })
+                    }
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("distance") => match decimal(words.next())? {
+                    Some(mm) if mm >= 0.0 => Ok(Command::SetGradeDistance { mm }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("bounds") => Grades::parse(words.by_ref())
+                    .map(Command::SetGrades)
+                    .ok_or(ParseError::BadArgument),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "haptic" => match words.next() {
+                None => Ok(Command::Haptic),
+                Some(name) => Trigger::parse(name)
+                    .map(Command::SetHaptic)
+                    .ok_or(ParseError::BadArgument),
+            },
+            "help" | "?" => Ok(Command::Help),
+            "hist" => match arg()? {
+                None => Ok(Command::Histogram),
+                Some(bin_width_mv) => Ok(Command::SetHistogram {
+                    bin_width_mv,
+                    window: arg()?,
+                }),
+            },
+            "last-panic" => Ok(Command::LastPanic),
+            "latency" => match words.next() {
+                None => Ok(Command::Latency),
+                Some("reset") => Ok(Command::ResetLatency),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "levitate" => match words.next() {
+                None => Ok(Command::Levitate),
+                Some("pid") => {
+                    let mut arg = || decimal(words.next())?.ok_or(ParseError::BadArgument);
+                    Ok(Command::SetLevitateGains(Gains {
+                        kp: arg()?,
+                        ki: arg()?,
+                        kd: arg()?,
+                    }))
+                }
+                setpoint => Ok(Command::SetLevitate {
+                    setpoint_mt: decimal(setpoint)?.ok_or(ParseError::BadArgument)?,
+                }),
+            },
+            "lockin" => match number(words.next())? {
+                None => Ok(Command::LockIn),
+                Some(frequency_hz) => Ok(Command::SetLockIn {
+                    frequency_hz,
+                    time_constant_ms: number(words.next())?.unwrap_or(1000),
+                }),
+            },
+            "log" => match (words.next(), words.next()) {
+                (None, _) => Ok(Command::Log),
+                (Some(level), None) => Ok(Command::SetLog {
+                    module: None,
+                    level: Level::parse(level).ok_or(ParseError::BadArgument)?,
+                }),
+                (Some(module), Some(level)) => Ok(Command::SetLog {
+                    module: Some(Module::parse(module).ok_or(ParseError::BadArgument)?),
+                    level: Level::parse(level).ok_or(ParseError::BadArgument)?,
+                }),
+            },
+            "mem" => Ok(Command::Memory),
+            "mock" => match words.next() {
+                None => Ok(Command::Mock(None)),
+                Some(name) => {
+                    let shape = Shape::parse(name).ok_or(ParseError::BadArgument)?;
+                    Ok(Command::Mock(Some(MockWaveform {
+                        shape,
+                        period_ms: number(words.next())?,
+                        amplitude_mv: number(words.next())?,
+                    })))
+                }
+            },
+            "mode" => match words.next() {
+                None => Ok(Command::Mode(None)),
+                Some(name) => Mode::parse(name)
+                    .map(|mode| Command::Mode(Some(mode)))
+                    .ok_or(ParseError::BadArgument),
+            },
+            "motor" => match words.next() {
+                None => Ok(Command::Motor),
+                Some("off") => Ok(Command::SetMotor {
+                    rpm: 0,
+                    reverse: false,
+                }),
+                Some("pid") => {
+                    let mut arg = || decimal(words.next())?.ok_or(ParseError::BadArgument);
+                    Ok(Command::SetMotorGains(Gains {
+                        kp: arg()?,
+                        ki: arg()?,
+                        kd: arg()?,
+                    }))
+                }
+                rpm => Ok(Command::SetMotor {
+                    rpm: number(rpm)?.ok_or(ParseError::BadArgument)?,
+                    reverse: match words.next() {
+                        None => false,
+                        Some("reverse") => true,
+                        Some(_) => return Err(ParseError::BadArgument),
+                    },
+                }),
+            },
+            "mux" => match number(words.next())? {
+                None => Ok(Command::Mux(None)),
+                Some(channel @ 0..16) => Ok(Command::Mux(Some(channel as u8))),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "night" => match words.next() {
+                None => Ok(Command::Night),
+                Some("off") => Ok(Command::NightOff),
+                start => {
+                    let start = start.and_then(TimeOfDay::parse);
+                    let end = words.next().and_then(TimeOfDay::parse);
+                    let brightness_pct = match words.next() {
+                        None | Some("off") => Some(0),
+                        pct => number(pct)?.filter(|&pct| pct <= 100),
+                    };
+                    match (start, end, brightness_pct) {
+                        (Some(start), Some(end), Some(brightness_pct)) if start != end => {
+                            Ok(Command::SetNight(NightSettings {
+                                enabled: true,
+                                start,
+                                end,
+                                brightness_pct: brightness_pct as u8,
+                            }))
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    }
+                }
+            },
+            "noise" => Ok(Command::Noise {
+                duration_s: arg()?.unwrap_or(10),
+            }),
+            "odometer" => match (words.next(), words.next()) {
+                (None, _) => Ok(Command::Odometer),
+                (Some("reset"), confirm) => Ok(Command::ResetOdometer {
+                    confirmed: confirm == Some("confirm"),
+                }),
+                _ => Err(ParseError::BadArgument),
+            },
+            "palette" => match words.next() {
+                None => Ok(Command::Palette),
+                Some(name) => Ok(Command::SetPalette(
+                    Palette::parse(name).ok_or(ParseError::BadArgument)?,
+                )),
+            },
+            "pas" => match words.next() {
+                None => Ok(Command::Pas),
+                Some("magnets") => match number(words.next())?.map(u8::try_from) {
+                    Some(Ok(magnets)) if magnets > 0 => Ok(Command::SetPasMagnets { magnets }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("curve") => Curve::parse(words.by_ref())
+                    .map(Command::SetPasCurve)
+                    .ok_or(ParseError::BadArgument),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "power" => match words.next() {
+                None => Ok(Command::Power),
+                Some("cal") => match decimal(words.next())? {
+                    Some(volts) if volts > 0.0 => Ok(Command::CalibrateVoltage { volts }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("reset") => Ok(Command::ResetEnergy {
+                    confirmed: words.next() == Some("confirm"),
+                }),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "quiet" => match words.next() {
+                None | Some("on") => Ok(Command::Quiet(true)),
+                Some("off") => Ok(Command::Quiet(false)),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "reading" => Ok(Command::Reading),
+            "relay" => match words.next() {
+                None => Ok(Command::Relay),
+                Some("on") => {
+                    let pull_in_mt = decimal(words.next())?;
+                    let drop_out_mt = match words.next() {
+                        Some("off") => decimal(words.next())?,
+                        _ => None,
+                    };
+                    match (pull_in_mt, drop_out_mt) {
+                        (Some(pull_in_mt), Some(drop_out_mt))
+                            if 0.0 <= drop_out_mt && drop_out_mt <= pull_in_mt =>
+                        {
+                            Ok(Command::SetRelayThresholds {
+                                pull_in_mt,
+                                drop_out_mt,
+                            })
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    }
+                }
+                Some("hold") => match number(words.next())? {
+                    Some(ms) => Ok(Command::SetRelayMinOn { ms }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some("failsafe") => words
+                    .next()
+                    .and_then(FailSafe::parse)
+                    .map(Command::SetRelayFailSafe)
+                    .ok_or(ParseError::BadArgument),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "replay" => match words.next() {
+                Some("stop") => Ok(Command::StopReplay),
+                start => Ok(Command::Replay {
+                    start: number(start)?.unwrap_or(0),
+                    count: number(words.next())?,
+                }),
+            },
+            "rule" => match words.next() {
+                None => Ok(Command::Rules),
+                Some("add") => {
+                    let comparison = words
+                        .next()
+                        .and_then(Comparison::parse)
+                        .ok_or(ParseError::BadArgument)?;
+                    let level_mt = decimal(words.next())?.ok_or(ParseError::BadArgument)?;
+                    let mut word = words.next();
+                    let mut for_ms = 0;
+                    if word == Some("for") {
+                        for_ms = number(words.next())?.ok_or(ParseError::BadArgument)?;
+                        word = words.next();
+                    }
+                    let action = match word {
+                        Some("output") => Action::Output,
+                        Some("publish") => Action::Publish,
+                        Some("flash") => Action::Flash(
+                            words
+                                .next()
+                                .and_then(Color::parse)
+                                .ok_or(ParseError::BadArgument)?,
+                        ),
+                        _ => return Err(ParseError::BadArgument),
+                    };
+                    Ok(Command::AddRule(Rule {
+                        condition: rules::Condition {
+                            comparison,
+                            level_mt,
+                            for_ms,
+                        },
+                        action,
+                    }))
+                }
+                Some("remove") => match number(words.next())? {
+                    Some(number) => Ok(Command::RemoveRule {
+                        number: number as usize,
+                    }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "schedule" => match words.next() {
+                None => Ok(Command::Schedule),
+                Some("add") => {
+                    let mut fields = [""; 5];
+                    for field in &mut fields {
+                        *field = words.next().ok_or(ParseError::BadArgument)?;
+                    }
+                    Ok(Command::AddSchedule(Entry {
+                        when: When::parse(fields).ok_or(ParseError::BadArgument)?,
+                        task: words
+                            .next()
+                            .and_then(Task::parse)
+                            .ok_or(ParseError::BadArgument)?,
+                    }))
+                }
+                Some("remove") => match number(words.next())? {
+                    Some(number) => Ok(Command::RemoveSchedule {
+                        number: number as usize,
+                    }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "servo" => match words.next() {
+                None => Ok(Command::Servo),
+                Some("range") => match (decimal(words.next())?, decimal(words.next())?) {
+                    (Some(low_mt), Some(high_mt)) if low_mt != high_mt => {
+                        Ok(Command::SetServoRange { low_mt, high_mt })
+                    }
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("pulse") => match (number(words.next())?, number(words.next())?) {
+                    (Some(min_us), Some(max_us)) if min_us < max_us && max_us <= 3000 => {
+                        Ok(Command::SetServoPulses {
+                            min_us: min_us as u16,
+                            max_us: max_us as u16,
+                        })
+                    }
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("slew") => match words.next() {
+                    Some("off") => Ok(Command::SetServoSlew { deg_per_s: 0.0 }),
+                    word => match decimal(word)? {
+                        Some(deg_per_s) if deg_per_s > 0.0 => {
+                            Ok(Command::SetServoSlew { deg_per_s })
+                        }
+                        _ => Err(ParseError::BadArgument),
+                    },
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "sleep" => Ok(Command::Sleep),
+            "spectrum" => match words.next() {
+                None => Ok(Command::Spectrum),
+                Some("burst") => Ok(Command::BurstSpectrum {
+                    points: number(words.next())?.unwrap_or(1024),
+                }),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "speed" => match words.next() {
+                None => Ok(Command::Speed),
+                Some("off") => Ok(Command::SetSpeed { rpm: 0 }),
+                Some("pid") => {
+                    let mut arg = || decimal(words.next())?.ok_or(ParseError::BadArgument);
+                    Ok(Command::SetSpeedGains(Gains {
+                        kp: arg()?,
+                        ki: arg()?,
+                        kd: arg()?,
+                    }))
+                }
+                rpm => Ok(Command::SetSpeed {
+                    rpm: number(rpm)?.ok_or(ParseError::BadArgument)?,
+                }),
+            },
+            "stats" => Ok(Command::Stats),
+            "tamper" => match words.next() {
+                None => Ok(Command::Tamper),
+                Some("learn") => Ok(Command::LearnTamper),
+                Some("clear") => Ok(Command::ClearTamper),
+                Some("tolerance") => match number(words.next())? {
+                    Some(pct @ 1..=100) => Ok(Command::SetTamperTolerance { pct: pct as u8 }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("hold") => match number(words.next())? {
+                    Some(ms) => Ok(Command::SetTamperHold { ms }),
+                    None => Err(ParseError::BadArgument),
+                },
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "tare" => match words.next() {
+                None => Ok(Command::Tare),
+                Some("now") => Ok(Command::SetTare),
+                Some("clear") => Ok(Command::ClearTare),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "time" => match words.next() {
+                None => Ok(Command::Time),
+                Some("set") => {
+                    // Accept both "2025-01-31T12:00:00" and "2025-01-31 12:00:00"
+                    let mut s: String<19> = String::new();
+                    let _ = s.push_str(words.next().ok_or(ParseError::BadArgument)?);
+                    if let Some(time) = words.next() {
+                        let _ = s.push(' ');
+                        let _ = s.push_str(time);
+                    }
+                    DateTime::parse(&s)
+                        .map(Command::SetTime)
+                        .ok_or(ParseError::BadArgument)
+                }
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "tone" => match words.next() {
+                None => Ok(Command::Tone),
+                Some("off") => Ok(Command::StopTone),
+                Some("burst") => Ok(Command::BurstTone {
+                    frequency_hz: number(words.next())?.ok_or(ParseError::BadArgument)?,
+                }),
+                frequency => Ok(Command::SetTone {
+                    frequency_hz: number(frequency)?.ok_or(ParseError::BadArgument)?,
+                    points: number(words.next())?.unwrap_or(256),
+                }),
+            },
+            "trigger" => match words.next() {
+                None => Ok(Command::Trigger),
+                Some("off") => Ok(Command::SetTrigger(Condition::Off)),
+                Some("peak") => {
+                    let level_mv = number(words.next())?.ok_or(ParseError::BadArgument)?;
+                    let rising = match words.next() {
+                        None | Some("rising") => true,
+                        Some("falling") => false,
+                        Some(_) => return Err(ParseError::BadArgument),
+                    };
+                    Ok(Command::SetTrigger(Condition::Peak { level_mv, rising }))
+                }
+                Some("pulse") => match number(words.next())? {
+                    Some(us @ 1..=MAX_PULSE_US) => Ok(Command::SetTriggerPulse { us }),
+                    _ => Err(ParseError::BadArgument),
+                },
+                Some("holdoff") => Ok(Command::SetTriggerHoldoff {
+                    ms: number(words.next())?.ok_or(ParseError::BadArgument)?,
+                }),
+                level => {
+                    let level_mv = number(level)?.ok_or(ParseError::BadArgument)?;
+                    let edge = match words.next() {
+                        None => Edge::Either,
+                        Some(name) => Edge::parse(name).ok_or(ParseError::BadArgument)?,
+                    };
+                    Ok(Command::SetTrigger(Condition::Crossing { level_mv, edge }))
+                }
+            },
+            "sync" => match words.next() {
+                None => Ok(Command::Sync),
+                Some("epoch") => Ok(Command::RestartSync),
+                Some(name) => Ok(Command::SetSync(
+                    Role::parse(name).ok_or(ParseError::BadArgument)?,
+                )),
+            },
+            "config" => match words.next() {
+                None => Ok(Command::Config),
+                Some("set") => Ok(Command::SetConfig(Update::from_pairs(words))),
+                Some(_) => Err(ParseError::BadArgument),
+            },
+            "profile" => {
+                let command = match words.next() {
+                    None => return Ok(Command::Profiles),
+                    Some("save") => Command::SaveProfile,
+                    Some("use") => Command::UseProfile,
+                    Some("remove") => Command::RemoveProfile,
+                    Some(_) => return Err(ParseError::BadArgument),
+                };
+                let name = words
+                    .next()
+                    .and_then(ProfileName::parse)
+                    .ok_or(ParseError::BadArgument)?;
+                Ok(command(name))
+            }
+            "units" => match words.next() {
+                None => Ok(Command::Units),
+                Some(name) => Ok(Command::SetUnits(
+                    Unit::parse(name).ok_or(ParseError::BadArgument)?,
+                )),
+            },
+            _ => Err(ParseError::UnknownCommand),
+        }
+    }
+}
+
+pub const HELP: &str = "\
+ambient                   show the light level and the range of brightness
+                          it sets the LED to
+ambient <min> <max> [s]   scale the LED's brightness with the light, from
+                          min% in the dark to max% in full light, smoothed
+                          over s seconds (default 5); kept across resets
+ambient off               leave the LED's brightness as it is set
+autozero                  show the auto-zero setting and the zero
+autozero <seconds>|off    follow thermal drift of the zero with this time
+                          constant (e.g. 600) while the field is quiet
+                          and within 20mV of it, or stop
+battery                   show the battery voltage and charge
+burst                     summarise the last burst
+burst run [Hz] [ms]       sample at 1 to 20kHz (default 10kHz) for up to
+                          2s (default 500ms), blocking everything else
+burst dump [factor]       stream the last burst as CSV, averaging each
+                          `factor` readings into one
+capture                   show the triggered capture, as CSV once complete
+capture arm <mV> [edge] [pre]
+                          capture around the next crossing of a level:
+                          rising, falling or either (the default) edge,
+                          keeping `pre` samples from before it
+config                    show the settings as key=value lines
+config set <key>=<value>...
+                          change settings as a whole, answering in JSON
+                          whether they were taken and if a restart is
+                          needed; kept across resets
+contact                   show whether the contact is open or closed, in
+                          contact mode (mode contact), its latest changes
+                          and the settings
+contact closed <mT> open <mT>
+                          read it as closed at or above this field
+                          strength, either pole, and open at or below the
+                          second; kept across resets
+contact debounce <ms>     count a change only once it has held this long
+                          (default 200); kept across resets
+count                     show the parts counted on rising crossings in
+                          count mode (mode count), the batches and the
+                          settings
+count dead <ms>           count a crossing only this long after the last
+                          one counted (default 50); kept across resets, as
+                          are the next two
+count batch <n>|off       gather the parts into batches of this many
+count pulse <ms>          pulse the batch output this long at the end of
+                          each batch (default 100)
+count reset               start the counts over
+current                   show the current, its scale and the closed
+                          loop, in current mode (mode current)
+current zero              take the output now, with no current flowing,
+                          as the zero; kept across resets
+current cal <A>           set the sensitivity from this known current,
+                          flowing now (negative if it runs the other
+                          way); kept across resets
+current profile [name] [mV]
+                          list the built-in sensor profiles, or take the
+                          scale from one, for the module run from this
+                          supply (default 5000mV)
+current loop <A>|off      null the core's flux with the compensation
+                          winding, which balances this much current at
+                          either end of its drive, or stop
+dump [start] [count]      stream the flash log as CSV
+diag                      print a JSON diagnostics report
+drift                     show the Allan deviation of the field by
+                          averaging time, from drift mode
+filter                    show the filter chain between the sensor and
+                          processing, which is kept across resets
+filter <n> median [samples]
+                          make stage n (from 1) a median of 3 to 9
+                          readings (default 5), for spikes
+filter <n> notch <50|60> [harmonics]
+                          make it a mains notch, with the harmonics up to
+                          the fifth, at wherever they alias to
+filter <n> ema [percent]  make it a moving average giving each reading
+                          this weight (default 20)
+filter <n> on|off         switch stage n on or off
+filter <n> remove         take stage n out of the chain
+fixture                   show the production test's limits and the last
+                          verdict, which the LED shows in fixture mode
+                          (mode fixture): green to pass, red to fail
+fixture limits <mT> <mT> [spread]
+                          pass a mean field between these, signed by the
+                          pole, spreading no more than this (default 1mT);
+                          kept across resets, as is the next
+fixture time <ms>         measure for this long in each test (default 500)
+fixture run [unit]        test a unit, switching to fixture mode, and
+                          print a JSON record of it, numbered this or
+                          from 1 since boot
+fixture scan <n> [unit]   test a magnet array on mux channels 0 to n-1
+                          in one pass, and print a table and one JSON
+                          record of it; the array passes if all do
+gauge                     show the analog gauge's scale and needle angle
+gauge range <mT> <mT>     turn the needle across this range of the field,
+                          the other way round if the first is higher;
+                          kept across resets
+gauge rpm <rpm>           set the speed at the end of the sweep, shown in
+                          tachometer mode (default 6000); kept too
+grade                     show the placements of the magnet being graded
+                          in grade mode (mode grade), the last magnet's
+                          grade, peak, repeatability and temperature, and
+                          the settings
+grade shots <n>           grade each magnet over this many placements, 1
+                          to 16 (default 5); kept across resets, as are
+                          the next two
+grade distance <mm>       note the fixture's distance from magnet to
+                          sensor with the results (default 5mm)
+grade bounds <mT>...      the lowest mean peak for grades A, B and so on,
+                          up to 4, falling (default 40 30 20)
+grade reset               start the magnet being graded over
+haptic                    show what the haptics buzz on
+haptic pole|threshold|off buzz on each pole found, two clicks for north
+                          and a long buzz for south (the default), or on
+                          rising threshold crossings, or never
+help                      show this text
+hist                      show a histogram of recent voltages, and the
+                          share at or above the threshold
+hist <mV> [samples]       restart it with bins this wide (at least 52mV)
+                          over this many samples (up to 2048)
+last-panic                show and clear the message of the last panic
+latency                   show the time from sample to LED, as measured
+                          in latency mode (mode latency): percentiles
+                          of every reading shown since entering it
+latency reset             start the measurement over
+levitate                  show the setpoint, PID gains, field and duty, in
+                          levitate mode (mode levitate)
+levitate <mT>             hold the field at this, e.g. 10.5
+levitate pid <kp> <ki> <kd>
+                          set the gains live, in duty (0 to 1) per mT,
+                          per mT.s and per mT/s; negative for a magnet
+                          the other way up
+lockin                    show the lock-in frequency and response, in
+                          lock-in mode (mode lockin)
+lockin <Hz> [ms]          drive the coil at this frequency (default 10Hz)
+                          and average over this time constant (default
+                          1000ms, at least a period)
+log                       show the log level of each module
+log [module] <level>      set a log level: off, error, warn or info
+mem                       show stack high-water mark and free heap
+mock [shape] [ms] [mV]    show or set the mock sensor's waveform: sine,
+                          step or noise, its period and amplitude
+mode [name]               show or set the mode: measure, calibrate,
+                          tachometer, diagnostics, spectrum or drift
+motor                     show the motor's setpoint, speed and duty
+motor <rpm> [reverse]|off run the brushless motor at this speed, or stop
+motor pid <kp> <ki> <kd>  set the speed loop's gains, in duty (0 to 1)
+                          per rpm, per rpm.s and per rpm/s
+mux [channel]             show or select the mux channel, 0 to 15, that
+                          the sensor is read through
+night                     show the night mode window, when the LED is
+                          dimmed or off, and whether it is night now
+night <start> <end> [%|off]
+                          dim the LED to this brightness (default off)
+                          from start to end each day, e.g. night 22:00
+                          07:00, by the wall clock (UTC); the MAX7219 and
+                          TM1637 go to their dimmest; kept across resets
+night off                 leave the LED as it is all day
+noise [seconds]           sample for 10s (or up to 60s) with no magnet
+                          near, and show the noise and effective bits
+odometer                  show the lifetime pulse count
+odometer reset confirm    clear the lifetime pulse count
+palette [name]            show or set the LED's colours: red-blue (the
+                          default), orange-blue, brightness (dark for
+                          north to full for south) or blink (blinking
+                          for north, steady for south, brighter the
+                          stronger the field); kept across resets
+pas                       show the cadence and assist, in pedal-assist
+                          mode (mode pas), and the settings
+pas magnets <n>           set the magnets around the crank's ring
+pas curve <rpm:%>...      set the assist at up to 4 cadences, rising, e.g.
+                          pas curve 20:30 60:70 90:100; kept across resets
+power                     show the voltage, power and energy total, in
+                          power mode (mode power)
+power cal <V>             set the voltage divider's ratio from this
+                          voltage, present now; kept across resets
+power reset confirm       clear the energy total
+profile                   list the saved profiles, * marking the active one
+profile save <name>       save the config in use, calibration included, as
+                          this profile (up to 12 letters, digits, - or _;
+                          4 profiles) and make it the active one
+profile use <name>        switch to this profile, as a double press of the
+                          button steps to the next
+profile remove <name>     delete this profile
+quiet [on|off]            stop or resume the per-sample log line
+reading                   show the latest reading, in the units set
+relay                     show the relay's thresholds, hold time,
+                          fail-safe state and whether it is in
+relay on <mT> off <mT>    pull the relay in at this field strength, either
+                          pole, and drop it out at or below the second;
+                          kept across resets, as are the next two
+relay hold <ms>           keep it in for at least this long (default 250)
+relay failsafe <state>    on, off or hold: what it does on a wiring
+                          fault, a low supply or no readings (default off)
+replay [start] [count]    feed flash log records to the processing in
+                          place of the sensor, at their recorded pace
+replay stop               return to the sensor
+rule                      list the automation rules, * marking those met
+rule add above|below <mT> [for <ms>] <action>
+                          when the field (signed by its pole) stays above
+                          or below this for this long: output (GPIO5 high
+                          while met, with the rule-output feature),
+                          publish (an event to telemetry) or flash
+                          <colour> (red, green, blue, yellow, cyan,
+                          magenta or white); up to 4 rules, kept across
+                          resets
+rule remove <n>           remove rule n; the others keep their numbers
+schedule                  list the scheduled actions
+schedule add <min> <hour> <day> <month> <weekday> <action>
+                          run an action when the wall clock (UTC)
+                          matches, each field * for any, a number or */n
+                          for every n-th, weekdays from 0 for Sunday, e.g.
+                          schedule add 0 3 * * * flush-log; the action is
+                          reset-range (the displays' min and max),
+                          flush-log (the flash log's samples held in RAM)
+                          or self-test (the sensor's, on live samples);
+                          up to 4, kept across resets
+schedule remove <n>       remove entry n; the others keep their numbers
+servo                     show the servo gauge's range, pulses, slew
+                          rate and angle
+servo range <mT> <mT>     turn the needle across this range of the field,
+                          the other way round if the first is higher;
+                          kept across resets, as are the next two
+servo pulse <us> <us>     set the pulse widths at either end of the
+                          travel (default 1000 and 2000, up to 3000)
+servo slew <deg/s>|off    limit how fast the needle moves (default 180)
+sleep                     sample once a minute in deep sleep until BOOT
+                          is pressed
+spectrum                  show the strongest frequencies found in
+                          spectrum mode
+spectrum burst [points]   find them in the last burst instead, from its
+                          first 256, 512 or 1024 (the default) readings
+speed                     show the motor's setpoint, speed and duty, in
+                          tachometer mode (mode tachometer)
+speed <rpm>|off           hold the brushed motor at this speed, or stop
+speed pid <kp> <ki> <kd>  set the speed loop's gains, in duty (0 to 1)
+                          per rpm, per rpm.s and per rpm/s
+stats                     show boot count, uptime and last reset reason
+sync                      show this board's part in sampling in step with
+                          others over GPIO1, and the sample index
+sync leader|follower|off  pace the other boards' samples, sample on the
+                          leader's pulses, or neither; kept across resets
+sync epoch                restart the shared sample index, as leader
+tamper                    show the tamper alarm, latched in tamper mode
+                          (mode tamper) until cleared, and the settings
+tamper learn              take the field now, averaged over a second, as
+                          the bias magnet's; kept across resets, as are
+                          the next two
+tamper tolerance <%>      how far the field may stray from it before an
+                          alarm, 1 to 100 (default 50)
+tamper hold <ms>          raise one only once it has held this long
+                          (default 100)
+tamper clear              clear the alarm, which survives resets
+tare                      show the tare taken off readings
+tare now|clear            take the field now, averaged over a second, as
+                          the zero of readings (e.g. the Earth's field),
+                          or go back to the calibrated zero
+time                      print the wall-clock time (UTC)
+time set <date> <time>    set the clock, e.g. time set 2025-01-31 12:00:00
+tone                      show the last amplitude and phase measured
+tone <Hz> [samples]       measure one frequency, below half the sample
+                          rate, in blocks of this many samples (default
+                          256, up to 4096), best a whole number of cycles
+tone off                  stop measuring it
+tone burst <Hz>           measure it over the last burst instead
+trigger                   show the trigger output's condition and pulse,
+                          and how long after each event it pulses
+trigger <mV> [edge]       pulse as the sensor crosses this level: rising,
+                          falling or either (the default) edge
+trigger peak <mV> [rising|falling]
+                          pulse at the top of each peak above this level,
+                          or the bottom of each dip below it if falling
+trigger off               stop pulsing; kept across resets, as are these
+trigger pulse <us>        pulse for this long, up to 1000us (default 10)
+trigger holdoff <ms>      ignore the condition this long after a pulse
+                          (default 10)
+units [unit]              show or set the unit readings are shown in:
+                          counts, mv, gauss or mt (the default); kept
+                          across resets
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_in_the_help_is_known() {
+        for line in HELP.lines().filter(|line| !line.starts_with(' ')) {
+            let name = line.split_ascii_whitespace().next().unwrap();
+            assert_ne!(
+                Command::parse(name),
+                Err(ParseError::UnknownCommand),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_nothing_and_nonsense() {
+        assert_eq!(Command::parse("  "), Err(ParseError::Empty));
+        assert_eq!(
+            Command::parse("frobnicate"),
+            Err(ParseError::UnknownCommand)
+        );
+        assert_eq!(
+            Command::parse("burst run fast"),
+            Err(ParseError::BadArgument)
+        );
+        assert_eq!(
+            Command::parse("count batch 0"),
+            Err(ParseError::BadArgument)
+        );
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        assert_eq!(
+            Command::parse("burst run"),
+            Ok(Command::RunBurst {
+                rate_hz: 10_000,
+                duration_ms: 500
+            })
+        );
+        assert_eq!(
+            Command::parse("capture arm 2000"),
+            Ok(Command::ArmCapture {
+                level_mv: 2000,
+                edge: Edge::Either,
+                pre: None
+            })
+        );
+        assert_eq!(
+            Command::parse("dump"),
+            Ok(Command::Dump {
+                start: 0,
+                count: None
+            })
+        );
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert_eq!(
+            Command::parse("capture arm 1500 rising 64"),
+            Ok(Command::ArmCapture {
+                level_mv: 1500,
+                edge: Edge::Rising,
+                pre: Some(64)
+            })
+        );
+        assert_eq!(
+            Command::parse("log storage warn"),
+            Ok(Command::SetLog {
+                module: Some(Module::Storage),
+                level: Level::Warn
+            })
+        );
+        assert_eq!(
+            Command::parse("contact closed 5 open 2.5"),
+            Ok(Command::SetContactThresholds {
+                closed_mt: 5.0,
+                open_mt: 2.5
+            })
+        );
+        // The open threshold must be below the closed one
+        assert_eq!(
+            Command::parse("contact closed 2 open 5"),
+            Err(ParseError::BadArgument)
+        );
+    }
+
+    #[test]
+    fn mock_changes_only_what_is_given() {
+        let current = Waveform {
+            shape: Shape::Sine,
+            period_ms: 1000,
+            amplitude_mv: 500,
+        };
+        let Ok(Command::Mock(Some(mock))) = Command::parse("mock step 250") else {
+            panic!("not parsed");
+        };
+        assert_eq!(
+            mock.apply(current),
+            Waveform {
+                shape: Shape::Step,
+                period_ms: 250,
+                amplitude_mv: 500,
+            }
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Contact sensing, as for a door or window with a magnet on the moving
+//! part: closed while the magnet's field, either pole, is strong enough,
+//! open once it has fallen well below that. A new state only counts once
+//! it has held for the debounce time, so a rattling door or a passing
+//! magnet does not report a string of changes.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// Field strength at or above which the contact reads closed, and at
+    /// or below which it reads open. Open is below closed.
+    pub closed_mt: f32,
+    pub open_mt: f32,
+    pub debounce_ms: u32,
+}
+
+impl Settings {
+    /// A door magnet a centimetre or two off an SS49E-class sensor.
+    pub const DEFAULT: Self = Self {
+        closed_mt: 3.0,
+        open_mt: 1.5,
+        debounce_ms: 200,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub struct Contact {
+    /// Debounced.
+    closed: Option<bool>,
+    /// Before debouncing, and since when.
+    level: Option<(bool, u64)>,
+}
+
+impl Contact {
+    /// Unknown until the first reading has held for the debounce time.
+    pub const fn new() -> Self {
+        Self {
+            closed: None,
+            level: None,
+        }
+    }
+
+    pub fn is_closed(&self) -> Option<bool> {
+        self.closed
+    }
+
+    /// Takes a valid reading, returning the new state on a change,
+    /// including the first state found.
+    pub fn update(&mut self, time_ms: u64, field_mt: f32, settings: &Settings) -> Option<bool> {
+        let strength_mt = field_mt.abs();
+        let level = if strength_mt >= settings.closed_mt {
+            true
+        } else if strength_mt <= settings.open_mt {
+            false
+        } else {
+            // Between the two, the level holds
+            self.level.is_some_and(|(level, _)| level)
+        };
+        let since_ms = match self.level {
+            Some((last, since_ms)) if last == level => since_ms,
+            _ => time_ms,
+        };
+        self.level = Some((level, since_ms));
+        if self.closed != Some(level) && time_ms - since_ms >= settings.debounce_ms as u64 {
+            self.closed = Some(level);
+            return self.closed;
+        }
+        None
+    }
+}
+
+impl Default for Contact {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_first_state_once_settled() {
+        let mut contact = Contact::new();
+        let settings = Settings::DEFAULT;
+        assert_eq!(contact.update(0, -4.0, &settings), None);
+        assert_eq!(contact.update(199, -4.0, &settings), None);
+        assert_eq!(contact.update(200, 5.0, &settings), Some(true));
+        assert_eq!(contact.update(300, 5.0, &settings), None);
+        assert_eq!(contact.is_closed(), Some(true));
+    }
+
+    #[test]
+    fn holds_between_the_thresholds() {
+        let mut contact = Contact::new();
+        let settings = Settings {
+            debounce_ms: 0,
+            ..Settings::DEFAULT
+        };
+        assert_eq!(contact.update(0, 3.0, &settings), Some(true));
+        assert_eq!(contact.update(1, 2.0, &settings), None);
+        assert_eq!(contact.update(2, 1.5, &settings), Some(false));
+        assert_eq!(contact.update(3, 2.9, &settings), None);
+        assert_eq!(contact.is_closed(), Some(false));
+    }
+
+    #[test]
+    fn ignores_bounces_shorter_than_the_debounce_time() {
+        let mut contact = Contact::new();
+        let settings = Settings::DEFAULT;
+        contact.update(0, 10.0, &settings);
+        assert_eq!(contact.update(200, 10.0, &settings), Some(true));
+        // Rattling open and shut
+        for t in (300..1000).step_by(100) {
+            let field_mt = if t % 200 == 0 { 0.0 } else { 10.0 };
+            assert_eq!(contact.update(t, field_mt, &settings), None);
+        }
+        assert_eq!(contact.update(1000, 0.0, &settings), None);
+        assert_eq!(contact.update(1200, 0.0, &settings), Some(false));
+    }
+}
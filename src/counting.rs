@@ -0,0 +1,135 @@
+//! Part counting, as on a conveyor carrying parts with magnets in them:
+//! each rising threshold crossing is a part going past. A crossing within
+//! the dead time of the last one counted is the same part, so a magnet
+//! that wobbles across the threshold counts once. Counts can be gathered
+//! into batches of a target size.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    pub dead_ms: u32,
+    /// Parts in a batch, or none at zero.
+    pub batch: u32,
+    /// How long the output is pulsed for at the end of a batch.
+    pub pulse_ms: u32,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        dead_ms: 50,
+        batch: 0,
+        pulse_ms: 100,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Count {
+    Counted,
+    /// Counted, and it completed a batch.
+    BatchDone,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Format)]
+pub struct Totals {
+    pub parts: u32,
+    /// Parts towards the batch under way.
+    pub in_batch: u32,
+    pub batches: u32,
+}
+
+pub struct Counter {
+    totals: Totals,
+    last_ms: Option<u64>,
+}
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self {
+            totals: Totals {
+                parts: 0,
+                in_batch: 0,
+                batches: 0,
+            },
+            last_ms: None,
+        }
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+
+    /// Takes a rising crossing, returning whether it counted.
+    pub fn pass(&mut self, time_ms: u64, settings: &Settings) -> Option<Count> {
+        if self
+            .last_ms
+            .is_some_and(|last_ms| time_ms - last_ms < settings.dead_ms as u64)
+        {
+            return None;
+        }
+        self.last_ms = Some(time_ms);
+        self.totals.parts += 1;
+        self.totals.in_batch += 1;
+        // A target lowered below the batch under way ends it at once
+        if settings.batch > 0 && self.totals.in_batch >= settings.batch {
+            self.totals.in_batch = 0;
+            self.totals.batches += 1;
+            return Some(Count::BatchDone);
+        }
+        Some(Count::Counted)
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossings_within_the_dead_time_count_once() {
+        let mut counter = Counter::new();
+        assert_eq!(counter.pass(0, &Settings::DEFAULT), Some(Count::Counted));
+        assert_eq!(counter.pass(49, &Settings::DEFAULT), None);
+        assert_eq!(counter.pass(50, &Settings::DEFAULT), Some(Count::Counted));
+        // Measured from the last one counted, not the last crossing
+        assert_eq!(counter.pass(60, &Settings::DEFAULT), None);
+        assert_eq!(counter.pass(100, &Settings::DEFAULT), Some(Count::Counted));
+        assert_eq!(counter.totals().parts, 3);
+        assert_eq!(counter.totals().batches, 0);
+    }
+
+    #[test]
+    fn batches_complete_at_the_target() {
+        let settings = Settings {
+            batch: 3,
+            ..Settings::DEFAULT
+        };
+        let mut counter = Counter::new();
+        let counts: [_; 7] = core::array::from_fn(|i| counter.pass(i as u64 * 100, &settings));
+        assert_eq!(counts[2], Some(Count::BatchDone));
+        assert_eq!(counts[5], Some(Count::BatchDone));
+        assert_eq!(counts[6], Some(Count::Counted));
+        assert_eq!(
+            counter.totals(),
+            Totals {
+                parts: 7,
+                in_batch: 1,
+                batches: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! CSV rendering of samples for file-based logs.
+
+use core::fmt::{self, Write};
+
+use crate::schema::{Config, Sample};
+
+pub const HEADER: &str = "time_us,raw,mv,mt,temp_c\n";
+
+/// Appends one row. `temp_c` is left empty when no temperature source exists.
+pub fn write_row<W: Write>(
+    w: &mut W,
+    sample: &Sample,
+    config: &Config,
+    temp_c: Option<f32>,
+) -> fmt::Result {
+    write!(
+        w,
+        "{},{},{},{:.3},",
+        sample.timestamp_us,
+        sample.raw,
+        sample.voltage_mv,
+        config.field_mt(sample.voltage_mv)
+    )?;
+    if let Some(t) = temp_c {
+        write!(w, "{:.1}", t)?;
+    }
+    w.write_char('\n')
+}
@@ -0,0 +1,241 @@
+//! Current sensing: a sensor in the gap of a core around a conductor, or
+//! an ACS71x-style module, reads in proportion to the current, so its
+//! output converts to amps with a zero and a sensitivity.
+//!
+//! Open loop, the accuracy is that of the core and sensor. Closed loop
+//! ([`NullFlux`]), a compensation winding on the core is driven to hold
+//! the flux at zero; the current is then read from the drive, which is
+//! linear, and the sensor only has to find zero.
+//!
+//! [`PROFILES`] hold the datasheet scaling of common modules, for use
+//! without a calibration.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::pid::{Gains, Pid};
+
+pub const ENCODED_SIZE: usize = 16;
+
+/// Rate of the null-flux loop, per second, for any scale: fast enough to
+/// follow a changing current over a few samples at the default rate, slow
+/// enough to stay stable.
+const LOOP_RATE_PER_S: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Scale {
+    /// Sensor output with no current.
+    pub zero_mv: f32,
+    /// Negative if the conductor runs through the other way.
+    pub mv_per_a: f32,
+}
+
+impl Scale {
+    /// Until calibrated: a mid-rail zero and 100mV/A, as on a 20A ACS712
+    /// read at 3.3V.
+    pub const DEFAULT: Self = Self {
+        zero_mv: 1650.0,
+        mv_per_a: 100.0,
+    };
+
+    pub fn amps(&self, voltage_mv: f32) -> f32 {
+        (voltage_mv - self.zero_mv) / self.mv_per_a
+    }
+
+    /// The scale that reads `voltage_mv` as `amps`, keeping the zero.
+    /// `None` if they are too close to the zero to tell a sensitivity.
+    pub fn calibrated(&self, voltage_mv: f32, amps: f32) -> Option<Self> {
+        let mv_per_a = (voltage_mv - self.zero_mv) / amps;
+        ((voltage_mv - self.zero_mv).abs() >= 1.0 && mv_per_a.is_finite()).then_some(Self {
+            zero_mv: self.zero_mv,
+            mv_per_a,
+        })
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A current sensor module's datasheet scaling. Their outputs are
+/// ratiometric: both the zero and the sensitivity follow the supply.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Profile {
+    pub name: &'static str,
+    /// At a 5V supply.
+    pub mv_per_a: f32,
+    /// Whether it reads current both ways, with its zero at half the
+    /// supply; one-way parts idle at a tenth of it, for more range.
+    pub bidirectional: bool,
+    /// The most current it reads, either way if bidirectional.
+    pub range_a: u32,
+}
+
+const PROFILE_SUPPLY_MV: f32 = 5000.0;
+
+pub const PROFILES: [Profile; 11] = [
+    Profile::new("acs712-5", 185.0, true, 5),
+    Profile::new("acs712-20", 100.0, true, 20),
+    Profile::new("acs712-30", 66.0, true, 30),
+    Profile::new("acs758-50b", 40.0, true, 50),
+    Profile::new("acs758-50u", 60.0, false, 50),
+    Profile::new("acs758-100b", 20.0, true, 100),
+    Profile::new("acs758-100u", 40.0, false, 100),
+    Profile::new("acs758-150b", 13.3, true, 150),
+    Profile::new("acs758-150u", 26.7, false, 150),
+    Profile::new("acs758-200b", 10.0, true, 200),
+    Profile::new("acs758-200u", 20.0, false, 200),
+];
+
+impl Profile {
+    const fn new(name: &'static str, mv_per_a: f32, bidirectional: bool, range_a: u32) -> Self {
+        Self {
+            name,
+            mv_per_a,
+            bidirectional,
+            range_a,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        PROFILES.into_iter().find(|p| p.name == name)
+    }
+
+    /// The scale for the module run from `supply_mv`, read straight into
+    /// the ADC.
+    pub fn scale(&self, supply_mv: u32) -> Scale {
+        let supply_mv = supply_mv as f32;
+        let zero_ratio = if self.bidirectional { 0.5 } else { 0.1 };
+        Scale {
+            zero_mv: supply_mv * zero_ratio,
+            mv_per_a: self.mv_per_a * supply_mv / PROFILE_SUPPLY_MV,
+        }
+    }
+}
+
+/// The closed loop: drives a compensation winding through a bipolar stage,
+/// where a duty of one half drives no current and the ends drive
+/// `full_scale_a` of primary current's worth either way.
+pub struct NullFlux {
+    pid: Pid,
+    scale: Scale,
+    full_scale_a: f32,
+    duty: f32,
+}
+
+impl NullFlux {
+    pub fn new(scale: Scale, full_scale_a: f32) -> Self {
+        // Gains in duty per millivolt of error, from the millivolts the
+        // drive moves across its range; negative, as more drive lowers the
+        // reading
+        let mv_per_duty = 2.0 * full_scale_a * scale.mv_per_a;
+        let mut pid = Pid::new(Gains {
+            kp: -0.2 / mv_per_duty,
+            ki: -LOOP_RATE_PER_S / mv_per_duty,
+            kd: 0.0,
+        });
+        pid.preset(0.5);
+        Self {
+            pid,
+            scale,
+            full_scale_a,
+            duty: 0.5,
+        }
+    }
+
+    /// Takes a reading and returns the new duty.
+    pub fn update(&mut self, voltage_mv: f32, dt_s: f32) -> f32 {
+        self.duty = self.pid.update(self.scale.zero_mv, voltage_mv, dt_s);
+        self.duty
+    }
+
+    /// The current the drive is compensating, and what it has yet to.
+    pub fn amps(&self, voltage_mv: f32) -> f32 {
+        (2.0 * self.duty - 1.0) * self.full_scale_a + self.scale.amps(voltage_mv)
+    }
+
+    pub fn duty(&self) -> f32 {
+        self.duty
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    pub fn full_scale_a(&self) -> f32 {
+        self.full_scale_a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_and_calibration() {
+        let scale = Scale::default();
+        assert_eq!(scale.amps(1650.0), 0.0);
+        assert_eq!(scale.amps(1850.0), 2.0);
+        assert_eq!(scale.amps(1450.0), -2.0);
+
+        let scale = scale.calibrated(1716.0, 5.0).unwrap();
+        assert!((scale.mv_per_a - 13.2).abs() < 1e-4);
+        assert!((scale.amps(1782.0) - 10.0).abs() < 1e-4);
+        assert_eq!(scale.calibrated(1650.5, 5.0), None);
+        assert_eq!(scale.calibrated(1700.0, 0.0), None);
+    }
+
+    #[test]
+    fn profiles_scale_with_the_supply() {
+        let profile = Profile::parse("acs712-20").unwrap();
+        let scale = profile.scale(5000);
+        assert_eq!(
+            scale,
+            Scale {
+                zero_mv: 2500.0,
+                mv_per_a: 100.0
+            }
+        );
+        assert!((scale.amps(3500.0) - 10.0).abs() < 1e-4);
+        let scale = profile.scale(3300);
+        assert!((scale.amps(1650.0 + 66.0) - 1.0).abs() < 1e-4);
+
+        // One-way parts have their range above a low zero
+        let profile = Profile::parse("acs758-50u").unwrap();
+        let scale = profile.scale(5000);
+        assert!((scale.zero_mv - 500.0).abs() < 1e-3);
+        assert!((scale.amps(500.0 + 50.0 * 60.0) - 50.0).abs() < 1e-3);
+        assert!(Profile::parse("acs712").is_none());
+    }
+
+    #[test]
+    fn null_flux_reads_the_drive() {
+        // The winding's field opposes the primary's at the sensor
+        let scale = Scale::default();
+        let full_scale_a = 10.0;
+        let mut null_flux = NullFlux::new(scale, full_scale_a);
+        for primary_a in [3.0, -7.5] {
+            let mut voltage_mv = 1650.0;
+            for _ in 0..200 {
+                let duty = null_flux.update(voltage_mv, 0.01);
+                let winding_a = (2.0 * duty - 1.0) * full_scale_a;
+                voltage_mv = 1650.0 + (primary_a - winding_a) * scale.mv_per_a;
+            }
+            assert!((voltage_mv - 1650.0).abs() < 1.0, "{voltage_mv}");
+            assert!((null_flux.amps(voltage_mv) - primary_a).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn largest_scale_fits_its_record() {
+        let scale = Scale {
+            zero_mv: f32::MAX,
+            mv_per_a: f32::MIN,
+        };
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = postcard::to_slice(&scale, &mut buf).unwrap();
+        assert_eq!(postcard::from_bytes::<Scale>(encoded).unwrap(), scale);
+    }
+}
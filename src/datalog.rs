@@ -0,0 +1,301 @@
+//! Records kept in the persistent on-flash log.
+
+use core::fmt::{self, Write};
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::csv;
+use crate::datetime::DateTime;
+use crate::delta::DeltaDecoder;
+use crate::schema::{Config, Event, Sample};
+
+/// Payload capacity of a [`Record::SampleBlock`].
+pub const BLOCK_SIZE: usize = 192;
+
+/// Upper bound on an encoded [`Record`].
+pub const MAX_RECORD_SIZE: usize = BLOCK_SIZE + 32;
+
+/// Bytes ahead of the record in a log entry: a marker and the entry's
+/// sequence number.
+pub const ENTRY_HEADER_SIZE: usize = 5;
+
+/// Upper bound on a log entry, see [`encode_entry`].
+pub const MAX_ENTRY_SIZE: usize = MAX_RECORD_SIZE + ENTRY_HEADER_SIZE;
+
+// A record starts with its postcard variant, which is never this, so the
+// unnumbered entries of older firmware are told apart
+const NUMBERED: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Record<'a> {
+    /// Single uncompressed sample, only written by older firmware.
+    Sample {
+        time_ms: u64,
+        raw: u16,
+        voltage_mv: u32,
+    },
+    Event { time_ms: u64, event: Event },
+    /// Samples starting at `time_ms`, compressed with [`crate::delta`].
+    /// `period_ms` is the mean spacing measured over the block.
+    SampleBlock {
+        time_ms: u64,
+        period_ms: u32,
+        deltas: &'a [u8],
+    },
+}
+
+impl<'a> Record<'a> {
+    pub fn encode<'b>(&self, buf: &'b mut [u8]) -> postcard::Result<&'b mut [u8]> {
+        postcard::to_slice(self, buf)
+    }
+
+    pub fn decode(bytes: &'a [u8]) -> postcard::Result<Self> {
+        postcard::from_bytes(bytes)
+    }
+
+    /// The samples a record holds: none for an event.
+    pub fn samples(&self) -> impl Iterator<Item = Sample> + use<'a> {
+        let (single, block) = match *self {
+            Record::Sample {
+                time_ms,
+                raw,
+                voltage_mv,
+            } => {
+                let sample = Sample {
+                    timestamp_us: time_ms * 1000,
+                    raw,
+                    voltage_mv,
+                };
+                (Some(sample), None)
+            }
+            Record::Event { .. } => (None, None),
+            Record::SampleBlock {
+                time_ms,
+                period_ms,
+                deltas,
+            } => {
+                let samples = DeltaDecoder::new(deltas, time_ms * 1000, period_ms as u64 * 1000);
+                (None, Some(samples))
+            }
+        };
+        single.into_iter().chain(block.into_iter().flatten())
+    }
+
+    /// Renders as rows in the [`csv`] layout. Events become `#` comment lines
+    /// so CSV readers can skip them.
+    pub fn write_csv<W: Write>(&self, w: &mut W, config: &Config) -> fmt::Result {
+        match *self {
+            Record::Event { time_ms, event } => write_event(w, time_ms, &event),
+            _ => self
+                .samples()
+                .try_for_each(|sample| csv::write_row(w, &sample, config, None)),
+        }
+    }
+}
+
+/// Writes `record` into `buf` as the log entry numbered `sequence`. Entries
+/// are numbered in the order they are written, so a reader can resume after
+/// the last one it saw however many the log has overwritten since.
+pub fn encode_entry<'b>(
+    sequence: u32,
+    record: &Record,
+    buf: &'b mut [u8],
+) -> postcard::Result<&'b mut [u8]> {
+    if buf.len() < ENTRY_HEADER_SIZE {
+        return Err(postcard::Error::SerializeBufferFull);
+    }
+    buf[0] = NUMBERED;
+    buf[1..ENTRY_HEADER_SIZE].copy_from_slice(&sequence.to_le_bytes());
+    let len = ENTRY_HEADER_SIZE + record.encode(&mut buf[ENTRY_HEADER_SIZE..])?.len();
+    Ok(&mut buf[..len])
+}
+
+/// Splits a log entry into its sequence number and its record's bytes. An
+/// entry older firmware wrote is a bare record, without a number.
+pub fn split_entry(bytes: &[u8]) -> (Option<u32>, &[u8]) {
+    match bytes.split_first_chunk::<ENTRY_HEADER_SIZE>() {
+        Some(([NUMBERED, sequence @ ..], record)) => (Some(u32::from_le_bytes(*sequence)), record),
+        _ => (None, bytes),
+    }
+}
+
+fn write_event<W: Write>(w: &mut W, time_ms: u64, event: &Event) -> fmt::Result {
+    match *event {
+        Event::Boot => writeln!(w, "# event,{},boot", time_ms),
+        Event::ThresholdCrossed {
+            threshold_mv,
+            voltage_mv,
+            rising,
+        } => writeln!(
+            w,
+            "# event,{},threshold,{},{},{}",
+            time_ms,
+            threshold_mv,
+            voltage_mv,
+            if rising { "rising" } else { "falling" }
+        ),
+        Event::ClockSet { unix_s } => writeln!(
+            w,
+            "# event,{},clock_set,{}",
+            time_ms,
+            DateTime::from_unix(unix_s)
+        ),
+        Event::LowSupply { duration_ms } => {
+            writeln!(w, "# event,{},low_supply,{}", time_ms, duration_ms)
+        }
+        Event::WiringFault { stuck_high } => writeln!(
+            w,
+            "# event,{},wiring_fault,{}",
+            time_ms,
+            if stuck_high { "high" } else { "low" }
+        ),
+        Event::WiringFaultCleared => writeln!(w, "# event,{},wiring_fault_cleared", time_ms),
+        Event::Battery { voltage_mv, low } => writeln!(
+            w,
+            "# event,{},battery,{}{}",
+            time_ms,
+            voltage_mv,
+            if low { ",low" } else { "" }
+        ),
+        Event::PowerStage { stage, voltage_mv } => writeln!(
+            w,
+            "# event,{},power_stage,{},{}",
+            time_ms,
+            stage.name(),
+            voltage_mv
+        ),
+        Event::Energy { watts, energy_wh } => {
+            writeln!(w, "# event,{},energy,{},{}", time_ms, watts, energy_wh)
+        }
+        Event::Contact { closed } => writeln!(
+            w,
+            "# event,{},contact,{}",
+            time_ms,
+            if closed { "closed" } else { "open" }
+        ),
+        Event::Tamper { cause } => writeln!(w, "# event,{},tamper,{}", time_ms, cause.name()),
+        Event::TamperCleared => writeln!(w, "# event,{},tamper_cleared", time_ms),
+        Event::Rule { rule, met } => writeln!(
+            w,
+            "# event,{},rule,{},{}",
+            time_ms,
+            rule,
+            if met { "met" } else { "cleared" }
+        ),
+    }
+}
+
+/// Averages the samples in each window of `interval_us` into one, stamped
+/// with the mean time of its inputs.
+pub struct Decimator {
+    interval_us: u64,
+    /// End of the window being averaged, once it has a sample.
+    end_us: Option<u64>,
+    count: u32,
+    time_sum: u64,
+    raw_sum: u32,
+    mv_sum: u32,
+}
+
+impl Decimator {
+    /// A zero `interval_us` passes every sample straight through.
+    pub const fn new(interval_us: u64) -> Self {
+        Self {
+            interval_us,
+            end_us: None,
+            count: 0,
+            time_sum: 0,
+            raw_sum: 0,
+            mv_sum: 0,
+        }
+    }
+
+    /// Returns the average of the last window once a sample arrives after
+    /// it ends.
+    pub fn push(&mut self, sample: &Sample) -> Option<Sample> {
+        if self.interval_us == 0 {
+            return Some(*sample);
+        }
+
+        let mut out = None;
+        match self.end_us {
+            Some(end_us) if sample.timestamp_us >= end_us => {
+                out = Some(Sample {
+                    timestamp_us: self.time_sum / self.count as u64,
+                    raw: (self.raw_sum / self.count) as u16,
+                    voltage_mv: self.mv_sum / self.count,
+                });
+                // Windows follow on from each other, unless the samples
+                // stopped for longer than one
+                let next_us = end_us + self.interval_us;
+                *self = Self::new(self.interval_us);
+                self.end_us = Some(if sample.timestamp_us < next_us {
+                    next_us
+                } else {
+                    sample.timestamp_us + self.interval_us
+                });
+            }
+            Some(_) => {}
+            None => self.end_us = Some(sample.timestamp_us + self.interval_us),
+        }
+
+        self.time_sum += sample.timestamp_us;
+        self.raw_sum += sample.raw as u32;
+        self.mv_sum += sample.voltage_mv;
+        self.count += 1;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_entries() {
+        let record = Record::Event {
+            time_ms: 1234,
+            event: Event::Boot,
+        };
+        let mut buf = [0u8; MAX_ENTRY_SIZE];
+        let entry = encode_entry(70_000, &record, &mut buf).unwrap();
+        let (sequence, bytes) = split_entry(entry);
+        assert_eq!(sequence, Some(70_000));
+        assert_eq!(Record::decode(bytes), Ok(record));
+
+        // As older firmware stored it
+        let mut buf = [0u8; MAX_RECORD_SIZE];
+        let bare = record.encode(&mut buf).unwrap();
+        assert_eq!(split_entry(bare), (None, &bare[..]));
+        assert_eq!(split_entry(&[]), (None, &[][..]));
+    }
+
+    #[test]
+    fn decimates_by_time() {
+        let sample = |timestamp_us, raw| Sample {
+            timestamp_us,
+            raw,
+            voltage_mv: raw as u32,
+        };
+        let mut decimator = Decimator::new(1_000_000);
+        // Every 10ms: the hundred samples in the first second are averaged
+        let mut out = (0..=100).filter_map(|n| decimator.push(&sample(n * 10_000, n as u16)));
+        assert_eq!(out.next(), Some(sample(495_000, 49)));
+        assert_eq!(out.next(), None);
+
+        // After a gap the next window starts from the sample that ends it
+        assert_eq!(
+            decimator.push(&sample(5_000_000, 7)),
+            Some(sample(1_000_000, 100))
+        );
+        assert_eq!(decimator.push(&sample(5_500_000, 9)), None);
+        assert_eq!(
+            decimator.push(&sample(6_000_000, 0)),
+            Some(sample(5_250_000, 8))
+        );
+
+        let mut passthrough = Decimator::new(0);
+        assert_eq!(passthrough.push(&sample(10, 1)), Some(sample(10, 1)));
+    }
+}
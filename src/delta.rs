@@ -0,0 +1,256 @@
+//! Delta + varint compression for runs of samples.
+//!
+//! A block starts with the first sample in absolute terms. Every following
+//! sample is stored as zigzag varint differences from its predecessor, and
+//! stretches of identical samples collapse into a single run-length entry,
+//! so a quiet sensor costs a couple of bytes per block instead of per sample.
+//!
+//! Entry layout: `varint(zigzag(d_raw) << 1 | is_run)`, then either the run
+//! length or `varint(zigzag(d_mv))`.
+//!
+//! Timestamps are not stored per sample; the decoder spaces samples evenly
+//! from a start time and period kept alongside the block.
+
+use heapless::Vec;
+
+use crate::schema::Sample;
+
+// Largest entry plus a pending run flush
+const ENTRY_HEADROOM: usize = 16;
+
+fn zigzag(v: i32) -> u32 {
+    ((v << 1) ^ (v >> 31)) as u32
+}
+
+fn unzigzag(v: u32) -> i32 {
+    ((v >> 1) as i32) ^ -((v & 1) as i32)
+}
+
+fn put_varint<const N: usize>(buf: &mut Vec<u8, N>, mut v: u32) {
+    while v >= 0x80 {
+        let _ = buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    let _ = buf.push(v as u8);
+}
+
+fn get_varint(bytes: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut v = 0u32;
+    for shift in (0..35).step_by(7) {
+        let b = *bytes.get(*pos)?;
+        *pos += 1;
+        v |= ((b & 0x7f) as u32) << shift;
+        if b & 0x80 == 0 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+pub struct DeltaEncoder<const N: usize> {
+    buf: Vec<u8, N>,
+    last: Option<Sample>,
+    run: u32,
+}
+
+impl<const N: usize> DeltaEncoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            last: None,
+            run: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last.is_none()
+    }
+
+    /// Appends `sample`, or returns `false` if the block is full and must be
+    /// taken with [`Self::finish`] first.
+    pub fn push(&mut self, sample: &Sample) -> bool {
+        let Some(last) = self.last else {
+            put_varint(&mut self.buf, sample.raw as u32);
+            put_varint(&mut self.buf, sample.voltage_mv);
+            self.last = Some(*sample);
+            return true;
+        };
+
+        if sample.raw == last.raw && sample.voltage_mv == last.voltage_mv {
+            self.run += 1;
+            return true;
+        }
+        if N - self.buf.len() < ENTRY_HEADROOM {
+            return false;
+        }
+
+        self.flush_run();
+        let d_raw = sample.raw as i32 - last.raw as i32;
+        let d_mv = sample.voltage_mv as i32 - last.voltage_mv as i32;
+        put_varint(&mut self.buf, zigzag(d_raw) << 1);
+        put_varint(&mut self.buf, zigzag(d_mv));
+        self.last = Some(*sample);
+        true
+    }
+
+    /// Returns the encoded block and resets the encoder for the next one.
+    pub fn finish(&mut self) -> Vec<u8, N> {
+        self.flush_run();
+        self.last = None;
+        core::mem::take(&mut self.buf)
+    }
+
+    fn flush_run(&mut self) {
+        if self.run > 0 {
+            put_varint(&mut self.buf, 1);
+            put_varint(&mut self.buf, self.run);
+            self.run = 0;
+        }
+    }
+}
+
+impl<const N: usize> Default for DeltaEncoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterates over the samples of an encoded block. Stops early on malformed
+/// input.
+pub struct DeltaDecoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    time_us: u64,
+    period_us: u64,
+    last: Option<Sample>,
+    run: u32,
+}
+
+impl<'a> DeltaDecoder<'a> {
+    pub fn new(bytes: &'a [u8], start_us: u64, period_us: u64) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            time_us: start_us,
+            period_us,
+            last: None,
+            run: 0,
+        }
+    }
+}
+
+impl Iterator for DeltaDecoder<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let mut sample = self.next_value()?;
+        sample.timestamp_us = self.time_us;
+        self.time_us += self.period_us;
+        self.last = Some(sample);
+        Some(sample)
+    }
+}
+
+impl DeltaDecoder<'_> {
+    fn next_value(&mut self) -> Option<Sample> {
+        if self.run > 0 {
+            self.run -= 1;
+            return self.last;
+        }
+
+        let Some(last) = self.last else {
+            let raw = get_varint(self.bytes, &mut self.pos)?;
+            let voltage_mv = get_varint(self.bytes, &mut self.pos)?;
+            return Some(Sample {
+                timestamp_us: 0,
+                raw: raw as u16,
+                voltage_mv,
+            });
+        };
+
+        let head = get_varint(self.bytes, &mut self.pos)?;
+        if head & 1 == 1 {
+            self.run = get_varint(self.bytes, &mut self.pos)?.checked_sub(1)?;
+            return self.last;
+        }
+
+        let d_mv = unzigzag(get_varint(self.bytes, &mut self.pos)?);
+        Some(Sample {
+            timestamp_us: 0,
+            raw: (last.raw as i32 + unzigzag(head >> 1)) as u16,
+            voltage_mv: (last.voltage_mv as i32 + d_mv) as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(raw: u16, voltage_mv: u32) -> Sample {
+        Sample {
+            timestamp_us: 0,
+            raw,
+            voltage_mv,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = [
+            sample(2048, 1650),
+            sample(2050, 1652),
+            sample(2050, 1652),
+            sample(2050, 1652),
+            sample(1000, 806),
+            sample(4095, 3300),
+            sample(0, 0),
+        ];
+        let mut encoder = DeltaEncoder::<64>::new();
+        for s in &input {
+            assert!(encoder.push(s));
+        }
+        let block = encoder.finish();
+        assert!(encoder.is_empty());
+
+        let mut output = DeltaDecoder::new(&block, 1000, 10);
+        for (i, s) in input.iter().enumerate() {
+            let out = output.next().unwrap();
+            assert_eq!((out.raw, out.voltage_mv), (s.raw, s.voltage_mv));
+            assert_eq!(out.timestamp_us, 1000 + 10 * i as u64);
+        }
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn runs_collapse() {
+        let mut encoder = DeltaEncoder::<64>::new();
+        for _ in 0..1000 {
+            encoder.push(&sample(2048, 1650));
+        }
+        let block = encoder.finish();
+        assert!(block.len() <= 8);
+        assert_eq!(DeltaDecoder::new(&block, 0, 1).count(), 1000);
+    }
+
+    #[test]
+    fn full_block_refuses_samples() {
+        let mut encoder = DeltaEncoder::<32>::new();
+        let pushed = (0..100)
+            .take_while(|&i| encoder.push(&sample(i * 100, i as u32 * 80)))
+            .count();
+        assert!(pushed < 100);
+        let block = encoder.finish();
+        assert_eq!(DeltaDecoder::new(&block, 0, 1).count(), pushed);
+    }
+
+    #[test]
+    fn truncated_input_stops() {
+        let mut encoder = DeltaEncoder::<64>::new();
+        encoder.push(&sample(2048, 1650));
+        encoder.push(&sample(3000, 2400));
+        let block = encoder.finish();
+        let truncated = &block[..block.len() - 1];
+        assert_eq!(DeltaDecoder::new(truncated, 0, 1).count(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+//! Running statistics for the diagnostics report.
+
+/// Spacing between consecutive samples, to check the achieved sample rate
+/// against the configured one.
+pub struct RateStats {
+    count: u64,
+    first_us: u64,
+    last_us: u64,
+    min_interval_us: u64,
+    max_interval_us: u64,
+    /// For the jitter; wide enough for days of samples a minute apart.
+    sum_squares_us: u128,
+}
+
+impl RateStats {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            first_us: 0,
+            last_us: 0,
+            min_interval_us: u64::MAX,
+            max_interval_us: 0,
+            sum_squares_us: 0,
+        }
+    }
+
+    pub fn update(&mut self, timestamp_us: u64) {
+        if self.count == 0 {
+            self.first_us = timestamp_us;
+        } else {
+            let interval_us = timestamp_us.saturating_sub(self.last_us);
+            self.min_interval_us = self.min_interval_us.min(interval_us);
+            self.max_interval_us = self.max_interval_us.max(interval_us);
+            self.sum_squares_us += interval_us as u128 * interval_us as u128;
+        }
+        self.last_us = timestamp_us;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean, minimum and maximum interval, once there are two samples.
+    pub fn intervals_us(&self) -> Option<(u64, u64, u64)> {
+        (self.count >= 2).then(|| {
+            (
+                (self.last_us - self.first_us) / (self.count - 1),
+                self.min_interval_us,
+                self.max_interval_us,
+            )
+        })
+    }
+
+    /// Standard deviation of the interval, once there are two samples.
+    pub fn jitter_us(&self) -> Option<u64> {
+        (self.count >= 2).then(|| {
+            let intervals = (self.count - 1) as u128;
+            let sum = (self.last_us - self.first_us) as u128;
+            // n·Σx² - (Σx)², over n², without losing the fraction of the mean
+            let variance = (intervals * self.sum_squares_us).saturating_sub(sum * sum)
+                / (intervals * intervals);
+            variance.isqrt() as u64
+        })
+    }
+}
+
+impl Default for RateStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_intervals_have_no_jitter() {
+        let mut rate = RateStats::new();
+        assert_eq!(rate.jitter_us(), None);
+        for i in 0..100 {
+            rate.update(i * 10_000);
+        }
+        assert_eq!(rate.intervals_us(), Some((10_000, 10_000, 10_000)));
+        assert_eq!(rate.jitter_us(), Some(0));
+    }
+
+    #[test]
+    fn alternating_intervals() {
+        let mut rate = RateStats::new();
+        let mut timestamp_us = 0;
+        for i in 0..101 {
+            rate.update(timestamp_us);
+            timestamp_us += if i % 2 == 0 { 9_900 } else { 10_100 };
+        }
+        assert_eq!(rate.intervals_us(), Some((10_000, 9_900, 10_100)));
+        assert_eq!(rate.jitter_us(), Some(100));
+    }
+}
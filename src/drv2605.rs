@@ -0,0 +1,90 @@
+//! Minimal driver for the TI DRV2605 haptic driver, playing effects from
+//! its built-in ROM library on internal trigger.
+
+use defmt::Format;
+use embedded_hal::i2c::I2c;
+
+const ADDRESS: u8 = 0x5a;
+
+const REG_STATUS: u8 = 0x00;
+const REG_MODE: u8 = 0x01;
+const REG_LIBRARY: u8 = 0x03;
+const REG_SEQUENCE: u8 = 0x04;
+const REG_GO: u8 = 0x0c;
+const REG_FEEDBACK: u8 = 0x1a;
+const REG_CONTROL3: u8 = 0x1d;
+
+// Out of standby, effects started by the GO bit
+const MODE_INTERNAL_TRIGGER: u8 = 0x00;
+const STATUS_DEVICE_ID_SHIFT: u8 = 5;
+const FEEDBACK_LRA: u8 = 1 << 7;
+const CONTROL3_ERM_OPEN_LOOP: u8 = 1 << 5;
+// The first ERM library suits most coin and bar motors; the LRA library is
+// the only one for LRAs
+const LIBRARY_ERM: u8 = 1;
+const LIBRARY_LRA: u8 = 6;
+
+/// Effects at most in one sequence.
+pub const MAX_SEQUENCE: usize = 8;
+
+/// Effects from the ROM library, by number.
+pub const STRONG_CLICK: u8 = 1;
+pub const DOUBLE_CLICK: u8 = 10;
+pub const STRONG_BUZZ: u8 = 14;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum Actuator {
+    /// Eccentric rotating mass: coin and bar vibration motors.
+    Erm,
+    /// Linear resonant actuator.
+    Lra,
+}
+
+pub struct Drv2605<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Drv2605<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Takes the chip out of standby for `actuator`, in open loop for an
+    /// ERM as it needs no calibration. Returns the device ID: 3 for the
+    /// DRV2605, 7 for the DRV2605L.
+    pub fn init(&mut self, actuator: Actuator) -> Result<u8, I::Error> {
+        let mut status = [0u8];
+        self.i2c.write_read(ADDRESS, &[REG_STATUS], &mut status)?;
+        self.i2c
+            .write(ADDRESS, &[REG_MODE, MODE_INTERNAL_TRIGGER])?;
+
+        let (library, feedback, control3) = match actuator {
+            Actuator::Erm => (LIBRARY_ERM, 0, CONTROL3_ERM_OPEN_LOOP),
+            Actuator::Lra => (LIBRARY_LRA, FEEDBACK_LRA, 0),
+        };
+        self.i2c.write(ADDRESS, &[REG_LIBRARY, library])?;
+        self.update(REG_FEEDBACK, FEEDBACK_LRA, feedback)?;
+        self.update(REG_CONTROL3, CONTROL3_ERM_OPEN_LOOP, control3)?;
+        Ok(status[0] >> STATUS_DEVICE_ID_SHIFT)
+    }
+
+    /// Plays up to [`MAX_SEQUENCE`] effects one after another, cutting
+    /// short any still playing.
+    pub fn play(&mut self, effects: &[u8]) -> Result<(), I::Error> {
+        self.i2c.write(ADDRESS, &[REG_GO, 0])?;
+        let mut sequence = [0u8; 1 + MAX_SEQUENCE];
+        sequence[0] = REG_SEQUENCE;
+        let len = effects.len().min(MAX_SEQUENCE);
+        sequence[1..=len].copy_from_slice(&effects[..len]);
+        // A zero after the last effect ends the sequence
+        self.i2c
+            .write(ADDRESS, &sequence[..(len + 2).min(sequence.len())])?;
+        self.i2c.write(ADDRESS, &[REG_GO, 1])
+    }
+
+    fn update(&mut self, reg: u8, mask: u8, bits: u8) -> Result<(), I::Error> {
+        let mut value = [0u8];
+        self.i2c.write_read(ADDRESS, &[reg], &mut value)?;
+        self.i2c.write(ADDRESS, &[reg, (value[0] & !mask) | bits])
+    }
+}
@@ -0,0 +1,101 @@
+//! Minimal driver for the DS3231 I2C real-time clock.
+
+use embedded_hal::i2c::I2c;
+
+use crate::datetime::DateTime;
+
+const ADDRESS: u8 = 0x68;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_STATUS: u8 = 0x0f;
+const REG_TEMPERATURE: u8 = 0x11;
+
+// Oscillator stopped since the flag was last cleared, time is not trustworthy
+const STATUS_OSF: u8 = 1 << 7;
+const MONTH_CENTURY: u8 = 1 << 7;
+const HOUR_12H: u8 = 1 << 6;
+
+fn from_bcd(b: u8) -> u8 {
+    (b >> 4) * 10 + (b & 0x0f)
+}
+
+fn to_bcd(v: u8) -> u8 {
+    ((v / 10) << 4) | (v % 10)
+}
+
+pub struct Ds3231<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Ds3231<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Reads the current time, or `None` if the clock lost power since it was
+    /// last set.
+    pub fn read(&mut self) -> Result<Option<DateTime>, I::Error> {
+        let mut status = [0u8];
+        self.i2c.write_read(ADDRESS, &[REG_STATUS], &mut status)?;
+        if status[0] & STATUS_OSF != 0 {
+            return Ok(None);
+        }
+
+        let mut r = [0u8; 7];
+        self.i2c.write_read(ADDRESS, &[REG_SECONDS], &mut r)?;
+
+        let hour = if r[2] & HOUR_12H != 0 {
+            // 12-hour mode: bit 5 is PM
+            from_bcd(r[2] & 0x1f) % 12 + if r[2] & 0x20 != 0 { 12 } else { 0 }
+        } else {
+            from_bcd(r[2] & 0x3f)
+        };
+        let century = if r[5] & MONTH_CENTURY != 0 { 100 } else { 0 };
+
+        let dt = DateTime {
+            year: 2000 + century + from_bcd(r[6]) as u16,
+            month: from_bcd(r[5] & 0x1f),
+            day: from_bcd(r[4] & 0x3f),
+            hour,
+            minute: from_bcd(r[1] & 0x7f),
+            second: from_bcd(r[0] & 0x7f),
+        };
+        Ok(dt.is_valid().then_some(dt))
+    }
+
+    /// The die temperature in degrees Celsius, to a quarter of a degree,
+    /// which the chip measures every 64s to trim its oscillator.
+    pub fn temperature_c(&mut self) -> Result<f32, I::Error> {
+        let mut r = [0u8; 2];
+        self.i2c.write_read(ADDRESS, &[REG_TEMPERATURE], &mut r)?;
+        // Two's complement, with the fraction in the top two bits of the second
+        Ok((i16::from_be_bytes(r) >> 6) as f32 * 0.25)
+    }
+
+    /// Sets the clock (24-hour mode) and clears the oscillator-stop flag.
+    pub fn set(&mut self, dt: &DateTime) -> Result<(), I::Error> {
+        let years = dt.year.saturating_sub(2000);
+        let century = if years >= 100 { MONTH_CENTURY } else { 0 };
+        // Day of week, 1 = Monday; 1970-01-01 was a Thursday
+        let weekday = ((dt.to_unix() / 86_400 + 3) % 7) as u8 + 1;
+
+        self.i2c.write(
+            ADDRESS,
+            &[
+                REG_SECONDS,
+                to_bcd(dt.second),
+                to_bcd(dt.minute),
+                to_bcd(dt.hour),
+                weekday,
+                to_bcd(dt.day),
+                to_bcd(dt.month) | century,
+                to_bcd((years % 100) as u8),
+            ],
+        )?;
+
+        let mut status = [0u8];
+        self.i2c.write_read(ADDRESS, &[REG_STATUS], &mut status)?;
+        self.i2c
+            .write(ADDRESS, &[REG_STATUS, status[0] & !STATUS_OSF])
+    }
+}
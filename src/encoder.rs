@@ -0,0 +1,140 @@
+//! On-device editing of the alarm threshold and LED brightness with a rotary
+//! encoder. The push-button steps through the settings; turning the knob
+//! changes the one selected.
+
+use defmt::Format;
+
+use crate::schema::Config;
+
+/// Quadrature counts per detent when both edges of both signals are counted.
+pub const COUNTS_PER_DETENT: i16 = 4;
+
+pub const THRESHOLD_STEP_MV: u32 = 10;
+pub const BRIGHTNESS_STEP: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Setting {
+    Threshold,
+    Brightness,
+}
+
+/// Turns raw counts into whole detents, carrying any remainder.
+pub struct Detents {
+    remainder: i16,
+}
+
+impl Detents {
+    pub const fn new() -> Self {
+        Self { remainder: 0 }
+    }
+
+    pub fn push(&mut self, counts: i16) -> i16 {
+        self.remainder = self.remainder.saturating_add(counts);
+        let detents = self.remainder / COUNTS_PER_DETENT;
+        self.remainder %= COUNTS_PER_DETENT;
+        detents
+    }
+}
+
+impl Default for Detents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Editor {
+    /// The setting being edited, if any.
+    pub setting: Option<Setting>,
+    pub threshold_mv: u32,
+    pub brightness: u8,
+}
+
+impl Editor {
+    pub const fn new(threshold_mv: u32, brightness: u8) -> Self {
+        Self {
+            setting: None,
+            threshold_mv,
+            brightness,
+        }
+    }
+
+    /// Moves on to the next setting, or stops editing after the last.
+    pub fn select(&mut self) -> Option<Setting> {
+        self.setting = match self.setting {
+            None => Some(Setting::Threshold),
+            Some(Setting::Threshold) => Some(Setting::Brightness),
+            Some(Setting::Brightness) => None,
+        };
+        self.setting
+    }
+
+    /// Applies `detents` to the selected setting. The threshold stays within
+    /// the sensor's output range. Returns whether the value changed.
+    pub fn turn(&mut self, detents: i16, config: &Config) -> bool {
+        match self.setting {
+            Some(Setting::Threshold) => {
+                let step = THRESHOLD_STEP_MV as i64 * detents as i64;
+                let threshold_mv = (self.threshold_mv as i64 + step)
+                    .clamp(config.min_voltage_mv as i64, config.max_voltage_mv as i64)
+                    as u32;
+                let changed = threshold_mv != self.threshold_mv;
+                self.threshold_mv = threshold_mv;
+                changed
+            }
+            Some(Setting::Brightness) => {
+                let step = BRIGHTNESS_STEP as i32 * detents as i32;
+                let brightness = (self.brightness as i32 + step).clamp(0, u8::MAX as i32) as u8;
+                let changed = brightness != self.brightness;
+                self.brightness = brightness;
+                changed
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_part_detents() {
+        let mut detents = Detents::new();
+        assert_eq!(detents.push(3), 0);
+        assert_eq!(detents.push(2), 1);
+        assert_eq!(detents.push(-6), -1);
+        assert_eq!(detents.push(-3), -1);
+    }
+
+    #[test]
+    fn steps_through_the_settings() {
+        let mut editor = Editor::new(2200, 128);
+        assert_eq!(editor.select(), Some(Setting::Threshold));
+        assert_eq!(editor.select(), Some(Setting::Brightness));
+        assert_eq!(editor.select(), None);
+        assert!(!editor.turn(1, &Config::default()));
+    }
+
+    #[test]
+    fn keeps_the_settings_in_range() {
+        let config = Config::default();
+        let mut editor = Editor::new(config.max_voltage_mv - 15, 250);
+        editor.select();
+        assert!(editor.turn(1, &config));
+        assert!(editor.turn(1, &config));
+        assert!(!editor.turn(1, &config));
+        assert_eq!(editor.threshold_mv, config.max_voltage_mv);
+        assert!(editor.turn(-2, &config));
+        assert_eq!(
+            editor.threshold_mv,
+            config.max_voltage_mv - 2 * THRESHOLD_STEP_MV
+        );
+
+        editor.select();
+        assert!(editor.turn(1, &config));
+        assert_eq!(editor.brightness, u8::MAX);
+        assert!(!editor.turn(1, &config));
+        assert!(editor.turn(-i16::MAX, &config));
+        assert_eq!(editor.brightness, 0);
+    }
+}
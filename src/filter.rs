@@ -0,0 +1,310 @@
+//! The filter chain between the sensor and processing: up to
+//! [`MAX_STAGES`] median, notch and moving-average stages in any order,
+//! each switched on or off and tuned from the console and kept in flash.
+//!
+//! [`Chain`] describes the stages; [`Filters`] runs them over samples taken
+//! at one rate, and is rebuilt from the chain when that or the rate
+//! changes.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::notch::{Mains, Notch};
+
+pub const MAX_STAGES: usize = 4;
+pub const ENCODED_SIZE: usize = 32;
+/// Longest median window, odd so the median is a reading.
+pub const MAX_MEDIAN_WINDOW: u8 = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Stage {
+    /// Median of the last `window` readings, for single-sample spikes.
+    Median { window: u8 },
+    /// Mains pickup.
+    Notch(Mains),
+    /// Exponential moving average giving each reading `weight_percent`.
+    Average { weight_percent: u8 },
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Median { .. } => "median",
+            Stage::Notch(_) => "notch",
+            Stage::Average { .. } => "ema",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct ChainStage {
+    pub stage: Stage,
+    pub enabled: bool,
+}
+
+/// The stages in order, packed at the front.
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Chain {
+    stages: [Option<ChainStage>; MAX_STAGES],
+}
+
+impl Chain {
+    /// Median, notch and average, all off, so readings pass untouched until
+    /// one is switched on.
+    pub const DEFAULT: Self = Self {
+        stages: [
+            Some(ChainStage {
+                stage: Stage::Median { window: 5 },
+                enabled: false,
+            }),
+            Some(ChainStage {
+                stage: Stage::Notch(Mains {
+                    frequency_hz: 50,
+                    harmonics: false,
+                }),
+                enabled: false,
+            }),
+            Some(ChainStage {
+                stage: Stage::Average { weight_percent: 20 },
+                enabled: false,
+            }),
+            None,
+        ],
+    };
+
+    pub fn stages(&self) -> impl Iterator<Item = &ChainStage> {
+        self.stages.iter().map_while(Option::as_ref)
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages[0].is_none()
+    }
+
+    /// Puts `stage`, switched on, at `index`, replacing the stage there or
+    /// adding one at the end. `false` if `index` is beyond that.
+    pub fn set(&mut self, index: usize, stage: Stage) -> bool {
+        if index > self.len() || index >= MAX_STAGES {
+            return false;
+        }
+        self.stages[index] = Some(ChainStage {
+            stage,
+            enabled: true,
+        });
+        true
+    }
+
+    /// `false` if there is no stage at `index`.
+    pub fn enable(&mut self, index: usize, enabled: bool) -> bool {
+        match self.stages.get_mut(index).and_then(Option::as_mut) {
+            Some(stage) => {
+                stage.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes out the stage at `index`, moving those after it up.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        self.stages[index..].rotate_left(1);
+        self.stages[MAX_STAGES - 1] = None;
+        true
+    }
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The enabled stages of a [`Chain`], with their state.
+pub struct Filters {
+    stages: [Option<Filter>; MAX_STAGES],
+}
+
+enum Filter {
+    Median(Median),
+    Notch(Notch),
+    Average(Average),
+}
+
+impl Filters {
+    pub fn new(chain: &Chain, rate_hz: f32) -> Self {
+        let mut filters = Self {
+            stages: [const { None }; MAX_STAGES],
+        };
+        let enabled = chain.stages().filter(|s| s.enabled);
+        for (slot, stage) in filters.stages.iter_mut().zip(enabled) {
+            *slot = Some(match stage.stage {
+                Stage::Median { window } => Filter::Median(Median::new(window)),
+                Stage::Notch(mains) => Filter::Notch(Notch::new(mains, rate_hz)),
+                Stage::Average { weight_percent } => Filter::Average(Average::new(weight_percent)),
+            });
+        }
+        filters
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages[0].is_none()
+    }
+
+    pub fn apply(&mut self, voltage_mv: u32) -> u32 {
+        self.stages
+            .iter_mut()
+            .map_while(Option::as_mut)
+            .fold(voltage_mv, |mv, filter| match filter {
+                Filter::Median(median) => median.apply(mv),
+                Filter::Notch(notch) => notch.apply(mv),
+                Filter::Average(average) => average.apply(mv),
+            })
+    }
+}
+
+struct Median {
+    recent: [u32; MAX_MEDIAN_WINDOW as usize],
+    window: usize,
+    next: usize,
+    len: usize,
+}
+
+impl Median {
+    fn new(window: u8) -> Self {
+        Self {
+            recent: [0; MAX_MEDIAN_WINDOW as usize],
+            window: window.clamp(1, MAX_MEDIAN_WINDOW) as usize,
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Of what there is until the window fills.
+    fn apply(&mut self, voltage_mv: u32) -> u32 {
+        self.recent[self.next] = voltage_mv;
+        self.next = (self.next + 1) % self.window;
+        self.len = (self.len + 1).min(self.window);
+        let mut sorted = self.recent;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+        sorted[self.len / 2]
+    }
+}
+
+struct Average {
+    weight: f32,
+    value: Option<f32>,
+}
+
+impl Average {
+    fn new(weight_percent: u8) -> Self {
+        Self {
+            weight: weight_percent.clamp(1, 100) as f32 / 100.0,
+            value: None,
+        }
+    }
+
+    /// Starts from the first reading rather than from zero.
+    fn apply(&mut self, voltage_mv: u32) -> u32 {
+        let x = voltage_mv as f32;
+        let value = match self.value {
+            Some(value) => value + self.weight * (x - value),
+            None => x,
+        };
+        self.value = Some(value);
+        (value + 0.5) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(chain: &Chain) -> Filters {
+        Filters::new(chain, 100.0)
+    }
+
+    #[test]
+    fn default_chain_passes_readings() {
+        let mut filters = build(&Chain::default());
+        assert!(filters.is_empty());
+        assert_eq!(filters.apply(1234), 1234);
+    }
+
+    #[test]
+    fn median_removes_spikes() {
+        let mut chain = Chain::default();
+        assert!(chain.enable(0, true));
+        let mut filters = build(&chain);
+        let out: [u32; 6] = [1000, 1000, 3300, 1000, 1000, 1010].map(|mv| filters.apply(mv));
+        assert_eq!(out, [1000, 1000, 1000, 1000, 1000, 1000]);
+    }
+
+    #[test]
+    fn average_follows_a_step() {
+        let mut chain = Chain::default();
+        assert!(chain.set(2, Stage::Average { weight_percent: 50 }));
+        let mut filters = build(&chain);
+        let out = [1000, 2000, 2000, 2000].map(|mv| filters.apply(mv));
+        assert_eq!(out, [1000, 1500, 1750, 1875]);
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        // A spike ahead of the average is taken out by the median first;
+        // the other way round it is smeared before the median sees it
+        let spike = [1000, 1000, 1000, 3000, 1000, 1000];
+        let mut chain = Chain::default();
+        chain.enable(0, true);
+        chain.set(2, Stage::Average { weight_percent: 50 });
+        let mut filters = build(&chain);
+        assert!(spike.iter().all(|&mv| filters.apply(mv) == 1000));
+
+        chain.remove(0);
+        chain.set(2, Stage::Median { window: 3 });
+        assert_eq!(
+            chain.stages().map(|s| s.stage.name()).last(),
+            Some("median")
+        );
+        let mut filters = build(&chain);
+        assert!(spike.iter().any(|&mv| filters.apply(mv) > 1000));
+    }
+
+    #[test]
+    fn editing() {
+        let mut chain = Chain::default();
+        assert_eq!(chain.len(), 3);
+        assert!(chain.set(3, Stage::Median { window: 3 }));
+        assert!(!chain.set(4, Stage::Median { window: 3 }));
+        assert!(!chain.enable(4, true));
+        assert!(chain.remove(0));
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.stages().next().unwrap().stage.name(), "notch");
+        while !chain.is_empty() {
+            chain.remove(0);
+        }
+        assert!(!chain.set(1, Stage::Median { window: 3 }));
+        assert!(chain.set(0, Stage::Median { window: 3 }));
+    }
+
+    #[test]
+    fn largest_chain_fits_its_record() {
+        let mut chain = Chain::default();
+        let mains = Mains {
+            frequency_hz: 60,
+            harmonics: true,
+        };
+        for index in 0..MAX_STAGES {
+            assert!(chain.set(index, Stage::Notch(mains)));
+        }
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = postcard::to_slice(&chain, &mut buf).unwrap();
+        assert_eq!(postcard::from_bytes::<Chain>(encoded).unwrap(), chain);
+    }
+}
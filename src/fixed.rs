@@ -0,0 +1,192 @@
+//! Q16.16 fixed-point versions of the per-sample arithmetic: the ADC count
+//! to millivolts, calibration to millitesla and the colour gradient. The
+//! ESP32-C3 and C6 have no FPU, so every `f32` operation there is a call
+//! into soft-float; with the `fixed-point` feature the functions in
+//! `burst`, `schema` and `color` use these instead. The tests check they
+//! agree with the `f32` versions.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use defmt::Format;
+
+use crate::color::RGB8;
+use crate::schema::Config;
+
+const FRAC_BITS: u32 = 16;
+
+/// A signed number with 16 integer and 16 fractional bits: about ±32768 in
+/// steps of 1/65536.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Format)]
+pub struct Q16(pub i32);
+
+impl Q16 {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << FRAC_BITS);
+
+    pub const fn from_int(n: i32) -> Self {
+        Self(n << FRAC_BITS)
+    }
+
+    /// `num / den`, rounded towards zero.
+    pub const fn ratio(num: i32, den: i32) -> Self {
+        Self((((num as i64) << FRAC_BITS) / den as i64) as i32)
+    }
+
+    /// For configuration, which is stored as `f32`; saturates out of range.
+    pub fn from_f32(x: f32) -> Self {
+        Self((x * (1 << FRAC_BITS) as f32) as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1 << FRAC_BITS) as f32
+    }
+
+    /// The integer part, rounded towards zero.
+    pub const fn to_int(self) -> i32 {
+        self.0 / (1 << FRAC_BITS)
+    }
+
+    pub const fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+}
+
+impl Add for Q16 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Q16 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Q16 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as i64 * rhs.0 as i64) >> FRAC_BITS) as i32)
+    }
+}
+
+/// Saturates rather than panicking on division by zero.
+impl Div for Q16 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            return Self(if self.0 < 0 { i32::MIN } else { i32::MAX });
+        }
+        Self((((self.0 as i64) << FRAC_BITS) / rhs.0 as i64) as i32)
+    }
+}
+
+impl Neg for Q16 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+/// The voltage for a 12-bit count, in integers alone.
+pub fn voltage_mv(raw: u16) -> u32 {
+    raw as u32 * 3300 / 4095
+}
+
+/// The field for `voltage_mv` with `config`'s calibration.
+pub fn field_mt(voltage_mv: u32, config: &Config) -> Q16 {
+    Q16::from_int(voltage_mv as i32 - config.zero_field_mv as i32)
+        / Q16::from_f32(config.sensitivity_mv_per_mt)
+}
+
+/// Red at `min_voltage_mv` through to blue at `max_voltage_mv`.
+pub fn voltage_to_color(voltage_mv: u32, config: &Config) -> RGB8 {
+    let (min, max) = (config.min_voltage_mv, config.max_voltage_mv);
+    let t = if voltage_mv <= min {
+        Q16::ZERO
+    } else if voltage_mv >= max {
+        Q16::ONE
+    } else {
+        Q16::ratio((voltage_mv - min) as i32, (max - min) as i32)
+    };
+    let full = Q16::from_int(255);
+    RGB8::new(
+        (full * (Q16::ONE - t)).to_int() as u8,
+        0,
+        (full * t).to_int() as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use crate::{burst, color};
+
+    #[test]
+    fn arithmetic() {
+        let a = Q16::from_f32(2.5);
+        let b = Q16::from_int(-4);
+        assert_eq!((a * b).to_f32(), -10.0);
+        assert_eq!((b / a).to_f32(), -1.5999908);
+        assert_eq!((a + b).to_int(), -1);
+        assert_eq!((a - b).to_f32(), 6.5);
+        assert_eq!((-a).abs(), a);
+        assert_eq!(Q16::ratio(1, 4), Q16::from_f32(0.25));
+        assert_eq!(Q16::ONE / Q16::ZERO, Q16(i32::MAX));
+    }
+
+    #[test]
+    fn voltage_matches_f32() {
+        for raw in 0..=4095 {
+            let (fixed, float) = (voltage_mv(raw), burst::voltage_mv(raw));
+            assert!(fixed.abs_diff(float) <= 1, "{raw}: {fixed} vs {float}");
+        }
+    }
+
+    #[test]
+    fn field_matches_f32() {
+        for sensitivity_mv_per_mt in [1.3, 14.0, 31.25, 100.0] {
+            let config = Config {
+                sensitivity_mv_per_mt,
+                ..Config::default()
+            };
+            for voltage_mv in (0..=3300).step_by(7) {
+                let fixed = field_mt(voltage_mv, &config).to_f32();
+                let float = config.field_mt(voltage_mv);
+                // The sensitivity's rounding to 1/65536 dominates, relative
+                // to the field
+                let tolerance = 1e-3 + float.abs() * 2e-5;
+                assert!(
+                    (fixed - float).abs() <= tolerance,
+                    "{voltage_mv}: {fixed} vs {float}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn color_matches_f32() {
+        for (min_voltage_mv, max_voltage_mv) in [(500, 2800), (0, 3300), (1600, 1700)] {
+            let config = Config {
+                min_voltage_mv,
+                max_voltage_mv,
+                ..Config::default()
+            };
+            for voltage_mv in 0..=3300 {
+                let fixed = voltage_to_color(voltage_mv, &config);
+                let float = color::voltage_to_color(voltage_mv, &config, Palette::RedBlue);
+                assert!(fixed.r.abs_diff(float.r) <= 1, "{voltage_mv}");
+                assert!(fixed.b.abs_diff(float.b) <= 1, "{voltage_mv}");
+                assert_eq!(fixed.g, 0);
+            }
+        }
+    }
+}
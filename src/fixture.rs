@@ -0,0 +1,346 @@
+//! Production testing of magnet assemblies: the field measured over a set
+//! time, judged against stored limits, and written up as one JSON line
+//! for the test station to parse. The limits are signed, so a magnet put
+//! in the wrong way round fails as well as a weak one. A magnet array is
+//! scanned a position at a time, and passes only if every position does.
+
+use core::fmt::{self, Write};
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Limits {
+    /// The mean field passes from `low_mt` to `high_mt`.
+    pub low_mt: f32,
+    pub high_mt: f32,
+    /// Widest spread from lowest to highest reading that passes, as from a
+    /// loose magnet or a noisy fixture.
+    pub spread_mt: f32,
+    pub measure_ms: u32,
+}
+
+impl Limits {
+    pub const DEFAULT: Self = Self {
+        low_mt: 10.0,
+        high_mt: 30.0,
+        spread_mt: 1.0,
+        measure_ms: 500,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Verdict {
+    Pass,
+    Low,
+    High,
+    Noisy,
+    /// No valid readings, or some invalid ones among them.
+    Sensor,
+}
+
+impl Verdict {
+    pub fn is_pass(&self) -> bool {
+        *self == Verdict::Pass
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Pass => "pass",
+            Verdict::Low => "low",
+            Verdict::High => "high",
+            Verdict::Noisy => "noisy",
+            Verdict::Sensor => "sensor",
+        }
+    }
+}
+
+/// The readings over one test, without keeping them.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Measurement {
+    count: u32,
+    invalid: u32,
+    sum_mt: f32,
+    min_mt: f32,
+    max_mt: f32,
+}
+
+impl Measurement {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            invalid: 0,
+            sum_mt: 0.0,
+            min_mt: f32::INFINITY,
+            max_mt: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Takes a reading, `None` if it is not valid.
+    pub fn push(&mut self, field_mt: Option<f32>) {
+        let Some(field_mt) = field_mt else {
+            self.invalid += 1;
+            return;
+        };
+        self.count += 1;
+        self.sum_mt += field_mt;
+        self.min_mt = self.min_mt.min(field_mt);
+        self.max_mt = self.max_mt.max(field_mt);
+    }
+
+    /// Valid readings taken.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean_mt(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.sum_mt / self.count as f32)
+    }
+
+    pub fn spread_mt(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.max_mt - self.min_mt)
+    }
+
+    pub fn verdict(&self, limits: &Limits) -> Verdict {
+        let (Some(mean_mt), Some(spread_mt)) = (self.mean_mt(), self.spread_mt()) else {
+            return Verdict::Sensor;
+        };
+        if self.invalid > 0 {
+            Verdict::Sensor
+        } else if mean_mt < limits.low_mt {
+            Verdict::Low
+        } else if mean_mt > limits.high_mt {
+            Verdict::High
+        } else if spread_mt > limits.spread_mt {
+            Verdict::Noisy
+        } else {
+            Verdict::Pass
+        }
+    }
+}
+
+impl Default for Measurement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes a test's result as one line of JSON, e.g.
+/// `{"unit":7,"result":"fail","reason":"low","mean_mt":9.112,...}`.
+pub fn write_record<W: Write>(
+    w: &mut W,
+    unit: u32,
+    measurement: &Measurement,
+    limits: &Limits,
+) -> fmt::Result {
+    write!(w, "{{\"unit\":{},", unit)?;
+    write_result(w, measurement, limits)?;
+    write_limits(w, limits)
+}
+
+/// Positions scanned in one pass, at most, one per mux channel.
+pub const MAX_POSITIONS: usize = 16;
+
+/// The measurements of a magnet array, one per position.
+pub struct Scan {
+    measurements: [Measurement; MAX_POSITIONS],
+    len: u8,
+}
+
+impl Scan {
+    pub const fn new() -> Self {
+        Self {
+            measurements: [Measurement::new(); MAX_POSITIONS],
+            len: 0,
+        }
+    }
+
+    /// Adds the next position's measurement, unless all are in.
+    pub fn push(&mut self, measurement: Measurement) {
+        if let Some(m) = self.measurements.get_mut(self.len as usize) {
+            *m = measurement;
+            self.len += 1;
+        }
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements[..self.len as usize]
+    }
+
+    /// The first failing position's verdict, so the array passes only if
+    /// every position does.
+    pub fn verdict(&self, limits: &Limits) -> Verdict {
+        if self.len == 0 {
+            return Verdict::Sensor;
+        }
+        self.measurements()
+            .iter()
+            .map(|m| m.verdict(limits))
+            .find(|verdict| !verdict.is_pass())
+            .unwrap_or(Verdict::Pass)
+    }
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes a scan's result as one line of JSON: the array's result, the
+/// positions that failed, counting from 0, and each position's result as
+/// for a single test.
+pub fn write_scan_record<W: Write>(
+    w: &mut W,
+    unit: u32,
+    scan: &Scan,
+    limits: &Limits,
+) -> fmt::Result {
+    let pass = scan.verdict(limits).is_pass();
+    write!(
+        w,
+        "{{\"unit\":{},\"result\":\"{}\",\"failed\":[",
+        unit,
+        if pass { "pass" } else { "fail" }
+    )?;
+    let failed = scan
+        .measurements()
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| !m.verdict(limits).is_pass());
+    for (i, (position, _)) in failed.enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        write!(w, "{}", position)?;
+    }
+    w.write_str("],\"positions\":[")?;
+    for (position, measurement) in scan.measurements().iter().enumerate() {
+        if position > 0 {
+            w.write_char(',')?;
+        }
+        write!(w, "{{\"position\":{},", position)?;
+        write_result(w, measurement, limits)?;
+        w.write_char('}')?;
+    }
+    w.write_str("],")?;
+    write_limits(w, limits)
+}
+
+fn write_result<W: Write>(w: &mut W, measurement: &Measurement, limits: &Limits) -> fmt::Result {
+    let verdict = measurement.verdict(limits);
+    write!(
+        w,
+        "\"result\":\"{}\",\"reason\":",
+        if verdict.is_pass() { "pass" } else { "fail" }
+    )?;
+    match verdict {
+        Verdict::Pass => write!(w, "null")?,
+        _ => write!(w, "\"{}\"", verdict.name())?,
+    }
+    match measurement.mean_mt() {
+        Some(mean_mt) => write!(
+            w,
+            ",\"mean_mt\":{:.3},\"min_mt\":{:.3},\"max_mt\":{:.3}",
+            mean_mt, measurement.min_mt, measurement.max_mt
+        )?,
+        None => write!(w, ",\"mean_mt\":null,\"min_mt\":null,\"max_mt\":null")?,
+    }
+    write!(
+        w,
+        ",\"readings\":{},\"invalid\":{}",
+        measurement.count, measurement.invalid
+    )
+}
+
+/// The limits, closing the record and its line.
+fn write_limits<W: Write>(w: &mut W, limits: &Limits) -> fmt::Result {
+    writeln!(
+        w,
+        ",\"limits\":{{\"low_mt\":{},\"high_mt\":{},\"spread_mt\":{}}}}}",
+        limits.low_mt, limits.high_mt, limits.spread_mt
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(fields: &[f32]) -> Measurement {
+        let mut measurement = Measurement::new();
+        for &field_mt in fields {
+            measurement.push(Some(field_mt));
+        }
+        measurement
+    }
+
+    #[test]
+    fn judges_the_mean_then_the_spread() {
+        let limits = Limits::DEFAULT;
+        assert_eq!(measured(&[20.0, 20.5]).verdict(&limits), Verdict::Pass);
+        assert_eq!(measured(&[9.0, 9.5]).verdict(&limits), Verdict::Low);
+        // The wrong way round
+        assert_eq!(measured(&[-20.0]).verdict(&limits), Verdict::Low);
+        assert_eq!(measured(&[31.0]).verdict(&limits), Verdict::High);
+        assert_eq!(measured(&[19.0, 21.0]).verdict(&limits), Verdict::Noisy);
+        assert_eq!(Measurement::new().verdict(&limits), Verdict::Sensor);
+
+        let mut measurement = measured(&[20.0]);
+        measurement.push(None);
+        assert_eq!(measurement.verdict(&limits), Verdict::Sensor);
+    }
+
+    #[test]
+    fn records_are_one_line_of_json() {
+        let mut record = String::new();
+        write_record(&mut record, 7, &measured(&[20.0, 20.5]), &Limits::DEFAULT).unwrap();
+        assert_eq!(
+            record,
+            "{\"unit\":7,\"result\":\"pass\",\"reason\":null,\"mean_mt\":20.250,\
+             \"min_mt\":20.000,\"max_mt\":20.500,\"readings\":2,\"invalid\":0,\
+             \"limits\":{\"low_mt\":10,\"high_mt\":30,\"spread_mt\":1}}\n"
+        );
+
+        record.clear();
+        write_record(&mut record, 8, &Measurement::new(), &Limits::DEFAULT).unwrap();
+        assert!(
+            record.starts_with(
+                "{\"unit\":8,\"result\":\"fail\",\"reason\":\"sensor\",\"mean_mt\":null"
+            )
+        );
+    }
+
+    #[test]
+    fn scans_pass_only_if_every_position_does() {
+        let limits = Limits::DEFAULT;
+        let mut scan = Scan::new();
+        assert_eq!(scan.verdict(&limits), Verdict::Sensor);
+        scan.push(measured(&[20.0]));
+        scan.push(measured(&[35.0]));
+        scan.push(measured(&[5.0]));
+        assert_eq!(scan.verdict(&limits), Verdict::High);
+
+        let mut record = String::new();
+        write_scan_record(&mut record, 3, &scan, &limits).unwrap();
+        assert!(record.starts_with(
+            "{\"unit\":3,\"result\":\"fail\",\"failed\":[1,2],\"positions\":[\
+             {\"position\":0,\"result\":\"pass\",\"reason\":null,\"mean_mt\":20.000,"
+        ));
+        assert!(record.contains("{\"position\":2,\"result\":\"fail\",\"reason\":\"low\""));
+        assert!(record.ends_with("\"spread_mt\":1}}\n"));
+        assert_eq!(record.lines().count(), 1);
+
+        for _ in 0..MAX_POSITIONS {
+            scan.push(measured(&[20.0]));
+        }
+        assert_eq!(scan.measurements().len(), MAX_POSITIONS);
+    }
+}
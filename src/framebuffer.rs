@@ -0,0 +1,88 @@
+//! Monochrome frame buffer in the page layout used by SSD1306-style
+//! controllers: each byte is eight vertical pixels, least significant bit at
+//! the top, and each page is one such row of bytes across the display.
+
+use crate::font::{self, ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+pub struct FrameBuffer<const WIDTH: usize, const PAGES: usize> {
+    pages: [[u8; WIDTH]; PAGES],
+}
+
+impl<const WIDTH: usize, const PAGES: usize> FrameBuffer<WIDTH, PAGES> {
+    pub const HEIGHT: usize = PAGES * 8;
+
+    pub const fn new() -> Self {
+        Self {
+            pages: [[0; WIDTH]; PAGES],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages = [[0; WIDTH]; PAGES];
+    }
+
+    pub fn pages(&self) -> &[[u8; WIDTH]; PAGES] {
+        &self.pages
+    }
+
+    /// Pixels outside the buffer are ignored.
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        if x >= WIDTH || y >= Self::HEIGHT {
+            return;
+        }
+        let bit = 1 << (y % 8);
+        if on {
+            self.pages[y / 8][x] |= bit;
+        } else {
+            self.pages[y / 8][x] &= !bit;
+        }
+    }
+
+    /// Pixels outside the buffer read as off.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < WIDTH && y < Self::HEIGHT && self.pages[y / 8][x] & (1 << (y % 8)) != 0
+    }
+
+    /// Vertical line covering `y0` to `y1` inclusive, in either order.
+    pub fn vline(&mut self, x: usize, y0: usize, y1: usize) {
+        for y in y0.min(y1)..=y0.max(y1) {
+            self.set(x, y, true);
+        }
+    }
+
+    pub fn hline(&mut self, x0: usize, x1: usize, y: usize) {
+        for x in x0.min(x1)..=x0.max(x1) {
+            self.set(x, y, true);
+        }
+    }
+
+    /// Draws `text` with its top-left corner at (`x`, `y`), each font pixel
+    /// `scale` pixels square. Returns the x just past the last character.
+    pub fn text(&mut self, x: usize, y: usize, text: &str, scale: usize) -> usize {
+        let mut x = x;
+        for c in text.chars() {
+            for (col, bits) in font::glyph(c).into_iter().enumerate() {
+                for row in (0..GLYPH_HEIGHT).filter(|row| bits & (1 << row) != 0) {
+                    for dx in 0..scale {
+                        for dy in 0..scale {
+                            self.set(x + col * scale + dx, y + row * scale + dy, true);
+                        }
+                    }
+                }
+            }
+            x += ADVANCE * scale;
+        }
+        x
+    }
+
+    /// Width in pixels that [`text`](Self::text) would use.
+    pub fn text_width(text: &str, scale: usize) -> usize {
+        (text.chars().count() * ADVANCE).saturating_sub(ADVANCE - GLYPH_WIDTH) * scale
+    }
+}
+
+impl<const WIDTH: usize, const PAGES: usize> Default for FrameBuffer<WIDTH, PAGES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
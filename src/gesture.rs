@@ -0,0 +1,167 @@
+//! Gestures made by moving a magnet past the sensor.
+//!
+//! A gesture is built from excursions: stretches where the field is strong
+//! enough to mean a magnet is close, ending once it has been weak for a
+//! moment. A short excursion of one polarity is a tap; one that shows both
+//! poles, as a magnet carried past with its axis along the motion does, is a
+//! swipe, and the order of the poles gives its direction. Excursions longer
+//! than a gesture, such as a magnet left in place, are ignored.
+
+use defmt::Format;
+
+// Field strength, either polarity, that counts as a magnet being close
+const ACTIVE_MT: f32 = 5.0;
+
+// How long the field must stay weak for an excursion to end
+const SETTLE_US: u64 = 60_000;
+
+const TAP_MAX_US: u64 = 400_000;
+const SWIPE_MAX_US: u64 = 1_000_000;
+// Longest pause between the two taps of a double tap
+const DOUBLE_TAP_GAP_US: u64 = 400_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Gesture {
+    Tap,
+    DoubleTap,
+    /// `north_first` is set when the north pole passed the sensor first.
+    Swipe {
+        north_first: bool,
+    },
+}
+
+struct Excursion {
+    start_us: u64,
+    last_active_us: u64,
+    first_north: bool,
+    both_poles: bool,
+}
+
+pub struct GestureDetector {
+    excursion: Option<Excursion>,
+    // End of a tap that may yet become a double tap
+    pending_tap_us: Option<u64>,
+}
+
+impl GestureDetector {
+    pub const fn new() -> Self {
+        Self {
+            excursion: None,
+            pending_tap_us: None,
+        }
+    }
+
+    /// Feeds one valid reading. A single tap is only reported once the
+    /// double-tap window has passed.
+    pub fn update(&mut self, timestamp_us: u64, field_mt: f32) -> Option<Gesture> {
+        let active = field_mt.abs() >= ACTIVE_MT;
+        // A negative field is a north pole
+        let north = field_mt < 0.0;
+
+        let Some(excursion) = self.excursion.as_mut() else {
+            if active {
+                self.excursion = Some(Excursion {
+                    start_us: timestamp_us,
+                    last_active_us: timestamp_us,
+                    first_north: north,
+                    both_poles: false,
+                });
+                return None;
+            }
+            return self
+                .pending_tap_us
+                .take_if(|end_us| timestamp_us - *end_us >= DOUBLE_TAP_GAP_US)
+                .map(|_| Gesture::Tap);
+        };
+
+        if active {
+            excursion.last_active_us = timestamp_us;
+            excursion.both_poles |= north != excursion.first_north;
+            return None;
+        }
+        if timestamp_us - excursion.last_active_us < SETTLE_US {
+            return None;
+        }
+
+        let duration_us = excursion.last_active_us - excursion.start_us;
+        let end_us = excursion.last_active_us;
+        let (first_north, both_poles) = (excursion.first_north, excursion.both_poles);
+        self.excursion = None;
+
+        let pending = self.pending_tap_us.take();
+        if both_poles && duration_us <= SWIPE_MAX_US {
+            Some(Gesture::Swipe {
+                north_first: first_north,
+            })
+        } else if !both_poles && duration_us <= TAP_MAX_US {
+            if pending.is_some() {
+                Some(Gesture::DoubleTap)
+            } else {
+                self.pending_tap_us = Some(end_us);
+                None
+            }
+        } else {
+            // Too long for a gesture, but an earlier tap still counts
+            pending.map(|_| Gesture::Tap)
+        }
+    }
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Readings every 10ms: each stretch is (duration in ms, field in mT),
+    // followed by a second of quiet
+    fn gestures(stretches: &[(u64, f32)]) -> Vec<Gesture> {
+        let mut detector = GestureDetector::new();
+        let mut timestamp_us = 0;
+        let mut out = Vec::new();
+        for &(duration_ms, field_mt) in stretches.iter().chain([&(1000, 0.0)]) {
+            for _ in 0..duration_ms / 10 {
+                out.extend(detector.update(timestamp_us, field_mt));
+                timestamp_us += 10_000;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn taps() {
+        assert_eq!(gestures(&[(100, 10.0)]), [Gesture::Tap]);
+        assert_eq!(
+            gestures(&[(100, 10.0), (100, 0.0), (100, -10.0)]),
+            [Gesture::DoubleTap]
+        );
+        // Too far apart for a double tap
+        assert_eq!(
+            gestures(&[(100, 10.0), (600, 0.0), (100, 10.0)]),
+            [Gesture::Tap, Gesture::Tap]
+        );
+    }
+
+    #[test]
+    fn swipes_in_either_direction() {
+        assert_eq!(
+            gestures(&[(100, -10.0), (100, 10.0)]),
+            [Gesture::Swipe { north_first: true }]
+        );
+        assert_eq!(
+            gestures(&[(100, 10.0), (100, -10.0)]),
+            [Gesture::Swipe { north_first: false }]
+        );
+    }
+
+    #[test]
+    fn ignores_a_magnet_left_in_place() {
+        assert!(gestures(&[(2000, 10.0)]).is_empty());
+        // A flicker too brief to end the excursion
+        assert!(gestures(&[(1000, 10.0), (30, 0.0), (1000, 10.0)]).is_empty());
+    }
+}
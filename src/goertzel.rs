@@ -0,0 +1,210 @@
+//! Goertzel detector: the amplitude and phase of one known frequency, such
+//! as a magnet spinning at a fixed rate, taken a sample at a time without
+//! the buffers or the work of a full spectrum. Each sample costs three
+//! multiplications, against the FFT's log2(N) butterflies per point.
+//!
+//! The mean of each block is taken out at the end, by running the same
+//! recurrence over ones, so the sensor's offset does not leak into a
+//! frequency that falls between FFT bins.
+
+use core::f32::consts::{FRAC_PI_2, PI};
+
+use defmt::Format;
+
+use crate::spectrum::{sin_cos, sqrt};
+
+/// Longest block, beyond which the recurrence's rounding in `f32` starts to
+/// show at low frequencies.
+pub const MAX_POINTS: u32 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Tone {
+    pub frequency_hz: f32,
+    /// Of the sinusoid at `frequency_hz`, in millivolts.
+    pub amplitude_mv: f32,
+    /// Of that sinusoid as a cosine at the block's first sample, from -180
+    /// to 180 degrees.
+    pub phase_deg: f32,
+}
+
+/// Measures one frequency over consecutive blocks of `points` samples.
+/// The amplitude is exact when a block holds a whole number of cycles, and
+/// falls off to 64% half a cycle either side.
+#[derive(Clone, Copy)]
+pub struct Goertzel {
+    frequency_hz: f32,
+    points: u32,
+    /// 2cos(w), the recurrence's one multiplier, for w radians per sample.
+    coeff: f32,
+    /// cos(w) and sin(w), to finish a block.
+    step: (f32, f32),
+    /// cos and sin of w(N - 1), to refer the phase back to the first sample.
+    turn: (f32, f32),
+    count: u32,
+    /// The last two states over the samples, and over ones.
+    samples: (f32, f32),
+    ones: (f32, f32),
+    sum: f32,
+}
+
+impl Goertzel {
+    /// `None` unless `frequency_hz` is under half of `rate_hz` and `points`
+    /// is from 2 to [`MAX_POINTS`].
+    pub fn new(frequency_hz: f32, rate_hz: f32, points: u32) -> Option<Self> {
+        let valid = frequency_hz > 0.0
+            && frequency_hz < rate_hz / 2.0
+            && (2..=MAX_POINTS).contains(&points);
+        if !valid {
+            return None;
+        }
+        let turns = frequency_hz / rate_hz;
+        let (sin, cos) = sin_cos(turns);
+        let (turn_sin, turn_cos) = sin_cos(turns * (points - 1) as f32 % 1.0);
+        Some(Self {
+            frequency_hz,
+            points,
+            coeff: 2.0 * cos,
+            step: (cos, sin),
+            turn: (turn_cos, turn_sin),
+            count: 0,
+            samples: (0.0, 0.0),
+            ones: (0.0, 0.0),
+            sum: 0.0,
+        })
+    }
+
+    pub fn frequency_hz(&self) -> f32 {
+        self.frequency_hz
+    }
+
+    pub fn points(&self) -> u32 {
+        self.points
+    }
+
+    /// Whether the next sample starts a block.
+    pub fn at_start(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Takes a sample, returning the block's tone once it is complete.
+    pub fn push(&mut self, voltage_mv: f32) -> Option<Tone> {
+        let (s1, s2) = self.samples;
+        self.samples = (voltage_mv + self.coeff * s1 - s2, s1);
+        let (u1, u2) = self.ones;
+        self.ones = (1.0 + self.coeff * u1 - u2, u1);
+        self.sum += voltage_mv;
+        self.count += 1;
+        if self.count < self.points {
+            return None;
+        }
+
+        let n = self.points as f32;
+        let mean = self.sum / n;
+        let s1 = self.samples.0 - mean * self.ones.0;
+        let s2 = self.samples.1 - mean * self.ones.1;
+        // The last output of the filter, then turned back by w(N - 1) to
+        // the DFT term of the block
+        let (cos, sin) = self.step;
+        let (re, im) = (s1 - cos * s2, sin * s2);
+        let (turn_cos, turn_sin) = self.turn;
+        let (re, im) = (re * turn_cos + im * turn_sin, im * turn_cos - re * turn_sin);
+
+        self.count = 0;
+        self.samples = (0.0, 0.0);
+        self.ones = (0.0, 0.0);
+        self.sum = 0.0;
+        Some(Tone {
+            frequency_hz: self.frequency_hz,
+            amplitude_mv: 2.0 * sqrt(re * re + im * im) / n,
+            phase_deg: atan2(im, re) * 180.0 / PI,
+        })
+    }
+}
+
+/// The angle of `(x, y)` in radians, halved into the eighth of a turn
+/// where a short Taylor series holds.
+pub(crate) fn atan2(y: f32, x: f32) -> f32 {
+    let (ax, ay) = (x.abs(), y.abs());
+    if ax == 0.0 && ay == 0.0 {
+        return 0.0;
+    }
+    let z = if ay > ax { ax / ay } else { ay / ax };
+    // The tangent of half the angle, at most tan(pi/8)
+    let h = z / (1.0 + sqrt(1.0 + z * z));
+    let h2 = h * h;
+    // h - h^3/3 + h^5/5 - ..., to the h^11 term
+    let mut series = 0.0;
+    for k in (0..6).rev() {
+        series = 1.0 / (2 * k + 1) as f32 - h2 * series;
+    }
+    let mut angle = 2.0 * h * series;
+    if ay > ax {
+        angle = FRAC_PI_2 - angle;
+    }
+    if x < 0.0 {
+        angle = PI - angle;
+    }
+    if y < 0.0 { -angle } else { angle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `amplitude_mv` at `frequency_hz` and `phase_deg` on a 1650mV offset.
+    fn tone(i: u32, rate_hz: f32, frequency_hz: f32, amplitude_mv: f32, phase_deg: f32) -> f32 {
+        let angle = core::f32::consts::TAU * frequency_hz * i as f32 / rate_hz;
+        1650.0 + amplitude_mv * (angle + phase_deg.to_radians()).cos()
+    }
+
+    #[test]
+    fn measures_amplitude_and_phase() {
+        // 10 cycles of a magnet spinning at 50Hz, sampled at 1kHz
+        let mut goertzel = Goertzel::new(50.0, 1000.0, 200).unwrap();
+        for i in 0..199 {
+            assert_eq!(goertzel.push(tone(i, 1000.0, 50.0, 100.0, 30.0)), None);
+        }
+        let found = goertzel.push(tone(199, 1000.0, 50.0, 100.0, 30.0)).unwrap();
+        assert!((found.amplitude_mv - 100.0).abs() < 0.1, "{found:?}");
+        assert!((found.phase_deg - 30.0).abs() < 0.1, "{found:?}");
+        assert!(goertzel.at_start());
+
+        // The next block starts a whole number of cycles later
+        let found = (200..400)
+            .find_map(|i| goertzel.push(tone(i, 1000.0, 50.0, 100.0, -120.0)))
+            .unwrap();
+        assert!((found.phase_deg + 120.0).abs() < 0.1, "{found:?}");
+    }
+
+    #[test]
+    fn ignores_other_frequencies_and_the_offset() {
+        let mut goertzel = Goertzel::new(50.0, 1000.0, 200).unwrap();
+        let found = (0..200)
+            .find_map(|i| goertzel.push(tone(i, 1000.0, 120.0, 500.0, 0.0)))
+            .unwrap();
+        assert!(found.amplitude_mv < 0.5, "{found:?}");
+
+        // Between bins, where leakage of the offset would be at its worst
+        let mut goertzel = Goertzel::new(52.5, 1000.0, 200).unwrap();
+        let found = (0..200).find_map(|_| goertzel.push(1650.0)).unwrap();
+        assert!(found.amplitude_mv < 0.5, "{found:?}");
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_measure() {
+        assert!(Goertzel::new(500.0, 1000.0, 200).is_none());
+        assert!(Goertzel::new(0.0, 1000.0, 200).is_none());
+        assert!(Goertzel::new(50.0, 1000.0, 1).is_none());
+        assert!(Goertzel::new(50.0, 1000.0, MAX_POINTS + 1).is_none());
+    }
+
+    #[test]
+    fn arctangent() {
+        for i in -180..=180 {
+            let angle = (i as f32 * 1.7).to_radians();
+            let (y, x) = (angle.sin() * 3.0, angle.cos() * 3.0);
+            assert!((atan2(y, x) - y.atan2(x)).abs() < 1e-5, "{i}");
+        }
+        assert_eq!(atan2(0.0, 0.0), 0.0);
+    }
+}
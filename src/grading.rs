@@ -0,0 +1,255 @@
+//! Magnet grading for quality control: each magnet is put in the fixture
+//! a few times, at the fixture's set distance from the sensor, and the
+//! peak field of each placement is taken. Their mean grades the magnet,
+//! and their spread says how repeatable the placement is, which is worth
+//! knowing before trusting a grade near a boundary.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+/// Grades, at most, named from A.
+pub const MAX_GRADES: usize = 4;
+
+/// Placements of one magnet, at most.
+pub const MAX_SHOTS: u8 = 16;
+
+pub const ENCODED_SIZE: usize = 32;
+
+/// Lowest peak, either pole, at which a magnet is in the fixture. It is
+/// out again at half this.
+pub const PRESENT_MT: f32 = 2.0;
+
+/// The lowest mean peak for each grade, strongest first. Below the last
+/// is a reject.
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Grades {
+    bounds_mt: [f32; MAX_GRADES],
+    len: u8,
+}
+
+impl Grades {
+    pub const DEFAULT: Self = Self {
+        bounds_mt: [40.0, 30.0, 20.0, 0.0],
+        len: 3,
+    };
+
+    /// From one to [`MAX_GRADES`] bounds. `None` unless they fall and
+    /// are above [`PRESENT_MT`].
+    pub fn new(bounds_mt: &[f32]) -> Option<Self> {
+        let valid = (1..=MAX_GRADES).contains(&bounds_mt.len())
+            && bounds_mt.iter().all(|&b| b >= PRESENT_MT)
+            && bounds_mt.windows(2).all(|w| w[0] > w[1]);
+        valid.then(|| {
+            let mut grades = Self {
+                bounds_mt: [0.0; MAX_GRADES],
+                len: bounds_mt.len() as u8,
+            };
+            grades.bounds_mt[..bounds_mt.len()].copy_from_slice(bounds_mt);
+            grades
+        })
+    }
+
+    /// From bounds in mT as words, e.g. `40 30 20`.
+    pub fn parse<'a>(words: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut bounds_mt = [0.0; MAX_GRADES];
+        let mut len = 0;
+        for word in words {
+            *bounds_mt.get_mut(len)? = word.trim_end_matches("mT").parse().ok()?;
+            len += 1;
+        }
+        Self::new(&bounds_mt[..len])
+    }
+
+    pub fn bounds_mt(&self) -> &[f32] {
+        &self.bounds_mt[..self.len as usize]
+    }
+
+    /// The grade's letter, or `None` for a reject.
+    pub fn grade(&self, peak_mt: f32) -> Option<char> {
+        self.bounds_mt()
+            .iter()
+            .position(|&bound| peak_mt >= bound)
+            .map(|i| (b'A' + i as u8) as char)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// The fixture's gap from magnet to sensor, kept with the results.
+    pub distance_mm: f32,
+    pub shots: u8,
+    pub grades: Grades,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        distance_mm: 5.0,
+        shots: 5,
+        grades: Grades::DEFAULT,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The peak field of each placement.
+pub struct Placement {
+    peak_mt: Option<f32>,
+}
+
+impl Placement {
+    pub const fn new() -> Self {
+        Self { peak_mt: None }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.peak_mt.is_some()
+    }
+
+    /// Takes a valid reading, returning the peak once the magnet is taken
+    /// out again.
+    pub fn update(&mut self, field_mt: f32) -> Option<f32> {
+        let strength_mt = field_mt.abs();
+        match self.peak_mt {
+            None if strength_mt >= PRESENT_MT => {
+                self.peak_mt = Some(strength_mt);
+                None
+            }
+            Some(_) if strength_mt < PRESENT_MT / 2.0 => self.peak_mt.take(),
+            Some(peak_mt) => {
+                self.peak_mt = Some(peak_mt.max(strength_mt));
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The peaks of one magnet's placements so far.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Run {
+    count: u8,
+    mean_mt: f32,
+    /// Sum of squared differences from the mean, as in Welford's method.
+    m2: f32,
+    min_mt: f32,
+    max_mt: f32,
+}
+
+impl Run {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            mean_mt: 0.0,
+            m2: 0.0,
+            min_mt: f32::INFINITY,
+            max_mt: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, peak_mt: f32) {
+        self.count += 1;
+        let delta = peak_mt - self.mean_mt;
+        self.mean_mt += delta / self.count as f32;
+        self.m2 += delta * (peak_mt - self.mean_mt);
+        self.min_mt = self.min_mt.min(peak_mt);
+        self.max_mt = self.max_mt.max(peak_mt);
+    }
+
+    pub fn count(&self) -> u8 {
+        self.count
+    }
+
+    pub fn mean_mt(&self) -> f32 {
+        self.mean_mt
+    }
+
+    pub fn min_mt(&self) -> f32 {
+        self.min_mt
+    }
+
+    pub fn max_mt(&self) -> f32 {
+        self.max_mt
+    }
+
+    /// Of the sample, zero until there are two placements.
+    pub fn std_dev_mt(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        crate::spectrum::sqrt(self.m2 / (self.count - 1) as f32)
+    }
+
+    /// The spread relative to the mean, in percent.
+    pub fn repeatability_pct(&self) -> f32 {
+        if self.mean_mt == 0.0 {
+            return 0.0;
+        }
+        self.std_dev_mt() / self.mean_mt * 100.0
+    }
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placements_give_their_peak_once_out() {
+        let mut placement = Placement::new();
+        assert_eq!(placement.update(1.5), None);
+        assert_eq!(placement.update(-10.0), None);
+        assert!(placement.is_present());
+        assert_eq!(placement.update(-25.0), None);
+        assert_eq!(placement.update(-12.0), None);
+        // Out below half the presence level only
+        assert_eq!(placement.update(-1.5), None);
+        assert_eq!(placement.update(-0.5), Some(25.0));
+        assert!(!placement.is_present());
+    }
+
+    #[test]
+    fn runs_gather_the_peaks() {
+        let mut run = Run::new();
+        for peak_mt in [30.0, 32.0, 34.0] {
+            run.push(peak_mt);
+        }
+        assert_eq!(run.count(), 3);
+        assert!((run.mean_mt() - 32.0).abs() < 1e-4);
+        assert!((run.std_dev_mt() - 2.0).abs() < 1e-3);
+        assert!((run.repeatability_pct() - 6.25).abs() < 1e-2);
+        assert_eq!((run.min_mt(), run.max_mt()), (30.0, 34.0));
+    }
+
+    #[test]
+    fn grades_from_the_strongest() {
+        let grades = Grades::DEFAULT;
+        assert_eq!(grades.grade(45.0), Some('A'));
+        assert_eq!(grades.grade(40.0), Some('A'));
+        assert_eq!(grades.grade(35.0), Some('B'));
+        assert_eq!(grades.grade(20.0), Some('C'));
+        assert_eq!(grades.grade(19.9), None);
+
+        assert_eq!(Grades::new(&[10.0, 20.0]), None);
+        assert_eq!(Grades::new(&[1.0]), None);
+        assert_eq!(Grades::new(&[]), None);
+        let grades = Grades::parse("50 10mT".split(' ')).unwrap();
+        assert_eq!(grades.bounds_mt(), &[50.0, 10.0]);
+        assert_eq!(grades.grade(12.0), Some('B'));
+        assert_eq!(Grades::parse("50 40 30 20 10".split(' ')), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! Haptic feedback for a handheld probe: a buzz on each threshold crossing,
+//! or on each pole found, with a different feel for north and south so the
+//! pole can be told without looking.
+//!
+//! A pole is found when the field grows strong enough on its side, and
+//! again only after the field has fallen back well below that or turned
+//! to the other pole, so a magnet held at the edge buzzes once.
+
+use defmt::Format;
+
+use crate::drv2605::{DOUBLE_CLICK, STRONG_BUZZ, STRONG_CLICK};
+
+/// Field strength, either pole, at which a pole is found.
+pub const DETECT_MT: f32 = 10.0;
+// Fraction of it the field falls below for the pole to be lost
+const RELEASE: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum Trigger {
+    Off,
+    /// Rising threshold crossings.
+    Threshold,
+    /// Each pole found.
+    Pole,
+}
+
+impl Trigger {
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Off, Self::Threshold, Self::Pole]
+            .into_iter()
+            .find(|trigger| trigger.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Threshold => "threshold",
+            Self::Pole => "pole",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum Pole {
+    North,
+    South,
+}
+
+impl Pole {
+    /// Two short clicks for north, one long buzz for south.
+    pub fn effects(self) -> &'static [u8] {
+        match self {
+            Self::North => &[DOUBLE_CLICK],
+            Self::South => &[STRONG_BUZZ],
+        }
+    }
+}
+
+/// The effect for a threshold crossing.
+pub const THRESHOLD_EFFECTS: &[u8] = &[STRONG_CLICK];
+
+pub struct PoleDetector {
+    pole: Option<Pole>,
+}
+
+impl PoleDetector {
+    pub const fn new() -> Self {
+        Self { pole: None }
+    }
+
+    /// Feeds one valid reading, returning the pole if one is newly found.
+    pub fn update(&mut self, field_mt: f32) -> Option<Pole> {
+        // A negative field is a north pole
+        let pole = if field_mt <= -DETECT_MT {
+            Some(Pole::North)
+        } else if field_mt >= DETECT_MT {
+            Some(Pole::South)
+        } else {
+            if field_mt.abs() < DETECT_MT * RELEASE {
+                self.pole = None;
+            }
+            return None;
+        };
+        if pole == self.pole {
+            return None;
+        }
+        self.pole = pole;
+        pole
+    }
+}
+
+impl Default for PoleDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_each_pole_once() {
+        let mut poles = PoleDetector::new();
+        assert_eq!(poles.update(3.0), None);
+        assert_eq!(poles.update(-12.0), Some(Pole::North));
+        // Wavering at the edge
+        assert_eq!(poles.update(-8.0), None);
+        assert_eq!(poles.update(-11.0), None);
+        // Straight over to the other pole
+        assert_eq!(poles.update(15.0), Some(Pole::South));
+        // Away, and back
+        assert_eq!(poles.update(2.0), None);
+        assert_eq!(poles.update(10.0), Some(Pole::South));
+    }
+
+    #[test]
+    fn poles_feel_different() {
+        assert_ne!(Pole::North.effects(), Pole::South.effects());
+        assert_eq!(Trigger::parse("pole"), Some(Trigger::Pole));
+        assert_eq!(Trigger::parse("buzz"), None);
+    }
+}
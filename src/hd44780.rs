@@ -0,0 +1,80 @@
+//! Minimal driver for HD44780 character LCDs behind a PCF8574 I2C backpack,
+//! as on the common 16x2 modules.
+//!
+//! The backpack drives the LCD in 4-bit mode: each byte goes over as two
+//! nibbles on P4-P7, latched by pulsing the enable line.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// Backpack address with A0-A2 open; PCF8574A-based ones are at 0x3f.
+pub const DEFAULT_ADDRESS: u8 = 0x27;
+
+pub const COLUMNS: usize = 16;
+pub const ROWS: usize = 2;
+
+// Backpack pins; RW stays low as the LCD is never read
+const RS: u8 = 1 << 0;
+const EN: u8 = 1 << 2;
+const BACKLIGHT: u8 = 1 << 3;
+
+const CMD_CLEAR: u8 = 0x01;
+const CMD_ENTRY_INCREMENT: u8 = 0x06;
+const CMD_DISPLAY_ON: u8 = 0x0c;
+const CMD_FUNCTION_4BIT_2LINE: u8 = 0x28;
+const CMD_SET_ADDRESS: u8 = 0x80;
+
+// Display RAM address of the start of each row
+const ROW_ADDRESS: [u8; ROWS] = [0x00, 0x40];
+
+pub struct Hd44780<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Hd44780<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    /// Switches the controller to 4-bit mode from whatever state it is in,
+    /// then clears it. Needs 40ms after power-up first.
+    pub fn init(&mut self, delay: &mut impl DelayNs) -> Result<(), I::Error> {
+        for _ in 0..3 {
+            self.write_nibble(0x30, 0)?;
+            delay.delay_ms(5);
+        }
+        self.write_nibble(0x20, 0)?;
+        self.write_byte(CMD_FUNCTION_4BIT_2LINE, 0)?;
+        self.write_byte(CMD_DISPLAY_ON, 0)?;
+        self.write_byte(CMD_ENTRY_INCREMENT, 0)?;
+        self.write_byte(CMD_CLEAR, 0)?;
+        delay.delay_ms(2);
+        Ok(())
+    }
+
+    /// Writes `text` to `row`, padded with spaces to the full width.
+    /// Characters outside ASCII show as `?`.
+    pub fn write_line(&mut self, row: usize, text: &str) -> Result<(), I::Error> {
+        self.write_byte(CMD_SET_ADDRESS | ROW_ADDRESS[row % ROWS], 0)?;
+        let mut chars = text.chars();
+        for _ in 0..COLUMNS {
+            let c = chars.next().unwrap_or(' ');
+            let byte = if c.is_ascii() { c as u8 } else { b'?' };
+            self.write_byte(byte, RS)?;
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8, mode: u8) -> Result<(), I::Error> {
+        self.write_nibble(byte & 0xf0, mode)?;
+        self.write_nibble(byte << 4, mode)
+    }
+
+    /// Latches the high four bits of `nibble` on the falling edge of EN. At
+    /// I2C speeds each transfer outlasts the controller's command time.
+    fn write_nibble(&mut self, nibble: u8, mode: u8) -> Result<(), I::Error> {
+        let bits = (nibble & 0xf0) | mode | BACKLIGHT;
+        self.i2c.write(self.address, &[bits | EN, bits])
+    }
+}
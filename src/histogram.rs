@@ -0,0 +1,169 @@
+//! Histogram of the most recent samples' voltages, for judging duty cycle
+//! and noise from the console without exporting raw data. Bins are
+//! `bin_width_mv` wide from 0V to the 3.3V rail, and the oldest sample is
+//! taken back out as each new one arrives once `window` are counted.
+
+/// Enough for 52mV bins across the ADC's range.
+pub const MAX_BINS: usize = 64;
+pub const MIN_BIN_WIDTH_MV: u32 = FULL_SCALE_MV.div_ceil(MAX_BINS as u32);
+
+const FULL_SCALE_MV: u32 = 3300;
+
+/// Counts of up to `N` recent samples.
+pub struct Histogram<const N: usize> {
+    counts: [u16; MAX_BINS],
+    /// The bin of each counted sample, oldest at `next` once full.
+    bins: [u8; N],
+    next: usize,
+    len: usize,
+    window: usize,
+    bin_width_mv: u32,
+}
+
+impl<const N: usize> Histogram<N> {
+    /// Over the last `window` samples, at most `N`.
+    pub const fn new(bin_width_mv: u32, window: usize) -> Self {
+        Self {
+            counts: [0; MAX_BINS],
+            bins: [0; N],
+            next: 0,
+            len: 0,
+            window: if window < 1 {
+                1
+            } else if window > N {
+                N
+            } else {
+                window
+            },
+            bin_width_mv: if bin_width_mv < MIN_BIN_WIDTH_MV {
+                MIN_BIN_WIDTH_MV
+            } else {
+                bin_width_mv
+            },
+        }
+    }
+
+    /// Starts over with new settings, limited as in [`Histogram::new`].
+    pub fn configure(&mut self, bin_width_mv: u32, window: usize) {
+        *self = Self::new(bin_width_mv, window);
+    }
+
+    pub fn bin_width_mv(&self) -> u32 {
+        self.bin_width_mv
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Samples counted, up to the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn bins(&self) -> usize {
+        FULL_SCALE_MV.div_ceil(self.bin_width_mv) as usize
+    }
+
+    pub fn add(&mut self, voltage_mv: u32) {
+        let bin = ((voltage_mv / self.bin_width_mv) as usize).min(self.bins() - 1);
+        if self.len == self.window {
+            // Full: the oldest sample's slot is the one about to be reused
+            self.counts[self.bins[self.next] as usize] -= 1;
+        } else {
+            self.len += 1;
+        }
+        self.bins[self.next] = bin as u8;
+        self.counts[bin] += 1;
+        self.next = (self.next + 1) % self.window;
+    }
+
+    /// The count in each bin, lowest voltage first.
+    pub fn counts(&self) -> &[u16] {
+        &self.counts[..self.bins()]
+    }
+
+    /// The voltages bin `index` covers, from inclusive to exclusive; the
+    /// last bin also takes anything above full scale.
+    pub fn range_mv(&self, index: usize) -> (u32, u32) {
+        let low = index as u32 * self.bin_width_mv;
+        (low, (low + self.bin_width_mv).min(FULL_SCALE_MV))
+    }
+
+    /// Share of the samples in bins from `level_mv` up, in percent: the
+    /// duty cycle of a signal switching across that level, to the nearest
+    /// bin.
+    pub fn percent_from(&self, level_mv: u32) -> u32 {
+        if self.len == 0 {
+            return 0;
+        }
+        let first = (level_mv.div_ceil(self.bin_width_mv) as usize).min(self.bins());
+        let above: u32 = self.counts()[first..].iter().map(|&c| c as u32).sum();
+        above * 100 / self.len as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_land_in_their_bins() {
+        let mut histogram = Histogram::<16>::new(100, 16);
+        assert_eq!(histogram.bins(), 33);
+        for mv in [0, 99, 100, 1650, 3299, 3300, 4000] {
+            histogram.add(mv);
+        }
+        let counts = histogram.counts();
+        assert_eq!(counts[0], 2);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[16], 1);
+        assert_eq!(counts[32], 3);
+        assert_eq!(histogram.len(), 7);
+        assert_eq!(histogram.range_mv(32), (3200, 3300));
+    }
+
+    #[test]
+    fn oldest_samples_drop_out() {
+        let mut histogram = Histogram::<8>::new(100, 4);
+        for _ in 0..4 {
+            histogram.add(50);
+        }
+        for _ in 0..3 {
+            histogram.add(250);
+        }
+        assert_eq!(histogram.counts()[0], 1);
+        assert_eq!(histogram.counts()[2], 3);
+        assert_eq!(histogram.len(), 4);
+        let total: u16 = histogram.counts().iter().sum();
+        assert_eq!(total, 4);
+    }
+
+    #[test]
+    fn duty_cycle() {
+        let mut histogram = Histogram::<100>::new(100, 100);
+        assert_eq!(histogram.percent_from(2000), 0);
+        for i in 0..100 {
+            histogram.add(if i % 4 == 0 { 3000 } else { 500 });
+        }
+        assert_eq!(histogram.percent_from(2000), 25);
+        assert_eq!(histogram.percent_from(0), 100);
+        assert_eq!(histogram.percent_from(5000), 0);
+    }
+
+    #[test]
+    fn settings_are_limited() {
+        let mut histogram = Histogram::<10>::new(1, 50);
+        assert_eq!(histogram.bin_width_mv(), MIN_BIN_WIDTH_MV);
+        assert_eq!(histogram.bins(), MAX_BINS);
+        assert_eq!(histogram.window(), 10);
+        histogram.add(1000);
+        histogram.configure(200, 0);
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.window(), 1);
+    }
+}
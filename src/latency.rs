@@ -0,0 +1,179 @@
+//! End-to-end latency distribution, from a sample's timestamp to the LED
+//! showing it, for knowing the response time an installation can count
+//! on. Latencies are counted in bins a quarter of an octave wide, fine
+//! enough to read percentiles to within a fifth, in a fixed few hundred
+//! bytes however long the run.
+
+use defmt::Format;
+
+/// Bins, covering up to about half a second; anything longer counts in
+/// the last.
+pub const BINS: usize = 72;
+
+/// Bins per doubling of the latency.
+const STEPS: u32 = 4;
+
+/// Counts of latencies, with the exact extremes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Distribution {
+    counts: [u32; BINS],
+    count: u32,
+    sum_us: u64,
+    min_us: u32,
+    max_us: u32,
+}
+
+/// Percentiles of a [`Distribution`], each the top of its bin but no
+/// higher than the maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Summary {
+    pub count: u32,
+    pub min_us: u32,
+    pub mean_us: u32,
+    pub p50_us: u32,
+    pub p90_us: u32,
+    pub p99_us: u32,
+    pub p999_us: u32,
+    pub max_us: u32,
+}
+
+impl Distribution {
+    pub const fn new() -> Self {
+        Self {
+            counts: [0; BINS],
+            count: 0,
+            sum_us: 0,
+            min_us: u32::MAX,
+            max_us: 0,
+        }
+    }
+
+    pub fn record(&mut self, latency_us: u32) {
+        self.counts[bin(latency_us).min(BINS - 1)] += 1;
+        self.count += 1;
+        self.sum_us += latency_us as u64;
+        self.min_us = self.min_us.min(latency_us);
+        self.max_us = self.max_us.max(latency_us);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The latency that `per_mille` thousandths of those recorded are no
+    /// longer than. `None` if nothing was recorded.
+    pub fn percentile_us(&self, per_mille: u32) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count as u64 * per_mille.min(1000) as u64)
+            .div_ceil(1000)
+            .max(1);
+        let mut seen = 0;
+        let top_us = self
+            .counts
+            .iter()
+            .enumerate()
+            .find(|&(_, &count)| {
+                seen += count as u64;
+                seen >= rank
+            })
+            .map_or(self.max_us, |(i, _)| floor_us(i + 1) - 1);
+        Some(top_us.clamp(self.min_us, self.max_us))
+    }
+
+    /// `None` if nothing was recorded.
+    pub fn summary(&self) -> Option<Summary> {
+        let p = |per_mille| self.percentile_us(per_mille);
+        Some(Summary {
+            count: self.count,
+            min_us: self.min_us,
+            mean_us: (self.sum_us / self.count.max(1) as u64) as u32,
+            p50_us: p(500)?,
+            p90_us: p(900)?,
+            p99_us: p(990)?,
+            p999_us: p(999)?,
+            max_us: self.max_us,
+        })
+    }
+}
+
+impl Default for Distribution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bin of a latency: one per microsecond below [`STEPS`], then
+/// [`STEPS`] to each doubling.
+fn bin(latency_us: u32) -> usize {
+    if latency_us < STEPS {
+        return latency_us as usize;
+    }
+    let octave = latency_us.ilog2() - STEPS.ilog2();
+    let step = (latency_us >> octave) - STEPS;
+    ((octave + 1) * STEPS + step) as usize
+}
+
+/// The lowest latency in bin `i`.
+fn floor_us(i: usize) -> u32 {
+    let i = i as u32;
+    if i < STEPS {
+        return i;
+    }
+    let octave = i / STEPS - 1;
+    (STEPS + i % STEPS) << octave
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bins_are_a_quarter_octave() {
+        for us in [0, 3, 4, 7, 8, 9, 10, 15, 16, 1000, 123_456] {
+            let i = bin(us);
+            assert!(
+                floor_us(i) <= us && us < floor_us(i + 1),
+                "{}us in bin {}",
+                us,
+                i
+            );
+        }
+        assert_eq!(bin(4), 4);
+        assert_eq!(bin(8), 8);
+        assert_eq!(bin(10), 9);
+        assert!(floor_us(BINS) > 500_000);
+    }
+
+    #[test]
+    fn percentiles_from_the_bins() {
+        let mut distribution = Distribution::new();
+        assert_eq!(distribution.summary(), None);
+        for _ in 0..990 {
+            distribution.record(1000);
+        }
+        for _ in 0..9 {
+            distribution.record(5000);
+        }
+        distribution.record(40_000);
+
+        let summary = distribution.summary().unwrap();
+        assert_eq!(summary.count, 1000);
+        assert_eq!((summary.min_us, summary.max_us), (1000, 40_000));
+        // Each the top of its bin, within a fifth
+        assert!((1000..1200).contains(&summary.p50_us));
+        assert_eq!(summary.p90_us, summary.p50_us);
+        assert_eq!(summary.p99_us, summary.p50_us);
+        assert!((5000..6000).contains(&summary.p999_us));
+        assert_eq!(distribution.percentile_us(1000), Some(40_000));
+        assert_eq!(summary.mean_us, 1075);
+    }
+
+    #[test]
+    fn long_latencies_count_in_the_last_bin() {
+        let mut distribution = Distribution::new();
+        distribution.record(u32::MAX);
+        assert_eq!(distribution.percentile_us(500), Some(u32::MAX));
+    }
+}
@@ -1 +1,83 @@
-#![no_std]
+//! The hardware-independent core of the firmware: sample processing,
+//! calibration, colour mapping, the log and telemetry formats, and drivers
+//! written against the `embedded-hal` traits. It builds without the ESP32
+//! crates (`cargo build --lib --no-default-features`) for any `no_std`
+//! target, e.g. an RP2040 or STM32; the ESP32 glue stays in `src/bin`.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod activity;
+pub mod allan;
+pub mod ambient;
+pub mod backoff;
+pub mod baseline;
+pub mod battery;
+pub mod bldc;
+pub mod burst;
+pub mod button;
+pub mod capture;
+#[cfg(feature = "tft")]
+pub mod chart;
+pub mod color;
+pub mod command;
+pub mod contact;
+pub mod counting;
+pub mod csv;
+pub mod current;
+pub mod datalog;
+pub mod datetime;
+pub mod delta;
+pub mod diag;
+pub mod drv2605;
+pub mod ds3231;
+pub mod encoder;
+pub mod filter;
+pub mod fixed;
+pub mod fixture;
+pub mod font;
+pub mod framebuffer;
+pub mod gesture;
+pub mod goertzel;
+pub mod grading;
+pub mod haptic;
+pub mod hd44780;
+pub mod histogram;
+pub mod latency;
+pub mod lockin;
+pub mod max7219;
+pub mod mode;
+pub mod nec;
+pub mod night;
+pub mod noise;
+pub mod notch;
+pub mod palette;
+pub mod pas;
+pub mod pid;
+pub mod power;
+pub mod profile;
+#[cfg(feature = "protobuf")]
+pub mod proto;
+pub mod relay;
+pub mod remote;
+pub mod report;
+pub mod rules;
+pub mod schedule;
+pub mod schema;
+pub mod selftest;
+pub mod servo;
+pub mod settings;
+pub mod spectrum;
+pub mod ssd1306;
+pub mod ssd1680;
+pub mod sync;
+pub mod tamper;
+pub mod threshold;
+pub mod timing;
+pub mod tm1637;
+pub mod touch;
+pub mod trigger;
+pub mod ulp;
+pub mod units;
+pub mod verbosity;
+pub mod waveform;
+pub mod x27;
@@ -0,0 +1,181 @@
+//! Lock-in detection: the part of the reading at the frequency an
+//! excitation coil is driven at, in step with the drive. Noise and fields
+//! at other frequencies average out, so a response far below the noise
+//! floor of a single reading can be measured, such as the field a piece of
+//! iron or steel returns when brought near the coil.
+//!
+//! The coil is driven with a square wave whose rising edges fall on whole
+//! periods of the monotonic clock, so [`reference_turns`] knows its phase
+//! from a reading's timestamp alone. Readings have their mean taken off and
+//! are multiplied by the reference's sine and cosine; two stages of
+//! exponential averaging with the same time constant leave the in-phase
+//! and quadrature parts of the response at the drive's fundamental.
+
+use core::f32::consts::PI;
+
+use crate::goertzel::atan2;
+use crate::spectrum::{sin_cos, sqrt};
+
+/// Time constants for the output to settle to well within a percent.
+pub const SETTLING_TIME_CONSTANTS: u32 = 7;
+
+/// The drive's phase at `timestamp_us`, in turns from a rising edge.
+pub fn reference_turns(frequency_hz: u32, timestamp_us: u64) -> f32 {
+    // In whole microsecond-cycles, so the phase stays exact however long
+    // the clock has run
+    (timestamp_us * frequency_hz as u64 % 1_000_000) as f32 / 1e6
+}
+
+/// Whether the drive is high at `timestamp_us`: for the first half of
+/// each period.
+pub fn drive_high(frequency_hz: u32, timestamp_us: u64) -> bool {
+    timestamp_us * frequency_hz as u64 % 1_000_000 < 500_000
+}
+
+/// The first edge of the drive after `timestamp_us`, for timing it.
+pub fn next_edge_us(frequency_hz: u32, timestamp_us: u64) -> u64 {
+    let half_periods = timestamp_us * 2 * frequency_hz as u64 / 1_000_000 + 1;
+    (half_periods * 1_000_000).div_ceil(2 * frequency_hz as u64)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Response {
+    /// Peak amplitude at the drive's fundamental, in the units pushed.
+    pub amplitude: f32,
+    /// Of the response against the reference, from -180 to 180 degrees.
+    pub phase_deg: f32,
+    /// Whether the averages have settled since the start.
+    pub settled: bool,
+}
+
+pub struct LockIn {
+    frequency_hz: u32,
+    time_constant_us: f32,
+    mean: Option<f32>,
+    /// In-phase and quadrature products after the first and second stage.
+    first: (f32, f32),
+    second: (f32, f32),
+    start_us: Option<u64>,
+    last_us: u64,
+}
+
+impl LockIn {
+    /// `None` for a frequency of zero or a time constant shorter than a
+    /// period of the drive, which would leave its ripple in the output.
+    pub fn new(frequency_hz: u32, time_constant_ms: u32) -> Option<Self> {
+        let valid = frequency_hz > 0 && time_constant_ms as u64 * frequency_hz as u64 >= 1000;
+        valid.then_some(Self {
+            frequency_hz,
+            time_constant_us: time_constant_ms as f32 * 1000.0,
+            mean: None,
+            first: (0.0, 0.0),
+            second: (0.0, 0.0),
+            start_us: None,
+            last_us: 0,
+        })
+    }
+
+    pub fn frequency_hz(&self) -> u32 {
+        self.frequency_hz
+    }
+
+    pub fn push(&mut self, timestamp_us: u64, value: f32) {
+        let start_us = *self.start_us.get_or_insert(timestamp_us);
+        let elapsed_us = timestamp_us.saturating_sub(self.last_us.max(start_us));
+        self.last_us = timestamp_us;
+        let weight = (elapsed_us as f32 / self.time_constant_us).min(1.0);
+
+        let mean = self.mean.get_or_insert(value);
+        *mean += weight * (value - *mean);
+        let ac = value - *mean;
+        let (sin, cos) = sin_cos(reference_turns(self.frequency_hz, timestamp_us));
+        self.first.0 += weight * (ac * sin - self.first.0);
+        self.first.1 += weight * (ac * cos - self.first.1);
+        self.second.0 += weight * (self.first.0 - self.second.0);
+        self.second.1 += weight * (self.first.1 - self.second.1);
+    }
+
+    pub fn response(&self) -> Response {
+        let (in_phase, quadrature) = self.second;
+        let settled_us = self.time_constant_us * SETTLING_TIME_CONSTANTS as f32;
+        let elapsed_us = self
+            .start_us
+            .map_or(0, |start_us| self.last_us.saturating_sub(start_us));
+        Response {
+            // Mixing halves the amplitude
+            amplitude: 2.0 * sqrt(in_phase * in_phase + quadrature * quadrature),
+            phase_deg: atan2(quadrature, in_phase) * 180.0 / PI,
+            settled: elapsed_us as f32 >= settled_us,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD_US: u64 = 10_000;
+
+    /// Runs `seconds` of readings every 10ms of `amplitude` at the drive's
+    /// frequency, `phase_deg` ahead of it, on an offset, plus `extra`.
+    fn run(
+        lockin: &mut LockIn,
+        seconds: u64,
+        amplitude: f32,
+        phase_deg: f32,
+        mut extra: impl FnMut(u64) -> f32,
+    ) -> Response {
+        let frequency_hz = lockin.frequency_hz();
+        for i in 0..seconds * 1_000_000 / PERIOD_US {
+            let timestamp_us = 1_234_567 + i * PERIOD_US;
+            let turns = reference_turns(frequency_hz, timestamp_us) + phase_deg / 360.0;
+            let (sin, _) = sin_cos(turns.rem_euclid(1.0));
+            lockin.push(timestamp_us, 1650.0 + amplitude * sin + extra(i));
+        }
+        lockin.response()
+    }
+
+    #[test]
+    fn measures_amplitude_and_phase() {
+        let mut lockin = LockIn::new(10, 1000).unwrap();
+        let response = run(&mut lockin, 10, 2.0, 30.0, |_| 0.0);
+        assert!(response.settled);
+        assert!((response.amplitude - 2.0).abs() < 0.02, "{response:?}");
+        assert!((response.phase_deg - 30.0).abs() < 1.0, "{response:?}");
+
+        let mut lockin = LockIn::new(10, 1000).unwrap();
+        let response = run(&mut lockin, 3, 2.0, -90.0, |_| 0.0);
+        assert!(!response.settled);
+        assert!((response.phase_deg + 90.0).abs() < 2.0, "{response:?}");
+    }
+
+    #[test]
+    fn rejects_noise_and_other_frequencies() {
+        // 0.1 against noise of 0.5 and a tone of 5 at 23Hz
+        let mut state = 0x1234_5678u32;
+        let mut lockin = LockIn::new(10, 5000).unwrap();
+        let response = run(&mut lockin, 60, 0.1, 0.0, |i| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = (state as f32 / u32::MAX as f32 - 0.5) * 3.0f32.sqrt();
+            let (tone, _) = sin_cos((i * 23 % 100) as f32 / 100.0);
+            noise + 5.0 * tone
+        });
+        assert!((response.amplitude - 0.1).abs() < 0.05, "{response:?}");
+    }
+
+    #[test]
+    fn drive_timing() {
+        assert_eq!(reference_turns(10, 1_025_000), 0.25);
+        assert!(drive_high(10, 1_049_999));
+        assert!(!drive_high(10, 1_050_000));
+        assert_eq!(next_edge_us(10, 1_000_000), 1_050_000);
+        assert_eq!(next_edge_us(10, 1_049_999), 1_050_000);
+        // A period of 333333.3us, its halves rounded up
+        assert_eq!(next_edge_us(3, 0), 166_667);
+        assert_eq!(next_edge_us(3, 166_667), 333_334);
+        assert!(LockIn::new(10, 99).is_none());
+        assert!(LockIn::new(0, 1000).is_none());
+    }
+}
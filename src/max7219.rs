@@ -0,0 +1,94 @@
+//! Minimal driver for chains of MAX7219 8x8 LED matrix modules, and the
+//! bar graph drawn on them.
+//!
+//! Module 0 is the leftmost, the one farthest from the input as on FC-16
+//! boards. In each row byte the most significant bit is the leftmost pixel.
+
+use embedded_hal::spi::SpiDevice;
+
+pub const ROWS: usize = 8;
+
+/// Brightest of the sixteen intensity levels.
+pub const MAX_INTENSITY: u8 = 15;
+
+const REG_DIGIT0: u8 = 0x01;
+const REG_DECODE_MODE: u8 = 0x09;
+const REG_INTENSITY: u8 = 0x0a;
+const REG_SCAN_LIMIT: u8 = 0x0b;
+const REG_SHUTDOWN: u8 = 0x0c;
+const REG_DISPLAY_TEST: u8 = 0x0f;
+
+/// One row byte per module for each of the eight rows, top first.
+pub type Frame<const N: usize> = [[u8; N]; ROWS];
+
+pub struct Max7219<S, const N: usize> {
+    spi: S,
+}
+
+impl<S: SpiDevice, const N: usize> Max7219<S, N> {
+    pub fn new(spi: S) -> Self {
+        Self { spi }
+    }
+
+    /// Sets every module up for raw pixels and blanks it.
+    pub fn init(&mut self) -> Result<(), S::Error> {
+        self.write_all(REG_SHUTDOWN, 0)?;
+        self.write_all(REG_DISPLAY_TEST, 0)?;
+        self.write_all(REG_DECODE_MODE, 0)?;
+        self.write_all(REG_SCAN_LIMIT, ROWS as u8 - 1)?;
+        self.flush(&[[0; N]; ROWS])?;
+        self.write_all(REG_SHUTDOWN, 1)
+    }
+
+    /// Intensity from 0 to [`MAX_INTENSITY`]; the display is never fully off.
+    pub fn set_intensity(&mut self, intensity: u8) -> Result<(), S::Error> {
+        self.write_all(REG_INTENSITY, intensity.min(MAX_INTENSITY))
+    }
+
+    pub fn flush(&mut self, frame: &Frame<N>) -> Result<(), S::Error> {
+        for (row, modules) in frame.iter().enumerate() {
+            self.write(REG_DIGIT0 + row as u8, modules)?;
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, register: u8, value: u8) -> Result<(), S::Error> {
+        self.write(register, &[value; N])
+    }
+
+    /// Writes `register` in every module in one transfer. What is shifted
+    /// in first ends up farthest along the chain, so module 0 goes first.
+    fn write(&mut self, register: u8, values: &[u8; N]) -> Result<(), S::Error> {
+        let mut buf = [[0u8; 2]; N];
+        for (pair, &value) in buf.iter_mut().zip(values) {
+            *pair = [register, value];
+        }
+        self.spi.write(buf.as_flattened())
+    }
+}
+
+/// A bar growing out from the centre, right for positive `fraction` and left
+/// for negative, with `1.0` filling one half. Each column fills from the
+/// bottom a row at a time, so the bar moves in steps of an eighth of a
+/// column. The top pixels of the two centre columns mark zero.
+pub fn bar<const N: usize>(fraction: f32) -> Frame<N> {
+    let mut frame = [[0u8; N]; ROWS];
+    let half = N * 8 / 2;
+    if half == 0 {
+        return frame;
+    }
+    let mut set = |x: usize, row: usize| frame[row][x / 8] |= 0x80 >> (x % 8);
+
+    set(half - 1, 0);
+    set(half, 0);
+
+    let steps = (fraction.abs().min(1.0) * (half * ROWS) as f32 + 0.5) as usize;
+    for k in 0..half {
+        let lit = steps.saturating_sub(k * ROWS).min(ROWS);
+        let x = if fraction < 0.0 { half - 1 - k } else { half + k };
+        for row in ROWS - lit..ROWS {
+            set(x, row);
+        }
+    }
+    frame
+}
@@ -0,0 +1,263 @@
+//! Operating modes and the rules for switching between them.
+
+use defmt::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Mode {
+    /// Normal operation: LED gradient, threshold events and logging.
+    Measure,
+    /// Averages the zero-field output, then returns to `Measure`.
+    Calibrate,
+    /// Reports the rate of rising threshold crossings.
+    Tachometer,
+    /// Logs every raw reading along with its processing latency.
+    Diagnostics,
+    /// Logs the strongest frequencies in each block of readings.
+    Spectrum,
+    /// Gathers the Allan deviation of the field over a long run.
+    Drift,
+    /// Drives the excitation coil and measures the field in step with it.
+    LockIn,
+    /// Holds a magnet under the electromagnet, with the field as feedback.
+    Levitate,
+    /// Reads the current through a core or current sensor module, in amps.
+    Current,
+    /// Reads the power drawn through the current sensor, with the voltage
+    /// from a divider, and totals the energy.
+    Power,
+    /// Reads the cadence from a ring of magnets on an e-bike's crank and
+    /// sets the motor's assist from it.
+    Pas,
+    /// Reports a door or window as open or closed from a magnet on it.
+    Contact,
+    /// Watches a bias magnet and latches an alarm when it is removed or
+    /// overpowered.
+    Tamper,
+    /// Counts parts going past with magnets in them, in batches.
+    Count,
+    /// Tests magnet assemblies against stored limits, for production.
+    Fixture,
+    /// Grades magnets by their peak field over a few placements each.
+    Grade,
+    /// Measures the time from each sample to the LED showing it.
+    Latency,
+}
+
+pub const MODES: [Mode; 17] = [
+    Mode::Measure,
+    Mode::Calibrate,
+    Mode::Tachometer,
+    Mode::Diagnostics,
+    Mode::Spectrum,
+    Mode::Drift,
+    Mode::LockIn,
+    Mode::Levitate,
+    Mode::Current,
+    Mode::Power,
+    Mode::Pas,
+    Mode::Contact,
+    Mode::Tamper,
+    Mode::Count,
+    Mode::Fixture,
+    Mode::Grade,
+    Mode::Latency,
+];
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Self> {
+        MODES.into_iter().find(|m| m.name() == name)
+    }
+
+    /// The mode after this one when cycling with the button. Calibration,
+    /// the spectrum, drift runs, lock-in detection, levitation, current
+    /// sensing, power metering, pedal assist, contact sensing, tamper
+    /// detection, part counting, production testing, magnet grading and
+    /// latency measurement are started separately, so they are skipped.
+    pub fn next(&self) -> Self {
+        match self {
+            Mode::Measure => Mode::Tachometer,
+            Mode::Tachometer => Mode::Diagnostics,
+            Mode::Diagnostics
+            | Mode::Calibrate
+            | Mode::Spectrum
+            | Mode::Drift
+            | Mode::LockIn
+            | Mode::Levitate
+            | Mode::Current
+            | Mode::Power
+            | Mode::Pas
+            | Mode::Contact
+            | Mode::Tamper
+            | Mode::Count
+            | Mode::Fixture
+            | Mode::Grade
+            | Mode::Latency => Mode::Measure,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Measure => "measure",
+            Mode::Calibrate => "calibrate",
+            Mode::Tachometer => "tachometer",
+            Mode::Diagnostics => "diagnostics",
+            Mode::Spectrum => "spectrum",
+            Mode::Drift => "drift",
+            Mode::LockIn => "lockin",
+            Mode::Levitate => "levitate",
+            Mode::Current => "current",
+            Mode::Power => "power",
+            Mode::Pas => "pas",
+            Mode::Contact => "contact",
+            Mode::Tamper => "tamper",
+            Mode::Count => "count",
+            Mode::Fixture => "fixture",
+            Mode::Grade => "grade",
+            Mode::Latency => "latency",
+        }
+    }
+}
+
+/// Samples averaged for a zero-field calibration.
+pub const CALIBRATION_SAMPLES: u32 = 100;
+
+/// Averages readings taken with no magnet near the sensor.
+pub struct ZeroCalibration {
+    sum_mv: u64,
+    count: u32,
+}
+
+impl ZeroCalibration {
+    pub const fn new() -> Self {
+        Self {
+            sum_mv: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns the zero-field voltage once enough samples have been seen.
+    pub fn push(&mut self, voltage_mv: u32) -> Option<u32> {
+        self.sum_mv += voltage_mv as u64;
+        self.count += 1;
+        self.is_done()
+            .then(|| (self.sum_mv / self.count as u64) as u32)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.count >= CALIBRATION_SAMPLES
+    }
+}
+
+impl Default for ZeroCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Revolutions per minute from the spacing of rising crossings, one pulse per
+/// revolution.
+pub struct Tachometer {
+    last_pulse_us: Option<u64>,
+    interval_us: Option<u64>,
+}
+
+// Slower than this reads as stopped
+const STOPPED_US: u64 = 10_000_000;
+
+impl Tachometer {
+    pub const fn new() -> Self {
+        Self {
+            last_pulse_us: None,
+            interval_us: None,
+        }
+    }
+
+    pub fn pulse(&mut self, timestamp_us: u64) {
+        if let Some(last_us) = self.last_pulse_us.replace(timestamp_us) {
+            self.interval_us = Some(timestamp_us - last_us);
+        }
+    }
+
+    /// Zero once no pulse has been seen for ten seconds.
+    pub fn rpm(&self, now_us: u64) -> u32 {
+        match (self.last_pulse_us, self.interval_us) {
+            (Some(last_us), Some(interval_us))
+                if interval_us > 0 && now_us - last_us < STOPPED_US =>
+            {
+                (60_000_000 / interval_us) as u32
+            }
+            _ => 0,
+        }
+    }
+
+    /// As [`Self::rpm`], but no faster than a pulse now would make it, so
+    /// a motor slowing down or stalled reads as such before its next
+    /// pulse, as a speed loop needs.
+    pub fn rpm_bound(&self, now_us: u64) -> f32 {
+        let rpm = self.rpm(now_us) as f32;
+        match self.last_pulse_us {
+            Some(last_us) if now_us > last_us => rpm.min(60e6 / (now_us - last_us) as f32),
+            _ => rpm,
+        }
+    }
+}
+
+impl Default for Tachometer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_averages_once_done() {
+        let mut calibration = ZeroCalibration::new();
+        for i in 0..CALIBRATION_SAMPLES - 1 {
+            assert_eq!(calibration.push(1600 + i % 2 * 100), None);
+        }
+        assert_eq!(calibration.push(1700), Some(1650));
+        assert!(calibration.is_done());
+    }
+
+    #[test]
+    fn tachometer_from_pulse_spacing() {
+        let mut tachometer = Tachometer::new();
+        assert_eq!(tachometer.rpm(0), 0);
+        tachometer.pulse(1_000_000);
+        assert_eq!(tachometer.rpm(1_000_000), 0);
+        tachometer.pulse(1_050_000);
+        assert_eq!(tachometer.rpm(1_050_000), 1200);
+    }
+
+    #[test]
+    fn tachometer_stops() {
+        let mut tachometer = Tachometer::new();
+        tachometer.pulse(0);
+        tachometer.pulse(500_000);
+        assert_eq!(tachometer.rpm(10_499_999), 120);
+        assert_eq!(tachometer.rpm(10_500_000), 0);
+    }
+
+    #[test]
+    fn bound_falls_between_pulses() {
+        let mut tachometer = Tachometer::new();
+        tachometer.pulse(0);
+        tachometer.pulse(50_000);
+        assert_eq!(tachometer.rpm_bound(60_000), 1200.0);
+        assert_eq!(tachometer.rpm_bound(150_000), 600.0);
+        assert_eq!(tachometer.rpm(150_000), 1200);
+    }
+
+    #[test]
+    fn cycling_skips_calibration() {
+        assert_eq!(Mode::Measure.next(), Mode::Tachometer);
+        assert_eq!(Mode::Diagnostics.next(), Mode::Measure);
+        assert_eq!(Mode::Calibrate.next(), Mode::Measure);
+        for mode in MODES {
+            assert_eq!(Mode::parse(mode.name()), Some(mode));
+        }
+    }
+}
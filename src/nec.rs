@@ -0,0 +1,129 @@
+//! NEC infrared remote protocol decoding.
+//!
+//! Works on (mark, space) durations in microseconds as captured from a
+//! demodulating IR receiver, so it is independent of the capture hardware.
+
+use defmt::Format;
+
+const LEADER_MARK_US: u32 = 9000;
+const LEADER_SPACE_US: u32 = 4500;
+const REPEAT_SPACE_US: u32 = 2250;
+const BIT_MARK_US: u32 = 560;
+const ZERO_SPACE_US: u32 = 560;
+const ONE_SPACE_US: u32 = 1690;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Frame {
+    /// `address` is both address bytes, low byte first; for plain NEC the
+    /// high byte is the inverse of the low one.
+    Key { address: u16, command: u8 },
+    /// Sent every 110ms while a key is held.
+    Repeat,
+}
+
+// Within 25% of the nominal duration
+fn near(us: u32, nominal_us: u32) -> bool {
+    us.abs_diff(nominal_us) <= nominal_us / 4
+}
+
+/// Decodes one frame. Returns `None` for anything that is not a well-formed
+/// NEC frame, including a command that does not match its inverse.
+pub fn decode(mut pulses: impl Iterator<Item = (u32, u32)>) -> Option<Frame> {
+    let (mark, space) = pulses.next()?;
+    if !near(mark, LEADER_MARK_US) {
+        return None;
+    }
+    if near(space, REPEAT_SPACE_US) {
+        return Some(Frame::Repeat);
+    }
+    if !near(space, LEADER_SPACE_US) {
+        return None;
+    }
+
+    // Least significant bit first
+    let mut bits = 0u32;
+    for bit in 0..32 {
+        let (mark, space) = pulses.next()?;
+        if !near(mark, BIT_MARK_US) {
+            return None;
+        }
+        if near(space, ONE_SPACE_US) {
+            bits |= 1 << bit;
+        } else if !near(space, ZERO_SPACE_US) {
+            return None;
+        }
+    }
+
+    let [address_low, address_high, command, inverse] = bits.to_le_bytes();
+    (command == !inverse).then_some(Frame::Key {
+        address: u16::from_le_bytes([address_low, address_high]),
+        command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Leader, then the four bytes least significant bit first
+    fn frame(bytes: [u8; 4]) -> Vec<(u32, u32)> {
+        let bits = u32::from_le_bytes(bytes);
+        let mut pulses = vec![(LEADER_MARK_US, LEADER_SPACE_US)];
+        pulses.extend((0..32).map(|bit| {
+            let space = if bits & (1 << bit) != 0 {
+                ONE_SPACE_US
+            } else {
+                ZERO_SPACE_US
+            };
+            (BIT_MARK_US, space)
+        }));
+        pulses
+    }
+
+    #[test]
+    fn decodes_a_key() {
+        assert_eq!(
+            decode(frame([0x04, 0xfb, 0x08, 0xf7]).into_iter()),
+            Some(Frame::Key {
+                address: 0xfb04,
+                command: 0x08
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_a_repeat() {
+        assert_eq!(
+            decode([(LEADER_MARK_US, REPEAT_SPACE_US)].into_iter()),
+            Some(Frame::Repeat)
+        );
+    }
+
+    #[test]
+    fn allows_for_timing_error() {
+        // Every duration 20% long
+        let slow = frame([0x00, 0xff, 0x45, 0xba])
+            .into_iter()
+            .map(|(mark, space)| (mark * 6 / 5, space * 6 / 5));
+        assert!(matches!(
+            decode(slow),
+            Some(Frame::Key { command: 0x45, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        // Command not matching its inverse
+        assert_eq!(decode(frame([0x00, 0xff, 0x45, 0xbb]).into_iter()), None);
+        // Cut short
+        assert_eq!(
+            decode(frame([0x00, 0xff, 0x45, 0xba]).into_iter().take(20)),
+            None
+        );
+        // No leader
+        assert_eq!(
+            decode(frame([0x00, 0xff, 0x45, 0xba]).into_iter().skip(1)),
+            None
+        );
+    }
+}
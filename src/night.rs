@@ -0,0 +1,135 @@
+//! Night mode: the LED dimmed, or off, for part of each day, so an
+//! installation in a bedroom or lab does not glow all night. Only what is
+//! shown changes; measurement and telemetry carry on as before. Times are
+//! of the day in UTC, as the wall clock is, and the window may run past
+//! midnight.
+
+use core::fmt;
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 8;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A time of day, in minutes from midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    /// Parses `HH:MM`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (hour, minute) = s.split_once(':')?;
+        let (hour, minute) = (hour.parse::<u16>().ok()?, minute.parse::<u16>().ok()?);
+        (hour < 24 && minute < 60 && s.len() == 5).then_some(Self(hour * 60 + minute))
+    }
+
+    pub fn of(unix_s: u64) -> Self {
+        Self((unix_s / 60 % MINUTES_PER_DAY as u64) as u16)
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    pub enabled: bool,
+    pub start: TimeOfDay,
+    /// The first minute after the window.
+    pub end: TimeOfDay,
+    /// The most the LED is lit at during it, in percent; zero for off.
+    pub brightness_pct: u8,
+}
+
+impl Settings {
+    /// Off until set, then dark from ten at night to seven in the morning.
+    pub const DEFAULT: Self = Self {
+        enabled: false,
+        start: TimeOfDay(22 * 60),
+        end: TimeOfDay(7 * 60),
+        brightness_pct: 0,
+    };
+
+    /// Whether `time` falls in the window; an empty window holds none.
+    pub fn covers(&self, time: TimeOfDay) -> bool {
+        let (start, end, time) = (self.start.0, self.end.0, time.0);
+        self.enabled
+            && if start <= end {
+                start <= time && time < end
+            } else {
+                time >= start || time < end
+            }
+    }
+
+    /// `brightness`, out of 255, as limited at `time`.
+    pub fn dim(&self, brightness: u8, time: TimeOfDay) -> u8 {
+        if !self.covers(time) {
+            return brightness;
+        }
+        let limit = (self.brightness_pct.min(100) as u16 * u8::MAX as u16 / 100) as u8;
+        brightness.min(limit)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> TimeOfDay {
+        TimeOfDay::parse(s).unwrap()
+    }
+
+    fn night(start: &str, end: &str, brightness_pct: u8) -> Settings {
+        Settings {
+            enabled: true,
+            start: time(start),
+            end: time(end),
+            brightness_pct,
+        }
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        assert_eq!(time("07:30").to_string(), "07:30");
+        assert_eq!(TimeOfDay::parse("24:00"), None);
+        assert_eq!(TimeOfDay::parse("7:30"), None);
+        assert_eq!(TimeOfDay::parse("07:60"), None);
+        // 2025-01-31 23:15:42 UTC
+        assert_eq!(TimeOfDay::of(1_738_365_342), time("23:15"));
+    }
+
+    #[test]
+    fn covers_a_window_past_midnight() {
+        let settings = night("22:00", "07:00", 0);
+        assert!(settings.covers(time("22:00")));
+        assert!(settings.covers(time("03:00")));
+        assert!(!settings.covers(time("07:00")));
+        assert!(!settings.covers(time("12:00")));
+
+        let lunch = night("12:00", "13:00", 0);
+        assert!(lunch.covers(time("12:30")));
+        assert!(!lunch.covers(time("13:30")));
+        assert!(!night("12:00", "12:00", 0).covers(time("12:00")));
+        assert!(!Settings::DEFAULT.covers(time("23:00")));
+    }
+
+    #[test]
+    fn limits_the_brightness_in_the_window() {
+        let settings = night("22:00", "07:00", 10);
+        assert_eq!(settings.dim(255, time("23:00")), 25);
+        assert_eq!(settings.dim(10, time("23:00")), 10);
+        assert_eq!(settings.dim(255, time("08:00")), 255);
+        assert_eq!(night("22:00", "07:00", 0).dim(255, time("23:00")), 0);
+    }
+}
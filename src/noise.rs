@@ -0,0 +1,146 @@
+//! Noise floor of the sensor and ADC, from raw readings taken with no
+//! magnet near: their spread, and the effective number of bits it leaves
+//! of the ADC's twelve. Oversampling by 4^k gains k bits back at best, so
+//! the effective bits say how much averaging is worth configuring.
+
+use core::f32::consts::LOG2_E;
+
+use crate::burst;
+
+pub const ADC_BITS: u32 = 12;
+
+/// Running statistics of raw readings, without keeping them.
+#[derive(Clone, Copy)]
+pub struct Noise {
+    count: u32,
+    mean: f64,
+    /// Sum of squared differences from the mean, updated as in Welford's
+    /// method so that no precision is lost to the large mean.
+    m2: f64,
+    min: u16,
+    max: u16,
+}
+
+impl Noise {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: u16::MAX,
+            max: 0,
+        }
+    }
+
+    pub fn push(&mut self, raw: u16) {
+        self.count += 1;
+        let x = raw as f64;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(raw);
+        self.max = self.max.max(raw);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// In ADC counts.
+    pub fn mean(&self) -> f32 {
+        self.mean as f32
+    }
+
+    /// In ADC counts, of the population.
+    pub fn std_dev(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        crate::spectrum::sqrt((self.m2 / self.count as f64) as f32)
+    }
+
+    /// In ADC counts.
+    pub fn peak_to_peak(&self) -> u16 {
+        self.max.saturating_sub(self.min)
+    }
+
+    /// log2 of full scale over the RMS noise of an ideal ADC of the same
+    /// spread, at most [`ADC_BITS`] as spread under a count is not seen.
+    pub fn effective_bits(&self) -> f32 {
+        // An ideal ADC's quantisation noise is a twelfth of a count squared
+        let rms = self.std_dev() * crate::spectrum::sqrt(12.0);
+        if rms <= 1.0 {
+            return ADC_BITS as f32;
+        }
+        ADC_BITS as f32 - log2(rms)
+    }
+
+    /// Counts converted as the sampler converts readings, so the figures
+    /// match the voltages reported elsewhere.
+    pub fn to_mv(counts: f32) -> f32 {
+        counts * burst::voltage_mv(4095) as f32 / 4095.0
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// From the exponent and a series for the mantissa's logarithm, to well
+/// under a hundredth of a bit.
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    // ln(m) = 2 atanh((m - 1) / (m + 1)), and s stays under a third
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let s2 = s * s;
+    let ln = 2.0 * s * (1.0 + s2 * (1.0 / 3.0 + s2 * (1.0 / 5.0 + s2 * (1.0 / 7.0 + s2 / 9.0))));
+    exponent as f32 + ln * LOG2_E
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics() {
+        let mut noise = Noise::new();
+        for raw in [2046, 2048, 2050, 2048] {
+            noise.push(raw);
+        }
+        assert_eq!(noise.count(), 4);
+        assert_eq!(noise.mean(), 2048.0);
+        assert!((noise.std_dev() - 2.0f32.sqrt()).abs() < 1e-4);
+        assert_eq!(noise.peak_to_peak(), 4);
+    }
+
+    #[test]
+    fn effective_bits_from_the_spread() {
+        let mut noise = Noise::new();
+        for _ in 0..100 {
+            noise.push(2048);
+        }
+        assert_eq!(noise.effective_bits(), 12.0);
+
+        // A deviation of 8/sqrt(12) counts costs three bits
+        let mut noise = Noise::new();
+        for i in 0..4096u32 {
+            noise.push(2044 + (i % 8) as u16);
+        }
+        let sigma = noise.std_dev();
+        let expected = 12.0 - (sigma * 12.0f32.sqrt()).log2();
+        assert!((noise.effective_bits() - expected).abs() < 1e-3);
+        assert!((noise.effective_bits() - 9.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn logarithm() {
+        for i in 1..2000 {
+            let x = i as f32 * 0.37;
+            assert!((log2(x) - x.log2()).abs() < 1e-4, "{x}");
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Notch filter for mains pickup, from sensor wiring run past a transformer
+//! or a mains cable. A biquad per frequency removes 50 or 60Hz, and
+//! optionally its harmonics, leaving the field's slower changes alone.
+//!
+//! At the usual 100Hz sample rate mains is at or above Nyquist, and what
+//! reaches the readings is its alias: 60Hz shows up at 40Hz, and 50Hz at
+//! 50Hz, flipping sign every sample. Each notch goes where its frequency
+//! folds to; one that folds onto another or close to DC is left out, as
+//! notching DC would remove the field itself.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::spectrum::sin_cos;
+
+/// The fundamental and harmonics up to the fifth, where rectifier loads
+/// put most of theirs.
+pub const MAX_STAGES: usize = 5;
+
+/// Width of each notch at -3dB, enough for the grid's drift from nominal.
+const BANDWIDTH_HZ: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Mains {
+    /// 50 or 60.
+    pub frequency_hz: u32,
+    /// Whether to notch the second to fifth harmonics too.
+    pub harmonics: bool,
+}
+
+/// A second-order section in transposed direct form II.
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// A notch at `frequency_hz`, `BANDWIDTH_HZ` wide; with the bandwidth
+    /// set through tan(pi BW / fs) it has unity gain at DC and Nyquist.
+    fn notch(frequency_hz: f32, rate_hz: f32) -> Self {
+        let (_, cos) = sin_cos(frequency_hz / rate_hz);
+        let (sin_bw, cos_bw) = sin_cos(BANDWIDTH_HZ / rate_hz / 2.0);
+        let alpha = sin_bw / cos_bw;
+        let gain = 1.0 / (1.0 + alpha);
+        Self {
+            b0: gain,
+            b1: -2.0 * cos * gain,
+            b2: gain,
+            a1: -2.0 * cos * gain,
+            a2: (1.0 - alpha) * gain,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Sets the state as if `x` had always been the input, so a steady
+    /// reading passes from the first sample.
+    fn prime(&mut self, x: f32) {
+        self.z2 = (self.b2 - self.a2) * x;
+        self.z1 = (self.b1 - self.a1) * x + self.z2;
+    }
+
+    fn step(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+pub struct Notch {
+    stages: [Biquad; MAX_STAGES],
+    /// The frequencies notched, after folding, for each stage in use.
+    frequencies_hz: [f32; MAX_STAGES],
+    len: usize,
+    primed: bool,
+}
+
+impl Notch {
+    /// Notches for `mains` in samples taken at `rate_hz`.
+    pub fn new(mains: Mains, rate_hz: f32) -> Self {
+        let mut notch = Self {
+            stages: [Biquad::default(); MAX_STAGES],
+            frequencies_hz: [0.0; MAX_STAGES],
+            len: 0,
+            primed: false,
+        };
+        let harmonics = if mains.harmonics { MAX_STAGES } else { 1 };
+        for harmonic in 1..=harmonics {
+            let frequency_hz = fold(mains.frequency_hz as f32 * harmonic as f32, rate_hz);
+            let distinct = notch
+                .frequencies()
+                .iter()
+                .all(|&f| (f - frequency_hz).abs() > BANDWIDTH_HZ);
+            if frequency_hz > BANDWIDTH_HZ && distinct {
+                notch.stages[notch.len] = Biquad::notch(frequency_hz, rate_hz);
+                notch.frequencies_hz[notch.len] = frequency_hz;
+                notch.len += 1;
+            }
+        }
+        notch
+    }
+
+    /// Where each notch sits, below half the sample rate.
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies_hz[..self.len]
+    }
+
+    pub fn apply(&mut self, voltage_mv: u32) -> u32 {
+        let mut x = voltage_mv as f32;
+        if !self.primed {
+            for stage in &mut self.stages[..self.len] {
+                stage.prime(x);
+            }
+            self.primed = true;
+        }
+        for stage in &mut self.stages[..self.len] {
+            x = stage.step(x);
+        }
+        (x + 0.5).max(0.0) as u32
+    }
+}
+
+/// The frequency `frequency_hz` shows up at when sampled at `rate_hz`.
+fn fold(frequency_hz: f32, rate_hz: f32) -> f32 {
+    let folded = frequency_hz % rate_hz;
+    if folded > rate_hz / 2.0 {
+        rate_hz - folded
+    } else {
+        folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINS_50: Mains = Mains {
+        frequency_hz: 50,
+        harmonics: false,
+    };
+
+    /// The largest departure from 1650mV over the last half of 2000
+    /// samples of `amplitude_mv` at `frequency_hz` through `notch`.
+    fn residual(notch: &mut Notch, rate_hz: f32, frequency_hz: f32, amplitude_mv: f32) -> f32 {
+        let mut worst = 0.0f32;
+        for i in 0..2000 {
+            let angle = core::f32::consts::TAU * frequency_hz * i as f32 / rate_hz;
+            let x = 1650.0 + amplitude_mv * angle.sin();
+            let y = notch.apply(x as u32) as f32;
+            if i >= 1000 {
+                worst = worst.max((y - 1650.0).abs());
+            }
+        }
+        worst
+    }
+
+    #[test]
+    fn removes_mains_and_keeps_the_field() {
+        let mut notch = Notch::new(MAINS_50, 1000.0);
+        assert_eq!(notch.frequencies(), [50.0]);
+        assert!(residual(&mut notch, 1000.0, 50.0, 200.0) < 3.0);
+        let mut notch = Notch::new(MAINS_50, 1000.0);
+        assert!(residual(&mut notch, 1000.0, 5.0, 200.0) > 195.0);
+    }
+
+    #[test]
+    fn steady_reading_passes_from_the_start() {
+        let mut notch = Notch::new(MAINS_50, 100.0);
+        for _ in 0..10 {
+            assert_eq!(notch.apply(2200), 2200);
+        }
+    }
+
+    #[test]
+    fn notches_go_where_mains_aliases() {
+        let mains = Mains {
+            frequency_hz: 60,
+            harmonics: false,
+        };
+        let mut notch = Notch::new(mains, 100.0);
+        assert_eq!(notch.frequencies(), [40.0]);
+        assert!(residual(&mut notch, 100.0, 60.0, 200.0) < 3.0);
+
+        // 50Hz sits at Nyquist, its second harmonic folds onto DC and its
+        // third onto itself
+        let mains = Mains {
+            frequency_hz: 50,
+            harmonics: true,
+        };
+        assert_eq!(Notch::new(mains, 100.0).frequencies(), [50.0]);
+        let mains = Mains {
+            frequency_hz: 60,
+            harmonics: true,
+        };
+        assert_eq!(
+            Notch::new(mains, 1000.0).frequencies(),
+            [60.0, 120.0, 180.0, 240.0, 300.0]
+        );
+    }
+}
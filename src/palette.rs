@@ -0,0 +1,139 @@
+//! The colours the LED shows the field in. Red for north through to blue
+//! for south is the default, which some colour vision cannot tell apart;
+//! the others keep the two poles apart without it. Orange and blue are
+//! told apart by most colour-blind eyes; brightness runs one colour from
+//! dark for north to full for south; and blinking shows the strength as
+//! brightness, blinking for north and steady for south.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::color::RGB8;
+
+pub const ENCODED_SIZE: usize = 1;
+
+/// How long one blink takes, lit for the first half.
+pub const BLINK_MS: u64 = 500;
+
+// The blinking palette's least brightness, so no field still shows
+const BLINK_FLOOR: f32 = 32.0;
+
+const ORANGE: RGB8 = RGB8::new(255, 160, 0);
+const BLUE: RGB8 = RGB8::new(0, 0, 255);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    RedBlue,
+    OrangeBlue,
+    Brightness,
+    Blink,
+}
+
+impl Palette {
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            Self::RedBlue,
+            Self::OrangeBlue,
+            Self::Brightness,
+            Self::Blink,
+        ]
+        .into_iter()
+        .find(|palette| palette.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RedBlue => "red-blue",
+            Self::OrangeBlue => "orange-blue",
+            Self::Brightness => "brightness",
+            Self::Blink => "blink",
+        }
+    }
+
+    /// The colour at `t` from 0 (north) to 1 (south) across the range.
+    pub fn color(self, t: f32) -> RGB8 {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |north: u8, south: u8| (north as f32 * (1.0 - t) + south as f32 * t) as u8;
+        match self {
+            Self::RedBlue => RGB8::new(mix(255, 0), 0, mix(0, 255)),
+            Self::OrangeBlue => RGB8::new(
+                mix(ORANGE.r, BLUE.r),
+                mix(ORANGE.g, BLUE.g),
+                mix(ORANGE.b, BLUE.b),
+            ),
+            Self::Brightness => {
+                let level = mix(0, 255);
+                RGB8::new(level, level, level)
+            }
+            Self::Blink => {
+                let strength = (2.0 * t - 1.0).abs();
+                let level = (BLINK_FLOOR + (255.0 - BLINK_FLOOR) * strength) as u8;
+                RGB8::new(level, level, level)
+            }
+        }
+    }
+
+    /// Whether the colour at `t` blinks: the blinking palette's north half.
+    pub fn blinks(self, t: f32) -> bool {
+        self == Self::Blink && t < 0.5
+    }
+}
+
+/// Whether a blinking colour is lit at `time_ms`.
+pub fn is_lit(time_ms: u64) -> bool {
+    time_ms % BLINK_MS < BLINK_MS / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Palette::parse("orange-blue"), Some(Palette::OrangeBlue));
+        assert_eq!(Palette::parse("blink"), Some(Palette::Blink));
+        assert_eq!(Palette::parse("green"), None);
+        assert_eq!(Palette::default().name(), "red-blue");
+    }
+
+    #[test]
+    fn ends_of_each_palette() {
+        assert_eq!(Palette::RedBlue.color(0.0), RGB8::new(255, 0, 0));
+        assert_eq!(Palette::RedBlue.color(1.0), RGB8::new(0, 0, 255));
+        assert_eq!(Palette::OrangeBlue.color(0.0), ORANGE);
+        assert_eq!(Palette::OrangeBlue.color(1.0), BLUE);
+        assert_eq!(Palette::Brightness.color(0.0), RGB8::new(0, 0, 0));
+        assert_eq!(Palette::Brightness.color(0.5), RGB8::new(127, 127, 127));
+        assert_eq!(Palette::Brightness.color(2.0), RGB8::new(255, 255, 255));
+    }
+
+    #[test]
+    fn blinks_for_north_only() {
+        let (north, middle, south) = (0.0, 0.5, 1.0);
+        assert_eq!(Palette::Blink.color(north), Palette::Blink.color(south));
+        assert_eq!(Palette::Blink.color(middle), RGB8::new(32, 32, 32));
+        assert!(Palette::Blink.blinks(north));
+        assert!(!Palette::Blink.blinks(south));
+        assert!(!Palette::OrangeBlue.blinks(north));
+        assert!(is_lit(0) && is_lit(249));
+        assert!(!is_lit(250) && !is_lit(499));
+        assert!(is_lit(500));
+    }
+
+    #[test]
+    fn none_is_green_alone() {
+        // Green alone is calibration's, and a fixture pass's
+        for palette in [
+            Palette::RedBlue,
+            Palette::OrangeBlue,
+            Palette::Brightness,
+            Palette::Blink,
+        ] {
+            for step in 0..=100 {
+                let color = palette.color(step as f32 / 100.0);
+                assert!(color.g == 0 || color.r > 0 || color.b > 0, "{palette:?}");
+            }
+        }
+    }
+}
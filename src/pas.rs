@@ -0,0 +1,281 @@
+//! Pedal assist (PAS) for e-bikes: a ring of magnets on the crank passes
+//! two sensors a quarter of the magnets' pitch apart. The cadence comes
+//! from the rate of pulses from the first, the direction from whether the
+//! second rose just before it or just after.
+//!
+//! An assist curve maps the cadence to a level, which goes out as a
+//! throttle voltage for a motor controller that takes a hall throttle.
+//! Only pedalling forward gets assist, and it stops within half a second
+//! of the pedals stopping.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+/// Points in an assist curve, at most.
+pub const MAX_POINTS: usize = 4;
+
+pub const ENCODED_SIZE: usize = 24;
+
+/// A hall throttle's output at rest and fully open. Above the idle level
+/// a controller reads a throttle as open; full is as high as a 3.3V
+/// output reaches, which most controllers take as full throttle.
+pub const THROTTLE_IDLE_MV: f32 = 800.0;
+pub const THROTTLE_FULL_MV: f32 = 3300.0;
+
+// Slower than this reads as stopped pedals
+const STOPPED_US: u64 = 500_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Point {
+    pub cadence_rpm: u16,
+    pub percent: u8,
+}
+
+/// Assist against cadence: none below the first point or with the pedals
+/// still, in a straight line between points, and the last point's above
+/// it.
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Curve {
+    points: [Point; MAX_POINTS],
+    len: u8,
+}
+
+impl Curve {
+    /// A gentle start, and full assist from a brisk 90rpm.
+    pub const DEFAULT: Self = Self {
+        points: [
+            Point {
+                cadence_rpm: 20,
+                percent: 30,
+            },
+            Point {
+                cadence_rpm: 60,
+                percent: 70,
+            },
+            Point {
+                cadence_rpm: 90,
+                percent: 100,
+            },
+            Point {
+                cadence_rpm: 0,
+                percent: 0,
+            },
+        ],
+        len: 3,
+    };
+
+    /// From one to [`MAX_POINTS`] points. `None` unless the cadences rise
+    /// and the levels are percentages.
+    pub fn new(points: &[Point]) -> Option<Self> {
+        let valid = (1..=MAX_POINTS).contains(&points.len())
+            && points.iter().all(|p| p.percent <= 100)
+            && points
+                .windows(2)
+                .all(|w| w[0].cadence_rpm < w[1].cadence_rpm);
+        valid.then(|| {
+            let mut curve = Self {
+                points: [Point {
+                    cadence_rpm: 0,
+                    percent: 0,
+                }; MAX_POINTS],
+                len: points.len() as u8,
+            };
+            curve.points[..points.len()].copy_from_slice(points);
+            curve
+        })
+    }
+
+    /// Points written `rpm:percent`, e.g. `20:30 60:70 90:100`.
+    pub fn parse<'a>(words: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut points = [Point {
+            cadence_rpm: 0,
+            percent: 0,
+        }; MAX_POINTS];
+        let mut len = 0;
+        for word in words {
+            let (cadence, percent) = word.split_once(':')?;
+            *points.get_mut(len)? = Point {
+                cadence_rpm: cadence.parse().ok()?,
+                percent: percent.trim_end_matches('%').parse().ok()?,
+            };
+            len += 1;
+        }
+        Self::new(&points[..len])
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points[..self.len as usize]
+    }
+
+    /// The assist, from 0 to 1, at `cadence_rpm`.
+    pub fn level(&self, cadence_rpm: f32) -> f32 {
+        let points = self.points();
+        let Some(first) = points.first() else {
+            return 0.0;
+        };
+        if cadence_rpm <= 0.0 || cadence_rpm < first.cadence_rpm as f32 {
+            return 0.0;
+        }
+        let percent = points
+            .windows(2)
+            .find(|w| cadence_rpm < w[1].cadence_rpm as f32)
+            .map_or(points[points.len() - 1].percent as f32, |w| {
+                let (a, b) = (w[0], w[1]);
+                let t =
+                    (cadence_rpm - a.cadence_rpm as f32) / (b.cadence_rpm - a.cadence_rpm) as f32;
+                a.percent as f32 + t * (b.percent as f32 - a.percent as f32)
+            });
+        percent / 100.0
+    }
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// Magnets around the ring; 12 is the most common.
+    pub magnets: u8,
+    pub curve: Curve,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        magnets: 12,
+        curve: Curve::DEFAULT,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The throttle voltage for an assist `level` from 0 to 1.
+pub fn throttle_mv(level: f32) -> f32 {
+    THROTTLE_IDLE_MV + level.clamp(0.0, 1.0) * (THROTTLE_FULL_MV - THROTTLE_IDLE_MV)
+}
+
+/// Cadence and direction from the two sensors' pulses.
+pub struct Cadence {
+    last_us: Option<u64>,
+    interval_us: Option<u64>,
+    backward: bool,
+}
+
+impl Cadence {
+    pub const fn new() -> Self {
+        Self {
+            last_us: None,
+            interval_us: None,
+            backward: false,
+        }
+    }
+
+    /// Takes a pulse from the first sensor at `timestamp_us`, with the
+    /// time the second last rose. Pedalling forward, it rose within the
+    /// quarter pitch before; backward, it rose a quarter pitch after the
+    /// one before, or has yet to. A change of direction starts the
+    /// measurement over.
+    pub fn pulse(&mut self, timestamp_us: u64, second_rose_us: Option<u64>) {
+        let half_pitch_us = self
+            .interval_us
+            .map_or(u64::MAX, |interval_us| interval_us / 2);
+        let backward = match second_rose_us {
+            Some(rose_us) if rose_us <= timestamp_us => timestamp_us - rose_us >= half_pitch_us,
+            Some(_) => true,
+            None => false,
+        };
+        if backward != self.backward {
+            self.backward = backward;
+            self.interval_us = None;
+        }
+        if let Some(last_us) = self.last_us.replace(timestamp_us) {
+            self.interval_us = Some(timestamp_us - last_us);
+        }
+    }
+
+    /// Crank revolutions per minute, negative pedalling backward, and no
+    /// faster than a pulse now would make it, so stopping reads as slowing
+    /// at once. Zero once the pedals have stopped.
+    pub fn rpm(&self, now_us: u64, magnets: u8) -> f32 {
+        let (Some(last_us), Some(interval_us)) = (self.last_us, self.interval_us) else {
+            return 0.0;
+        };
+        let since_us = now_us.saturating_sub(last_us);
+        if since_us >= STOPPED_US || interval_us == 0 || magnets == 0 {
+            return 0.0;
+        }
+        let rpm = 60e6 / (interval_us.max(since_us) * magnets as u64) as f32;
+        if self.backward { -rpm } else { rpm }
+    }
+}
+
+impl Default for Cadence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_interpolates_and_parses() {
+        let curve = Curve::DEFAULT;
+        assert_eq!(curve.level(10.0), 0.0);
+        assert!((curve.level(20.0) - 0.3).abs() < 1e-6);
+        assert!((curve.level(40.0) - 0.5).abs() < 1e-6);
+        assert_eq!(curve.level(120.0), 1.0);
+        let from_rest = Curve::parse("0:20 60:100".split(' ')).unwrap();
+        assert_eq!(from_rest.level(0.0), 0.0);
+        assert_eq!(from_rest.level(-30.0), 0.0);
+        assert!((from_rest.level(30.0) - 0.6).abs() < 1e-6);
+
+        assert_eq!(Curve::parse("20:30 60:70% 90:100".split(' ')), Some(curve));
+        assert_eq!(Curve::parse("60:70 20:30".split(' ')), None);
+        assert_eq!(Curve::parse("20:130".split(' ')), None);
+        assert_eq!(Curve::parse("1:1 2:2 3:3 4:4 5:5".split(' ')), None);
+        assert_eq!(Curve::parse(core::iter::empty()), None);
+    }
+
+    #[test]
+    fn cadence_and_direction() {
+        // 60rpm with 12 magnets: a pulse every 83ms, the second sensor
+        // rising 21ms before each
+        let pitch_us = 83_333;
+        let mut cadence = Cadence::new();
+        for i in 1..4 {
+            let t = i * pitch_us;
+            cadence.pulse(t, Some(t - pitch_us / 4));
+        }
+        let rpm = cadence.rpm(3 * pitch_us, 12);
+        assert!((rpm - 60.0).abs() < 0.1, "{rpm}");
+        // Slowing reads at once; stopped after half a second
+        assert!(cadence.rpm(5 * pitch_us, 12) < 31.0);
+        assert_eq!(cadence.rpm(3 * pitch_us + STOPPED_US, 12), 0.0);
+
+        // Backward: the second sensor rose three quarters of a pitch ago,
+        // or has not yet been seen to rise after this pulse
+        let mut cadence = Cadence::new();
+        cadence.pulse(pitch_us, None);
+        for i in 2..5 {
+            let t = i * pitch_us;
+            cadence.pulse(t, Some(t - 3 * pitch_us / 4));
+        }
+        assert!(cadence.rpm(4 * pitch_us, 12) < -59.0);
+        cadence.pulse(5 * pitch_us, Some(5 * pitch_us + 100));
+        assert!(cadence.rpm(5 * pitch_us, 12) < -59.0);
+    }
+
+    #[test]
+    fn throttle_spans_idle_to_full() {
+        assert_eq!(throttle_mv(0.0), THROTTLE_IDLE_MV);
+        assert_eq!(throttle_mv(2.0), THROTTLE_FULL_MV);
+    }
+}
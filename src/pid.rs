@@ -0,0 +1,136 @@
+//! PID control of a duty cycle from a field reading, for holding a magnet
+//! under an electromagnet (levitation).
+//!
+//! The derivative is of the reading rather than the error, so moving the
+//! setpoint gives the coil no kick, and is smoothed over a few periods as
+//! the sensor's noise would otherwise reach the coil amplified. The
+//! integral stops growing while the output is pinned at either end, so it
+//! does not wind up while the magnet is out of reach.
+
+use defmt::Format;
+
+/// Share of the new derivative taken each period.
+const DERIVATIVE_SMOOTHING: f32 = 0.25;
+
+/// Of the duty, from 0 to 1, per millitesla of error.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Gains {
+    pub kp: f32,
+    /// Per millitesla-second.
+    pub ki: f32,
+    /// Per millitesla per second.
+    pub kd: f32,
+}
+
+pub struct Pid {
+    gains: Gains,
+    integral: f32,
+    last_mt: Option<f32>,
+    derivative: f32,
+}
+
+impl Pid {
+    pub const fn new(gains: Gains) -> Self {
+        Self {
+            gains,
+            integral: 0.0,
+            last_mt: None,
+            derivative: 0.0,
+        }
+    }
+
+    /// Takes on new gains, keeping the state, so they can be tuned while
+    /// the loop runs.
+    pub fn set_gains(&mut self, gains: Gains) {
+        self.gains = gains;
+    }
+
+    /// Starts the integral at `output`, so a loop begins from the duty it
+    /// is expected to settle at rather than from zero.
+    pub fn preset(&mut self, output: f32) {
+        self.integral = output;
+    }
+
+    /// The duty for a reading of `field_mt`, `dt_s` after the last.
+    /// Negative gains suit a magnet whose field reads the other way.
+    pub fn update(&mut self, setpoint_mt: f32, field_mt: f32, dt_s: f32) -> f32 {
+        let Gains { kp, ki, kd } = self.gains;
+        let error = setpoint_mt - field_mt;
+        if let Some(last_mt) = self.last_mt.replace(field_mt)
+            && dt_s > 0.0
+        {
+            let derivative = (last_mt - field_mt) / dt_s;
+            self.derivative += DERIVATIVE_SMOOTHING * (derivative - self.derivative);
+        }
+
+        let unclamped = kp * error + self.integral + ki * error * dt_s + kd * self.derivative;
+        let output = unclamped.clamp(0.0, 1.0);
+        // Only while the output can still move the way the integral pushes
+        if output == unclamped || (unclamped > 1.0) != (ki * error > 0.0) {
+            self.integral += ki * error * dt_s;
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT_S: f32 = 0.001;
+
+    #[test]
+    fn proportional_and_clamped() {
+        let gains = Gains {
+            kp: 0.1,
+            ki: 0.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains);
+        assert!((pid.update(5.0, 2.0, DT_S) - 0.3).abs() < 1e-6);
+        assert_eq!(pid.update(20.0, 2.0, DT_S), 1.0);
+        assert_eq!(pid.update(2.0, 5.0, DT_S), 0.0);
+    }
+
+    #[test]
+    fn integral_settles_a_plant_without_winding_up() {
+        // A field that follows the duty, as a coil's own would
+        let gains = Gains {
+            kp: 0.02,
+            ki: 2.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains);
+        let mut field_mt = 0.0;
+        for _ in 0..5000 {
+            let duty = pid.update(12.0, field_mt, DT_S);
+            field_mt += (duty * 20.0 - field_mt) * 0.05;
+        }
+        assert!((field_mt - 12.0).abs() < 0.05, "{field_mt}");
+
+        // Out of reach: the integral stops at the end it is pinned to, so
+        // the output comes off as soon as the error turns
+        let mut pid = Pid::new(gains);
+        for _ in 0..5000 {
+            assert_eq!(pid.update(50.0, 0.0, DT_S), 1.0);
+        }
+        assert!(pid.update(0.0, 1.0, DT_S) < 1.0);
+    }
+
+    #[test]
+    fn derivative_of_the_reading() {
+        let gains = Gains {
+            kp: 0.0,
+            ki: 0.0,
+            kd: 0.001,
+        };
+        let mut pid = Pid::new(gains);
+        // A setpoint step gives no kick
+        pid.update(0.0, 0.5, DT_S);
+        assert_eq!(pid.update(10.0, 0.5, DT_S), 0.0);
+        // A falling field does, growing as the smoothing catches up
+        let first = pid.update(10.0, 0.4, DT_S);
+        let second = pid.update(10.0, 0.3, DT_S);
+        assert!(first > 0.0 && second > first && second < 0.1, "{first} {second}");
+    }
+}
@@ -0,0 +1,162 @@
+//! Named calibration and configuration profiles, e.g. one per magnet type
+//! or per fixture. Each holds a whole [`Config`], calibration included,
+//! under a short name, in one of a few slots; switching to one makes its
+//! config the one in use. Each slot has a colour of its own, shown on the
+//! LED for a moment to tell which profile is active.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::color::RGB8;
+use crate::schema::Config;
+
+/// Slots for profiles.
+pub const MAX_PROFILES: usize = 4;
+
+/// Longest profile name, in bytes.
+pub const NAME_LEN: usize = 12;
+
+pub const ENCODED_SIZE: usize = crate::remote::ENCODED_SIZE + NAME_LEN + 4;
+
+// One per slot, none of them in the voltage gradient, which never has a
+// green component
+const COLORS: [RGB8; MAX_PROFILES] = [
+    RGB8 { r: 0, g: 255, b: 0 },
+    RGB8 {
+        r: 0,
+        g: 255,
+        b: 255,
+    },
+    RGB8 {
+        r: 255,
+        g: 255,
+        b: 0,
+    },
+    RGB8 {
+        r: 255,
+        g: 255,
+        b: 255,
+    },
+];
+
+/// Why a profile operation was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Error {
+    NotFound,
+    /// Every slot holds a profile of another name.
+    Full,
+    /// The state store is unavailable or failed; logged where it happened.
+    Storage,
+}
+
+impl Error {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::NotFound => "no such profile",
+            Self::Full => "no free slot, remove a profile first",
+            Self::Storage => "state store unavailable",
+        }
+    }
+}
+
+/// A profile's name: letters, digits, `-` and `_`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Name {
+    len: u8,
+    bytes: [u8; NAME_LEN],
+}
+
+impl Name {
+    pub fn parse(name: &str) -> Option<Self> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if name.is_empty() || name.len() > NAME_LEN || !name.chars().all(valid) {
+            return None;
+        }
+        let mut bytes = [0; NAME_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Self {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever made from a checked `&str`; a corrupt copy reads as empty
+        self.bytes
+            .get(..self.len as usize)
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .unwrap_or("")
+    }
+}
+
+impl Format for Name {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: Name,
+    pub config: Config,
+}
+
+/// The colour shown for the profile in `slot`.
+pub fn color(slot: usize) -> RGB8 {
+    COLORS[slot % MAX_PROFILES]
+}
+
+/// The slot holding `name` among `names`, by slot, or else the first free
+/// one to save it in.
+pub fn slot_for(names: &[Option<Name>; MAX_PROFILES], name: &Name) -> Result<usize, Error> {
+    names
+        .iter()
+        .position(|stored| stored.as_ref() == Some(name))
+        .or_else(|| names.iter().position(Option::is_none))
+        .ok_or(Error::Full)
+}
+
+/// The first occupied slot after `active`, wrapping round, for stepping
+/// through the profiles; `None` with none saved.
+pub fn next(names: &[Option<Name>; MAX_PROFILES], active: Option<usize>) -> Option<usize> {
+    let start = active.map_or(0, |slot| slot + 1);
+    (start..start + MAX_PROFILES)
+        .map(|slot| slot % MAX_PROFILES)
+        .find(|&slot| names[slot].is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Name {
+        Name::parse(name).unwrap()
+    }
+
+    #[test]
+    fn names_are_short_and_plain() {
+        assert_eq!(name("n52-disc").as_str(), "n52-disc");
+        assert_eq!(Name::parse(""), None);
+        assert_eq!(Name::parse("fixture one"), None);
+        assert_eq!(Name::parse("a_very_long_name"), None);
+    }
+
+    #[test]
+    fn saves_over_the_same_name_or_in_a_free_slot() {
+        let mut names = [Some(name("a")), None, Some(name("c")), None];
+        assert_eq!(slot_for(&names, &name("c")), Ok(2));
+        assert_eq!(slot_for(&names, &name("d")), Ok(1));
+        names[1] = Some(name("b"));
+        names[3] = Some(name("d"));
+        assert_eq!(slot_for(&names, &name("e")), Err(Error::Full));
+    }
+
+    #[test]
+    fn steps_through_the_saved_profiles() {
+        let names = [Some(name("a")), None, Some(name("c")), None];
+        assert_eq!(next(&names, None), Some(0));
+        assert_eq!(next(&names, Some(0)), Some(2));
+        assert_eq!(next(&names, Some(2)), Some(0));
+        assert_eq!(next(&[None; MAX_PROFILES], Some(1)), None);
+    }
+}
@@ -0,0 +1,415 @@
+//! Protobuf encoding of [`Message`], matching `proto/telemetry.proto`.
+//!
+//! Hand-written rather than generated: the schema is small and this keeps the
+//! build free of a codegen step. Zero-valued scalars are omitted, as proto3
+//! encoders do.
+
+use crate::schema::{Config, Event, Message, PowerStage, Sample, Tamper};
+
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub struct BufferFull;
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+const WIRE_FIXED32: u32 = 5;
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn byte(&mut self, b: u8) -> Result<(), BufferFull> {
+        *self.buf.get_mut(self.pos).ok_or(BufferFull)? = b;
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn varint(&mut self, mut v: u64) -> Result<(), BufferFull> {
+        while v >= 0x80 {
+            self.byte((v as u8) | 0x80)?;
+            v >>= 7;
+        }
+        self.byte(v as u8)
+    }
+
+    fn uint32(&mut self, field: u32, v: u32) -> Result<(), BufferFull> {
+        self.uint64(field, v as u64)
+    }
+
+    fn uint64(&mut self, field: u32, v: u64) -> Result<(), BufferFull> {
+        if v == 0 {
+            return Ok(());
+        }
+        self.varint(((field << 3) | WIRE_VARINT) as u64)?;
+        self.varint(v)
+    }
+
+    fn float(&mut self, field: u32, v: f32) -> Result<(), BufferFull> {
+        if v == 0.0 {
+            return Ok(());
+        }
+        self.varint(((field << 3) | WIRE_FIXED32) as u64)?;
+        v.to_le_bytes().into_iter().try_for_each(|b| self.byte(b))
+    }
+
+    fn bool(&mut self, field: u32, v: bool) -> Result<(), BufferFull> {
+        self.uint32(field, v as u32)
+    }
+
+    fn nested<M: Encode>(&mut self, field: u32, msg: &M) -> Result<(), BufferFull> {
+        self.varint(((field << 3) | WIRE_LEN) as u64)?;
+        self.varint(msg.encoded_len() as u64)?;
+        msg.encode_fields(self)
+    }
+}
+
+fn varint_len(v: u64) -> usize {
+    (64 - (v | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn uint32_len(field: u32, v: u32) -> usize {
+    uint64_len(field, v as u64)
+}
+
+fn uint64_len(field: u32, v: u64) -> usize {
+    if v == 0 {
+        0
+    } else {
+        varint_len((field << 3) as u64) + varint_len(v)
+    }
+}
+
+fn float_len(field: u32, v: f32) -> usize {
+    if v == 0.0 {
+        0
+    } else {
+        varint_len((field << 3) as u64) + 4
+    }
+}
+
+fn nested_len(field: u32, len: usize) -> usize {
+    varint_len((field << 3) as u64) + varint_len(len as u64) + len
+}
+
+trait Encode {
+    fn encoded_len(&self) -> usize;
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull>;
+}
+
+impl Encode for Sample {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.raw as u32)
+            + uint32_len(2, self.voltage_mv)
+            + uint64_len(3, self.timestamp_us)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.raw as u32)?;
+        w.uint32(2, self.voltage_mv)?;
+        w.uint64(3, self.timestamp_us)
+    }
+}
+
+struct Empty;
+
+impl Encode for Empty {
+    fn encoded_len(&self) -> usize {
+        0
+    }
+
+    fn encode_fields(&self, _: &mut Writer<'_>) -> Result<(), BufferFull> {
+        Ok(())
+    }
+}
+
+struct ThresholdCrossed {
+    threshold_mv: u32,
+    voltage_mv: u32,
+    rising: bool,
+}
+
+impl Encode for ThresholdCrossed {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.threshold_mv)
+            + uint32_len(2, self.voltage_mv)
+            + uint32_len(3, self.rising as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.threshold_mv)?;
+        w.uint32(2, self.voltage_mv)?;
+        w.bool(3, self.rising)
+    }
+}
+
+struct ClockSet {
+    unix_s: u64,
+}
+
+impl Encode for ClockSet {
+    fn encoded_len(&self) -> usize {
+        uint64_len(1, self.unix_s)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint64(1, self.unix_s)
+    }
+}
+
+struct LowSupply {
+    duration_ms: u32,
+}
+
+impl Encode for LowSupply {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.duration_ms)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.duration_ms)
+    }
+}
+
+struct WiringFault {
+    stuck_high: bool,
+}
+
+impl Encode for WiringFault {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.stuck_high as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.bool(1, self.stuck_high)
+    }
+}
+
+struct Battery {
+    voltage_mv: u32,
+    low: bool,
+}
+
+impl Encode for Battery {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.voltage_mv) + uint32_len(2, self.low as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.voltage_mv)?;
+        w.bool(2, self.low)
+    }
+}
+
+struct PowerStageChanged {
+    stage: PowerStage,
+    voltage_mv: u32,
+}
+
+impl Encode for PowerStageChanged {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.stage as u32) + uint32_len(2, self.voltage_mv)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.stage as u32)?;
+        w.uint32(2, self.voltage_mv)
+    }
+}
+
+struct Energy {
+    watts: f32,
+    energy_wh: f32,
+}
+
+impl Encode for Energy {
+    fn encoded_len(&self) -> usize {
+        float_len(1, self.watts) + float_len(2, self.energy_wh)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.float(1, self.watts)?;
+        w.float(2, self.energy_wh)
+    }
+}
+
+struct Contact {
+    closed: bool,
+}
+
+impl Encode for Contact {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.closed as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.bool(1, self.closed)
+    }
+}
+
+struct TamperAlarm {
+    cause: Tamper,
+}
+
+impl Encode for TamperAlarm {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.cause as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.cause as u32)
+    }
+}
+
+struct RuleChanged {
+    rule: u8,
+    met: bool,
+}
+
+impl Encode for RuleChanged {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.rule as u32) + uint32_len(2, self.met as u32)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.rule as u32)?;
+        w.bool(2, self.met)
+    }
+}
+
+impl Encode for Event {
+    fn encoded_len(&self) -> usize {
+        match *self {
+            Event::Boot => nested_len(1, 0),
+            Event::ThresholdCrossed {
+                threshold_mv,
+                voltage_mv,
+                rising,
+            } => nested_len(
+                2,
+                ThresholdCrossed {
+                    threshold_mv,
+                    voltage_mv,
+                    rising,
+                }
+                .encoded_len(),
+            ),
+            Event::ClockSet { unix_s } => nested_len(3, ClockSet { unix_s }.encoded_len()),
+            Event::LowSupply { duration_ms } => {
+                nested_len(4, LowSupply { duration_ms }.encoded_len())
+            }
+            Event::WiringFault { stuck_high } => {
+                nested_len(5, WiringFault { stuck_high }.encoded_len())
+            }
+            Event::WiringFaultCleared => nested_len(6, 0),
+            Event::Battery { voltage_mv, low } => {
+                nested_len(7, Battery { voltage_mv, low }.encoded_len())
+            }
+            Event::PowerStage { stage, voltage_mv } => {
+                nested_len(8, PowerStageChanged { stage, voltage_mv }.encoded_len())
+            }
+            Event::Energy { watts, energy_wh } => {
+                nested_len(9, Energy { watts, energy_wh }.encoded_len())
+            }
+            Event::Contact { closed } => nested_len(10, Contact { closed }.encoded_len()),
+            Event::Tamper { cause } => nested_len(11, TamperAlarm { cause }.encoded_len()),
+            Event::TamperCleared => nested_len(12, 0),
+            Event::Rule { rule, met } => nested_len(13, RuleChanged { rule, met }.encoded_len()),
+        }
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        match *self {
+            Event::Boot => w.nested(1, &Empty),
+            Event::ThresholdCrossed {
+                threshold_mv,
+                voltage_mv,
+                rising,
+            } => w.nested(
+                2,
+                &ThresholdCrossed {
+                    threshold_mv,
+                    voltage_mv,
+                    rising,
+                },
+            ),
+            Event::ClockSet { unix_s } => w.nested(3, &ClockSet { unix_s }),
+            Event::LowSupply { duration_ms } => w.nested(4, &LowSupply { duration_ms }),
+            Event::WiringFault { stuck_high } => w.nested(5, &WiringFault { stuck_high }),
+            Event::WiringFaultCleared => w.nested(6, &Empty),
+            Event::Battery { voltage_mv, low } => w.nested(7, &Battery { voltage_mv, low }),
+            Event::PowerStage { stage, voltage_mv } => {
+                w.nested(8, &PowerStageChanged { stage, voltage_mv })
+            }
+            Event::Energy { watts, energy_wh } => w.nested(9, &Energy { watts, energy_wh }),
+            Event::Contact { closed } => w.nested(10, &Contact { closed }),
+            Event::Tamper { cause } => w.nested(11, &TamperAlarm { cause }),
+            Event::TamperCleared => w.nested(12, &Empty),
+            Event::Rule { rule, met } => w.nested(13, &RuleChanged { rule, met }),
+        }
+    }
+}
+
+impl Encode for Config {
+    fn encoded_len(&self) -> usize {
+        uint32_len(1, self.min_voltage_mv)
+            + uint32_len(2, self.max_voltage_mv)
+            + uint32_len(3, self.sample_period_ms)
+            + uint32_len(4, self.zero_field_mv)
+            + float_len(5, self.sensitivity_mv_per_mt)
+            + uint32_len(6, self.threshold_mv)
+            + uint32_len(7, self.hysteresis_mv)
+            + uint32_len(8, self.fault_timeout_ms)
+            + uint32_len(9, self.low_battery_mv)
+            + uint32_len(10, self.battery_reduced_mv)
+            + uint32_len(11, self.battery_shutdown_mv)
+            + uint32_len(12, self.idle_sample_period_ms)
+            + float_len(13, self.activity_mt_per_s)
+            + uint32_len(14, self.report_delta_mv)
+            + uint32_len(15, self.report_heartbeat_ms)
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        w.uint32(1, self.min_voltage_mv)?;
+        w.uint32(2, self.max_voltage_mv)?;
+        w.uint32(3, self.sample_period_ms)?;
+        w.uint32(4, self.zero_field_mv)?;
+        w.float(5, self.sensitivity_mv_per_mt)?;
+        w.uint32(6, self.threshold_mv)?;
+        w.uint32(7, self.hysteresis_mv)?;
+        w.uint32(8, self.fault_timeout_ms)?;
+        w.uint32(9, self.low_battery_mv)?;
+        w.uint32(10, self.battery_reduced_mv)?;
+        w.uint32(11, self.battery_shutdown_mv)?;
+        w.uint32(12, self.idle_sample_period_ms)?;
+        w.float(13, self.activity_mt_per_s)?;
+        w.uint32(14, self.report_delta_mv)?;
+        w.uint32(15, self.report_heartbeat_ms)
+    }
+}
+
+impl Encode for Message {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Message::Sample(s) => nested_len(1, s.encoded_len()),
+            Message::Event(e) => nested_len(2, e.encoded_len()),
+            Message::Config(c) => nested_len(3, c.encoded_len()),
+        }
+    }
+
+    fn encode_fields(&self, w: &mut Writer<'_>) -> Result<(), BufferFull> {
+        match self {
+            Message::Sample(s) => w.nested(1, s),
+            Message::Event(e) => w.nested(2, e),
+            Message::Config(c) => w.nested(3, c),
+        }
+    }
+}
+
+/// Encodes `msg` as a `hall_effect.Message` and returns the bytes written.
+pub fn encode<'a>(msg: &Message, buf: &'a mut [u8]) -> Result<&'a mut [u8], BufferFull> {
+    let mut w = Writer { buf, pos: 0 };
+    msg.encode_fields(&mut w)?;
+    let len = w.pos;
+    Ok(&mut w.buf[..len])
+}
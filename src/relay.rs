@@ -0,0 +1,170 @@
+//! A relay or contactor switched by magnet presence: it pulls in once the
+//! field's strength reaches one threshold and drops out once it falls to
+//! another, lower one, and stays in for at least a set time so a magnet
+//! passing at the edge does not make it chatter.
+//!
+//! Without a trustworthy reading, a wiring fault or a sagging supply, the
+//! relay goes to its fail-safe state at once, whatever the on-time.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 16;
+
+/// What the relay does without a valid reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum FailSafe {
+    Off,
+    On,
+    /// Stays as it was.
+    Hold,
+}
+
+impl FailSafe {
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Off, Self::On, Self::Hold]
+            .into_iter()
+            .find(|state| state.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::On => "on",
+            Self::Hold => "hold",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// Thresholds on the field's strength, either pole. Drop-out is at or
+    /// below pull-in.
+    pub pull_in_mt: f32,
+    pub drop_out_mt: f32,
+    pub min_on_ms: u32,
+    pub fail_safe: FailSafe,
+}
+
+impl Settings {
+    /// A small magnet a few millimetres off an SS49E-class sensor, held in
+    /// for a quarter of a second, and off without a reading.
+    pub const DEFAULT: Self = Self {
+        pull_in_mt: 20.0,
+        drop_out_mt: 10.0,
+        min_on_ms: 250,
+        fail_safe: FailSafe::Off,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub struct Relay {
+    on: bool,
+    pulled_in_ms: u64,
+}
+
+impl Relay {
+    /// Off, as at boot.
+    pub const fn new() -> Self {
+        Self {
+            on: false,
+            pulled_in_ms: 0,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Takes a reading, `None` if it is not valid, and returns whether the
+    /// relay should be energised.
+    pub fn update(&mut self, time_ms: u64, field_mt: Option<f32>, settings: &Settings) -> bool {
+        let Some(field_mt) = field_mt else {
+            match settings.fail_safe {
+                FailSafe::Off => self.on = false,
+                FailSafe::On => self.switch_on(time_ms),
+                FailSafe::Hold => {}
+            }
+            return self.on;
+        };
+        let strength_mt = field_mt.abs();
+        if strength_mt >= settings.pull_in_mt {
+            self.switch_on(time_ms);
+        } else if self.on
+            && strength_mt <= settings.drop_out_mt
+            && time_ms - self.pulled_in_ms >= settings.min_on_ms as u64
+        {
+            self.on = false;
+        }
+        self.on
+    }
+
+    fn switch_on(&mut self, time_ms: u64) {
+        if !self.on {
+            self.on = true;
+            self.pulled_in_ms = time_ms;
+        }
+    }
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_with_hysteresis_on_either_pole() {
+        let settings = Settings {
+            min_on_ms: 0,
+            ..Settings::DEFAULT
+        };
+        let mut relay = Relay::new();
+        assert!(!relay.update(0, Some(15.0), &settings));
+        assert!(relay.update(1, Some(-25.0), &settings));
+        assert!(relay.update(2, Some(15.0), &settings));
+        assert!(!relay.update(3, Some(-10.0), &settings));
+        assert!(!relay.update(4, Some(19.0), &settings));
+    }
+
+    #[test]
+    fn stays_in_for_the_minimum_on_time() {
+        let settings = Settings::DEFAULT;
+        let mut relay = Relay::new();
+        assert!(relay.update(1000, Some(30.0), &settings));
+        assert!(relay.update(1100, Some(0.0), &settings));
+        // Pulling in again while held does not restart the time
+        assert!(relay.update(1200, Some(30.0), &settings));
+        assert!(relay.update(1249, Some(0.0), &settings));
+        assert!(!relay.update(1250, Some(0.0), &settings));
+    }
+
+    #[test]
+    fn goes_to_the_fail_safe_state_without_a_reading() {
+        let mut settings = Settings::DEFAULT;
+        let mut relay = Relay::new();
+        relay.update(0, Some(30.0), &settings);
+        // At once, within the on-time
+        assert!(!relay.update(10, None, &settings));
+
+        settings.fail_safe = FailSafe::On;
+        assert!(relay.update(20, None, &settings));
+        // On from the fault, held for the on-time like any other pull-in
+        assert!(relay.update(100, Some(0.0), &settings));
+        assert!(!relay.update(270, Some(0.0), &settings));
+
+        settings.fail_safe = FailSafe::Hold;
+        assert!(!relay.update(300, None, &settings));
+        relay.update(310, Some(30.0), &settings);
+        assert!(relay.update(320, None, &settings));
+    }
+}
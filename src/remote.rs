@@ -0,0 +1,307 @@
+//! Remote configuration: changes to the [`Config`] as `key=value` pairs,
+//! the payload a `.../config/set` topic would carry, taken as a whole or
+//! not at all. The changed config is checked for consistency before it is
+//! accepted, and the sender gets a JSON acknowledgement saying which, and
+//! whether a restart is needed for the change to reach everything.
+
+use core::fmt::{self, Write};
+
+use defmt::Format;
+
+use crate::schema::Config;
+
+pub const ENCODED_SIZE: usize = 80;
+
+/// Longest sample period, and so longest idle period, accepted.
+pub const MAX_SAMPLE_PERIOD_MS: u32 = 60_000;
+
+/// Why an update was refused; nothing is changed.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Rejection {
+    /// A pair without an `=`, or no pairs at all.
+    Malformed,
+    UnknownKey,
+    BadValue,
+    /// The values make no sense together, for this reason.
+    Inconsistent(&'static str),
+    /// Updates arriving faster than processing takes them.
+    Busy,
+}
+
+impl Rejection {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Malformed => "expected key=value pairs",
+            Self::UnknownKey => "unknown key",
+            Self::BadValue => "bad value",
+            Self::Inconsistent(reason) => reason,
+            Self::Busy => "busy, try again",
+        }
+    }
+}
+
+/// The settings to change, each `None` to leave as it is. Named as the
+/// [`Config`] fields they set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Format)]
+pub struct Update {
+    pub min_voltage_mv: Option<u32>,
+    pub max_voltage_mv: Option<u32>,
+    pub sample_period_ms: Option<u32>,
+    pub zero_field_mv: Option<u32>,
+    pub sensitivity_mv_per_mt: Option<f32>,
+    pub threshold_mv: Option<u32>,
+    pub hysteresis_mv: Option<u32>,
+    pub fault_timeout_ms: Option<u32>,
+    pub low_battery_mv: Option<u32>,
+    pub battery_reduced_mv: Option<u32>,
+    pub battery_shutdown_mv: Option<u32>,
+    pub idle_sample_period_ms: Option<u32>,
+    pub activity_mt_per_s: Option<f32>,
+    pub report_delta_mv: Option<u32>,
+    pub report_heartbeat_ms: Option<u32>,
+}
+
+impl Update {
+    /// Pairs separated by spaces, commas or new lines.
+    pub fn parse(payload: &str) -> Result<Self, Rejection> {
+        Self::from_pairs(
+            payload
+                .split(|c: char| c == ',' || c.is_ascii_whitespace())
+                .filter(|pair| !pair.is_empty()),
+        )
+    }
+
+    /// Pairs already split, as from the console's words.
+    pub fn from_pairs<'a>(pairs: impl Iterator<Item = &'a str>) -> Result<Self, Rejection> {
+        let mut update = Self::default();
+        let mut pairs = pairs.peekable();
+        if pairs.peek().is_none() {
+            return Err(Rejection::Malformed);
+        }
+        for pair in pairs {
+            let (key, value) = pair.split_once('=').ok_or(Rejection::Malformed)?;
+            let whole = || value.parse::<u32>().map_err(|_| Rejection::BadValue);
+            let real = || match value.parse::<f32>() {
+                Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+                _ => Err(Rejection::BadValue),
+            };
+            match key {
+                "min_voltage_mv" => update.min_voltage_mv = Some(whole()?),
+                "max_voltage_mv" => update.max_voltage_mv = Some(whole()?),
+                "sample_period_ms" => update.sample_period_ms = Some(whole()?),
+                "zero_field_mv" => update.zero_field_mv = Some(whole()?),
+                "sensitivity_mv_per_mt" => update.sensitivity_mv_per_mt = Some(real()?),
+                "threshold_mv" => update.threshold_mv = Some(whole()?),
+                "hysteresis_mv" => update.hysteresis_mv = Some(whole()?),
+                "fault_timeout_ms" => update.fault_timeout_ms = Some(whole()?),
+                "low_battery_mv" => update.low_battery_mv = Some(whole()?),
+                "battery_reduced_mv" => update.battery_reduced_mv = Some(whole()?),
+                "battery_shutdown_mv" => update.battery_shutdown_mv = Some(whole()?),
+                "idle_sample_period_ms" => update.idle_sample_period_ms = Some(whole()?),
+                "activity_mt_per_s" => update.activity_mt_per_s = Some(real()?),
+                "report_delta_mv" => update.report_delta_mv = Some(whole()?),
+                "report_heartbeat_ms" => update.report_heartbeat_ms = Some(whole()?),
+                _ => return Err(Rejection::UnknownKey),
+            }
+        }
+        Ok(update)
+    }
+
+    /// `config` with the changes, if the result is consistent.
+    pub fn apply(&self, config: &Config) -> Result<Config, Rejection> {
+        let config = Config {
+            min_voltage_mv: self.min_voltage_mv.unwrap_or(config.min_voltage_mv),
+            max_voltage_mv: self.max_voltage_mv.unwrap_or(config.max_voltage_mv),
+            sample_period_ms: self.sample_period_ms.unwrap_or(config.sample_period_ms),
+            zero_field_mv: self.zero_field_mv.unwrap_or(config.zero_field_mv),
+            sensitivity_mv_per_mt: self
+                .sensitivity_mv_per_mt
+                .unwrap_or(config.sensitivity_mv_per_mt),
+            threshold_mv: self.threshold_mv.unwrap_or(config.threshold_mv),
+            hysteresis_mv: self.hysteresis_mv.unwrap_or(config.hysteresis_mv),
+            fault_timeout_ms: self.fault_timeout_ms.unwrap_or(config.fault_timeout_ms),
+            low_battery_mv: self.low_battery_mv.unwrap_or(config.low_battery_mv),
+            battery_reduced_mv: self.battery_reduced_mv.unwrap_or(config.battery_reduced_mv),
+            battery_shutdown_mv: self
+                .battery_shutdown_mv
+                .unwrap_or(config.battery_shutdown_mv),
+            idle_sample_period_ms: self
+                .idle_sample_period_ms
+                .unwrap_or(config.idle_sample_period_ms),
+            activity_mt_per_s: self.activity_mt_per_s.unwrap_or(config.activity_mt_per_s),
+            report_delta_mv: self.report_delta_mv.unwrap_or(config.report_delta_mv),
+            report_heartbeat_ms: self
+                .report_heartbeat_ms
+                .unwrap_or(config.report_heartbeat_ms),
+        };
+        validate(&config)?;
+        Ok(config)
+    }
+
+    /// Whether the update changes settings the sampler, LED, displays and
+    /// loggers took at boot, so only reach them after a restart. The rest
+    /// apply to processing from the next sample.
+    pub fn needs_restart(&self) -> bool {
+        self.min_voltage_mv.is_some()
+            || self.max_voltage_mv.is_some()
+            || self.sample_period_ms.is_some()
+            || self.zero_field_mv.is_some()
+            || self.sensitivity_mv_per_mt.is_some()
+            || self.idle_sample_period_ms.is_some()
+    }
+}
+
+/// Checks that the settings are in range and agree with each other.
+pub fn validate(config: &Config) -> Result<(), Rejection> {
+    let range = config.min_voltage_mv..=config.max_voltage_mv;
+    if config.min_voltage_mv >= config.max_voltage_mv {
+        return Err(Rejection::Inconsistent("min_voltage_mv not below max"));
+    }
+    if !(1..=MAX_SAMPLE_PERIOD_MS).contains(&config.sample_period_ms) {
+        return Err(Rejection::Inconsistent("sample_period_ms out of range"));
+    }
+    if config.idle_sample_period_ms != 0
+        && !(config.sample_period_ms..=MAX_SAMPLE_PERIOD_MS).contains(&config.idle_sample_period_ms)
+    {
+        return Err(Rejection::Inconsistent(
+            "idle_sample_period_ms out of range",
+        ));
+    }
+    if !range.contains(&config.zero_field_mv) || !range.contains(&config.threshold_mv) {
+        return Err(Rejection::Inconsistent(
+            "zero or threshold outside the voltage range",
+        ));
+    }
+    if config.sensitivity_mv_per_mt <= 0.0 {
+        return Err(Rejection::Inconsistent("sensitivity not above zero"));
+    }
+    if config.hysteresis_mv >= config.max_voltage_mv - config.min_voltage_mv {
+        return Err(Rejection::Inconsistent("hysteresis_mv too wide"));
+    }
+    if config.fault_timeout_ms == 0 {
+        return Err(Rejection::Inconsistent("fault_timeout_ms is zero"));
+    }
+    // Each battery stage in use is at or below the one before it
+    let stages = [
+        config.low_battery_mv,
+        config.battery_reduced_mv,
+        config.battery_shutdown_mv,
+    ];
+    let mut above_mv = u32::MAX;
+    for stage_mv in stages.into_iter().filter(|&mv| mv != 0) {
+        if stage_mv > above_mv {
+            return Err(Rejection::Inconsistent("battery levels out of order"));
+        }
+        above_mv = stage_mv;
+    }
+    if config.report_delta_mv != 0 && config.report_heartbeat_ms == 0 {
+        return Err(Rejection::Inconsistent("report_heartbeat_ms is zero"));
+    }
+    Ok(())
+}
+
+/// The config as `key=value` lines, which [`Update::parse`] takes back.
+pub fn write_pairs<W: Write>(w: &mut W, config: &Config) -> fmt::Result {
+    writeln!(w, "min_voltage_mv={}", config.min_voltage_mv)?;
+    writeln!(w, "max_voltage_mv={}", config.max_voltage_mv)?;
+    writeln!(w, "sample_period_ms={}", config.sample_period_ms)?;
+    writeln!(w, "zero_field_mv={}", config.zero_field_mv)?;
+    writeln!(w, "sensitivity_mv_per_mt={}", config.sensitivity_mv_per_mt)?;
+    writeln!(w, "threshold_mv={}", config.threshold_mv)?;
+    writeln!(w, "hysteresis_mv={}", config.hysteresis_mv)?;
+    writeln!(w, "fault_timeout_ms={}", config.fault_timeout_ms)?;
+    writeln!(w, "low_battery_mv={}", config.low_battery_mv)?;
+    writeln!(w, "battery_reduced_mv={}", config.battery_reduced_mv)?;
+    writeln!(w, "battery_shutdown_mv={}", config.battery_shutdown_mv)?;
+    writeln!(w, "idle_sample_period_ms={}", config.idle_sample_period_ms)?;
+    writeln!(w, "activity_mt_per_s={}", config.activity_mt_per_s)?;
+    writeln!(w, "report_delta_mv={}", config.report_delta_mv)?;
+    writeln!(w, "report_heartbeat_ms={}", config.report_heartbeat_ms)
+}
+
+/// The acknowledgement, as one JSON object.
+pub fn write_ack<W: Write>(w: &mut W, result: Result<&Update, Rejection>) -> fmt::Result {
+    match result {
+        Ok(update) => write!(w, "{{\"ok\":true,\"restart\":{}}}", update.needs_restart()),
+        Err(rejection) => write!(w, "{{\"ok\":false,\"error\":\"{}\"}}", rejection.reason()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_pairs_as_a_whole() {
+        let update =
+            Update::parse("threshold_mv=2300, hysteresis_mv=60\nfault_timeout_ms=500").unwrap();
+        let config = update.apply(&Config::default()).unwrap();
+        assert_eq!(config.threshold_mv, 2300);
+        assert_eq!(config.hysteresis_mv, 60);
+        assert_eq!(config.fault_timeout_ms, 500);
+        assert_eq!(config.sample_period_ms, Config::default().sample_period_ms);
+        assert!(!update.needs_restart());
+
+        let update = Update::parse("sample_period_ms=5 sensitivity_mv_per_mt=2.5").unwrap();
+        assert_eq!(update.sensitivity_mv_per_mt, Some(2.5));
+        assert!(update.needs_restart());
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_take() {
+        assert_eq!(Update::parse(""), Err(Rejection::Malformed));
+        assert_eq!(Update::parse("threshold_mv"), Err(Rejection::Malformed));
+        assert_eq!(Update::parse("colour=red"), Err(Rejection::UnknownKey));
+        assert_eq!(Update::parse("threshold_mv=-1"), Err(Rejection::BadValue));
+        assert_eq!(
+            Update::parse("activity_mt_per_s=inf"),
+            Err(Rejection::BadValue)
+        );
+
+        let default = Config::default();
+        for payload in [
+            "threshold_mv=3000",
+            "min_voltage_mv=2800",
+            "sample_period_ms=0",
+            "idle_sample_period_ms=5",
+            "sensitivity_mv_per_mt=0",
+            "battery_reduced_mv=3600",
+            "report_delta_mv=10 report_heartbeat_ms=0",
+        ] {
+            let update = Update::parse(payload).unwrap();
+            assert!(
+                matches!(update.apply(&default), Err(Rejection::Inconsistent(_))),
+                "{}",
+                payload
+            );
+        }
+        // A stage switched off leaves the rest in order
+        let update = Update::parse("battery_reduced_mv=0").unwrap();
+        assert!(update.apply(&default).is_ok());
+    }
+
+    #[test]
+    fn pairs_read_back() {
+        let config = Config {
+            sensitivity_mv_per_mt: 2.5,
+            threshold_mv: 2300,
+            ..Config::default()
+        };
+        let mut pairs = String::new();
+        write_pairs(&mut pairs, &config).unwrap();
+        let update = Update::parse(&pairs).unwrap();
+        assert_eq!(update.apply(&Config::default()), Ok(config));
+    }
+
+    #[test]
+    fn acknowledges_in_json() {
+        let mut ack = String::new();
+        let update = Update::parse("zero_field_mv=1600").unwrap();
+        write_ack(&mut ack, Ok(&update)).unwrap();
+        assert_eq!(ack, "{\"ok\":true,\"restart\":true}");
+
+        ack.clear();
+        write_ack(&mut ack, Err(Rejection::UnknownKey)).unwrap();
+        assert_eq!(ack, "{\"ok\":false,\"error\":\"unknown key\"}");
+    }
+}
@@ -0,0 +1,72 @@
+//! Report-by-exception: a sample is only passed on when it differs from the
+//! last one passed on by more than `Config::report_delta_mv`, or when
+//! `Config::report_heartbeat_ms` has gone by without one.
+
+use crate::schema::{Config, Sample};
+
+pub struct ExceptionFilter {
+    /// Timestamp and voltage of the last sample passed on.
+    last: Option<(u64, u32)>,
+}
+
+impl ExceptionFilter {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Whether `sample` should be reported. Every sample is while
+    /// `report_delta_mv` is zero.
+    pub fn pass(&mut self, sample: &Sample, config: &Config) -> bool {
+        let pass = config.report_delta_mv == 0
+            || self.last.is_none_or(|(last_us, last_mv)| {
+                sample.voltage_mv.abs_diff(last_mv) > config.report_delta_mv
+                    || sample.timestamp_us - last_us >= config.report_heartbeat_ms as u64 * 1000
+            });
+        if pass {
+            self.last = Some((sample.timestamp_us, sample.voltage_mv));
+        }
+        pass
+    }
+}
+
+impl Default for ExceptionFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp_us: u64, voltage_mv: u32) -> Sample {
+        Sample {
+            timestamp_us,
+            raw: 0,
+            voltage_mv,
+        }
+    }
+
+    #[test]
+    fn passes_everything_without_a_delta() {
+        let config = Config::default();
+        let mut filter = ExceptionFilter::new();
+        assert!(filter.pass(&sample(0, 1650), &config));
+        assert!(filter.pass(&sample(1, 1650), &config));
+    }
+
+    #[test]
+    fn passes_changes_and_heartbeats() {
+        let config = Config {
+            report_delta_mv: 20,
+            report_heartbeat_ms: 1000,
+            ..Config::default()
+        };
+        let mut filter = ExceptionFilter::new();
+        assert!(filter.pass(&sample(0, 1650), &config));
+        assert!(!filter.pass(&sample(10_000, 1670), &config));
+        assert!(filter.pass(&sample(20_000, 1671), &config));
+        assert!(!filter.pass(&sample(1_019_999, 1671), &config));
+        assert!(filter.pass(&sample(1_020_000, 1671), &config));
+    }
+}
@@ -0,0 +1,293 @@
+//! Automation rules: "if the field is above 20mT for 2 seconds, set the
+//! output". Each rule has one condition on the field and one action, and
+//! is met once its condition has held for the rule's time, for as long as
+//! it goes on holding. A reading that is not valid holds no condition, so
+//! a wiring fault clears every rule.
+//!
+//! Rules keep their numbers, from 1, as others are removed; a new rule
+//! takes the first free one.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::color::RGB8;
+
+/// Rules kept at once.
+pub const MAX_RULES: usize = 4;
+
+pub const ENCODED_SIZE: usize = MAX_RULES * (RULE_SIZE + 1);
+
+// Tag, level and a varint time for the condition, tag and colour for the
+// action
+const RULE_SIZE: usize = 1 + 4 + 5 + 1 + 1;
+
+/// The rules by number, less one.
+pub type Rules = [Option<Rule>; MAX_RULES];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+impl Comparison {
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Above, Self::Below]
+            .into_iter()
+            .find(|comparison| comparison.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Above => "above",
+            Self::Below => "below",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Condition {
+    pub comparison: Comparison,
+    /// Signed by the pole, as readings are.
+    pub level_mt: f32,
+    /// How long it must hold before the rule is met; zero for at once.
+    pub for_ms: u32,
+}
+
+impl Condition {
+    fn holds(&self, field_mt: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => field_mt > self.level_mt,
+            Comparison::Below => field_mt < self.level_mt,
+        }
+    }
+}
+
+/// Colours an action can flash the LED in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Color {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Cyan,
+    Magenta,
+    White,
+}
+
+impl Color {
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            Self::Red,
+            Self::Green,
+            Self::Blue,
+            Self::Yellow,
+            Self::Cyan,
+            Self::Magenta,
+            Self::White,
+        ]
+        .into_iter()
+        .find(|color| color.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Blue => "blue",
+            Self::Yellow => "yellow",
+            Self::Cyan => "cyan",
+            Self::Magenta => "magenta",
+            Self::White => "white",
+        }
+    }
+
+    pub fn rgb(self) -> RGB8 {
+        match self {
+            Self::Red => RGB8::new(255, 0, 0),
+            Self::Green => RGB8::new(0, 255, 0),
+            Self::Blue => RGB8::new(0, 0, 255),
+            Self::Yellow => RGB8::new(255, 255, 0),
+            Self::Cyan => RGB8::new(0, 255, 255),
+            Self::Magenta => RGB8::new(255, 0, 255),
+            Self::White => RGB8::new(255, 255, 255),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Action {
+    /// Drive the rule output high while met.
+    Output,
+    /// Publish an event when met and when cleared.
+    Publish,
+    /// Flash the LED in this colour when met.
+    Flash(Color),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Rule {
+    pub condition: Condition,
+    pub action: Action,
+}
+
+/// Puts `rule` in the first free place, returning its number, or `None`
+/// with every place taken.
+pub fn add(rules: &mut Rules, rule: Rule) -> Option<usize> {
+    let index = rules.iter().position(Option::is_none)?;
+    rules[index] = Some(rule);
+    Some(index + 1)
+}
+
+/// Removes rule `number`, returning whether there was one.
+pub fn remove(rules: &mut Rules, number: usize) -> bool {
+    number
+        .checked_sub(1)
+        .and_then(|index| rules.get_mut(index))
+        .and_then(Option::take)
+        .is_some()
+}
+
+/// Evaluates the rules on each reading.
+pub struct Engine {
+    /// Since when each rule's condition has held.
+    since_ms: [Option<u64>; MAX_RULES],
+    met: [bool; MAX_RULES],
+}
+
+impl Engine {
+    /// Nothing met, as at boot or after the rules change.
+    pub const fn new() -> Self {
+        Self {
+            since_ms: [None; MAX_RULES],
+            met: [false; MAX_RULES],
+        }
+    }
+
+    pub fn is_met(&self, number: usize) -> bool {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.met.get(index))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Takes a reading, `None` if it is not valid, and returns, by rule
+    /// number less one, whether each rule that changed is now met.
+    pub fn update(
+        &mut self,
+        time_ms: u64,
+        field_mt: Option<f32>,
+        rules: &Rules,
+    ) -> [Option<bool>; MAX_RULES] {
+        let mut changes = [None; MAX_RULES];
+        for (index, rule) in rules.iter().enumerate() {
+            let holds = rule
+                .zip(field_mt)
+                .is_some_and(|(rule, field_mt)| rule.condition.holds(field_mt));
+            let since_ms = match (holds, self.since_ms[index]) {
+                (false, _) => None,
+                (true, since_ms) => Some(since_ms.unwrap_or(time_ms)),
+            };
+            self.since_ms[index] = since_ms;
+            let met = rule
+                .zip(since_ms)
+                .is_some_and(|(rule, since_ms)| time_ms - since_ms >= rule.condition.for_ms as u64);
+            if met != self.met[index] {
+                self.met[index] = met;
+                changes[index] = Some(met);
+            }
+        }
+        changes
+    }
+
+    /// Whether a met rule drives the output.
+    pub fn output(&self, rules: &Rules) -> bool {
+        rules
+            .iter()
+            .zip(self.met)
+            .any(|(rule, met)| met && rule.is_some_and(|rule| rule.action == Action::Output))
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(comparison: Comparison, level_mt: f32, for_ms: u32, action: Action) -> Rule {
+        Rule {
+            condition: Condition {
+                comparison,
+                level_mt,
+                for_ms,
+            },
+            action,
+        }
+    }
+
+    #[test]
+    fn met_once_the_condition_has_held_for_its_time() {
+        let rules = [
+            Some(rule(Comparison::Above, 20.0, 2000, Action::Output)),
+            None,
+            None,
+            None,
+        ];
+        let mut engine = Engine::new();
+        assert_eq!(engine.update(0, Some(25.0), &rules)[0], None);
+        assert_eq!(engine.update(1999, Some(25.0), &rules)[0], None);
+        assert_eq!(engine.update(2000, Some(25.0), &rules)[0], Some(true));
+        assert!(engine.output(&rules));
+        // Only reported on the change
+        assert_eq!(engine.update(2100, Some(30.0), &rules)[0], None);
+        assert_eq!(engine.update(2200, Some(20.0), &rules)[0], Some(false));
+        assert!(!engine.output(&rules));
+
+        // A dip starts the time over
+        engine.update(3000, Some(25.0), &rules);
+        engine.update(4000, Some(15.0), &rules);
+        engine.update(4500, Some(25.0), &rules);
+        assert_eq!(engine.update(6000, Some(25.0), &rules)[0], None);
+        assert_eq!(engine.update(6500, Some(25.0), &rules)[0], Some(true));
+    }
+
+    #[test]
+    fn a_reading_that_is_not_valid_clears_every_rule() {
+        let rules = [
+            None,
+            Some(rule(Comparison::Below, -5.0, 0, Action::Publish)),
+            None,
+            None,
+        ];
+        let mut engine = Engine::new();
+        assert_eq!(engine.update(0, Some(-6.0), &rules)[1], Some(true));
+        assert!(engine.is_met(2));
+        assert_eq!(engine.update(1, None, &rules)[1], Some(false));
+        // Publishing is no output
+        engine.update(2, Some(-6.0), &rules);
+        assert!(!engine.output(&rules));
+    }
+
+    #[test]
+    fn rules_keep_their_numbers() {
+        let flash = rule(Comparison::Above, 1.0, 0, Action::Flash(Color::Red));
+        let mut rules = [None; MAX_RULES];
+        assert_eq!(add(&mut rules, flash), Some(1));
+        assert_eq!(add(&mut rules, flash), Some(2));
+        assert_eq!(add(&mut rules, flash), Some(3));
+        assert!(remove(&mut rules, 2));
+        assert!(!remove(&mut rules, 2));
+        assert!(!remove(&mut rules, 0));
+        assert_eq!(rules[2], Some(flash));
+        assert_eq!(add(&mut rules, flash), Some(2));
+        assert_eq!(add(&mut rules, flash), Some(4));
+        assert_eq!(add(&mut rules, flash), None);
+        assert_eq!(Color::parse("magenta"), Some(Color::Magenta));
+    }
+}
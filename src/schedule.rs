@@ -0,0 +1,271 @@
+//! Actions run at set times, from cron-like entries: minute, hour, day of
+//! the month, month and day of the week (0 for Sunday), each `*` for any,
+//! a number, or `*/n` for every n-th from the first. `0 3 * * *` is 03:00
+//! every day, `*/15 * * * 1` every quarter hour on Mondays. Times are UTC,
+//! as the wall clock is, and entries only run once it is set.
+//!
+//! An entry runs once in each minute it matches; a minute skipped by
+//! setting the clock forward is not made up. Entries keep their numbers,
+//! from 1, as others are removed, as rules do.
+
+use core::fmt;
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::datetime::DateTime;
+
+/// Entries kept at once.
+pub const MAX_ENTRIES: usize = 4;
+
+pub const ENCODED_SIZE: usize = MAX_ENTRIES * (ENTRY_SIZE + 1);
+
+// Tag and value for each of the five fields, and the task's tag
+const ENTRY_SIZE: usize = 5 * 2 + 1;
+
+/// The entries by number, less one.
+pub type Entries = [Option<Entry>; MAX_ENTRIES];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Field {
+    Any,
+    At(u8),
+    /// Every n-th value from the field's first.
+    Every(u8),
+}
+
+impl Field {
+    /// A field whose values run from `first` to `last`.
+    fn parse(word: &str, first: u8, last: u8) -> Option<Self> {
+        if word == "*" {
+            return Some(Self::Any);
+        }
+        let field = match word.strip_prefix("*/") {
+            Some(step) => Self::Every(step.parse().ok()?),
+            None => Self::At(word.parse().ok()?),
+        };
+        let valid = match field {
+            Self::Any => true,
+            Self::At(value) => (first..=last).contains(&value),
+            Self::Every(step) => (1..=last - first + 1).contains(&step),
+        };
+        valid.then_some(field)
+    }
+
+    fn matches(self, value: u8, first: u8) -> bool {
+        match self {
+            Self::Any => true,
+            Self::At(at) => value == at,
+            Self::Every(step) => step > 0 && (value - first).is_multiple_of(step),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "*"),
+            Self::At(value) => write!(f, "{}", value),
+            Self::Every(step) => write!(f, "*/{}", step),
+        }
+    }
+}
+
+/// When an entry runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub struct When {
+    pub minute: Field,
+    pub hour: Field,
+    pub day: Field,
+    pub month: Field,
+    pub weekday: Field,
+}
+
+impl When {
+    /// From the five fields, in cron's order.
+    pub fn parse(words: [&str; 5]) -> Option<Self> {
+        let [minute, hour, day, month, weekday] = words;
+        Some(Self {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day: Field::parse(day, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            weekday: Field::parse(weekday, 0, 6)?,
+        })
+    }
+
+    /// Whether the minute holding `unix_s` matches.
+    pub fn matches(&self, unix_s: u64) -> bool {
+        let time = DateTime::from_unix(unix_s);
+        // 1970-01-01 was a Thursday
+        let weekday = ((unix_s / 86_400 + 4) % 7) as u8;
+        self.minute.matches(time.minute, 0)
+            && self.hour.matches(time.hour, 0)
+            && self.day.matches(time.day, 1)
+            && self.month.matches(time.month, 1)
+            && self.weekday.matches(weekday, 0)
+    }
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.minute, self.hour, self.day, self.month, self.weekday
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Task {
+    /// Start the displays' minimum and maximum over.
+    ResetRange,
+    /// Write out the samples the flash log holds in RAM.
+    FlushLog,
+    /// Run the sensor self-test on the next samples.
+    SelfTest,
+}
+
+impl Task {
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::ResetRange, Self::FlushLog, Self::SelfTest]
+            .into_iter()
+            .find(|task| task.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ResetRange => "reset-range",
+            Self::FlushLog => "flush-log",
+            Self::SelfTest => "self-test",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub struct Entry {
+    pub when: When,
+    pub task: Task,
+}
+
+/// Puts `entry` in the first free place, returning its number, or `None`
+/// with every place taken.
+pub fn add(entries: &mut Entries, entry: Entry) -> Option<usize> {
+    let index = entries.iter().position(Option::is_none)?;
+    entries[index] = Some(entry);
+    Some(index + 1)
+}
+
+/// Removes entry `number`, returning whether there was one.
+pub fn remove(entries: &mut Entries, number: usize) -> bool {
+    number
+        .checked_sub(1)
+        .and_then(|index| entries.get_mut(index))
+        .and_then(Option::take)
+        .is_some()
+}
+
+pub struct Scheduler {
+    /// The last minute checked, since the epoch.
+    checked_minute: Option<u64>,
+}
+
+impl Scheduler {
+    pub const fn new() -> Self {
+        Self {
+            checked_minute: None,
+        }
+    }
+
+    /// Takes the Unix time, returning by entry number less one whether
+    /// each entry is due; each minute is only checked once.
+    pub fn due(&mut self, unix_s: u64, entries: &Entries) -> [bool; MAX_ENTRIES] {
+        let minute = unix_s / 60;
+        if self.checked_minute.replace(minute) == Some(minute) {
+            return [false; MAX_ENTRIES];
+        }
+        entries.map(|entry| entry.is_some_and(|entry| entry.when.matches(unix_s)))
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn when(words: &str) -> When {
+        let words: Vec<&str> = words.split(' ').collect();
+        When::parse(words.try_into().unwrap()).unwrap()
+    }
+
+    fn unix(s: &str) -> u64 {
+        DateTime::parse(s).unwrap().to_unix()
+    }
+
+    #[test]
+    fn parses_cron_fields() {
+        assert_eq!(when("*/15 3 * * 1").to_string(), "*/15 3 * * 1");
+        assert_eq!(When::parse(["60", "*", "*", "*", "*"]), None);
+        assert_eq!(When::parse(["*", "*", "0", "*", "*"]), None);
+        assert_eq!(When::parse(["*/0", "*", "*", "*", "*"]), None);
+        assert_eq!(When::parse(["*", "*", "*", "*", "7"]), None);
+        assert_eq!(Task::parse("flush-log"), Some(Task::FlushLog));
+    }
+
+    #[test]
+    fn matches_the_minutes_it_names() {
+        let nightly = when("0 3 * * *");
+        assert!(nightly.matches(unix("2025-01-31 03:00:00")));
+        assert!(nightly.matches(unix("2025-01-31 03:00:59")));
+        assert!(!nightly.matches(unix("2025-01-31 03:01:00")));
+
+        // 2025-02-03 was a Monday
+        let mondays = when("*/15 * * * 1");
+        assert!(mondays.matches(unix("2025-02-03 17:45:00")));
+        assert!(!mondays.matches(unix("2025-02-03 17:50:00")));
+        assert!(!mondays.matches(unix("2025-02-04 17:45:00")));
+
+        // Every other day from the first
+        let odd_days = when("0 0 */2 * *");
+        assert!(odd_days.matches(unix("2025-03-01 00:00:00")));
+        assert!(!odd_days.matches(unix("2025-03-02 00:00:00")));
+        assert!(odd_days.matches(unix("2025-03-03 00:00:00")));
+    }
+
+    #[test]
+    fn runs_each_entry_once_a_minute() {
+        let entry = Entry {
+            when: when("* * * * *"),
+            task: Task::SelfTest,
+        };
+        let entries = [None, Some(entry), None, None];
+        let mut scheduler = Scheduler::new();
+        let start = unix("2025-01-31 12:00:00");
+        assert_eq!(scheduler.due(start, &entries), [false, true, false, false]);
+        assert_eq!(scheduler.due(start + 30, &entries), [false; MAX_ENTRIES]);
+        assert_eq!(
+            scheduler.due(start + 60, &entries),
+            [false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn entries_keep_their_numbers() {
+        let entry = Entry {
+            when: when("0 * * * *"),
+            task: Task::FlushLog,
+        };
+        let mut entries = [None; MAX_ENTRIES];
+        assert_eq!(add(&mut entries, entry), Some(1));
+        assert_eq!(add(&mut entries, entry), Some(2));
+        assert!(remove(&mut entries, 1));
+        assert!(!remove(&mut entries, 5));
+        assert_eq!(add(&mut entries, entry), Some(1));
+    }
+}
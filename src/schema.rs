@@ -0,0 +1,228 @@
+//! Wire schema shared by every transport.
+//!
+//! Samples, events and configuration are serialized with postcard and framed
+//! with COBS, so a zero byte always marks the end of a message regardless of
+//! whether it travels over UART, MQTT or BLE.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::fixed;
+
+/// Largest encoded message, including the COBS overhead and frame delimiter.
+pub const MAX_MESSAGE_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Sample {
+    /// Monotonic time of the ADC conversion, in microseconds since boot.
+    pub timestamp_us: u64,
+    pub raw: u16,
+    pub voltage_mv: u32,
+}
+
+/// Load shed as the battery runs down, in order. Each stage keeps the
+/// measures of the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Format, Serialize, Deserialize)]
+pub enum PowerStage {
+    Normal,
+    /// The LED is dimmed.
+    Dimmed,
+    /// The LED is off, and samples are taken and published less often.
+    Reduced,
+    /// Deep sleep until the BOOT button is pressed, to protect the cell.
+    Shutdown,
+}
+
+impl PowerStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerStage::Normal => "normal",
+            PowerStage::Dimmed => "dimmed",
+            PowerStage::Reduced => "reduced",
+            PowerStage::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// What set off a tamper alarm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Tamper {
+    /// The bias magnet's field fell away.
+    Removed,
+    /// A field far stronger than the bias magnet's, or against it.
+    StrongField,
+    /// The sensor's readings became invalid, as with a cut wire.
+    Sensor,
+}
+
+impl Tamper {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tamper::Removed => "removed",
+            Tamper::StrongField => "strong_field",
+            Tamper::Sensor => "sensor",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Event {
+    Boot,
+    ThresholdCrossed {
+        threshold_mv: u32,
+        voltage_mv: u32,
+        rising: bool,
+    },
+    /// The wall clock was set; `unix_s` is the time at this event.
+    ClockSet {
+        unix_s: u64,
+    },
+    /// The supply dipped below the brown-out threshold for `duration_ms`.
+    LowSupply {
+        duration_ms: u32,
+    },
+    /// Readings have been pinned at a rail for longer than
+    /// `Config::fault_timeout_ms`; samples are invalid until cleared.
+    WiringFault {
+        stuck_high: bool,
+    },
+    WiringFaultCleared,
+    /// Periodic battery report, and on becoming low or recovering.
+    Battery {
+        voltage_mv: u32,
+        low: bool,
+    },
+    /// The battery moved to another stage of load shedding.
+    PowerStage {
+        stage: PowerStage,
+        voltage_mv: u32,
+    },
+    /// Periodic report in power mode: the power now and the energy total.
+    Energy {
+        watts: f32,
+        energy_wh: f32,
+    },
+    /// A contact, in contact mode, settled open or closed.
+    Contact {
+        closed: bool,
+    },
+    /// A tamper alarm went off in tamper mode, latched until cleared.
+    Tamper {
+        cause: Tamper,
+    },
+    TamperCleared,
+    /// Automation rule number `rule` was met, or stopped being met.
+    Rule {
+        rule: u8,
+        met: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Config {
+    pub min_voltage_mv: u32, // ~0.5V for strong north pole
+    pub max_voltage_mv: u32, // ~2.8V for strong south pole
+    pub sample_period_ms: u32,
+    pub zero_field_mv: u32, // sensor output with no field applied
+    pub sensitivity_mv_per_mt: f32,
+    pub threshold_mv: u32,
+    pub hysteresis_mv: u32,
+    /// How long readings may sit at a rail before it counts as a fault.
+    pub fault_timeout_ms: u32,
+    /// Battery voltage below which the battery counts as low, and the LED
+    /// is dimmed. Zero disables the stage, as for the two below.
+    pub low_battery_mv: u32,
+    /// Battery voltage below which the LED is switched off and sampling
+    /// slows down.
+    pub battery_reduced_mv: u32,
+    /// Battery voltage below which the device shuts down into deep sleep.
+    pub battery_shutdown_mv: u32,
+    /// Sample period while the field is quiet; `sample_period_ms` applies
+    /// while it is active. Zero always samples at the active rate.
+    pub idle_sample_period_ms: u32,
+    /// Rate of change of the field above which it counts as active.
+    pub activity_mt_per_s: f32,
+    /// Change since the last published sample needed to publish another.
+    /// Zero publishes every sample.
+    pub report_delta_mv: u32,
+    /// Longest time without a published sample while `report_delta_mv` is
+    /// in use.
+    pub report_heartbeat_ms: u32,
+}
+
+impl Config {
+    pub fn field_mt(&self, voltage_mv: u32) -> f32 {
+        if cfg!(feature = "fixed-point") {
+            return fixed::field_mt(voltage_mv, self).to_f32();
+        }
+        (voltage_mv as f32 - self.zero_field_mv as f32) / self.sensitivity_mv_per_mt
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_voltage_mv: 500,
+            max_voltage_mv: 2800,
+            sample_period_ms: 10,
+            zero_field_mv: 1650,
+            sensitivity_mv_per_mt: 14.0, // SS49E-class linear sensor
+            threshold_mv: 2200,
+            hysteresis_mv: 50,
+            fault_timeout_ms: 2000,
+            low_battery_mv: 3500, // single-cell Li-ion, about 10% left
+            battery_reduced_mv: 3400,
+            battery_shutdown_mv: 3300,
+            idle_sample_period_ms: 0,
+            activity_mt_per_s: 20.0,
+            report_delta_mv: 0,
+            report_heartbeat_ms: 60_000,
+        }
+    }
+}
+
+/// Envelope carried by all transports.
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Message {
+    Sample(Sample),
+    Event(Event),
+    Config(Config),
+}
+
+impl Message {
+    /// Serializes into `buf` as a single COBS frame terminated by a zero byte.
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> postcard::Result<&'a mut [u8]> {
+        postcard::to_slice_cobs(self, buf)
+    }
+
+    /// Decodes one COBS frame in place. `frame` may include the trailing zero.
+    pub fn decode(frame: &mut [u8]) -> postcard::Result<Self> {
+        postcard::from_bytes_cobs(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_from_voltage() {
+        let config = Config::default();
+        assert_eq!(config.field_mt(1650), 0.0);
+        assert_eq!(config.field_mt(1650 + 140), 10.0);
+        assert_eq!(config.field_mt(1650 - 280), -20.0);
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let message = Message::Event(Event::ThresholdCrossed {
+            threshold_mv: 2200,
+            voltage_mv: 2260,
+            rising: true,
+        });
+        let mut buf = [0u8; MAX_MESSAGE_SIZE];
+        let frame = message.encode(&mut buf).unwrap();
+        assert_eq!(frame.last(), Some(&0));
+        assert_eq!(Message::decode(frame).unwrap(), message);
+    }
+}
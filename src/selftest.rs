@@ -0,0 +1,236 @@
+//! Plausibility checks of the sensor and its wiring: a one-off check at boot
+//! and a monitor for readings that get pinned at a rail at runtime.
+//!
+//! Where a sample's `raw` reading tops out depends on the ADC: calibrated
+//! readings are in millivolts, up to about 1750 at 6 dB, while uncalibrated
+//! ones are 12-bit counts. The caller gives that full scale.
+
+use defmt::Format;
+
+use crate::schema::{Config, Event, Sample};
+
+/// Readings taken for the check.
+pub const SAMPLES: usize = 16;
+
+/// Checks made at boot before carrying on regardless, so that a magnet
+/// left by the sensor doesn't hold up the boot.
+pub const ATTEMPTS: u32 = 3;
+
+// Farthest the mean reading may be from the zero-field voltage: the spread
+// of a ratiometric sensor's quiescent output, and of its supply
+const QUIESCENT_TOLERANCE_MV: u32 = 250;
+
+// Readings within this share of the full scale from either end count as
+// the rail
+const RAIL_MARGIN_DIVISOR: u16 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Fault {
+    /// Every reading at ground: output shorted low or sensor unpowered.
+    StuckLow,
+    /// Every reading at the rail: output shorted high or ground open.
+    StuckHigh,
+    /// Mean quiescent voltage too far from `Config::zero_field_mv`: a
+    /// magnet near the sensor, or a sensor other than the one configured.
+    OutOfRange { voltage_mv: u32 },
+}
+
+impl Fault {
+    /// Number of LED blinks that identifies the fault.
+    pub fn blink_code(&self) -> u8 {
+        match self {
+            Fault::StuckLow => 1,
+            Fault::StuckHigh => 2,
+            Fault::OutOfRange { .. } => 3,
+        }
+    }
+}
+
+/// `Some(true)` for a reading at the supply rail, `Some(false)` at ground.
+fn rail(raw: u16, full_scale: u16) -> Option<bool> {
+    let margin = full_scale / RAIL_MARGIN_DIVISOR;
+    if raw <= margin {
+        Some(false)
+    } else if raw >= full_scale - margin {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Checks readings taken with no field applied. `full_scale` is the highest
+/// `raw` reading the ADC gives.
+pub fn check(samples: &[Sample], config: &Config, full_scale: u16) -> Result<(), Fault> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    if samples
+        .iter()
+        .all(|s| rail(s.raw, full_scale) == Some(false))
+    {
+        return Err(Fault::StuckLow);
+    }
+    if samples
+        .iter()
+        .all(|s| rail(s.raw, full_scale) == Some(true))
+    {
+        return Err(Fault::StuckHigh);
+    }
+
+    let sum: u64 = samples.iter().map(|s| s.voltage_mv as u64).sum();
+    let voltage_mv = (sum / samples.len() as u64) as u32;
+    if voltage_mv.abs_diff(config.zero_field_mv) > QUIESCENT_TOLERANCE_MV {
+        return Err(Fault::OutOfRange { voltage_mv });
+    }
+    Ok(())
+}
+
+/// Flags readings that stay at one rail for longer than
+/// `Config::fault_timeout_ms`. The fault clears on the first reading off
+/// the rail.
+pub struct RailMonitor {
+    /// Rail and time of the first reading in the current run at a rail.
+    pinned: Option<(bool, u64)>,
+    faulted: bool,
+    full_scale: u16,
+}
+
+impl RailMonitor {
+    /// `full_scale` is the highest `raw` reading the ADC gives.
+    pub const fn new(full_scale: u16) -> Self {
+        Self {
+            pinned: None,
+            faulted: false,
+            full_scale,
+        }
+    }
+
+    /// Whether samples should currently be treated as invalid.
+    pub fn is_faulted(&self) -> bool {
+        self.faulted
+    }
+
+    /// Returns an event when the fault is raised or cleared.
+    pub fn update(&mut self, sample: &Sample, config: &Config) -> Option<Event> {
+        let Some(high) = rail(sample.raw, self.full_scale) else {
+            self.pinned = None;
+            return core::mem::take(&mut self.faulted).then_some(Event::WiringFaultCleared);
+        };
+
+        let since_us = match self.pinned {
+            Some((rail, since_us)) if rail == high => since_us,
+            _ => {
+                self.pinned = Some((high, sample.timestamp_us));
+                sample.timestamp_us
+            }
+        };
+        let pinned_ms = (sample.timestamp_us - since_us) / 1000;
+        if !self.faulted && pinned_ms >= config.fault_timeout_ms as u64 {
+            self.faulted = true;
+            return Some(Event::WiringFault { stuck_high: high });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Calibrated millivolts at 6 dB, and 12-bit counts
+    const CALIBRATED: u16 = 1750;
+    const COUNTS: u16 = 4095;
+
+    fn sample(time_ms: u64, raw: u16) -> Sample {
+        Sample {
+            timestamp_us: time_ms * 1000,
+            raw,
+            voltage_mv: raw as u32 * 3300 / 4095,
+        }
+    }
+
+    fn readings(raw: u16, voltage_mv: u32) -> [Sample; SAMPLES] {
+        core::array::from_fn(|i| Sample {
+            voltage_mv,
+            ..sample(i as u64, raw)
+        })
+    }
+
+    #[test]
+    fn checks_the_quiescent_voltage() {
+        let config = Config::default();
+        assert_eq!(check(&[], &config, COUNTS), Ok(()));
+        assert_eq!(check(&readings(2048, 1650), &config, COUNTS), Ok(()));
+        assert_eq!(check(&readings(1200, 1850), &config, CALIBRATED), Ok(()));
+
+        // Within the LED's range, but a magnet's worth from zero
+        assert_eq!(
+            check(&readings(2600, 2100), &config, COUNTS),
+            Err(Fault::OutOfRange { voltage_mv: 2100 })
+        );
+        assert_eq!(
+            check(&readings(1000, 800), &config, COUNTS),
+            Err(Fault::OutOfRange { voltage_mv: 800 })
+        );
+    }
+
+    #[test]
+    fn finds_the_output_at_either_rail() {
+        let config = Config::default();
+        assert_eq!(
+            check(&readings(0, 0), &config, COUNTS),
+            Err(Fault::StuckLow)
+        );
+        assert_eq!(
+            check(&readings(20, 16), &config, CALIBRATED),
+            Err(Fault::StuckLow)
+        );
+        assert_eq!(
+            check(&readings(4095, 3300), &config, COUNTS),
+            Err(Fault::StuckHigh)
+        );
+        // Calibrated readings stop far short of 4095
+        assert_eq!(
+            check(&readings(1742, 1403), &config, CALIBRATED),
+            Err(Fault::StuckHigh)
+        );
+        assert_eq!(check(&readings(1742, 1403), &config, COUNTS), Ok(()));
+
+        // Only some readings at ground is noise, not a short
+        let mut samples = readings(0, 0);
+        samples[3] = Sample {
+            raw: 2048,
+            voltage_mv: 1650,
+            ..samples[3]
+        };
+        assert_eq!(
+            check(&samples, &config, COUNTS),
+            Err(Fault::OutOfRange { voltage_mv: 103 })
+        );
+    }
+
+    #[test]
+    fn flags_a_reading_pinned_at_either_rail() {
+        let config = Config::default();
+        for (raw, stuck_high) in [(CALIBRATED, true), (0, false)] {
+            let mut monitor = RailMonitor::new(CALIBRATED);
+            assert_eq!(monitor.update(&sample(0, 900), &config), None);
+            for time_ms in (100..2100).step_by(100) {
+                assert_eq!(monitor.update(&sample(time_ms, raw), &config), None);
+            }
+            assert!(!monitor.is_faulted());
+            assert_eq!(
+                monitor.update(&sample(2100, raw), &config),
+                Some(Event::WiringFault { stuck_high })
+            );
+            assert!(monitor.is_faulted());
+            assert_eq!(monitor.update(&sample(2200, raw), &config), None);
+
+            assert_eq!(
+                monitor.update(&sample(2300, 900), &config),
+                Some(Event::WiringFaultCleared)
+            );
+            assert!(!monitor.is_faulted());
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! A hobby servo as a needle gauge: the field maps onto the servo's travel
+//! over a set range, and the needle moves towards it no faster than a set
+//! rate, so noise and sudden steps do not make it jump.
+//!
+//! The servo takes a pulse every 20ms whose width sets the position, from
+//! about 1ms at one end to 2ms at the other; many go further, which the
+//! pulse range allows for.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+/// The frame a servo expects a pulse in.
+pub const PERIOD_US: u32 = 20_000;
+
+/// The usual travel between the pulse range's ends.
+pub const TRAVEL_DEG: f32 = 180.0;
+
+pub const ENCODED_SIZE: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// The field at the start of the travel, and at its end. The other way
+    /// round turns the needle the other way.
+    pub low_mt: f32,
+    pub high_mt: f32,
+    /// Pulse widths at the start and end of the travel.
+    pub min_pulse_us: u16,
+    pub max_pulse_us: u16,
+    /// Zero for no limit.
+    pub slew_deg_per_s: f32,
+}
+
+impl Settings {
+    /// Either pole of an SS49E-class sensor's range, centred, with the
+    /// standard pulses and a needle that crosses the dial in a second.
+    pub const DEFAULT: Self = Self {
+        low_mt: -100.0,
+        high_mt: 100.0,
+        min_pulse_us: 1000,
+        max_pulse_us: 2000,
+        slew_deg_per_s: 180.0,
+    };
+
+    /// Where `field_mt` falls in the travel, from 0 to 1.
+    pub fn position(&self, field_mt: f32) -> f32 {
+        let position = (field_mt - self.low_mt) / (self.high_mt - self.low_mt);
+        if position.is_nan() {
+            0.5
+        } else {
+            position.clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn pulse_us(&self, position: f32) -> f32 {
+        let span_us = self.max_pulse_us as f32 - self.min_pulse_us as f32;
+        self.min_pulse_us as f32 + position.clamp(0.0, 1.0) * span_us
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The needle's position, moved towards its target at a limited rate.
+pub struct Slew {
+    position: Option<f32>,
+}
+
+impl Slew {
+    pub const fn new() -> Self {
+        Self { position: None }
+    }
+
+    /// Moves towards `target`, from 0 to 1, over `dt_s`. The first target
+    /// is taken at once, as the needle's start is not known.
+    pub fn step(&mut self, target: f32, slew_deg_per_s: f32, dt_s: f32) -> f32 {
+        let position = match self.position {
+            Some(position) if slew_deg_per_s > 0.0 => {
+                let max_step = slew_deg_per_s / TRAVEL_DEG * dt_s;
+                position + (target - position).clamp(-max_step, max_step)
+            }
+            _ => target,
+        };
+        self.position = Some(position);
+        position
+    }
+}
+
+impl Default for Slew {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_maps_onto_the_travel() {
+        let settings = Settings::DEFAULT;
+        assert_eq!(settings.position(0.0), 0.5);
+        assert_eq!(settings.position(-250.0), 0.0);
+        assert_eq!(settings.pulse_us(settings.position(50.0)), 1750.0);
+
+        let reversed = Settings {
+            low_mt: 10.0,
+            high_mt: 0.0,
+            min_pulse_us: 500,
+            max_pulse_us: 2500,
+            ..Settings::DEFAULT
+        };
+        assert_eq!(reversed.position(2.5), 0.75);
+        assert_eq!(reversed.pulse_us(reversed.position(2.5)), 2000.0);
+    }
+
+    #[test]
+    fn needle_moves_at_the_slew_rate() {
+        let mut slew = Slew::new();
+        assert_eq!(slew.step(0.0, 90.0, 0.02), 0.0);
+        // A quarter of the travel a second: full travel takes four
+        let mut t = 0.0;
+        while slew.step(1.0, 45.0, 0.02) < 1.0 {
+            t += 0.02;
+            assert!(t < 4.1);
+        }
+        assert!(t > 3.9, "{t}");
+        // No limit
+        assert_eq!(slew.step(0.2, 0.0, 0.02), 0.2);
+    }
+}
@@ -0,0 +1,834 @@
+//! Every user setting in one document, kept as a whole in flash behind a
+//! schema version. postcard records no field names, so a firmware that
+//! adds or changes a setting bumps [`VERSION`] and teaches [`decode`] to
+//! read the previous layout and bring it up to date, rather than failing
+//! to read it and falling back to the defaults.
+//!
+//! The document is saved to two slots in turn, each copy numbered and
+//! checksummed, so a write cut short by a power loss leaves the copy
+//! before it to load; [`newest`] picks the copy to use.
+//!
+//! Each setting's module gives the most bytes it takes encoded with
+//! postcard as its `ENCODED_SIZE`, which the tests here check against
+//! the setting at its largest.
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::ambient::Settings as AmbientSettings;
+use crate::contact::Settings as ContactSettings;
+use crate::counting::Settings as CountSettings;
+use crate::current::Scale;
+use crate::filter::Chain;
+use crate::fixture::Limits as FixtureLimits;
+use crate::grading::Settings as GradeSettings;
+use crate::night::Settings as NightSettings;
+use crate::palette::Palette;
+use crate::pas::Settings as PasSettings;
+use crate::relay::Settings as RelaySettings;
+use crate::rules::Rules;
+use crate::schedule::Entries as Schedule;
+use crate::schema::Config;
+use crate::servo::Settings as ServoSettings;
+use crate::sync::Role as SyncRole;
+use crate::tamper::Settings as TamperSettings;
+use crate::trigger::Settings as TriggerSettings;
+use crate::units::Unit;
+use crate::x27::Scale as GaugeScale;
+
+/// The layout [`Document`] is written in.
+pub const VERSION: u16 = 9;
+
+/// Bytes of version and sequence number ahead of the document.
+const HEADER_SIZE: usize = 6;
+
+/// Bytes of checksum after it.
+const CRC_SIZE: usize = 4;
+
+/// Room for a [`Document`] with its header and checksum, encoded.
+pub const ENCODED_SIZE: usize = 512;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// The settings, each `None` until first set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    pub config: Option<Config>,
+    pub filters: Option<Chain>,
+    pub tare_mt: Option<f32>,
+    pub current_scale: Option<Scale>,
+    pub voltage_ratio: Option<f32>,
+    pub pas: Option<PasSettings>,
+    pub servo: Option<ServoSettings>,
+    pub gauge_scale: Option<GaugeScale>,
+    pub relay: Option<RelaySettings>,
+    pub contact: Option<ContactSettings>,
+    pub tamper: Option<TamperSettings>,
+    pub count: Option<CountSettings>,
+    pub fixture: Option<FixtureLimits>,
+    pub grading: Option<GradeSettings>,
+    pub trigger: Option<TriggerSettings>,
+    pub sync_role: Option<SyncRole>,
+    /// Slot of the profile last switched to.
+    pub profile: Option<u8>,
+    /// Unit readings are shown in.
+    pub unit: Option<Unit>,
+    pub rules: Option<Rules>,
+    pub schedule: Option<Schedule>,
+    pub night: Option<NightSettings>,
+    pub ambient: Option<AmbientSettings>,
+    pub palette: Option<Palette>,
+}
+
+/// The settings as version 1 laid them out. Each later version adds its
+/// own at the end, which an earlier copy leaves unset.
+#[derive(Deserialize)]
+struct V1 {
+    config: Option<Config>,
+    filters: Option<Chain>,
+    tare_mt: Option<f32>,
+    current_scale: Option<Scale>,
+    voltage_ratio: Option<f32>,
+    pas: Option<PasSettings>,
+    servo: Option<ServoSettings>,
+    gauge_scale: Option<GaugeScale>,
+    relay: Option<RelaySettings>,
+    contact: Option<ContactSettings>,
+    tamper: Option<TamperSettings>,
+    count: Option<CountSettings>,
+    fixture: Option<FixtureLimits>,
+    grading: Option<GradeSettings>,
+    trigger: Option<TriggerSettings>,
+    sync_role: Option<SyncRole>,
+}
+
+impl From<V1> for Document {
+    fn from(v1: V1) -> Self {
+        Self {
+            config: v1.config,
+            filters: v1.filters,
+            tare_mt: v1.tare_mt,
+            current_scale: v1.current_scale,
+            voltage_ratio: v1.voltage_ratio,
+            pas: v1.pas,
+            servo: v1.servo,
+            gauge_scale: v1.gauge_scale,
+            relay: v1.relay,
+            contact: v1.contact,
+            tamper: v1.tamper,
+            count: v1.count,
+            fixture: v1.fixture,
+            grading: v1.grading,
+            trigger: v1.trigger,
+            sync_role: v1.sync_role,
+            ..Self::default()
+        }
+    }
+}
+
+/// Version 2 numbered and checksummed each copy, but kept the settings as
+/// they were.
+type V2 = V1;
+
+/// Version 3 added the active profile.
+#[derive(Deserialize)]
+struct V3 {
+    earlier: V2,
+    profile: Option<u8>,
+}
+
+impl From<V3> for Document {
+    fn from(v3: V3) -> Self {
+        Self {
+            profile: v3.profile,
+            ..v3.earlier.into()
+        }
+    }
+}
+
+/// Version 4 added the unit readings are shown in.
+#[derive(Deserialize)]
+struct V4 {
+    earlier: V3,
+    unit: Option<Unit>,
+}
+
+impl From<V4> for Document {
+    fn from(v4: V4) -> Self {
+        Self {
+            unit: v4.unit,
+            ..v4.earlier.into()
+        }
+    }
+}
+
+/// Version 5 added the automation rules.
+#[derive(Deserialize)]
+struct V5 {
+    earlier: V4,
+    rules: Option<Rules>,
+}
+
+impl From<V5> for Document {
+    fn from(v5: V5) -> Self {
+        Self {
+            rules: v5.rules,
+            ..v5.earlier.into()
+        }
+    }
+}
+
+/// Version 6 added the schedule.
+#[derive(Deserialize)]
+struct V6 {
+    earlier: V5,
+    schedule: Option<Schedule>,
+}
+
+impl From<V6> for Document {
+    fn from(v6: V6) -> Self {
+        Self {
+            schedule: v6.schedule,
+            ..v6.earlier.into()
+        }
+    }
+}
+
+/// Version 7 added night mode.
+#[derive(Deserialize)]
+struct V7 {
+    earlier: V6,
+    night: Option<NightSettings>,
+}
+
+impl From<V7> for Document {
+    fn from(v7: V7) -> Self {
+        Self {
+            night: v7.night,
+            ..v7.earlier.into()
+        }
+    }
+}
+
+/// Version 8 added scaling the brightness with ambient light.
+#[derive(Deserialize)]
+struct V8 {
+    earlier: V7,
+    ambient: Option<AmbientSettings>,
+}
+
+impl From<V8> for Document {
+    fn from(v8: V8) -> Self {
+        Self {
+            ambient: v8.ambient,
+            ..v8.earlier.into()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum DecodeError {
+    /// Too short to hold a version.
+    Truncated,
+    /// Written by a later firmware, in a layout this one cannot read.
+    Newer(u16),
+    /// The checksum does not match, as after a write cut short.
+    Checksum,
+    /// A version this firmware knows, that would not decode.
+    Corrupt,
+}
+
+impl DecodeError {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Truncated => "truncated",
+            Self::Newer(_) => "newer",
+            Self::Checksum => "checksum",
+            Self::Corrupt => "corrupt",
+        }
+    }
+}
+
+/// One of the two slots the document is saved to in turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+        }
+    }
+}
+
+/// A copy of the document as read back, with its place in the sequence
+/// of saves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stored {
+    pub document: Document,
+    pub sequence: u32,
+}
+
+/// Which copy was loaded at boot, for the diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Loaded {
+    pub slot: Slot,
+    pub sequence: u32,
+    /// Why the other slot's copy could not be used, if there was one.
+    pub other_error: Option<DecodeError>,
+}
+
+/// Writes the version, `sequence`, the document and a checksum of them
+/// into `buf`, returning the part written.
+pub fn encode<'a>(
+    document: &Document,
+    sequence: u32,
+    buf: &'a mut [u8],
+) -> postcard::Result<&'a mut [u8]> {
+    if buf.len() < HEADER_SIZE + CRC_SIZE {
+        return Err(postcard::Error::SerializeBufferFull);
+    }
+    buf[..2].copy_from_slice(&VERSION.to_le_bytes());
+    buf[2..HEADER_SIZE].copy_from_slice(&sequence.to_le_bytes());
+    let body_end = buf.len() - CRC_SIZE;
+    let len = HEADER_SIZE + postcard::to_slice(document, &mut buf[HEADER_SIZE..body_end])?.len();
+    let crc = CRC32.checksum(&buf[..len]);
+    buf[len..len + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    Ok(&mut buf[..len + CRC_SIZE])
+}
+
+/// Reads a copy of this or an earlier version.
+pub fn decode(bytes: &[u8]) -> Result<Stored, DecodeError> {
+    let (version, rest) = bytes
+        .split_first_chunk::<2>()
+        .ok_or(DecodeError::Truncated)?;
+    let version = u16::from_le_bytes(*version);
+    let (body, sequence) = match version {
+        2..=VERSION => {
+            if bytes.len() < HEADER_SIZE + CRC_SIZE {
+                return Err(DecodeError::Truncated);
+            }
+            let (framed, crc) = bytes
+                .split_last_chunk::<CRC_SIZE>()
+                .ok_or(DecodeError::Truncated)?;
+            let (sequence, body) = framed[2..]
+                .split_first_chunk::<4>()
+                .ok_or(DecodeError::Truncated)?;
+            if CRC32.checksum(framed) != u32::from_le_bytes(*crc) {
+                return Err(DecodeError::Checksum);
+            }
+            (body, u32::from_le_bytes(*sequence))
+        }
+        // A single copy, without a sequence number or checksum
+        1 => (rest, 0),
+        version if version > VERSION => return Err(DecodeError::Newer(version)),
+        _ => return Err(DecodeError::Corrupt),
+    };
+    let document = match version {
+        VERSION => postcard::from_bytes(body),
+        8 => postcard::from_bytes::<V8>(body).map(Document::from),
+        7 => postcard::from_bytes::<V7>(body).map(Document::from),
+        6 => postcard::from_bytes::<V6>(body).map(Document::from),
+        5 => postcard::from_bytes::<V5>(body).map(Document::from),
+        4 => postcard::from_bytes::<V4>(body).map(Document::from),
+        3 => postcard::from_bytes::<V3>(body).map(Document::from),
+        // Versions 1 and 2
+        _ => postcard::from_bytes::<V2>(body).map(Document::from),
+    };
+    Ok(Stored {
+        document: document.map_err(|_| DecodeError::Corrupt)?,
+        sequence,
+    })
+}
+
+/// The later of the two slots' copies, or whichever could be read.
+pub fn newest(a: Option<Stored>, b: Option<Stored>) -> Option<(Slot, Stored)> {
+    match (a, b) {
+        // Later by sequence number, which may have wrapped
+        (Some(a), Some(b)) if (b.sequence.wrapping_sub(a.sequence) as i32) > 0 => {
+            Some((Slot::B, b))
+        }
+        (Some(a), _) => Some((Slot::A, a)),
+        (None, Some(b)) => Some((Slot::B, b)),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(sequence: u32) -> Stored {
+        Stored {
+            document: Document {
+                tare_mt: Some(sequence as f32),
+                ..Document::default()
+            },
+            sequence,
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let document = Document {
+            config: Some(Config::default()),
+            tare_mt: Some(-1.5),
+            sync_role: Some(SyncRole::Leader),
+            profile: Some(2),
+            unit: Some(Unit::Gauss),
+            rules: Some([None; crate::rules::MAX_RULES]),
+            schedule: Some([None; crate::schedule::MAX_ENTRIES]),
+            night: Some(NightSettings::DEFAULT),
+            ambient: Some(AmbientSettings::DEFAULT),
+            palette: Some(Palette::Blink),
+            ..Document::default()
+        };
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = encode(&document, 7, &mut buf).unwrap();
+        assert_eq!(encoded[..2], VERSION.to_le_bytes());
+        assert_eq!(
+            decode(encoded),
+            Ok(Stored {
+                document,
+                sequence: 7
+            })
+        );
+
+        // A bit flipped anywhere, as by a write cut short
+        let last = encoded.len() - 1;
+        encoded[last / 2] ^= 0x10;
+        assert_eq!(decode(encoded), Err(DecodeError::Checksum));
+    }
+
+    /// Appends `value` as postcard encodes it.
+    fn append<T: Serialize>(value: &T, buf: &mut [u8], len: &mut usize) {
+        *len += postcard::to_slice(value, &mut buf[*len..]).unwrap().len();
+    }
+
+    /// The settings `version` knew, as it laid them out: version 1's, then
+    /// one more for each version from 3 on.
+    fn layout(version: u16, document: &Document, buf: &mut [u8]) -> usize {
+        let v1 = (
+            document.config,
+            document.filters,
+            document.tare_mt,
+            document.current_scale,
+            document.voltage_ratio,
+            document.pas,
+            document.servo,
+            document.gauge_scale,
+            document.relay,
+            document.contact,
+            document.tamper,
+            document.count,
+            document.fixture,
+            document.grading,
+            (document.trigger, document.sync_role),
+        );
+        let mut len = 0;
+        append(&v1, buf, &mut len);
+        if version >= 3 {
+            append(&document.profile, buf, &mut len);
+        }
+        if version >= 4 {
+            append(&document.unit, buf, &mut len);
+        }
+        if version >= 5 {
+            append(&document.rules, buf, &mut len);
+        }
+        if version >= 6 {
+            append(&document.schedule, buf, &mut len);
+        }
+        if version >= 7 {
+            append(&document.night, buf, &mut len);
+        }
+        if version >= 8 {
+            append(&document.ambient, buf, &mut len);
+        }
+        if version >= 9 {
+            append(&document.palette, buf, &mut len);
+        }
+        len
+    }
+
+    /// A copy as version 1 wrote it, alone in its slot.
+    fn version_1(document: &Document, buf: &mut [u8]) -> usize {
+        buf[..2].copy_from_slice(&1u16.to_le_bytes());
+        2 + layout(1, document, &mut buf[2..])
+    }
+
+    /// A copy as `version`, from 2 on, saved it: numbered and checksummed.
+    fn saved(version: u16, document: &Document, sequence: u32, buf: &mut [u8]) -> usize {
+        buf[..2].copy_from_slice(&version.to_le_bytes());
+        buf[2..HEADER_SIZE].copy_from_slice(&sequence.to_le_bytes());
+        let len = HEADER_SIZE + layout(version, document, &mut buf[HEADER_SIZE..]);
+        let crc = CRC32.checksum(&buf[..len]);
+        buf[len..len + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+        len + CRC_SIZE
+    }
+
+    #[test]
+    fn reads_the_single_copy_of_version_1() {
+        let document = Document {
+            tare_mt: Some(2.0),
+            ..Document::default()
+        };
+        let mut buf = [0u8; ENCODED_SIZE];
+        let len = version_1(&document, &mut buf);
+        assert_eq!(
+            decode(&buf[..len]),
+            Ok(Stored {
+                document,
+                sequence: 0
+            })
+        );
+    }
+
+    /// A setting from each version.
+    fn every_version() -> Document {
+        Document {
+            tare_mt: Some(2.0),
+            sync_role: Some(SyncRole::Follower),
+            profile: Some(1),
+            unit: Some(Unit::Gauss),
+            rules: Some([None; crate::rules::MAX_RULES]),
+            schedule: Some([None; crate::schedule::MAX_ENTRIES]),
+            night: Some(NightSettings::DEFAULT),
+            ambient: Some(AmbientSettings::DEFAULT),
+            palette: Some(Palette::Blink),
+            ..Document::default()
+        }
+    }
+
+    #[test]
+    fn lays_out_the_document_as_the_latest_version() {
+        // A setting added without a version of its own would be left out
+        let document = every_version();
+        let mut buf = [0u8; ENCODED_SIZE];
+        let len = layout(VERSION, &document, &mut buf);
+        let mut expected = [0u8; ENCODED_SIZE];
+        assert_eq!(
+            &buf[..len],
+            postcard::to_slice(&document, &mut expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_earlier_layouts() {
+        let document = every_version();
+        // What each version knew of it
+        let mut known = Document {
+            profile: None,
+            unit: None,
+            rules: None,
+            schedule: None,
+            night: None,
+            ambient: None,
+            palette: None,
+            ..document
+        };
+        for version in 2..VERSION {
+            if version >= 3 {
+                known.profile = document.profile;
+            }
+            if version >= 4 {
+                known.unit = document.unit;
+            }
+            if version >= 5 {
+                known.rules = document.rules;
+            }
+            if version >= 6 {
+                known.schedule = document.schedule;
+            }
+            if version >= 7 {
+                known.night = document.night;
+            }
+            if version >= 8 {
+                known.ambient = document.ambient;
+            }
+            let mut buf = [0u8; ENCODED_SIZE];
+            let len = saved(version, &document, 9, &mut buf);
+            assert_eq!(
+                decode(&buf[..len]),
+                Ok(Stored {
+                    document: known,
+                    sequence: 9
+                }),
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_later_layouts() {
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = encode(&Document::default(), 0, &mut buf).unwrap();
+        encoded[..2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(decode(encoded), Err(DecodeError::Newer(VERSION + 1)));
+        assert_eq!(decode(&[2]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&0u16.to_le_bytes()), Err(DecodeError::Corrupt));
+    }
+
+    #[test]
+    fn picks_the_later_copy() {
+        assert_eq!(newest(None, None), None);
+        assert_eq!(newest(Some(stored(3)), None), Some((Slot::A, stored(3))));
+        assert_eq!(newest(None, Some(stored(3))), Some((Slot::B, stored(3))));
+        assert_eq!(
+            newest(Some(stored(3)), Some(stored(4))),
+            Some((Slot::B, stored(4)))
+        );
+        assert_eq!(
+            newest(Some(stored(5)), Some(stored(4))),
+            Some((Slot::A, stored(5)))
+        );
+        // Past the wrap, 0 follows u32::MAX
+        assert_eq!(
+            newest(Some(stored(0)), Some(stored(u32::MAX))),
+            Some((Slot::A, stored(0)))
+        );
+    }
+
+    /// Encodes `value` in `size` bytes, and reads it back.
+    fn round_trip<T>(value: T, size: usize)
+    where
+        T: Serialize + for<'a> Deserialize<'a> + PartialEq + core::fmt::Debug,
+    {
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = postcard::to_slice(&value, &mut buf[..size])
+            .unwrap_or_else(|e| panic!("{value:?} needs more than {size} bytes: {e}"));
+        assert_eq!(postcard::from_bytes::<T>(encoded).unwrap(), value);
+    }
+
+    // Every varint at its longest
+    const WIDEST_CONFIG: Config = Config {
+        min_voltage_mv: u32::MAX,
+        max_voltage_mv: u32::MAX,
+        sample_period_ms: u32::MAX,
+        zero_field_mv: u32::MAX,
+        sensitivity_mv_per_mt: f32::MAX,
+        threshold_mv: u32::MAX,
+        hysteresis_mv: u32::MAX,
+        fault_timeout_ms: u32::MAX,
+        low_battery_mv: u32::MAX,
+        battery_reduced_mv: u32::MAX,
+        battery_shutdown_mv: u32::MAX,
+        idle_sample_period_ms: u32::MAX,
+        activity_mt_per_s: f32::MAX,
+        report_delta_mv: u32::MAX,
+        report_heartbeat_ms: u32::MAX,
+    };
+
+    #[test]
+    fn each_setting_fits_its_size() {
+        use crate::capture::Edge;
+        use crate::filter::{MAX_STAGES, Stage};
+        use crate::night::TimeOfDay;
+        use crate::notch::Mains;
+        use crate::pas::{Curve, Point};
+        use crate::profile::{Name, Profile};
+        use crate::rules::{Action, Color, Comparison, Rule};
+        use crate::schedule::{Entry, Task, When};
+        use crate::schema::Tamper;
+        use crate::trigger::Condition;
+
+        round_trip(WIDEST_CONFIG, crate::remote::ENCODED_SIZE);
+        round_trip(
+            Profile {
+                name: Name::parse("fixture-12ab").unwrap(),
+                config: WIDEST_CONFIG,
+            },
+            crate::profile::ENCODED_SIZE,
+        );
+
+        // Notches take the most, at the frequencies the console accepts
+        let mut filters = Chain::DEFAULT;
+        for index in 0..MAX_STAGES {
+            let notch = Stage::Notch(Mains {
+                frequency_hz: 60,
+                harmonics: true,
+            });
+            assert!(filters.set(index, notch));
+        }
+        round_trip(filters, crate::filter::ENCODED_SIZE);
+
+        round_trip(
+            Scale {
+                zero_mv: f32::MAX,
+                mv_per_a: f32::MIN,
+            },
+            crate::current::ENCODED_SIZE,
+        );
+        let points = [0, 1, 2, 3].map(|i| Point {
+            cadence_rpm: u16::MAX - 3 + i,
+            percent: 100,
+        });
+        round_trip(
+            PasSettings {
+                magnets: u8::MAX,
+                curve: Curve::new(&points).unwrap(),
+            },
+            crate::pas::ENCODED_SIZE,
+        );
+        round_trip(
+            ServoSettings {
+                min_pulse_us: u16::MAX,
+                max_pulse_us: u16::MAX,
+                ..ServoSettings::DEFAULT
+            },
+            crate::servo::ENCODED_SIZE,
+        );
+        round_trip(
+            GaugeScale {
+                low_mt: f32::MIN,
+                high_mt: f32::MAX,
+                full_rpm: u32::MAX,
+            },
+            crate::x27::ENCODED_SIZE,
+        );
+        round_trip(
+            RelaySettings {
+                min_on_ms: u32::MAX,
+                fail_safe: crate::relay::FailSafe::Hold,
+                ..RelaySettings::DEFAULT
+            },
+            crate::relay::ENCODED_SIZE,
+        );
+        round_trip(
+            ContactSettings {
+                debounce_ms: u32::MAX,
+                ..ContactSettings::DEFAULT
+            },
+            crate::contact::ENCODED_SIZE,
+        );
+        round_trip(
+            TamperSettings {
+                expected_mt: -20.0,
+                tolerance_pct: u8::MAX,
+                hold_ms: u32::MAX,
+            },
+            crate::tamper::ENCODED_SIZE,
+        );
+        round_trip(Some(Tamper::StrongField), crate::tamper::ALARM_ENCODED_SIZE);
+        round_trip(
+            CountSettings {
+                dead_ms: u32::MAX,
+                batch: u32::MAX,
+                pulse_ms: u32::MAX,
+            },
+            crate::counting::ENCODED_SIZE,
+        );
+        round_trip(
+            FixtureLimits {
+                low_mt: f32::MIN,
+                high_mt: f32::MAX,
+                spread_mt: f32::MAX,
+                measure_ms: u32::MAX,
+            },
+            crate::fixture::ENCODED_SIZE,
+        );
+        round_trip(
+            GradeSettings {
+                distance_mm: f32::MAX,
+                shots: crate::grading::MAX_SHOTS,
+                grades: crate::grading::Grades::new(&[f32::MAX, 1e30, 1e20, 1e10]).unwrap(),
+            },
+            crate::grading::ENCODED_SIZE,
+        );
+        round_trip(
+            TriggerSettings {
+                condition: Condition::Crossing {
+                    level_mv: u32::MAX,
+                    edge: Edge::Either,
+                },
+                pulse_us: u32::MAX,
+                holdoff_ms: u32::MAX,
+            },
+            crate::trigger::ENCODED_SIZE,
+        );
+        round_trip(SyncRole::Follower, crate::sync::ENCODED_SIZE);
+        round_trip(Unit::Millitesla, crate::units::ENCODED_SIZE);
+
+        let rule = Rule {
+            condition: crate::rules::Condition {
+                comparison: Comparison::Below,
+                level_mt: -123.45,
+                for_ms: u32::MAX,
+            },
+            action: Action::Flash(Color::White),
+        };
+        let rules: Rules = [Some(rule); crate::rules::MAX_RULES];
+        round_trip(rules, crate::rules::ENCODED_SIZE);
+        let entry = Entry {
+            when: When::parse(["*/59", "23", "31", "12", "6"]).unwrap(),
+            task: Task::SelfTest,
+        };
+        let schedule: Schedule = [Some(entry); crate::schedule::MAX_ENTRIES];
+        round_trip(schedule, crate::schedule::ENCODED_SIZE);
+
+        round_trip(
+            NightSettings {
+                enabled: true,
+                start: TimeOfDay::parse("23:59").unwrap(),
+                end: TimeOfDay::parse("23:58").unwrap(),
+                brightness_pct: 100,
+            },
+            crate::night::ENCODED_SIZE,
+        );
+        round_trip(
+            AmbientSettings {
+                enabled: true,
+                smoothing_s: u16::MAX,
+                ..AmbientSettings::DEFAULT
+            },
+            crate::ambient::ENCODED_SIZE,
+        );
+        round_trip(Palette::Blink, crate::palette::ENCODED_SIZE);
+    }
+
+    #[test]
+    fn document_fits_when_encoded() {
+        // Each setting fits its size, as checked above, and takes a byte
+        // more for being optional
+        let sizes = [
+            crate::remote::ENCODED_SIZE,
+            crate::filter::ENCODED_SIZE,
+            size_of::<f32>(),
+            crate::current::ENCODED_SIZE,
+            size_of::<f32>(),
+            crate::pas::ENCODED_SIZE,
+            crate::servo::ENCODED_SIZE,
+            crate::x27::ENCODED_SIZE,
+            crate::relay::ENCODED_SIZE,
+            crate::contact::ENCODED_SIZE,
+            crate::tamper::ENCODED_SIZE,
+            crate::counting::ENCODED_SIZE,
+            crate::fixture::ENCODED_SIZE,
+            crate::grading::ENCODED_SIZE,
+            crate::trigger::ENCODED_SIZE,
+            crate::sync::ENCODED_SIZE,
+            size_of::<u8>(),
+            crate::units::ENCODED_SIZE,
+            crate::rules::ENCODED_SIZE,
+            crate::schedule::ENCODED_SIZE,
+            crate::night::ENCODED_SIZE,
+            crate::ambient::ENCODED_SIZE,
+            crate::palette::ENCODED_SIZE,
+        ];
+        let most = HEADER_SIZE + CRC_SIZE + sizes.iter().map(|size| size + 1).sum::<usize>();
+        assert!(most <= ENCODED_SIZE, "{} bytes", most);
+    }
+}
@@ -0,0 +1,215 @@
+//! Spectrum analysis: a radix-2 FFT of a block of voltages and the
+//! strongest peaks in it, for telling motor vibration or mains pickup in the
+//! sensor wiring from the field being measured.
+//!
+//! `core` has no trigonometry or square root, so both are approximated
+//! here, closely enough that they add nothing to a Hann window's leakage.
+
+use core::f32::consts::FRAC_PI_2;
+
+use defmt::Format;
+
+/// Block sizes the analysis accepts, powers of two from 256 to 1024.
+pub const MIN_POINTS: usize = 256;
+pub const MAX_POINTS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Peak {
+    /// Interpolated between bins.
+    pub frequency_hz: f32,
+    /// Of the sinusoid that would give this peak, in millivolts.
+    pub amplitude_mv: f32,
+}
+
+/// Whether `points` is a block size the analysis accepts.
+pub fn valid_points(points: usize) -> bool {
+    points.is_power_of_two() && (MIN_POINTS..=MAX_POINTS).contains(&points)
+}
+
+/// The `K` strongest peaks of `signal`, in millivolts sampled at `rate_hz`,
+/// strongest first. The mean is removed and a Hann window applied first.
+/// `signal` is overwritten, and `scratch` with it; both must be the same
+/// power-of-two length.
+pub fn peaks<const K: usize>(
+    signal: &mut [f32],
+    scratch: &mut [f32],
+    rate_hz: f32,
+) -> [Option<Peak>; K] {
+    let n = signal.len();
+    assert!(n.is_power_of_two() && scratch.len() == n);
+
+    let mean = signal.iter().sum::<f32>() / n as f32;
+    for (i, (re, im)) in signal.iter_mut().zip(scratch.iter_mut()).enumerate() {
+        let (_, cos) = sin_cos(i as f32 / n as f32);
+        *re = (*re - mean) * 0.5 * (1.0 - cos);
+        *im = 0.0;
+    }
+    fft(signal, scratch);
+
+    // Magnitudes of the positive frequencies, in place; the Hann window's
+    // gain of a half and the other half of the power in the negative
+    // frequencies cancel to 4/N
+    for i in 0..n / 2 {
+        signal[i] = sqrt(signal[i] * signal[i] + scratch[i] * scratch[i]) * 4.0 / n as f32;
+    }
+    let magnitude = &signal[..n / 2];
+
+    let mut found = [None::<Peak>; K];
+    for bin in 2..n / 2 - 1 {
+        let (before, at, after) = (magnitude[bin - 1], magnitude[bin], magnitude[bin + 1]);
+        if at <= before || at < after {
+            continue;
+        }
+        // A parabola through the three bins puts the peak between them
+        let curve = before - 2.0 * at + after;
+        let offset = if curve < 0.0 {
+            0.5 * (before - after) / curve
+        } else {
+            0.0
+        };
+        let peak = Peak {
+            frequency_hz: (bin as f32 + offset) * rate_hz / n as f32,
+            amplitude_mv: at - 0.25 * (before - after) * offset,
+        };
+        // Insert in order, dropping the weakest
+        if let Some(slot) = found
+            .iter()
+            .position(|p| p.is_none_or(|p| p.amplitude_mv < peak.amplitude_mv))
+        {
+            found[slot..].rotate_right(1);
+            found[slot] = Some(peak);
+        }
+    }
+    found
+}
+
+/// In-place iterative radix-2 FFT.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let (step_sin, step_cos) = sin_cos(1.0 / len as f32);
+        for start in (0..n).step_by(len) {
+            let (mut w_re, mut w_im) = (1.0, 0.0);
+            for k in 0..len / 2 {
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                // Turn the twiddle factor on by -1/len of a turn
+                (w_re, w_im) = (
+                    w_re * step_cos + w_im * step_sin,
+                    w_im * step_cos - w_re * step_sin,
+                );
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Sine and cosine of a non-negative angle in turns, from Taylor series
+/// over the eighth of a turn either side of the nearest quarter.
+pub(crate) fn sin_cos(turns: f32) -> (f32, f32) {
+    let quarters = turns * 4.0;
+    let nearest = (quarters + 0.5) as u32;
+    let x = (quarters - nearest as f32) * FRAC_PI_2;
+    let x2 = x * x;
+    let sin = x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))));
+    let cos = 1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0)));
+    match nearest % 4 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+/// Newton's method from the exponent-halving guess.
+pub(crate) fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1fc0_0000);
+    for _ in 0..3 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `amplitude_mv` at `frequency_hz` on a 1650mV offset.
+    fn tone(signal: &mut [f32], rate_hz: f32, frequency_hz: f32, amplitude_mv: f32) {
+        for (i, s) in signal.iter_mut().enumerate() {
+            let (sin, _) = sin_cos(frequency_hz * i as f32 / rate_hz % 1.0);
+            *s += amplitude_mv * sin;
+        }
+    }
+
+    #[test]
+    fn finds_two_tones() {
+        let mut signal = [1650.0; 1024];
+        let mut scratch = [0.0; 1024];
+        // Mains pickup under a motor's vibration, at a burst's 10kHz
+        tone(&mut signal, 10_000.0, 50.0, 20.0);
+        tone(&mut signal, 10_000.0, 733.0, 200.0);
+        let [first, second, _] = peaks::<3>(&mut signal, &mut scratch, 10_000.0);
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!((first.frequency_hz - 733.0).abs() < 2.0, "{first:?}");
+        assert!((first.amplitude_mv - 200.0).abs() < 10.0, "{first:?}");
+        assert!((second.frequency_hz - 50.0).abs() < 2.0, "{second:?}");
+        assert!((second.amplitude_mv - 20.0).abs() < 2.0, "{second:?}");
+    }
+
+    #[test]
+    fn flat_signal_has_no_peaks() {
+        let mut signal = [1650.0; 256];
+        let mut scratch = [0.0; 256];
+        assert_eq!(peaks::<2>(&mut signal, &mut scratch, 100.0), [None, None]);
+    }
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut re = [0.0; 8];
+        let mut im = [0.0; 8];
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        assert_eq!(re, [1.0; 8]);
+        assert_eq!(im, [0.0; 8]);
+    }
+
+    #[test]
+    fn approximations() {
+        for i in 0..=1000 {
+            let turns = i as f32 / 1000.0;
+            let angle = turns * core::f32::consts::TAU;
+            let (sin, cos) = sin_cos(turns);
+            assert!((sin - angle.sin()).abs() < 1e-6, "{turns}");
+            assert!((cos - angle.cos()).abs() < 1e-6, "{turns}");
+            let x = i as f32 * 7.3;
+            assert!((sqrt(x) - x.sqrt()).abs() <= x.sqrt() * 1e-6, "{x}");
+        }
+        assert!(valid_points(512));
+        assert!(!valid_points(128));
+        assert!(!valid_points(768));
+    }
+}
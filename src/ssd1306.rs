@@ -0,0 +1,140 @@
+//! Minimal driver for 128x64 SSD1306 OLED displays on I2C.
+
+use embedded_hal::i2c::I2c;
+
+use crate::framebuffer::FrameBuffer;
+
+const ADDRESS: u8 = 0x3c;
+
+pub const WIDTH: usize = 128;
+pub const PAGES: usize = 8;
+
+pub type Frame = FrameBuffer<WIDTH, PAGES>;
+
+// First byte of every transfer: the rest is commands, or display data
+const CONTROL_COMMAND: u8 = 0x00;
+const CONTROL_DATA: u8 = 0x40;
+
+#[rustfmt::skip]
+const INIT: [u8; 26] = [
+    CONTROL_COMMAND,
+    0xae,       // display off
+    0xd5, 0x80, // clock divider
+    0xa8, 0x3f, // multiplex ratio, 64 rows
+    0xd3, 0x00, // no display offset
+    0x40,       // start line 0
+    0x8d, 0x14, // charge pump on
+    0x20, 0x00, // horizontal addressing
+    0xa1,       // column 127 at the left
+    0xc8,       // scan from the bottom row up
+    0xda, 0x12, // alternative COM pin layout
+    0x81, 0xcf, // contrast
+    0xd9, 0xf1, // pre-charge period
+    0xdb, 0x40, // VCOMH level
+    0xa4,       // show the RAM contents
+    0xa6,       // not inverted
+    0xaf,       // display on
+];
+
+pub struct Ssd1306<I> {
+    i2c: I,
+    /// Each page as last sent, so that unchanged ones are skipped.
+    shown: [Option<[u8; WIDTH]>; PAGES],
+}
+
+impl<I: I2c> Ssd1306<I> {
+    pub fn new(i2c: I) -> Self {
+        Self {
+            i2c,
+            shown: [None; PAGES],
+        }
+    }
+
+    pub fn init(&mut self) -> Result<(), I::Error> {
+        self.shown = [None; PAGES];
+        self.i2c.write(ADDRESS, &INIT)
+    }
+
+    /// Sends page `page` of `frame`, unless the display shows it already.
+    /// Returns whether it was sent. Each page is a transfer of its own, so
+    /// the caller can let other work run in between.
+    pub fn flush_page(&mut self, frame: &Frame, page: usize) -> Result<bool, I::Error> {
+        let data = &frame.pages()[page];
+        if self.shown[page].as_ref() == Some(data) {
+            return Ok(false);
+        }
+        self.i2c.write(
+            ADDRESS,
+            &[
+                CONTROL_COMMAND,
+                0x21,
+                0,
+                WIDTH as u8 - 1,
+                0x22,
+                page as u8,
+                page as u8,
+            ],
+        )?;
+        let mut buf = [0u8; WIDTH + 1];
+        buf[0] = CONTROL_DATA;
+        buf[1..].copy_from_slice(data);
+        self.i2c.write(ADDRESS, &buf)?;
+        self.shown[page] = Some(*data);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    use super::*;
+
+    /// Counts the bytes written.
+    struct Bus(usize);
+
+    impl ErrorType for Bus {
+        type Error = Infallible;
+    }
+
+    impl I2c for Bus {
+        fn transaction(
+            &mut self,
+            _: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    self.0 += bytes.len();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn flush(display: &mut Ssd1306<Bus>, frame: &Frame) -> usize {
+        (0..PAGES)
+            .filter(|&page| display.flush_page(frame, page).unwrap())
+            .count()
+    }
+
+    #[test]
+    fn sends_only_the_pages_that_changed() {
+        let mut display = Ssd1306::new(Bus(0));
+        let mut frame = Frame::new();
+        assert_eq!(flush(&mut display, &frame), PAGES);
+        assert_eq!(display.i2c.0, PAGES * (7 + WIDTH + 1));
+
+        assert_eq!(flush(&mut display, &frame), 0);
+        frame.set(5, 20, true);
+        assert_eq!(flush(&mut display, &frame), 1);
+        frame.hline(0, WIDTH - 1, 63);
+        assert_eq!(flush(&mut display, &frame), 1);
+
+        // The display's memory is not known after a reset
+        display.init().unwrap();
+        assert_eq!(flush(&mut display, &frame), PAGES);
+    }
+}
@@ -0,0 +1,148 @@
+//! Minimal driver for SSD1680 e-paper controllers, as on the Waveshare
+//! 2.13" V4 panel (122x250), used in landscape.
+//!
+//! Refreshes take from a third of a second (partial) to a couple of seconds
+//! (full) while the controller holds BUSY high. The driver only issues the
+//! commands; waiting on BUSY and pulsing RESET are left to the caller, so
+//! they can be done without blocking.
+//!
+//! The frame is drawn in the page layout of [`FrameBuffer`] and turned into
+//! the controller's row layout, one bit per pixel with 1 for white, as it is
+//! sent.
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+
+use crate::framebuffer::FrameBuffer;
+
+/// Landscape size of the panel.
+pub const WIDTH: usize = 250;
+pub const HEIGHT: usize = 122;
+pub const PAGES: usize = HEIGHT.div_ceil(8);
+
+pub type Frame = FrameBuffer<WIDTH, PAGES>;
+
+// The controller's own rows run along the short side
+const ROW_BYTES: usize = HEIGHT.div_ceil(8);
+
+const CMD_DRIVER_OUTPUT: u8 = 0x01;
+const CMD_DEEP_SLEEP: u8 = 0x10;
+const CMD_DATA_ENTRY: u8 = 0x11;
+const CMD_SOFTWARE_RESET: u8 = 0x12;
+const CMD_TEMPERATURE_SENSOR: u8 = 0x18;
+const CMD_MASTER_ACTIVATION: u8 = 0x20;
+const CMD_UPDATE_CONTROL_1: u8 = 0x21;
+const CMD_UPDATE_CONTROL_2: u8 = 0x22;
+const CMD_WRITE_RAM: u8 = 0x24;
+const CMD_WRITE_BASE_RAM: u8 = 0x26;
+const CMD_BORDER: u8 = 0x3c;
+const CMD_X_WINDOW: u8 = 0x44;
+const CMD_Y_WINDOW: u8 = 0x45;
+const CMD_X_COUNTER: u8 = 0x4e;
+const CMD_Y_COUNTER: u8 = 0x4f;
+
+// Update sequences: full waveform with the internal temperature, and the
+// partial (differential) waveform
+const UPDATE_FULL: u8 = 0xf7;
+const UPDATE_PARTIAL: u8 = 0xff;
+
+// Border follows the white level for a full refresh and is left alone for a
+// partial one
+const BORDER_FULL: u8 = 0x05;
+const BORDER_PARTIAL: u8 = 0x80;
+
+pub struct Ssd1680<S, D> {
+    spi: S,
+    dc: D,
+}
+
+impl<S: SpiDevice, D: OutputPin<Error = Infallible>> Ssd1680<S, D> {
+    pub fn new(spi: S, dc: D) -> Self {
+        Self { spi, dc }
+    }
+
+    /// Wait for BUSY to go low afterwards.
+    pub fn software_reset(&mut self) -> Result<(), S::Error> {
+        self.command(CMD_SOFTWARE_RESET, &[])
+    }
+
+    /// Sets up the panel geometry and addressing. Needed after every
+    /// hardware reset, which is also what wakes the controller from deep
+    /// sleep.
+    pub fn configure(&mut self, partial: bool) -> Result<(), S::Error> {
+        let last_row = (WIDTH - 1) as u16;
+        let [row_lo, row_hi] = last_row.to_le_bytes();
+        let border = if partial { BORDER_PARTIAL } else { BORDER_FULL };
+
+        self.command(CMD_DRIVER_OUTPUT, &[row_lo, row_hi, 0x00])?;
+        // X then Y incrementing
+        self.command(CMD_DATA_ENTRY, &[0x03])?;
+        self.command(CMD_X_WINDOW, &[0x00, ROW_BYTES as u8 - 1])?;
+        self.command(CMD_Y_WINDOW, &[0x00, 0x00, row_lo, row_hi])?;
+        self.command(CMD_BORDER, &[border])?;
+        if !partial {
+            self.command(CMD_UPDATE_CONTROL_1, &[0x00, 0x80])?;
+            self.command(CMD_TEMPERATURE_SENSOR, &[0x80])?;
+        }
+        Ok(())
+    }
+
+    /// Loads `frame` into display RAM. With `base` it also becomes the image
+    /// later partial refreshes are made against.
+    pub fn write_frame(&mut self, frame: &Frame, base: bool) -> Result<(), S::Error> {
+        self.write_ram(CMD_WRITE_RAM, frame)?;
+        if base {
+            self.write_ram(CMD_WRITE_BASE_RAM, frame)?;
+        }
+        Ok(())
+    }
+
+    /// Starts showing the RAM contents. Wait for BUSY to go low afterwards.
+    pub fn refresh(&mut self, partial: bool) -> Result<(), S::Error> {
+        let sequence = if partial { UPDATE_PARTIAL } else { UPDATE_FULL };
+        self.command(CMD_UPDATE_CONTROL_2, &[sequence])?;
+        self.command(CMD_MASTER_ACTIVATION, &[])
+    }
+
+    /// Deep sleep keeps the display RAM, so a partial refresh can follow the
+    /// next hardware reset.
+    pub fn sleep(&mut self) -> Result<(), S::Error> {
+        self.command(CMD_DEEP_SLEEP, &[0x01])
+    }
+
+    fn write_ram(&mut self, command: u8, frame: &Frame) -> Result<(), S::Error> {
+        self.command(CMD_X_COUNTER, &[0x00])?;
+        self.command(CMD_Y_COUNTER, &[0x00, 0x00])?;
+        self.command(command, &[])?;
+
+        // Controller row `y` is landscape column `y`; its bits run from the
+        // bottom of the landscape frame to the top
+        let mut row = [0u8; ROW_BYTES];
+        for x in 0..WIDTH {
+            for (i, byte) in row.iter_mut().enumerate() {
+                *byte = 0;
+                for bit in 0..8 {
+                    let y = (HEIGHT - 1).checked_sub(i * 8 + bit);
+                    let black = y.is_some_and(|y| frame.get(x, y));
+                    if !black {
+                        *byte |= 0x80 >> bit;
+                    }
+                }
+            }
+            self.spi.write(&row)?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, command: u8, data: &[u8]) -> Result<(), S::Error> {
+        let Ok(()) = self.dc.set_low();
+        self.spi.write(&[command])?;
+        let Ok(()) = self.dc.set_high();
+        if !data.is_empty() {
+            self.spi.write(data)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! Sampling in step across boards over a shared sync line. The leader
+//! pulses the line at each of its sample ticks and followers sample on
+//! the pulse, so every board converts within an interrupt's latency of
+//! the others. The leader marks an epoch by keeping the line quiet for a
+//! few periods; every board numbers its samples from the first pulse
+//! after it, which lines their logs up sample for sample.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+pub const ENCODED_SIZE: usize = 4;
+
+/// Periods the leader keeps the line quiet to mark an epoch.
+pub const EPOCH_GAP_PERIODS: u64 = 4;
+
+/// Periods without a pulse before a follower samples on its own timer.
+pub const LOST_PERIODS: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Role {
+    /// Samples on its own timer and leaves the line alone.
+    Off,
+    Leader,
+    Follower,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Off, Self::Leader, Self::Follower]
+            .into_iter()
+            .find(|role| role.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Leader => "leader",
+            Self::Follower => "follower",
+        }
+    }
+}
+
+/// The leader's half: which ticks to pulse.
+pub struct Leader {
+    quiet: u64,
+    index: u32,
+}
+
+impl Leader {
+    /// Starts with an epoch.
+    pub const fn new() -> Self {
+        Self {
+            quiet: EPOCH_GAP_PERIODS,
+            index: 0,
+        }
+    }
+
+    /// Takes a tick, returning the sample's index to pulse for, or `None`
+    /// while marking the epoch.
+    pub fn tick(&mut self) -> Option<u32> {
+        if self.quiet > 0 {
+            self.quiet -= 1;
+            return None;
+        }
+        let index = self.index;
+        self.index = self.index.wrapping_add(1);
+        Some(index)
+    }
+}
+
+impl Default for Leader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The follower's half: numbers the pulses and notices when they stop.
+pub struct Follower {
+    last_pulse_us: u64,
+    index: Option<u32>,
+}
+
+impl Follower {
+    /// From `now_us`, so a leader starting a few periods later starts an
+    /// epoch, and one already running does not.
+    pub const fn new(now_us: u64) -> Self {
+        Self {
+            last_pulse_us: now_us,
+            index: None,
+        }
+    }
+
+    /// Takes a pulse, returning its sample's index, or `None` until an
+    /// epoch has been seen.
+    pub fn pulse(&mut self, timestamp_us: u64, period_us: u64) -> Option<u32> {
+        let gap_us = timestamp_us.saturating_sub(self.last_pulse_us);
+        self.last_pulse_us = timestamp_us;
+        self.index = if gap_us > (EPOCH_GAP_PERIODS - 1) * period_us {
+            Some(0)
+        } else {
+            self.index.map(|index| index.wrapping_add(1))
+        };
+        self.index
+    }
+
+    /// Whether the pulses are still pacing the samples at `now_us`; if
+    /// not, the follower's own timer takes over until they are back.
+    pub fn is_locked(&self, now_us: u64, period_us: u64) -> bool {
+        now_us.saturating_sub(self.last_pulse_us) < LOST_PERIODS * period_us
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD_US: u64 = 10_000;
+
+    #[test]
+    fn the_leader_marks_an_epoch_then_numbers_its_ticks() {
+        let mut leader = Leader::new();
+        let ticks: Vec<_> = (0..7).map(|_| leader.tick()).collect();
+        assert_eq!(ticks, [None, None, None, None, Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn followers_number_pulses_from_the_epoch() {
+        // Up before the leader, so its first pulse after the gap is 0
+        let mut follower = Follower::new(0);
+        let first_us = EPOCH_GAP_PERIODS * PERIOD_US;
+        assert_eq!(follower.pulse(first_us, PERIOD_US), Some(0));
+        assert_eq!(follower.pulse(first_us + PERIOD_US, PERIOD_US), Some(1));
+        assert!(follower.is_locked(first_us + PERIOD_US + 15_000, PERIOD_US));
+        assert!(!follower.is_locked(first_us + PERIOD_US + 20_000, PERIOD_US));
+
+        // Up after it, so nothing until the leader marks another epoch
+        let mut follower = Follower::new(1_000_000);
+        assert_eq!(follower.pulse(1_005_000, PERIOD_US), None);
+        assert_eq!(follower.pulse(1_015_000, PERIOD_US), None);
+        assert_eq!(follower.pulse(1_065_000, PERIOD_US), Some(0));
+        assert_eq!(follower.pulse(1_075_000, PERIOD_US), Some(1));
+    }
+
+    #[test]
+    fn parses_roles() {
+        for role in [Role::Off, Role::Leader, Role::Follower] {
+            assert_eq!(Role::parse(role.name()), Some(role));
+        }
+        assert_eq!(Role::parse("master"), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Tamper detection for magnetic security sensors. A bias magnet sits at a
+//! fixed distance from the sensor, and its field is learned. The field
+//! falling away means the magnet was moved or removed. A field far
+//! stronger than it, or against it, means an outside magnet is being used
+//! to fool the sensor, a common attack on reed and hall door sensors.
+//! Invalid readings, as from a cut wire, count as tampering too.
+//!
+//! A condition counts once it has held for the hold time, so noise and a
+//! knock do not set it off.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Tamper;
+
+pub const ENCODED_SIZE: usize = 16;
+
+/// Room for a latched alarm, or none, encoded with postcard.
+pub const ALARM_ENCODED_SIZE: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    /// The bias magnet's field, signed by its pole. Zero until learned.
+    pub expected_mt: f32,
+    /// How far the field may stray from it, in percent of it.
+    pub tolerance_pct: u8,
+    pub hold_ms: u32,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        expected_mt: 0.0,
+        tolerance_pct: 50,
+        hold_ms: 100,
+    };
+
+    pub fn is_learned(&self) -> bool {
+        self.expected_mt != 0.0
+    }
+
+    /// What is wrong with `field_mt`, if anything.
+    pub fn check(&self, field_mt: f32) -> Option<Tamper> {
+        if !self.is_learned() {
+            return None;
+        }
+        let tolerance = self.tolerance_pct as f32 / 100.0;
+        // 1 for the bias magnet alone, negative against it
+        let ratio = field_mt / self.expected_mt;
+        if ratio > 1.0 + tolerance || ratio < -tolerance {
+            Some(Tamper::StrongField)
+        } else if ratio < 1.0 - tolerance {
+            Some(Tamper::Removed)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub struct Detector {
+    /// The condition seen, and since when.
+    pending: Option<(Tamper, u64)>,
+}
+
+impl Detector {
+    pub const fn new() -> Self {
+        Self { pending: None }
+    }
+
+    /// Takes a reading, `None` if it is not valid, and returns the
+    /// condition once it has held for the hold time, for as long as it
+    /// lasts.
+    pub fn update(
+        &mut self,
+        time_ms: u64,
+        field_mt: Option<f32>,
+        settings: &Settings,
+    ) -> Option<Tamper> {
+        let condition = match field_mt {
+            Some(field_mt) => settings.check(field_mt),
+            None => Some(Tamper::Sensor),
+        };
+        let Some(condition) = condition else {
+            self.pending = None;
+            return None;
+        };
+        let since_ms = match self.pending {
+            Some((pending, since_ms)) if pending == condition => since_ms,
+            _ => time_ms,
+        };
+        self.pending = Some((condition, since_ms));
+        (time_ms - since_ms >= settings.hold_ms as u64).then_some(condition)
+    }
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEARNED: Settings = Settings {
+        expected_mt: -20.0,
+        ..Settings::DEFAULT
+    };
+
+    #[test]
+    fn checks_the_field_against_the_bias_magnet() {
+        assert_eq!(LEARNED.check(-20.0), None);
+        assert_eq!(LEARNED.check(-11.0), None);
+        assert_eq!(LEARNED.check(-29.0), None);
+        assert_eq!(LEARNED.check(-9.0), Some(Tamper::Removed));
+        assert_eq!(LEARNED.check(0.5), Some(Tamper::Removed));
+        assert_eq!(LEARNED.check(-31.0), Some(Tamper::StrongField));
+        // An outside magnet overpowering the bias magnet's pole
+        assert_eq!(LEARNED.check(11.0), Some(Tamper::StrongField));
+        // Nothing to check against yet
+        assert_eq!(Settings::DEFAULT.check(100.0), None);
+    }
+
+    #[test]
+    fn conditions_count_once_held() {
+        let mut detector = Detector::new();
+        assert_eq!(detector.update(0, Some(-5.0), &LEARNED), None);
+        assert_eq!(detector.update(50, Some(-20.0), &LEARNED), None);
+        assert_eq!(detector.update(60, Some(-5.0), &LEARNED), None);
+        // A change of condition starts the hold again
+        assert_eq!(detector.update(100, Some(-50.0), &LEARNED), None);
+        assert_eq!(detector.update(199, Some(-50.0), &LEARNED), None);
+        assert_eq!(
+            detector.update(200, Some(-50.0), &LEARNED),
+            Some(Tamper::StrongField)
+        );
+        assert_eq!(detector.update(250, None, &LEARNED), None);
+        assert_eq!(detector.update(350, None, &LEARNED), Some(Tamper::Sensor));
+    }
+}
@@ -0,0 +1,101 @@
+//! Threshold crossing detection with hysteresis.
+
+use crate::schema::{Config, Event};
+
+pub struct ThresholdDetector {
+    above: Option<bool>,
+}
+
+impl ThresholdDetector {
+    pub const fn new() -> Self {
+        Self { above: None }
+    }
+
+    /// Picks up where a detector that was on the `above` side left off, e.g.
+    /// across a deep sleep.
+    pub const fn resume(above: Option<bool>) -> Self {
+        Self { above }
+    }
+
+    /// Which side of the threshold the last crossing left the voltage on,
+    /// `None` before the first reading.
+    pub fn is_above(&self) -> Option<bool> {
+        self.above
+    }
+
+    /// Returns an event when the voltage leaves the hysteresis band on the
+    /// opposite side from the last crossing. The first reading only
+    /// establishes the initial side.
+    pub fn update(&mut self, voltage_mv: u32, config: &Config) -> Option<Event> {
+        let upper = config.threshold_mv.saturating_add(config.hysteresis_mv);
+        let lower = config.threshold_mv.saturating_sub(config.hysteresis_mv);
+
+        let above = match self.above {
+            None => {
+                self.above = Some(voltage_mv >= config.threshold_mv);
+                return None;
+            }
+            Some(false) if voltage_mv >= upper => true,
+            Some(true) if voltage_mv <= lower => false,
+            Some(_) => return None,
+        };
+
+        self.above = Some(above);
+        Some(Event::ThresholdCrossed {
+            threshold_mv: config.threshold_mv,
+            voltage_mv,
+            rising: above,
+        })
+    }
+}
+
+impl Default for ThresholdDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Threshold 2200mV with 50mV of hysteresis
+    fn crossings(voltages: &[u32]) -> Vec<bool> {
+        let config = Config::default();
+        let mut detector = ThresholdDetector::new();
+        voltages
+            .iter()
+            .filter_map(|&mv| match detector.update(mv, &config) {
+                Some(Event::ThresholdCrossed { rising, .. }) => Some(rising),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_reading_only_sets_the_side() {
+        assert!(crossings(&[2500]).is_empty());
+        assert!(crossings(&[1000]).is_empty());
+    }
+
+    #[test]
+    fn crossings_need_the_hysteresis() {
+        assert_eq!(crossings(&[2000, 2249, 2250, 2151, 2150]), [true, false]);
+    }
+
+    #[test]
+    fn no_chatter_inside_the_band() {
+        assert_eq!(crossings(&[2000, 2250, 2190, 2240, 2160, 2249]), [true]);
+    }
+
+    #[test]
+    fn resumes_on_the_saved_side() {
+        let config = Config::default();
+        let mut detector = ThresholdDetector::resume(Some(false));
+        assert!(matches!(
+            detector.update(2300, &config),
+            Some(Event::ThresholdCrossed { rising: true, .. })
+        ));
+        assert_eq!(detector.is_above(), Some(true));
+    }
+}
@@ -0,0 +1,100 @@
+//! Duration statistics for checking loop timing: min, mean, max and jitter
+//! over a reporting window.
+
+use defmt::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Summary {
+    pub count: u32,
+    pub min_us: u32,
+    pub mean_us: u32,
+    pub max_us: u32,
+    /// Standard deviation.
+    pub jitter_us: u32,
+}
+
+pub struct Window {
+    count: u32,
+    sum: u64,
+    sum_sq: u64,
+    min_us: u32,
+    max_us: u32,
+}
+
+impl Window {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0,
+            sum_sq: 0,
+            min_us: u32::MAX,
+            max_us: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration_us: u32) {
+        self.count += 1;
+        self.sum += duration_us as u64;
+        self.sum_sq += (duration_us as u64) * (duration_us as u64);
+        self.min_us = self.min_us.min(duration_us);
+        self.max_us = self.max_us.max(duration_us);
+    }
+
+    /// Summarises the window and starts a new one. `None` if nothing was
+    /// recorded.
+    pub fn take(&mut self) -> Option<Summary> {
+        let window = core::mem::take(self);
+        let count = window.count as u64;
+        (count > 0).then(|| {
+            let mean = window.sum / count;
+            let variance = (window.sum_sq / count).saturating_sub(mean * mean);
+            Summary {
+                count: window.count,
+                min_us: window.min_us,
+                mean_us: mean as u32,
+                max_us: window.max_us,
+                jitter_us: variance.isqrt() as u32,
+            }
+        })
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarises_and_starts_again() {
+        let mut window = Window::new();
+        assert_eq!(window.take(), None);
+        for duration_us in [10, 20, 30] {
+            window.record(duration_us);
+        }
+        assert_eq!(
+            window.take(),
+            Some(Summary {
+                count: 3,
+                min_us: 10,
+                mean_us: 20,
+                max_us: 30,
+                jitter_us: 8,
+            })
+        );
+        assert_eq!(window.take(), None);
+    }
+
+    #[test]
+    fn steady_timing_has_no_jitter() {
+        let mut window = Window::new();
+        for _ in 0..100 {
+            window.record(1000);
+        }
+        assert_eq!(window.take().map(|summary| summary.jitter_us), Some(0));
+    }
+}
@@ -0,0 +1,143 @@
+//! Minimal driver for TM1637 4-digit 7-segment displays, and the formatting
+//! of readings for them.
+//!
+//! The TM1637 has a two-wire bus that is close to I2C but has no addresses
+//! and sends bytes least significant bit first, so it is bit-banged. DIO is
+//! read back for the acknowledge and must be open-drain with a pull-up.
+
+use defmt::Format;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+pub const DIGITS: usize = 4;
+
+/// Brightest of the eight levels.
+pub const MAX_BRIGHTNESS: u8 = 7;
+
+const CMD_DATA_AUTO_INCREMENT: u8 = 0x40;
+const CMD_ADDRESS: u8 = 0xc0;
+const CMD_DISPLAY_ON: u8 = 0x88;
+
+// Half a clock period; the TM1637 manages about 250kHz
+const BIT_DELAY_US: u32 = 5;
+
+// Segments a to g in bits 0 to 6. Bit 7 is the decimal point, or on most
+// clock-style modules the colon after the second digit.
+const SEG_DIGITS: [u8; 10] = [0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f];
+const SEG_MINUS: u8 = 0x40;
+const SEG_POINT: u8 = 0x80;
+
+/// Shown when the value does not fit.
+pub const OVERFLOW: [u8; DIGITS] = [SEG_MINUS; DIGITS];
+/// "Err", shown while readings are invalid.
+pub const ERROR: [u8; DIGITS] = [0x79, 0x50, 0x50, 0x00];
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Error {
+    /// A pin could not be driven or read.
+    Pin,
+    /// The display did not acknowledge a byte.
+    Nack,
+}
+
+/// Segments for `value` with `decimals` digits after the point, right
+/// aligned, or `None` if it needs more than four digits.
+pub fn segments(value: f32, decimals: u8) -> Option<[u8; DIGITS]> {
+    let scaled = (0..decimals).fold(value, |v, _| v * 10.0);
+    // Rounded half away from zero
+    let scaled = (if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 }) as i32;
+    let negative = scaled < 0;
+    let mut magnitude = scaled.unsigned_abs();
+
+    let mut out = [0u8; DIGITS];
+    let mut i = DIGITS;
+    // At least one digit before the point
+    let min_digits = decimals as usize + 1;
+    let mut written = 0;
+    while magnitude > 0 || written < min_digits {
+        i = i.checked_sub(1)?;
+        out[i] = SEG_DIGITS[(magnitude % 10) as usize];
+        if written == decimals as usize && decimals > 0 {
+            out[i] |= SEG_POINT;
+        }
+        magnitude /= 10;
+        written += 1;
+    }
+    if negative {
+        i = i.checked_sub(1)?;
+        out[i] = SEG_MINUS;
+    }
+    Some(out)
+}
+
+pub struct Tm1637<C, D, T> {
+    clk: C,
+    dio: D,
+    delay: T,
+}
+
+impl<C: OutputPin, D: OutputPin + InputPin, T: DelayNs> Tm1637<C, D, T> {
+    pub fn new(clk: C, dio: D, delay: T) -> Self {
+        Self { clk, dio, delay }
+    }
+
+    /// Shows `segments`, leftmost digit first, at `brightness` from 0 to
+    /// [`MAX_BRIGHTNESS`].
+    pub fn show(&mut self, segments: &[u8; DIGITS], brightness: u8) -> Result<(), Error> {
+        self.command(&[CMD_DATA_AUTO_INCREMENT])?;
+        let mut data = [CMD_ADDRESS; DIGITS + 1];
+        data[1..].copy_from_slice(segments);
+        self.command(&data)?;
+        self.command(&[CMD_DISPLAY_ON | brightness.min(MAX_BRIGHTNESS)])
+    }
+
+    fn command(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.start()?;
+        let result = bytes.iter().try_for_each(|&byte| self.write(byte));
+        // Always release the bus, even after a missing acknowledge
+        self.stop()?;
+        result
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        self.clk.set_high().map_err(|_| Error::Pin)?;
+        self.dio.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        self.dio.set_low().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.clk.set_low().map_err(|_| Error::Pin)?;
+        self.dio.set_low().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        self.clk.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        self.dio.set_high().map_err(|_| Error::Pin)
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), Error> {
+        for bit in 0..8 {
+            self.clk.set_low().map_err(|_| Error::Pin)?;
+            if byte & (1 << bit) != 0 {
+                self.dio.set_high().map_err(|_| Error::Pin)?;
+            } else {
+                self.dio.set_low().map_err(|_| Error::Pin)?;
+            }
+            self.delay.delay_us(BIT_DELAY_US);
+            self.clk.set_high().map_err(|_| Error::Pin)?;
+            self.delay.delay_us(BIT_DELAY_US);
+        }
+
+        // Ninth clock: the display pulls DIO low to acknowledge
+        self.clk.set_low().map_err(|_| Error::Pin)?;
+        self.dio.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        self.clk.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(BIT_DELAY_US);
+        let ack = self.dio.is_low().map_err(|_| Error::Pin)?;
+        self.clk.set_low().map_err(|_| Error::Pin)?;
+        if ack { Ok(()) } else { Err(Error::Nack) }
+    }
+}
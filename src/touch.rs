@@ -0,0 +1,99 @@
+//! Touch detection on the raw readings of a capacitive pad.
+//!
+//! The untouched level drifts with temperature and humidity, so it is tracked
+//! while the pad is not touched. A finger raises the reading well above it.
+
+/// Readings averaged for the initial baseline.
+pub const BASELINE_READINGS: u32 = 16;
+
+// A rise of 1/TOUCH_FRACTION over the baseline is a touch; it is released
+// once the rise falls below half of that
+const TOUCH_FRACTION: u32 = 8;
+
+// Weight of each new reading in the baseline, as 1/DRIFT
+const DRIFT: u32 = 64;
+
+pub struct TouchDetector {
+    baseline: u32,
+    count: u32,
+    touched: bool,
+}
+
+impl TouchDetector {
+    pub const fn new() -> Self {
+        Self {
+            baseline: 0,
+            count: 0,
+            touched: false,
+        }
+    }
+
+    /// Returns the new state when the pad is touched or released. The first
+    /// readings only establish the baseline, so the pad must not be touched
+    /// at start-up.
+    pub fn update(&mut self, raw: u32) -> Option<bool> {
+        if self.count < BASELINE_READINGS {
+            self.baseline += raw / BASELINE_READINGS;
+            self.count += 1;
+            return None;
+        }
+
+        let rise = self.baseline / TOUCH_FRACTION;
+        if !self.touched && raw >= self.baseline + rise {
+            self.touched = true;
+            Some(true)
+        } else if self.touched && raw < self.baseline + rise / 2 {
+            self.touched = false;
+            Some(false)
+        } else {
+            if !self.touched {
+                self.baseline = (self.baseline * (DRIFT - 1) + raw) / DRIFT;
+            }
+            None
+        }
+    }
+
+    pub fn is_touched(&self) -> bool {
+        self.touched
+    }
+}
+
+impl Default for TouchDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A baseline of 992, as 1000 is averaged in sixteenths
+    fn settled() -> TouchDetector {
+        let mut detector = TouchDetector::new();
+        for _ in 0..BASELINE_READINGS {
+            assert_eq!(detector.update(1000), None);
+        }
+        detector
+    }
+
+    #[test]
+    fn touches_and_releases_with_hysteresis() {
+        // Touched at an eighth over the baseline, released under a sixteenth
+        let mut detector = settled();
+        assert_eq!(detector.update(1116), Some(true));
+        assert!(detector.is_touched());
+        assert_eq!(detector.update(1060), None);
+        assert_eq!(detector.update(1053), Some(false));
+        assert!(!detector.is_touched());
+    }
+
+    #[test]
+    fn follows_slow_drift() {
+        let mut detector = settled();
+        for _ in 0..1000 {
+            assert_eq!(detector.update(1100), None);
+        }
+        assert_eq!(detector.update(1120), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Trigger output for an oscilloscope or high-speed camera: a short pulse
+//! as soon as the sensor's voltage crosses a level, or at the top of a
+//! peak past it, so the instrument can be synchronised to a magnet going
+//! past. Levels are in millivolts at the sensor, as for the triggered
+//! capture, so they hold whatever the calibration. After each pulse the
+//! trigger holds off for a set time, so a noisy edge fires it once.
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::Edge;
+use crate::schema::Sample;
+
+pub const ENCODED_SIZE: usize = 24;
+
+/// Longest pulse; the sampler is held up for the whole of it.
+pub const MAX_PULSE_US: u32 = 1000;
+
+/// How far the voltage comes back from a peak before it counts as one.
+pub const PEAK_DROP_MV: u32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub enum Condition {
+    Off,
+    /// The voltage reaching `level_mv` on this edge.
+    Crossing {
+        level_mv: u32,
+        edge: Edge,
+    },
+    /// The top of a peak above `level_mv`, or if not `rising` the bottom
+    /// of a dip below it. The pulse comes once the voltage has come back
+    /// [`PEAK_DROP_MV`], and the peak's own time is reported with it.
+    Peak {
+        level_mv: u32,
+        rising: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Settings {
+    pub condition: Condition,
+    pub pulse_us: u32,
+    pub holdoff_ms: u32,
+}
+
+impl Settings {
+    /// Off until set, with a pulse most scopes and camera inputs catch.
+    pub const DEFAULT: Self = Self {
+        condition: Condition::Off,
+        pulse_us: 10,
+        holdoff_ms: 10,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Watches the samples for the condition.
+pub struct Trigger {
+    last_mv: Option<u32>,
+    /// Whether the last sample was at or above a peak's level.
+    above: Option<bool>,
+    /// The peak so far, and its time.
+    extreme: Option<(u32, u64)>,
+    /// Set once this side's peak has fired.
+    spent: bool,
+    holdoff_until_us: u64,
+}
+
+impl Trigger {
+    pub const fn new() -> Self {
+        Self {
+            last_mv: None,
+            above: None,
+            extreme: None,
+            spent: false,
+            holdoff_until_us: 0,
+        }
+    }
+
+    /// Takes a sample, returning the time of the event to pulse for: the
+    /// sample itself for a crossing, the top of the peak for a peak.
+    pub fn update(&mut self, sample: &Sample, settings: &Settings) -> Option<u64> {
+        let last_mv = self.last_mv.replace(sample.voltage_mv);
+        let event_us = match settings.condition {
+            Condition::Off => None,
+            Condition::Crossing { level_mv, edge } => last_mv
+                .filter(|&last_mv| edge.crossed(last_mv, sample.voltage_mv, level_mv))
+                .map(|_| sample.timestamp_us),
+            Condition::Peak { level_mv, rising } => self.peak(sample, level_mv, rising),
+        };
+        let event_us = event_us.filter(|_| sample.timestamp_us >= self.holdoff_until_us)?;
+        self.holdoff_until_us = sample.timestamp_us + settings.holdoff_ms as u64 * 1000;
+        Some(event_us)
+    }
+
+    fn peak(&mut self, sample: &Sample, level_mv: u32, rising: bool) -> Option<u64> {
+        let voltage_mv = sample.voltage_mv;
+        let above = voltage_mv >= level_mv;
+        if self.above.replace(above) != Some(above) {
+            self.extreme = None;
+            self.spent = false;
+        }
+        if above != rising || self.spent {
+            return None;
+        }
+        let (extreme_mv, extreme_us) = *self
+            .extreme
+            .get_or_insert((voltage_mv, sample.timestamp_us));
+        let beyond = if above {
+            voltage_mv >= extreme_mv
+        } else {
+            voltage_mv <= extreme_mv
+        };
+        if beyond {
+            self.extreme = Some((voltage_mv, sample.timestamp_us));
+            None
+        } else if voltage_mv.abs_diff(extreme_mv) >= PEAK_DROP_MV {
+            self.spent = true;
+            Some(extreme_us)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(trigger: &mut Trigger, settings: &Settings, voltages: &[u32]) -> Vec<u64> {
+        voltages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &voltage_mv)| {
+                let sample = Sample {
+                    timestamp_us: i as u64 * 1000,
+                    raw: 0,
+                    voltage_mv,
+                };
+                trigger.update(&sample, settings)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_on_the_crossing_then_holds_off() {
+        let settings = Settings {
+            condition: Condition::Crossing {
+                level_mv: 2000,
+                edge: Edge::Rising,
+            },
+            holdoff_ms: 3,
+            ..Settings::DEFAULT
+        };
+        let mut trigger = Trigger::new();
+        let voltages = [1900, 2000, 1990, 2010, 1900, 1900, 2100, 1600];
+        // The chatter at 2 and 3 falls in the hold-off
+        assert_eq!(run(&mut trigger, &settings, &voltages), [1000, 6000]);
+
+        let mut trigger = Trigger::new();
+        let settings = Settings {
+            condition: Condition::Off,
+            ..settings
+        };
+        assert!(run(&mut trigger, &settings, &voltages).is_empty());
+    }
+
+    #[test]
+    fn fires_once_per_peak_with_its_time() {
+        let settings = Settings {
+            condition: Condition::Peak {
+                level_mv: 2000,
+                rising: true,
+            },
+            holdoff_ms: 0,
+            ..Settings::DEFAULT
+        };
+        let mut trigger = Trigger::new();
+        let voltages = [
+            1650, 2100, 2300, 2290, 2270, 2200, 2400, 1650, 2050, 2040, 1650,
+        ];
+        // The rise to 2400 after the first peak is the same pass, and the
+        // second pass falls back below the level before coming back enough
+        assert_eq!(run(&mut trigger, &settings, &voltages), [2000]);
+
+        let settings = Settings {
+            condition: Condition::Peak {
+                level_mv: 1600,
+                rising: false,
+            },
+            ..settings
+        };
+        let mut trigger = Trigger::new();
+        let voltages = [1650, 1550, 1500, 1520, 1700, 1590, 1585, 1650];
+        assert_eq!(run(&mut trigger, &settings, &voltages), [2000]);
+    }
+}
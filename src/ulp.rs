@@ -0,0 +1,114 @@
+//! Programs for the ESP32-S3's ULP-FSM coprocessor, which keeps running in
+//! deep sleep.
+//!
+//! Instructions are encoded as in ESP-IDF's `ulp.h` for the ESP32-S3; only
+//! the handful the programs here use are provided.
+
+const OPCODE_ADC: u32 = 5;
+const OPCODE_BRANCH: u32 = 8;
+const OPCODE_END: u32 = 9;
+const OPCODE_HALT: u32 = 11;
+
+// Branch relative to the PC on a comparison of R0 with an immediate
+const SUB_OPCODE_B: u32 = 1;
+const SUB_OPCODE_END: u32 = 0;
+
+const BRCOND_LT: u32 = 0;
+const BRCOND_GE: u32 = 1;
+
+/// Reads SAR ADC1 `channel` into `reg`.
+pub const fn adc(reg: u32, channel: u32) -> u32 {
+    // Pads are numbered from one; SAR ADC1 is selected by a zero
+    (reg & 0x3) | (((channel + 1) & 0xf) << 2) | (OPCODE_ADC << 28)
+}
+
+/// Jumps `offset` instructions if R0 is below `imm`.
+pub const fn branch_lt(offset: i8, imm: u16) -> u32 {
+    branch(offset, BRCOND_LT, imm)
+}
+
+/// Jumps `offset` instructions if R0 is at least `imm`.
+pub const fn branch_ge(offset: i8, imm: u16) -> u32 {
+    branch(offset, BRCOND_GE, imm)
+}
+
+const fn branch(offset: i8, cmp: u32, imm: u16) -> u32 {
+    let sign = (offset < 0) as u32;
+    let magnitude = offset.unsigned_abs() as u32 & 0x7f;
+    imm as u32
+        | (cmp << 16)
+        | (magnitude << 17)
+        | (sign << 24)
+        | (SUB_OPCODE_B << 25)
+        | (OPCODE_BRANCH << 28)
+}
+
+/// Wakes the main CPU if it is asleep.
+pub const fn wake() -> u32 {
+    1 | (SUB_OPCODE_END << 25) | (OPCODE_END << 28)
+}
+
+/// Ends this run; the ULP timer starts the program again.
+pub const fn halt() -> u32 {
+    OPCODE_HALT << 28
+}
+
+pub const THRESHOLD_PROGRAM_LEN: usize = 5;
+
+/// Reads `channel` once per run and wakes the CPU when the raw reading
+/// crosses to the other side of the threshold: below `lower_raw` if it was
+/// `above`, otherwise up to `upper_raw` or more.
+pub const fn threshold_program(
+    channel: u32,
+    above: bool,
+    lower_raw: u16,
+    upper_raw: u16,
+) -> [u32; THRESHOLD_PROGRAM_LEN] {
+    let crossed = if above {
+        branch_lt(2, lower_raw)
+    } else {
+        branch_ge(2, upper_raw)
+    };
+    [adc(0, channel), crossed, halt(), wake(), halt()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Words as ESP-IDF's I_ADC, I_BL, I_BGE, I_WAKE and I_HALT encode them
+
+    #[test]
+    fn encodes_adc() {
+        assert_eq!(adc(0, 3), 0x5000_0010);
+        assert_eq!(adc(2, 0), 0x5000_0006);
+    }
+
+    #[test]
+    fn encodes_branches() {
+        assert_eq!(branch_lt(2, 1000), 0x8204_03e8);
+        assert_eq!(branch_ge(2, 0x0fff), 0x8205_0fff);
+        assert_eq!(branch_lt(-3, 5), 0x8306_0005);
+    }
+
+    #[test]
+    fn encodes_wake_and_halt() {
+        assert_eq!(wake(), 0x9000_0001);
+        assert_eq!(halt(), 0xb000_0000);
+    }
+
+    #[test]
+    fn branches_over_the_wake_until_the_threshold_is_crossed() {
+        assert_eq!(
+            threshold_program(3, false, 100, 200),
+            [
+                0x5000_0010,
+                0x8205_00c8,
+                0xb000_0000,
+                0x9000_0001,
+                0xb000_0000
+            ]
+        );
+        assert_eq!(threshold_program(3, true, 100, 200)[1], 0x8204_0064);
+    }
+}
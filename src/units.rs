@@ -0,0 +1,214 @@
+//! The unit readings are shown in: raw ADC counts, millivolts at the
+//! sensor, gauss or millitesla. Each unit has its own number of places,
+//! and values are rounded half away from zero to them, as the 7-segment
+//! display does, so a value that rounds to zero never shows as `-0`.
+
+use core::fmt;
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Sample;
+
+pub const ENCODED_SIZE: usize = 4;
+
+const GAUSS_PER_MT: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum Unit {
+    Counts,
+    Millivolts,
+    Gauss,
+    #[default]
+    Millitesla,
+}
+
+impl Unit {
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            Self::Counts,
+            Self::Millivolts,
+            Self::Gauss,
+            Self::Millitesla,
+        ]
+        .into_iter()
+        .find(|unit| unit.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Counts => "counts",
+            Self::Millivolts => "mv",
+            Self::Gauss => "gauss",
+            Self::Millitesla => "mt",
+        }
+    }
+
+    /// As written after a value.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Counts => "counts",
+            Self::Millivolts => "mV",
+            Self::Gauss => "G",
+            Self::Millitesla => "mT",
+        }
+    }
+
+    /// Places after the point.
+    pub fn decimals(self) -> u8 {
+        match self {
+            Self::Counts | Self::Millivolts => 0,
+            Self::Gauss => 1,
+            Self::Millitesla => 2,
+        }
+    }
+
+    /// `sample`, whose field is `field_mt`, in this unit.
+    pub fn value(self, sample: &Sample, field_mt: f32) -> f32 {
+        match self {
+            Self::Counts => sample.raw as f32,
+            Self::Millivolts => sample.voltage_mv as f32,
+            Self::Gauss => field_mt * GAUSS_PER_MT,
+            Self::Millitesla => field_mt,
+        }
+    }
+
+    pub fn quantity(self, sample: &Sample, field_mt: f32) -> Quantity {
+        Quantity {
+            value: self.value(sample, field_mt),
+            unit: self,
+        }
+    }
+}
+
+/// A value in a unit, shown rounded and followed by the unit's symbol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quantity {
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    /// The value alone, rounded; a width, if given, right-aligns it.
+    pub fn number(self) -> Rounded {
+        Rounded {
+            value: self.value,
+            decimals: self.unit.decimals(),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.number(), self.unit.symbol())
+    }
+}
+
+/// The lowest and highest values seen, in one unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub min: Quantity,
+    pub max: Quantity,
+}
+
+impl Range {
+    /// `range` taking in `quantity`, started over if that is in another
+    /// unit, as after the unit is changed.
+    pub fn widen(range: Option<Self>, quantity: Quantity) -> Self {
+        match range {
+            Some(range) if range.min.unit == quantity.unit => Self {
+                min: if quantity.value < range.min.value {
+                    quantity
+                } else {
+                    range.min
+                },
+                max: if quantity.value > range.max.value {
+                    quantity
+                } else {
+                    range.max
+                },
+            },
+            _ => Self {
+                min: quantity,
+                max: quantity,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rounded {
+    value: f32,
+    decimals: u8,
+}
+
+impl fmt::Display for Rounded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = 10i64.pow(self.decimals as u32);
+        let scaled = self.value * scale as f32;
+        let scaled = (if scaled < 0.0 {
+            scaled - 0.5
+        } else {
+            scaled + 0.5
+        }) as i64;
+        // The nearest f32 to a whole number of places prints as exactly that
+        let rounded = scaled as f32 / scale as f32;
+        let decimals = self.decimals as usize;
+        match f.width() {
+            Some(width) => write!(f, "{:>width$.decimals$}", rounded),
+            None => write!(f, "{:.decimals$}", rounded),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: Sample = Sample {
+        timestamp_us: 0,
+        raw: 2048,
+        voltage_mv: 1650,
+    };
+
+    fn shown(unit: Unit, field_mt: f32) -> String {
+        unit.quantity(&SAMPLE, field_mt).to_string()
+    }
+
+    #[test]
+    fn each_unit_has_its_value_and_symbol() {
+        assert_eq!(shown(Unit::Counts, 1.0), "2048 counts");
+        assert_eq!(shown(Unit::Millivolts, 1.0), "1650 mV");
+        assert_eq!(shown(Unit::Gauss, 1.234), "12.3 G");
+        assert_eq!(shown(Unit::Millitesla, 1.234), "1.23 mT");
+        assert_eq!(Unit::parse("gauss"), Some(Unit::Gauss));
+        assert_eq!(Unit::parse("tesla"), None);
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(shown(Unit::Gauss, 0.125), "1.3 G");
+        assert_eq!(shown(Unit::Gauss, -0.125), "-1.3 G");
+        assert_eq!(shown(Unit::Millitesla, -0.004), "0.00 mT");
+        assert_eq!(shown(Unit::Millitesla, 2.999), "3.00 mT");
+    }
+
+    #[test]
+    fn ranges_start_over_in_another_unit() {
+        let range = [-2.0, 3.0, 1.0]
+            .into_iter()
+            .map(|field_mt| Unit::Millitesla.quantity(&SAMPLE, field_mt))
+            .fold(None, |range, quantity| Some(Range::widen(range, quantity)))
+            .unwrap();
+        assert_eq!((range.min.value, range.max.value), (-2.0, 3.0));
+
+        let range = Range::widen(Some(range), Unit::Counts.quantity(&SAMPLE, 0.0));
+        assert_eq!((range.min.value, range.max.value), (2048.0, 2048.0));
+    }
+
+    #[test]
+    fn a_width_right_aligns_the_number() {
+        let quantity = Unit::Millitesla.quantity(&SAMPLE, -1.5);
+        assert_eq!(format!("[{:8}]", quantity.number()), "[   -1.50]");
+    }
+}
@@ -0,0 +1,113 @@
+//! Runtime log filtering: a level per firmware module, changed from the
+//! console. Messages above the compile-time `DEFMT_LOG` level are not built
+//! in and cannot be enabled here.
+
+use defmt::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Format)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Module {
+    /// The per-sample voltage line.
+    Sample,
+    /// ADC reads, threshold crossings and wiring faults.
+    Sensor,
+    Supply,
+    /// Flash log, SD log and the state store.
+    Storage,
+    Led,
+    Clock,
+    /// Periodic loop timing reports.
+    Timing,
+    /// Display backends other than the status LED.
+    Display,
+}
+
+pub const MODULES: [Module; 8] = [
+    Module::Sample,
+    Module::Sensor,
+    Module::Supply,
+    Module::Storage,
+    Module::Led,
+    Module::Clock,
+    Module::Timing,
+    Module::Display,
+];
+
+impl Module {
+    pub fn parse(name: &str) -> Option<Self> {
+        MODULES.into_iter().find(|m| m.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Module::Sample => "sample",
+            Module::Sensor => "sensor",
+            Module::Supply => "supply",
+            Module::Storage => "storage",
+            Module::Led => "led",
+            Module::Clock => "clock",
+            Module::Timing => "timing",
+            Module::Display => "display",
+        }
+    }
+}
+
+/// Current level of every module; all start at `Info`.
+#[derive(Clone, Copy)]
+pub struct Levels([Level; MODULES.len()]);
+
+impl Levels {
+    pub const fn new() -> Self {
+        Self([Level::Info; MODULES.len()])
+    }
+
+    pub fn get(&self, module: Module) -> Level {
+        self.0[module as usize]
+    }
+
+    pub fn set(&mut self, module: Module, level: Level) {
+        self.0[module as usize] = level;
+    }
+
+    pub fn set_all(&mut self, level: Level) {
+        self.0 = [level; MODULES.len()];
+    }
+
+    pub fn enabled(&self, module: Module, level: Level) -> bool {
+        level != Level::Off && level <= self.get(module)
+    }
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,174 @@
+//! Synthetic sensor output, for developing the rest of the firmware without
+//! a sensor: the `mock-sensor` build samples it in place of the ADC, and the
+//! simulator runs it through the processing on the host.
+
+use defmt::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Shape {
+    /// A magnet rotating past: north, then south, once per period.
+    Sine,
+    /// A magnet arriving for the second half of each period, as on a door.
+    Step,
+    /// Uniform noise, a new value for every sample.
+    Noise,
+}
+
+pub const SHAPES: [Shape; 3] = [Shape::Sine, Shape::Step, Shape::Noise];
+
+impl Shape {
+    pub fn parse(name: &str) -> Option<Self> {
+        SHAPES.into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Sine => "sine",
+            Shape::Step => "step",
+            Shape::Noise => "noise",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub struct Waveform {
+    pub shape: Shape,
+    pub period_ms: u32,
+    /// Peak deviation from the zero-field output.
+    pub amplitude_mv: u32,
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Sine,
+            period_ms: 2000,
+            amplitude_mv: 800,
+        }
+    }
+}
+
+/// Produces a waveform's voltage at the sensor pin over time.
+pub struct Generator {
+    waveform: Waveform,
+    zero_mv: u32,
+    // xorshift, so runs are repeatable
+    noise: u32,
+}
+
+impl Generator {
+    /// A generator centred on `zero_mv`, the sensor's zero-field output.
+    pub const fn new(waveform: Waveform, zero_mv: u32) -> Self {
+        Self {
+            waveform,
+            zero_mv,
+            noise: 0x2545_f491,
+        }
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn set(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// The voltage at `timestamp_us`, within the 0 to 3300mV an ADC reads.
+    pub fn voltage_mv(&mut self, timestamp_us: u64) -> u32 {
+        let period_us = self.waveform.period_ms.max(1) as u64 * 1000;
+        let phase = (timestamp_us % period_us) as f32 / period_us as f32;
+        let offset = match self.waveform.shape {
+            Shape::Sine => sine(phase),
+            Shape::Step => (phase >= 0.5) as u8 as f32,
+            Shape::Noise => self.next_noise(),
+        };
+        let voltage_mv = self.zero_mv as f32 + offset * self.waveform.amplitude_mv as f32;
+        voltage_mv.clamp(0.0, 3300.0) as u32
+    }
+
+    /// From -1 to 1.
+    fn next_noise(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+/// The sine of a `phase` in turns (0 to 1), to within 0.2% by Bhaskara's
+/// approximation, as `core` has no `sin`.
+fn sine(phase: f32) -> f32 {
+    let (x, sign) = if phase < 0.5 {
+        (phase * 2.0, 1.0)
+    } else {
+        (phase * 2.0 - 1.0, -1.0)
+    };
+    // x in half-turns, so x(1 - x) peaks at 1/4
+    let p = x * (1.0 - x);
+    sign * 16.0 * p / (5.0 - 4.0 * p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(shape: Shape) -> Generator {
+        let waveform = Waveform {
+            shape,
+            period_ms: 1000,
+            amplitude_mv: 1000,
+        };
+        Generator::new(waveform, 1650)
+    }
+
+    #[test]
+    fn sine_follows_the_period() {
+        let mut sine = generator(Shape::Sine);
+        assert_eq!(sine.voltage_mv(0), 1650);
+        assert_eq!(sine.voltage_mv(250_000), 2650);
+        assert_eq!(sine.voltage_mv(500_000), 1650);
+        assert_eq!(sine.voltage_mv(750_000), 650);
+        assert_eq!(sine.voltage_mv(1_250_000), 2650);
+    }
+
+    #[test]
+    fn sine_approximation() {
+        for i in 0..=100 {
+            let phase = i as f32 / 100.0;
+            let exact = (phase * core::f32::consts::TAU).sin();
+            assert!((sine(phase) - exact).abs() < 0.002, "phase {phase}");
+        }
+    }
+
+    #[test]
+    fn step_rises_halfway() {
+        let mut step = generator(Shape::Step);
+        assert_eq!(step.voltage_mv(0), 1650);
+        assert_eq!(step.voltage_mv(499_000), 1650);
+        assert_eq!(step.voltage_mv(500_000), 2650);
+        assert_eq!(step.voltage_mv(999_000), 2650);
+    }
+
+    #[test]
+    fn noise_stays_within_amplitude_and_rails() {
+        let mut noise = generator(Shape::Noise);
+        let readings: Vec<u32> = (0..1000).map(|i| noise.voltage_mv(i)).collect();
+        assert!(readings.iter().all(|mv| (650..=2650).contains(mv)));
+        assert!(readings.windows(2).any(|w| w[0] != w[1]));
+
+        noise.set(Waveform {
+            amplitude_mv: 5000,
+            ..noise.waveform()
+        });
+        assert!((0..1000).all(|i| noise.voltage_mv(i) <= 3300));
+    }
+
+    #[test]
+    fn shape_names() {
+        for shape in SHAPES {
+            assert_eq!(Shape::parse(shape.name()), Some(shape));
+        }
+        assert_eq!(Shape::parse("square"), None);
+    }
+}
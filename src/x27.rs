@@ -0,0 +1,242 @@
+//! Driver for X27.168-style automotive gauge steppers (Switec X25, VID29
+//! and their clones), and the needle's acceleration-limited motion.
+//!
+//! The motor's two coils take their four wires straight from GPIO, in a
+//! six-state sequence of partial steps, a third of a degree each over a
+//! 315 degree sweep. It has no position feedback: homing drives it past
+//! the whole sweep against its end stop, where it stalls harmlessly.
+//!
+//! Steps are timed for a constant acceleration up to a top speed and down
+//! again, so the needle does not skip steps starting off or overshoot the
+//! target.
+
+use defmt::Format;
+use embedded_hal::digital::{OutputPin, PinState};
+use serde::{Deserialize, Serialize};
+
+/// Partial steps across the sweep.
+pub const STEPS: u32 = 945;
+pub const SWEEP_DEG: f32 = 315.0;
+
+/// The wait between steps while homing: slow enough to be sure of every
+/// step at the stop.
+pub const HOMING_STEP_US: u32 = 1500;
+
+/// The motor's datasheet limits, in steps per second and per second
+/// squared, with some margin.
+pub const MAX_SPEED: f32 = 1800.0;
+pub const ACCELERATION: f32 = 6000.0;
+
+pub const ENCODED_SIZE: usize = 16;
+
+// Coil levels on pins 1 to 4, in bits 0 to 3, for each partial step
+const SEQUENCE: [u8; 6] = [0b1001, 0b0001, 0b0111, 0b0110, 0b1110, 0b1000];
+
+pub struct X27<P> {
+    pins: [P; 4],
+    state: usize,
+}
+
+impl<P: OutputPin> X27<P> {
+    pub fn new(pins: [P; 4]) -> Self {
+        Self { pins, state: 0 }
+    }
+
+    /// One partial step, clockwise if `forward`.
+    pub fn step(&mut self, forward: bool) -> Result<(), P::Error> {
+        self.state = if forward {
+            (self.state + 1) % SEQUENCE.len()
+        } else {
+            (self.state + SEQUENCE.len() - 1) % SEQUENCE.len()
+        };
+        let levels = SEQUENCE[self.state];
+        for (i, pin) in self.pins.iter_mut().enumerate() {
+            pin.set_state(PinState::from(levels & (1 << i) != 0))?;
+        }
+        Ok(())
+    }
+}
+
+/// What the dial reads across its sweep.
+#[derive(Clone, Copy, Debug, PartialEq, Format, Serialize, Deserialize)]
+pub struct Scale {
+    /// The field at the start of the sweep, and at its end. The other way
+    /// round turns the needle the other way.
+    pub low_mt: f32,
+    pub high_mt: f32,
+    /// The speed at the end of the sweep, from zero at its start.
+    pub full_rpm: u32,
+}
+
+impl Scale {
+    /// Either pole of an SS49E-class sensor's range, centred, and a
+    /// tachometer dial to 6000rpm.
+    pub const DEFAULT: Self = Self {
+        low_mt: -100.0,
+        high_mt: 100.0,
+        full_rpm: 6000,
+    };
+
+    /// The needle's position for `field_mt`, in steps from the start.
+    pub fn field_steps(&self, field_mt: f32) -> i32 {
+        steps((field_mt - self.low_mt) / (self.high_mt - self.low_mt))
+    }
+
+    pub fn rpm_steps(&self, rpm: u32) -> i32 {
+        steps(rpm as f32 / self.full_rpm as f32)
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Steps for a fraction of the sweep, kept within it.
+fn steps(fraction: f32) -> i32 {
+    if fraction.is_nan() {
+        return 0;
+    }
+    (fraction.clamp(0.0, 1.0) * STEPS as f32 + 0.5) as i32
+}
+
+/// Where the needle is, where it is going and how fast, in steps.
+pub struct Motion {
+    position: i32,
+    target: i32,
+    /// Squared, which keeps the arithmetic exact on the way down to a
+    /// stop. Always positive while moving.
+    speed_sq: f32,
+    forward: bool,
+    max_speed: f32,
+    acceleration: f32,
+}
+
+impl Motion {
+    /// At rest at `position`.
+    pub const fn new(position: i32, max_speed: f32, acceleration: f32) -> Self {
+        Self {
+            position,
+            target: position,
+            speed_sq: 0.0,
+            forward: true,
+            max_speed,
+            acceleration,
+        }
+    }
+
+    pub fn set_target(&mut self, target: i32) {
+        self.target = target;
+    }
+
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// The next step to take and how long to wait before taking it, or
+    /// `None` at rest at the target. The needle slows to a stop before
+    /// turning round for a target behind it.
+    pub fn advance(&mut self) -> Option<(bool, u32)> {
+        let distance = self.target - self.position;
+        if self.speed_sq == 0.0 {
+            if distance == 0 {
+                return None;
+            }
+            self.forward = distance > 0;
+        }
+        let ahead = if self.forward { distance } else { -distance };
+        let slower = self.speed_sq - 2.0 * self.acceleration;
+        let speed_sq = if ahead <= 0 {
+            slower
+        } else {
+            // Fast enough to stop by the target, no faster
+            let faster = self.speed_sq + 2.0 * self.acceleration;
+            let stoppable = 2.0 * self.acceleration * ahead as f32;
+            faster
+                .min(self.max_speed * self.max_speed)
+                .min(stoppable)
+                .max(slower)
+        };
+        self.speed_sq = if speed_sq > 0.0 {
+            speed_sq
+        } else if distance == 0 {
+            self.speed_sq = 0.0;
+            return None;
+        } else {
+            // Turning round, from rest
+            self.forward = distance > 0;
+            2.0 * self.acceleration
+        };
+        self.position += if self.forward { 1 } else { -1 };
+        let step_us = 1e6 / crate::spectrum::sqrt(self.speed_sq);
+        Some((self.forward, step_us as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(motion: &mut Motion, limit: usize) -> (usize, u32) {
+        let (mut steps, mut min_wait_us) = (0, u32::MAX);
+        while let Some((_, wait_us)) = motion.advance() {
+            steps += 1;
+            min_wait_us = min_wait_us.min(wait_us);
+            assert!(steps < limit);
+        }
+        (steps, min_wait_us)
+    }
+
+    #[test]
+    fn scale_maps_onto_the_sweep() {
+        let scale = Scale::DEFAULT;
+        assert_eq!(scale.field_steps(0.0), 473);
+        assert_eq!(scale.field_steps(-500.0), 0);
+        assert_eq!(scale.field_steps(500.0), STEPS as i32);
+        assert_eq!(scale.rpm_steps(3000), 473);
+        assert_eq!(scale.rpm_steps(9000), STEPS as i32);
+
+        let mut buf = [0u8; ENCODED_SIZE];
+        let encoded = postcard::to_slice(&scale, &mut buf).unwrap();
+        assert_eq!(postcard::from_bytes::<Scale>(encoded).unwrap(), scale);
+    }
+
+    #[test]
+    fn moves_to_the_target_within_the_limits() {
+        let mut motion = Motion::new(0, MAX_SPEED, ACCELERATION);
+        motion.set_target(STEPS as i32);
+        let (steps, min_wait_us) = run(&mut motion, 2000);
+        assert_eq!(steps, STEPS as usize);
+        assert_eq!(motion.position(), STEPS as i32);
+        assert!(min_wait_us as f32 >= 1e6 / MAX_SPEED - 1.0);
+
+        // A short move never reaches top speed
+        motion.set_target(STEPS as i32 - 10);
+        let (steps, min_wait_us) = run(&mut motion, 20);
+        assert_eq!(steps, 10);
+        assert!(min_wait_us as f32 > 1e6 / MAX_SPEED * 2.0);
+    }
+
+    #[test]
+    fn slows_down_before_turning_round() {
+        let mut motion = Motion::new(0, MAX_SPEED, ACCELERATION);
+        motion.set_target(500);
+        for _ in 0..100 {
+            motion.advance();
+        }
+        motion.set_target(0);
+        let mut furthest = 0;
+        let mut last_wait_us = 0;
+        while let Some((forward, wait_us)) = motion.advance() {
+            if forward {
+                // Each wait longer than the last until stopped
+                assert!(wait_us >= last_wait_us);
+                last_wait_us = wait_us;
+            }
+            furthest = furthest.max(motion.position());
+        }
+        assert!(furthest > 100);
+        assert_eq!(motion.position(), 0);
+    }
+}
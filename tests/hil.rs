@@ -0,0 +1,207 @@
+//! On-target tests of the HAL features the firmware leans on, run on an
+//! ESP32-S3-DevKitC-1 through probe-rs with `cargo hil`. They are meant to
+//! catch what an esp-hal or esp-storage upgrade breaks before the firmware
+//! misbehaves in the field:
+//!
+//! - the RMT's pulse timing, by looping a WS2812 frame back to an RX channel
+//!   through the GPIO matrix, so no jumper is needed;
+//! - SAR ADC readings of the sensor pin, with the sensor connected;
+//! - a sequential-storage round trip through a scratch range at the end of
+//!   the flash, past every partition, so neither the device's state nor
+//!   ESP-IDF's NVS data (PHY calibration, credentials) is touched.
+//!
+//! A benchmark of the LED colour table against computing each colour runs
+//! alongside them, and logs both times.
+
+#![no_std]
+#![no_main]
+
+// The sensor pin as the firmware has it; the rest of the board goes unused
+#[allow(dead_code, unused_imports, unused_macros)]
+#[path = "../src/bin/board.rs"]
+mod board;
+
+#[cfg(test)]
+#[embedded_test::tests(default_timeout = 5, executor = esp_rtos::embassy::Executor::new())]
+mod tests {
+    use defmt::info;
+    use embassy_embedded_hal::adapter::BlockingAsync;
+    use embedded_storage_async::nor_flash::ReadNorFlash;
+    use esp_bootloader_esp_idf::partitions::{self, PARTITION_TABLE_MAX_LEN};
+    use esp_hal::analog::adc::{Adc, AdcCalCurve, AdcConfig, Attenuation};
+    use esp_hal::gpio::Level;
+    use esp_hal::peripherals::Peripherals;
+    use esp_hal::rmt::{PulseCode, Rmt, RxChannelConfig, RxChannelCreator};
+    use esp_hal::rmt::{TxChannelConfig, TxChannelCreator};
+    use esp_hal::time::{Instant, Rate};
+    use esp_hal::timer::timg::TimerGroup;
+    use esp_storage::FlashStorage;
+    use hall_effect::color::{self, Gradient, RGB8, WS2812_BITS};
+    use hall_effect::schema::{Config, Sample};
+    use hall_effect::selftest;
+    use sequential_storage::cache::Cache;
+    use sequential_storage::map::{MapConfig, MapStorage};
+
+    use crate::board;
+
+    const RMT_CLOCK_MHZ: u32 = 80;
+
+    // Allowed error of a received pulse, in 12.5ns ticks; the input
+    // synchroniser alone accounts for one or two
+    const PULSE_TOLERANCE: u16 = 3;
+
+    // Top of the calibrated readings on the 6dB range, as the sampler has it
+    const FULL_SCALE: u16 = 1750;
+
+    const TEST_KEY: u8 = 0xfe;
+
+    // Sectors at the end of the flash for the storage round trip, two being
+    // the fewest a map can use
+    const SCRATCH_SECTORS: u32 = 2;
+
+    #[init]
+    fn init() -> Peripherals {
+        rtt_target::rtt_init_defmt!();
+        let peripherals = esp_hal::init(esp_hal::Config::default());
+        let timg0 = TimerGroup::new(peripherals.TIMG0);
+        esp_rtos::start(timg0.timer0);
+        peripherals
+    }
+
+    #[test]
+    fn rmt_loopback(peripherals: Peripherals) {
+        let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(RMT_CLOCK_MHZ)).unwrap();
+        // GPIO2 is unused by the default build
+        // SAFETY: nothing else drives or reads the pin
+        let (input, output) = unsafe { peripherals.GPIO2.split() };
+        let tx_config = TxChannelConfig::default()
+            .with_clk_divider(1)
+            .with_idle_output_level(Level::Low)
+            .with_carrier_modulation(false)
+            .with_idle_output(true);
+        // The LED's 1.25us bit period is far under the idle threshold
+        let rx_config = RxChannelConfig::default()
+            .with_clk_divider(1)
+            .with_carrier_modulation(false)
+            .with_filter_threshold(10)
+            .with_idle_threshold(1000);
+        let tx = rmt.channel0.configure_tx(output, tx_config).unwrap();
+        let rx = rmt.channel4.configure_rx(input, rx_config).unwrap();
+
+        let color = RGB8::new(0x5a, 0xc3, 0x0f);
+        let mut frame = [PulseCode::default(); WS2812_BITS + 1];
+        for (code, bit) in frame.iter_mut().zip(color::ws2812_bits(color)) {
+            let (high, low) = color::pulse_ticks(bit, RMT_CLOCK_MHZ);
+            *code = PulseCode::new(Level::High.into(), high, Level::Low.into(), low);
+        }
+        frame[WS2812_BITS] = PulseCode::new(Level::Low.into(), 0, Level::Low.into(), 0);
+
+        let mut received = [PulseCode::default(); 48];
+        let reception = rx.receive(&mut received).unwrap();
+        tx.transmit(&frame).unwrap().wait().unwrap();
+        let (count, _) = reception.wait().unwrap();
+        info!("Received {} codes", count);
+        assert!(count >= WS2812_BITS);
+
+        let near = |actual: u16, expected: u16| actual.abs_diff(expected) <= PULSE_TOLERANCE;
+        for (i, (sent, got)) in frame[..WS2812_BITS].iter().zip(&received).enumerate() {
+            assert_eq!(got.level1(), Level::High, "code {}", i);
+            assert!(
+                near(got.length1(), sent.length1()),
+                "code {}: high {} for {}",
+                i,
+                got.length1(),
+                sent.length1()
+            );
+            // The last low time runs on into the idle line
+            if i < WS2812_BITS - 1 {
+                assert!(
+                    near(got.length2(), sent.length2()),
+                    "code {}: low {} for {}",
+                    i,
+                    got.length2(),
+                    sent.length2()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn adc_reads_sensor_in_range(peripherals: Peripherals) {
+        let mut adc_config = AdcConfig::new();
+        let mut pin = adc_config.enable_pin_with_cal::<_, AdcCalCurve<_>>(
+            board::sensor_pin!(peripherals),
+            Attenuation::_6dB,
+        );
+        let mut adc = Adc::new(peripherals.ADC1, adc_config);
+
+        let samples: [Sample; selftest::SAMPLES] = core::array::from_fn(|_| {
+            let raw = nb::block!(adc.read_oneshot(&mut pin)).unwrap();
+            assert!(raw <= 4095, "raw {}", raw);
+            Sample {
+                timestamp_us: 0,
+                raw,
+                voltage_mv: ((raw as f32 / 4095.0) * 3300.0) as u32,
+            }
+        });
+        info!("First reading {}", samples[0]);
+        // The boot-time check, so a sensor that passes here boots cleanly
+        assert_eq!(
+            selftest::check(&samples, &Config::default(), FULL_SCALE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn gradient_table_beats_computing() {
+        use core::hint::black_box;
+
+        let config = Config::default();
+        let gradient = Gradient::new(&config);
+        let time_us = |color: &dyn Fn(u32) -> RGB8| {
+            let start = Instant::now();
+            for voltage_mv in 0..=3300 {
+                black_box(color(black_box(voltage_mv)));
+            }
+            (Instant::now() - start).as_micros()
+        };
+        let computed_us = time_us(&|mv| color::voltage_to_color(mv, &config));
+        let table_us = time_us(&|mv| gradient.color(mv));
+        info!(
+            "3301 colours: {}us computed, {}us from the table",
+            computed_us, table_us
+        );
+        assert!(table_us < computed_us);
+    }
+
+    #[test]
+    async fn storage_round_trip(peripherals: Peripherals) {
+        let mut storage = FlashStorage::new(peripherals.FLASH);
+        let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+        let table = partitions::read_partition_table(&mut storage, &mut buf).unwrap();
+        let used = table.iter().map(|p| p.offset() + p.len()).max().unwrap();
+
+        let flash = BlockingAsync::new(storage);
+        let end = flash.capacity() as u32;
+        let range = end - SCRATCH_SECTORS * FlashStorage::SECTOR_SIZE..end;
+        assert!(used <= range.start, "no free flash past the partitions");
+
+        let mut map = MapStorage::<u8, _, _>::new(
+            flash,
+            MapConfig::try_new(range).unwrap(),
+            Cache::new_uncached(),
+        );
+        // Only the scratch range
+        map.erase_all().await.unwrap();
+
+        let mut data = [0u8; 16];
+        for value in [0x1234_5678u32, 0xdead_beef] {
+            map.store_item(&mut data, &TEST_KEY, &value).await.unwrap();
+            let stored = map.fetch_item::<u32>(&mut data, &TEST_KEY).await.unwrap();
+            assert_eq!(stored, Some(value));
+        }
+        map.remove_item(&mut data, &TEST_KEY).await.unwrap();
+        let removed = map.fetch_item::<u32>(&mut data, &TEST_KEY).await.unwrap();
+        assert_eq!(removed, None);
+    }
+}